@@ -0,0 +1,60 @@
+//! Генерация QR кодов: прежняя попиксельная отрисовка против блочной и кэша.
+//! Запуск: `cargo bench --bench qr`
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use crypto_server::qr::{render_png, QrService};
+use image::{ImageBuffer, Rgb, RgbImage};
+use qrcode::{EcLevel, QrCode};
+
+const URL: &str = "solana:https://pay.example.com/api/payment/pay_0b7f3c1e9a8d4f2b8c6e5d4a3b2c1d0e/transaction";
+
+/// Прежняя реализация: RGB, каждый пиксель каждого модуля через `put_pixel`
+fn legacy_render_png(data: &str) -> Vec<u8> {
+    use image::codecs::png::PngEncoder;
+    use image::ImageEncoder;
+
+    let code = QrCode::with_error_correction_level(data, EcLevel::M).unwrap();
+    let (size, border) = (10, 4);
+    let width = code.width();
+    let img_size = (width + 2 * border) * size;
+
+    let mut img: RgbImage = ImageBuffer::new(img_size as u32, img_size as u32);
+    for pixel in img.pixels_mut() {
+        *pixel = Rgb([255, 255, 255]);
+    }
+    for y in 0..width {
+        for x in 0..width {
+            if code[(x, y)] == qrcode::Color::Dark {
+                for dy in 0..size {
+                    for dx in 0..size {
+                        let px = (border + x) * size + dx;
+                        let py = (border + y) * size + dy;
+                        img.put_pixel(px as u32, py as u32, Rgb([0, 0, 0]));
+                    }
+                }
+            }
+        }
+    }
+
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png)
+        .write_image(img.as_raw(), img_size as u32, img_size as u32, image::ColorType::Rgb8)
+        .unwrap();
+    png
+}
+
+fn qr_generation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("qr");
+
+    group.bench_function("legacy_pixel_by_pixel", |b| b.iter(|| legacy_render_png(black_box(URL))));
+    group.bench_function("blit_render", |b| b.iter(|| render_png(black_box(URL)).unwrap()));
+
+    let service = QrService::new();
+    service.png(URL).unwrap();
+    group.bench_function("cached", |b| b.iter(|| service.png(black_box(URL)).unwrap()));
+
+    group.finish();
+}
+
+criterion_group!(benches, qr_generation);
+criterion_main!(benches);
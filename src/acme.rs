@@ -0,0 +1,428 @@
+use actix_web::{web, HttpResponse};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::tls::{certified_key_from_pem, CertResolver};
+
+/// Как часто проверять срок действия сертификата
+const CHECK_INTERVAL_SECS: u64 = 12 * 3600;
+
+/// Пауза перед повтором после неудачного выпуска (лимиты Let's Encrypt строгие)
+const RETRY_AFTER_FAILURE_SECS: u64 = 3600;
+
+/// Опрос статуса authorization/order
+const POLL_ATTEMPTS: u32 = 30;
+const POLL_INTERVAL_SECS: u64 = 2;
+
+const ACCOUNT_KEY_FILE: &str = "account.pk8";
+const CERT_FILE: &str = "cert.pem";
+const KEY_FILE: &str = "key.pem";
+
+/// Ответы на HTTP-01 challenges: token -> key authorization
+#[derive(Clone, Default)]
+pub struct AcmeChallenges {
+    tokens: Arc<RwLock<HashMap<String, String>>>,
+}
+
+impl AcmeChallenges {
+    pub fn get(&self, token: &str) -> Option<String> {
+        self.tokens.read().unwrap_or_else(|e| e.into_inner()).get(token).cloned()
+    }
+
+    fn insert(&self, token: String, key_authorization: String) {
+        self.tokens.write().unwrap_or_else(|e| e.into_inner()).insert(token, key_authorization);
+    }
+
+    fn remove(&self, token: &str) {
+        self.tokens.write().unwrap_or_else(|e| e.into_inner()).remove(token);
+    }
+}
+
+/// `GET /.well-known/acme-challenge/{token}` на plain HTTP слушателе
+pub async fn acme_challenge(token: web::Path<String>, challenges: web::Data<AcmeChallenges>) -> HttpResponse {
+    match challenges.get(&token) {
+        Some(key_authorization) => HttpResponse::Ok()
+            .content_type("application/octet-stream")
+            .body(key_authorization),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+/// Выпуск и продление сертификата для `server.domain` по ACME (HTTP-01).
+/// Сертификат хранится в `acme.cert_dir` и подменяется в TLS без перезапуска.
+/// Challenges хранятся в памяти — рассчитано на одну реплику
+#[derive(Clone)]
+pub struct AcmeService {
+    config: Config,
+    resolver: CertResolver,
+    challenges: AcmeChallenges,
+    client: reqwest::Client,
+}
+
+impl AcmeService {
+    pub fn new(config: Config, resolver: CertResolver, challenges: AcmeChallenges) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .unwrap_or_default();
+
+        Self { config, resolver, challenges, client }
+    }
+
+    /// Фоновая проверка: загрузить сохраненный сертификат, выпустить/продлить при необходимости
+    pub fn spawn(&self) {
+        let service = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let pause = match service.ensure_certificate().await {
+                    Ok(()) => CHECK_INTERVAL_SECS,
+                    Err(e) => {
+                        tracing::error!(error = format!("{:#}", e), "ACME certificate provisioning failed");
+                        RETRY_AFTER_FAILURE_SECS
+                    }
+                };
+                tokio::time::sleep(Duration::from_secs(pause)).await;
+            }
+        });
+    }
+
+    /// Выпустить сертификат, если его нет или он скоро истечет
+    pub async fn ensure_certificate(&self) -> anyhow::Result<()> {
+        if let Some(expires_at) = self.load_saved()? {
+            let renew_at = expires_at - ChronoDuration::days(self.config.acme.renew_before_days as i64);
+            if Utc::now() < renew_at {
+                return Ok(());
+            }
+            tracing::info!(%expires_at, "ACME certificate expires soon, renewing");
+        }
+
+        let (cert_pem, key_pem) = self.issue().await?;
+        let certified_key = certified_key_from_pem(cert_pem.as_bytes(), key_pem.as_bytes())?;
+
+        write_file(&self.path(CERT_FILE), cert_pem.as_bytes(), false)?;
+        write_file(&self.path(KEY_FILE), key_pem.as_bytes(), true)?;
+        self.resolver.set(certified_key);
+
+        tracing::info!(domain = %self.config.server.domain, "ACME certificate installed");
+        Ok(())
+    }
+
+    /// Загрузить сертификат с диска в TLS; срок действия или `None`, если сертификата нет
+    fn load_saved(&self) -> anyhow::Result<Option<DateTime<Utc>>> {
+        let (cert_path, key_path) = (self.path(CERT_FILE), self.path(KEY_FILE));
+        if !cert_path.exists() || !key_path.exists() {
+            return Ok(None);
+        }
+
+        let cert_pem = std::fs::read(&cert_path)?;
+        let key_pem = std::fs::read(&key_path)?;
+        let certified_key = match certified_key_from_pem(&cert_pem, &key_pem) {
+            Ok(key) => key,
+            Err(e) => {
+                tracing::warn!(path = %cert_path.display(), error = %e, "Saved certificate is unusable, reissuing");
+                return Ok(None);
+            }
+        };
+        let expires_at = certificate_expiry(&cert_pem)?;
+
+        self.resolver.set(certified_key);
+        Ok(Some(expires_at))
+    }
+
+    /// Полный ACME цикл: аккаунт, order, HTTP-01 challenges, CSR, скачивание цепочки
+    async fn issue(&self) -> anyhow::Result<(String, String)> {
+        let domain = self.config.domain_host()
+            .ok_or_else(|| anyhow::anyhow!("Invalid server.domain: {}", self.config.server.domain))?;
+        tracing::info!(%domain, directory = %self.config.acme.directory_url, "Requesting ACME certificate");
+
+        let directory: Directory = self.client.get(&self.config.acme.directory_url)
+            .send().await?
+            .error_for_status()?
+            .json().await?;
+        let mut session = AcmeSession {
+            client: &self.client,
+            directory,
+            key: self.account_key()?,
+            kid: None,
+            nonce: None,
+        };
+
+        session.register(self.config.acme.contact_email.as_deref()).await?;
+        let (order_url, order) = session.new_order(&domain).await?;
+
+        for authorization_url in &order.authorizations {
+            self.authorize(&mut session, authorization_url).await?;
+        }
+
+        let mut params = rcgen::CertificateParams::new(vec![domain]);
+        params.distinguished_name = rcgen::DistinguishedName::new();
+        let certificate_key = rcgen::Certificate::from_params(params)?;
+        let csr = certificate_key.serialize_request_der()?;
+
+        session.post(&order.finalize, Some(&json!({ "csr": b64(&csr) }))).await?;
+        let order: Order = serde_json::from_value(session.poll_until_valid(&order_url).await?)?;
+        let certificate_url = order.certificate
+            .ok_or_else(|| anyhow::anyhow!("ACME order is valid but has no certificate URL"))?;
+
+        let cert_pem = session.post(&certificate_url, None).await?.text().await?;
+        Ok((cert_pem, certificate_key.serialize_private_key_pem()))
+    }
+
+    /// Пройти HTTP-01 challenge одной authorization
+    async fn authorize(&self, session: &mut AcmeSession<'_>, authorization_url: &str) -> anyhow::Result<()> {
+        let authorization: Authorization = session.post(authorization_url, None).await?.json().await?;
+        if authorization.status == "valid" {
+            return Ok(());
+        }
+
+        let challenge = authorization.challenges.iter()
+            .find(|c| c.kind == "http-01")
+            .ok_or_else(|| anyhow::anyhow!("CA offered no http-01 challenge for {}", authorization_url))?;
+        let key_authorization = format!("{}.{}", challenge.token, session.key.thumbprint());
+        self.challenges.insert(challenge.token.clone(), key_authorization);
+
+        let result = async {
+            session.post(&challenge.url, Some(&json!({}))).await?;
+            session.poll_until_valid(authorization_url).await
+        }.await;
+
+        self.challenges.remove(&challenge.token);
+        result.map(|_| ())
+    }
+
+    /// Ключ ACME аккаунта: с диска или новый
+    fn account_key(&self) -> anyhow::Result<AccountKey> {
+        let path = self.path(ACCOUNT_KEY_FILE);
+        let rng = SystemRandom::new();
+
+        let pkcs8 = if path.exists() {
+            std::fs::read(&path)?
+        } else {
+            let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+                .map_err(|_| anyhow::anyhow!("Failed to generate ACME account key"))?;
+            write_file(&path, pkcs8.as_ref(), true)?;
+            pkcs8.as_ref().to_vec()
+        };
+
+        let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &pkcs8, &rng)
+            .map_err(|e| anyhow::anyhow!("Invalid ACME account key {}: {}", path.display(), e))?;
+        Ok(AccountKey { pair, rng })
+    }
+
+    fn path(&self, file: &str) -> PathBuf {
+        Path::new(&self.config.acme.cert_dir).join(file)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Directory {
+    new_nonce: String,
+    new_account: String,
+    new_order: String,
+}
+
+#[derive(Deserialize)]
+struct Order {
+    authorizations: Vec<String>,
+    finalize: String,
+    certificate: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Authorization {
+    status: String,
+    challenges: Vec<Challenge>,
+}
+
+#[derive(Deserialize)]
+struct Challenge {
+    #[serde(rename = "type")]
+    kind: String,
+    url: String,
+    token: String,
+}
+
+/// Ключ аккаунта (ES256) для подписи JWS запросов
+struct AccountKey {
+    pair: EcdsaKeyPair,
+    rng: SystemRandom,
+}
+
+impl AccountKey {
+    /// Координаты публичного ключа P-256 (без префикса 0x04)
+    fn coordinates(&self) -> (String, String) {
+        let public = self.pair.public_key().as_ref();
+        (b64(&public[1..33]), b64(&public[33..65]))
+    }
+
+    fn jwk(&self) -> Value {
+        let (x, y) = self.coordinates();
+        json!({ "crv": "P-256", "kty": "EC", "x": x, "y": y })
+    }
+
+    /// JWK thumbprint (RFC 7638): поля в лексикографическом порядке без пробелов
+    fn thumbprint(&self) -> String {
+        let (x, y) = self.coordinates();
+        let canonical = format!(r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#, x, y);
+        b64(ring::digest::digest(&ring::digest::SHA256, canonical.as_bytes()).as_ref())
+    }
+
+    /// JWS в flattened JSON; без payload — POST-as-GET
+    fn sign(&self, url: &str, nonce: &str, kid: Option<&str>, payload: Option<&Value>) -> anyhow::Result<String> {
+        let mut protected = json!({ "alg": "ES256", "nonce": nonce, "url": url });
+        match kid {
+            Some(kid) => protected["kid"] = json!(kid),
+            None => protected["jwk"] = self.jwk(),
+        }
+        let protected = b64(&serde_json::to_vec(&protected)?);
+        let payload = match payload {
+            Some(payload) => b64(&serde_json::to_vec(payload)?),
+            None => String::new(),
+        };
+
+        let signature = self.pair.sign(&self.rng, format!("{}.{}", protected, payload).as_bytes())
+            .map_err(|_| anyhow::anyhow!("Failed to sign ACME request"))?;
+
+        Ok(json!({ "protected": protected, "payload": payload, "signature": b64(signature.as_ref()) }).to_string())
+    }
+}
+
+/// Запросы к CA от имени аккаунта с учетом Replay-Nonce
+struct AcmeSession<'a> {
+    client: &'a reqwest::Client,
+    directory: Directory,
+    key: AccountKey,
+    /// URL аккаунта после регистрации
+    kid: Option<String>,
+    nonce: Option<String>,
+}
+
+impl AcmeSession<'_> {
+    async fn register(&mut self, contact_email: Option<&str>) -> anyhow::Result<()> {
+        let mut payload = json!({ "termsOfServiceAgreed": true });
+        if let Some(email) = contact_email {
+            payload["contact"] = json!([format!("mailto:{}", email)]);
+        }
+
+        let url = self.directory.new_account.clone();
+        let response = self.post(&url, Some(&payload)).await?;
+        self.kid = Some(location(&response)?);
+        Ok(())
+    }
+
+    async fn new_order(&mut self, domain: &str) -> anyhow::Result<(String, Order)> {
+        let payload = json!({ "identifiers": [{ "type": "dns", "value": domain }] });
+        let url = self.directory.new_order.clone();
+        let response = self.post(&url, Some(&payload)).await?;
+        let order_url = location(&response)?;
+        Ok((order_url, response.json().await?))
+    }
+
+    /// Опрашивать authorization/order, пока статус не станет `valid`
+    async fn poll_until_valid(&mut self, url: &str) -> anyhow::Result<Value> {
+        for _ in 0..POLL_ATTEMPTS {
+            let resource: Value = self.post(url, None).await?.json().await?;
+            match resource["status"].as_str() {
+                Some("valid") => return Ok(resource),
+                Some("invalid") => anyhow::bail!("ACME resource {} is invalid: {}", url, resource),
+                _ => tokio::time::sleep(Duration::from_secs(POLL_INTERVAL_SECS)).await,
+            }
+        }
+        anyhow::bail!("ACME resource {} did not become valid in time", url)
+    }
+
+    /// Подписанный POST; один повтор при `badNonce`
+    async fn post(&mut self, url: &str, payload: Option<&Value>) -> anyhow::Result<reqwest::Response> {
+        let mut retried = false;
+        loop {
+            let nonce = self.nonce().await?;
+            let body = self.key.sign(url, &nonce, self.kid.as_deref(), payload)?;
+            let response = self.client.post(url)
+                .header("Content-Type", "application/jose+json")
+                .body(body)
+                .send()
+                .await?;
+            self.nonce = replay_nonce(&response);
+
+            if response.status().is_success() {
+                return Ok(response);
+            }
+
+            let status = response.status();
+            let problem: Value = response.json().await.unwrap_or_default();
+            if !retried && problem["type"] == "urn:ietf:params:acme:error:badNonce" {
+                retried = true;
+                continue;
+            }
+            anyhow::bail!(
+                "ACME request to {} failed ({}): {}",
+                url, status, problem["detail"].as_str().unwrap_or("no details")
+            );
+        }
+    }
+
+    async fn nonce(&mut self) -> anyhow::Result<String> {
+        if let Some(nonce) = self.nonce.take() {
+            return Ok(nonce);
+        }
+        let response = self.client.head(&self.directory.new_nonce).send().await?;
+        replay_nonce(&response).ok_or_else(|| anyhow::anyhow!("CA returned no Replay-Nonce"))
+    }
+}
+
+fn replay_nonce(response: &reqwest::Response) -> Option<String> {
+    response.headers().get("Replay-Nonce")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+fn location(response: &reqwest::Response) -> anyhow::Result<String> {
+    response.headers().get("Location")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+        .ok_or_else(|| anyhow::anyhow!("CA response has no Location header"))
+}
+
+fn b64(data: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(data)
+}
+
+/// Срок действия первого (leaf) сертификата цепочки
+fn certificate_expiry(cert_pem: &[u8]) -> anyhow::Result<DateTime<Utc>> {
+    let (_, pem) = x509_parser::pem::parse_x509_pem(cert_pem)
+        .map_err(|e| anyhow::anyhow!("Invalid certificate PEM: {}", e))?;
+    let certificate = pem.parse_x509()
+        .map_err(|e| anyhow::anyhow!("Invalid certificate: {}", e))?;
+    DateTime::from_timestamp(certificate.validity().not_after.timestamp(), 0)
+        .ok_or_else(|| anyhow::anyhow!("Certificate expiry out of range"))
+}
+
+/// Записать файл в `cert_dir`; ключи — только для владельца
+fn write_file(path: &Path, data: &[u8], private: bool) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, data)
+        .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))?;
+
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+
+    Ok(())
+}
@@ -0,0 +1,191 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use url::form_urlencoded::byte_serialize;
+
+use crate::config::Config;
+use crate::explorer::Cluster;
+use crate::payment::{Payment, PaymentStatus};
+
+/// Версия спецификации Solana Actions (заголовок `X-Action-Version`)
+pub const ACTION_VERSION: &str = "2.1.3";
+
+/// Иконка action, если у токена платежа нет логотипа
+pub const DEFAULT_ICON: &str = "https://solana.com/src/img/branding/solanaLogoMark.svg";
+
+/// Путь action платежа; Blink — `solana-action:<base_url>/api/actions/pay/{id}`
+pub const ACTIONS_PATH: &str = "/api/actions/pay";
+
+/// Заголовки CORS, которых требуют клиенты Blinks (X, кошельки, dial.to)
+pub const CORS_HEADERS: [(&str, &str); 4] = [
+    ("access-control-allow-origin", "*"),
+    ("access-control-allow-methods", "GET, POST, PUT, OPTIONS"),
+    (
+        "access-control-allow-headers",
+        "Content-Type, Authorization, Content-Encoding, Accept-Encoding, X-Action-Version, X-Blockchain-Ids",
+    ),
+    ("access-control-expose-headers", "X-Action-Version, X-Blockchain-Ids"),
+];
+
+/// Запрос к Actions API: с другого сайта, preflight пропускается для любого origin
+pub fn is_action_path(path: &str) -> bool {
+    path == "/actions.json" || path.starts_with("/api/actions/")
+}
+
+/// CAIP-2 идентификатор кластера (заголовок `X-Blockchain-Ids`)
+pub fn blockchain_id(cluster: Cluster) -> &'static str {
+    match cluster {
+        Cluster::MainnetBeta => "solana:5eykt4UsFv8P8NJdTREpY1vzqKqZKvdp",
+        Cluster::Devnet => "solana:EtWTRABZaYq6iMfeYKouRu166VU2xqa1",
+        Cluster::Testnet => "solana:4uhcVJyU9pJkvQyS88uRDiswHXSCkY3z",
+    }
+}
+
+/// Blink URL платежа для публикации в X и кошельках. С токеном transaction request
+/// ссылка передается URL-encoded, как того требует спецификация для query параметров
+pub fn action_url(config: &Config, payment_id: &str, token: Option<&str>) -> String {
+    let link = format!("{}{}/{}", config.base_url(), ACTIONS_PATH, payment_id);
+    match token {
+        Some(token) => format!(
+            "solana-action:{}",
+            byte_serialize(format!("{}?t={}", link, token).as_bytes()).collect::<String>(),
+        ),
+        None => format!("solana-action:{}", link),
+    }
+}
+
+/// `actions.json` домена: ссылки `/pay/{id}` раскрываются в action платежа
+pub fn actions_json() -> serde_json::Value {
+    serde_json::json!({
+        "rules": [
+            { "pathPattern": "/pay/*", "apiPath": format!("{}/*", ACTIONS_PATH) },
+            { "pathPattern": "/api/actions/**", "apiPath": "/api/actions/**" },
+        ]
+    })
+}
+
+/// Ответ GET action: карточка платежа с кнопками оплаты
+#[derive(Debug, Clone, Serialize)]
+pub struct ActionGetResponse {
+    /// `action` — можно оплатить, `completed` — платеж уже оплачен
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub icon: String,
+    pub title: String,
+    pub description: String,
+    pub label: String,
+    pub disabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub links: Option<ActionLinks>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ActionError>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ActionLinks {
+    pub actions: Vec<LinkedAction>,
+}
+
+/// Кнопка Blink: POST на `href` возвращает транзакцию
+#[derive(Debug, Clone, Serialize)]
+pub struct LinkedAction {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub label: String,
+    pub href: String,
+    /// Поля ввода; их значения подставляются в `href` вместо `{name}`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub parameters: Vec<ActionParameter>,
+}
+
+/// Поле ввода Blink (сумма intent платежа)
+#[derive(Debug, Clone, Serialize)]
+pub struct ActionParameter {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub name: &'static str,
+    pub label: String,
+    pub required: bool,
+    pub min: f64,
+    pub max: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ActionError {
+    pub message: String,
+}
+
+/// Тело POST action. Без deny_unknown_fields: клиенты присылают `type` и `data`
+#[derive(Debug, Clone, Deserialize)]
+pub struct ActionPostRequest {
+    pub account: String,
+}
+
+impl ActionGetResponse {
+    /// Карточка платежа. Чаевые — отдельные кнопки, сумму intent платежа вводит плательщик;
+    /// оплаченный или закрытый платеж показывается без кнопок
+    pub fn for_payment(payment: &Payment, config: &Config) -> Self {
+        let icon = config.merchant_icon(payment.merchant_id.as_deref())
+            .or_else(|| config.get_token_config(&payment.token).and_then(|token| token.logo_uri))
+            .unwrap_or_else(|| DEFAULT_ICON.to_string());
+        let label = format!("Pay {}", payment.total_display);
+        let mut response = Self {
+            kind: "action",
+            icon,
+            title: payment.label.clone(),
+            description: payment.message.clone(),
+            label: label.clone(),
+            disabled: false,
+            links: None,
+            error: None,
+        };
+
+        let closed = match payment.status {
+            PaymentStatus::Completed | PaymentStatus::Held => {
+                response.kind = "completed";
+                Some("Payment is already paid".to_string())
+            }
+            PaymentStatus::Pending if Utc::now() > payment.expires_at => Some("Payment has expired".to_string()),
+            status if !status.is_open() => Some(format!("Payment is {}", status)),
+            // Sandbox платеж завершается только через simulate_complete
+            _ if payment.sandbox => Some("Sandbox payments cannot be paid on-chain".to_string()),
+            _ => None,
+        };
+        if let Some(message) = closed {
+            response.disabled = true;
+            response.error = Some(ActionError { message });
+            return response;
+        }
+
+        let link = format!("{}{}/{}", config.base_url(), ACTIONS_PATH, payment.id);
+        let (href, separator) = match &payment.request_token {
+            Some(request_token) => (format!("{}?t={}", link, request_token.token), '&'),
+            None => (link, '?'),
+        };
+        if let Some(range) = payment.amount_range {
+            let action = LinkedAction {
+                kind: "transaction",
+                label: format!("Pay {}", payment.token),
+                href: format!("{}{}amount={{amount}}", href, separator),
+                parameters: vec![ActionParameter {
+                    kind: "number",
+                    name: "amount",
+                    label: format!("Amount from {} to {} {}", range.min, range.max, payment.token),
+                    required: true,
+                    min: range.min,
+                    max: range.max,
+                }],
+            };
+            response.links = Some(ActionLinks { actions: vec![action] });
+            return response;
+        }
+        let mut actions = vec![LinkedAction { kind: "transaction", label, href: href.clone(), parameters: Vec::new() }];
+        actions.extend(payment.tip_presets_bps.iter().map(|bps| LinkedAction {
+            kind: "transaction",
+            label: format!("Pay with {}% tip", *bps as f64 / 100.0),
+            href: format!("{}{}tip_bps={}", href, separator, bps),
+            parameters: Vec::new(),
+        }));
+        response.links = Some(ActionLinks { actions });
+        response
+    }
+}
@@ -0,0 +1,216 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{http::StatusCode, web, HttpMessage, HttpRequest, HttpResponse, ResponseError};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::config::{constant_time_eq, Config, MerchantConfig};
+use crate::tenants::TenantRegistry;
+
+/// Заголовок с API ключом мерчанта
+pub const API_KEY_HEADER: &str = "X-API-Key";
+
+/// Заголовок с ключом администратора
+pub const ADMIN_KEY_HEADER: &str = "X-Admin-Key";
+
+/// ID основного ключа мерчанта (`api_key`) в списке ключей
+pub const PRIMARY_KEY_ID: &str = "primary";
+
+/// Маршруты ключа `create_only`: создание платежей и то, что нужно терминалу, чтобы
+/// показать платеж покупателю и дождаться оплаты
+const CREATE_ROUTES: &[(Method, &str)] = &[
+    (Method::POST, "/api/payment/create"),
+    (Method::GET, "/api/payment/{id}"),
+    (Method::GET, "/api/payment/{id}/qr"),
+    (Method::GET, "/api/payment/{id}/events"),
+    (Method::GET, "/api/payment/{id}/wait"),
+    (Method::POST, "/api/pos/session"),
+    (Method::GET, "/api/pos/session/{id}"),
+    (Method::POST, "/api/pos/session/{id}/charge"),
+    (Method::POST, "/api/pools/{id}/claim"),
+];
+
+/// Запросы на чтение, которые отправляются методом POST
+const READ_POST_ROUTES: &[&str] = &["/api/payments/status", "/api/graphql", "/api/debug/decode-transaction"];
+
+/// Права API ключа мерчанта
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiKeyScope {
+    /// Все API мерчанта, включая webhooks и управление ключами
+    Admin,
+    /// Только создание платежей (POS терминалы, касса)
+    CreateOnly,
+    /// Только чтение: платежи, выгрузки, расчеты
+    ReadOnly,
+}
+
+impl ApiKeyScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiKeyScope::Admin => "admin",
+            ApiKeyScope::CreateOnly => "create_only",
+            ApiKeyScope::ReadOnly => "read_only",
+        }
+    }
+
+    /// Разрешен ли ключу запрос `method path`
+    pub fn permits(&self, method: &Method, path: &str) -> bool {
+        match self {
+            ApiKeyScope::Admin => true,
+            ApiKeyScope::CreateOnly => CREATE_ROUTES.iter()
+                .any(|(allowed, pattern)| allowed == method && route_matches(pattern, path)),
+            ApiKeyScope::ReadOnly => {
+                matches!(*method, Method::GET | Method::HEAD)
+                    || (*method == Method::POST && READ_POST_ROUTES.contains(&path))
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ApiKeyScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Путь под шаблон маршрута: `{...}` совпадает с любым непустым сегментом
+fn route_matches(pattern: &str, path: &str) -> bool {
+    let (mut pattern, mut path) = (pattern.split('/'), path.trim_end_matches('/').split('/'));
+    loop {
+        match (pattern.next(), path.next()) {
+            (None, None) => return true,
+            (Some(expected), Some(segment)) if expected.starts_with('{') && !segment.is_empty() => {}
+            (Some(expected), Some(segment)) if expected == segment => {}
+            _ => return false,
+        }
+    }
+}
+
+/// Ключ, которым авторизован запрос
+#[derive(Debug, Clone)]
+pub struct ApiKeyGrant {
+    pub merchant: Arc<MerchantConfig>,
+    /// `primary` или ID ключа из `api_keys`
+    pub key_id: String,
+    pub scope: ApiKeyScope,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("Invalid API key")]
+    InvalidApiKey,
+    #[error("API key has expired")]
+    ApiKeyExpired,
+    #[error("API key scope {0} does not allow this request")]
+    InsufficientScope(ApiKeyScope),
+    #[error("Merchant API key required")]
+    MerchantRequired,
+    #[error("Admin API is disabled")]
+    AdminDisabled,
+    #[error("Invalid admin key")]
+    InvalidAdminKey,
+}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::AdminDisabled => StatusCode::NOT_FOUND,
+            AuthError::InvalidApiKey
+            | AuthError::ApiKeyExpired
+            | AuthError::MerchantRequired
+            | AuthError::InvalidAdminKey => StatusCode::UNAUTHORIZED,
+            AuthError::InsufficientScope(_) => StatusCode::FORBIDDEN,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(serde_json::json!({
+            "success": false, "error": self.to_string()
+        }))
+    }
+}
+
+/// Проверить ключ для запроса `method path`: ключ известен, не истек и его права
+/// допускают запрос. Успешная проверка отмечает использование ключа
+pub fn authorize_key(
+    tenants: &TenantRegistry,
+    api_key: &str,
+    method: &Method,
+    path: &str,
+) -> Result<ApiKeyGrant, AuthError> {
+    let grant = tenants.authenticate(api_key).ok_or(AuthError::InvalidApiKey)?;
+    if grant.expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+        return Err(AuthError::ApiKeyExpired);
+    }
+    if !grant.scope.permits(method, path) {
+        return Err(AuthError::InsufficientScope(grant.scope));
+    }
+    tenants.record_use(&grant.merchant.id, &grant.key_id);
+    Ok(grant)
+}
+
+/// Middleware: проверка X-API-Key (срок действия и права ключа) до обработчика.
+/// Проверенный ключ сохраняется в запросе для `resolve_merchant`
+pub async fn authorize(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let tenants = req.app_data::<web::Data<TenantRegistry>>().cloned();
+    let header = req.headers().get(API_KEY_HEADER).map(|value| value.to_str().unwrap_or_default().to_string());
+    let (Some(tenants), Some(api_key)) = (tenants, header) else {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    };
+    match authorize_key(&tenants, &api_key, req.method(), req.path()) {
+        Ok(grant) => {
+            req.extensions_mut().insert(grant);
+            next.call(req).await.map(ServiceResponse::map_into_left_body)
+        }
+        Err(e) => {
+            tracing::warn!(method = %req.method(), path = req.path(), error = %e, "API key rejected");
+            let response = e.error_response();
+            Ok(req.into_response(response).map_into_right_body())
+        }
+    }
+}
+
+/// Мерчант по заголовку X-API-Key. Без заголовка — анонимный запрос с глобальной политикой
+pub fn resolve_merchant(
+    req: &HttpRequest,
+    config: &Config,
+) -> Result<Option<Arc<MerchantConfig>>, AuthError> {
+    if let Some(grant) = req.extensions().get::<ApiKeyGrant>() {
+        return Ok(Some(grant.merchant.clone()));
+    }
+    let Some(header) = req.headers().get(API_KEY_HEADER) else {
+        return Ok(None);
+    };
+
+    // Без middleware `authorize` (тесты, отдельные приложения) — та же проверка здесь
+    let api_key = header.to_str().map_err(|_| AuthError::InvalidApiKey)?;
+    authorize_key(&config.tenants, api_key, req.method(), req.path()).map(|grant| Some(grant.merchant))
+}
+
+/// Мерчант по заголовку X-API-Key, анонимные запросы запрещены
+pub fn require_merchant(
+    req: &HttpRequest,
+    config: &Config,
+) -> Result<Arc<MerchantConfig>, AuthError> {
+    resolve_merchant(req, config)?.ok_or(AuthError::MerchantRequired)
+}
+
+/// Проверка заголовка X-Admin-Key для /admin эндпоинтов
+pub fn require_admin(req: &HttpRequest, config: &Config) -> Result<(), AuthError> {
+    let expected = config.server.admin_api_key.as_ref()
+        .ok_or(AuthError::AdminDisabled)?;
+
+    let provided = req.headers().get(ADMIN_KEY_HEADER).and_then(|v| v.to_str().ok());
+    match provided {
+        Some(key) if constant_time_eq(key.as_bytes(), expected.as_bytes()) => Ok(()),
+        _ => Err(AuthError::InvalidAdminKey),
+    }
+}
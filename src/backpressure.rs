@@ -0,0 +1,141 @@
+use serde::Serialize;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+use crate::config::ServerConfig;
+
+/// Сборка транзакции не началась: все слоты заняты, а очередь полна или ожидание истекло
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum Saturated {
+    #[error("Transaction builder is saturated: {queued} requests already queued")]
+    QueueFull { queued: usize, retry_after_secs: u64 },
+    #[error("Timed out waiting for a free transaction builder slot")]
+    QueueTimeout { retry_after_secs: u64 },
+}
+
+impl Saturated {
+    pub fn retry_after_secs(&self) -> u64 {
+        match self {
+            Saturated::QueueFull { retry_after_secs, .. } | Saturated::QueueTimeout { retry_after_secs } => {
+                *retry_after_secs
+            }
+        }
+    }
+}
+
+/// Состояние очереди сборки транзакций
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildQueueStats {
+    pub max_concurrent: usize,
+    /// Собираются сейчас
+    pub in_flight: usize,
+    pub max_queued: usize,
+    /// Ждут свободного слота
+    pub queued: usize,
+    /// Наибольшая глубина очереди с запуска
+    pub peak_queued: usize,
+    pub admitted_total: u64,
+    pub rejected_total: u64,
+}
+
+struct Inner {
+    slots: Semaphore,
+    max_concurrent: usize,
+    max_queued: usize,
+    queue_timeout: Duration,
+    queued: AtomicUsize,
+    peak_queued: AtomicUsize,
+    admitted: AtomicU64,
+    rejected: AtomicU64,
+}
+
+/// Ограничение параллельных сборок транзакций: каждая сборка ходит в RPC, поэтому
+/// сверх `max_concurrent` запросы ждут в очереди, а переполнение получает отказ
+/// вместо роста задержки у всех
+#[derive(Clone)]
+pub struct BuildLimiter {
+    inner: Arc<Inner>,
+}
+
+/// Снимает запрос с очереди и при отмене ожидания (клиент отключился)
+struct QueuedGuard<'a>(&'a AtomicUsize);
+
+impl Drop for QueuedGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl BuildLimiter {
+    pub fn new(max_concurrent: usize, max_queued: usize, queue_timeout: Duration) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                slots: Semaphore::new(max_concurrent),
+                max_concurrent,
+                max_queued,
+                queue_timeout,
+                queued: AtomicUsize::new(0),
+                peak_queued: AtomicUsize::new(0),
+                admitted: AtomicU64::new(0),
+                rejected: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    pub fn from_config(config: &ServerConfig) -> Self {
+        Self::new(
+            config.max_concurrent_builds,
+            config.max_queued_builds,
+            Duration::from_millis(config.build_queue_timeout_ms),
+        )
+    }
+
+    /// Через сколько секунд клиенту стоит повторить запрос
+    fn retry_after_secs(&self) -> u64 {
+        self.inner.queue_timeout.as_secs_f64().ceil().max(1.0) as u64
+    }
+
+    /// Выполнить сборку в свободном слоте, дождавшись его в очереди не дольше `build_queue_timeout_ms`
+    pub async fn run<F: Future>(&self, build: F) -> Result<F::Output, Saturated> {
+        let inner = &self.inner;
+        let permit = match inner.slots.try_acquire() {
+            Ok(permit) => permit,
+            Err(_) => {
+                let queued = inner.queued.fetch_add(1, Ordering::SeqCst) + 1;
+                let _guard = QueuedGuard(&inner.queued);
+                if queued > inner.max_queued {
+                    inner.rejected.fetch_add(1, Ordering::Relaxed);
+                    return Err(Saturated::QueueFull { queued: queued - 1, retry_after_secs: self.retry_after_secs() });
+                }
+                inner.peak_queued.fetch_max(queued, Ordering::Relaxed);
+                match tokio::time::timeout(inner.queue_timeout, inner.slots.acquire()).await {
+                    Ok(Ok(permit)) => permit,
+                    _ => {
+                        inner.rejected.fetch_add(1, Ordering::Relaxed);
+                        return Err(Saturated::QueueTimeout { retry_after_secs: self.retry_after_secs() });
+                    }
+                }
+            }
+        };
+        inner.admitted.fetch_add(1, Ordering::Relaxed);
+        let output = build.await;
+        drop(permit);
+        Ok(output)
+    }
+
+    pub fn stats(&self) -> BuildQueueStats {
+        let inner = &self.inner;
+        BuildQueueStats {
+            max_concurrent: inner.max_concurrent,
+            in_flight: inner.max_concurrent - inner.slots.available_permits(),
+            max_queued: inner.max_queued,
+            queued: inner.queued.load(Ordering::SeqCst),
+            peak_queued: inner.peak_queued.load(Ordering::Relaxed),
+            admitted_total: inner.admitted.load(Ordering::Relaxed),
+            rejected_total: inner.rejected.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,377 @@
+//! Администрирование инстанса из терминала через admin API (заголовок X-Admin-Key):
+//! платежи, ручная смена статуса, повторная отправка webhooks, фоновые задачи, токены, мерчанты,
+//! коды скидок, споры, статистика и сверка.
+//!
+//! Запуск: `cargo run --bin cryptonow-admin -- --url http://127.0.0.1:3001 payments list --status pending`
+//!
+//! Хранилище живет в памяти процесса сервера, поэтому все команды идут через HTTP.
+//! Ответ печатается как JSON; при ошибке API — код выхода 1.
+
+use clap::{Parser, Subcommand, ValueEnum};
+use crypto_server::auth::ADMIN_KEY_HEADER;
+use serde_json::{json, Value};
+use std::io::Write;
+
+#[derive(Parser)]
+#[command(name = "cryptonow-admin", about = "CryptoNow operator CLI (admin API)")]
+struct Cli {
+    /// Адрес сервера
+    #[arg(long, env = "CRYPTONOW_URL", default_value = "http://127.0.0.1:3001")]
+    url: String,
+    /// Ключ admin API
+    #[arg(long, env = "ADMIN_API_KEY", hide_env_values = true)]
+    admin_key: String,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Платежи
+    #[command(subcommand)]
+    Payments(PaymentsCommand),
+    /// Доставки webhooks
+    #[command(subcommand)]
+    Webhooks(WebhooksCommand),
+    /// Фоновые задачи
+    #[command(subcommand)]
+    Jobs(JobsCommand),
+    /// Реестр токенов
+    #[command(subcommand)]
+    Tokens(TokensCommand),
+    /// Мерчанты и их настройки
+    #[command(subcommand)]
+    Merchants(MerchantsCommand),
+    /// Коды скидок
+    #[command(subcommand)]
+    Discounts(DiscountsCommand),
+    /// Споры по платежам
+    #[command(subcommand)]
+    Disputes(DisputesCommand),
+    /// Выручка, очередь сборки транзакций и состояние RPC
+    Stats,
+    /// Сверка незавершенных платежей с блокчейном
+    Reconcile {
+        /// Только показать, что изменится
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum PaymentsCommand {
+    /// Последние платежи всех мерчантов
+    List {
+        #[arg(long)]
+        status: Option<String>,
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+    },
+    /// Перевести ожидающий платеж в expired
+    Expire {
+        payment_id: String,
+        #[arg(long, default_value = "Expired by operator")]
+        reason: String,
+    },
+    /// Отменить ожидающий платеж
+    Cancel {
+        payment_id: String,
+        #[arg(long, default_value = "Cancelled by operator")]
+        reason: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum WebhooksCommand {
+    /// Доставки webhooks, новые первыми
+    Deliveries {
+        #[arg(long, value_enum)]
+        status: Option<DeliveryFilter>,
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+    },
+    /// Повторно отправить доставки по ID
+    Resend {
+        #[arg(required = true)]
+        delivery_ids: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum JobsCommand {
+    /// Расписания и итоги последних запусков
+    List,
+    /// Выполнить задачу сейчас, вне расписания
+    Run { name: String },
+}
+
+#[derive(Subcommand)]
+enum TokensCommand {
+    /// Токены реестра с источником
+    List,
+    /// Добавить токен по символу из token list или адресу mint
+    Add {
+        token: String,
+        /// Свой символ вместо найденного
+        #[arg(long)]
+        symbol: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum MerchantsCommand {
+    /// Мерчанты с источником настроек
+    List,
+    /// Настройки мерчанта
+    Show { merchant_id: String },
+    /// Создать мерчанта или заменить его настройки из JSON файла (поля как в [[merchants]])
+    Put { file: std::path::PathBuf },
+    /// Удалить мерчанта, созданного через admin API
+    Delete { merchant_id: String },
+}
+
+#[derive(Subcommand)]
+enum DiscountsCommand {
+    /// Коды с числом использований
+    List,
+    /// Код и платежи, которые его использовали
+    Show { code: String },
+    /// Создать код: `--percent-bps 1000` (10%) или `--amount 5 --token USDC`
+    Create {
+        code: String,
+        #[arg(long, conflicts_with_all = ["amount", "token"], required_unless_present = "amount")]
+        percent_bps: Option<u16>,
+        #[arg(long, requires = "token")]
+        amount: Option<f64>,
+        #[arg(long)]
+        token: Option<String>,
+        /// Только для платежей мерчанта
+        #[arg(long)]
+        merchant: Option<String>,
+        /// RFC 3339, например 2026-12-31T23:59:59Z
+        #[arg(long)]
+        expires_at: Option<String>,
+        #[arg(long)]
+        max_uses: Option<u32>,
+    },
+    /// Выключить код
+    Disable { code: String },
+}
+
+#[derive(Subcommand)]
+enum DisputesCommand {
+    /// Споры, новые первыми
+    List {
+        #[arg(long, value_enum)]
+        status: Option<DisputeFilter>,
+        #[arg(long)]
+        merchant: Option<String>,
+    },
+    /// Спор с журналом действий
+    Show { dispute_id: String },
+    /// Решить спор: возврат плательщику или отказ
+    Resolve {
+        dispute_id: String,
+        #[arg(value_enum)]
+        resolution: DisputeResolution,
+        #[arg(long)]
+        note: Option<String>,
+        /// Транзакция ручного возврата завершенного платежа
+        #[arg(long)]
+        refund_signature: Option<String>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DisputeFilter {
+    Open,
+    Refunded,
+    Denied,
+}
+
+impl DisputeFilter {
+    fn as_str(self) -> &'static str {
+        match self {
+            DisputeFilter::Open => "open",
+            DisputeFilter::Refunded => "refunded",
+            DisputeFilter::Denied => "denied",
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DisputeResolution {
+    Refund,
+    Deny,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DeliveryFilter {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+impl DeliveryFilter {
+    fn as_str(self) -> &'static str {
+        match self {
+            DeliveryFilter::Pending => "pending",
+            DeliveryFilter::Delivered => "delivered",
+            DeliveryFilter::Failed => "failed",
+        }
+    }
+}
+
+/// Клиент admin API
+struct AdminClient {
+    http: reqwest::Client,
+    url: String,
+    admin_key: String,
+}
+
+impl AdminClient {
+    /// `data` успешного ответа; иначе ошибка с текстом API
+    async fn call(&self, method: reqwest::Method, path: &str, body: Option<Value>) -> anyhow::Result<Value> {
+        let mut request = self.http
+            .request(method, format!("{}{}", self.url, path))
+            .header(ADMIN_KEY_HEADER, &self.admin_key);
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        let body: Value = response.json().await
+            .map_err(|e| anyhow::anyhow!("{} returned {} with a non-JSON body: {}", path, status, e))?;
+        if !status.is_success() || body["success"] == false {
+            anyhow::bail!("{} returned {}: {}", path, status, body.get("error").unwrap_or(&body));
+        }
+        Ok(body.get("data").cloned().unwrap_or(Value::Null))
+    }
+
+    async fn get(&self, path: &str) -> anyhow::Result<Value> {
+        self.call(reqwest::Method::GET, path, None).await
+    }
+
+    async fn post(&self, path: &str, body: Option<Value>) -> anyhow::Result<Value> {
+        self.call(reqwest::Method::POST, path, body).await
+    }
+}
+
+async fn run(cli: Cli) -> anyhow::Result<Value> {
+    let client = AdminClient {
+        http: reqwest::Client::builder().timeout(std::time::Duration::from_secs(60)).build()?,
+        url: cli.url.trim_end_matches('/').to_string(),
+        admin_key: cli.admin_key,
+    };
+
+    match cli.command {
+        Command::Payments(PaymentsCommand::List { status, limit }) => {
+            let status = status.map(|status| format!("&status={}", status)).unwrap_or_default();
+            client.get(&format!("/api/payments?limit={}{}", limit, status)).await
+        }
+        Command::Payments(PaymentsCommand::Expire { payment_id, reason }) => {
+            let body = json!({"status": "expired", "reason": reason});
+            client.post(&format!("/admin/payments/{}/status", payment_id), Some(body)).await
+        }
+        Command::Payments(PaymentsCommand::Cancel { payment_id, reason }) => {
+            let body = json!({"status": "cancelled", "reason": reason});
+            client.post(&format!("/admin/payments/{}/status", payment_id), Some(body)).await
+        }
+        Command::Webhooks(WebhooksCommand::Deliveries { status, limit }) => {
+            let status = status.map(|status| format!("&status={}", status.as_str())).unwrap_or_default();
+            client.get(&format!("/admin/webhooks/deliveries?limit={}{}", limit, status)).await
+        }
+        Command::Webhooks(WebhooksCommand::Resend { delivery_ids }) => {
+            let mut results = Vec::new();
+            for id in delivery_ids {
+                let result = client.post(&format!("/admin/webhooks/deliveries/{}/redeliver", id), None).await;
+                results.push(match result {
+                    Ok(delivery) => json!({"delivery_id": id, "status": delivery["status"]}),
+                    Err(e) => json!({"delivery_id": id, "error": e.to_string()}),
+                });
+            }
+            Ok(Value::Array(results))
+        }
+        Command::Jobs(JobsCommand::List) => client.get("/admin/jobs").await,
+        Command::Jobs(JobsCommand::Run { name }) => client.post(&format!("/admin/jobs/{}/run", name), None).await,
+        Command::Tokens(TokensCommand::List) => client.get("/admin/tokens").await,
+        Command::Tokens(TokensCommand::Add { token, symbol }) => {
+            client.post("/admin/tokens", Some(json!({"token": token, "symbol": symbol}))).await
+        }
+        Command::Merchants(MerchantsCommand::List) => client.get("/admin/merchants").await,
+        Command::Merchants(MerchantsCommand::Show { merchant_id }) => {
+            client.get(&format!("/admin/merchants/{}", merchant_id)).await
+        }
+        Command::Merchants(MerchantsCommand::Put { file }) => {
+            let body: Value = serde_json::from_slice(&std::fs::read(&file)?)?;
+            client.post("/admin/merchants", Some(body)).await
+        }
+        Command::Merchants(MerchantsCommand::Delete { merchant_id }) => {
+            client.call(reqwest::Method::DELETE, &format!("/admin/merchants/{}", merchant_id), None).await
+        }
+        Command::Discounts(DiscountsCommand::List) => client.get("/admin/discounts").await,
+        Command::Discounts(DiscountsCommand::Show { code }) => client.get(&format!("/admin/discounts/{}", code)).await,
+        Command::Discounts(DiscountsCommand::Create {
+            code, percent_bps, amount, token, merchant, expires_at, max_uses,
+        }) => {
+            let kind = match percent_bps {
+                Some(percent_bps) => json!({"type": "percentage", "percent_bps": percent_bps}),
+                None => json!({"type": "fixed", "amount": amount, "token": token}),
+            };
+            let mut body = json!({
+                "code": code, "merchant_id": merchant, "expires_at": expires_at, "max_uses": max_uses,
+            });
+            if let (Value::Object(body), Value::Object(kind)) = (&mut body, kind) {
+                body.extend(kind);
+            }
+            client.post("/admin/discounts", Some(body)).await
+        }
+        Command::Discounts(DiscountsCommand::Disable { code }) => {
+            client.call(reqwest::Method::DELETE, &format!("/admin/discounts/{}", code), None).await
+        }
+        Command::Disputes(DisputesCommand::List { status, merchant }) => {
+            let mut query = Vec::new();
+            if let Some(status) = status {
+                query.push(format!("status={}", status.as_str()));
+            }
+            if let Some(merchant) = merchant {
+                query.push(format!("merchant_id={}", merchant));
+            }
+            client.get(&format!("/admin/disputes?{}", query.join("&"))).await
+        }
+        Command::Disputes(DisputesCommand::Show { dispute_id }) => {
+            client.get(&format!("/admin/disputes/{}", dispute_id)).await
+        }
+        Command::Disputes(DisputesCommand::Resolve { dispute_id, resolution, note, refund_signature }) => {
+            let resolution = match resolution {
+                DisputeResolution::Refund => "refund",
+                DisputeResolution::Deny => "deny",
+            };
+            let body = json!({"resolution": resolution, "note": note, "refund_signature": refund_signature});
+            client.post(&format!("/admin/disputes/{}/resolve", dispute_id), Some(body)).await
+        }
+        Command::Stats => {
+            let (revenue, builds, rpc) = tokio::try_join!(
+                client.get("/admin/revenue"),
+                client.get("/admin/builds"),
+                client.get("/admin/rpc"),
+            )?;
+            Ok(json!({"revenue": revenue, "builds": builds, "rpc": rpc}))
+        }
+        Command::Reconcile { dry_run } => client.post(&format!("/admin/reconcile?dry_run={}", dry_run), None).await,
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    match run(Cli::parse()).await {
+        // Закрытый pipe (`| head`) — не ошибка
+        Ok(output) => {
+            let _ = writeln!(std::io::stdout(), "{}", serde_json::to_string_pretty(&output).unwrap_or_default());
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
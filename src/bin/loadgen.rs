@@ -0,0 +1,204 @@
+//! Нагрузочный тест локального инстанса: N параллельных сценариев
+//! create → transaction → verify с p50/p99 по каждому шагу.
+//!
+//! Запуск: `cargo run --release --bin loadgen -- --url http://127.0.0.1:3001 --concurrency 50 --flows 1000`
+//!
+//! Шаг transaction требует доступного RPC (blockhash). Транзакции не подписываются и
+//! не отправляются, поэтому verify завершается ошибкой проверки — меряется время ответа.
+
+use solana_sdk::signature::{Keypair, Signature, Signer};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+const STEPS: [&str; 3] = ["create", "transaction", "verify"];
+
+struct Options {
+    url: String,
+    concurrency: usize,
+    flows: usize,
+    api_key: Option<String>,
+    recipient: String,
+    token: String,
+    amount: f64,
+}
+
+impl Options {
+    fn parse() -> anyhow::Result<Self> {
+        let mut options = Options {
+            url: "http://127.0.0.1:3001".to_string(),
+            concurrency: 10,
+            flows: 100,
+            api_key: None,
+            recipient: Keypair::new().pubkey().to_string(),
+            token: "SOL".to_string(),
+            amount: 0.01,
+        };
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| anyhow::anyhow!("{} requires a value", arg));
+            match arg.as_str() {
+                "--url" => options.url = value()?.trim_end_matches('/').to_string(),
+                "--concurrency" => options.concurrency = value()?.parse()?,
+                "--flows" => options.flows = value()?.parse()?,
+                "--api-key" => options.api_key = Some(value()?),
+                "--recipient" => options.recipient = value()?,
+                "--token" => options.token = value()?,
+                "--amount" => options.amount = value()?.parse()?,
+                "--help" | "-h" => {
+                    println!(
+                        "Usage: loadgen [--url URL] [--concurrency N] [--flows N] [--api-key KEY] \
+                         [--recipient PUBKEY] [--token SYMBOL] [--amount AMOUNT]"
+                    );
+                    std::process::exit(0);
+                }
+                other => anyhow::bail!("Unknown argument: {}", other),
+            }
+        }
+
+        if options.concurrency == 0 || options.flows == 0 {
+            anyhow::bail!("--concurrency and --flows must be positive");
+        }
+        Ok(options)
+    }
+}
+
+/// Результаты одного шага сценария
+#[derive(Default)]
+struct StepStats {
+    latencies: Vec<Duration>,
+    errors: usize,
+}
+
+impl StepStats {
+    fn percentile(sorted: &[Duration], p: f64) -> Duration {
+        if sorted.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+        sorted[rank.clamp(1, sorted.len()) - 1]
+    }
+
+    fn report(&mut self, name: &str) {
+        self.latencies.sort();
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        println!(
+            "{:<12} {:>7} {:>7} {:>10.2} {:>10.2} {:>10.2}",
+            name,
+            self.latencies.len(),
+            self.errors,
+            ms(Self::percentile(&self.latencies, 50.0)),
+            ms(Self::percentile(&self.latencies, 99.0)),
+            ms(self.latencies.last().copied().unwrap_or_default()),
+        );
+    }
+}
+
+struct LoadGen {
+    options: Options,
+    client: reqwest::Client,
+    stats: Mutex<[StepStats; 3]>,
+}
+
+impl LoadGen {
+    /// Выполнить запрос шага и записать латентность; тело ответа — при успехе
+    async fn step(&self, index: usize, request: reqwest::RequestBuilder) -> Option<serde_json::Value> {
+        let started = Instant::now();
+        let result = async {
+            let response = request.send().await?;
+            let success = response.status().is_success();
+            let body: serde_json::Value = response.json().await.unwrap_or_default();
+            Ok::<_, reqwest::Error>((success, body))
+        }.await;
+        let elapsed = started.elapsed();
+
+        let mut stats = self.stats.lock().await;
+        stats[index].latencies.push(elapsed);
+        match result {
+            Ok((true, body)) => Some(body),
+            _ => {
+                stats[index].errors += 1;
+                None
+            }
+        }
+    }
+
+    async fn flow(&self) {
+        let base = &self.options.url;
+        let mut create = self.client.post(format!("{}/api/payment/create", base))
+            .json(&serde_json::json!({
+                "recipient": self.options.recipient,
+                "amount": self.options.amount,
+                "token": self.options.token,
+            }));
+        if let Some(api_key) = &self.options.api_key {
+            create = create.header("X-API-Key", api_key);
+        }
+        let Some(created) = self.step(0, create).await else {
+            return;
+        };
+        let Some(payment_id) = created["data"]["id"].as_str() else {
+            return;
+        };
+
+        let payer = Keypair::new().pubkey().to_string();
+        let transaction = self.client.post(format!("{}/api/payment/{}/transaction", base, payment_id))
+            .json(&serde_json::json!({ "account": payer }));
+        self.step(1, transaction).await;
+
+        // Подпись-заглушка: транзакция не отправлялась, verify ответит ошибкой проверки
+        let verify = self.client.post(format!("{}/api/payment/{}/verify", base, payment_id))
+            .json(&serde_json::json!({ "signature": Signature::new_unique().to_string() }));
+        self.step(2, verify).await;
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let options = Options::parse()?;
+    println!(
+        "Load test: {} flows, concurrency {}, target {}",
+        options.flows, options.concurrency, options.url
+    );
+
+    let client = reqwest::Client::builder()
+        .pool_max_idle_per_host(options.concurrency)
+        .timeout(Duration::from_secs(30))
+        .build()?;
+    let (flows, concurrency) = (options.flows, options.concurrency);
+    let loadgen = Arc::new(LoadGen { options, client, stats: Mutex::default() });
+
+    let next = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let started = Instant::now();
+    let workers: Vec<_> = (0..concurrency)
+        .map(|_| {
+            let (loadgen, next) = (loadgen.clone(), next.clone());
+            tokio::spawn(async move {
+                while next.fetch_add(1, std::sync::atomic::Ordering::Relaxed) < flows {
+                    loadgen.flow().await;
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.await?;
+    }
+    let elapsed = started.elapsed();
+
+    println!();
+    println!("{:<12} {:>7} {:>7} {:>10} {:>10} {:>10}", "step", "count", "errors", "p50 ms", "p99 ms", "max ms");
+    let mut stats = loadgen.stats.lock().await;
+    for (name, step) in STEPS.iter().zip(stats.iter_mut()) {
+        step.report(name);
+    }
+    println!();
+    println!(
+        "{} flows in {:.2}s ({:.1} flows/s)",
+        flows,
+        elapsed.as_secs_f64(),
+        flows as f64 / elapsed.as_secs_f64()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,146 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::error::{InternalError, JsonPayloadError};
+use actix_web::http::header;
+use actix_web::middleware::Next;
+use actix_web::{web, HttpMessage, HttpResponse};
+use bytes::BytesMut;
+use futures::StreamExt;
+
+use crate::config::ServerConfig;
+
+/// Ограничения JSON тела запроса
+#[derive(Debug, Clone, Copy)]
+pub struct JsonLimits {
+    pub max_bytes: usize,
+    pub max_depth: usize,
+}
+
+impl JsonLimits {
+    pub fn from_config(config: &ServerConfig) -> Self {
+        Self { max_bytes: config.max_json_body_bytes, max_depth: config.max_json_depth }
+    }
+
+    /// Конфигурация `web::Json`: лимит размера и структурированные ошибки разбора
+    pub fn json_config(&self) -> web::JsonConfig {
+        web::JsonConfig::default()
+            .limit(self.max_bytes)
+            .error_handler(|error, _| {
+                let response = json_error_response(&error);
+                InternalError::from_response(error, response).into()
+            })
+    }
+}
+
+/// 413 с лимитом, который превышен
+pub fn payload_too_large(limit: usize) -> HttpResponse {
+    HttpResponse::PayloadTooLarge().json(serde_json::json!({
+        "success": false,
+        "error": format!("Request body exceeds {} bytes", limit),
+        "code": "payload_too_large",
+        "limit": limit,
+    }))
+}
+
+fn json_error_response(error: &JsonPayloadError) -> HttpResponse {
+    let (code, message) = match error {
+        JsonPayloadError::OverflowKnownLength { limit, .. } | JsonPayloadError::Overflow { limit } => {
+            return payload_too_large(*limit);
+        }
+        JsonPayloadError::ContentType => {
+            return HttpResponse::UnsupportedMediaType().json(serde_json::json!({
+                "success": false,
+                "error": "Content-Type must be application/json",
+                "code": "unsupported_media_type",
+            }));
+        }
+        // deny_unknown_fields: лишнее поле не проходит мимо валидации молча
+        JsonPayloadError::Deserialize(e) if e.to_string().starts_with("unknown field") => ("unknown_field", e.to_string()),
+        JsonPayloadError::Deserialize(e) => ("invalid_json", e.to_string()),
+        e => ("invalid_body", e.to_string()),
+    };
+    HttpResponse::BadRequest().json(serde_json::json!({
+        "success": false,
+        "error": format!("Invalid request body: {}", message),
+        "code": code,
+    }))
+}
+
+/// Наибольшая вложенность объектов и массивов JSON; разбор прекращается, как только
+/// превышен `limit`. Строки (в том числе скобки в них) пропускаются
+pub fn json_depth(body: &[u8], limit: usize) -> usize {
+    let (mut depth, mut max_depth) = (0usize, 0usize);
+    let (mut in_string, mut escaped) = (false, false);
+    for &byte in body {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'{' | b'[' => {
+                depth += 1;
+                max_depth = max_depth.max(depth);
+                if max_depth > limit {
+                    break;
+                }
+            }
+            b'}' | b']' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    max_depth
+}
+
+fn is_json(req: &ServiceRequest) -> bool {
+    req.headers().get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|mime| {
+            let mime = mime.trim().to_ascii_lowercase();
+            mime == "application/json" || mime.ends_with("+json")
+        })
+        .unwrap_or(false)
+}
+
+/// Middleware: JSON тело читается с лимитом размера и отклоняется при чрезмерной
+/// вложенности до десериализации (serde разбирает вложенность рекурсивно)
+pub async fn json_guard(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let limits = req.app_data::<web::Data<JsonLimits>>()
+        .map(|limits| *limits.get_ref())
+        .filter(|_| is_json(&req));
+    let Some(limits) = limits else {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    };
+
+    let mut payload = req.take_payload();
+    let mut body = BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk?;
+        if body.len() + chunk.len() > limits.max_bytes {
+            return Ok(req.into_response(payload_too_large(limits.max_bytes)).map_into_right_body());
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    if json_depth(&body, limits.max_depth) > limits.max_depth {
+        let response = HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false,
+            "error": format!("Request body is nested deeper than {} levels", limits.max_depth),
+            "code": "json_too_deep",
+            "limit": limits.max_depth,
+        }));
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+
+    req.set_payload(Payload::from(body.freeze()));
+    next.call(req).await.map(ServiceResponse::map_into_left_body)
+}
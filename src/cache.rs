@@ -0,0 +1,198 @@
+use actix_web::http::header::{EntityTag, Header, IfNoneMatch};
+use actix_web::HttpRequest;
+use chrono::Utc;
+use lru::LruCache;
+use serde::Serialize;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::StorageConfig;
+use crate::payment::{Payment, PaymentStatus};
+
+/// Счетчики кэша платежей
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheStats {
+    pub enabled: bool,
+    pub capacity: usize,
+    pub entries: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub invalidations: u64,
+    /// Доля чтений из кэша; 0 — чтений еще не было
+    pub hit_rate: f64,
+}
+
+struct CachedPayment {
+    payment: Payment,
+    valid_until: Instant,
+}
+
+struct Inner {
+    entries: Mutex<LruCache<String, CachedPayment>>,
+    ttl: Duration,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    invalidations: AtomicU64,
+}
+
+/// Read-through кэш платежей по ID перед таблицей хранилища. Запись в хранилище
+/// обновляет или сбрасывает запись кэша, поэтому кэш не отдает устаревший статус.
+/// Ожидающий платеж живет в кэше не дольше своего `expires_at`
+#[derive(Clone)]
+pub struct PaymentCache {
+    inner: Option<Arc<Inner>>,
+}
+
+impl std::fmt::Debug for PaymentCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PaymentCache").field("enabled", &self.inner.is_some()).finish()
+    }
+}
+
+impl Default for PaymentCache {
+    fn default() -> Self {
+        Self::disabled()
+    }
+}
+
+impl PaymentCache {
+    /// Кэш на `capacity` платежей; вытесняются давно не читанные
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        let Some(capacity) = NonZeroUsize::new(capacity) else {
+            return Self::disabled();
+        };
+        Self {
+            inner: Some(Arc::new(Inner {
+                entries: Mutex::new(LruCache::new(capacity)),
+                ttl,
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+                invalidations: AtomicU64::new(0),
+            })),
+        }
+    }
+
+    /// Без кэша: чтения всегда идут в хранилище
+    pub fn disabled() -> Self {
+        Self { inner: None }
+    }
+
+    pub fn from_config(config: &StorageConfig) -> Self {
+        Self::new(config.cache_capacity, Duration::from_secs(config.cache_ttl_secs))
+    }
+
+    fn entries(inner: &Inner) -> std::sync::MutexGuard<'_, LruCache<String, CachedPayment>> {
+        inner.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Платеж из кэша с обратным отсчетом на текущий момент
+    pub fn get(&self, payment_id: &str) -> Option<Payment> {
+        let inner = self.inner.as_ref()?;
+        let mut entries = Self::entries(inner);
+        let cached = match entries.get(payment_id) {
+            Some(cached) if cached.valid_until > Instant::now() => Some(cached.payment.clone()),
+            Some(_) => {
+                entries.pop(payment_id);
+                None
+            }
+            None => None,
+        };
+        drop(entries);
+        match cached {
+            Some(mut payment) => {
+                inner.hits.fetch_add(1, Ordering::Relaxed);
+                payment.refresh_countdown();
+                Some(payment)
+            }
+            None => {
+                inner.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Положить прочитанный или только что сохраненный платеж
+    pub fn put(&self, payment: &Payment) {
+        let Some(inner) = &self.inner else {
+            return;
+        };
+        let mut ttl = inner.ttl;
+        // После expires_at ожидающий платеж истекает — статус надо перечитать
+        if payment.status == PaymentStatus::Pending {
+            let left = (payment.expires_at - Utc::now()).to_std().unwrap_or_default();
+            ttl = ttl.min(left);
+        }
+        if ttl.is_zero() {
+            self.invalidate(&payment.id);
+            return;
+        }
+        let cached = CachedPayment { payment: payment.clone(), valid_until: Instant::now() + ttl };
+        Self::entries(inner).put(payment.id.clone(), cached);
+    }
+
+    pub fn invalidate(&self, payment_id: &str) {
+        if let Some(inner) = &self.inner {
+            if Self::entries(inner).pop(payment_id).is_some() {
+                inner.invalidations.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Сбросить весь кэш (восстановление хранилища из снимка)
+    pub fn clear(&self) {
+        if let Some(inner) = &self.inner {
+            let mut entries = Self::entries(inner);
+            inner.invalidations.fetch_add(entries.len() as u64, Ordering::Relaxed);
+            entries.clear();
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let Some(inner) = &self.inner else {
+            return CacheStats {
+                enabled: false,
+                capacity: 0,
+                entries: 0,
+                hits: 0,
+                misses: 0,
+                invalidations: 0,
+                hit_rate: 0.0,
+            };
+        };
+        let entries = Self::entries(inner);
+        let hits = inner.hits.load(Ordering::Relaxed);
+        let misses = inner.misses.load(Ordering::Relaxed);
+        CacheStats {
+            enabled: true,
+            capacity: entries.cap().get(),
+            entries: entries.len(),
+            hits,
+            misses,
+            invalidations: inner.invalidations.load(Ordering::Relaxed),
+            hit_rate: if hits + misses == 0 { 0.0 } else { hits as f64 / (hits + misses) as f64 },
+        }
+    }
+}
+
+/// Weak ETag платежа: меняется при каждом сохранении и зависит от набора полей ответа
+/// и курсов пересчета в фиат
+pub fn payment_etag(payment: &Payment, fields: Option<&str>) -> EntityTag {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    fields.hash(&mut hasher);
+    if let Some(fiat) = &payment.fiat {
+        (&fiat.currency, fiat.token_rate.to_bits(), fiat.fee_token_rate.to_bits()).hash(&mut hasher);
+    }
+    EntityTag::new_weak(format!("{:x}-{:x}", payment.updated_at.timestamp_micros(), hasher.finish()))
+}
+
+/// Клиент уже получил ответ с этим ETag (If-None-Match) — можно ответить 304
+pub fn not_modified(request: &HttpRequest, etag: &EntityTag) -> bool {
+    match IfNoneMatch::parse(request) {
+        Ok(IfNoneMatch::Any) => true,
+        Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(etag)),
+        Err(_) => false,
+    }
+}
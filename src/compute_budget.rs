@@ -0,0 +1,148 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction,
+    instruction::Instruction,
+    message::Message,
+    pubkey::Pubkey,
+    transaction::Transaction,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use crate::config::Config;
+use crate::rpc::SolanaRpc;
+
+/// Максимальный лимит compute units транзакции
+pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+/// Таймаут симуляции; без оценки транзакция собирается без лимита
+const SIMULATION_TIMEOUT_SECS: u64 = 5;
+
+/// Кэш оценок: форма инструкций → (максимум потребленных units, время измерения)
+type EstimateCache = Arc<RwLock<HashMap<Vec<InstructionShape>, (u64, DateTime<Utc>)>>>;
+
+/// Форма инструкции: программа, аккаунты (с флагами) и длина данных — от сумм
+/// переводов потребление compute units не зависит
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct InstructionShape {
+    program_id: Pubkey,
+    accounts: Vec<(bool, bool)>,
+    data_len: usize,
+}
+
+/// Лимит compute units и priority fee транзакции
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ComputeBudget {
+    pub unit_limit: u32,
+    /// Priority fee за compute unit, микролампорты
+    pub unit_price_micro_lamports: u64,
+}
+
+impl ComputeBudget {
+    /// Инструкции ComputeBudget (ставятся в начало транзакции)
+    pub fn instructions(&self) -> Vec<Instruction> {
+        let mut instructions = vec![ComputeBudgetInstruction::set_compute_unit_limit(self.unit_limit)];
+        if self.unit_price_micro_lamports > 0 {
+            instructions.push(ComputeBudgetInstruction::set_compute_unit_price(self.unit_price_micro_lamports));
+        }
+        instructions
+    }
+
+    /// Priority fee транзакции в лампортах (с округлением вверх, как считает сеть)
+    pub fn priority_fee_lamports(&self) -> u64 {
+        (self.unit_limit as u64 * self.unit_price_micro_lamports).div_ceil(1_000_000)
+    }
+}
+
+/// Оценка compute units симуляцией: плательщик платит priority fee только за реально
+/// нужные units, а не за лимит по умолчанию (200k на инструкцию)
+#[derive(Debug, Clone)]
+pub struct ComputeEstimator {
+    config: Config,
+    cache: EstimateCache,
+}
+
+impl ComputeEstimator {
+    pub fn new(config: Config) -> Self {
+        Self { config, cache: Arc::new(RwLock::new(HashMap::new())) }
+    }
+
+    /// Бюджет для инструкций `instructions` с плательщиком комиссии `fee_payer`.
+    /// `None` — бюджет выключен или оценить не удалось (транзакция собирается как раньше)
+    pub async fn estimate(
+        &self,
+        rpc: &dyn SolanaRpc,
+        instructions: &[Instruction],
+        fee_payer: &Pubkey,
+    ) -> Option<ComputeBudget> {
+        let settings = &self.config.compute_budget;
+        if !settings.enabled {
+            return None;
+        }
+
+        let shape: Vec<InstructionShape> = instructions.iter().map(|instruction| InstructionShape {
+            program_id: instruction.program_id,
+            accounts: instruction.accounts.iter().map(|a| (a.is_signer, a.is_writable)).collect(),
+            data_len: instruction.data.len(),
+        }).collect();
+        let ttl = Duration::seconds(settings.cache_ttl_secs as i64);
+
+        let cached = self.cache.read().await.get(&shape)
+            .filter(|(_, measured_at)| Utc::now() - *measured_at < ttl)
+            .map(|(units, _)| *units);
+        let units = match cached {
+            Some(units) => units,
+            None => {
+                let units = self.simulate(rpc, instructions, fee_payer).await?;
+                let mut cache = self.cache.write().await;
+                let entry = cache.entry(shape).or_insert((units, Utc::now()));
+                // Создание ATA дороже, когда аккаунта еще нет — держим максимум измерений
+                if Utc::now() - entry.1 >= ttl {
+                    *entry = (units, Utc::now());
+                } else {
+                    entry.0 = entry.0.max(units);
+                }
+                entry.0
+            }
+        };
+
+        let with_margin = units + units * settings.margin_bps as u64 / 10_000;
+        Some(ComputeBudget {
+            unit_limit: with_margin.min(MAX_COMPUTE_UNIT_LIMIT as u64) as u32,
+            unit_price_micro_lamports: settings.unit_price_micro_lamports,
+        })
+    }
+
+    async fn simulate(&self, rpc: &dyn SolanaRpc, instructions: &[Instruction], fee_payer: &Pubkey) -> Option<u64> {
+        // Симулируем с максимальным лимитом и теми же инструкциями бюджета, что будут в транзакции
+        let budget = ComputeBudget {
+            unit_limit: MAX_COMPUTE_UNIT_LIMIT,
+            unit_price_micro_lamports: self.config.compute_budget.unit_price_micro_lamports,
+        };
+        let mut all = budget.instructions();
+        all.extend_from_slice(instructions);
+        let transaction = Transaction::new_unsigned(Message::new(&all, Some(fee_payer)));
+
+        let simulation = tokio::time::timeout(
+            std::time::Duration::from_secs(SIMULATION_TIMEOUT_SECS),
+            rpc.simulate(&transaction),
+        ).await;
+        match simulation {
+            Ok(Ok(result)) if result.error.is_none() => result.units_consumed,
+            Ok(Ok(result)) => {
+                tracing::warn!(error = result.error.unwrap_or_default(), "Compute unit simulation failed");
+                None
+            }
+            Ok(Err(e)) => {
+                tracing::warn!(error = %e, "Compute unit simulation failed");
+                None
+            }
+            Err(_) => {
+                tracing::warn!("Compute unit simulation timed out");
+                None
+            }
+        }
+    }
+}
@@ -1,10 +1,368 @@
 use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::auth::{ApiKeyScope, PRIMARY_KEY_ID};
+
+use crate::deeplink::CHECKOUT_ID_PLACEHOLDER;
+use crate::explorer::{Cluster, Explorer, ExplorerKind};
+use crate::plugins::InstructionPlugins;
+use crate::tenants::TenantRegistry;
+use crate::tokens::TokenRegistry;
+use std::collections::BTreeMap;
 use std::env;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Файл конфигурации по умолчанию (используется, если существует)
+pub const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+/// Максимально допустимая комиссия за платеж (защита от опечаток в конфиге)
+pub const MAX_FEE_AMOUNT: f64 = 1_000.0;
+
+/// Максимальное число знаков после запятой у SPL токена
+const MAX_TOKEN_DECIMALS: u8 = 18;
+
+/// Максимальное проскальзывание свопа (10%)
+const MAX_SWAP_SLIPPAGE_BPS: u16 = 1_000;
+
+/// Максимальный запас к измеренным compute units (100%)
+const MAX_COMPUTE_UNIT_MARGIN_BPS: u16 = 10_000;
+
+/// Минимальная длина API ключей
+const MIN_API_KEY_LENGTH: usize = 16;
+
+const VALID_COMMITMENTS: &[&str] = &["processed", "confirmed", "finalized"];
+
+/// Production directory Let's Encrypt
+pub const LETS_ENCRYPT_DIRECTORY_URL: &str = "https://acme-v02.api.letsencrypt.org/directory";
+
+/// Валюты показа сумм по умолчанию
+pub const DEFAULT_DISPLAY_CURRENCIES: &[&str] = &[
+    "USD", "EUR", "GBP", "JPY", "CHF", "CAD", "AUD", "CNY", "INR", "BRL", "MXN", "KRW", "PLN", "TRY",
+];
+
+/// События платежей, о которых можно уведомлять мерчанта
+pub const NOTIFICATION_EVENTS: &[&str] = &[
+    "payment.created",
+    "payment.processing",
+    "payment.completed",
+    "payment.failed",
+    "payment.expired",
+    "payment.cancelled",
+    "payment.refunded",
+    "payment.held",
+    "payment.released",
+    "dispute.opened",
+    "dispute.refunded",
+    "dispute.denied",
+];
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub server: ServerConfig,
     pub solana: SolanaConfig,
+    /// Мерчанты при запуске; добавленные и измененные через admin API — в `Config::tenants`
+    pub merchants: Vec<MerchantConfig>,
+    pub sweep: SweepConfig,
+    pub coordination: CoordinationConfig,
+    pub notifications: NotificationsConfig,
+    pub acme: AcmeConfig,
+    pub retention: RetentionConfig,
+    pub storage: StorageConfig,
+    pub swap: SwapConfig,
+    pub settlement: SettlementConfig,
+    pub sponsorship: SponsorshipConfig,
+    pub escrow: EscrowConfig,
+    pub receipt_nft: ReceiptNftConfig,
+    pub compute_budget: ComputeBudgetConfig,
+    pub screening: ScreeningConfig,
+    pub watcher: WatcherConfig,
+    pub error_reporting: ErrorReportingConfig,
+    pub registration: RegistrationConfig,
+    /// Фоновые задачи по имени (см. `jobs::JOB_NAMES`)
+    pub jobs: BTreeMap<String, JobConfig>,
+    /// Плагины сборщика транзакций `[[instruction_plugins]]` в порядке применения
+    pub instruction_plugins: Vec<InstructionPluginConfig>,
+    /// Собранные плагины `instruction_plugins` и зарегистрированные в коде
+    #[serde(skip)]
+    pub decorators: InstructionPlugins,
+    /// Токены: `solana.supported_tokens` и добавленные во время работы
+    #[serde(skip)]
+    pub tokens: TokenRegistry,
+    /// Мерчанты (tenants) с API ключами и индивидуальными настройками: `merchants` и
+    /// сохраненные в хранилище
+    #[serde(skip)]
+    pub tenants: TenantRegistry,
+}
+
+/// Расписание фоновой задачи `jobs.<имя>`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobConfig {
+    pub enabled: bool,
+    /// Cron (UTC) из 5 полей или из 6 с секундами впереди, либо `@every 30s`
+    pub schedule: String,
+}
+
+/// Отправка ошибок (паники, сбои RPC, ошибки сборки транзакций) в Sentry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorReportingConfig {
+    /// DSN проекта Sentry; без DSN отправка выключена
+    pub sentry_dsn: Option<String>,
+    /// Окружение события; по умолчанию — кластер Solana
+    pub environment: String,
+    /// Релиз события; по умолчанию — `crypto-server@<версия>`
+    pub release: String,
+}
+
+/// Поиск транзакций платежей по reference: подписка на логи через WebSocket (PubSub)
+/// и опрос `getSignaturesForAddress`, пока WebSocket недоступен
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatcherConfig {
+    pub enabled: bool,
+    /// PubSub endpoint; по умолчанию — `rpc_url` со схемой ws/wss
+    pub ws_url: String,
+    /// Период опроса и сверки подписок, секунды
+    pub poll_interval_secs: u64,
+    /// Пауза перед переподключением WebSocket, секунды
+    pub reconnect_secs: u64,
+}
+
+/// Проверка адресов плательщика и получателя по denylist и санкционным спискам
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScreeningConfig {
+    pub enabled: bool,
+    /// Что делать с платежом при совпадении
+    pub action: ScreeningAction,
+    /// Локальный denylist: адрес и необязательная причина в строке, `#` — комментарий
+    pub denylist_path: Option<String>,
+    /// Ключ Chainalysis Sanctions API; без ключа проверка только по denylist
+    pub chainalysis_api_key: Option<String>,
+    pub chainalysis_api_url: String,
+    /// Пропускать платеж, если внешний сервис проверки недоступен
+    pub fail_open: bool,
+}
+
+/// Реакция на адрес из списка
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScreeningAction {
+    /// Отклонить создание платежа или выдачу транзакции
+    Reject,
+    /// Пропустить, пометив платеж для ручной проверки
+    Flag,
+}
+
+impl FromStr for ScreeningAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(ScreeningAction::Reject),
+            "flag" => Ok(ScreeningAction::Flag),
+            other => Err(format!("expected 'reject' or 'flag', got '{}'", other)),
+        }
+    }
+}
+
+/// Самостоятельная регистрация мерчантов: `POST /api/merchants/register` создает мерчанта
+/// и выдает ему API ключ
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistrationConfig {
+    pub enabled: bool,
+    /// Коды приглашения; пусто — регистрация открыта всем
+    pub invite_codes: Vec<String>,
+}
+
+/// Лимит compute units по симуляции транзакции и priority fee
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComputeBudgetConfig {
+    pub enabled: bool,
+    /// Priority fee за compute unit, микролампорты (0 — только лимит)
+    pub unit_price_micro_lamports: u64,
+    /// Запас к измеренным units в базисных пунктах
+    pub margin_bps: u16,
+    /// Сколько секунд оценка для одной формы инструкций считается актуальной
+    pub cache_ttl_secs: u64,
+}
+
+/// Gasless платежи: hot wallet платит сетевую комиссию (и rent токен-аккаунтов получателей),
+/// плательщику без SOL достаточно токена платежа. Только для SPL платежей без свопа
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SponsorshipConfig {
+    pub enabled: bool,
+    /// Максимум лампортов, которые hot wallet может потратить на один платеж
+    pub max_lamports_per_payment: u64,
+    /// Лимит спонсирования за последние 24 часа, лампорты
+    pub daily_limit_lamports: u64,
+}
+
+/// Платежи с удержанием (`hold`): средства поступают на hot wallet и ждут списания
+/// мерчанту (`capture`) или возврата плательщику (`release`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EscrowConfig {
+    pub enabled: bool,
+    /// Срок удержания по умолчанию, секунды; после него средства возвращаются автоматически
+    pub default_hold_secs: u64,
+    /// Максимальный срок удержания, который можно запросить
+    pub max_hold_secs: u64,
+}
+
+/// NFT-квитанции: после оплаты hot wallet выпускает NFT с данными счета на кошелек
+/// плательщика (для мерчантов с `receipt_nft = true`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReceiptNftConfig {
+    pub enabled: bool,
+    pub kind: ReceiptNftKind,
+    /// Дерево Bubblegum для `cnft`; hot wallet должен быть его создателем или делегатом
+    pub merkle_tree: Option<String>,
+    /// Символ коллекции в метаданных (до 10 байт)
+    pub symbol: String,
+    /// Сколько неудачных попыток выпуска допускается на платеж
+    pub max_attempts: u32,
+}
+
+/// Вид NFT-квитанции
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiptNftKind {
+    /// Обычный NFT: mint с supply 1 и метаданные Metaplex
+    Nft,
+    /// Сжатый NFT (Metaplex Bubblegum) — дешевле, требует заранее созданного дерева
+    Cnft,
+}
+
+impl FromStr for ReceiptNftKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nft" => Ok(ReceiptNftKind::Nft),
+            "cnft" => Ok(ReceiptNftKind::Cnft),
+            other => Err(format!("expected 'nft' or 'cnft', got '{}'", other)),
+        }
+    }
+}
+
+/// Задача пересчета выручки мерчантов в токен расчетов (см. `MerchantConfig::settlement`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettlementConfig {
+    /// Период в секундах — расписание по умолчанию для `jobs.settlement`
+    /// (0 — только вручную через admin API)
+    pub interval_secs: u64,
+    /// Платежи, завершенные раньше, не пересчитываются (выручка, скорее всего, уже выведена)
+    pub max_age_hours: u32,
+    /// Сколько неудачных попыток допускается на платеж
+    pub max_attempts: u32,
+}
+
+/// Оплата любым токеном: своп через Jupiter в токен платежа в той же транзакции
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwapConfig {
+    pub enabled: bool,
+    /// Jupiter Swap API (quote и swap-instructions)
+    pub api_url: String,
+    /// Допустимое проскальзывание в базисных пунктах
+    pub slippage_bps: u16,
+}
+
+/// Хранение истории платежей: старые платежи архивируются (остаются доступны
+/// для учета), удаляются только архивные и только через admin API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionConfig {
+    /// Через сколько дней после оплаты архивировать завершенный платеж
+    pub completed_days: u32,
+    /// Через сколько дней после истечения архивировать неоплаченный платеж
+    pub expired_days: u32,
+    /// Период архивации в секундах — расписание по умолчанию для `jobs.retention` (0 — выключена)
+    pub interval_secs: u64,
+}
+
+/// In-memory хранилище: снимок на диск по задаче `jobs.storage_snapshot` и при
+/// остановке, восстановление при старте
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageConfig {
+    /// Файл снимка (JSON); без него снимки выключены
+    pub snapshot_path: Option<String>,
+    /// Сколько платежей держать в кэше чтений по ID; 0 — кэш выключен
+    pub cache_capacity: usize,
+    /// Срок записи кэша; ожидающий платеж — не дольше его `expires_at`
+    pub cache_ttl_secs: u64,
+    /// Допустимое отставание реплики для списков и выгрузок платежей (`?consistency=eventual`);
+    /// 0 — все чтения из основной таблицы
+    pub replica_max_lag_ms: u64,
+}
+
+/// Автоматический сертификат Let's Encrypt (ACME, HTTP-01) для `server.domain`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AcmeConfig {
+    pub enabled: bool,
+    pub directory_url: String,
+    /// Контакт для уведомлений CA об истечении сертификата
+    pub contact_email: Option<String>,
+    /// Куда сохранять ключ аккаунта, сертификат и его ключ
+    pub cert_dir: String,
+    /// За сколько дней до истечения продлевать
+    pub renew_before_days: u32,
+}
+
+/// Доступ к каналам уведомлений мерчантов (SMTP и Telegram бот)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationsConfig {
+    /// SMTP сервер для email; без хоста email уведомления выключены
+    pub smtp_host: Option<String>,
+    pub smtp_port: u16,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    /// Адрес отправителя, например `CryptoNow <noreply@example.com>`
+    pub email_from: Option<String>,
+    /// Токен Telegram бота; без токена Telegram уведомления выключены
+    pub telegram_bot_token: Option<String>,
+}
+
+/// Координация фоновых задач между репликами
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CoordinationConfig {
+    /// Идентификатор инстанса (владелец блокировок)
+    pub instance_id: String,
+    pub lock_backend: LockBackend,
+    /// Redis для блокировок при `lock_backend = "redis"`
+    pub redis_url: Option<String>,
+}
+
+/// Где хранятся блокировки фоновых задач
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LockBackend {
+    /// В памяти процесса — только для одной реплики
+    Memory,
+    /// Общий Redis — для нескольких реплик
+    Redis,
+}
+
+impl FromStr for LockBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "memory" => Ok(LockBackend::Memory),
+            "redis" => Ok(LockBackend::Redis),
+            other => Err(format!("expected 'memory' or 'redis', got '{}'", other)),
+        }
+    }
+}
+
+/// Перевод накопленных комиссий с fee кошелька в холодное хранилище
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SweepConfig {
+    /// Куда переводить; без адреса sweep выключен
+    pub cold_wallet: Option<String>,
+    pub token: String,
+    /// Минимальный баланс (в токенах), начиная с которого делаем sweep
+    pub threshold: f64,
+    /// Период автоматического sweep в секундах — расписание по умолчанию для `jobs.sweep`
+    /// (0 — только вручную через admin API)
+    pub interval_secs: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -12,17 +370,643 @@ pub struct ServerConfig {
     pub host: String,
     pub port: u16,
     pub domain: String,
+    /// Публичный URL сервера — https. Без сертификата TLS терминирует reverse proxy
     pub ssl: bool,
+    /// Сертификат (цепочка, PEM) — сервер сам принимает HTTPS с HTTP/2
+    pub tls_cert_path: Option<String>,
+    /// Приватный ключ сертификата (PEM: PKCS#8, RSA или EC)
+    pub tls_key_path: Option<String>,
+    /// Порт с редиректом plain HTTP на HTTPS; без порта редирект выключен
+    pub http_redirect_port: Option<u16>,
+    /// Ключ для /admin эндпоинтов (заголовок X-Admin-Key). Без ключа admin API выключен
+    pub admin_api_key: Option<String>,
+    /// Ed25519 ключ подписи данных платежей (JSON solana-keygen); без ключа ответы и
+    /// webhooks не подписываются
+    pub signing_key_path: Option<String>,
+    /// Порт gRPC API; без порта gRPC выключен
+    pub grpc_port: Option<u16>,
+    /// Число actix воркеров; по умолчанию — по числу ядер
+    pub workers: Option<usize>,
+    /// Keep-alive простаивающих соединений в секундах (0 — выключен)
+    pub keep_alive_secs: u64,
+    /// Сколько ждать заголовки запроса от клиента, мс (0 — без ограничения)
+    pub client_request_timeout_ms: u64,
+    /// За сколько секунд до истечения платежа отправить событие `expiring_soon` (0 — не отправлять)
+    pub expiring_soon_secs: u64,
+    /// Страница оплаты для universal links кошельков (`{id}` — ID платежа);
+    /// без нее ссылки Phantom/Solflare не генерируются
+    pub checkout_url: Option<String>,
+    /// Минимальный интервал между пересборками транзакции платежа со свежим blockhash, секунды
+    pub transaction_refresh_secs: u64,
+    /// Сколько раз можно пересобрать транзакцию одного платежа
+    pub transaction_refresh_limit: u32,
+    /// Предельный размер JSON тела запроса, байт
+    pub max_json_body_bytes: usize,
+    /// Предельная вложенность объектов и массивов в JSON теле запроса
+    pub max_json_depth: usize,
+    /// Формат логов в stderr
+    pub log_format: LogFormat,
+    /// Профиль окружения — задает дефолты остальных настроек
+    pub profile: Profile,
+    /// Уровни логов (синтаксис env_logger); `RUST_LOG` приоритетнее
+    pub log_filter: String,
+    /// Origin, с которых браузер может обращаться к API; `*` — любой
+    pub cors_origins: Vec<String>,
+    /// Запросов в минуту с одного IP клиента (0 — без ограничения)
+    pub rate_limit_per_minute: u32,
+    /// Сколько транзакций собирается одновременно (каждая сборка обращается к RPC)
+    pub max_concurrent_builds: usize,
+    /// Сколько запросов сборки может ждать свободного слота; сверх — 503
+    pub max_queued_builds: usize,
+    /// Сколько запрос ждет слота в очереди, мс; дольше — 503
+    pub build_queue_timeout_ms: u64,
+    /// Формат ID новых платежей; платежи со старыми ID остаются доступны
+    pub payment_id_format: PaymentIdFormat,
+    /// Длина короткого кода вместе с контрольным символом
+    pub short_id_length: usize,
+    /// Transaction request по короткой ссылке `/t/{code}` вместо `/api/payment/{id}/transaction`:
+    /// короче URL — реже QR код, который надежнее читают дешевые сканеры
+    pub short_links: bool,
+    /// Длина кода короткой ссылки
+    pub short_link_length: usize,
+    /// Одноразовый токен `?t=` в transaction request и action URL: по одному ID платежа
+    /// (утекшему или подобранному) транзакцию и метаданные не получить
+    pub transaction_request_tokens: bool,
+    /// Валюты для `GET /api/payment/{id}?display_currency=` (коды ISO 4217)
+    pub display_currencies: Vec<String>,
+    /// Запросы дольше порога пишутся в лог с трассировкой вызовов RPC и операций
+    /// хранилища, мс; 0 — не трассировать
+    pub slow_request_ms: u64,
+}
+
+/// Формат ID платежа
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentIdFormat {
+    /// `pay_` + UUID без дефисов
+    Uuid,
+    /// `pay_` + короткий base32 код с контрольным символом (`pay_7F3KQ2`) — удобно диктовать
+    Short,
+}
+
+impl FromStr for PaymentIdFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "uuid" => Ok(PaymentIdFormat::Uuid),
+            "short" => Ok(PaymentIdFormat::Short),
+            other => Err(format!("expected 'uuid' or 'short', got '{}'", other)),
+        }
+    }
+}
+
+/// Профиль окружения: дефолты кластера, уровней логов, CORS и лимита запросов.
+/// Явные значения из файла и env приоритетнее профиля
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Profile {
+    /// Локальная разработка: devnet, подробные логи, без ограничений
+    Dev,
+    /// Тестовый стенд: devnet, CORS открыт, мягкий лимит запросов
+    Staging,
+    /// Боевой сервер: mainnet-beta, CORS только для своих origin, лимит запросов
+    Prod,
+}
+
+impl Profile {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Profile::Dev => "dev",
+            Profile::Staging => "staging",
+            Profile::Prod => "prod",
+        }
+    }
+
+    pub fn cluster(&self) -> Cluster {
+        match self {
+            Profile::Dev | Profile::Staging => Cluster::Devnet,
+            Profile::Prod => Cluster::MainnetBeta,
+        }
+    }
+
+    pub fn log_filter(&self) -> &'static str {
+        match self {
+            Profile::Dev => "info,crypto_server=debug",
+            Profile::Staging | Profile::Prod => "warn,crypto_server=info",
+        }
+    }
+
+    /// Открыт ли CORS для любого origin; в prod — только сервер и страница оплаты
+    pub fn allows_any_origin(&self) -> bool {
+        !matches!(self, Profile::Prod)
+    }
+
+    pub fn rate_limit_per_minute(&self) -> u32 {
+        match self {
+            Profile::Dev => 0,
+            Profile::Staging => 1200,
+            Profile::Prod => 600,
+        }
+    }
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dev" => Ok(Profile::Dev),
+            "staging" => Ok(Profile::Staging),
+            "prod" => Ok(Profile::Prod),
+            other => Err(format!("expected 'dev', 'staging' or 'prod', got '{}'", other)),
+        }
+    }
+}
+
+/// Формат строк лога
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// Текст: сообщение и поля `key=value`
+    Text,
+    /// JSON объект в строке — для систем сбора логов
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!("expected 'text' or 'json', got '{}'", other)),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SolanaConfig {
+    /// Основной RPC (первый из `rpc_endpoints`)
     pub rpc_url: String,
+    /// Пул RPC узлов — запросы распределяются между ними по весам
+    pub rpc_endpoints: Vec<RpcEndpoint>,
+    /// Кластер для ссылок на обозреватель
+    pub cluster: Cluster,
+    pub explorer: ExplorerKind,
+    /// Commitment по умолчанию (аккаунты, симуляция) и для операций ниже, если они не заданы
     pub commitment: String,
+    /// Commitment получения recent blockhash для транзакций
+    pub blockhash_commitment: String,
+    /// Commitment поиска транзакции при верификации платежа; `processed` — как `confirmed`
+    pub verification_commitment: String,
+    /// Commitment подписки watcher на транзакции платежей; `processed` — как `confirmed`
+    pub watcher_commitment: String,
+    /// Основной кошелек для комиссий (первый из `fee_wallets`)
     pub fee_wallet: String,
+    /// Все кошельки для комиссий — платежи распределяются между ними
+    pub fee_wallets: Vec<FeeWalletConfig>,
+    pub fee_wallet_strategy: FeeWalletStrategy,
     pub fee_amount: f64,
+    /// Комиссия в USD — конвертируется в fee_token по курсу при создании платежа.
+    /// Если задана, `fee_amount` игнорируется
+    pub fee_usd: Option<f64>,
     pub fee_token: String,
+    /// Jupiter Price API для курсов токенов
+    pub price_api_url: String,
+    /// Курсы валют к USD: ответ `{"rates": {"EUR": 0.92, ...}}`
+    pub fx_api_url: String,
+    /// Токены при запуске; добавленные через admin API — в `Config::tokens`
     pub supported_tokens: Vec<TokenConfig>,
+    /// Solana token list (`{"tokens": [...]}`) для поиска токенов по символу при
+    /// добавлении через admin API
+    pub token_list_url: Option<String>,
+    /// Разрешать платежи только на кошельки, владение которыми подтверждено подписью
+    pub require_verified_recipient: bool,
+    /// Что делать с SOL переводом на новый аккаунт ниже rent-exempt минимума
+    pub rent_policy: RentPolicy,
+    /// Что делать с получателем вне кривой ed25519 (PDA, vault мультисига)
+    pub off_curve_recipients: OffCurvePolicy,
+    /// Keypair сервера (hot wallet) для подписи собственных транзакций (sweep и т.п.)
+    pub server_keypair_path: Option<String>,
+}
+
+/// Мерчант, авторизующийся по API ключу (заголовок X-API-Key)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MerchantConfig {
+    pub id: String,
+    pub name: String,
+    /// Основной ключ: все права, без срока действия
+    pub api_key: String,
+    /// Дополнительные ключи с ограниченными правами и сроком действия
+    #[serde(default)]
+    pub api_keys: Vec<MerchantApiKey>,
+    /// Sandbox ключ: платежи не касаются блокчейна, завершаются через
+    /// `POST /api/payment/{id}/simulate_complete` и не попадают в выручку и выгрузки
+    #[serde(default)]
+    pub sandbox: bool,
+    /// Переопределения глобальной политики комиссий
+    #[serde(default)]
+    pub fee_amount: Option<f64>,
+    #[serde(default)]
+    pub fee_usd: Option<f64>,
+    #[serde(default)]
+    pub fee_token: Option<String>,
+    /// Куда уведомлять о завершении/истечении платежей
+    #[serde(default)]
+    pub notifications: Vec<NotificationTarget>,
+    /// Автоматически конвертировать выручку и переводить на адрес расчетов
+    #[serde(default)]
+    pub settlement: Option<MerchantSettlement>,
+    /// Лимиты на создание платежей за скользящие 24 часа
+    #[serde(default)]
+    pub limits: Option<MerchantLimits>,
+    /// Разрешение задавать комиссию в запросе на создание платежа; без него поля
+    /// `fee_amount`/`fee_token` запроса отклоняются
+    #[serde(default)]
+    pub fee_override: Option<FeeOverridePolicy>,
+    /// Выпускать плательщикам NFT-квитанции (нужен включенный `receipt_nft`)
+    #[serde(default)]
+    pub receipt_nft: bool,
+    /// Бонусные баллы покупателям за оплаченные платежи
+    #[serde(default)]
+    pub loyalty: Option<MerchantLoyalty>,
+    /// Кошелек для комиссий платежей мерчанта вместо `solana.fee_wallets`
+    #[serde(default)]
+    pub fee_wallet: Option<String>,
+    /// Токены, которыми можно платить мерчанту; пусто — все поддерживаемые
+    #[serde(default)]
+    pub tokens: Vec<String>,
+    /// Название и иконка в кошельке плательщика (transaction request, Solana Actions)
+    #[serde(default)]
+    pub branding: Option<MerchantBranding>,
+}
+
+impl MerchantConfig {
+    /// Основной и дополнительные ключи
+    pub fn all_api_keys(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.api_key.as_str()).chain(self.api_keys.iter().map(|key| key.api_key.as_str()))
+    }
+
+    /// Есть ли у мерчантов общий ключ (сравнение за постоянное время)
+    pub fn shares_api_key_with(&self, other: &MerchantConfig) -> bool {
+        self.all_api_keys().any(|key| other.all_api_keys().any(|theirs| constant_time_eq(key.as_bytes(), theirs.as_bytes())))
+    }
+
+    /// Можно ли платить мерчанту токеном `token`
+    pub fn accepts_token(&self, token: &str) -> bool {
+        self.tokens.is_empty() || self.tokens.iter().any(|t| t == token)
+    }
+}
+
+/// Дополнительный API ключ мерчанта (`[[merchants.api_keys]]`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MerchantApiKey {
+    pub id: String,
+    pub api_key: String,
+    pub scope: ApiKeyScope,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// После этого момента ключ отклоняется
+    #[serde(default)]
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default)]
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Оформление мерчанта в кошельке плательщика
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MerchantBranding {
+    /// Метка платежа по умолчанию вместо «Оплата <токен>»
+    #[serde(default)]
+    pub display_name: Option<String>,
+    /// Иконка transaction request и Solana Actions (https)
+    #[serde(default)]
+    pub icon_url: Option<String>,
+}
+
+/// Бонусная программа мерчанта: баллы начисляются на кошелек плательщика и могут
+/// оплатить часть следующих платежей
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MerchantLoyalty {
+    /// Токен → баллов за 1 оплаченный токен (дробные баллы отбрасываются)
+    #[serde(default)]
+    pub earn_rate: BTreeMap<String, f64>,
+    /// Токен → сколько токена стоит 1 балл при оплате баллами; пусто — списание выключено
+    #[serde(default)]
+    pub redeem_value: BTreeMap<String, f64>,
+    /// Какую долю суммы платежа можно оплатить баллами, базисные пункты
+    #[serde(default = "default_max_redeem_bps")]
+    pub max_redeem_bps: u16,
+}
+
+fn default_max_redeem_bps() -> u16 {
+    5_000
+}
+
+/// Границы комиссии, которую мерчант может задать платежу (например, промо без комиссии)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FeeOverridePolicy {
+    /// Минимальная комиссия в токене комиссии (0 — разрешены платежи без комиссии)
+    #[serde(default)]
+    pub min_fee_amount: f64,
+    /// Максимальная комиссия; по умолчанию — MAX_FEE_AMOUNT
+    #[serde(default)]
+    pub max_fee_amount: Option<f64>,
+    /// Разрешенные токены комиссии; пусто — токен комиссии мерчанта не меняется
+    #[serde(default)]
+    pub fee_tokens: Vec<String>,
+}
+
+impl FeeOverridePolicy {
+    pub fn max_fee_amount(&self) -> f64 {
+        self.max_fee_amount.unwrap_or(MAX_FEE_AMOUNT)
+    }
+}
+
+/// Риск-лимиты мерчанта за скользящие 24 часа (администратор может временно их переопределить)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MerchantLimits {
+    /// Максимум созданных платежей (включая истекшие)
+    #[serde(default)]
+    pub max_daily_payments: Option<u32>,
+    /// Токен → максимум суммы ожидающих, отправленных и завершенных платежей
+    #[serde(default)]
+    pub max_daily_volume: BTreeMap<String, f64>,
+}
+
+/// Расчеты с мерчантом: выручка каждого платежа конвертируется в `token` и уходит на `address`.
+/// Сервер подписывает hot wallet ключом: получатель платежа — сам hot wallet или
+/// мерчант выдал ему delegate на свой токен-аккаунт (`spl-token approve`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MerchantSettlement {
+    pub token: String,
+    pub address: String,
+}
+
+/// Получатель уведомлений мерчанта
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationTarget {
+    #[serde(flatten)]
+    pub channel: NotificationChannel,
+    /// Типы событий, о которых уведомлять
+    #[serde(default = "default_notification_events")]
+    pub events: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "channel", rename_all = "snake_case")]
+pub enum NotificationChannel {
+    Email { to: String },
+    Telegram { chat_id: String },
+}
+
+fn default_notification_events() -> Vec<String> {
+    vec!["payment.completed".to_string(), "payment.expired".to_string()]
+}
+
+/// RPC узел пула (Helius, QuickNode, Triton и т.п.)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RpcEndpoint {
+    pub url: String,
+    /// Ключ API: в заголовке `api_key_header` или, без него, параметром `?api-key=` (Helius)
+    #[serde(default)]
+    pub api_key: Option<String>,
+    #[serde(default)]
+    pub api_key_header: Option<String>,
+    /// Дополнительные HTTP заголовки запросов
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default = "default_rpc_endpoint_weight")]
+    pub weight: u32,
+    /// Бюджет запросов в секунду по тарифу провайдера; без него — без ограничения
+    #[serde(default)]
+    pub max_requests_per_sec: Option<u32>,
+}
+
+impl RpcEndpoint {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            api_key: None,
+            api_key_header: None,
+            headers: BTreeMap::new(),
+            weight: default_rpc_endpoint_weight(),
+            max_requests_per_sec: None,
+        }
+    }
+}
+
+fn default_rpc_endpoint_weight() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeeWalletConfig {
+    pub address: String,
+    #[serde(default = "default_fee_wallet_weight")]
+    pub weight: u32,
+}
+
+fn default_fee_wallet_weight() -> u32 {
+    1
+}
+
+/// Стратегия выбора кошелька для комиссии
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeWalletStrategy {
+    /// По очереди, веса игнорируются
+    RoundRobin,
+    /// Пропорционально весам
+    Weighted,
+}
+
+impl FromStr for FeeWalletStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "round_robin" => Ok(FeeWalletStrategy::RoundRobin),
+            "weighted" => Ok(FeeWalletStrategy::Weighted),
+            other => Err(format!("expected 'round_robin' or 'weighted', got '{}'", other)),
+        }
+    }
+}
+
+/// Разобрать `FEE_WALLETS=addr1:3,addr2:1` (вес по умолчанию 1)
+fn parse_fee_wallets(raw: &str) -> anyhow::Result<Vec<FeeWalletConfig>> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (address, weight) = match entry.split_once(':') {
+                Some((address, weight)) => (
+                    address,
+                    weight.parse().map_err(|e| {
+                        anyhow::anyhow!("Invalid weight in FEE_WALLETS entry '{}': {}", entry, e)
+                    })?,
+                ),
+                None => (entry, default_fee_wallet_weight()),
+            };
+            Ok(FeeWalletConfig { address: address.to_string(), weight })
+        })
+        .collect()
+}
+
+/// Публичный RPC кластера — дефолт без `solana.rpc_url` и `rpc_endpoints`
+fn public_rpc_url(cluster: Cluster) -> &'static str {
+    match cluster {
+        Cluster::MainnetBeta => "https://api.mainnet-beta.solana.com",
+        Cluster::Devnet => "https://api.devnet.solana.com",
+        Cluster::Testnet => "https://api.testnet.solana.com",
+    }
+}
+
+/// PubSub endpoint узла: та же адресная строка со схемой ws/wss. У локального
+/// валидатора PubSub на следующем порту (8899 → 8900)
+fn websocket_url(rpc_url: &str) -> String {
+    let Ok(mut url) = url::Url::parse(rpc_url) else {
+        return rpc_url.to_string();
+    };
+    let scheme = if url.scheme() == "http" { "ws" } else { "wss" };
+    let _ = url.set_scheme(scheme);
+    if let Some(port) = url.port() {
+        let _ = url.set_port(Some(port + 1));
+    }
+    url.to_string()
+}
+
+/// Разобрать `SOLANA_RPC_ENDPOINTS=url1,url2` (ключ API — в самом URL, вес 1)
+fn parse_rpc_endpoints(raw: &str) -> Vec<RpcEndpoint> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(RpcEndpoint::new)
+        .collect()
+}
+
+/// Политика для SOL переводов, которые оставят получателя ниже rent-exempt минимума
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RentPolicy {
+    /// Вернуть ошибку — транзакция не будет собрана
+    Reject,
+    /// Доплатить до rent-exempt минимума за счет плательщика
+    TopUp,
+}
+
+impl FromStr for RentPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(RentPolicy::Reject),
+            "top_up" => Ok(RentPolicy::TopUp),
+            other => Err(format!("expected 'reject' or 'top_up', got '{}'", other)),
+        }
+    }
+}
+
+/// Политика для получателей вне кривой ed25519: у такого адреса нет приватного ключа,
+/// средства доступны только программе (например, vault мультисига Squads)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OffCurvePolicy {
+    /// Принимать, записав предупреждение в лог
+    Warn,
+    /// Принимать молча — мерчанты используют мультисиги
+    Allow,
+    /// Отклонять как опечатку или адрес не того аккаунта
+    Reject,
+}
+
+impl FromStr for OffCurvePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warn" => Ok(OffCurvePolicy::Warn),
+            "allow" => Ok(OffCurvePolicy::Allow),
+            "reject" => Ok(OffCurvePolicy::Reject),
+            other => Err(format!("expected 'warn', 'allow' or 'reject', got '{}'", other)),
+        }
+    }
+}
+
+/// Плагин, добавляющий инструкции в каждую транзакцию платежа (`[[instruction_plugins]]`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum InstructionPluginConfig {
+    /// SPL Memo, подписанный плательщиком. В `text` подставляются `{payment_id}`,
+    /// `{reference}` и `{merchant_id}`
+    Memo {
+        name: String,
+        text: String,
+        #[serde(default)]
+        position: PluginPosition,
+        #[serde(default)]
+        required: bool,
+    },
+    /// Фиксированный лимит compute units и priority fee (только при `compute_budget.enabled = false`);
+    /// всегда в начале транзакции, в транзакции свопа не добавляется (бюджет задает маршрут)
+    ComputeBudget {
+        name: String,
+        unit_limit: u32,
+        #[serde(default)]
+        unit_price_micro_lamports: u64,
+    },
+    /// Вызов программы (например, начисление в программе лояльности)
+    Program {
+        name: String,
+        program_id: String,
+        #[serde(default)]
+        accounts: Vec<PluginAccountConfig>,
+        /// Данные инструкции в base64
+        #[serde(default)]
+        data: String,
+        #[serde(default)]
+        position: PluginPosition,
+        #[serde(default)]
+        required: bool,
+    },
+}
+
+impl InstructionPluginConfig {
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Memo { name, .. } | Self::ComputeBudget { name, .. } | Self::Program { name, .. } => name,
+        }
+    }
+}
+
+/// Аккаунт инструкции плагина: адрес или участник платежа (`payer`, `fee_payer`,
+/// `recipient`, `fee_recipient`, `reference`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PluginAccountConfig {
+    pub pubkey: String,
+    /// Только для `payer` и `fee_payer`: других подписей у транзакции нет
+    #[serde(default)]
+    pub signer: bool,
+    #[serde(default)]
+    pub writable: bool,
+}
+
+/// Где инструкции плагина стоят относительно инструкций платежа. Внутри позиции
+/// плагины идут в порядке конфигурации
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PluginPosition {
+    /// Самое начало транзакции (до бюджета compute units по симуляции)
+    Start,
+    /// Перед переводами платежа
+    Before,
+    /// После переводов и memo платежа
+    #[default]
+    After,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,87 +1015,1429 @@ pub struct TokenConfig {
     pub mint: Option<String>, // None для SOL (native)
     pub decimals: u8,
     pub name: String,
+    /// Логотип для страницы оплаты
+    #[serde(default)]
+    pub logo_uri: Option<String>,
+}
+
+/// Частичная конфигурация из файла — все поля опциональны,
+/// отсутствующие берутся из env или дефолтов
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileConfig {
+    server: FileServerConfig,
+    solana: FileSolanaConfig,
+    merchants: Vec<MerchantConfig>,
+    sweep: FileSweepConfig,
+    coordination: FileCoordinationConfig,
+    notifications: FileNotificationsConfig,
+    acme: FileAcmeConfig,
+    retention: FileRetentionConfig,
+    storage: FileStorageConfig,
+    swap: FileSwapConfig,
+    settlement: FileSettlementConfig,
+    sponsorship: FileSponsorshipConfig,
+    escrow: FileEscrowConfig,
+    receipt_nft: FileReceiptNftConfig,
+    compute_budget: FileComputeBudgetConfig,
+    screening: FileScreeningConfig,
+    watcher: FileWatcherConfig,
+    error_reporting: FileErrorReportingConfig,
+    registration: FileRegistrationConfig,
+    jobs: BTreeMap<String, FileJobConfig>,
+    instruction_plugins: Vec<InstructionPluginConfig>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileJobConfig {
+    enabled: Option<bool>,
+    schedule: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileErrorReportingConfig {
+    sentry_dsn: Option<String>,
+    environment: Option<String>,
+    release: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileRegistrationConfig {
+    enabled: Option<bool>,
+    invite_codes: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileWatcherConfig {
+    enabled: Option<bool>,
+    ws_url: Option<String>,
+    poll_interval_secs: Option<u64>,
+    reconnect_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileScreeningConfig {
+    enabled: Option<bool>,
+    action: Option<ScreeningAction>,
+    denylist_path: Option<String>,
+    chainalysis_api_key: Option<String>,
+    chainalysis_api_url: Option<String>,
+    fail_open: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileComputeBudgetConfig {
+    enabled: Option<bool>,
+    unit_price_micro_lamports: Option<u64>,
+    margin_bps: Option<u16>,
+    cache_ttl_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileSponsorshipConfig {
+    enabled: Option<bool>,
+    max_lamports_per_payment: Option<u64>,
+    daily_limit_lamports: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileEscrowConfig {
+    enabled: Option<bool>,
+    default_hold_secs: Option<u64>,
+    max_hold_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileReceiptNftConfig {
+    enabled: Option<bool>,
+    kind: Option<ReceiptNftKind>,
+    merkle_tree: Option<String>,
+    symbol: Option<String>,
+    max_attempts: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileSettlementConfig {
+    interval_secs: Option<u64>,
+    max_age_hours: Option<u32>,
+    max_attempts: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileSwapConfig {
+    enabled: Option<bool>,
+    api_url: Option<String>,
+    slippage_bps: Option<u16>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileRetentionConfig {
+    completed_days: Option<u32>,
+    expired_days: Option<u32>,
+    interval_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileStorageConfig {
+    snapshot_path: Option<String>,
+    cache_capacity: Option<usize>,
+    cache_ttl_secs: Option<u64>,
+    replica_max_lag_ms: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileAcmeConfig {
+    enabled: Option<bool>,
+    directory_url: Option<String>,
+    contact_email: Option<String>,
+    cert_dir: Option<String>,
+    renew_before_days: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileNotificationsConfig {
+    smtp_host: Option<String>,
+    smtp_port: Option<u16>,
+    smtp_username: Option<String>,
+    smtp_password: Option<String>,
+    email_from: Option<String>,
+    telegram_bot_token: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileCoordinationConfig {
+    instance_id: Option<String>,
+    lock_backend: Option<LockBackend>,
+    redis_url: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileSweepConfig {
+    cold_wallet: Option<String>,
+    token: Option<String>,
+    threshold: Option<f64>,
+    interval_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileServerConfig {
+    host: Option<String>,
+    port: Option<u16>,
+    domain: Option<String>,
+    ssl: Option<bool>,
+    tls_cert_path: Option<String>,
+    tls_key_path: Option<String>,
+    http_redirect_port: Option<u16>,
+    admin_api_key: Option<String>,
+    signing_key_path: Option<String>,
+    grpc_port: Option<u16>,
+    workers: Option<usize>,
+    keep_alive_secs: Option<u64>,
+    client_request_timeout_ms: Option<u64>,
+    expiring_soon_secs: Option<u64>,
+    checkout_url: Option<String>,
+    transaction_refresh_secs: Option<u64>,
+    transaction_refresh_limit: Option<u32>,
+    max_json_body_bytes: Option<usize>,
+    max_json_depth: Option<usize>,
+    log_format: Option<LogFormat>,
+    profile: Option<Profile>,
+    log_filter: Option<String>,
+    cors_origins: Option<Vec<String>>,
+    rate_limit_per_minute: Option<u32>,
+    max_concurrent_builds: Option<usize>,
+    max_queued_builds: Option<usize>,
+    build_queue_timeout_ms: Option<u64>,
+    payment_id_format: Option<PaymentIdFormat>,
+    short_id_length: Option<usize>,
+    short_links: Option<bool>,
+    short_link_length: Option<usize>,
+    transaction_request_tokens: Option<bool>,
+    slow_request_ms: Option<u64>,
+    display_currencies: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileSolanaConfig {
+    rpc_url: Option<String>,
+    cluster: Option<Cluster>,
+    explorer: Option<ExplorerKind>,
+    commitment: Option<String>,
+    blockhash_commitment: Option<String>,
+    verification_commitment: Option<String>,
+    watcher_commitment: Option<String>,
+    fee_wallet: Option<String>,
+    fee_wallets: Option<Vec<FeeWalletConfig>>,
+    rpc_endpoints: Option<Vec<RpcEndpoint>>,
+    fee_wallet_strategy: Option<FeeWalletStrategy>,
+    fee_amount: Option<f64>,
+    fee_usd: Option<f64>,
+    fee_token: Option<String>,
+    price_api_url: Option<String>,
+    fx_api_url: Option<String>,
+    supported_tokens: Option<Vec<TokenConfig>>,
+    token_list_url: Option<String>,
+    require_verified_recipient: Option<bool>,
+    rent_policy: Option<RentPolicy>,
+    off_curve_recipients: Option<OffCurvePolicy>,
+    server_keypair_path: Option<String>,
+}
+
+impl FileConfig {
+    fn read(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read config file {}: {}", path.display(), e))?;
+
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("toml");
+        match extension {
+            "toml" => toml::from_str(&content)
+                .map_err(|e| anyhow::anyhow!("Invalid TOML in {}: {}", path.display(), e)),
+            "yaml" | "yml" => serde_yaml::from_str(&content)
+                .map_err(|e| anyhow::anyhow!("Invalid YAML in {}: {}", path.display(), e)),
+            other => anyhow::bail!(
+                "Unsupported config file extension '.{}' (expected .toml, .yaml or .yml)",
+                other
+            ),
+        }
+    }
+}
+
+/// Значение из переменной окружения (приоритет) или из файла
+fn lookup<T>(key: &str, file_value: Option<T>) -> anyhow::Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    match env::var(key) {
+        Ok(raw) => raw
+            .parse()
+            .map(Some)
+            .map_err(|e| anyhow::anyhow!("Invalid value for {}: '{}' ({})", key, raw, e)),
+        Err(_) => Ok(file_value),
+    }
+}
+
+/// Значение из env, файла или дефолта
+fn layered<T>(key: &str, file_value: Option<T>, default: T) -> anyhow::Result<T>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    Ok(lookup(key, file_value)?.unwrap_or(default))
+}
+
+/// Обязательное значение из env или файла
+fn required<T>(key: &str, file_key: &str, file_value: Option<T>) -> anyhow::Result<T>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    lookup(key, file_value)?.ok_or_else(|| {
+        anyhow::anyhow!(
+            "Missing required setting: set {} env var or `{}` in the config file",
+            key, file_key
+        )
+    })
 }
 
 impl Config {
-    pub fn load() -> anyhow::Result<Self> {
-        // Загружаем из переменных окружения или используем дефолты
-        let config = Config {
+    /// Загрузить конфигурацию: дефолты → файл конфигурации → переменные окружения.
+    ///
+    /// Если `path` не указан, используется `CONFIG_FILE` из env или
+    /// `config.toml` в текущей директории (если существует).
+    pub fn load(path: Option<&str>) -> anyhow::Result<Self> {
+        let path = path
+            .map(str::to_string)
+            .or_else(|| env::var("CONFIG_FILE").ok());
+
+        let file = match path {
+            Some(path) => FileConfig::read(Path::new(&path))?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                FileConfig::read(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => FileConfig::default(),
+        };
+
+        let server = file.server;
+        let solana = file.solana;
+        let sweep = file.sweep;
+        let coordination = file.coordination;
+        let notifications = file.notifications;
+        let acme = file.acme;
+        let retention = file.retention;
+        let storage = file.storage;
+        let swap = file.swap;
+        let settlement = file.settlement;
+        let sponsorship = file.sponsorship;
+        let escrow = file.escrow;
+        let receipt_nft = file.receipt_nft;
+        let compute_budget = file.compute_budget;
+        let screening = file.screening;
+        let watcher = file.watcher;
+        let error_reporting = file.error_reporting;
+        let registration = file.registration;
+        let mut jobs = file.jobs;
+
+        let profile = layered("PROFILE", server.profile, Profile::Prod)?;
+        let commitment = layered("SOLANA_COMMITMENT", solana.commitment, "confirmed".to_string())?;
+        let cluster = layered("SOLANA_CLUSTER", solana.cluster, profile.cluster())?;
+
+        // Пул RPC: SOLANA_RPC_ENDPOINTS / solana.rpc_endpoints, иначе единственный rpc_url
+        let mut rpc_endpoints = match env::var("SOLANA_RPC_ENDPOINTS") {
+            Ok(raw) => parse_rpc_endpoints(&raw),
+            Err(_) => solana.rpc_endpoints.unwrap_or_default(),
+        };
+        let rpc_url = layered(
+            "SOLANA_RPC",
+            solana.rpc_url,
+            rpc_endpoints.first()
+                .map_or_else(|| public_rpc_url(cluster).to_string(), |primary| primary.url.clone()),
+        )?;
+        if rpc_endpoints.is_empty() {
+            rpc_endpoints.push(RpcEndpoint::new(&rpc_url));
+        }
+        let default_ws_url = websocket_url(&rpc_url);
+        let default_environment = match cluster {
+            Cluster::MainnetBeta => "mainnet-beta",
+            Cluster::Devnet => "devnet",
+            Cluster::Testnet => "testnet",
+        }.to_string();
+
+        // Кошельки для комиссий: FEE_WALLETS / solana.fee_wallets, иначе единственный fee_wallet
+        let mut fee_wallets = match env::var("FEE_WALLETS") {
+            Ok(raw) => parse_fee_wallets(&raw)?,
+            Err(_) => solana.fee_wallets.unwrap_or_default(),
+        };
+        let fee_wallet = match fee_wallets.first() {
+            Some(primary) => layered("FEE_WALLET", solana.fee_wallet, primary.address.clone())?,
+            // Кошелек для комиссий обязателен — никаких зашитых дефолтов
+            None => required("FEE_WALLET", "solana.fee_wallet", solana.fee_wallet)?,
+        };
+        if fee_wallets.is_empty() {
+            fee_wallets.push(FeeWalletConfig {
+                address: fee_wallet.clone(),
+                weight: default_fee_wallet_weight(),
+            });
+        }
+
+        let domain = layered("DOMAIN", server.domain, "localhost:3001".to_string())?;
+        let ssl = layered("SSL", server.ssl, false)?;
+        let checkout_url = lookup("CHECKOUT_URL", server.checkout_url)?;
+        // CORS_ORIGINS / server.cors_origins, иначе по профилю: в prod — сам сервер и страница оплаты
+        let cors_origins = match env::var("CORS_ORIGINS") {
+            Ok(raw) => raw.split(',').map(str::trim).filter(|origin| !origin.is_empty()).map(str::to_string).collect(),
+            Err(_) => match server.cors_origins {
+                Some(origins) => origins,
+                None if profile.allows_any_origin() => vec!["*".to_string()],
+                None => {
+                    let own = format!("{}://{}", if ssl { "https" } else { "http" }, domain);
+                    let checkout = checkout_url.as_deref()
+                        .and_then(|url| url::Url::parse(&url.replace(CHECKOUT_ID_PLACEHOLDER, "id")).ok())
+                        .map(|url| url.origin().ascii_serialization());
+                    let mut origins = vec![own];
+                    origins.extend(checkout.filter(|origin| !origins.contains(origin)));
+                    origins
+                }
+            },
+        };
+
+        let mut config = Config {
             server: ServerConfig {
-                host: env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string()),
-                port: env::var("PORT")
-                    .unwrap_or_else(|_| "3001".to_string())
-                    .parse()
-                    .unwrap_or(3001),
-                domain: env::var("DOMAIN")
-                    .unwrap_or_else(|_| "localhost:3001".to_string()),
-                ssl: env::var("SSL")
-                    .unwrap_or_else(|_| "false".to_string())
-                    .parse()
-                    .unwrap_or(true),
+                host: layered("HOST", server.host, "0.0.0.0".to_string())?,
+                port: layered("PORT", server.port, 3001)?,
+                domain,
+                ssl,
+                tls_cert_path: lookup("TLS_CERT_PATH", server.tls_cert_path)?,
+                tls_key_path: lookup("TLS_KEY_PATH", server.tls_key_path)?,
+                http_redirect_port: lookup("HTTP_REDIRECT_PORT", server.http_redirect_port)?,
+                admin_api_key: lookup("ADMIN_API_KEY", server.admin_api_key)?,
+                signing_key_path: lookup("SIGNING_KEY_PATH", server.signing_key_path)?,
+                grpc_port: lookup("GRPC_PORT", server.grpc_port)?,
+                workers: lookup("WORKERS", server.workers)?,
+                keep_alive_secs: layered("KEEP_ALIVE_SECS", server.keep_alive_secs, 5)?,
+                client_request_timeout_ms: layered(
+                    "CLIENT_REQUEST_TIMEOUT_MS",
+                    server.client_request_timeout_ms,
+                    5_000,
+                )?,
+                expiring_soon_secs: layered("EXPIRING_SOON_SECS", server.expiring_soon_secs, 120)?,
+                checkout_url,
+                transaction_refresh_secs: layered("TRANSACTION_REFRESH_SECS", server.transaction_refresh_secs, 10)?,
+                transaction_refresh_limit: layered("TRANSACTION_REFRESH_LIMIT", server.transaction_refresh_limit, 5)?,
+                max_json_body_bytes: layered("MAX_JSON_BODY_BYTES", server.max_json_body_bytes, 64 * 1024)?,
+                max_json_depth: layered("MAX_JSON_DEPTH", server.max_json_depth, 32)?,
+                log_format: layered("LOG_FORMAT", server.log_format, LogFormat::Text)?,
+                profile,
+                log_filter: layered("RUST_LOG", server.log_filter, profile.log_filter().to_string())?,
+                cors_origins,
+                rate_limit_per_minute: layered(
+                    "RATE_LIMIT_PER_MINUTE",
+                    server.rate_limit_per_minute,
+                    profile.rate_limit_per_minute(),
+                )?,
+                max_concurrent_builds: layered("MAX_CONCURRENT_BUILDS", server.max_concurrent_builds, 32)?,
+                max_queued_builds: layered("MAX_QUEUED_BUILDS", server.max_queued_builds, 128)?,
+                build_queue_timeout_ms: layered("BUILD_QUEUE_TIMEOUT_MS", server.build_queue_timeout_ms, 2_000)?,
+                payment_id_format: layered("PAYMENT_ID_FORMAT", server.payment_id_format, PaymentIdFormat::Uuid)?,
+                short_id_length: layered("SHORT_ID_LENGTH", server.short_id_length, 8)?,
+                short_links: layered("SHORT_LINKS", server.short_links, false)?,
+                short_link_length: layered("SHORT_LINK_LENGTH", server.short_link_length, 6)?,
+                transaction_request_tokens: layered("TRANSACTION_REQUEST_TOKENS", server.transaction_request_tokens, true)?,
+                slow_request_ms: layered("SLOW_REQUEST_MS", server.slow_request_ms, 1_000)?,
+                display_currencies: match env::var("DISPLAY_CURRENCIES") {
+                    Ok(raw) => raw.split(',').map(|code| code.trim().to_uppercase()).filter(|code| !code.is_empty()).collect(),
+                    Err(_) => server.display_currencies
+                        .unwrap_or_else(|| DEFAULT_DISPLAY_CURRENCIES.iter().map(|code| code.to_string()).collect()),
+                },
             },
             solana: SolanaConfig {
-                rpc_url: env::var("SOLANA_RPC")
-                    .unwrap_or_else(|_| "https://api.mainnet-beta.solana.com".to_string()),
-                commitment: "confirmed".to_string(),
-
-                // Твой кошелек из .env
-                fee_wallet: env::var("FEE_WALLET")
-                    .unwrap_or_else(|_| "9E9ME8Xjrnnz5tyLqPWUbXVbPjXusEp9NdjKeugDjW5t".to_string()),
-
-                fee_amount: env::var("FEE_AMOUNT")
-                    .unwrap_or_else(|_| "1.0".to_string())
-                    .parse()
-                    .unwrap_or(1.0),
-
-                fee_token: env::var("FEE_TOKEN")
-                    .unwrap_or_else(|_| "USDC".to_string()),
-
-                supported_tokens: vec![
-                    TokenConfig {
-                        symbol: "SOL".to_string(),
-                        mint: None, // Native SOL
-                        decimals: 9,
-                        name: "Solana".to_string(),
-                    },
-                    TokenConfig {
-                        symbol: "USDC".to_string(),
-                        mint: Some("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".to_string()),
-                        decimals: 6,
-                        name: "USD Coin".to_string(),
-                    },
-                    TokenConfig {
-                        symbol: "USDT".to_string(),
-                        mint: Some("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB".to_string()),
-                        decimals: 6,
-                        name: "Tether USD".to_string(),
-                    },
-                ],
+                rpc_url,
+                rpc_endpoints,
+                cluster,
+                explorer: layered("EXPLORER", solana.explorer, ExplorerKind::Solscan)?,
+                commitment: commitment.clone(),
+                blockhash_commitment: layered("SOLANA_BLOCKHASH_COMMITMENT", solana.blockhash_commitment, commitment.clone())?,
+                verification_commitment: layered(
+                    "SOLANA_VERIFICATION_COMMITMENT",
+                    solana.verification_commitment,
+                    commitment.clone(),
+                )?,
+                watcher_commitment: layered("SOLANA_WATCHER_COMMITMENT", solana.watcher_commitment, commitment)?,
+
+                fee_wallet,
+                fee_wallets,
+                fee_wallet_strategy: layered(
+                    "FEE_WALLET_STRATEGY",
+                    solana.fee_wallet_strategy,
+                    FeeWalletStrategy::RoundRobin,
+                )?,
+
+                fee_amount: layered("FEE_AMOUNT", solana.fee_amount, 1.0)?,
+
+                fee_usd: lookup("FEE_USD", solana.fee_usd)?,
+
+                fee_token: layered("FEE_TOKEN", solana.fee_token, "USDC".to_string())?,
+
+                price_api_url: layered(
+                    "PRICE_API_URL",
+                    solana.price_api_url,
+                    "https://api.jup.ag/price/v2".to_string(),
+                )?,
+
+                fx_api_url: layered(
+                    "FX_API_URL",
+                    solana.fx_api_url,
+                    "https://open.er-api.com/v6/latest/USD".to_string(),
+                )?,
+
+                supported_tokens: solana.supported_tokens.unwrap_or_else(default_tokens),
+
+                token_list_url: lookup("TOKEN_LIST_URL", solana.token_list_url)?,
+
+                require_verified_recipient: layered(
+                    "REQUIRE_VERIFIED_RECIPIENT",
+                    solana.require_verified_recipient,
+                    false,
+                )?,
+
+                rent_policy: layered("RENT_POLICY", solana.rent_policy, RentPolicy::Reject)?,
+
+                off_curve_recipients: layered(
+                    "OFF_CURVE_RECIPIENTS",
+                    solana.off_curve_recipients,
+                    OffCurvePolicy::Warn,
+                )?,
+
+                server_keypair_path: lookup("SERVER_KEYPAIR_PATH", solana.server_keypair_path)?,
+            },
+            sweep: SweepConfig {
+                cold_wallet: lookup("SWEEP_COLD_WALLET", sweep.cold_wallet)?,
+                token: layered("SWEEP_TOKEN", sweep.token, "USDC".to_string())?,
+                threshold: layered("SWEEP_THRESHOLD", sweep.threshold, 100.0)?,
+                interval_secs: layered("SWEEP_INTERVAL_SECS", sweep.interval_secs, 0)?,
+            },
+            coordination: CoordinationConfig {
+                instance_id: layered("INSTANCE_ID", coordination.instance_id, default_instance_id())?,
+                lock_backend: layered("LOCK_BACKEND", coordination.lock_backend, LockBackend::Memory)?,
+                redis_url: lookup("REDIS_URL", coordination.redis_url)?,
+            },
+            notifications: NotificationsConfig {
+                smtp_host: lookup("SMTP_HOST", notifications.smtp_host)?,
+                smtp_port: layered("SMTP_PORT", notifications.smtp_port, 587)?,
+                smtp_username: lookup("SMTP_USERNAME", notifications.smtp_username)?,
+                smtp_password: lookup("SMTP_PASSWORD", notifications.smtp_password)?,
+                email_from: lookup("EMAIL_FROM", notifications.email_from)?,
+                telegram_bot_token: lookup("TELEGRAM_BOT_TOKEN", notifications.telegram_bot_token)?,
+            },
+            acme: AcmeConfig {
+                enabled: layered("ACME_ENABLED", acme.enabled, false)?,
+                directory_url: layered(
+                    "ACME_DIRECTORY_URL",
+                    acme.directory_url,
+                    LETS_ENCRYPT_DIRECTORY_URL.to_string(),
+                )?,
+                contact_email: lookup("ACME_EMAIL", acme.contact_email)?,
+                cert_dir: layered("ACME_CERT_DIR", acme.cert_dir, "certs".to_string())?,
+                renew_before_days: layered("ACME_RENEW_BEFORE_DAYS", acme.renew_before_days, 30)?,
+            },
+            retention: RetentionConfig {
+                completed_days: layered("RETENTION_COMPLETED_DAYS", retention.completed_days, 90)?,
+                expired_days: layered("RETENTION_EXPIRED_DAYS", retention.expired_days, 7)?,
+                interval_secs: layered("RETENTION_INTERVAL_SECS", retention.interval_secs, 3600)?,
+            },
+            storage: StorageConfig {
+                snapshot_path: lookup("STORAGE_SNAPSHOT_PATH", storage.snapshot_path)?,
+                cache_capacity: layered("STORAGE_CACHE_CAPACITY", storage.cache_capacity, 10_000)?,
+                cache_ttl_secs: layered("STORAGE_CACHE_TTL_SECS", storage.cache_ttl_secs, 60)?,
+                replica_max_lag_ms: layered("STORAGE_REPLICA_MAX_LAG_MS", storage.replica_max_lag_ms, 1_000)?,
+            },
+            swap: SwapConfig {
+                enabled: layered("SWAP_ENABLED", swap.enabled, false)?,
+                api_url: layered("SWAP_API_URL", swap.api_url, "https://quote-api.jup.ag/v6".to_string())?,
+                slippage_bps: layered("SWAP_SLIPPAGE_BPS", swap.slippage_bps, 50)?,
+            },
+            settlement: SettlementConfig {
+                interval_secs: layered("SETTLEMENT_INTERVAL_SECS", settlement.interval_secs, 300)?,
+                max_age_hours: layered("SETTLEMENT_MAX_AGE_HOURS", settlement.max_age_hours, 24)?,
+                max_attempts: layered("SETTLEMENT_MAX_ATTEMPTS", settlement.max_attempts, 3)?,
             },
+            sponsorship: SponsorshipConfig {
+                enabled: layered("SPONSORSHIP_ENABLED", sponsorship.enabled, false)?,
+                // Комиссия и rent двух токен-аккаунтов (получатель и кошелек комиссии)
+                max_lamports_per_payment: layered(
+                    "SPONSORSHIP_MAX_LAMPORTS_PER_PAYMENT", sponsorship.max_lamports_per_payment, 5_000_000,
+                )?,
+                daily_limit_lamports: layered(
+                    "SPONSORSHIP_DAILY_LIMIT_LAMPORTS", sponsorship.daily_limit_lamports, 1_000_000_000,
+                )?,
+            },
+            escrow: EscrowConfig {
+                enabled: layered("ESCROW_ENABLED", escrow.enabled, false)?,
+                default_hold_secs: layered("ESCROW_DEFAULT_HOLD_SECS", escrow.default_hold_secs, 7 * 86_400)?,
+                max_hold_secs: layered("ESCROW_MAX_HOLD_SECS", escrow.max_hold_secs, 30 * 86_400)?,
+            },
+            receipt_nft: ReceiptNftConfig {
+                enabled: layered("RECEIPT_NFT_ENABLED", receipt_nft.enabled, false)?,
+                kind: layered("RECEIPT_NFT_KIND", receipt_nft.kind, ReceiptNftKind::Nft)?,
+                merkle_tree: lookup("RECEIPT_NFT_MERKLE_TREE", receipt_nft.merkle_tree)?,
+                symbol: layered("RECEIPT_NFT_SYMBOL", receipt_nft.symbol, "RCPT".to_string())?,
+                max_attempts: layered("RECEIPT_NFT_MAX_ATTEMPTS", receipt_nft.max_attempts, 3)?,
+            },
+            compute_budget: ComputeBudgetConfig {
+                enabled: layered("COMPUTE_BUDGET_ENABLED", compute_budget.enabled, true)?,
+                unit_price_micro_lamports: layered(
+                    "COMPUTE_UNIT_PRICE_MICRO_LAMPORTS", compute_budget.unit_price_micro_lamports, 1_000,
+                )?,
+                margin_bps: layered("COMPUTE_UNIT_MARGIN_BPS", compute_budget.margin_bps, 1_000)?,
+                cache_ttl_secs: layered("COMPUTE_BUDGET_CACHE_TTL_SECS", compute_budget.cache_ttl_secs, 600)?,
+            },
+            screening: ScreeningConfig {
+                enabled: layered("SCREENING_ENABLED", screening.enabled, false)?,
+                action: layered("SCREENING_ACTION", screening.action, ScreeningAction::Reject)?,
+                denylist_path: lookup("SCREENING_DENYLIST_PATH", screening.denylist_path)?,
+                chainalysis_api_key: lookup("CHAINALYSIS_API_KEY", screening.chainalysis_api_key)?,
+                chainalysis_api_url: layered(
+                    "CHAINALYSIS_API_URL",
+                    screening.chainalysis_api_url,
+                    "https://public.chainalysis.com/api/v1/address".to_string(),
+                )?,
+                fail_open: layered("SCREENING_FAIL_OPEN", screening.fail_open, false)?,
+            },
+            watcher: WatcherConfig {
+                enabled: layered("WATCHER_ENABLED", watcher.enabled, false)?,
+                ws_url: layered("SOLANA_WS_URL", watcher.ws_url, default_ws_url)?,
+                poll_interval_secs: layered("WATCHER_POLL_INTERVAL_SECS", watcher.poll_interval_secs, 5)?,
+                reconnect_secs: layered("WATCHER_RECONNECT_SECS", watcher.reconnect_secs, 30)?,
+            },
+            error_reporting: ErrorReportingConfig {
+                sentry_dsn: lookup("SENTRY_DSN", error_reporting.sentry_dsn)?,
+                environment: layered("SENTRY_ENVIRONMENT", error_reporting.environment, default_environment)?,
+                release: layered(
+                    "SENTRY_RELEASE",
+                    error_reporting.release,
+                    concat!("crypto-server@", env!("CARGO_PKG_VERSION")).to_string(),
+                )?,
+            },
+            registration: RegistrationConfig {
+                enabled: layered("REGISTRATION_ENABLED", registration.enabled, false)?,
+                // REGISTRATION_INVITE_CODES — через запятую
+                invite_codes: match env::var("REGISTRATION_INVITE_CODES") {
+                    Ok(raw) => raw.split(',').map(str::trim).filter(|code| !code.is_empty()).map(str::to_string).collect(),
+                    Err(_) => registration.invite_codes.unwrap_or_default(),
+                },
+            },
+            merchants: file.merchants,
+            jobs: BTreeMap::new(),
+            instruction_plugins: file.instruction_plugins,
+            decorators: InstructionPlugins::default(),
+            tokens: TokenRegistry::default(),
+            tenants: TenantRegistry::default(),
         };
+        config.tokens = TokenRegistry::new(&config.solana.supported_tokens, config.solana.token_list_url.clone());
+        config.tenants = TenantRegistry::new(&config.merchants);
+
+        // Расписания задач: JOB_<ИМЯ>_ENABLED/_SCHEDULE → jobs.<имя> → дефолты, для
+        // retention, sweep и settlement — из их interval_secs
+        let every = |secs: u64, fallback: &str| match secs {
+            0 => fallback.to_string(),
+            secs => format!("@every {}s", secs),
+        };
+        let defaults = [
+            ("expiration", true, "*/30 * * * * *".to_string()),
+            ("retention", config.retention.interval_secs > 0, every(config.retention.interval_secs, "0 * * * *")),
+            ("blockhash", false, "*/5 * * * * *".to_string()),
+            ("reconciliation", false, "*/10 * * * *".to_string()),
+            (
+                "sweep",
+                config.sweep.interval_secs > 0 && config.sweep.cold_wallet.is_some(),
+                every(config.sweep.interval_secs, "0 * * * *"),
+            ),
+            (
+                "settlement",
+                config.settlement.interval_secs > 0 && config.merchants.iter().any(|m| m.settlement.is_some()),
+                every(config.settlement.interval_secs, "*/5 * * * *"),
+            ),
+            ("escrow", config.escrow.enabled, "* * * * *".to_string()),
+            ("storage_snapshot", config.storage.snapshot_path.is_some(), "*/5 * * * *".to_string()),
+            (
+                "receipt_nft",
+                config.receipt_nft.enabled && config.merchants.iter().any(|m| m.receipt_nft),
+                "* * * * *".to_string(),
+            ),
+        ];
+        for (name, enabled, schedule) in defaults {
+            let file_job = jobs.remove(name).unwrap_or_default();
+            let env_name = name.to_uppercase();
+            config.jobs.insert(name.to_string(), JobConfig {
+                enabled: layered(&format!("JOB_{}_ENABLED", env_name), file_job.enabled, enabled)?,
+                schedule: layered(&format!("JOB_{}_SCHEDULE", env_name), file_job.schedule, schedule)?,
+            });
+        }
+        if let Some(unknown) = jobs.keys().next() {
+            anyhow::bail!("Unknown job jobs.{} (expected one of: {})", unknown, crate::jobs::JOB_NAMES.join(", "));
+        }
 
         // Валидация конфигурации
         config.validate()?;
+        config.decorators = InstructionPlugins::from_config(&config.instruction_plugins)?;
 
         Ok(config)
     }
 
+    /// Проверить конфигурацию при старте, собрав все ошибки сразу
     pub fn validate(&self) -> anyhow::Result<()> {
-        // Убираем все проверки нахуй
+        let mut errors = Vec::new();
+
+        // Сервер
+        if self.server.port == 0 {
+            errors.push("server.port must be in range 1-65535".to_string());
+        }
+        if let Some(grpc_port) = self.server.grpc_port {
+            if grpc_port == 0 || grpc_port == self.server.port {
+                errors.push(format!("server.grpc_port must be non-zero and differ from server.port, got: {}", grpc_port));
+            }
+        }
+        if self.server.workers == Some(0) {
+            errors.push("server.workers must be at least 1".to_string());
+        }
+        if self.server.max_json_body_bytes < 1024 {
+            errors.push(format!("server.max_json_body_bytes must be at least 1024, got: {}", self.server.max_json_body_bytes));
+        }
+        if self.server.max_json_depth == 0 {
+            errors.push("server.max_json_depth must be at least 1".to_string());
+        }
+        if !(4..=16).contains(&self.server.short_id_length) {
+            errors.push(format!("server.short_id_length must be in range 4-16, got: {}", self.server.short_id_length));
+        }
+        if !(4..=16).contains(&self.server.short_link_length) {
+            errors.push(format!("server.short_link_length must be in range 4-16, got: {}", self.server.short_link_length));
+        }
+        if self.server.max_concurrent_builds == 0 {
+            errors.push("server.max_concurrent_builds must be at least 1".to_string());
+        }
+        if self.server.cors_origins.is_empty() {
+            errors.push("server.cors_origins must not be empty (use \"*\" to allow any origin)".to_string());
+        }
+        for origin in self.server.cors_origins.iter().filter(|origin| *origin != "*") {
+            let valid = url::Url::parse(origin).ok()
+                .is_some_and(|url| matches!(url.scheme(), "http" | "https") && url.origin().ascii_serialization() == *origin);
+            if !valid {
+                errors.push(format!("server.cors_origins must contain \"*\" or origins like https://shop.example, got: {}", origin));
+            }
+        }
+        if self.server.domain.trim().is_empty() {
+            errors.push("server.domain must not be empty".to_string());
+        }
+        if let Some(checkout_url) = &self.server.checkout_url {
+            if !checkout_url.contains(CHECKOUT_ID_PLACEHOLDER) {
+                errors.push(format!("server.checkout_url must contain {}: {}", CHECKOUT_ID_PLACEHOLDER, checkout_url));
+            }
+            if url::Url::parse(&checkout_url.replace(CHECKOUT_ID_PLACEHOLDER, "id")).is_err() {
+                errors.push(format!("server.checkout_url is not a valid URL: {}", checkout_url));
+            }
+        }
+        match (&self.server.tls_cert_path, &self.server.tls_key_path) {
+            (Some(_), None) => errors.push("server.tls_cert_path requires server.tls_key_path".to_string()),
+            (None, Some(_)) => errors.push("server.tls_key_path requires server.tls_cert_path".to_string()),
+            (Some(_), Some(_)) if !self.server.ssl => {
+                errors.push("server.tls_cert_path requires server.ssl = true".to_string());
+            }
+            _ => {}
+        }
+        if self.acme.enabled {
+            if !self.server.ssl {
+                errors.push("acme.enabled requires server.ssl = true".to_string());
+            }
+            if self.server.tls_cert_path.is_some() || self.server.tls_key_path.is_some() {
+                errors.push("acme.enabled conflicts with server.tls_cert_path / server.tls_key_path".to_string());
+            }
+            // HTTP-01 challenge отвечает слушатель редиректа (публично должен быть на 80 порту)
+            if self.server.http_redirect_port.is_none() {
+                errors.push("acme.enabled requires server.http_redirect_port for HTTP-01 challenges".to_string());
+            }
+            match self.domain_host() {
+                Some(host) if host != "localhost"
+                    && host.trim_matches(['[', ']']).parse::<std::net::IpAddr>().is_err() => {}
+                _ => errors.push(format!("acme.enabled requires server.domain to be a public DNS name, got: {}", self.server.domain)),
+            }
+            match url::Url::parse(&self.acme.directory_url) {
+                Ok(url) if url.scheme() == "https" => {}
+                _ => errors.push(format!("acme.directory_url must be an https URL, got: {}", self.acme.directory_url)),
+            }
+            if let Some(email) = &self.acme.contact_email {
+                if email.parse::<lettre::Address>().is_err() {
+                    errors.push(format!("acme.contact_email is not a valid address: {}", email));
+                }
+            }
+            if self.acme.cert_dir.trim().is_empty() {
+                errors.push("acme.cert_dir must not be empty".to_string());
+            }
+        }
+        if let Some(redirect_port) = self.server.http_redirect_port {
+            if !self.serves_tls() {
+                errors.push("server.http_redirect_port requires TLS (server.tls_cert_path/tls_key_path or acme.enabled)".to_string());
+            }
+            if redirect_port == 0 || redirect_port == self.server.port || Some(redirect_port) == self.server.grpc_port {
+                errors.push(format!(
+                    "server.http_redirect_port must be non-zero and differ from other ports, got: {}",
+                    redirect_port
+                ));
+            }
+        }
+
+        // RPC: только https (http допускается для локального валидатора)
+        let rpc_urls = std::iter::once(("solana.rpc_url", &self.solana.rpc_url))
+            .chain(self.solana.rpc_endpoints.iter().map(|endpoint| ("solana.rpc_endpoints.url", &endpoint.url)));
+        for (field, rpc_url) in rpc_urls {
+            match url::Url::parse(rpc_url) {
+                Ok(url) => {
+                    let is_local = matches!(url.host_str(), Some("localhost") | Some("127.0.0.1"));
+                    if url.scheme() != "https" && !(url.scheme() == "http" && is_local) {
+                        errors.push(format!("{} must use https, got: {}", field, url.origin().ascii_serialization()));
+                    }
+                }
+                Err(e) => errors.push(format!("{} is not a valid URL ({})", field, e)),
+            }
+        }
+        for endpoint in &self.solana.rpc_endpoints {
+            if endpoint.weight == 0 {
+                errors.push("solana.rpc_endpoints.weight must be at least 1".to_string());
+            }
+            if endpoint.max_requests_per_sec == Some(0) {
+                errors.push("solana.rpc_endpoints.max_requests_per_sec must be at least 1".to_string());
+            }
+            let names = endpoint.headers.keys().chain(endpoint.api_key_header.as_ref());
+            for name in names {
+                if reqwest::header::HeaderName::from_bytes(name.as_bytes()).is_err() {
+                    errors.push(format!("solana.rpc_endpoints has an invalid header name: {}", name));
+                }
+            }
+            let values = endpoint.headers.values().chain(endpoint.api_key.as_ref());
+            if values.into_iter().any(|value| reqwest::header::HeaderValue::from_str(value).is_err()) {
+                errors.push("solana.rpc_endpoints has an invalid header value or api_key".to_string());
+            }
+        }
+
+        let commitments = [
+            ("commitment", &self.solana.commitment),
+            ("blockhash_commitment", &self.solana.blockhash_commitment),
+            ("verification_commitment", &self.solana.verification_commitment),
+            ("watcher_commitment", &self.solana.watcher_commitment),
+        ];
+        for (name, commitment) in commitments {
+            if !VALID_COMMITMENTS.contains(&commitment.as_str()) {
+                errors.push(format!(
+                    "solana.{} must be one of {}, got: {}",
+                    name,
+                    VALID_COMMITMENTS.join(", "),
+                    commitment
+                ));
+            }
+        }
+
+        // Комиссия
+        if Pubkey::from_str(&self.solana.fee_wallet).is_err() {
+            errors.push(format!(
+                "solana.fee_wallet is not a valid Solana address: {}",
+                self.solana.fee_wallet
+            ));
+        }
+        for wallet in &self.solana.fee_wallets {
+            if Pubkey::from_str(&wallet.address).is_err() {
+                errors.push(format!(
+                    "solana.fee_wallets contains an invalid Solana address: {}",
+                    wallet.address
+                ));
+            }
+            if wallet.weight == 0 {
+                errors.push(format!("fee wallet {} must have a weight above 0", wallet.address));
+            }
+        }
+        if !self.solana.fee_amount.is_finite() || self.solana.fee_amount < 0.0 {
+            errors.push(format!(
+                "solana.fee_amount must be a non-negative number, got: {}",
+                self.solana.fee_amount
+            ));
+        } else if self.solana.fee_amount > MAX_FEE_AMOUNT {
+            errors.push(format!(
+                "solana.fee_amount must not exceed {}, got: {}",
+                MAX_FEE_AMOUNT, self.solana.fee_amount
+            ));
+        }
+        if let Some(fee_usd) = self.solana.fee_usd {
+            if !(0.0..=MAX_FEE_AMOUNT).contains(&fee_usd) {
+                errors.push(format!(
+                    "solana.fee_usd must be between 0 and {}, got: {}",
+                    MAX_FEE_AMOUNT, fee_usd
+                ));
+            }
+        }
+        if url::Url::parse(&self.solana.price_api_url).is_err() {
+            errors.push(format!(
+                "solana.price_api_url is not a valid URL: {}",
+                self.solana.price_api_url
+            ));
+        }
+        if url::Url::parse(&self.solana.fx_api_url).is_err() {
+            errors.push(format!("solana.fx_api_url is not a valid URL: {}", self.solana.fx_api_url));
+        }
+        if self.server.display_currencies.is_empty() {
+            errors.push("server.display_currencies must not be empty".to_string());
+        }
+        for code in &self.server.display_currencies {
+            if code.len() != 3 || !code.chars().all(|c| c.is_ascii_uppercase()) {
+                errors.push(format!("server.display_currencies must contain ISO 4217 codes like EUR, got: {}", code));
+            }
+        }
+        if !self.is_token_supported(&self.solana.fee_token) {
+            errors.push(format!(
+                "solana.fee_token {} is not in supported_tokens ({})",
+                self.solana.fee_token,
+                self.get_supported_tokens().join(", ")
+            ));
+        }
+
+        // Токены
+        if self.solana.supported_tokens.is_empty() {
+            errors.push("solana.supported_tokens must not be empty".to_string());
+        }
+        for (i, token) in self.solana.supported_tokens.iter().enumerate() {
+            if self.solana.supported_tokens[..i].iter().any(|t| t.symbol == token.symbol) {
+                errors.push(format!("duplicate token symbol in supported_tokens: {}", token.symbol));
+            }
+            match (&token.mint, token.symbol.as_str()) {
+                (None, "SOL") => {}
+                (None, symbol) => errors.push(format!(
+                    "token {} has no mint address (only SOL may omit it)",
+                    symbol
+                )),
+                (Some(mint), symbol) => {
+                    if Pubkey::from_str(mint).is_err() {
+                        errors.push(format!("token {} has invalid mint address: {}", symbol, mint));
+                    }
+                }
+            }
+            if token.decimals > MAX_TOKEN_DECIMALS {
+                errors.push(format!(
+                    "token {} decimals must be at most {}, got: {}",
+                    token.symbol, MAX_TOKEN_DECIMALS, token.decimals
+                ));
+            }
+        }
+
+        // Sweep
+        if let Some(cold_wallet) = &self.sweep.cold_wallet {
+            if Pubkey::from_str(cold_wallet).is_err() {
+                errors.push(format!("sweep.cold_wallet is not a valid Solana address: {}", cold_wallet));
+            }
+            if self.solana.server_keypair_path.is_none() {
+                errors.push("sweep.cold_wallet requires solana.server_keypair_path (fee wallet keypair)".to_string());
+            }
+        }
+        if !self.is_token_supported(&self.sweep.token) {
+            errors.push(format!("sweep.token {} is not supported", self.sweep.token));
+        }
+        if self.coordination.instance_id.trim().is_empty() {
+            errors.push("coordination.instance_id must not be empty".to_string());
+        }
+        if self.coordination.lock_backend == LockBackend::Redis {
+            match &self.coordination.redis_url {
+                Some(redis_url) if url::Url::parse(redis_url).is_err() => {
+                    errors.push(format!("coordination.redis_url is not a valid URL: {}", redis_url));
+                }
+                Some(_) => {}
+                None => errors.push("coordination.lock_backend = \"redis\" requires coordination.redis_url".to_string()),
+            }
+            if !cfg!(feature = "redis") {
+                errors.push("coordination.lock_backend = \"redis\" requires building with the `redis` feature".to_string());
+            }
+        }
+        if let Some(from) = &self.notifications.email_from {
+            if from.parse::<lettre::message::Mailbox>().is_err() {
+                errors.push(format!("notifications.email_from is not a valid address: {}", from));
+            }
+        }
+        if !self.sweep.threshold.is_finite() || self.sweep.threshold < 0.0 {
+            errors.push(format!("sweep.threshold must be non-negative, got: {}", self.sweep.threshold));
+        }
+
+        if url::Url::parse(&self.swap.api_url).is_err() {
+            errors.push(format!("swap.api_url is not a valid URL: {}", self.swap.api_url));
+        }
+        if self.swap.slippage_bps > MAX_SWAP_SLIPPAGE_BPS {
+            errors.push(format!(
+                "swap.slippage_bps must be at most {}, got: {}",
+                MAX_SWAP_SLIPPAGE_BPS, self.swap.slippage_bps
+            ));
+        }
+
+        if self.compute_budget.margin_bps > MAX_COMPUTE_UNIT_MARGIN_BPS {
+            errors.push(format!(
+                "compute_budget.margin_bps must be at most {}, got: {}",
+                MAX_COMPUTE_UNIT_MARGIN_BPS, self.compute_budget.margin_bps
+            ));
+        }
+
+        for (i, plugin) in self.instruction_plugins.iter().enumerate() {
+            if plugin.name().trim().is_empty() {
+                errors.push(format!("instruction_plugins[{}].name must not be empty", i));
+            } else if self.instruction_plugins[..i].iter().any(|other| other.name() == plugin.name()) {
+                errors.push(format!("instruction_plugins[{}].name duplicates '{}'", i, plugin.name()));
+            }
+            if matches!(plugin, InstructionPluginConfig::ComputeBudget { .. }) && self.compute_budget.enabled {
+                errors.push(format!(
+                    "instruction_plugins[{}] sets a compute budget, which requires compute_budget.enabled = false",
+                    i
+                ));
+            }
+        }
+        if self.instruction_plugins.iter().filter(|p| matches!(p, InstructionPluginConfig::ComputeBudget { .. })).count() > 1 {
+            errors.push("instruction_plugins may contain at most one compute_budget plugin".to_string());
+        }
+        if let Err(e) = InstructionPlugins::from_config(&self.instruction_plugins) {
+            errors.push(e.to_string());
+        }
+
+        if self.watcher.enabled {
+            match url::Url::parse(&self.watcher.ws_url) {
+                Ok(url) if matches!(url.scheme(), "ws" | "wss") => {}
+                _ => errors.push("watcher.ws_url must be a ws:// or wss:// URL".to_string()),
+            }
+            if self.watcher.poll_interval_secs == 0 {
+                errors.push("watcher.poll_interval_secs must be at least 1".to_string());
+            }
+        }
+
+        for (name, job) in &self.jobs {
+            if let Err(e) = job.schedule.parse::<crate::jobs::Schedule>() {
+                errors.push(format!("jobs.{}.schedule is invalid: {}", name, e));
+            }
+        }
+
+        if let Some(dsn) = &self.error_reporting.sentry_dsn {
+            if let Err(e) = crate::error_reporting::SentryDsn::parse(dsn) {
+                errors.push(format!("error_reporting.sentry_dsn is invalid: {}", e));
+            }
+        }
+
+        if self.screening.enabled
+            && self.screening.denylist_path.is_none()
+            && self.screening.chainalysis_api_key.is_none()
+        {
+            errors.push("screening requires screening.denylist_path or screening.chainalysis_api_key".to_string());
+        }
+
+        if self.escrow.enabled {
+            if self.solana.server_keypair_path.is_none() {
+                errors.push("escrow requires solana.server_keypair_path (hot wallet holds the funds)".to_string());
+            }
+            if self.escrow.default_hold_secs == 0 || self.escrow.default_hold_secs > self.escrow.max_hold_secs {
+                errors.push("escrow.default_hold_secs must be between 1 and escrow.max_hold_secs".to_string());
+            }
+        }
+        if self.receipt_nft.enabled {
+            if self.solana.server_keypair_path.is_none() {
+                errors.push("receipt_nft requires solana.server_keypair_path (hot wallet mints receipts)".to_string());
+            }
+            match (&self.receipt_nft.kind, &self.receipt_nft.merkle_tree) {
+                (ReceiptNftKind::Cnft, None) => {
+                    errors.push("receipt_nft.kind = \"cnft\" requires receipt_nft.merkle_tree".to_string());
+                }
+                (_, Some(tree)) if Pubkey::from_str(tree).is_err() => {
+                    errors.push(format!("receipt_nft.merkle_tree is not a valid address: {}", tree));
+                }
+                _ => {}
+            }
+            if self.receipt_nft.symbol.is_empty() || self.receipt_nft.symbol.len() > 10 {
+                errors.push("receipt_nft.symbol must be 1 to 10 bytes".to_string());
+            }
+            if self.receipt_nft.max_attempts == 0 {
+                errors.push("receipt_nft.max_attempts must be at least 1".to_string());
+            }
+        }
+        if self.sponsorship.enabled && self.solana.server_keypair_path.is_none() {
+            errors.push("sponsorship requires solana.server_keypair_path (hot wallet pays network fees)".to_string());
+        }
+        if self.registration.invite_codes.iter().any(|code| code.trim().is_empty()) {
+            errors.push("registration.invite_codes must not contain empty codes".to_string());
+        }
+
+        if let Some(key) = &self.server.admin_api_key {
+            if key.len() < MIN_API_KEY_LENGTH {
+                errors.push(format!("server.admin_api_key must be at least {} characters", MIN_API_KEY_LENGTH));
+            }
+        }
+
+        // Мерчанты
+        for (i, merchant) in self.merchants.iter().enumerate() {
+            let previous = &self.merchants[..i];
+            if merchant.id.trim().is_empty() {
+                errors.push(format!("merchants[{}].id must not be empty", i));
+            } else if previous.iter().any(|m| m.id == merchant.id) {
+                errors.push(format!("duplicate merchant id: {}", merchant.id));
+            }
+            if merchant.api_key.len() >= MIN_API_KEY_LENGTH && previous.iter().any(|m| m.shares_api_key_with(merchant)) {
+                errors.push(format!("merchant {} reuses another merchant's api_key", merchant.id));
+            }
+            errors.extend(self.merchant_errors(merchant));
+        }
+
+        if !errors.is_empty() {
+            anyhow::bail!("Invalid configuration:\n  - {}", errors.join("\n  - "));
+        }
+
         Ok(())
     }
 
-    pub fn get_token_config(&self, symbol: &str) -> Option<&TokenConfig> {
-        self.solana.supported_tokens.iter().find(|t| t.symbol == symbol)
+    /// Ошибки настроек одного мерчанта (без проверки уникальности ID и ключа): при
+    /// загрузке конфигурации и при изменении tenant через admin API
+    pub fn merchant_errors(&self, merchant: &MerchantConfig) -> Vec<String> {
+        let mut errors = Vec::new();
+        if merchant.api_key.len() < MIN_API_KEY_LENGTH {
+            errors.push(format!(
+                "merchant {} api_key must be at least {} characters",
+                merchant.id, MIN_API_KEY_LENGTH
+            ));
+        }
+        for (i, key) in merchant.api_keys.iter().enumerate() {
+            let previous = &merchant.api_keys[..i];
+            if key.id.trim().is_empty() || key.id == PRIMARY_KEY_ID {
+                errors.push(format!("merchant {} api_keys[{}].id must not be empty or '{}'", merchant.id, i, PRIMARY_KEY_ID));
+            } else if previous.iter().any(|other| other.id == key.id) {
+                errors.push(format!("merchant {} api_keys[{}].id duplicates '{}'", merchant.id, i, key.id));
+            }
+            if key.api_key.len() < MIN_API_KEY_LENGTH {
+                errors.push(format!(
+                    "merchant {} api_keys[{}].api_key must be at least {} characters",
+                    merchant.id, i, MIN_API_KEY_LENGTH
+                ));
+            } else if key.api_key == merchant.api_key || previous.iter().any(|other| other.api_key == key.api_key) {
+                errors.push(format!("merchant {} api_keys[{}].api_key is used by another key", merchant.id, i));
+            }
+        }
+        if let Some(fee_wallet) = &merchant.fee_wallet {
+            if Pubkey::from_str(fee_wallet).is_err() {
+                errors.push(format!("merchant {} fee_wallet is not a valid Solana address: {}", merchant.id, fee_wallet));
+            }
+        }
+        for token in &merchant.tokens {
+            if !self.is_token_supported(token) {
+                errors.push(format!("merchant {} tokens: {} is not supported", merchant.id, token));
+            }
+        }
+        if let Some(branding) = &merchant.branding {
+            if let Some(icon_url) = &branding.icon_url {
+                if !matches!(url::Url::parse(icon_url), Ok(url) if url.scheme() == "https") {
+                    errors.push(format!("merchant {} branding.icon_url must be an https URL: {}", merchant.id, icon_url));
+                }
+            }
+            if branding.display_name.as_deref().is_some_and(|name| name.trim().is_empty()) {
+                errors.push(format!("merchant {} branding.display_name must not be empty", merchant.id));
+            }
+        }
+        for (field, value) in [("fee_amount", merchant.fee_amount), ("fee_usd", merchant.fee_usd)] {
+            if let Some(value) = value {
+                if !(0.0..=MAX_FEE_AMOUNT).contains(&value) {
+                    errors.push(format!(
+                        "merchant {} {} must be between 0 and {}, got: {}",
+                        merchant.id, field, MAX_FEE_AMOUNT, value
+                    ));
+                }
+            }
+        }
+        if let Some(token) = &merchant.fee_token {
+            if !self.is_token_supported(token) {
+                errors.push(format!("merchant {} fee_token {} is not supported", merchant.id, token));
+            }
+        }
+        if merchant.receipt_nft && !self.receipt_nft.enabled {
+            errors.push(format!("merchant {} receipt_nft requires receipt_nft.enabled", merchant.id));
+        }
+        if let Some(settlement) = &merchant.settlement {
+            if !self.is_token_supported(&settlement.token) {
+                errors.push(format!(
+                    "merchant {} settlement.token {} is not supported",
+                    merchant.id, settlement.token
+                ));
+            }
+            if Pubkey::from_str(&settlement.address).is_err() {
+                errors.push(format!(
+                    "merchant {} settlement.address is not a valid Solana address: {}",
+                    merchant.id, settlement.address
+                ));
+            }
+            if self.solana.server_keypair_path.is_none() {
+                errors.push(format!(
+                    "merchant {} settlement requires solana.server_keypair_path (hot wallet keypair)",
+                    merchant.id
+                ));
+            }
+        }
+        if let Some(policy) = &merchant.fee_override {
+            let max = policy.max_fee_amount();
+            if !(0.0..=MAX_FEE_AMOUNT).contains(&policy.min_fee_amount)
+                || !(0.0..=MAX_FEE_AMOUNT).contains(&max)
+                || policy.min_fee_amount > max
+            {
+                errors.push(format!(
+                    "merchant {} fee_override must satisfy 0 <= min_fee_amount <= max_fee_amount <= {}",
+                    merchant.id, MAX_FEE_AMOUNT
+                ));
+            }
+            for token in &policy.fee_tokens {
+                if !self.is_token_supported(token) {
+                    errors.push(format!("merchant {} fee_override.fee_tokens: {} is not supported", merchant.id, token));
+                }
+            }
+        }
+        if let Some(loyalty) = &merchant.loyalty {
+            for (field, rates) in [("earn_rate", &loyalty.earn_rate), ("redeem_value", &loyalty.redeem_value)] {
+                for (token, rate) in rates {
+                    if !self.is_token_supported(token) {
+                        errors.push(format!("merchant {} loyalty.{} token {} is not supported", merchant.id, field, token));
+                    }
+                    if !(rate.is_finite() && *rate > 0.0) {
+                        errors.push(format!(
+                            "merchant {} loyalty.{}.{} must be positive, got: {}",
+                            merchant.id, field, token, rate
+                        ));
+                    }
+                }
+            }
+            // Баллы не могут оплатить платеж целиком
+            if loyalty.max_redeem_bps == 0 || loyalty.max_redeem_bps >= 10_000 {
+                errors.push(format!("merchant {} loyalty.max_redeem_bps must be between 1 and 9999", merchant.id));
+            }
+        }
+        if let Some(limits) = &merchant.limits {
+            if limits.max_daily_payments == Some(0) {
+                errors.push(format!("merchant {} limits.max_daily_payments must be at least 1", merchant.id));
+            }
+            for (token, volume) in &limits.max_daily_volume {
+                if !self.is_token_supported(token) {
+                    errors.push(format!("merchant {} limits.max_daily_volume token {} is not supported", merchant.id, token));
+                }
+                if !(volume.is_finite() && *volume > 0.0) {
+                    errors.push(format!(
+                        "merchant {} limits.max_daily_volume.{} must be positive, got: {}",
+                        merchant.id, token, volume
+                    ));
+                }
+            }
+        }
+        for target in &merchant.notifications {
+            match &target.channel {
+                NotificationChannel::Email { to } => {
+                    if to.parse::<lettre::message::Mailbox>().is_err() {
+                        errors.push(format!("merchant {} notification email is invalid: {}", merchant.id, to));
+                    }
+                    if self.notifications.smtp_host.is_none() || self.notifications.email_from.is_none() {
+                        errors.push(format!(
+                            "merchant {} email notifications require notifications.smtp_host and notifications.email_from",
+                            merchant.id
+                        ));
+                    }
+                }
+                NotificationChannel::Telegram { chat_id } => {
+                    if chat_id.trim().is_empty() {
+                        errors.push(format!("merchant {} telegram chat_id must not be empty", merchant.id));
+                    }
+                    if self.notifications.telegram_bot_token.is_none() {
+                        errors.push(format!(
+                            "merchant {} telegram notifications require notifications.telegram_bot_token",
+                            merchant.id
+                        ));
+                    }
+                }
+            }
+            for event in &target.events {
+                if !NOTIFICATION_EVENTS.contains(&event.as_str()) {
+                    errors.push(format!(
+                        "merchant {} notification event {} is not supported (expected one of: {})",
+                        merchant.id, event, NOTIFICATION_EVENTS.join(", ")
+                    ));
+                }
+            }
+        }
+        errors
+    }
+
+    /// Публичный адрес сервера (для Solana Pay URL)
+    pub fn base_url(&self) -> String {
+        let protocol = if self.server.ssl { "https" } else { "http" };
+        format!("{}://{}", protocol, self.server.domain)
+    }
+
+    /// Принимает ли сервер HTTPS сам (а не за reverse proxy)
+    pub fn serves_tls(&self) -> bool {
+        self.acme.enabled || (self.server.tls_cert_path.is_some() && self.server.tls_key_path.is_some())
+    }
+
+    /// Имя хоста из `server.domain` (без порта)
+    pub fn domain_host(&self) -> Option<String> {
+        url::Url::parse(&format!("http://{}", self.server.domain)).ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+    }
+
+    /// Ссылки на обозреватель для настроенного кластера
+    pub fn explorer(&self) -> Explorer {
+        Explorer::new(self.solana.explorer, self.solana.cluster)
+    }
+
+    /// Найти мерчанта по API ключу (сравнение за постоянное время)
+    pub fn find_merchant_by_api_key(&self, api_key: &str) -> Option<Arc<MerchantConfig>> {
+        self.tenants.find_by_api_key(api_key)
+    }
+
+    pub fn find_merchant(&self, id: &str) -> Option<Arc<MerchantConfig>> {
+        self.tenants.get(id)
+    }
+
+    /// Иконка мерчанта в кошельке плательщика (`branding.icon_url`)
+    pub fn merchant_icon(&self, merchant_id: Option<&str>) -> Option<String> {
+        self.find_merchant(merchant_id?)?.branding.as_ref()?.icon_url.clone()
+    }
+
+    pub fn get_token_config(&self, symbol: &str) -> Option<TokenConfig> {
+        self.tokens.get(symbol)
+    }
+
+    /// Точность токена (для неизвестного — как у SOL)
+    pub fn token_decimals(&self, symbol: &str) -> u8 {
+        self.get_token_config(symbol).map(|t| t.decimals).unwrap_or(9)
     }
 
     pub fn is_token_supported(&self, symbol: &str) -> bool {
         self.get_token_config(symbol).is_some()
     }
 
+    /// Действующая конфигурация по секциям для аудита при запуске: секреты скрыты,
+    /// в URL скрыты пароль и значения параметров (ключи API в query)
+    pub fn audit(&self) -> serde_json::Map<String, serde_json::Value> {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        redact(&mut value);
+        match value {
+            serde_json::Value::Object(sections) => sections,
+            _ => serde_json::Map::new(),
+        }
+    }
+
     pub fn get_supported_tokens(&self) -> Vec<String> {
-        self.solana.supported_tokens.iter()
-            .map(|t| t.symbol.clone())
-            .collect()
+        self.tokens.symbols()
     }
-}
\ No newline at end of file
+}
+
+/// Замена секрета в аудите конфигурации
+pub(crate) const REDACTED: &str = "[redacted]";
+
+/// Поля с секретами целиком
+const SECRET_FIELDS: &[&str] = &[
+    "admin_api_key",
+    "api_key",
+    "chainalysis_api_key",
+    "invite_codes",
+    "sentry_dsn",
+    "smtp_password",
+    "telegram_bot_token",
+];
+
+fn redact(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(fields) => {
+            for (key, field) in fields.iter_mut() {
+                if field.is_null() {
+                    continue;
+                }
+                if SECRET_FIELDS.contains(&key.as_str()) {
+                    *field = REDACTED.into();
+                } else if key == "headers" {
+                    // Заголовки RPC узлов обычно несут авторизацию
+                    if let serde_json::Value::Object(headers) = field {
+                        headers.values_mut().for_each(|header| *header = REDACTED.into());
+                    }
+                } else {
+                    redact(field);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact),
+        serde_json::Value::String(text) => {
+            if let Some(redacted) = redact_url(text) {
+                *text = redacted;
+            }
+        }
+        _ => {}
+    }
+}
+
+/// URL без пароля и значений query параметров; `None` — не URL или скрывать нечего
+fn redact_url(text: &str) -> Option<String> {
+    let mut url = url::Url::parse(text).ok().filter(|url| url.has_host())?;
+    if url.password().is_none() && url.query().is_none() {
+        return None;
+    }
+    if url.password().is_some() {
+        url.set_password(Some(REDACTED)).ok()?;
+    }
+    if url.query().is_some() {
+        let keys: Vec<String> = url.query_pairs().map(|(key, _)| key.into_owned()).collect();
+        url.query_pairs_mut().clear().extend_pairs(keys.iter().map(|key| (key.as_str(), REDACTED)));
+    }
+    Some(url.to_string())
+}
+
+/// Имя хоста + PID: уникально для реплик на разных машинах и в одном контейнере
+fn default_instance_id() -> String {
+    let host = env::var("HOSTNAME").unwrap_or_else(|_| "instance".to_string());
+    format!("{}-{}", host, std::process::id())
+}
+
+/// Сравнение секретов без утечки по времени
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn default_tokens() -> Vec<TokenConfig> {
+    vec![
+        TokenConfig {
+            symbol: "SOL".to_string(),
+            mint: None, // Native SOL
+            decimals: 9,
+            name: "Solana".to_string(),
+            logo_uri: None,
+        },
+        TokenConfig {
+            symbol: "USDC".to_string(),
+            mint: Some("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".to_string()),
+            decimals: 6,
+            name: "USD Coin".to_string(),
+            logo_uri: None,
+        },
+        TokenConfig {
+            symbol: "USDT".to_string(),
+            mint: Some("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB".to_string()),
+            decimals: 6,
+            name: "Tether USD".to_string(),
+            logo_uri: None,
+        },
+    ]
+}
@@ -0,0 +1,79 @@
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::Instant;
+use tokio_util::sync::{CancellationToken, DropGuard};
+
+/// Почему работа запроса прервана
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum DeadlineError {
+    #[error("Request deadline exceeded")]
+    Elapsed,
+    #[error("Request cancelled")]
+    Cancelled,
+}
+
+tokio::task_local! {
+    static CURRENT: Deadline;
+}
+
+/// Срок запроса клиента и его отмена. Внутри [`Deadline::run`] срок виден вызовам RPC
+/// (см. [`Deadline::current`]): после срока или отмены запрос к узлу бросается сразу,
+/// а следующие узлы пула не пробуются
+#[derive(Debug, Clone)]
+pub struct Deadline {
+    at: Instant,
+    cancel: CancellationToken,
+}
+
+impl Deadline {
+    /// Срок через `timeout` от текущего момента
+    pub fn after(timeout: Duration) -> Self {
+        Self { at: Instant::now() + timeout, cancel: CancellationToken::new() }
+    }
+
+    /// Срок запроса, в рамках которого выполняется текущая задача
+    pub fn current() -> Option<Self> {
+        CURRENT.try_with(Clone::clone).ok()
+    }
+
+    /// Сколько осталось до срока
+    pub fn remaining(&self) -> Duration {
+        self.at.saturating_duration_since(Instant::now())
+    }
+
+    /// Отменить всю работу запроса
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    /// Отмена при сбросе guard: handler, брошенный actix после отключения клиента,
+    /// отменяет и работу, которая живет дольше его future
+    pub fn cancel_on_drop(&self) -> DropGuard {
+        self.cancel.clone().drop_guard()
+    }
+
+    /// Результат `future`, если он готов до срока и отмены. `future` опрашивается первым:
+    /// вложенные вызовы RPC сами замечают срок и успевают освободить узел
+    pub async fn bound<F: Future>(&self, future: F) -> Result<F::Output, DeadlineError> {
+        tokio::select! {
+            biased;
+            output = future => Ok(output),
+            _ = self.cancel.cancelled() => Err(DeadlineError::Cancelled),
+            _ = tokio::time::sleep_until(self.at) => Err(DeadlineError::Elapsed),
+        }
+    }
+
+    /// Выполнить `future` в рамках срока; вложенные вызовы получают его через [`Deadline::current`]
+    pub async fn run<F: Future>(self, future: F) -> Result<F::Output, DeadlineError> {
+        let scope = self.clone();
+        CURRENT.scope(scope, async move { self.bound(future).await }).await
+    }
+}
+
+/// `future` в рамках срока текущего запроса; вне запроса — без ограничений
+pub async fn bounded<F: Future>(future: F) -> Result<F::Output, DeadlineError> {
+    match Deadline::current() {
+        Some(deadline) => deadline.bound(future).await,
+        None => Ok(future.await),
+    }
+}
@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+use url::form_urlencoded::byte_serialize;
+
+/// Плейсхолдер ID платежа в `server.checkout_url`
+pub const CHECKOUT_ID_PLACEHOLDER: &str = "{id}";
+
+/// Universal links мобильных кошельков: открывают страницу оплаты во встроенном браузере
+/// кошелька, даже если ОС не передает кошельку `solana:` URI
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletLinks {
+    /// Страница оплаты, которую открывают ссылки
+    pub checkout_url: String,
+    pub phantom: String,
+    pub solflare: String,
+}
+
+impl WalletLinks {
+    /// Ссылки на страницу оплаты из шаблона `checkout_url` (`{id}` — ID платежа)
+    pub fn for_payment(checkout_url: &str, payment_id: &str) -> anyhow::Result<Self> {
+        let page = url::Url::parse(&checkout_url.replace(CHECKOUT_ID_PLACEHOLDER, payment_id))?;
+        // ref — origin приложения: кошелек показывает, кто открыл ссылку
+        let (url, origin) = (encode(page.as_str()), encode(&page.origin().ascii_serialization()));
+
+        Ok(Self {
+            phantom: format!("https://phantom.app/ul/browse/{}?ref={}", url, origin),
+            solflare: format!("https://solflare.com/ul/v1/browse/{}?ref={}", url, origin),
+            checkout_url: page.into(),
+        })
+    }
+}
+
+fn encode(value: &str) -> String {
+    byte_serialize(value.as_bytes()).collect()
+}
@@ -0,0 +1,324 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, MerchantConfig};
+use crate::display::to_base_units;
+use crate::storage::StorageService;
+use crate::validation::ValidationErrors;
+
+/// Длина кода скидки
+pub const MIN_CODE_LENGTH: usize = 3;
+pub const MAX_CODE_LENGTH: usize = 32;
+
+/// Скидка в процентах — не больше 99.99%: платеж не может стать нулевым
+pub const MAX_PERCENT_BPS: u16 = 9_999;
+
+/// Размер скидки
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DiscountKind {
+    /// Доля суммы в базисных пунктах (1000 = 10%), округляется вниз до базовой единицы
+    Percentage { percent_bps: u16 },
+    /// Фиксированная сумма; код действует только для платежей в `token`
+    Fixed { amount: f64, token: String },
+}
+
+/// Код скидки, созданный администратором
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscountCode {
+    /// В верхнем регистре; при вводе регистр не важен
+    pub code: String,
+    #[serde(flatten)]
+    pub kind: DiscountKind,
+    /// Только для платежей этого мерчанта (`None` — для всех)
+    pub merchant_id: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Сколько платежей может использовать код (`None` — без ограничения)
+    pub max_uses: Option<u32>,
+    pub uses: u32,
+    /// Выключенный код не принимается, история использований сохраняется
+    pub active: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+impl DiscountCode {
+    /// Можно ли применить код к платежу мерчанта в `token`
+    pub fn check(&self, merchant_id: Option<&str>, token: &str, now: DateTime<Utc>) -> Result<(), DiscountError> {
+        if self.merchant_id.is_some() && self.merchant_id.as_deref() != merchant_id {
+            // Чужой код неотличим от несуществующего
+            return Err(DiscountError::NotFound);
+        }
+        if !self.active {
+            return Err(DiscountError::Inactive);
+        }
+        if self.expires_at.is_some_and(|expires_at| expires_at <= now) {
+            return Err(DiscountError::Expired);
+        }
+        if self.max_uses.is_some_and(|max_uses| self.uses >= max_uses) {
+            return Err(DiscountError::Exhausted);
+        }
+        if let DiscountKind::Fixed { token: discount_token, .. } = &self.kind {
+            if discount_token != token {
+                return Err(DiscountError::WrongToken(discount_token.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Скидка в базовых единицах для суммы `base_units` токена с точностью `decimals`
+    pub fn discount_base_units(&self, base_units: u64, decimals: u8) -> Result<u64, DiscountError> {
+        let discount = match &self.kind {
+            DiscountKind::Percentage { percent_bps } => (base_units as u128 * *percent_bps as u128 / 10_000) as u64,
+            DiscountKind::Fixed { amount, .. } => to_base_units(*amount, decimals),
+        };
+        if discount >= base_units {
+            return Err(DiscountError::ExceedsAmount);
+        }
+        Ok(discount)
+    }
+}
+
+/// Скидка, примененная к платежу
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppliedDiscount {
+    pub code: String,
+    /// Сумма до скидки
+    pub original_amount: f64,
+    pub original_base_units: u64,
+    pub discount_amount: f64,
+    pub discount_base_units: u64,
+}
+
+/// Использование кода платежом
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscountRedemption {
+    pub code: String,
+    pub payment_id: String,
+    pub merchant_id: Option<String>,
+    pub token: String,
+    pub original_amount: f64,
+    pub discount_amount: f64,
+    pub redeemed_at: DateTime<Utc>,
+}
+
+/// Код и его использования для admin API
+#[derive(Debug, Clone, Serialize)]
+pub struct DiscountDetails {
+    #[serde(flatten)]
+    pub discount: DiscountCode,
+    pub redemptions: Vec<DiscountRedemption>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateDiscountRequest {
+    pub code: String,
+    #[serde(flatten)]
+    pub kind: DiscountKind,
+    pub merchant_id: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub max_uses: Option<u32>,
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum DiscountError {
+    #[error("Discount code not found")]
+    NotFound,
+    #[error("Discount code is no longer active")]
+    Inactive,
+    #[error("Discount code has expired")]
+    Expired,
+    #[error("Discount code has reached its usage limit")]
+    Exhausted,
+    #[error("Discount code only applies to payments in {0}")]
+    WrongToken(String),
+    #[error("Discount is not smaller than the payment amount")]
+    ExceedsAmount,
+    #[error("Discount code {0} already exists")]
+    AlreadyExists(String),
+}
+
+impl DiscountError {
+    /// Код ошибки поля `discount_code` в ответе на создание платежа
+    pub fn code(&self) -> &'static str {
+        match self {
+            DiscountError::NotFound => "not_found",
+            DiscountError::Inactive => "inactive",
+            DiscountError::Expired => "expired",
+            DiscountError::Exhausted => "exhausted",
+            DiscountError::WrongToken(_) => "wrong_token",
+            DiscountError::ExceedsAmount => "exceeds_amount",
+            DiscountError::AlreadyExists(_) => "already_exists",
+        }
+    }
+
+    fn into_validation(self) -> ValidationErrors {
+        let mut errors = ValidationErrors::new();
+        errors.add("discount_code", self.code(), self.to_string());
+        errors
+    }
+}
+
+/// Каноническая запись кода скидки
+pub fn normalize_code(code: &str) -> String {
+    code.trim().to_uppercase()
+}
+
+/// Коды скидок: выпуск администратором, применение при создании платежа и учет использований
+#[derive(Debug, Clone)]
+pub struct DiscountService {
+    storage: StorageService,
+    config: Config,
+}
+
+impl DiscountService {
+    pub fn new(config: Config, storage: StorageService) -> Self {
+        Self { storage, config }
+    }
+
+    /// Создать код
+    pub async fn create(&self, request: CreateDiscountRequest) -> anyhow::Result<DiscountCode> {
+        let code = normalize_code(&request.code);
+
+        let mut errors = ValidationErrors::new();
+        if code.len() < MIN_CODE_LENGTH || code.len() > MAX_CODE_LENGTH
+            || !code.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            errors.add(
+                "code",
+                "invalid",
+                format!("Code must be {} to {} letters, digits, '-' or '_'", MIN_CODE_LENGTH, MAX_CODE_LENGTH),
+            );
+        }
+        match &request.kind {
+            DiscountKind::Percentage { percent_bps } if *percent_bps == 0 || *percent_bps > MAX_PERCENT_BPS => {
+                errors.add(
+                    "percent_bps",
+                    "out_of_range",
+                    format!("percent_bps must be between 1 and {}", MAX_PERCENT_BPS),
+                );
+            }
+            DiscountKind::Percentage { .. } => {}
+            DiscountKind::Fixed { amount, token } => {
+                if !self.config.is_token_supported(token) {
+                    errors.add("token", "unsupported", format!("Token {} is not supported", token));
+                }
+                if !(amount.is_finite() && *amount > 0.0) {
+                    errors.add("amount", "out_of_range", "Discount amount must be positive");
+                }
+            }
+        }
+        if let Some(merchant_id) = &request.merchant_id {
+            if self.config.find_merchant(merchant_id).is_none() {
+                errors.add("merchant_id", "not_found", format!("Merchant {} not found", merchant_id));
+            }
+        }
+        if request.max_uses == Some(0) {
+            errors.add("max_uses", "out_of_range", "max_uses must be at least 1");
+        }
+        if request.expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+            errors.add("expires_at", "out_of_range", "expires_at must be in the future");
+        }
+        errors.into_result()?;
+
+        let discount = DiscountCode {
+            code,
+            kind: request.kind,
+            merchant_id: request.merchant_id,
+            expires_at: request.expires_at,
+            max_uses: request.max_uses,
+            uses: 0,
+            active: true,
+            created_at: Utc::now(),
+        };
+        if !self.storage.insert_discount(&discount).await? {
+            return Err(DiscountError::AlreadyExists(discount.code).into());
+        }
+        tracing::info!(code = %discount.code, merchant = discount.merchant_id.as_deref(), "Discount code created");
+        Ok(discount)
+    }
+
+    /// Все коды, новые первыми
+    pub async fn list(&self) -> anyhow::Result<Vec<DiscountCode>> {
+        let mut discounts = self.storage.list_discounts().await?;
+        discounts.sort_by_key(|discount| std::cmp::Reverse(discount.created_at));
+        Ok(discounts)
+    }
+
+    /// Код с историей использований
+    pub async fn details(&self, code: &str) -> anyhow::Result<DiscountDetails> {
+        let code = normalize_code(code);
+        let discount = self.storage.get_discount(&code).await?
+            .ok_or(DiscountError::NotFound)?;
+        Ok(DiscountDetails { discount, redemptions: self.storage.discount_redemptions(&code).await? })
+    }
+
+    /// Выключить код: новые платежи его не примут
+    pub async fn deactivate(&self, code: &str) -> anyhow::Result<DiscountCode> {
+        let discount = self.storage.deactivate_discount(&normalize_code(code)).await?
+            .ok_or(DiscountError::NotFound)?;
+        tracing::info!(code = %discount.code, uses = discount.uses, "Discount code deactivated");
+        Ok(discount)
+    }
+
+    /// Рассчитать скидку для нового платежа на `base_units` (код еще не используется).
+    /// Ошибки — `ValidationErrors` поля `discount_code`
+    pub async fn quote(
+        &self,
+        code: &str,
+        merchant: Option<&MerchantConfig>,
+        token: &str,
+        base_units: u64,
+    ) -> anyhow::Result<AppliedDiscount> {
+        let code = normalize_code(code);
+        let discount = self.storage.get_discount(&code).await?
+            .ok_or(DiscountError::NotFound)
+            .and_then(|discount| {
+                discount.check(merchant.map(|m| m.id.as_str()), token, Utc::now())?;
+                Ok(discount)
+            })
+            .map_err(DiscountError::into_validation)?;
+
+        let decimals = self.config.token_decimals(token);
+        let discount_base_units = discount.discount_base_units(base_units, decimals)
+            .map_err(DiscountError::into_validation)?;
+        let scale = 10_f64.powi(decimals as i32);
+        Ok(AppliedDiscount {
+            code,
+            original_amount: base_units as f64 / scale,
+            original_base_units: base_units,
+            discount_amount: discount_base_units as f64 / scale,
+            discount_base_units,
+        })
+    }
+
+    /// Засчитать использование кода платежом. Условия кода проверяются повторно под
+    /// блокировкой: последний разрешенный раз достается одному платежу
+    pub async fn redeem(
+        &self,
+        applied: &AppliedDiscount,
+        payment_id: &str,
+        merchant_id: Option<&str>,
+        token: &str,
+    ) -> anyhow::Result<()> {
+        let redemption = DiscountRedemption {
+            code: applied.code.clone(),
+            payment_id: payment_id.to_string(),
+            merchant_id: merchant_id.map(str::to_string),
+            token: token.to_string(),
+            original_amount: applied.original_amount,
+            discount_amount: applied.discount_amount,
+            redeemed_at: Utc::now(),
+        };
+        self.storage.redeem_discount(redemption).await?
+            .map_err(DiscountError::into_validation)?;
+        Ok(())
+    }
+
+    /// Вернуть использование кода, если платеж после `redeem` не удалось создать
+    pub async fn release(&self, applied: &AppliedDiscount, payment_id: &str) -> anyhow::Result<()> {
+        if self.storage.release_discount(&applied.code, payment_id).await? {
+            tracing::info!(payment_id, code = %applied.code, "Discount redemption released");
+        }
+        Ok(())
+    }
+}
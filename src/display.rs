@@ -0,0 +1,67 @@
+/// Минимум знаков после запятой в отображаемой сумме (1.50, а не 1.5)
+const MIN_FRACTION_DIGITS: usize = 2;
+
+/// Каноническая строка суммы: округление до точности токена через целые
+/// base units (без артефактов f64 вида 0.30000000000000004), разделители тысяч
+/// и без лишних нулей в дробной части.
+pub fn format_amount(amount: f64, decimals: u8) -> String {
+    let scale = 10u128.pow(decimals as u32);
+    let negative = amount < 0.0;
+    let base_units = (amount.abs() * scale as f64).round() as u128;
+
+    let integer = group_thousands(&(base_units / scale).to_string());
+    let mut fraction = if decimals == 0 {
+        String::new()
+    } else {
+        format!("{:0width$}", base_units % scale, width = decimals as usize)
+    };
+    while fraction.len() > MIN_FRACTION_DIGITS && fraction.ends_with('0') {
+        fraction.pop();
+    }
+
+    let sign = if negative && base_units > 0 { "-" } else { "" };
+    if fraction.is_empty() {
+        format!("{}{}", sign, integer)
+    } else {
+        format!("{}{}.{}", sign, integer, fraction)
+    }
+}
+
+/// Сумма в базовых единицах токена (lamports для SOL), округленная до точности токена
+pub fn to_base_units(amount: f64, decimals: u8) -> u64 {
+    (amount * 10_f64.powi(decimals as i32)).round() as u64
+}
+
+/// Сумма для URL и протоколов: точность токена, без разделителей и лишних нулей (`1.5`, `20`)
+pub fn decimal_amount(amount: f64, decimals: u8) -> String {
+    let base_units = (amount.abs() * 10u128.pow(decimals as u32) as f64).round() as u128;
+    decimal_base_units(base_units, decimals)
+}
+
+/// То же для суммы в базовых единицах — без f64 (`1500000`, 6 → `1.5`)
+pub fn decimal_base_units(base_units: u128, decimals: u8) -> String {
+    let scale = 10u128.pow(decimals as u32);
+    let fraction = format!("{:0width$}", base_units % scale, width = decimals as usize);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        (base_units / scale).to_string()
+    } else {
+        format!("{}.{}", base_units / scale, fraction)
+    }
+}
+
+/// Сумма с символом токена: `1,234.50 USDC`
+pub fn format_token_amount(amount: f64, decimals: u8, symbol: &str) -> String {
+    format!("{} {}", format_amount(amount, decimals), symbol)
+}
+
+fn group_thousands(digits: &str) -> String {
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, ch) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(ch);
+    }
+    grouped
+}
@@ -0,0 +1,345 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::escrow::EscrowService;
+use crate::payment::{PaymentService, PaymentStatus};
+use crate::validation::ValidationErrors;
+
+/// Ограничения полей спора
+pub const MAX_DESCRIPTION_LENGTH: usize = 2000;
+pub const MAX_EVIDENCE_ITEMS: usize = 10;
+pub const MAX_EVIDENCE_URL_LENGTH: usize = 2048;
+
+/// Причина спора
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputeReason {
+    /// Товар или услуга не получены
+    NotReceived,
+    /// Не соответствует описанию
+    NotAsDescribed,
+    /// Оплачено дважды
+    Duplicate,
+    /// Оплата без ведома владельца кошелька
+    Fraudulent,
+    Other,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputeStatus {
+    /// Ждет решения администратора
+    Open,
+    /// Решен в пользу плательщика: средства возвращены
+    Refunded,
+    /// Отклонен: платеж остается у мерчанта
+    Denied,
+}
+
+impl DisputeStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DisputeStatus::Open => "open",
+            DisputeStatus::Refunded => "refunded",
+            DisputeStatus::Denied => "denied",
+        }
+    }
+
+    /// Событие outbox при переходе в статус
+    pub fn event_type(&self) -> String {
+        match self {
+            DisputeStatus::Open => "dispute.opened".to_string(),
+            other => format!("dispute.{}", other.as_str()),
+        }
+    }
+}
+
+impl std::fmt::Display for DisputeStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Доказательство: описание и ссылка на файл или переписку
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DisputeEvidence {
+    pub description: String,
+    pub url: Option<String>,
+}
+
+/// Кто совершил действие по спору
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputeActor {
+    /// Анонимный запрос (страница оплаты)
+    Payer,
+    Merchant,
+    Admin,
+}
+
+/// Запись журнала спора
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DisputeEntry {
+    pub at: DateTime<Utc>,
+    pub actor: DisputeActor,
+    pub action: String,
+    pub note: Option<String>,
+}
+
+/// Спор по платежу: журнал для операторов маркетплейса
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Dispute {
+    pub id: String,
+    pub payment_id: String,
+    pub merchant_id: Option<String>,
+    pub status: DisputeStatus,
+    pub reason: DisputeReason,
+    pub description: String,
+    pub evidence: Vec<DisputeEvidence>,
+    /// Как связаться с открывшим спор
+    pub contact: Option<String>,
+    /// Статус платежа на момент открытия
+    pub payment_status: PaymentStatus,
+    pub history: Vec<DisputeEntry>,
+    pub created_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub resolution_note: Option<String>,
+    /// Транзакция возврата, если она известна (удержание или указана администратором)
+    pub refund_signature: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OpenDisputeRequest {
+    pub reason: DisputeReason,
+    pub description: String,
+    #[serde(default)]
+    pub evidence: Vec<DisputeEvidence>,
+    pub contact: Option<String>,
+}
+
+/// Решение администратора
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputeResolution {
+    /// Вернуть средства: удержание возвращается плательщику, завершенный платеж
+    /// помечается `refunded` (перевод оператор делает сам)
+    Refund,
+    Deny,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResolveDisputeRequest {
+    pub resolution: DisputeResolution,
+    pub note: Option<String>,
+    /// Транзакция ручного возврата завершенного платежа
+    pub refund_signature: Option<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DisputeError {
+    #[error("Dispute not found")]
+    NotFound,
+    #[error("Payment in status {0} cannot be disputed")]
+    NotDisputable(PaymentStatus),
+    #[error("Payment already has open dispute {0}")]
+    AlreadyOpen(String),
+    #[error("Dispute is already {0}")]
+    AlreadyResolved(DisputeStatus),
+}
+
+/// Отбор споров
+#[derive(Debug, Default, Deserialize)]
+pub struct DisputeFilter {
+    pub status: Option<DisputeStatus>,
+    pub merchant_id: Option<String>,
+    pub payment_id: Option<String>,
+}
+
+impl DisputeFilter {
+    pub fn matches(&self, dispute: &Dispute) -> bool {
+        self.status.is_none_or(|status| dispute.status == status)
+            && self.merchant_id.as_ref().is_none_or(|id| dispute.merchant_id.as_ref() == Some(id))
+            && self.payment_id.as_ref().is_none_or(|id| dispute.payment_id == *id)
+    }
+}
+
+/// Споры по оплаченным платежам: открытие плательщиком или мерчантом, решение
+/// администратором (возврат или отказ) и события `dispute.*`
+#[derive(Clone)]
+pub struct DisputeService {
+    payments: PaymentService,
+    escrow: EscrowService,
+}
+
+impl DisputeService {
+    pub fn new(payments: PaymentService, escrow: EscrowService) -> Self {
+        Self { payments, escrow }
+    }
+
+    /// Открыть спор по завершенному или удержанному платежу. `merchant_id` — спор
+    /// открывает мерчант (только по своему платежу). `None` — платеж не найден
+    pub async fn open(
+        &self,
+        payment_id: &str,
+        merchant_id: Option<&str>,
+        request: OpenDisputeRequest,
+    ) -> anyhow::Result<Option<Dispute>> {
+        let Some(payment) = self.payments.get_payment(payment_id).await? else {
+            return Ok(None);
+        };
+        if merchant_id.is_some_and(|id| payment.merchant_id.as_deref() != Some(id)) {
+            return Ok(None);
+        }
+        validate(&request)?;
+        if !matches!(payment.status, PaymentStatus::Completed | PaymentStatus::Held) {
+            return Err(DisputeError::NotDisputable(payment.status).into());
+        }
+
+        let now = Utc::now();
+        let actor = if merchant_id.is_some() { DisputeActor::Merchant } else { DisputeActor::Payer };
+        let description = request.description.trim().to_string();
+        let dispute = Dispute {
+            id: format!("dsp_{}", uuid::Uuid::new_v4().simple()),
+            payment_id: payment.id.clone(),
+            merchant_id: payment.merchant_id.clone(),
+            status: DisputeStatus::Open,
+            reason: request.reason,
+            description: description.clone(),
+            evidence: request.evidence,
+            contact: request.contact.filter(|contact| !contact.trim().is_empty()),
+            payment_status: payment.status,
+            history: vec![DisputeEntry { at: now, actor, action: "opened".to_string(), note: Some(description) }],
+            created_at: now,
+            resolved_at: None,
+            resolution_note: None,
+            refund_signature: None,
+        };
+        if let Some(open) = self.payments.storage().save_dispute_with_event(&dispute, &payment, None).await? {
+            return Err(DisputeError::AlreadyOpen(open).into());
+        }
+        tracing::info!(dispute_id = %dispute.id, payment_id, reason = ?dispute.reason, "Dispute opened");
+        Ok(Some(dispute))
+    }
+
+    pub async fn get(&self, dispute_id: &str) -> anyhow::Result<Dispute> {
+        Ok(self.payments.storage().get_dispute(dispute_id).await?.ok_or(DisputeError::NotFound)?)
+    }
+
+    /// Споры по фильтру, новые первыми
+    pub async fn list(&self, filter: &DisputeFilter) -> anyhow::Result<Vec<Dispute>> {
+        let mut disputes: Vec<Dispute> = self.payments.storage().list_disputes().await?
+            .into_iter()
+            .filter(|dispute| filter.matches(dispute))
+            .collect();
+        disputes.sort_by_key(|dispute| std::cmp::Reverse(dispute.created_at));
+        Ok(disputes)
+    }
+
+    /// Решить открытый спор. Возврат удержанного платежа переводит средства
+    /// плательщику; завершенный платеж становится `refunded`
+    pub async fn resolve(&self, dispute_id: &str, request: ResolveDisputeRequest) -> anyhow::Result<Dispute> {
+        let mut dispute = self.get(dispute_id).await?;
+        if dispute.status != DisputeStatus::Open {
+            return Err(DisputeError::AlreadyResolved(dispute.status).into());
+        }
+        if let Some(signature) = &request.refund_signature {
+            let mut errors = ValidationErrors::new();
+            errors.check_signature("refund_signature", signature);
+            if request.resolution != DisputeResolution::Refund {
+                errors.add("refund_signature", "not_allowed", "refund_signature is only accepted with refund");
+            }
+            errors.into_result()?;
+        }
+
+        let (status, refund_signature) = match request.resolution {
+            DisputeResolution::Deny => (DisputeStatus::Denied, None),
+            DisputeResolution::Refund => (DisputeStatus::Refunded, self.refund(&dispute, &request).await?),
+        };
+
+        let previous = dispute.status;
+        let now = Utc::now();
+        dispute.status = status;
+        dispute.resolved_at = Some(now);
+        dispute.resolution_note = request.note.clone();
+        dispute.refund_signature = refund_signature;
+        dispute.history.push(DisputeEntry {
+            at: now,
+            actor: DisputeActor::Admin,
+            action: status.as_str().to_string(),
+            note: request.note,
+        });
+        let payment = self.payments.get_payment(&dispute.payment_id).await?
+            .ok_or_else(|| anyhow::anyhow!("Payment {} not found", dispute.payment_id))?;
+        if self.payments.storage().save_dispute_with_event(&dispute, &payment, Some(previous)).await?.is_some() {
+            // Параллельное решение успело раньше
+            let current = self.get(dispute_id).await?;
+            return Err(DisputeError::AlreadyResolved(current.status).into());
+        }
+        tracing::info!(dispute_id, payment_id = %dispute.payment_id, %status, "Dispute resolved");
+        Ok(dispute)
+    }
+
+    /// Вернуть средства по спору; подпись транзакции возврата, если она есть
+    async fn refund(&self, dispute: &Dispute, request: &ResolveDisputeRequest) -> anyhow::Result<Option<String>> {
+        let payment = self.payments.get_payment(&dispute.payment_id).await?
+            .ok_or_else(|| anyhow::anyhow!("Payment {} not found", dispute.payment_id))?;
+        let reason = format!("Refunded by dispute {}", dispute.id);
+        match payment.status {
+            PaymentStatus::Held => {
+                let payment = self.escrow.release(&payment.id, None, None).await?
+                    .ok_or_else(|| anyhow::anyhow!("Payment {} not found", dispute.payment_id))?;
+                Ok(payment.hold.and_then(|hold| hold.signature))
+            }
+            PaymentStatus::Completed => {
+                self.payments.change_status(&payment.id, None, PaymentStatus::Refunded, Some(&reason)).await?;
+                Ok(request.refund_signature.clone())
+            }
+            // Уже возвращен вне спора
+            PaymentStatus::Refunded | PaymentStatus::Released => Ok(request.refund_signature.clone()),
+            other => Err(DisputeError::NotDisputable(other).into()),
+        }
+    }
+}
+
+fn validate(request: &OpenDisputeRequest) -> Result<(), ValidationErrors> {
+    let mut errors = ValidationErrors::new();
+    let description = request.description.trim();
+    if description.is_empty() {
+        errors.add("description", "required", "Describe the problem");
+    } else if description.len() > MAX_DESCRIPTION_LENGTH {
+        errors.add("description", "too_long", format!("Description must be at most {} bytes", MAX_DESCRIPTION_LENGTH));
+    }
+    if request.evidence.len() > MAX_EVIDENCE_ITEMS {
+        errors.add("evidence", "too_many", format!("At most {} evidence items", MAX_EVIDENCE_ITEMS));
+    }
+    for (i, evidence) in request.evidence.iter().enumerate() {
+        let field = format!("evidence[{}]", i);
+        if evidence.description.trim().is_empty() {
+            errors.add(&format!("{}.description", field), "required", "Evidence description is required");
+        } else if evidence.description.len() > MAX_DESCRIPTION_LENGTH {
+            errors.add(
+                &format!("{}.description", field),
+                "too_long",
+                format!("Evidence description must be at most {} bytes", MAX_DESCRIPTION_LENGTH),
+            );
+        }
+        let Some(url) = &evidence.url else { continue };
+        let field = format!("{}.url", field);
+        if url.len() > MAX_EVIDENCE_URL_LENGTH {
+            errors.add(&field, "too_long", format!("URL must be at most {} bytes", MAX_EVIDENCE_URL_LENGTH));
+            continue;
+        }
+        match url::Url::parse(url) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+            Ok(_) => errors.add(&field, "invalid_url", "Evidence URL must use http or https"),
+            Err(e) => errors.add(&field, "invalid_url", format!("Not a valid URL: {}", e)),
+        }
+    }
+    if request.contact.as_ref().is_some_and(|contact| contact.len() > 256) {
+        errors.add("contact", "too_long", "Contact must be at most 256 bytes");
+    }
+    errors.into_result()
+}
@@ -0,0 +1,237 @@
+use async_trait::async_trait;
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::sync::{mpsc, Arc, OnceLock};
+use std::time::Duration;
+
+use crate::config::ErrorReportingConfig;
+use crate::payment::Payment;
+
+/// Таймаут отправки одного события
+const REPORT_TIMEOUT_SECS: u64 = 5;
+/// Сколько паника ждет отправки события, прежде чем поток продолжит раскрутку
+const PANIC_FLUSH_TIMEOUT_SECS: u64 = 3;
+/// Поток, из которого события уходят получателю
+const REPORTER_THREAD: &str = "error-reporting";
+
+/// Источник ошибки
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    Panic,
+    /// Сбой RPC узла (исключение из пула, отказ всех узлов)
+    Rpc,
+    /// Ошибка или таймаут сборки транзакции платежа
+    Transaction,
+}
+
+impl ErrorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorKind::Panic => "panic",
+            ErrorKind::Rpc => "rpc",
+            ErrorKind::Transaction => "transaction",
+        }
+    }
+}
+
+/// Событие об ошибке: теги для поиска и группировки, extra — подробности
+#[derive(Debug, Clone)]
+pub struct ErrorEvent {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub tags: BTreeMap<String, String>,
+    pub extra: Map<String, Value>,
+}
+
+impl ErrorEvent {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self { kind, message: message.into(), tags: BTreeMap::new(), extra: Map::new() }
+    }
+
+    pub fn tag(mut self, key: &str, value: impl ToString) -> Self {
+        self.tags.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    pub fn extra(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.extra.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// Контекст платежа: id, мерчант и токен — теги, остальное — extra
+    pub fn payment(mut self, payment: &Payment) -> Self {
+        self = self.tag("payment_id", &payment.id).tag("token", &payment.token);
+        if let Some(merchant_id) = &payment.merchant_id {
+            self = self.tag("merchant_id", merchant_id);
+        }
+        self.extra("amount", payment.amount)
+            .extra("recipient", payment.recipient.clone())
+            .extra("status", payment.status.to_string())
+    }
+}
+
+/// Получатель событий об ошибках
+#[async_trait]
+pub trait ErrorReporter: Send + Sync {
+    async fn report(&self, event: &ErrorEvent) -> anyhow::Result<()>;
+}
+
+/// DSN проекта Sentry: `https://<public_key>@<host>/<project_id>`
+#[derive(Debug, Clone)]
+pub struct SentryDsn {
+    pub store_url: String,
+    pub public_key: String,
+}
+
+impl SentryDsn {
+    pub fn parse(dsn: &str) -> anyhow::Result<Self> {
+        let url = url::Url::parse(dsn)?;
+        if !matches!(url.scheme(), "http" | "https") {
+            anyhow::bail!("expected an http(s) URL");
+        }
+        if url.username().is_empty() {
+            anyhow::bail!("public key is missing");
+        }
+        let host = url.host_str().ok_or_else(|| anyhow::anyhow!("host is missing"))?;
+        let path = url.path().trim_end_matches('/');
+        let (prefix, project_id) = path.rsplit_once('/').unwrap_or(("", path));
+        if project_id.is_empty() {
+            anyhow::bail!("project id is missing");
+        }
+        let port = url.port().map(|port| format!(":{}", port)).unwrap_or_default();
+        Ok(Self {
+            store_url: format!("{}://{}{}{}/api/{}/store/", url.scheme(), host, port, prefix, project_id),
+            public_key: url.username().to_string(),
+        })
+    }
+}
+
+/// Отправка событий в Sentry через HTTP API (store endpoint)
+pub struct SentryReporter {
+    client: reqwest::Client,
+    dsn: SentryDsn,
+    environment: String,
+    release: String,
+}
+
+impl SentryReporter {
+    pub fn new(dsn: &str, environment: &str, release: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: reqwest::Client::builder().timeout(Duration::from_secs(REPORT_TIMEOUT_SECS)).build()?,
+            dsn: SentryDsn::parse(dsn)?,
+            environment: environment.to_string(),
+            release: release.to_string(),
+        })
+    }
+
+    /// Тело события Sentry
+    pub fn payload(&self, event: &ErrorEvent) -> Value {
+        let mut tags = event.tags.clone();
+        tags.insert("kind".to_string(), event.kind.as_str().to_string());
+        json!({
+            "event_id": uuid::Uuid::new_v4().simple().to_string(),
+            "timestamp": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            "platform": "other",
+            "level": if event.kind == ErrorKind::Panic { "fatal" } else { "error" },
+            "logger": event.kind.as_str(),
+            "release": self.release,
+            "environment": self.environment,
+            "message": {"formatted": event.message},
+            "exception": {"values": [{"type": event.kind.as_str(), "value": event.message}]},
+            "tags": tags,
+            "extra": event.extra,
+        })
+    }
+}
+
+#[async_trait]
+impl ErrorReporter for SentryReporter {
+    async fn report(&self, event: &ErrorEvent) -> anyhow::Result<()> {
+        let auth = format!(
+            "Sentry sentry_version=7, sentry_client=crypto-server/{}, sentry_key={}",
+            env!("CARGO_PKG_VERSION"), self.dsn.public_key
+        );
+        self.client.post(&self.dsn.store_url)
+            .header("X-Sentry-Auth", auth)
+            .json(&self.payload(event))
+            .send().await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Событие и канал подтверждения отправки (для паники)
+type Queued = (ErrorEvent, Option<mpsc::Sender<()>>);
+
+static QUEUE: OnceLock<tokio::sync::mpsc::UnboundedSender<Queued>> = OnceLock::new();
+
+/// Установить получателя событий и хук паники. События отправляются из отдельного потока
+/// со своим рантаймом: отправка из хука не зависит от рантайма упавшего потока
+pub fn install(reporter: Arc<dyn ErrorReporter>) -> anyhow::Result<()> {
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel::<Queued>();
+    QUEUE.set(sender).map_err(|_| anyhow::anyhow!("Error reporter is already installed"))?;
+
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    std::thread::Builder::new().name(REPORTER_THREAD.to_string()).spawn(move || {
+        runtime.block_on(async move {
+            while let Some((event, done)) = receiver.recv().await {
+                if let Err(e) = reporter.report(&event).await {
+                    tracing::warn!(kind = event.kind.as_str(), error = %e, "Failed to report error");
+                }
+                if let Some(done) = done {
+                    let _ = done.send(());
+                }
+            }
+        })
+    })?;
+
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        previous(info);
+        let thread = std::thread::current();
+        if thread.name() == Some(REPORTER_THREAD) {
+            return;
+        }
+        let message = info.payload().downcast_ref::<&str>().map(|message| message.to_string())
+            .or_else(|| info.payload().downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "panic".to_string());
+        let mut event = ErrorEvent::new(ErrorKind::Panic, message)
+            .tag("thread", thread.name().unwrap_or("unnamed"));
+        if let Some(location) = info.location() {
+            event = event.extra("location", location.to_string());
+        }
+        flush(event, Duration::from_secs(PANIC_FLUSH_TIMEOUT_SECS));
+    }));
+    Ok(())
+}
+
+/// Включить отправку ошибок в Sentry, если задан DSN
+pub fn init(config: &ErrorReportingConfig) -> anyhow::Result<()> {
+    let Some(dsn) = &config.sentry_dsn else {
+        return Ok(());
+    };
+    install(Arc::new(SentryReporter::new(dsn, &config.environment, &config.release)?))?;
+    tracing::info!(environment = %config.environment, release = %config.release, "Sentry error reporting enabled");
+    Ok(())
+}
+
+/// Отправить событие в фоне; без установленного получателя ничего не делает
+pub fn capture(event: ErrorEvent) {
+    if let Some(queue) = QUEUE.get() {
+        let _ = queue.send((event, None));
+    }
+}
+
+/// Отправить событие и дождаться отправки, но не дольше `timeout`
+pub fn flush(event: ErrorEvent, timeout: Duration) {
+    let Some(queue) = QUEUE.get() else {
+        return;
+    };
+    let (done, sent) = mpsc::channel();
+    if queue.send((event, Some(done))).is_ok() {
+        let _ = sent.recv_timeout(timeout);
+    }
+}
@@ -0,0 +1,218 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    message::{v0, VersionedMessage},
+    pubkey::Pubkey,
+    transaction::VersionedTransaction,
+};
+use std::str::FromStr;
+
+use crate::display::to_base_units;
+use crate::payment::{Payment, PaymentService, PaymentStatus, TransitionError};
+use crate::settlement::{mint, transfer};
+use crate::signer::ServerSigner;
+use crate::validation::ValidationErrors;
+
+/// Удержание платежа: сумма и чаевые поступают на hot wallet и ждут решения мерчанта
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentHold {
+    /// Счет удержания (hot wallet) — получатель переводов в транзакции платежа
+    pub escrow_account: String,
+    /// Срок удержания с момента оплаты, секунды
+    pub hold_secs: u64,
+    /// До какого момента можно списать средства; потом они возвращаются плательщику.
+    /// Проставляется при оплате
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Идущий перевод: второй capture или release до его завершения отклоняется
+    pub pending_action: Option<HoldAction>,
+    /// Транзакция списания или возврата
+    pub signature: Option<String>,
+}
+
+/// Чем завершается удержание
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HoldAction {
+    /// Перевести мерчанту
+    Capture,
+    /// Вернуть плательщику
+    Release,
+}
+
+impl HoldAction {
+    fn status(self) -> PaymentStatus {
+        match self {
+            HoldAction::Capture => PaymentStatus::Completed,
+            HoldAction::Release => PaymentStatus::Released,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EscrowError {
+    #[error("Hold payments require escrow.enabled and the server keypair")]
+    NotEnabled,
+    #[error("Payment was created without hold")]
+    NotHeld,
+    #[error("Payment is already being {}", match .0 { HoldAction::Capture => "captured", HoldAction::Release => "released" })]
+    InProgress(HoldAction),
+    #[error("Payment has open dispute {0}; funds stay on hold until it is resolved")]
+    Disputed(String),
+}
+
+/// Списание и возврат удержанных платежей переводом с hot wallet
+#[derive(Clone)]
+pub struct EscrowService {
+    payments: PaymentService,
+    signer: Option<ServerSigner>,
+}
+
+impl EscrowService {
+    pub fn new(payments: PaymentService, signer: Option<ServerSigner>) -> Self {
+        Self { payments, signer }
+    }
+
+    /// Перевести удержанные средства мерчанту: платеж становится `completed`.
+    /// `None` — платеж не найден или принадлежит другому мерчанту
+    pub async fn capture(&self, payment_id: &str, merchant_id: Option<&str>) -> anyhow::Result<Option<Payment>> {
+        self.settle(payment_id, merchant_id, HoldAction::Capture, None, "Captured").await
+    }
+
+    /// Вернуть удержанные средства плательщику (или на `refund_address`): платеж
+    /// становится `released`
+    pub async fn release(
+        &self,
+        payment_id: &str,
+        merchant_id: Option<&str>,
+        refund_address: Option<&str>,
+    ) -> anyhow::Result<Option<Payment>> {
+        self.settle(payment_id, merchant_id, HoldAction::Release, refund_address, "Released").await
+    }
+
+    /// Вернуть средства по удержаниям с истекшим сроком (задача `escrow`)
+    pub async fn release_expired(&self) -> anyhow::Result<usize> {
+        let now = Utc::now();
+        let due: Vec<String> = self.payments.storage().get_all_payments().await?
+            .into_values()
+            .filter(|payment| payment.status == PaymentStatus::Held)
+            .filter(|payment| payment.hold.as_ref().is_some_and(|hold| {
+                hold.pending_action.is_none() && hold.expires_at.is_some_and(|at| at <= now)
+            }))
+            .map(|payment| payment.id)
+            .collect();
+
+        let mut released = 0;
+        for payment_id in due {
+            // Спорный платеж ждет решения администратора
+            if self.payments.storage().open_dispute(&payment_id).await?.is_some() {
+                continue;
+            }
+            match self.settle(&payment_id, None, HoldAction::Release, None, "Hold period elapsed").await {
+                Ok(_) => released += 1,
+                // Без адреса плательщика (или при сбое RPC) — ручной возврат или следующий проход
+                Err(e) => tracing::warn!(payment_id = %payment_id, error = %e, "Failed to release expired hold"),
+            }
+        }
+        if released > 0 {
+            tracing::info!(released, "Released expired holds");
+        }
+        Ok(released)
+    }
+
+    async fn settle(
+        &self,
+        payment_id: &str,
+        merchant_id: Option<&str>,
+        action: HoldAction,
+        refund_address: Option<&str>,
+        reason: &str,
+    ) -> anyhow::Result<Option<Payment>> {
+        let signer = self.signer.as_ref().ok_or(EscrowError::NotEnabled)?;
+        let Some(payment) = self.payments.get_payment(payment_id).await? else {
+            return Ok(None);
+        };
+        if merchant_id.is_some_and(|id| payment.merchant_id.as_deref() != Some(id)) {
+            return Ok(None);
+        }
+        if action == HoldAction::Capture {
+            if let Some(dispute) = self.payments.storage().open_dispute(payment_id).await? {
+                return Err(EscrowError::Disputed(dispute.id).into());
+            }
+        }
+        let destination = match action {
+            HoldAction::Capture => payment.recipient.clone(),
+            HoldAction::Release => {
+                let mut errors = ValidationErrors::new();
+                match refund_address.map(str::to_string).or(payment.payer.clone()) {
+                    Some(address) => {
+                        errors.check_pubkey("refund_address", &address);
+                        errors.into_result()?;
+                        address
+                    }
+                    None => {
+                        errors.add("refund_address", "required", "Payer is unknown; specify refund_address");
+                        return Err(errors.into());
+                    }
+                }
+            }
+        };
+
+        // Заявка на перевод сохраняется до отправки: параллельный запрос ее увидит
+        let claimed = self.payments.update_payment(payment_id, |payment| {
+            let status = payment.status;
+            let hold = payment.hold.as_mut().ok_or(EscrowError::NotHeld)?;
+            if status != PaymentStatus::Held {
+                return Err(TransitionError::Illegal { from: status, to: action.status() }.into());
+            }
+            if let Some(pending) = hold.pending_action {
+                return Err(EscrowError::InProgress(pending).into());
+            }
+            hold.pending_action = Some(action);
+            Ok(true)
+        }).await?.ok_or_else(|| anyhow::anyhow!("Payment not found"))?;
+
+        let signature = match self.send(&claimed, signer, &destination).await {
+            Ok(signature) => signature,
+            Err(e) => {
+                tracing::error!(payment_id, ?action, error = %e, "Hold transfer failed");
+                self.payments.update_payment(payment_id, |payment| {
+                    if let Some(hold) = payment.hold.as_mut() {
+                        hold.pending_action = None;
+                    }
+                    Ok(true)
+                }).await?;
+                return Err(e);
+            }
+        };
+
+        let reason = format!("{}: transaction {} to {}", reason, signature, destination);
+        let payment = self.payments.update_payment(payment_id, |payment| {
+            payment.transition(action.status(), Some(&reason))?;
+            if let Some(hold) = payment.hold.as_mut() {
+                hold.pending_action = None;
+                hold.signature = Some(signature.clone());
+            }
+            Ok(true)
+        }).await?;
+        tracing::info!(payment_id, ?action, %signature, %destination, "Hold settled");
+        Ok(payment)
+    }
+
+    /// Перевести сумму и чаевые платежа с hot wallet на `destination`
+    async fn send(&self, payment: &Payment, signer: &ServerSigner, destination: &str) -> anyhow::Result<String> {
+        let config = self.payments.config();
+        let token = config.get_token_config(&payment.token)
+            .ok_or_else(|| anyhow::anyhow!("Token {} not supported", payment.token))?;
+        let hot_wallet = signer.pubkey();
+        let destination = Pubkey::from_str(destination)?;
+        let amount = payment.amount_base_units
+            + payment.tip_amount.map_or(0, |tip| to_base_units(tip, token.decimals));
+
+        let instructions = transfer(&token, &mint(&token)?, &hot_wallet, &hot_wallet, false, &destination, amount)?;
+        let rpc = self.payments.rpc();
+        let blockhash = rpc.get_latest_blockhash().await?;
+        let message = v0::Message::try_compile(&hot_wallet, &instructions, &[], blockhash)?;
+        let transaction = VersionedTransaction::try_new(VersionedMessage::V0(message), &[signer.keypair()])?;
+        Ok(rpc.send_transaction(&transaction).await?.to_string())
+    }
+}
@@ -0,0 +1,263 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+use crate::config::NOTIFICATION_EVENTS;
+use crate::disputes::{Dispute, DisputeEvidence, DisputeReason};
+use crate::payment::Payment;
+use crate::validation::ValidationErrors;
+
+/// Заголовок с версией схемы событий: в запросе регистрации webhook — выбор версии,
+/// в доставке — версия тела
+pub const API_VERSION_HEADER: &str = "X-CryptoNow-Api-Version";
+
+/// Заголовок доставки с ключом идемпотентности события: один для всех доставок, повторов
+/// и повторных отправок события, по нему мерчант отбрасывает дубликаты
+pub const IDEMPOTENCY_KEY_HEADER: &str = "X-CryptoNow-Idempotency-Key";
+
+/// Поддерживаемые версии схемы событий
+pub const API_VERSIONS: &[u32] = &[1];
+
+/// Версия для endpoints, зарегистрированных без заголовка
+pub const CURRENT_API_VERSION: u32 = 1;
+
+/// Версия из заголовка `X-CryptoNow-Api-Version`; без заголовка — текущая
+pub fn negotiate(header: Option<&str>) -> Result<u32, ValidationErrors> {
+    let Some(header) = header else {
+        return Ok(CURRENT_API_VERSION);
+    };
+    match header.trim().trim_start_matches('v').parse::<u32>() {
+        Ok(version) if API_VERSIONS.contains(&version) => Ok(version),
+        _ => {
+            let mut errors = ValidationErrors::new();
+            let supported: Vec<String> = API_VERSIONS.iter().map(u32::to_string).collect();
+            errors.add(
+                "api_version",
+                "unsupported",
+                format!("Unsupported API version '{}', supported: {}", header, supported.join(", ")),
+            );
+            Err(errors)
+        }
+    }
+}
+
+/// Платеж в событиях `payment.*`, схема v1. В пределах версии поля не удаляются
+/// и не меняют тип — изменения `Payment` не доходят до мерчанта без новой версии
+#[derive(Debug, Clone, Serialize)]
+pub struct PaymentEventV1 {
+    pub id: String,
+    pub merchant_id: Option<String>,
+    pub status: String,
+    pub sandbox: bool,
+    pub recipient: String,
+    pub amount: f64,
+    pub token: String,
+    pub tip_amount: Option<f64>,
+    pub fee_amount: f64,
+    pub fee_token: String,
+    pub total_display: String,
+    pub label: String,
+    pub memo: Option<String>,
+    pub metadata: BTreeMap<String, String>,
+    pub reference: String,
+    pub signature: Option<String>,
+    pub explorer_url: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub status_changed_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub verified_at: Option<DateTime<Utc>>,
+}
+
+impl From<&Payment> for PaymentEventV1 {
+    fn from(payment: &Payment) -> Self {
+        Self {
+            id: payment.id.clone(),
+            merchant_id: payment.merchant_id.clone(),
+            status: payment.status.to_string(),
+            sandbox: payment.sandbox,
+            recipient: payment.recipient.clone(),
+            amount: payment.amount,
+            token: payment.token.clone(),
+            tip_amount: payment.tip_amount,
+            fee_amount: payment.fee_amount,
+            fee_token: payment.fee_token.clone(),
+            total_display: payment.total_display.clone(),
+            label: payment.label.clone(),
+            memo: payment.memo.clone(),
+            metadata: payment.metadata.clone(),
+            reference: payment.reference.clone(),
+            signature: payment.signature.clone(),
+            explorer_url: payment.explorer_url.clone(),
+            created_at: payment.created_at,
+            status_changed_at: payment.status_changed_at,
+            expires_at: payment.expires_at,
+            verified_at: payment.verified_at,
+        }
+    }
+}
+
+/// Ключ идемпотентности события `event_type` о платеже или споре `subject_id`. Каждый
+/// статус проходится не больше одного раза, поэтому повторная запись того же перехода
+/// (гонка воркеров, повтор после сбоя) дает тот же ключ
+pub fn idempotency_key(event_type: &str, subject_id: &str) -> String {
+    let digest = Sha256::digest(format!("{}:{}", event_type, subject_id).as_bytes());
+    format!("idk_{}", &hex::encode(digest)[..32])
+}
+
+/// Данные события о платеже в текущей версии схемы (так событие хранится в outbox)
+pub fn payment_payload(payment: &Payment) -> anyhow::Result<Value> {
+    Ok(serde_json::to_value(PaymentEventV1::from(payment))?)
+}
+
+/// Спор в событиях `dispute.*`, схема v1. Поля платежа `token`, `memo` и `metadata`
+/// повторяются, чтобы фильтры webhooks работали и для споров
+#[derive(Debug, Clone, Serialize)]
+pub struct DisputeEventV1 {
+    pub id: String,
+    pub payment_id: String,
+    pub merchant_id: Option<String>,
+    pub status: String,
+    pub reason: DisputeReason,
+    pub description: String,
+    pub evidence: Vec<DisputeEvidence>,
+    pub payment_status: String,
+    pub amount: f64,
+    pub token: String,
+    pub total_display: String,
+    pub memo: Option<String>,
+    pub metadata: BTreeMap<String, String>,
+    pub created_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub resolution_note: Option<String>,
+    pub refund_signature: Option<String>,
+}
+
+/// Данные события о споре в текущей версии схемы
+pub fn dispute_payload(dispute: &Dispute, payment: &Payment) -> anyhow::Result<Value> {
+    Ok(serde_json::to_value(DisputeEventV1 {
+        id: dispute.id.clone(),
+        payment_id: dispute.payment_id.clone(),
+        merchant_id: dispute.merchant_id.clone(),
+        status: dispute.status.to_string(),
+        reason: dispute.reason,
+        description: dispute.description.clone(),
+        evidence: dispute.evidence.clone(),
+        payment_status: payment.status.to_string(),
+        amount: payment.amount,
+        token: payment.token.clone(),
+        total_display: payment.total_display.clone(),
+        memo: payment.memo.clone(),
+        metadata: payment.metadata.clone(),
+        created_at: dispute.created_at,
+        resolved_at: dispute.resolved_at,
+        resolution_note: dispute.resolution_note.clone(),
+        refund_signature: dispute.refund_signature.clone(),
+    })?)
+}
+
+/// Данные события из outbox в версии endpoint
+pub fn render(version: u32, payload: &Value) -> anyhow::Result<Value> {
+    match version {
+        1 => Ok(payload.clone()),
+        other => anyhow::bail!("Unsupported event API version {}", other),
+    }
+}
+
+/// JSON Schema тела доставки webhook в версии `version`
+pub fn schema(version: u32) -> Option<Value> {
+    if version != 1 {
+        return None;
+    }
+    let string = json!({"type": "string"});
+    let nullable_string = json!({"type": ["string", "null"]});
+    let time = json!({"type": "string", "format": "date-time"});
+    let payment = json!({
+        "type": "object",
+        "additionalProperties": false,
+        "required": [
+            "id", "merchant_id", "status", "sandbox", "recipient", "amount", "token", "tip_amount",
+            "fee_amount", "fee_token", "total_display", "label", "memo", "metadata", "reference",
+            "signature", "explorer_url", "created_at", "status_changed_at", "expires_at", "verified_at",
+        ],
+        "properties": {
+            "id": string,
+            "merchant_id": nullable_string,
+            "status": {
+                "enum": ["pending", "processing", "completed", "failed", "expired", "cancelled", "refunded", "held", "released"],
+            },
+            "sandbox": {"type": "boolean", "description": "Test payment of a sandbox API key"},
+            "recipient": {"type": "string", "description": "Merchant wallet (base58)"},
+            "amount": {"type": "number"},
+            "token": {"type": "string", "description": "Token symbol, e.g. USDC"},
+            "tip_amount": {"type": ["number", "null"]},
+            "fee_amount": {"type": "number"},
+            "fee_token": string,
+            "total_display": {"type": "string", "description": "Total to pay, formatted for display"},
+            "label": string,
+            "memo": nullable_string,
+            "metadata": {"type": "object", "additionalProperties": {"type": "string"}},
+            "reference": {"type": "string", "description": "Solana Pay reference key (base58)"},
+            "signature": nullable_string,
+            "explorer_url": nullable_string,
+            "created_at": time,
+            "status_changed_at": time,
+            "expires_at": time,
+            "verified_at": {"type": ["string", "null"], "format": "date-time"},
+        },
+    });
+    let dispute = json!({
+        "type": "object",
+        "additionalProperties": false,
+        "required": [
+            "id", "payment_id", "merchant_id", "status", "reason", "description", "evidence",
+            "payment_status", "amount", "token", "total_display", "memo", "metadata", "created_at",
+            "resolved_at", "resolution_note", "refund_signature",
+        ],
+        "properties": {
+            "id": {"type": "string", "description": "Dispute ID (dsp_...)"},
+            "payment_id": string,
+            "merchant_id": nullable_string,
+            "status": {"enum": ["open", "refunded", "denied"]},
+            "reason": {"enum": ["not_received", "not_as_described", "duplicate", "fraudulent", "other"]},
+            "description": string,
+            "evidence": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "required": ["description", "url"],
+                    "properties": {"description": string, "url": nullable_string},
+                },
+            },
+            "payment_status": {"type": "string", "description": "Payment status when the event was written"},
+            "amount": {"type": "number"},
+            "token": string,
+            "total_display": string,
+            "memo": nullable_string,
+            "metadata": {"type": "object", "additionalProperties": {"type": "string"}},
+            "created_at": time,
+            "resolved_at": {"type": ["string", "null"], "format": "date-time"},
+            "resolution_note": nullable_string,
+            "refund_signature": nullable_string,
+        },
+    });
+    Some(json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": format!("CryptoNow webhook event v{}", version),
+        "type": "object",
+        "additionalProperties": false,
+        "required": ["id", "type", "api_version", "created_at", "data"],
+        "properties": {
+            "id": string,
+            "type": {"enum": NOTIFICATION_EVENTS},
+            "api_version": {"const": version},
+            "created_at": time,
+            "data": {
+                "description": "Payment for payment.* events, dispute for dispute.* events",
+                "oneOf": [payment, dispute],
+            },
+        },
+    }))
+}
@@ -0,0 +1,84 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Кластер Solana, в котором работает сервер
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Cluster {
+    MainnetBeta,
+    Devnet,
+    Testnet,
+}
+
+impl FromStr for Cluster {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mainnet-beta" | "mainnet" => Ok(Cluster::MainnetBeta),
+            "devnet" => Ok(Cluster::Devnet),
+            "testnet" => Ok(Cluster::Testnet),
+            other => Err(format!("expected mainnet-beta, devnet or testnet, got '{}'", other)),
+        }
+    }
+}
+
+/// Какой обозреватель блоков использовать для ссылок
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExplorerKind {
+    Solscan,
+    SolanaExplorer,
+}
+
+impl FromStr for ExplorerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "solscan" => Ok(ExplorerKind::Solscan),
+            "solana_explorer" => Ok(ExplorerKind::SolanaExplorer),
+            other => Err(format!("expected solscan or solana_explorer, got '{}'", other)),
+        }
+    }
+}
+
+/// Ссылки на обозреватель блоков с учетом кластера
+#[derive(Debug, Clone, Copy)]
+pub struct Explorer {
+    kind: ExplorerKind,
+    cluster: Cluster,
+}
+
+impl Explorer {
+    pub fn new(kind: ExplorerKind, cluster: Cluster) -> Self {
+        Self { kind, cluster }
+    }
+
+    /// Ссылка на транзакцию
+    pub fn transaction_url(&self, signature: &str) -> String {
+        self.url("tx", signature)
+    }
+
+    /// Ссылка на адрес (кошелек, mint, token account)
+    pub fn address_url(&self, address: &str) -> String {
+        let path = match self.kind {
+            ExplorerKind::Solscan => "account",
+            ExplorerKind::SolanaExplorer => "address",
+        };
+        self.url(path, address)
+    }
+
+    fn url(&self, path: &str, id: &str) -> String {
+        let base = match self.kind {
+            ExplorerKind::Solscan => "https://solscan.io",
+            ExplorerKind::SolanaExplorer => "https://explorer.solana.com",
+        };
+
+        match self.cluster {
+            Cluster::MainnetBeta => format!("{}/{}/{}", base, path, id),
+            Cluster::Devnet => format!("{}/{}/{}?cluster=devnet", base, path, id),
+            Cluster::Testnet => format!("{}/{}/{}?cluster=testnet", base, path, id),
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use bytes::Bytes;
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::Stream;
+use rust_xlsxwriter::{Format, Workbook};
+
+use crate::payment::Payment;
+use crate::validation::ValidationErrors;
+
+/// Строк CSV в одном чанке потока
+const CSV_CHUNK_ROWS: usize = 500;
+
+/// Колонки выгрузки (одинаковые для CSV и xlsx)
+const COLUMNS: &[&str] = &[
+    "id",
+    "created_at",
+    "status",
+    "status_changed_at",
+    "merchant_id",
+    "recipient",
+    "amount",
+    "token",
+    "tip_amount",
+    "fee_amount",
+    "fee_token",
+    "fee_usd",
+    "token_usd_rate",
+    "fee_token_usd_rate",
+    "amount_usd_at_completion",
+    "tip_usd_at_completion",
+    "fee_usd_at_completion",
+    "memo",
+    "reference",
+    "signature",
+    "verified_at",
+    "block_time",
+    "explorer_url",
+    "archived_at",
+    "metadata",
+];
+
+/// Период выгрузки по времени создания платежа; границы включительно
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExportRange {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl ExportRange {
+    /// Границы — RFC 3339 или дата `YYYY-MM-DD` (для `to` — до конца дня, UTC)
+    pub fn parse(from: Option<&str>, to: Option<&str>) -> Result<Self, ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        let range = Self {
+            from: from.and_then(|value| parse_bound(&mut errors, "from", value, false)),
+            to: to.and_then(|value| parse_bound(&mut errors, "to", value, true)),
+        };
+        if let (Some(from), Some(to)) = (range.from, range.to) {
+            if from > to {
+                errors.add("to", "out_of_range", "to must not be earlier than from");
+            }
+        }
+        errors.into_result().map(|_| range)
+    }
+
+    pub fn contains(&self, payment: &Payment) -> bool {
+        self.from.is_none_or(|from| payment.created_at >= from)
+            && self.to.is_none_or(|to| payment.created_at <= to)
+    }
+}
+
+fn parse_bound(errors: &mut ValidationErrors, field: &str, value: &str, end_of_day: bool) -> Option<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Some(time.with_timezone(&Utc));
+    }
+    let day = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok().and_then(|date| {
+        if end_of_day { date.and_hms_milli_opt(23, 59, 59, 999) } else { date.and_hms_opt(0, 0, 0) }
+    });
+    if day.is_none() {
+        errors.add(field, "invalid_date", format!("{} must be an RFC 3339 timestamp or YYYY-MM-DD date", field));
+    }
+    day.map(|time| time.and_utc())
+}
+
+fn time(value: Option<DateTime<Utc>>) -> String {
+    value.map(|t| t.to_rfc3339()).unwrap_or_default()
+}
+
+fn number(value: Option<f64>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+/// Данные мерчанта одной ячейкой — JSON объект (пусто, если их нет)
+fn metadata(payment: &Payment) -> String {
+    if payment.metadata.is_empty() {
+        return String::new();
+    }
+    serde_json::to_string(&payment.metadata).unwrap_or_default()
+}
+
+fn row(payment: &Payment) -> [String; COLUMNS.len()] {
+    let valuation = payment.usd_valuation.as_ref();
+    [
+        payment.id.clone(),
+        payment.created_at.to_rfc3339(),
+        payment.status.to_string(),
+        payment.status_changed_at.to_rfc3339(),
+        payment.merchant_id.clone().unwrap_or_default(),
+        payment.recipient.clone(),
+        payment.amount.to_string(),
+        payment.token.clone(),
+        number(payment.tip_amount),
+        payment.fee_amount.to_string(),
+        payment.fee_token.clone(),
+        number(payment.fee_usd),
+        number(valuation.map(|v| v.token_usd_rate)),
+        number(valuation.map(|v| v.fee_token_usd_rate)),
+        number(valuation.map(|v| v.amount_usd)),
+        number(valuation.and_then(|v| v.tip_usd)),
+        number(valuation.map(|v| v.fee_usd)),
+        payment.memo.clone().unwrap_or_default(),
+        payment.reference.clone(),
+        payment.signature.clone().unwrap_or_default(),
+        time(payment.verified_at),
+        time(payment.block_time),
+        payment.explorer_url.clone().unwrap_or_default(),
+        time(payment.archived_at),
+        metadata(payment),
+    ]
+}
+
+fn csv_chunk(header: bool, payments: &[Payment]) -> anyhow::Result<Bytes> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    if header {
+        writer.write_record(COLUMNS)?;
+    }
+    for payment in payments {
+        writer.write_record(row(payment))?;
+    }
+    Ok(Bytes::from(writer.into_inner()?))
+}
+
+/// CSV (RFC 4180, UTF-8) для импорта в бухгалтерию: строки формируются по мере отправки
+pub fn csv_stream(payments: Vec<Payment>) -> impl Stream<Item = anyhow::Result<Bytes>> {
+    async_stream::try_stream! {
+        yield csv_chunk(true, &[])?;
+        for chunk in payments.chunks(CSV_CHUNK_ROWS) {
+            yield csv_chunk(false, chunk)?;
+        }
+    }
+}
+
+/// Книга Excel с теми же колонками; суммы записываются числами
+pub fn to_xlsx(payments: &[Payment]) -> anyhow::Result<Vec<u8>> {
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet();
+    sheet.set_name("Payments")?;
+    sheet.write_row_with_format(0, 0, COLUMNS.iter().copied(), &Format::new().set_bold())?;
+    sheet.set_freeze_panes(1, 0)?;
+
+    for (index, payment) in payments.iter().enumerate() {
+        let line = index as u32 + 1;
+        for (column, value) in row(payment).into_iter().enumerate() {
+            let column = column as u16;
+            match (COLUMNS[column as usize], value.parse::<f64>()) {
+                (name, Ok(number)) if name.contains("amount") || name.contains("usd") => {
+                    sheet.write_number(line, column, number)?
+                }
+                _ => sheet.write_string(line, column, value)?,
+            };
+        }
+    }
+    sheet.autofit();
+
+    Ok(workbook.save_to_buffer()?)
+}
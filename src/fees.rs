@@ -0,0 +1,161 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::config::{Config, FeeWalletConfig, FeeWalletStrategy, MerchantConfig, SolanaConfig};
+use crate::price::PriceOracle;
+use crate::validation::ValidationErrors;
+
+/// Выбор кошелька для комиссии из нескольких настроенных
+#[derive(Debug, Clone)]
+pub struct FeeWalletSelector {
+    wallets: Vec<FeeWalletConfig>,
+    strategy: FeeWalletStrategy,
+    counter: Arc<AtomicUsize>,
+}
+
+impl FeeWalletSelector {
+    pub fn new(config: &SolanaConfig) -> Self {
+        Self {
+            wallets: config.fee_wallets.clone(),
+            strategy: config.fee_wallet_strategy,
+            counter: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Выбрать кошелек для очередного платежа
+    pub fn next(&self) -> &str {
+        let tick = self.counter.fetch_add(1, Ordering::Relaxed);
+
+        match self.strategy {
+            FeeWalletStrategy::RoundRobin => &self.wallets[tick % self.wallets.len()].address,
+            FeeWalletStrategy::Weighted => {
+                // Взвешенный round-robin: кошелек с весом 3 выбирается в 3 раза чаще
+                let total: usize = self.wallets.iter().map(|w| w.weight as usize).sum();
+                let mut slot = tick % total;
+                for wallet in &self.wallets {
+                    if slot < wallet.weight as usize {
+                        return &wallet.address;
+                    }
+                    slot -= wallet.weight as usize;
+                }
+                &self.wallets[0].address
+            }
+        }
+    }
+}
+
+/// Комиссия, рассчитанная для конкретного платежа
+#[derive(Debug, Clone)]
+pub struct CalculatedFee {
+    /// Сумма в `token`
+    pub amount: f64,
+    pub token: String,
+    /// Сумма в USD, если комиссия привязана к доллару
+    pub usd_value: Option<f64>,
+    /// Курс токена комиссии к USD, зафиксированный при создании платежа
+    pub usd_rate: Option<f64>,
+}
+
+/// Расчет комиссии: фиксированная сумма или эквивалент USD по курсу оракула,
+/// с учетом переопределений мерчанта
+#[derive(Debug, Clone)]
+pub struct FeeCalculator {
+    oracle: PriceOracle,
+    config: Config,
+}
+
+impl FeeCalculator {
+    pub fn new(config: Config, oracle: PriceOracle) -> Self {
+        Self { oracle, config }
+    }
+
+    pub async fn calculate(&self, merchant: Option<&MerchantConfig>) -> anyhow::Result<CalculatedFee> {
+        let solana = &self.config.solana;
+
+        let token = merchant
+            .and_then(|m| m.fee_token.clone())
+            .unwrap_or_else(|| solana.fee_token.clone());
+
+        // Переопределение мерчанта имеет приоритет над глобальной политикой целиком
+        let (fee_amount, fee_usd) = match merchant {
+            Some(MerchantConfig { fee_usd: Some(usd), .. }) => (solana.fee_amount, Some(*usd)),
+            Some(MerchantConfig { fee_amount: Some(amount), .. }) => (*amount, None),
+            _ => (solana.fee_amount, solana.fee_usd),
+        };
+
+        let Some(fee_usd) = fee_usd else {
+            return Ok(CalculatedFee {
+                amount: fee_amount,
+                token,
+                usd_value: None,
+                usd_rate: None,
+            });
+        };
+
+        let token_config = self.config.get_token_config(&token)
+            .ok_or_else(|| anyhow::anyhow!("Fee token {} not supported", token))?;
+        let rate = self.oracle.usd_price(&token).await
+            .map_err(|e| anyhow::anyhow!("Failed to get {} price for USD fee: {}", token, e))?;
+
+        // Округляем вверх до минимальной единицы токена, чтобы не недобрать комиссию
+        let scale = 10_f64.powi(token_config.decimals as i32);
+        let amount = (fee_usd / rate * scale).ceil() / scale;
+
+        tracing::info!(fee_usd, amount, token, rate, "USD fee converted");
+
+        Ok(CalculatedFee {
+            amount,
+            token,
+            usd_value: Some(fee_usd),
+            usd_rate: Some(rate),
+        })
+    }
+
+    /// Комиссия, заданная в запросе на создание платежа, в границах `fee_override`
+    /// политики мерчанта. `None` — запрос комиссию не задает
+    pub fn override_fee(
+        &self,
+        merchant: Option<&MerchantConfig>,
+        amount: Option<f64>,
+        token: Option<&str>,
+    ) -> Result<Option<CalculatedFee>, ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        let Some(amount) = amount else {
+            if token.is_some() {
+                errors.add("fee_amount", "required", "fee_amount is required when fee_token is set");
+            }
+            return errors.into_result().map(|_| None);
+        };
+        let Some(policy) = merchant.and_then(|m| m.fee_override.as_ref()) else {
+            errors.add("fee_amount", "not_allowed", "This API key is not allowed to override the fee");
+            return Err(errors);
+        };
+
+        let default_token = merchant
+            .and_then(|m| m.fee_token.clone())
+            .unwrap_or_else(|| self.config.solana.fee_token.clone());
+        let token = token.map(str::to_string).unwrap_or(default_token.clone());
+        if token != default_token && !policy.fee_tokens.contains(&token) {
+            errors.add("fee_token", "not_allowed", format!("Fee token {} is not allowed for this API key", token));
+        } else if !self.config.is_token_supported(&token) {
+            errors.add("fee_token", "unsupported", format!("Fee token {} not supported", token));
+        }
+
+        if !amount.is_finite() || amount < policy.min_fee_amount {
+            errors.add(
+                "fee_amount",
+                "too_small",
+                format!("fee_amount must be at least {}, got: {}", policy.min_fee_amount, amount),
+            );
+        } else if amount > policy.max_fee_amount() {
+            errors.add(
+                "fee_amount",
+                "too_large",
+                format!("fee_amount must be at most {}, got: {}", policy.max_fee_amount(), amount),
+            );
+        }
+
+        errors.into_result()?;
+        Ok(Some(CalculatedFee { amount, token, usd_value: None, usd_rate: None }))
+    }
+}
@@ -0,0 +1,407 @@
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, Enum, Guard, InputObject, Object, Result, Schema, SimpleObject,
+};
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+
+use crate::payment::{Payment, PaymentService, PaymentStatus};
+use crate::replica::ReadConsistency;
+
+/// Схема GraphQL для дашбордов мерчантов (только чтение)
+pub type DashboardSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+/// Максимальный размер страницы
+const MAX_PAGE_SIZE: usize = 100;
+
+/// Кто выполняет запрос (определяется по API ключу до выполнения запроса)
+#[derive(Debug, Clone)]
+pub enum Viewer {
+    Merchant(String),
+    Admin,
+}
+
+impl Viewer {
+    /// Видит ли viewer платеж
+    pub fn can_see(&self, payment: &Payment) -> bool {
+        match self {
+            Viewer::Admin => true,
+            Viewer::Merchant(id) => payment.merchant_id.as_deref() == Some(id.as_str()),
+        }
+    }
+
+    /// Учитывается ли платеж в сводках и выгрузках. Без явного `sandbox` администратор
+    /// видит только боевые платежи; у мерчанта платежи одного вида (sandbox задан ключом)
+    pub fn in_reports(&self, payment: &Payment, sandbox: Option<bool>) -> bool {
+        match (sandbox, self) {
+            (Some(sandbox), _) => payment.sandbox == sandbox,
+            (None, Viewer::Admin) => !payment.sandbox,
+            (None, Viewer::Merchant(_)) => true,
+        }
+    }
+}
+
+/// Поля и запросы только для администратора
+struct AdminGuard;
+
+impl Guard for AdminGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        match ctx.data::<Viewer>()? {
+            Viewer::Admin => Ok(()),
+            Viewer::Merchant(_) => Err("Admin key required".into()),
+        }
+    }
+}
+
+pub fn build_schema(payments: PaymentService) -> DashboardSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(payments)
+        .limit_depth(8)
+        .finish()
+}
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+#[graphql(name = "PaymentStatus")]
+pub enum GqlPaymentStatus {
+    Pending,
+    Processing,
+    Completed,
+    Failed,
+    Expired,
+    Cancelled,
+    Refunded,
+    Held,
+    Released,
+}
+
+impl From<&PaymentStatus> for GqlPaymentStatus {
+    fn from(status: &PaymentStatus) -> Self {
+        match status {
+            PaymentStatus::Pending => GqlPaymentStatus::Pending,
+            PaymentStatus::Processing => GqlPaymentStatus::Processing,
+            PaymentStatus::Completed => GqlPaymentStatus::Completed,
+            PaymentStatus::Failed => GqlPaymentStatus::Failed,
+            PaymentStatus::Expired => GqlPaymentStatus::Expired,
+            PaymentStatus::Cancelled => GqlPaymentStatus::Cancelled,
+            PaymentStatus::Refunded => GqlPaymentStatus::Refunded,
+            PaymentStatus::Held => GqlPaymentStatus::Held,
+            PaymentStatus::Released => GqlPaymentStatus::Released,
+        }
+    }
+}
+
+#[derive(InputObject, Default)]
+pub struct PaymentFilter {
+    pub status: Option<GqlPaymentStatus>,
+    pub token: Option<String>,
+    /// Только для администратора: платежи конкретного мерчанта
+    pub merchant_id: Option<String>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    pub min_amount: Option<f64>,
+    pub max_amount: Option<f64>,
+    /// Только sandbox (`true`) или только боевые (`false`) платежи
+    pub sandbox: Option<bool>,
+}
+
+impl PaymentFilter {
+    fn matches(&self, payment: &Payment) -> bool {
+        self.status.is_none_or(|s| s == GqlPaymentStatus::from(&payment.status))
+            && self.token.as_ref().is_none_or(|t| *t == payment.token)
+            && self.merchant_id.as_ref().is_none_or(|m| Some(m) == payment.merchant_id.as_ref())
+            && self.created_after.is_none_or(|t| payment.created_at >= t)
+            && self.created_before.is_none_or(|t| payment.created_at < t)
+            && self.min_amount.is_none_or(|a| payment.amount >= a)
+            && self.max_amount.is_none_or(|a| payment.amount <= a)
+            && self.sandbox.is_none_or(|sandbox| sandbox == payment.sandbox)
+    }
+}
+
+/// Платеж в GraphQL
+pub struct PaymentNode(Payment);
+
+#[Object(name = "Payment")]
+impl PaymentNode {
+    async fn id(&self) -> &str {
+        &self.0.id
+    }
+    async fn merchant_id(&self) -> Option<&str> {
+        self.0.merchant_id.as_deref()
+    }
+    async fn sandbox(&self) -> bool {
+        self.0.sandbox
+    }
+    async fn status(&self) -> GqlPaymentStatus {
+        (&self.0.status).into()
+    }
+    async fn recipient(&self) -> &str {
+        &self.0.recipient
+    }
+    async fn amount(&self) -> f64 {
+        self.0.amount
+    }
+    /// Точная сумма в базовых единицах токена (строкой: не помещается в Int)
+    async fn amount_base_units(&self) -> String {
+        self.0.amount_base_units.to_string()
+    }
+    async fn token(&self) -> &str {
+        &self.0.token
+    }
+    async fn amount_display(&self) -> &str {
+        &self.0.amount_display
+    }
+    async fn fee_amount(&self) -> f64 {
+        self.0.fee_amount
+    }
+    async fn fee_token(&self) -> &str {
+        &self.0.fee_token
+    }
+    async fn fee_overridden(&self) -> bool {
+        self.0.fee_overridden
+    }
+    async fn fee_display(&self) -> &str {
+        &self.0.fee_display
+    }
+    async fn tip_amount(&self) -> Option<f64> {
+        self.0.tip_amount
+    }
+    async fn total_display(&self) -> &str {
+        &self.0.total_display
+    }
+    async fn label(&self) -> &str {
+        &self.0.label
+    }
+    async fn message(&self) -> &str {
+        &self.0.message
+    }
+    async fn memo(&self) -> Option<&str> {
+        self.0.memo.as_deref()
+    }
+    async fn url(&self) -> &str {
+        &self.0.url
+    }
+    async fn transfer_url(&self) -> &str {
+        &self.0.transfer_url
+    }
+    async fn signature(&self) -> Option<&str> {
+        self.0.signature.as_deref()
+    }
+    async fn explorer_url(&self) -> Option<&str> {
+        self.0.explorer_url.as_deref()
+    }
+    async fn receipt_nft_mint(&self) -> Option<&str> {
+        self.0.receipt_nft.as_ref().and_then(|nft| nft.mint.as_deref())
+    }
+    async fn created_at(&self) -> DateTime<Utc> {
+        self.0.created_at
+    }
+    async fn expires_at(&self) -> DateTime<Utc> {
+        self.0.expires_at
+    }
+    async fn verified_at(&self) -> Option<DateTime<Utc>> {
+        self.0.verified_at
+    }
+    async fn block_time(&self) -> Option<DateTime<Utc>> {
+        self.0.block_time
+    }
+    /// Данные мерчанта из запроса на создание
+    async fn metadata(&self) -> Vec<MetadataEntry> {
+        self.0.metadata.iter()
+            .map(|(key, value)| MetadataEntry { key: key.clone(), value: value.clone() })
+            .collect()
+    }
+    /// Когда платеж ушел в архив по политике хранения
+    async fn archived_at(&self) -> Option<DateTime<Utc>> {
+        self.0.archived_at
+    }
+    /// Кошелек комиссии сервиса (только администратор)
+    #[graphql(guard = "AdminGuard")]
+    async fn fee_recipient(&self) -> &str {
+        &self.0.fee_recipient
+    }
+    /// Курс комиссии к USD на момент создания (только администратор)
+    #[graphql(guard = "AdminGuard")]
+    async fn fee_usd_rate(&self) -> Option<f64> {
+        self.0.fee_usd_rate
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct MetadataEntry {
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(SimpleObject)]
+pub struct PageInfo {
+    pub has_next_page: bool,
+    /// Курсор для `after` следующей страницы
+    pub end_cursor: Option<String>,
+}
+
+#[derive(SimpleObject)]
+pub struct PaymentConnection {
+    pub nodes: Vec<PaymentNode>,
+    pub total_count: usize,
+    pub page_info: PageInfo,
+}
+
+#[derive(SimpleObject)]
+pub struct TokenAmount {
+    pub token: String,
+    pub amount: f64,
+}
+
+#[derive(SimpleObject, Default)]
+pub struct PaymentStats {
+    pub total: usize,
+    pub pending: usize,
+    pub processing: usize,
+    pub completed: usize,
+    pub expired: usize,
+    pub failed: usize,
+    pub cancelled: usize,
+    pub refunded: usize,
+    pub held: usize,
+    pub released: usize,
+    /// Оборот по завершенным платежам
+    pub volume: Vec<TokenAmount>,
+    /// Комиссии по завершенным платежам
+    pub fees: Vec<TokenAmount>,
+}
+
+#[derive(SimpleObject)]
+pub struct ViewerInfo {
+    pub is_admin: bool,
+    pub merchant_id: Option<String>,
+}
+
+pub struct QueryRoot;
+
+impl QueryRoot {
+    /// Платежи, видимые viewer, по фильтру; новые первыми
+    async fn visible_payments(ctx: &Context<'_>, filter: &PaymentFilter) -> Result<Vec<Payment>> {
+        let viewer = ctx.data::<Viewer>()?;
+        if filter.merchant_id.is_some() && !matches!(viewer, Viewer::Admin) {
+            return Err("Filtering by merchantId requires the admin key".into());
+        }
+
+        let service = ctx.data::<PaymentService>()?;
+        let mut payments: Vec<Payment> = service.storage().list_payments(ReadConsistency::Eventual).await?
+            .iter()
+            .filter(|p| viewer.can_see(p) && filter.matches(p))
+            .cloned()
+            .collect();
+        payments.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| a.id.cmp(&b.id)));
+        Ok(payments)
+    }
+}
+
+#[Object]
+impl QueryRoot {
+    async fn viewer(&self, ctx: &Context<'_>) -> Result<ViewerInfo> {
+        Ok(match ctx.data::<Viewer>()? {
+            Viewer::Admin => ViewerInfo { is_admin: true, merchant_id: None },
+            Viewer::Merchant(id) => ViewerInfo { is_admin: false, merchant_id: Some(id.clone()) },
+        })
+    }
+
+    async fn payment(&self, ctx: &Context<'_>, id: String) -> Result<Option<PaymentNode>> {
+        let viewer = ctx.data::<Viewer>()?;
+        let service = ctx.data::<PaymentService>()?;
+        Ok(service.get_payment(&id).await?
+            .filter(|p| viewer.can_see(p))
+            .map(PaymentNode))
+    }
+
+    /// Страница платежей. `after` — курсор из `pageInfo.endCursor`
+    async fn payments(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<PaymentFilter>,
+        #[graphql(default = 20)] first: usize,
+        after: Option<String>,
+    ) -> Result<PaymentConnection> {
+        let filter = filter.unwrap_or_default();
+        let payments = Self::visible_payments(ctx, &filter).await?;
+        let total_count = payments.len();
+
+        let offset = match after {
+            Some(cursor) => cursor.parse::<usize>().map_err(|_| "Invalid cursor")?,
+            None => 0,
+        };
+        let first = first.min(MAX_PAGE_SIZE);
+        let nodes: Vec<PaymentNode> = payments.into_iter()
+            .skip(offset)
+            .take(first)
+            .map(PaymentNode)
+            .collect();
+
+        let end = offset + nodes.len();
+        Ok(PaymentConnection {
+            page_info: PageInfo {
+                has_next_page: end < total_count,
+                end_cursor: (!nodes.is_empty()).then(|| end.to_string()),
+            },
+            nodes,
+            total_count,
+        })
+    }
+
+    /// Сводка по платежам viewer (с тем же фильтром, что и `payments`); sandbox платежи
+    /// администратор видит только с `sandbox: true`
+    async fn stats(&self, ctx: &Context<'_>, filter: Option<PaymentFilter>) -> Result<PaymentStats> {
+        let filter = filter.unwrap_or_default();
+        let viewer = ctx.data::<Viewer>()?;
+        let payments: Vec<Payment> = Self::visible_payments(ctx, &filter).await?
+            .into_iter()
+            .filter(|payment| viewer.in_reports(payment, filter.sandbox))
+            .collect();
+
+        let mut stats = PaymentStats { total: payments.len(), ..Default::default() };
+        let mut volume: BTreeMap<String, f64> = BTreeMap::new();
+        let mut fees: BTreeMap<String, f64> = BTreeMap::new();
+
+        for payment in &payments {
+            match payment.status {
+                PaymentStatus::Pending => stats.pending += 1,
+                PaymentStatus::Completed => {
+                    stats.completed += 1;
+                    *volume.entry(payment.token.clone()).or_default() +=
+                        payment.amount + payment.tip_amount.unwrap_or(0.0);
+                    *fees.entry(payment.fee_token.clone()).or_default() += payment.fee_amount;
+                }
+                PaymentStatus::Processing => stats.processing += 1,
+                PaymentStatus::Expired => stats.expired += 1,
+                PaymentStatus::Failed => stats.failed += 1,
+                PaymentStatus::Cancelled => stats.cancelled += 1,
+                PaymentStatus::Refunded => stats.refunded += 1,
+                PaymentStatus::Held => stats.held += 1,
+                PaymentStatus::Released => stats.released += 1,
+            }
+        }
+
+        stats.volume = volume.into_iter().map(|(token, amount)| TokenAmount { token, amount }).collect();
+        stats.fees = fees.into_iter().map(|(token, amount)| TokenAmount { token, amount }).collect();
+        Ok(stats)
+    }
+
+    /// Доход сервиса от комиссий по мерчантам (только администратор)
+    #[graphql(guard = "AdminGuard")]
+    async fn revenue_by_merchant(&self, ctx: &Context<'_>) -> Result<Vec<MerchantRevenue>> {
+        let service = ctx.data::<PaymentService>()?;
+        let report = service.revenue_report().await?;
+
+        Ok(report.by_merchant.into_iter()
+            .map(|(merchant_id, by_token)| MerchantRevenue {
+                merchant_id,
+                fees: by_token.into_iter().map(|(token, amount)| TokenAmount { token, amount }).collect(),
+            })
+            .collect())
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct MerchantRevenue {
+    pub merchant_id: String,
+    pub fees: Vec<TokenAmount>,
+}
@@ -0,0 +1,264 @@
+use std::pin::Pin;
+
+use tokio::sync::broadcast::error::RecvError;
+use tokio_stream::Stream;
+use actix_web::http::Method;
+use tonic::{Request, Response, Status};
+
+use crate::auth::{authorize_key, AuthError};
+use crate::payment::{self, PaymentService, PaymentStatus};
+use crate::validation::ValidationErrors;
+
+pub mod proto {
+    tonic::include_proto!("cryptonow.v1");
+}
+
+use proto::payments_server::{Payments, PaymentsServer};
+
+/// Metadata с API ключом мерчанта (аналог заголовка X-API-Key)
+const API_KEY_METADATA: &str = "x-api-key";
+
+/// gRPC API поверх того же PaymentService, что и REST
+#[derive(Clone)]
+pub struct GrpcPayments {
+    payments: PaymentService,
+}
+
+impl GrpcPayments {
+    pub fn new(payments: PaymentService) -> Self {
+        Self { payments }
+    }
+
+    pub fn into_server(self) -> PaymentsServer<Self> {
+        PaymentsServer::new(self)
+    }
+
+    async fn find_payment(&self, payment_id: &str) -> Result<payment::Payment, Status> {
+        self.payments.get_payment(payment_id).await
+            .map_err(internal)?
+            .ok_or_else(|| Status::not_found("Payment not found"))
+    }
+}
+
+fn internal(e: anyhow::Error) -> Status {
+    Status::internal(e.to_string())
+}
+
+/// Ошибки валидации — INVALID_ARGUMENT, остальное — FAILED_PRECONDITION
+fn rejected(e: anyhow::Error) -> Status {
+    match e.downcast_ref::<ValidationErrors>() {
+        Some(errors) => Status::invalid_argument(errors.to_string()),
+        None => Status::failed_precondition(e.to_string()),
+    }
+}
+
+fn status_to_proto(status: &PaymentStatus) -> proto::PaymentStatus {
+    match status {
+        PaymentStatus::Pending => proto::PaymentStatus::Pending,
+        PaymentStatus::Processing => proto::PaymentStatus::Processing,
+        PaymentStatus::Completed => proto::PaymentStatus::Completed,
+        PaymentStatus::Expired => proto::PaymentStatus::Expired,
+        PaymentStatus::Failed => proto::PaymentStatus::Failed,
+        PaymentStatus::Cancelled => proto::PaymentStatus::Cancelled,
+        PaymentStatus::Refunded => proto::PaymentStatus::Refunded,
+        PaymentStatus::Held => proto::PaymentStatus::Held,
+        PaymentStatus::Released => proto::PaymentStatus::Released,
+    }
+}
+
+/// Удержанный платеж еще ждет capture или release
+fn is_final(status: &PaymentStatus) -> bool {
+    !status.is_open() && *status != PaymentStatus::Held
+}
+
+impl From<payment::Payment> for proto::Payment {
+    fn from(p: payment::Payment) -> Self {
+        Self {
+            status: status_to_proto(&p.status) as i32,
+            created_at_unix: p.created_at.timestamp(),
+            expires_at_unix: p.expires_at.timestamp(),
+            verified_at_unix: p.verified_at.map(|t| t.timestamp()),
+            id: p.id,
+            merchant_id: p.merchant_id,
+            recipient: p.recipient,
+            amount: p.amount,
+            amount_base_units: p.amount_base_units,
+            discount_code: p.discount.as_ref().map(|d| d.code.clone()),
+            discount_amount: p.discount.as_ref().map(|d| d.discount_amount),
+            hold_escrow_account: p.hold.as_ref().map(|hold| hold.escrow_account.clone()),
+            hold_expires_at_unix: p.hold.as_ref().and_then(|hold| hold.expires_at).map(|t| t.timestamp()),
+            receipt_nft_mint: p.receipt_nft.as_ref().and_then(|nft| nft.mint.clone()),
+            allowed_payers: p.allowed_payers,
+            min_amount: p.amount_range.map(|range| range.min),
+            max_amount: p.amount_range.map(|range| range.max),
+            paid_amount: p.paid_amount,
+            token: p.token,
+            fee_recipient: p.fee_recipient,
+            fee_amount: p.fee_amount,
+            fee_token: p.fee_token,
+            tip_amount: p.tip_amount,
+            amount_display: p.amount_display,
+            fee_display: p.fee_display,
+            total_display: p.total_display,
+            label: p.label,
+            message: p.message,
+            memo: p.memo,
+            url: p.url,
+            qr_code: p.qr_code.unwrap_or_default(),
+            transfer_url: p.transfer_url,
+            transfer_qr_code: p.transfer_qr_code.unwrap_or_default(),
+            signature: p.signature,
+            explorer_url: p.explorer_url,
+            metadata: p.metadata.into_iter().collect(),
+            success_url: p.success_url,
+            cancel_url: p.cancel_url,
+            redirect_url: p.redirect_url,
+        }
+    }
+}
+
+fn status_update(p: &payment::Payment) -> proto::PaymentStatusUpdate {
+    proto::PaymentStatusUpdate {
+        payment_id: p.id.clone(),
+        status: status_to_proto(&p.status) as i32,
+        signature: p.signature.clone(),
+        updated_at_unix: p.verified_at.unwrap_or_else(chrono::Utc::now).timestamp(),
+        redirect_url: p.redirect_url.clone(),
+    }
+}
+
+type StatusStream = Pin<Box<dyn Stream<Item = Result<proto::PaymentStatusUpdate, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Payments for GrpcPayments {
+    async fn create_payment(
+        &self,
+        request: Request<proto::CreatePaymentRequest>,
+    ) -> Result<Response<proto::Payment>, Status> {
+        let config = self.payments.config();
+        // Права ключа — как у `POST /api/payment/create`
+        let merchant = match request.metadata().get(API_KEY_METADATA) {
+            Some(key) => {
+                let key = key.to_str().map_err(|_| Status::unauthenticated("Invalid API key"))?;
+                let grant = authorize_key(&config.tenants, key, &Method::POST, "/api/payment/create")
+                    .map_err(|e| match e {
+                        AuthError::InsufficientScope(_) => Status::permission_denied(e.to_string()),
+                        _ => Status::unauthenticated(e.to_string()),
+                    })?;
+                Some(grant.merchant)
+            }
+            None => None,
+        };
+
+        let req = request.into_inner();
+        let tip_presets_bps = if req.tip_presets_bps.is_empty() {
+            None
+        } else {
+            Some(req.tip_presets_bps.iter()
+                .map(|&bps| u16::try_from(bps).unwrap_or(u16::MAX))
+                .collect())
+        };
+
+        let payment = self.payments.create_payment_with_fee(
+            payment::CreatePaymentRequest {
+                recipient: req.recipient,
+                amount: req.amount,
+                amount_base_units: req.amount_base_units,
+                token: req.token,
+                label: req.label,
+                message: req.message,
+                memo: req.memo,
+                tip_presets_bps,
+                locale: req.locale,
+                metadata: (!req.metadata.is_empty()).then(|| req.metadata.into_iter().collect()),
+                fee_amount: req.fee_amount,
+                fee_token: req.fee_token,
+                success_url: req.success_url,
+                cancel_url: req.cancel_url,
+                discount_code: req.discount_code,
+                redeem_points: None,
+                allowed_payers: (!req.allowed_payers.is_empty()).then_some(req.allowed_payers),
+                hold: req.hold,
+                hold_secs: req.hold_secs,
+                amount_range: match (req.min_amount, req.max_amount) {
+                    (None, None) => None,
+                    (min, max) => Some(payment::AmountRange { min: min.unwrap_or_default(), max: max.unwrap_or_default() }),
+                },
+            },
+            merchant.as_deref(),
+        ).await.map_err(rejected)?;
+        let payment = self.payments.with_qr_code(payment).map_err(internal)?;
+
+        Ok(Response::new(payment.into()))
+    }
+
+    async fn get_payment(
+        &self,
+        request: Request<proto::GetPaymentRequest>,
+    ) -> Result<Response<proto::Payment>, Status> {
+        let payment = self.find_payment(&request.into_inner().payment_id).await?;
+        let payment = self.payments.with_qr_code(payment).map_err(internal)?;
+        Ok(Response::new(payment.into()))
+    }
+
+    async fn verify_payment(
+        &self,
+        request: Request<proto::VerifyPaymentRequest>,
+    ) -> Result<Response<proto::VerificationResult>, Status> {
+        let req = request.into_inner();
+        let result = self.payments.verify_payment(&req.payment_id, &req.signature).await
+            .map_err(rejected)?;
+
+        Ok(Response::new(proto::VerificationResult {
+            success: result.success,
+            status: status_to_proto(&result.status) as i32,
+            verified: result.verified,
+            signature: result.signature,
+            explorer_url: result.explorer_url,
+            details: result.details,
+        }))
+    }
+
+    type StreamPaymentStatusStream = StatusStream;
+
+    async fn stream_payment_status(
+        &self,
+        request: Request<proto::GetPaymentRequest>,
+    ) -> Result<Response<Self::StreamPaymentStatusStream>, Status> {
+        let payment_id = request.into_inner().payment_id;
+
+        // Подписываемся до чтения текущего состояния, чтобы не пропустить изменение между ними
+        let mut updates = self.payments.storage().subscribe_payments();
+        let current = self.find_payment(&payment_id).await?;
+        let service = self.clone();
+
+        let stream = async_stream::try_stream! {
+            let mut last_status = status_to_proto(&current.status);
+            yield status_update(&current);
+            if is_final(&current.status) {
+                return;
+            }
+
+            loop {
+                let payment = match updates.recv().await {
+                    Ok(payment) if payment.id == payment_id => payment,
+                    Ok(_) => continue,
+                    // Пропустили часть обновлений — перечитываем состояние
+                    Err(RecvError::Lagged(_)) => service.find_payment(&payment_id).await?,
+                    Err(RecvError::Closed) => break,
+                };
+
+                let status = status_to_proto(&payment.status);
+                if status != last_status {
+                    last_status = status;
+                    yield status_update(&payment);
+                }
+                if is_final(&payment.status) {
+                    break;
+                }
+            }
+        };
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
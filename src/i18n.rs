@@ -0,0 +1,158 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::payment::Payment;
+use crate::transaction::TransactionPart;
+
+/// Язык строк, которые показывает кошелек плательщика (label/message)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    #[default]
+    En,
+    Ru,
+    Es,
+    De,
+    Fr,
+}
+
+impl FromStr for Locale {
+    type Err = String;
+
+    /// Принимает код языка и BCP 47 теги вида `ru-RU`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let language = s.split(['-', '_']).next().unwrap_or_default().to_ascii_lowercase();
+        match language.as_str() {
+            "en" => Ok(Locale::En),
+            "ru" => Ok(Locale::Ru),
+            "es" => Ok(Locale::Es),
+            "de" => Ok(Locale::De),
+            "fr" => Ok(Locale::Fr),
+            _ => Err(format!("Unsupported locale '{}'. Supported locales: {}", s, Locale::SUPPORTED.join(", "))),
+        }
+    }
+}
+
+/// Шаблоны строк одного языка.
+///
+/// Плейсхолдеры: `{amount}`, `{token}`, `{fee}`, `{fee_token}`, `{tip}` (фрагмент чаевых)
+/// и `{tip_amount}` (внутри фрагмента чаевых)
+struct Templates {
+    label: &'static str,
+    message: &'static str,
+    pay: &'static str,
+    pay_with_fee: &'static str,
+    pay_fee: &'static str,
+    tip: &'static str,
+}
+
+const EN: Templates = Templates {
+    label: "Payment {token}",
+    message: "{amount} {token} + {fee} {fee_token} fee",
+    pay: "Pay {amount} {token}{tip}",
+    pay_with_fee: "Pay {amount} {token}{tip} + {fee} {fee_token} fee",
+    pay_fee: "Pay {fee} {fee_token} fee",
+    tip: " + {tip_amount} {token} tip",
+};
+
+const RU: Templates = Templates {
+    label: "Платеж {token}",
+    message: "{amount} {token} + комиссия {fee} {fee_token}",
+    pay: "Оплатить {amount} {token}{tip}",
+    pay_with_fee: "Оплатить {amount} {token}{tip} + комиссия {fee} {fee_token}",
+    pay_fee: "Оплатить комиссию {fee} {fee_token}",
+    tip: " + чаевые {tip_amount} {token}",
+};
+
+const ES: Templates = Templates {
+    label: "Pago {token}",
+    message: "{amount} {token} + {fee} {fee_token} de comisión",
+    pay: "Pagar {amount} {token}{tip}",
+    pay_with_fee: "Pagar {amount} {token}{tip} + {fee} {fee_token} de comisión",
+    pay_fee: "Pagar {fee} {fee_token} de comisión",
+    tip: " + {tip_amount} {token} de propina",
+};
+
+const DE: Templates = Templates {
+    label: "Zahlung {token}",
+    message: "{amount} {token} + {fee} {fee_token} Gebühr",
+    pay: "{amount} {token}{tip} bezahlen",
+    pay_with_fee: "{amount} {token}{tip} + {fee} {fee_token} Gebühr bezahlen",
+    pay_fee: "{fee} {fee_token} Gebühr bezahlen",
+    tip: " + {tip_amount} {token} Trinkgeld",
+};
+
+const FR: Templates = Templates {
+    label: "Paiement {token}",
+    message: "{amount} {token} + {fee} {fee_token} de frais",
+    pay: "Payer {amount} {token}{tip}",
+    pay_with_fee: "Payer {amount} {token}{tip} + {fee} {fee_token} de frais",
+    pay_fee: "Payer {fee} {fee_token} de frais",
+    tip: " + {tip_amount} {token} de pourboire",
+};
+
+/// Значения для подстановки в шаблон
+struct Args<'a> {
+    amount: f64,
+    token: &'a str,
+    fee: f64,
+    fee_token: &'a str,
+    tip: String,
+}
+
+impl Locale {
+    pub const SUPPORTED: [&'static str; 5] = ["en", "ru", "es", "de", "fr"];
+
+    fn templates(&self) -> &'static Templates {
+        match self {
+            Locale::En => &EN,
+            Locale::Ru => &RU,
+            Locale::Es => &ES,
+            Locale::De => &DE,
+            Locale::Fr => &FR,
+        }
+    }
+
+    /// Label платежа по умолчанию
+    pub fn payment_label(&self, token: &str) -> String {
+        self.templates().label.replace("{token}", token)
+    }
+
+    /// Message платежа по умолчанию
+    pub fn payment_message(&self, amount: f64, token: &str, fee: f64, fee_token: &str) -> String {
+        render(self.templates().message, &Args { amount, token, fee, fee_token, tip: String::new() })
+    }
+
+    /// Строка для кошелька: что именно оплачивает транзакция
+    pub fn transaction_message(&self, payment: &Payment, part: TransactionPart) -> String {
+        let templates = self.templates();
+        let tip = match payment.tip_amount {
+            Some(tip) if tip > 0.0 => templates.tip
+                .replace("{tip_amount}", &tip.to_string())
+                .replace("{token}", &payment.token),
+            _ => String::new(),
+        };
+        let template = match part {
+            TransactionPart::Full => templates.pay_with_fee,
+            TransactionPart::Main => templates.pay,
+            TransactionPart::Fee => templates.pay_fee,
+        };
+
+        render(template, &Args {
+            amount: payment.amount,
+            token: &payment.token,
+            fee: payment.fee_amount,
+            fee_token: &payment.fee_token,
+            tip,
+        })
+    }
+}
+
+fn render(template: &str, args: &Args) -> String {
+    template
+        .replace("{amount}", &args.amount.to_string())
+        .replace("{fee_token}", args.fee_token)
+        .replace("{fee}", &args.fee.to_string())
+        .replace("{token}", args.token)
+        .replace("{tip}", &args.tip)
+}
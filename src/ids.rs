@@ -0,0 +1,118 @@
+use rand::Rng;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::config::{PaymentIdFormat, ServerConfig};
+
+/// Префикс ID платежа
+pub const PAYMENT_ID_PREFIX: &str = "pay_";
+
+/// Base32 Крокфорда: без I, L, O и U — их не спутать с цифрами при диктовке
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Генератор ID платежей
+pub trait IdGenerator: Send + Sync {
+    /// Новый ID (уникальность проверяет хранилище)
+    fn generate(&self) -> String;
+
+    /// Каноническая запись ID, введенного человеком; `None` — ID этого формата
+    /// с ошибкой (не сходится контрольный символ)
+    fn normalize(&self, id: &str) -> Option<String> {
+        Some(id.to_string())
+    }
+}
+
+/// `pay_` + UUID без дефисов — формат по умолчанию
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UuidIds;
+
+impl IdGenerator for UuidIds {
+    fn generate(&self) -> String {
+        format!("{}{}", PAYMENT_ID_PREFIX, Uuid::new_v4().simple())
+    }
+}
+
+/// Короткие коды для диктовки: `pay_` + base32 символы, последний — контрольный
+/// (Luhn mod 32: ловит любую одну ошибку в символе). При вводе регистр не важен,
+/// O читается как 0, I и L — как 1
+#[derive(Debug, Clone, Copy)]
+pub struct ShortCodes {
+    /// Длина кода вместе с контрольным символом
+    length: usize,
+}
+
+impl ShortCodes {
+    pub fn new(length: usize) -> Self {
+        Self { length }
+    }
+
+    fn value(symbol: char) -> Option<u32> {
+        let symbol = match symbol.to_ascii_uppercase() {
+            'O' => '0',
+            'I' | 'L' => '1',
+            other => other,
+        };
+        ALPHABET.iter().position(|&c| c as char == symbol).map(|position| position as u32)
+    }
+
+    /// Контрольный символ Luhn mod 32
+    fn check_digit(values: &[u32]) -> u32 {
+        let base = ALPHABET.len() as u32;
+        let sum: u32 = values.iter().rev().enumerate()
+            .map(|(i, value)| {
+                let addend = if i % 2 == 0 { value * 2 } else { *value };
+                addend / base + addend % base
+            })
+            .sum();
+        (base - sum % base) % base
+    }
+}
+
+impl IdGenerator for ShortCodes {
+    fn generate(&self) -> String {
+        let mut rng = rand::thread_rng();
+        let mut values: Vec<u32> = (1..self.length).map(|_| rng.gen_range(0..ALPHABET.len() as u32)).collect();
+        values.push(Self::check_digit(&values));
+        let code: String = values.iter().map(|&value| ALPHABET[value as usize] as char).collect();
+        format!("{}{}", PAYMENT_ID_PREFIX, code)
+    }
+
+    fn normalize(&self, id: &str) -> Option<String> {
+        let code = id.get(..PAYMENT_ID_PREFIX.len())
+            .filter(|prefix| prefix.eq_ignore_ascii_case(PAYMENT_ID_PREFIX))
+            .map_or(id, |_| &id[PAYMENT_ID_PREFIX.len()..]);
+        let values: Option<Vec<u32>> = code.chars().map(Self::value).collect();
+        // Не короткий код (например, UUID платежа, созданного до смены формата)
+        let Some(values) = values.filter(|values| values.len() == self.length) else {
+            return Some(id.to_string());
+        };
+        let (payload, check) = values.split_at(self.length - 1);
+        if Self::check_digit(payload) != check[0] {
+            return None;
+        }
+        let code: String = values.iter().map(|&value| ALPHABET[value as usize] as char).collect();
+        Some(format!("{}{}", PAYMENT_ID_PREFIX, code))
+    }
+}
+
+/// Код короткой ссылки `/t/{code}`: символы base32 Крокфорда в верхнем регистре
+pub fn short_link_code(length: usize) -> String {
+    let mut rng = rand::thread_rng();
+    (0..length).map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())] as char).collect()
+}
+
+/// Каноническая запись кода короткой ссылки (регистр и похожие символы не важны);
+/// `None` — в коде есть символы не из алфавита
+pub fn normalize_short_link(code: &str) -> Option<String> {
+    code.chars()
+        .map(|symbol| ShortCodes::value(symbol).map(|value| ALPHABET[value as usize] as char))
+        .collect()
+}
+
+/// Генератор по `server.payment_id_format`
+pub fn from_config(config: &ServerConfig) -> Arc<dyn IdGenerator> {
+    match config.payment_id_format {
+        PaymentIdFormat::Uuid => Arc::new(UuidIds),
+        PaymentIdFormat::Short => Arc::new(ShortCodes::new(config.short_id_length)),
+    }
+}
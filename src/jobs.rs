@@ -0,0 +1,336 @@
+use chrono::{DateTime, Datelike, Duration, DurationRound, Timelike, Utc};
+use futures::future::BoxFuture;
+use serde::Serialize;
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::config::JobConfig;
+use crate::lock::LeaderElection;
+
+/// Фоновые задачи по расписанию
+pub const JOB_NAMES: &[&str] = &[
+    "expiration", "retention", "blockhash", "reconciliation", "sweep", "settlement", "escrow", "receipt_nft",
+    "storage_snapshot",
+];
+
+/// Дальше этого горизонта следующий запуск не ищется (расписание вроде 30 февраля)
+const SEARCH_HORIZON_DAYS: i64 = 366 * 5;
+
+/// Расписание задачи (время UTC): cron из 5 полей (`мин час день месяц день_недели`),
+/// из 6 полей — с секундами впереди, или `@every 30s` / `@every 5m` / `@every 1h`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Schedule {
+    Every(std::time::Duration),
+    Cron(Cron),
+}
+
+/// Разобранное cron выражение: допустимые значения каждого поля битовой маской
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cron {
+    seconds: u64,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Поля дня месяца и дня недели заданы оба: подходит любое из них (как в cron)
+    either_day: bool,
+}
+
+impl Schedule {
+    /// Ближайший запуск строго после `after`
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Schedule::Every(period) => Some(after + Duration::from_std(*period).ok()?),
+            Schedule::Cron(cron) => cron.next_after(after),
+        }
+    }
+
+    /// Интервал между запуском `at` и следующим — по нему выбирается срок lease лидера
+    pub fn period_at(&self, at: DateTime<Utc>) -> std::time::Duration {
+        self.next_after(at)
+            .and_then(|next| (next - at).to_std().ok())
+            .unwrap_or(std::time::Duration::from_secs(60))
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(period) = s.strip_prefix("@every") {
+            let period = period.trim();
+            let (value, unit) = period.split_at(period.find(|c: char| !c.is_ascii_digit()).unwrap_or(period.len()));
+            let value: u64 = value.parse().map_err(|_| format!("expected '@every <N>s|m|h', got '{}'", s))?;
+            let secs = match unit {
+                "s" => value,
+                "m" => value * 60,
+                "h" => value * 3600,
+                _ => return Err(format!("expected '@every <N>s|m|h', got '{}'", s)),
+            };
+            if secs == 0 {
+                return Err("@every period must be positive".to_string());
+            }
+            return Ok(Schedule::Every(std::time::Duration::from_secs(secs)));
+        }
+
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let (seconds, rest) = match fields.len() {
+            5 => ("0", &fields[..]),
+            6 => (fields[0], &fields[1..]),
+            n => return Err(format!("cron expression must have 5 or 6 fields, got {} in '{}'", n, s)),
+        };
+        let weekdays = parse_field(rest[4], 0, 7, "day of week")?;
+        Ok(Schedule::Cron(Cron {
+            seconds: parse_field(seconds, 0, 59, "second")?,
+            minutes: parse_field(rest[0], 0, 59, "minute")?,
+            hours: parse_field(rest[1], 0, 23, "hour")?,
+            days: parse_field(rest[2], 1, 31, "day of month")?,
+            months: parse_field(rest[3], 1, 12, "month")?,
+            // 7 — тоже воскресенье
+            weekdays: (weekdays | (weekdays >> 7)) & 0x7f,
+            either_day: rest[2] != "*" && rest[4] != "*",
+        }))
+    }
+}
+
+/// Поле cron: `*`, `5`, `1-5`, `*/15`, `10-50/10` и списки через запятую
+fn parse_field(field: &str, min: u32, max: u32, name: &str) -> Result<u64, String> {
+    let invalid = || format!("invalid {} field '{}'", name, field);
+    let mut mask = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().map_err(|_| invalid())?),
+            None => (part, 1),
+        };
+        let (from, to) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((from, to)) => (from.parse().map_err(|_| invalid())?, to.parse().map_err(|_| invalid())?),
+                None => {
+                    let value = range.parse().map_err(|_| invalid())?;
+                    (value, if part.contains('/') { max } else { value })
+                }
+            },
+        };
+        if step == 0 || from < min || to > max || from > to {
+            return Err(invalid());
+        }
+        for value in (from..=to).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+    Ok(mask)
+}
+
+impl Cron {
+    fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let has = |mask: u64, value: u32| mask & (1 << value) != 0;
+        let horizon = after + Duration::days(SEARCH_HORIZON_DAYS);
+        let mut t = after.duration_trunc(Duration::seconds(1)).ok()? + Duration::seconds(1);
+        // Несовпавшее поле пропускается целиком: месяц, день, час, минута
+        while t < horizon {
+            if !has(self.months, t.month()) {
+                let (year, month) = if t.month() == 12 { (t.year() + 1, 1) } else { (t.year(), t.month() + 1) };
+                t = t.with_day(1)?.with_month(month)?.with_year(year)?.duration_trunc(Duration::days(1)).ok()?;
+                continue;
+            }
+            let day_of_month = has(self.days, t.day());
+            let day_of_week = has(self.weekdays, t.weekday().num_days_from_sunday());
+            let day = if self.either_day { day_of_month || day_of_week } else { day_of_month && day_of_week };
+            if !day {
+                t = t.duration_trunc(Duration::days(1)).ok()? + Duration::days(1);
+                continue;
+            }
+            if !has(self.hours, t.hour()) {
+                t = t.duration_trunc(Duration::hours(1)).ok()? + Duration::hours(1);
+                continue;
+            }
+            if !has(self.minutes, t.minute()) {
+                t = t.duration_trunc(Duration::minutes(1)).ok()? + Duration::minutes(1);
+                continue;
+            }
+            if !has(self.seconds, t.second()) {
+                t += Duration::seconds(1);
+                continue;
+            }
+            return Some(t);
+        }
+        None
+    }
+}
+
+/// Итог запуска задачи
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "outcome", content = "error")]
+pub enum JobOutcome {
+    Completed,
+    Failed(String),
+    /// Предыдущий запуск еще не закончился
+    Skipped,
+}
+
+/// Состояние задачи для `GET /admin/jobs`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct JobStatus {
+    pub name: String,
+    pub schedule: String,
+    pub enabled: bool,
+    /// Выполняется только на реплике-лидере
+    pub leader_only: bool,
+    pub running: bool,
+    pub next_run_at: Option<DateTime<Utc>>,
+    pub last_started_at: Option<DateTime<Utc>>,
+    pub last_finished_at: Option<DateTime<Utc>>,
+    pub last_duration_ms: Option<u64>,
+    pub last_error: Option<String>,
+    pub runs: u64,
+    pub failures: u64,
+    /// Запуски, пропущенные из-за незавершенного предыдущего
+    pub skipped: u64,
+}
+
+type JobFn = Arc<dyn Fn() -> BoxFuture<'static, anyhow::Result<()>> + Send + Sync>;
+
+struct Job {
+    schedule: Schedule,
+    /// Имя lease лидера; `None` — задача выполняется на каждой реплике
+    lease: Option<&'static str>,
+    run: JobFn,
+    running: AtomicBool,
+    status: Mutex<JobStatus>,
+}
+
+/// Снимает флаг выполнения и при панике задачи
+struct RunningGuard<'a>(&'a AtomicBool);
+
+impl Drop for RunningGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+impl Job {
+    fn status(&self) -> std::sync::MutexGuard<'_, JobStatus> {
+        self.status.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Выполнить задачу, если предыдущий запуск закончился
+    async fn execute(&self) -> JobOutcome {
+        let name = self.status().name.clone();
+        if self.running.swap(true, Ordering::SeqCst) {
+            self.status().skipped += 1;
+            tracing::warn!(job = %name, "Previous run is still in progress, skipping");
+            return JobOutcome::Skipped;
+        }
+        let _guard = RunningGuard(&self.running);
+        let started = std::time::Instant::now();
+        self.status().last_started_at = Some(Utc::now());
+
+        let result = (self.run)().await;
+
+        let mut status = self.status();
+        status.runs += 1;
+        status.last_finished_at = Some(Utc::now());
+        status.last_duration_ms = Some(started.elapsed().as_millis() as u64);
+        match result {
+            Ok(()) => {
+                status.last_error = None;
+                tracing::debug!(job = %name, duration_ms = status.last_duration_ms, "Job completed");
+                JobOutcome::Completed
+            }
+            Err(e) => {
+                status.failures += 1;
+                status.last_error = Some(format!("{:#}", e));
+                tracing::error!(job = %name, error = format!("{:#}", e), "Scheduled job failed");
+                JobOutcome::Failed(format!("{:#}", e))
+            }
+        }
+    }
+}
+
+/// Планировщик фоновых задач: у каждой задачи расписание из `jobs.<имя>`, флаг включения
+/// и защита от наложения запусков (новый запуск пропускается, пока идет предыдущий)
+#[derive(Clone)]
+pub struct JobScheduler {
+    jobs: Vec<Arc<Job>>,
+    leader: LeaderElection,
+}
+
+impl JobScheduler {
+    pub fn new(leader: LeaderElection) -> Self {
+        Self { jobs: Vec::new(), leader }
+    }
+
+    /// Добавить задачу с настройками `config`. `lease` — имя блокировки лидера
+    /// (`None` — задача нужна на каждой реплике)
+    pub fn add<F, Fut>(&mut self, name: &str, config: &JobConfig, lease: Option<&'static str>, run: F) -> anyhow::Result<()>
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        let schedule: Schedule = config.schedule.parse()
+            .map_err(|e| anyhow::anyhow!("jobs.{}.schedule: {}", name, e))?;
+        self.jobs.push(Arc::new(Job {
+            schedule,
+            lease,
+            run: Arc::new(move || Box::pin(run())),
+            running: AtomicBool::new(false),
+            status: Mutex::new(JobStatus {
+                name: name.to_string(),
+                schedule: config.schedule.clone(),
+                enabled: config.enabled,
+                leader_only: lease.is_some(),
+                ..JobStatus::default()
+            }),
+        }));
+        Ok(())
+    }
+
+    /// Запустить включенные задачи по расписанию
+    pub fn spawn(&self) {
+        for job in self.jobs.iter().filter(|job| job.status().enabled) {
+            let (job, leader) = (job.clone(), self.leader.clone());
+            let (name, schedule) = {
+                let status = job.status();
+                (status.name.clone(), status.schedule.clone())
+            };
+            tracing::info!(job = %name, %schedule, "Job scheduled");
+            tokio::spawn(async move {
+                loop {
+                    let now = Utc::now();
+                    let Some(next) = job.schedule.next_after(now) else {
+                        tracing::warn!(job = %name, "Job schedule has no upcoming runs");
+                        return;
+                    };
+                    job.status().next_run_at = Some(next);
+                    tokio::time::sleep((next - now).to_std().unwrap_or_default()).await;
+                    if let Some(lease) = job.lease {
+                        // Lease переживает один пропущенный запуск
+                        let ttl = job.schedule.period_at(next) * 2;
+                        if !leader.is_leader(lease, ttl).await {
+                            continue;
+                        }
+                    }
+                    let job = job.clone();
+                    tokio::spawn(async move { job.execute().await });
+                }
+            });
+        }
+    }
+
+    /// Выполнить задачу сейчас, вне расписания; `None` — задачи нет
+    pub async fn run_now(&self, name: &str) -> Option<JobOutcome> {
+        let job = self.jobs.iter().find(|job| job.status().name == name)?.clone();
+        Some(job.execute().await)
+    }
+
+    pub fn status(&self) -> Vec<JobStatus> {
+        self.jobs.iter()
+            .map(|job| JobStatus { running: job.running.load(Ordering::SeqCst), ..job.status().clone() })
+            .collect()
+    }
+}
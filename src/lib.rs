@@ -1,5 +1,66 @@
+pub mod acme;
+pub mod actions;
+pub mod auth;
+pub mod backpressure;
+pub mod body_limits;
+pub mod cache;
+pub mod compute_budget;
 pub mod config;
+pub mod deadline;
+pub mod deeplink;
+pub mod discounts;
+pub mod display;
+pub mod disputes;
+pub mod error_reporting;
+pub mod escrow;
+pub mod events;
+pub mod explorer;
+pub mod export;
+pub mod fees;
+pub mod graphql;
+pub mod grpc;
+pub mod i18n;
+pub mod ids;
+pub mod jobs;
+pub mod limits;
+pub mod lock;
+pub mod logging;
+pub mod loyalty;
 pub mod multichain;
+pub mod notifications;
+pub mod observability;
+pub mod outbox;
+pub mod ownership;
+pub mod payload_signer;
 pub mod payment;
+pub mod plugins;
+pub mod pools;
+pub mod pos;
+pub mod price;
 pub mod qr;
-pub mod storage;
\ No newline at end of file
+pub mod rate_limit;
+pub mod receipt;
+pub mod receipt_nft;
+pub mod recipient;
+pub mod reconcile;
+pub mod registration;
+pub mod replica;
+pub mod retention;
+pub mod rpc;
+pub mod screening;
+pub mod settlement;
+pub mod signer;
+pub mod snapshot;
+pub mod sponsorship;
+pub mod sse;
+pub mod static_qr;
+pub mod storage;
+pub mod swap;
+pub mod sweep;
+pub mod tenants;
+pub mod tls;
+pub mod tokens;
+pub mod transaction;
+pub mod validation;
+pub mod watcher;
+pub mod webhook;
\ No newline at end of file
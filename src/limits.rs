@@ -0,0 +1,187 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::config::{Config, MerchantConfig, MerchantLimits};
+use crate::storage::StorageService;
+use crate::validation::ValidationErrors;
+
+/// Окно лимитов мерчанта, часов
+pub const LIMIT_WINDOW_HOURS: i64 = 24;
+
+/// Платежи мерчанта за окно лимитов
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MerchantUsage {
+    /// Созданные платежи (включая истекшие)
+    pub payments: u32,
+    /// Токен → сумма ожидающих, отправленных и завершенных платежей
+    pub volume: BTreeMap<String, f64>,
+    /// Самый старый платеж в окне — когда он выйдет из окна, освободится место
+    pub oldest_created_at: Option<DateTime<Utc>>,
+}
+
+/// Временное переопределение лимитов мерчанта администратором. Заменяет лимиты
+/// из конфигурации целиком: не заданное измерение не ограничено
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LimitOverride {
+    pub merchant_id: String,
+    pub limits: MerchantLimits,
+    pub reason: Option<String>,
+    /// Без срока — до удаления
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl LimitOverride {
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_none_or(|expires_at| expires_at > now)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LimitOverrideRequest {
+    #[serde(flatten)]
+    pub limits: MerchantLimits,
+    pub reason: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Лимиты и использование мерчанта для admin API
+#[derive(Debug, Clone, Serialize)]
+pub struct LimitStatus {
+    pub merchant_id: String,
+    pub configured: Option<MerchantLimits>,
+    #[serde(rename = "override")]
+    pub limit_override: Option<LimitOverride>,
+    /// Лимиты, действующие сейчас
+    pub effective: Option<MerchantLimits>,
+    pub usage: MerchantUsage,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LimitError {
+    #[error("Merchant payment limit reached: {limit} payments per {LIMIT_WINDOW_HOURS}h")]
+    TooManyPayments { limit: u32, retry_after_secs: u64 },
+    #[error("Merchant volume limit exceeded: {used} of {limit} {token} per {LIMIT_WINDOW_HOURS}h")]
+    VolumeExceeded { token: String, limit: f64, used: f64 },
+    #[error("Merchant not found")]
+    MerchantNotFound,
+}
+
+/// Риск-лимиты мерчантов: число и объем платежей за скользящие 24 часа
+#[derive(Debug, Clone)]
+pub struct LimitService {
+    storage: StorageService,
+    config: Config,
+}
+
+impl LimitService {
+    pub fn new(config: Config, storage: StorageService) -> Self {
+        Self { storage, config }
+    }
+
+    /// Проверить, что мерчант может создать еще один платеж на `amount` `token`
+    pub async fn check(&self, merchant: &MerchantConfig, token: &str, amount: f64) -> anyhow::Result<()> {
+        self.check_batch(merchant, token, amount, 1).await
+    }
+
+    /// Проверить, что мерчант может создать еще `count` платежей по `amount` `token`
+    pub async fn check_batch(
+        &self,
+        merchant: &MerchantConfig,
+        token: &str,
+        amount: f64,
+        count: usize,
+    ) -> anyhow::Result<()> {
+        let Some(limits) = self.effective(merchant).await? else {
+            return Ok(());
+        };
+        let now = Utc::now();
+        let usage = self.storage.merchant_usage(&merchant.id, now - Duration::hours(LIMIT_WINDOW_HOURS)).await?;
+
+        if let Some(limit) = limits.max_daily_payments {
+            if usage.payments as usize + count > limit as usize {
+                let retry_after_secs = usage.oldest_created_at
+                    .map(|oldest| (oldest + Duration::hours(LIMIT_WINDOW_HOURS) - now).num_seconds().max(1) as u64)
+                    .unwrap_or(1);
+                return Err(LimitError::TooManyPayments { limit, retry_after_secs }.into());
+            }
+        }
+        if let Some(&limit) = limits.max_daily_volume.get(token) {
+            let used = usage.volume.get(token).copied().unwrap_or(0.0);
+            if used + amount * count as f64 > limit {
+                return Err(LimitError::VolumeExceeded { token: token.to_string(), limit, used }.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Действующие лимиты: активное переопределение или лимиты из конфигурации
+    async fn effective(&self, merchant: &MerchantConfig) -> anyhow::Result<Option<MerchantLimits>> {
+        match self.storage.get_limit_override(&merchant.id).await? {
+            Some(limit_override) if limit_override.is_active(Utc::now()) => Ok(Some(limit_override.limits)),
+            _ => Ok(merchant.limits.clone()),
+        }
+    }
+
+    /// Лимиты и использование мерчанта
+    pub async fn status(&self, merchant_id: &str) -> anyhow::Result<LimitStatus> {
+        let merchant = self.merchant(merchant_id)?;
+        let now = Utc::now();
+        let limit_override = self.storage.get_limit_override(merchant_id).await?
+            .filter(|limit_override| limit_override.is_active(now));
+        Ok(LimitStatus {
+            merchant_id: merchant.id.clone(),
+            configured: merchant.limits.clone(),
+            effective: self.effective(&merchant).await?,
+            limit_override,
+            usage: self.storage.merchant_usage(merchant_id, now - Duration::hours(LIMIT_WINDOW_HOURS)).await?,
+        })
+    }
+
+    /// Переопределить лимиты мерчанта (заменяет предыдущее переопределение)
+    pub async fn set_override(&self, merchant_id: &str, request: LimitOverrideRequest) -> anyhow::Result<LimitOverride> {
+        let merchant = self.merchant(merchant_id)?;
+        let now = Utc::now();
+
+        let mut errors = ValidationErrors::new();
+        if request.limits.max_daily_payments == Some(0) {
+            errors.add("max_daily_payments", "out_of_range", "max_daily_payments must be at least 1");
+        }
+        for (token, volume) in &request.limits.max_daily_volume {
+            if !self.config.is_token_supported(token) {
+                errors.add("max_daily_volume", "unsupported_token", format!("Token {} is not supported", token));
+            }
+            if !(volume.is_finite() && *volume > 0.0) {
+                errors.add("max_daily_volume", "out_of_range", format!("Volume limit for {} must be positive", token));
+            }
+        }
+        if request.expires_at.is_some_and(|expires_at| expires_at <= now) {
+            errors.add("expires_at", "out_of_range", "expires_at must be in the future");
+        }
+        errors.into_result()?;
+
+        let limit_override = LimitOverride {
+            merchant_id: merchant.id.clone(),
+            limits: request.limits,
+            reason: request.reason,
+            expires_at: request.expires_at,
+            created_at: now,
+        };
+        self.storage.save_limit_override(&limit_override).await?;
+        tracing::info!(merchant = %merchant.id, expires_at = ?limit_override.expires_at, "Merchant limits overridden");
+        Ok(limit_override)
+    }
+
+    /// Вернуть лимиты из конфигурации. `false` — переопределения не было
+    pub async fn clear_override(&self, merchant_id: &str) -> anyhow::Result<bool> {
+        self.merchant(merchant_id)?;
+        self.storage.delete_limit_override(merchant_id).await
+    }
+
+    fn merchant(&self, merchant_id: &str) -> anyhow::Result<Arc<MerchantConfig>> {
+        self.config.find_merchant(merchant_id)
+            .ok_or_else(|| LimitError::MerchantNotFound.into())
+    }
+}
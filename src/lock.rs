@@ -0,0 +1,158 @@
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use crate::config::{CoordinationConfig, LockBackend};
+
+/// Хранилище блокировок с lease: владелец должен продлевать блокировку раньше, чем истечет TTL
+#[async_trait]
+pub trait LockManager: Send + Sync {
+    /// Взять свободную (или истекшую) блокировку либо продлить свою. `true` — блокировка наша
+    async fn try_acquire(&self, name: &str, owner: &str, ttl: Duration) -> anyhow::Result<bool>;
+
+    /// Отпустить блокировку, если она наша
+    async fn release(&self, name: &str, owner: &str) -> anyhow::Result<()>;
+}
+
+/// Блокировки в памяти процесса (одна реплика)
+#[derive(Default)]
+pub struct InMemoryLockManager {
+    leases: Mutex<HashMap<String, (String, Instant)>>,
+}
+
+#[async_trait]
+impl LockManager for InMemoryLockManager {
+    async fn try_acquire(&self, name: &str, owner: &str, ttl: Duration) -> anyhow::Result<bool> {
+        let mut leases = self.leases.lock().await;
+        let now = Instant::now();
+
+        match leases.get(name) {
+            Some((holder, expires_at)) if holder != owner && *expires_at > now => Ok(false),
+            _ => {
+                leases.insert(name.to_string(), (owner.to_string(), now + ttl));
+                Ok(true)
+            }
+        }
+    }
+
+    async fn release(&self, name: &str, owner: &str) -> anyhow::Result<()> {
+        let mut leases = self.leases.lock().await;
+        if leases.get(name).is_some_and(|(holder, _)| holder == owner) {
+            leases.remove(name);
+        }
+        Ok(())
+    }
+}
+
+/// Блокировки в общем Redis (несколько реплик): `SET NX PX` + проверка владельца в Lua
+#[cfg(feature = "redis")]
+pub struct RedisLockManager {
+    connection: redis::aio::ConnectionManager,
+}
+
+#[cfg(feature = "redis")]
+impl RedisLockManager {
+    const KEY_PREFIX: &'static str = "cryptonow:lock:";
+
+    pub async fn connect(redis_url: &str) -> anyhow::Result<Self> {
+        let client = redis::Client::open(redis_url)?;
+        let connection = redis::aio::ConnectionManager::new(client).await?;
+        Ok(Self { connection })
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl LockManager for RedisLockManager {
+    async fn try_acquire(&self, name: &str, owner: &str, ttl: Duration) -> anyhow::Result<bool> {
+        // Продлеваем свою блокировку или берем свободную — атомарно
+        let script = redis::Script::new(
+            r#"
+            if redis.call("GET", KEYS[1]) == ARGV[1] then
+                return redis.call("PEXPIRE", KEYS[1], ARGV[2])
+            end
+            if redis.call("SET", KEYS[1], ARGV[1], "NX", "PX", ARGV[2]) then
+                return 1
+            end
+            return 0
+            "#,
+        );
+        let acquired: i32 = script
+            .key(format!("{}{}", Self::KEY_PREFIX, name))
+            .arg(owner)
+            .arg(ttl.as_millis() as u64)
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        Ok(acquired == 1)
+    }
+
+    async fn release(&self, name: &str, owner: &str) -> anyhow::Result<()> {
+        let script = redis::Script::new(
+            r#"
+            if redis.call("GET", KEYS[1]) == ARGV[1] then
+                return redis.call("DEL", KEYS[1])
+            end
+            return 0
+            "#,
+        );
+        let _: i32 = script
+            .key(format!("{}{}", Self::KEY_PREFIX, name))
+            .arg(owner)
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        Ok(())
+    }
+}
+
+/// Выбор лидера для фоновых задач: задачу на каждом тике выполняет только
+/// реплика, удерживающая блокировку с ее именем
+#[derive(Clone)]
+pub struct LeaderElection {
+    manager: Arc<dyn LockManager>,
+    instance_id: String,
+}
+
+impl LeaderElection {
+    pub async fn from_config(config: &CoordinationConfig) -> anyhow::Result<Self> {
+        let manager: Arc<dyn LockManager> = match config.lock_backend {
+            LockBackend::Memory => Arc::new(InMemoryLockManager::default()),
+            #[cfg(feature = "redis")]
+            LockBackend::Redis => {
+                let redis_url = config.redis_url.as_deref()
+                    .ok_or_else(|| anyhow::anyhow!("coordination.redis_url is required for the redis lock backend"))?;
+                Arc::new(RedisLockManager::connect(redis_url).await?)
+            }
+            #[cfg(not(feature = "redis"))]
+            LockBackend::Redis => anyhow::bail!("Redis lock backend requires the `redis` feature"),
+        };
+
+        tracing::info!(instance = %config.instance_id, backend = ?config.lock_backend, "Leader election configured");
+
+        Ok(Self::new(manager, config.instance_id.clone()))
+    }
+
+    pub fn new(manager: Arc<dyn LockManager>, instance_id: String) -> Self {
+        Self { manager, instance_id }
+    }
+
+    /// Является ли эта реплика лидером для задачи (берет/продлевает lease).
+    /// Ошибка хранилища блокировок — не лидер: лучше пропустить тик, чем выполнить дважды
+    pub async fn is_leader(&self, task: &str, ttl: Duration) -> bool {
+        match self.manager.try_acquire(task, &self.instance_id, ttl).await {
+            Ok(leader) => leader,
+            Err(e) => {
+                tracing::warn!(task, error = %e, "Lock backend error");
+                false
+            }
+        }
+    }
+
+    /// Отказаться от лидерства (например, при остановке)
+    pub async fn resign(&self, task: &str) {
+        if let Err(e) = self.manager.release(task, &self.instance_id).await {
+            tracing::warn!(task, error = %e, "Failed to release lock");
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use chrono::{SecondsFormat, Utc};
+use env_logger::filter::Filter;
+use serde_json::{Map, Value};
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::field::{Field, Visit};
+use tracing::span;
+
+use crate::config::LogFormat;
+use crate::observability;
+
+/// Логгер сервера: события `tracing` со структурными полями и записи `log` из зависимостей
+/// пишутся в stderr одним форматом. Уровни — `server.log_filter` (синтаксис env_logger)
+#[derive(Clone)]
+pub struct Logger {
+    filter: Arc<Filter>,
+    format: LogFormat,
+}
+
+impl Logger {
+    pub fn new(format: LogFormat, filters: &str) -> Self {
+        let filter = env_logger::filter::Builder::new().parse(filters).build();
+        Self { filter: Arc::new(filter), format }
+    }
+
+    /// Установить глобальный логгер для `tracing` и `log`
+    pub fn init(format: LogFormat, filters: &str) -> anyhow::Result<()> {
+        let logger = Self::new(format, filters);
+        log::set_max_level(logger.filter.filter());
+        log::set_boxed_logger(Box::new(logger.clone()))?;
+        tracing::subscriber::set_global_default(logger)?;
+        Ok(())
+    }
+
+    /// Строка лога без перевода строки
+    pub fn format_line(&self, level: &str, target: &str, message: &str, fields: &Map<String, Value>) -> String {
+        let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+        match self.format {
+            LogFormat::Text => {
+                let mut line = format!("[{} {:<5} {}] {}", timestamp, level, target, message);
+                for (key, value) in fields {
+                    match value {
+                        Value::String(text) => write!(line, " {}={}", key, text),
+                        other => write!(line, " {}={}", key, other),
+                    }.ok();
+                }
+                line
+            }
+            LogFormat::Json => {
+                let mut object = Map::new();
+                object.insert("timestamp".into(), timestamp.into());
+                object.insert("level".into(), level.into());
+                object.insert("target".into(), target.into());
+                object.insert("message".into(), message.into());
+                for (key, value) in fields {
+                    object.entry(key.clone()).or_insert_with(|| value.clone());
+                }
+                Value::Object(object).to_string()
+            }
+        }
+    }
+
+    fn write(&self, level: &str, target: &str, message: &str, fields: &Map<String, Value>) {
+        let line = self.format_line(level, target, message, fields);
+        let _ = writeln!(std::io::stderr().lock(), "{}", line);
+    }
+}
+
+fn log_level(level: &tracing::Level) -> log::Level {
+    match *level {
+        tracing::Level::ERROR => log::Level::Error,
+        tracing::Level::WARN => log::Level::Warn,
+        tracing::Level::INFO => log::Level::Info,
+        tracing::Level::DEBUG => log::Level::Debug,
+        tracing::Level::TRACE => log::Level::Trace,
+    }
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.filter.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.filter.matches(record) {
+            self.write(record.level().as_str(), record.target(), &record.args().to_string(), &Map::new());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Сообщение и поля события
+#[derive(Default)]
+struct EventFields {
+    message: String,
+    fields: Map<String, Value>,
+}
+
+impl Visit for EventFields {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            self.fields.insert(field.name().to_string(), Value::String(format!("{:?}", value)));
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.fields.insert(field.name().to_string(), value.into());
+        }
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.fields.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.fields.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.fields.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.fields.insert(field.name().to_string(), value.into());
+    }
+}
+
+/// Спаны не используются: учитываются только события
+impl tracing::Subscriber for Logger {
+    fn enabled(&self, metadata: &tracing::Metadata<'_>) -> bool {
+        let metadata = log::Metadata::builder()
+            .level(log_level(metadata.level()))
+            .target(metadata.target())
+            .build();
+        self.filter.enabled(&metadata)
+    }
+
+    fn max_level_hint(&self) -> Option<tracing::level_filters::LevelFilter> {
+        Some(match self.filter.filter() {
+            log::LevelFilter::Off => tracing::level_filters::LevelFilter::OFF,
+            log::LevelFilter::Error => tracing::level_filters::LevelFilter::ERROR,
+            log::LevelFilter::Warn => tracing::level_filters::LevelFilter::WARN,
+            log::LevelFilter::Info => tracing::level_filters::LevelFilter::INFO,
+            log::LevelFilter::Debug => tracing::level_filters::LevelFilter::DEBUG,
+            log::LevelFilter::Trace => tracing::level_filters::LevelFilter::TRACE,
+        })
+    }
+
+    fn new_span(&self, _span: &span::Attributes<'_>) -> span::Id {
+        span::Id::from_u64(1)
+    }
+
+    fn record(&self, _span: &span::Id, _values: &span::Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+    fn event(&self, event: &tracing::Event<'_>) {
+        let mut visitor = EventFields::default();
+        event.record(&mut visitor);
+        if let Some(correlation_id) = observability::correlation_id() {
+            visitor.fields.entry("correlation_id").or_insert(correlation_id.into());
+        }
+        let metadata = event.metadata();
+        self.write(log_level(metadata.level()).as_str(), metadata.target(), &visitor.message, &visitor.fields);
+    }
+
+    fn enter(&self, _span: &span::Id) {}
+
+    fn exit(&self, _span: &span::Id) {}
+}
+
+/// Middleware: строка лога на каждый HTTP запрос с методом, путем, статусом и временем ответа
+pub async fn request_log(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let started = Instant::now();
+    let (method, path) = (req.method().to_string(), req.path().to_string());
+    let response = next.call(req).await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+    match &response {
+        Ok(response) => tracing::info!(
+            method, path, status = response.status().as_u16(), latency_ms, "HTTP request"
+        ),
+        Err(e) => tracing::warn!(method, path, latency_ms, error = %e, "HTTP request failed"),
+    }
+    response
+}
@@ -0,0 +1,299 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, MerchantConfig};
+use crate::display::to_base_units;
+use crate::payment::{Payment, PaymentStatus};
+use crate::storage::StorageService;
+use crate::validation::ValidationErrors;
+
+/// Операция по бонусному счету
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoyaltyEntryKind {
+    /// Начислены за оплаченный платеж
+    Earned,
+    /// Списаны в счет суммы платежа
+    Redeemed,
+    /// Списанные баллы возвращены: платеж не оплачен или возвращен
+    Restored,
+    /// Начисленные баллы сняты: платеж возвращен
+    Reversed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoyaltyEntry {
+    pub payment_id: String,
+    pub kind: LoyaltyEntryKind,
+    pub points: u64,
+    pub at: DateTime<Utc>,
+}
+
+/// Баллы кошелька у мерчанта
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoyaltyAccount {
+    pub merchant_id: String,
+    pub wallet: String,
+    pub balance: u64,
+    pub earned: u64,
+    pub redeemed: u64,
+    pub entries: Vec<LoyaltyEntry>,
+}
+
+impl LoyaltyAccount {
+    pub fn new(merchant_id: &str, wallet: &str) -> Self {
+        Self {
+            merchant_id: merchant_id.to_string(),
+            wallet: wallet.to_string(),
+            balance: 0,
+            earned: 0,
+            redeemed: 0,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Провести операцию. Операция одного вида по одному платежу проводится один раз
+    pub fn apply(&mut self, mut entry: LoyaltyEntry) -> Result<(), LoyaltyError> {
+        if self.entry(&entry.payment_id, entry.kind).is_some() {
+            return Err(LoyaltyError::Duplicate);
+        }
+        match entry.kind {
+            LoyaltyEntryKind::Earned => {
+                self.balance += entry.points;
+                self.earned += entry.points;
+            }
+            LoyaltyEntryKind::Redeemed => {
+                if self.balance < entry.points {
+                    return Err(LoyaltyError::Insufficient(self.balance));
+                }
+                self.balance -= entry.points;
+                self.redeemed += entry.points;
+            }
+            LoyaltyEntryKind::Restored => {
+                self.balance += entry.points;
+                self.redeemed = self.redeemed.saturating_sub(entry.points);
+            }
+            LoyaltyEntryKind::Reversed => {
+                // Часть баллов уже могла быть потрачена — снимается остаток
+                entry.points = entry.points.min(self.balance);
+                self.balance -= entry.points;
+                self.earned = self.earned.saturating_sub(entry.points);
+            }
+        }
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    pub fn entry(&self, payment_id: &str, kind: LoyaltyEntryKind) -> Option<&LoyaltyEntry> {
+        self.entries.iter().find(|entry| entry.payment_id == payment_id && entry.kind == kind)
+    }
+}
+
+/// Оплата части платежа баллами
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoyaltyRedemption {
+    pub wallet: String,
+    pub points: u64,
+    /// Сколько оплачено баллами; `amount` платежа уже за ее вычетом
+    pub amount: f64,
+    pub amount_base_units: u64,
+}
+
+/// Запрос на оплату баллами при создании платежа
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RedeemPointsRequest {
+    /// Кошелек покупателя, на котором копятся баллы
+    pub wallet: String,
+    pub points: u64,
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum LoyaltyError {
+    #[error("Merchant does not accept loyalty points for this token")]
+    NotEnabled,
+    #[error("Not enough points, balance: {0}")]
+    Insufficient(u64),
+    #[error("Points exceed the share of the payment that can be paid with points")]
+    ExceedsLimit,
+    #[error("Loyalty operation is already recorded for this payment")]
+    Duplicate,
+}
+
+impl LoyaltyError {
+    /// Код ошибки поля `redeem_points` в ответе на создание платежа
+    pub fn code(&self) -> &'static str {
+        match self {
+            LoyaltyError::NotEnabled => "not_enabled",
+            LoyaltyError::Insufficient(_) => "insufficient",
+            LoyaltyError::ExceedsLimit => "exceeds_limit",
+            LoyaltyError::Duplicate => "duplicate",
+        }
+    }
+
+    fn into_validation(self) -> ValidationErrors {
+        let mut errors = ValidationErrors::new();
+        errors.add("redeem_points", self.code(), self.to_string());
+        errors
+    }
+}
+
+/// Бонусные баллы: начисление за оплаченные платежи, списание в счет новых платежей
+/// и возврат при отмене или возврате платежа
+#[derive(Debug, Clone)]
+pub struct LoyaltyService {
+    storage: StorageService,
+    config: Config,
+}
+
+impl LoyaltyService {
+    pub fn new(config: Config, storage: StorageService) -> Self {
+        Self { storage, config }
+    }
+
+    /// Баллы кошелька у всех мерчантов или у одного
+    pub async fn balances(&self, wallet: &str, merchant_id: Option<&str>) -> anyhow::Result<Vec<LoyaltyAccount>> {
+        let mut accounts = self.storage.loyalty_accounts(wallet).await?;
+        accounts.retain(|account| merchant_id.is_none_or(|id| account.merchant_id == id));
+        accounts.sort_by(|a, b| a.merchant_id.cmp(&b.merchant_id));
+        Ok(accounts)
+    }
+
+    /// Рассчитать оплату баллами нового платежа на `base_units` (баллы еще не списываются).
+    /// Ошибки — `ValidationErrors` поля `redeem_points`
+    pub async fn quote(
+        &self,
+        request: &RedeemPointsRequest,
+        merchant: Option<&MerchantConfig>,
+        token: &str,
+        base_units: u64,
+    ) -> anyhow::Result<LoyaltyRedemption> {
+        let mut errors = ValidationErrors::new();
+        errors.check_pubkey("redeem_points.wallet", &request.wallet);
+        if request.points == 0 {
+            errors.add("redeem_points.points", "out_of_range", "points must be at least 1");
+        }
+        errors.into_result()?;
+
+        let (loyalty, value) = merchant
+            .and_then(|merchant| merchant.loyalty.as_ref())
+            .and_then(|loyalty| Some((loyalty, *loyalty.redeem_value.get(token)?)))
+            .ok_or_else(|| LoyaltyError::NotEnabled.into_validation())?;
+        let balance = match &merchant {
+            Some(merchant) => self.storage.get_loyalty_account(&merchant.id, &request.wallet).await?
+                .map_or(0, |account| account.balance),
+            None => 0,
+        };
+        if balance < request.points {
+            return Err(LoyaltyError::Insufficient(balance).into_validation().into());
+        }
+
+        let decimals = self.config.token_decimals(token);
+        let amount_base_units = to_base_units(request.points as f64 * value, decimals);
+        let max_base_units = (base_units as u128 * loyalty.max_redeem_bps as u128 / 10_000) as u64;
+        if amount_base_units > max_base_units {
+            return Err(LoyaltyError::ExceedsLimit.into_validation().into());
+        }
+        Ok(LoyaltyRedemption {
+            wallet: request.wallet.clone(),
+            points: request.points,
+            amount: amount_base_units as f64 / 10_f64.powi(decimals as i32),
+            amount_base_units,
+        })
+    }
+
+    /// Списать баллы за платеж. Баланс проверяется повторно под блокировкой
+    pub async fn redeem(&self, redemption: &LoyaltyRedemption, payment_id: &str, merchant_id: &str) -> anyhow::Result<()> {
+        let entry = LoyaltyEntry {
+            payment_id: payment_id.to_string(),
+            kind: LoyaltyEntryKind::Redeemed,
+            points: redemption.points,
+            at: Utc::now(),
+        };
+        self.storage.apply_loyalty_entry(merchant_id, &redemption.wallet, entry).await?
+            .map_err(LoyaltyError::into_validation)?;
+        Ok(())
+    }
+
+    /// Вернуть баллы, если платеж после `redeem` не удалось создать
+    pub async fn release(&self, redemption: &LoyaltyRedemption, payment_id: &str, merchant_id: &str) -> anyhow::Result<()> {
+        let entry = LoyaltyEntry {
+            payment_id: payment_id.to_string(),
+            kind: LoyaltyEntryKind::Restored,
+            points: redemption.points,
+            at: Utc::now(),
+        };
+        self.storage.apply_loyalty_entry(merchant_id, &redemption.wallet, entry).await?
+            .map_err(LoyaltyError::into_validation)?;
+        tracing::info!(payment_id, merchant_id, wallet = %redemption.wallet, points = redemption.points, "Loyalty redemption released");
+        Ok(())
+    }
+
+    /// Начислить, вернуть или снять баллы после смены статуса платежа
+    pub async fn on_status_change(&self, payment: &Payment) -> anyhow::Result<()> {
+        let Some(merchant_id) = payment.merchant_id.as_deref() else {
+            return Ok(());
+        };
+        // Начисление — на кошелек, с которого пришла оплата
+        let earner = payment.payer.as_deref()
+            .or(payment.loyalty.as_ref().map(|redemption| redemption.wallet.as_str()));
+
+        match payment.status {
+            // Sandbox платежи баллов не приносят
+            PaymentStatus::Completed if !payment.sandbox => {
+                let rate = self.config.find_merchant(merchant_id)
+                    .and_then(|merchant| merchant.loyalty.as_ref()?.earn_rate.get(&payment.token).copied());
+                let (Some(rate), Some(wallet)) = (rate, earner) else {
+                    return Ok(());
+                };
+                let points = (payment.amount * rate).floor() as u64;
+                if points > 0 {
+                    self.record(merchant_id, wallet, payment, LoyaltyEntryKind::Earned, points).await?;
+                }
+            }
+            PaymentStatus::Refunded => {
+                if let Some(wallet) = earner {
+                    let earned = self.storage.get_loyalty_account(merchant_id, wallet).await?
+                        .and_then(|account| account.entry(&payment.id, LoyaltyEntryKind::Earned).map(|e| e.points));
+                    if let Some(points) = earned {
+                        self.record(merchant_id, wallet, payment, LoyaltyEntryKind::Reversed, points).await?;
+                    }
+                }
+                self.restore(merchant_id, payment).await?;
+            }
+            PaymentStatus::Expired | PaymentStatus::Cancelled | PaymentStatus::Failed | PaymentStatus::Released => {
+                self.restore(merchant_id, payment).await?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Вернуть баллы, списанные в счет платежа
+    async fn restore(&self, merchant_id: &str, payment: &Payment) -> anyhow::Result<()> {
+        if let Some(redemption) = &payment.loyalty {
+            self.record(merchant_id, &redemption.wallet, payment, LoyaltyEntryKind::Restored, redemption.points).await?;
+        }
+        Ok(())
+    }
+
+    async fn record(
+        &self,
+        merchant_id: &str,
+        wallet: &str,
+        payment: &Payment,
+        kind: LoyaltyEntryKind,
+        points: u64,
+    ) -> anyhow::Result<()> {
+        let entry = LoyaltyEntry { payment_id: payment.id.clone(), kind, points, at: Utc::now() };
+        match self.storage.apply_loyalty_entry(merchant_id, wallet, entry).await? {
+            Ok(account) => {
+                tracing::info!(payment_id = %payment.id, merchant_id, wallet, ?kind, points, balance = account.balance, "Loyalty points updated");
+            }
+            // Повторная смена статуса (например, сверка) не дублирует операцию
+            Err(LoyaltyError::Duplicate) => {}
+            Err(e) => return Err(e.into()),
+        }
+        Ok(())
+    }
+}
@@ -1,25 +1,78 @@
 use actix_cors::Cors;
-use actix_web::{web, App, HttpResponse, HttpServer, Result, middleware::Logger};
+use actix_web::http::header;
+use actix_web::{http::KeepAlive, web, App, HttpRequest, HttpResponse, HttpServer, Result, middleware::from_fn};
 use serde::{Deserialize, Serialize};
-use solana_sdk::{
-    transaction::Transaction,
-    pubkey::Pubkey,
-    system_instruction,
-    message::Message,
+use std::collections::BTreeMap;
+use tokio::time::Duration;
+
+use crypto_server::acme::{acme_challenge, AcmeChallenges, AcmeService};
+use crypto_server::actions::{self, ActionGetResponse, ActionPostRequest};
+use crypto_server::auth::{authorize, require_admin, require_merchant, resolve_merchant, AuthError, ADMIN_KEY_HEADER};
+use crypto_server::body_limits::{json_guard, JsonLimits};
+use crypto_server::cache::{not_modified, payment_etag};
+use crypto_server::config::{Config, MerchantConfig};
+use crypto_server::deadline::{Deadline, DeadlineError};
+use crypto_server::discounts::{CreateDiscountRequest, DiscountError};
+use crypto_server::disputes::{
+    DisputeError, DisputeFilter, DisputeService, DisputeStatus, OpenDisputeRequest, ResolveDisputeRequest,
+};
+use crypto_server::error_reporting::{self, ErrorEvent, ErrorKind};
+use crypto_server::escrow::{EscrowError, EscrowService};
+use crypto_server::events;
+use crypto_server::export::{self, ExportRange};
+use crypto_server::graphql::{build_schema, DashboardSchema, Viewer};
+use crypto_server::grpc::GrpcPayments;
+use crypto_server::i18n::Locale;
+use crypto_server::jobs::{JobOutcome, JobScheduler};
+use crypto_server::limits::{LimitError, LimitOverrideRequest};
+use crypto_server::lock::LeaderElection;
+use crypto_server::logging::{request_log, Logger};
+use crypto_server::observability::{observe, Observability, CORRELATION_ID_HEADER};
+use crypto_server::notifications::NotificationService;
+use crypto_server::outbox::OutboxDispatcher;
+use crypto_server::ownership::OwnershipProofRequest;
+use crypto_server::payload_signer::{PayloadSigner, PAYLOAD_SIGNATURE_HEADER, SIGNING_KEY_HEADER};
+use crypto_server::payment::{
+    select_fields, Payment, PaymentListFilter, PaymentService, PaymentStatus, CreatePaymentRequest, PaymentResponse, PayerNotAllowed, SandboxError,
+    PaymentStatusSummary, RequestTokenError, TransitionError, MAX_STATUS_BATCH,
 };
-use spl_token::instruction as token_instruction;
-use std::str::FromStr;
-use base64::{Engine as _, engine::general_purpose};
-use tokio::time::{timeout, Duration};
+use crypto_server::pools::{CreatePoolRequest, PoolError, PoolService};
+use crypto_server::pos::{CreatePosSessionRequest, PosChargeRequest, PosError, PosService};
+use crypto_server::qr::QrKind;
+use crypto_server::rate_limit::{rate_limit, RateLimiter};
+use crypto_server::receipt::Receipt;
+use crypto_server::receipt_nft::{ReceiptNftMetadata, ReceiptNftService};
+use crypto_server::reconcile::{ReconciliationBusy, ReconciliationService};
+use crypto_server::registration::{RegisterMerchantRequest, RegistrationError, RegistrationService};
+use crypto_server::replica::ReadConsistency;
+use crypto_server::retention::RetentionService;
+use crypto_server::rpc::transaction_commitment;
+use crypto_server::screening::ScreeningError;
+use crypto_server::settlement::SettlementService;
+use crypto_server::signer::ServerSigner;
+use crypto_server::snapshot::SnapshotService;
+use crypto_server::sponsorship::SponsorshipService;
+use crypto_server::sse;
+use crypto_server::static_qr::{CreateStaticQrRequest, StaticQrError, StaticQrService};
+use crypto_server::storage::StorageSnapshot;
+use crypto_server::sweep::SweepService;
+use crypto_server::tenants::{CreateApiKeyRequest, TenantError};
+use crypto_server::tls::{load_certified_key, redirect_to_https, server_config, CertResolver, HttpsRedirect};
+use crypto_server::tokens::{AddTokenRequest, TokenError};
+use crypto_server::swap::SwapInstructions;
+use crypto_server::transaction::{
+    create_payment_transaction, create_sponsored_payment_transaction, create_swap_payment_transaction,
+    preview_payment_transaction, RefreshError, TransactionPart,
+};
+use crypto_server::validation::{Validate, ValidationErrors};
+use crypto_server::watcher::PaymentWatcher;
+use crypto_server::webhook::{DeliveryStatus, RedeliverRequest, RegisterWebhookRequest, WebhookError, WebhookService};
 
-mod config;
-mod multichain;
-mod payment;
-mod qr;
-mod storage;
+/// Размер фрагмента при потоковой отдаче PNG с QR кодом
+const QR_STREAM_CHUNK: usize = 16 * 1024;
 
-use config::Config;
-use payment::{PaymentService, CreatePaymentRequest, PaymentResponse};
+/// Срок сборки транзакции вместе с ожиданием в очереди сборок, секунды
+const TRANSACTION_DEADLINE_SECS: u64 = 20;
 
 #[derive(Serialize)]
 struct ServerInfo {
@@ -29,9 +82,97 @@ struct ServerInfo {
     supported_networks: Vec<String>,
 }
 
+// Без deny_unknown_fields: кошельки Solana Pay могут присылать дополнительные поля
 #[derive(Deserialize)]
 struct TransactionRequestPost {
     account: String,
+    /// Чаевые в базисных пунктах (одно из tip_presets_bps платежа)
+    #[serde(default)]
+    tip_bps: Option<u16>,
+    /// Сумма intent платежа в пределах его amount_range
+    #[serde(default)]
+    amount: Option<f64>,
+}
+
+impl Validate for TransactionRequestPost {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        errors.check_pubkey("account", &self.account);
+        errors.into_result()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SubmitTransactionRequest {
+    /// Транзакция, подписанная кошельком (base64)
+    transaction: String,
+}
+
+impl Validate for SubmitTransactionRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        if self.transaction.trim().is_empty() {
+            errors.add("transaction", "required", "Signed transaction is required");
+        }
+        errors.into_result()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DecodeTransactionRequest {
+    /// Транзакция кошелька (base64), подписанная или нет
+    transaction: String,
+    /// Сверить с транзакцией, выданной для этого платежа
+    payment_id: Option<String>,
+}
+
+impl Validate for DecodeTransactionRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        if self.transaction.trim().is_empty() {
+            errors.add("transaction", "required", "Transaction is required");
+        }
+        errors.into_result()
+    }
+}
+
+#[derive(Deserialize)]
+struct TransactionQuery {
+    /// Часть платежа при раздельной оплате (main/fee), по умолчанию — все сразу
+    #[serde(default)]
+    part: TransactionPart,
+    /// Чаевые можно передать и в URL (приоритетнее тела запроса)
+    tip_bps: Option<u16>,
+    /// Сумма intent платежа, тоже приоритетнее тела запроса
+    amount: Option<f64>,
+    /// Язык сообщения для кошелька (иначе — язык платежа)
+    locale: Option<String>,
+    /// Оплатить другим токеном (символ или mint): своп Jupiter в токен платежа
+    pay_with: Option<String>,
+    /// Одноразовый токен из transaction request URL
+    t: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RequestTokenQuery {
+    /// Одноразовый токен из transaction request URL
+    t: Option<String>,
+}
+
+// Ответ на неверный или уже закрепленный за другим кошельком токен transaction request.
+// Без верного токена ответ как для несуществующего платежа — ID не подобрать перебором
+fn request_token_rejection(e: &anyhow::Error) -> Option<HttpResponse> {
+    let response = match e.downcast_ref::<RequestTokenError>()? {
+        RequestTokenError::Invalid => HttpResponse::NotFound()
+            .append_header(("Access-Control-Allow-Origin", "*"))
+            .json(serde_json::json!({"error": "Payment not found"})),
+        RequestTokenError::Claimed => HttpResponse::Conflict()
+            .append_header(("Access-Control-Allow-Origin", "*"))
+            .json(serde_json::json!({"error": e.to_string(), "code": "request_token_claimed"})),
+    };
+    Some(response)
 }
 
 #[derive(Serialize)]
@@ -46,6 +187,67 @@ struct TransactionResponse {
     message: Option<String>,
 }
 
+// Ответ 422 со списком ошибок по полям
+fn validation_failed(errors: &ValidationErrors) -> HttpResponse {
+    HttpResponse::UnprocessableEntity().json(serde_json::json!({
+        "success": false,
+        "error": "Validation failed",
+        "errors": errors.errors(),
+    }))
+}
+
+// Ответ на отказ риск-контроля: адрес отклонен проверкой (denylist, санкции) — 403,
+// исчерпан лимит числа платежей мерчанта — 429, лимит объема — 403
+fn risk_rejection(e: &anyhow::Error) -> Option<HttpResponse> {
+    if let Some(ScreeningError::Blocked { address, reason }) = e.downcast_ref::<ScreeningError>() {
+        return Some(HttpResponse::Forbidden()
+            .append_header(("Access-Control-Allow-Origin", "*"))
+            .json(serde_json::json!({
+                "success": false,
+                "error": e.to_string(),
+                "address": address,
+                "reason": reason,
+            })));
+    }
+    let body = serde_json::json!({"success": false, "error": e.to_string()});
+    match e.downcast_ref::<LimitError>()? {
+        LimitError::TooManyPayments { retry_after_secs, .. } => Some(HttpResponse::TooManyRequests()
+            .append_header(("Retry-After", retry_after_secs.to_string()))
+            .json(body)),
+        LimitError::VolumeExceeded { .. } => Some(HttpResponse::Forbidden().json(body)),
+        LimitError::MerchantNotFound => Some(HttpResponse::NotFound().json(body)),
+    }
+}
+
+// Отделенная подпись `data` ответа ключом сервера: POS приложения проверяют, что
+// данные платежа не изменены посредниками
+fn sign_payload<T: Serialize>(response: &mut actix_web::HttpResponseBuilder, signer: Option<&PayloadSigner>, data: &T) {
+    let Some(signer) = signer else {
+        return;
+    };
+    match signer.sign(data) {
+        Ok(signature) => {
+            response
+                .insert_header((PAYLOAD_SIGNATURE_HEADER, signature))
+                .insert_header((SIGNING_KEY_HEADER, signer.public_key()));
+        }
+        Err(e) => tracing::warn!(error = %e, "Failed to sign response payload"),
+    }
+}
+
+// GET: Публичный ключ подписи данных платежей; без `server.signing_key_path` — 404
+async fn public_key(payment_service: web::Data<PaymentService>) -> HttpResponse {
+    match payment_service.payload_signer() {
+        Some(signer) => HttpResponse::Ok().json(serde_json::json!({
+            "algorithm": "ed25519",
+            "encoding": "base58",
+            "public_key": signer.public_key(),
+            "signature_header": PAYLOAD_SIGNATURE_HEADER,
+        })),
+        None => HttpResponse::NotFound().json(serde_json::json!({"error": "Payload signing is not configured"})),
+    }
+}
+
 // Главная страница API
 async fn index() -> Result<HttpResponse> {
     let info = ServerInfo {
@@ -60,21 +262,34 @@ async fn index() -> Result<HttpResponse> {
 // Создать платеж с комиссией
 async fn create_payment(
     payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
     req: web::Json<CreatePaymentRequest>,
 ) -> Result<HttpResponse> {
-    log::info!("Creating payment: {:?}", req);
+    let merchant = resolve_merchant(&http_req, payment_service.config())?;
+    tracing::debug!(
+        merchant = merchant.as_ref().map(|m| m.id.as_str()), amount = req.amount, token = %req.token, "Creating payment"
+    );
 
-    match payment_service.create_payment_with_fee(req.into_inner()).await {
+    let created = payment_service.create_payment_with_fee(req.into_inner(), merchant.as_deref()).await
+        .and_then(|payment| payment_service.with_qr_code(payment));
+    match created {
         Ok(payment) => {
-            log::info!("Payment created successfully: {}", payment.id);
-            Ok(HttpResponse::Ok().json(PaymentResponse {
+            let mut response = HttpResponse::Ok();
+            sign_payload(&mut response, payment_service.payload_signer(), &payment);
+            Ok(response.json(PaymentResponse {
                 success: true,
                 data: Some(payment),
                 error: None,
             }))
         }
         Err(e) => {
-            log::error!("Payment creation failed: {}", e);
+            tracing::warn!(error = %e, "Payment creation failed");
+            if let Some(errors) = e.downcast_ref::<ValidationErrors>() {
+                return Ok(validation_failed(errors));
+            }
+            if let Some(response) = risk_rejection(&e) {
+                return Ok(response);
+            }
             Ok(HttpResponse::BadRequest().json(PaymentResponse {
                 success: false,
                 data: None,
@@ -88,11 +303,15 @@ async fn create_payment(
 async fn transaction_get(
     payment_service: web::Data<PaymentService>,
     path: web::Path<String>,
+    query: web::Query<RequestTokenQuery>,
 ) -> Result<HttpResponse> {
     let payment_id = path.into_inner();
-    log::info!("GET transaction metadata for payment: {}", payment_id);
+    tracing::debug!(payment_id = %payment_id, "Transaction request metadata");
 
     match payment_service.get_payment(&payment_id).await {
+        Ok(Some(payment)) if payment.check_request_token(query.t.as_deref()).is_err() => {
+            Ok(HttpResponse::NotFound().json(serde_json::json!({"error": "Payment not found"})))
+        }
         Ok(Some(payment)) => {
             Ok(HttpResponse::Ok()
                 .append_header(("Content-Type", "application/json"))
@@ -100,10 +319,9 @@ async fn transaction_get(
                 .append_header(("Access-Control-Allow-Methods", "GET, POST, OPTIONS"))
                 .append_header(("Access-Control-Allow-Headers", "Content-Type"))
                 .json(TransactionRequestGet {
-                    label: format!("Pay {} {} + {} {} fee",
-                                   payment.amount, payment.token,
-                                   payment.fee_amount, payment.fee_token),
-                    icon: "https://solana.com/src/img/branding/solanaLogoMark.svg".to_string(),
+                    label: payment.locale.transaction_message(&payment, TransactionPart::Full),
+                    icon: payment_service.config().merchant_icon(payment.merchant_id.as_deref())
+                        .unwrap_or_else(|| actions::DEFAULT_ICON.to_string()),
                 }))
         }
         Ok(None) => Ok(HttpResponse::NotFound().json(serde_json::json!({"error": "Payment not found"}))),
@@ -111,34 +329,156 @@ async fn transaction_get(
     }
 }
 
+// Заголовки Solana Actions: CORS для любого сайта, версия спецификации и кластер
+fn with_action_headers(mut response: HttpResponse, config: &Config) -> HttpResponse {
+    let headers = response.headers_mut();
+    for (name, value) in actions::CORS_HEADERS {
+        headers.insert(header::HeaderName::from_static(name), header::HeaderValue::from_static(value));
+    }
+    headers.insert(
+        header::HeaderName::from_static("x-action-version"),
+        header::HeaderValue::from_static(actions::ACTION_VERSION),
+    );
+    headers.insert(
+        header::HeaderName::from_static("x-blockchain-ids"),
+        header::HeaderValue::from_static(actions::blockchain_id(config.solana.cluster)),
+    );
+    response
+}
+
+// GET /actions.json: какие ссылки домена клиенты Blinks раскрывают в actions
+async fn actions_json(payment_service: web::Data<PaymentService>) -> HttpResponse {
+    with_action_headers(HttpResponse::Ok().json(actions::actions_json()), payment_service.config())
+}
+
+// GET: Solana Action платежа — карточка с кнопками оплаты и чаевых
+async fn action_get(
+    payment_service: web::Data<PaymentService>,
+    path: web::Path<String>,
+    query: web::Query<RequestTokenQuery>,
+) -> Result<HttpResponse> {
+    let config = payment_service.config();
+    let response = match payment_service.get_payment(&path.into_inner()).await {
+        Ok(Some(payment)) if payment.check_request_token(query.t.as_deref()).is_err() => {
+            HttpResponse::NotFound().json(serde_json::json!({"message": "Payment not found"}))
+        }
+        Ok(Some(payment)) => HttpResponse::Ok().json(ActionGetResponse::for_payment(&payment, config)),
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({"message": "Payment not found"})),
+        Err(e) => HttpResponse::InternalServerError().json(serde_json::json!({"message": e.to_string()})),
+    };
+    Ok(with_action_headers(response, config))
+}
+
+#[derive(Deserialize)]
+struct ActionQuery {
+    tip_bps: Option<u16>,
+    amount: Option<f64>,
+    t: Option<String>,
+}
+
+// POST: Транзакция Solana Action — тот же transaction request, что и у Solana Pay.
+// Ответ приводится к спецификации Actions: `type` у транзакции, `message` у ошибки
+async fn action_post(
+    payment_service: web::Data<PaymentService>,
+    sponsorship_service: web::Data<SponsorshipService>,
+    path: web::Path<String>,
+    query: web::Query<ActionQuery>,
+    req: web::Json<ActionPostRequest>,
+) -> Result<HttpResponse> {
+    let query = web::Query(TransactionQuery {
+        part: TransactionPart::Full,
+        tip_bps: query.tip_bps,
+        amount: query.amount,
+        locale: None,
+        pay_with: None,
+        t: query.into_inner().t,
+    });
+    let request = web::Json(TransactionRequestPost { account: req.into_inner().account, tip_bps: None, amount: None });
+    let response = transaction_post(payment_service.clone(), sponsorship_service, path, query, request).await?;
+
+    let status = response.status();
+    let retry_after = response.headers().get(header::RETRY_AFTER).cloned();
+    let body = actix_web::body::to_bytes(response.into_body()).await.unwrap_or_default();
+    let mut body: serde_json::Value = serde_json::from_slice(&body).unwrap_or_default();
+    let body = if status.is_success() {
+        body["type"] = "transaction".into();
+        body
+    } else {
+        let message = body.get("error").and_then(|e| e.as_str()).unwrap_or("Transaction request failed");
+        serde_json::json!({"message": message})
+    };
+    let mut response = HttpResponse::build(status);
+    if let Some(retry_after) = retry_after {
+        response.insert_header((header::RETRY_AFTER, retry_after));
+    }
+    Ok(with_action_headers(response.json(body), payment_service.config()))
+}
+
+// Короткая ссылка `/t/{code}` из QR кода: тот же transaction request, что и по ID
+// платежа. Запрос обслуживается на месте, а не редиректом — не все кошельки
+// повторяют POST по редиректу
+async fn short_link_payment_id(payment_service: &PaymentService, code: &str) -> Result<String, HttpResponse> {
+    match payment_service.get_payment_by_short_code(code).await {
+        Ok(Some(payment)) => Ok(payment.id),
+        Ok(None) => Err(HttpResponse::NotFound().json(serde_json::json!({"error": "Payment not found"}))),
+        Err(e) => Err(HttpResponse::InternalServerError().json(serde_json::json!({"error": e.to_string()}))),
+    }
+}
+
+async fn short_link_get(
+    payment_service: web::Data<PaymentService>,
+    path: web::Path<String>,
+    query: web::Query<RequestTokenQuery>,
+) -> Result<HttpResponse> {
+    match short_link_payment_id(&payment_service, &path).await {
+        Ok(payment_id) => transaction_get(payment_service, web::Path::from(payment_id), query).await,
+        Err(response) => Ok(response),
+    }
+}
+
+async fn short_link_post(
+    payment_service: web::Data<PaymentService>,
+    sponsorship_service: web::Data<SponsorshipService>,
+    path: web::Path<String>,
+    query: web::Query<TransactionQuery>,
+    req: web::Json<TransactionRequestPost>,
+) -> Result<HttpResponse> {
+    match short_link_payment_id(&payment_service, &path).await {
+        Ok(payment_id) => {
+            transaction_post(payment_service, sponsorship_service, web::Path::from(payment_id), query, req).await
+        }
+        Err(response) => Ok(response),
+    }
+}
+
 // POST: Создание транзакции для Solana Pay
 async fn transaction_post(
     payment_service: web::Data<PaymentService>,
+    sponsorship_service: web::Data<SponsorshipService>,
     path: web::Path<String>,
+    query: web::Query<TransactionQuery>,
     req: web::Json<TransactionRequestPost>,
 ) -> Result<HttpResponse> {
     let payment_id = path.into_inner();
+    if let Err(errors) = req.validate() {
+        return Ok(validation_failed(&errors));
+    }
     let account = req.account.clone();
 
-    log::info!("🚀 POST /api/payment/{}/transaction", payment_id);
-    log::info!("📋 Request account: {}", account);
+    tracing::info!(payment_id = %payment_id, %account, "Transaction requested");
 
     // Получаем платеж
     let payment = match payment_service.get_payment(&payment_id).await {
-        Ok(Some(payment)) => {
-            log::info!("✅ Payment found: {} {} + {} {} fee",
-                payment.amount, payment.token, payment.fee_amount, payment.fee_token);
-            payment
-        },
+        Ok(Some(payment)) => payment,
         Ok(None) => {
-            log::warn!("❌ Payment not found: {}", payment_id);
+            tracing::warn!(payment_id = %payment_id, "Payment not found");
             return Ok(HttpResponse::NotFound()
                 .append_header(("Content-Type", "application/json"))
                 .append_header(("Access-Control-Allow-Origin", "*"))
                 .json(serde_json::json!({"error": "Payment not found"})));
         }
         Err(e) => {
-            log::error!("❌ Database error: {}", e);
+            tracing::error!(payment_id = %payment_id, error = %e, "Storage error");
             return Ok(HttpResponse::InternalServerError()
                 .append_header(("Content-Type", "application/json"))
                 .append_header(("Access-Control-Allow-Origin", "*"))
@@ -146,323 +486,2835 @@ async fn transaction_post(
         }
     };
 
-    // Создаем транзакцию с расширенными таймаутами
-    log::info!("🔧 Creating transaction...");
-    match timeout(Duration::from_secs(20), create_payment_transaction(&payment, &account)).await {
-        Ok(Ok(transaction_base64)) => {
-            log::info!("✅ Transaction created successfully for payment {}", payment_id);
-            log::info!("📦 Transaction size: {} bytes", transaction_base64.len());
+    // Счет для определенных плательщиков (allowed_payers): другим кошелькам транзакция не выдается
+    if !payment.allows_payer(&account) {
+        tracing::warn!(payment_id = %payment_id, %account, "Transaction requested by a payer outside allowed_payers");
+        return Ok(HttpResponse::Forbidden()
+            .append_header(("Access-Control-Allow-Origin", "*"))
+            .json(serde_json::json!({
+                "error": PayerNotAllowed(account).to_string(),
+                "code": "payer_not_allowed",
+            })));
+    }
 
-            Ok(HttpResponse::Ok()
-                .append_header(("Content-Type", "application/json"))
+    // Без токена из URL транзакция не выдается; первый кошелек закрепляет токен за собой
+    let payment = match payment_service.claim_request_token(payment, query.t.as_deref(), &account).await {
+        Ok(payment) => payment,
+        Err(e) => {
+            if let Some(response) = request_token_rejection(&e) {
+                tracing::warn!(payment_id = %payment_id, %account, error = %e, "Transaction request token rejected");
+                return Ok(response);
+            }
+            return Ok(HttpResponse::InternalServerError()
                 .append_header(("Access-Control-Allow-Origin", "*"))
-                .append_header(("Access-Control-Allow-Methods", "GET, POST, OPTIONS"))
-                .append_header(("Access-Control-Allow-Headers", "Content-Type"))
-                .json(TransactionResponse {
-                    transaction: transaction_base64,
-                    message: Some(format!("Pay {} {} + {} {} fee",
-                                          payment.amount, payment.token,
-                                          payment.fee_amount, payment.fee_token)),
-                }))
+                .json(serde_json::json!({"error": e.to_string()})));
         }
-        Ok(Err(e)) => {
-            log::error!("❌ Transaction creation failed for payment {}: {}", payment_id, e);
-            Ok(HttpResponse::BadRequest()
-                .append_header(("Content-Type", "application/json"))
+    };
+
+    // Плательщик из denylist или санкционного списка не получает транзакцию
+    let payment = match payment_service.screen_payer(payment, &account).await {
+        Ok(payment) => payment,
+        Err(e) => {
+            if let Some(response) = risk_rejection(&e) {
+                return Ok(response);
+            }
+            return Ok(HttpResponse::InternalServerError()
                 .append_header(("Access-Control-Allow-Origin", "*"))
-                .json(serde_json::json!({
-                    "error": format!("Transaction creation failed: {}", e),
-                    "payment_id": payment_id,
-                    "details": "Check server logs for more information"
-                })))
+                .json(serde_json::json!({"error": e.to_string()})));
         }
-        Err(_) => {
-            log::error!("❌ Transaction creation timed out for payment {}", payment_id);
-            Ok(HttpResponse::RequestTimeout()
-                .append_header(("Content-Type", "application/json"))
+    };
+
+    // Сумма intent платежа, выбранная плательщиком, фиксируется на платеже
+    let payment = match payment_service.apply_amount(payment, query.amount.or(req.amount)).await {
+        Ok(payment) => payment,
+        Err(e) => {
+            if let Some(errors) = e.downcast_ref::<ValidationErrors>() {
+                return Ok(validation_failed(errors));
+            }
+            return Ok(HttpResponse::InternalServerError()
                 .append_header(("Access-Control-Allow-Origin", "*"))
-                .json(serde_json::json!({
-                    "error": "Transaction creation timed out",
-                    "payment_id": payment_id,
-                    "timeout": "20 seconds"
-                })))
+                .json(serde_json::json!({"error": e.to_string()})));
         }
-    }
-}
-
-// ПРАВИЛЬНАЯ функция создания транзакции с двумя переводами
-// ПРАВИЛЬНАЯ функция создания ОДНОЙ транзакции с несколькими инструкциями
-async fn create_payment_transaction(
-    payment: &payment::Payment,
-    payer_str: &str,
-) -> anyhow::Result<String> {
-    log::info!("🔧 Starting single transaction creation with multiple instructions...");
+    };
 
-    let payer = Pubkey::from_str(payer_str)
-        .map_err(|e| anyhow::anyhow!("Invalid payer address: {}", e))?;
-    let recipient = Pubkey::from_str(&payment.recipient)
-        .map_err(|e| anyhow::anyhow!("Invalid recipient address: {}", e))?;
-    let fee_recipient = Pubkey::from_str(&payment.fee_recipient)
-        .map_err(|e| anyhow::anyhow!("Invalid fee recipient address: {}", e))?;
+    // Чаевые, выбранные плательщиком, фиксируются на платеже
+    let payment = match payment_service.apply_tip(payment, query.tip_bps.or(req.tip_bps)).await {
+        Ok(payment) => payment,
+        Err(e) => {
+            if let Some(errors) = e.downcast_ref::<ValidationErrors>() {
+                return Ok(validation_failed(errors));
+            }
+            return Ok(HttpResponse::InternalServerError()
+                .append_header(("Access-Control-Allow-Origin", "*"))
+                .json(serde_json::json!({"error": e.to_string()})));
+        }
+    };
 
-    log::info!("✅ Addresses parsed successfully");
-    log::info!("   Payer: {}", payer);
-    log::info!("   Recipient: {}", recipient);
-    log::info!("   Fee recipient: {}", fee_recipient);
+    // Неизвестный язык в URL не ломает оплату — используем язык платежа
+    let locale = query.locale.as_deref()
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(payment.locale);
 
-    let mut instructions = Vec::new();
+    // Оплата другим токеном: своп в той же транзакции, только целиком
+    let (payment, swap) = match &query.pay_with {
+        Some(pay_with) => {
+            if query.part != TransactionPart::Full {
+                let mut errors = ValidationErrors::new();
+                errors.add("pay_with", "not_allowed", "Swap payments cannot be split into parts");
+                return Ok(validation_failed(&errors));
+            }
+            let payer = account.parse().map_err(actix_web::error::ErrorBadRequest)?;
+            match payment_service.prepare_swap(payment, &payer, pay_with).await {
+                Ok((payment, swap)) => (payment, Some(swap)),
+                Err(e) => {
+                    if let Some(errors) = e.downcast_ref::<ValidationErrors>() {
+                        return Ok(validation_failed(errors));
+                    }
+                    tracing::warn!(payment_id = %payment_id, error = %e, "Swap quote failed");
+                    return Ok(HttpResponse::BadGateway()
+                        .append_header(("Access-Control-Allow-Origin", "*"))
+                        .json(serde_json::json!({"error": format!("Swap is unavailable: {}", e)})));
+                }
+            }
+        }
+        None => (payment, None),
+    };
 
-    // 1. ОСНОВНОЙ ПЛАТЕЖ
-    log::info!("🔧 Creating main payment instruction...");
-    if payment.token == "SOL" {
-        log::info!("💰 SOL transfer: {} SOL", payment.amount);
-        let lamports = (payment.amount * 1_000_000_000.0) as u64;
-        instructions.push(system_instruction::transfer(&payer, &recipient, lamports));
-        log::info!("✅ SOL instruction added: {} lamports", lamports);
-    } else {
-        log::info!("💰 SPL token transfer: {} {}", payment.amount, payment.token);
+    // Gasless: комиссию платит hot wallet (свопы не спонсируются)
+    let fee_payer = if swap.is_none() { sponsorship_service.fee_payer(&payment) } else { None };
+    let sponsorship = fee_payer.is_some().then_some(sponsorship_service.get_ref());
 
-        let mint = match payment.token.as_str() {
-            "USDC" => Pubkey::from_str("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v")?,
-            "USDT" => Pubkey::from_str("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB")?,
-            _ => anyhow::bail!("Unsupported token: {}", payment.token),
-        };
+    if let Err(e) = payment_service.remember_transaction(
+        &payment, &account, query.part, locale, swap.as_ref(), fee_payer.as_ref(),
+    ).await {
+        tracing::warn!(payment_id = %payment_id, error = %e, "Failed to remember issued transaction");
+    }
+    Ok(transaction_response(&payment_service, &payment, &account, query.part, locale, swap, sponsorship).await)
+}
 
-        let decimals = if payment.token == "USDC" || payment.token == "USDT" { 6 } else { 9 };
-        let amount = (payment.amount * 10_f64.powi(decimals)) as u64;
+#[derive(Deserialize)]
+struct PreviewQuery {
+    account: String,
+    #[serde(default)]
+    part: TransactionPart,
+    /// Одноразовый токен из transaction request URL
+    t: Option<String>,
+}
 
-        let from_token_account = spl_associated_token_account::get_associated_token_address(&payer, &mint);
-        let to_token_account = spl_associated_token_account::get_associated_token_address(&recipient, &mint);
+// GET: Разбор транзакции платежа для плательщика `account` без выдачи подписываемой
+// транзакции: переводы, создаваемые токен-аккаунты, комиссия сети и итог по токенам
+async fn transaction_preview(
+    payment_service: web::Data<PaymentService>,
+    sponsorship_service: web::Data<SponsorshipService>,
+    path: web::Path<String>,
+    query: web::Query<PreviewQuery>,
+) -> Result<HttpResponse> {
+    let payment_id = path.into_inner();
+    let mut errors = ValidationErrors::new();
+    errors.check_pubkey("account", &query.account);
+    if let Err(errors) = errors.into_result() {
+        return Ok(validation_failed(&errors));
+    }
 
-        log::info!("🔧 Main token transfer: {} {} tokens", amount, payment.token);
+    let payment = match payment_service.get_payment(&payment_id).await {
+        // Без токена из URL разбор транзакции не отдается, как и сама транзакция
+        Ok(Some(payment)) if payment.check_request_token(query.t.as_deref()).is_err() => {
+            return Ok(HttpResponse::NotFound()
+                .append_header(("Access-Control-Allow-Origin", "*"))
+                .json(serde_json::json!({"success": false, "error": "Payment not found"})));
+        }
+        Ok(Some(payment)) => payment,
+        Ok(None) => return Ok(HttpResponse::NotFound()
+            .append_header(("Access-Control-Allow-Origin", "*"))
+            .json(serde_json::json!({"success": false, "error": "Payment not found"}))),
+        Err(e) => return Ok(HttpResponse::InternalServerError()
+            .append_header(("Access-Control-Allow-Origin", "*"))
+            .json(serde_json::json!({"success": false, "error": e.to_string()}))),
+    };
 
-        // Создание ATA для получателя (если не существует)
-        instructions.push(
-            spl_associated_token_account::instruction::create_associated_token_account(
-                &payer, &recipient, &mint, &spl_token::ID,
-            )
-        );
-
-        // Основной transfer
-        instructions.push(token_instruction::transfer(
-            &spl_token::ID,
-            &from_token_account,
-            &to_token_account,
-            &payer,
-            &[],
-            amount,
-        )?);
-        log::info!("✅ Main transfer instruction added");
-    }
-
-    // 2. КОМИССИЯ В USDC
-    log::info!("🔧 Adding fee instruction to the same transaction...");
-    let usdc_mint = Pubkey::from_str("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v")?;
-    let fee_amount = (payment.fee_amount * 1_000_000.0) as u64;
-
-    let from_usdc_account = spl_associated_token_account::get_associated_token_address(&payer, &usdc_mint);
-    let to_usdc_account = spl_associated_token_account::get_associated_token_address(&fee_recipient, &usdc_mint);
-
-    log::info!("💳 Fee transfer: {} micro-USDC", fee_amount);
-
-    // Создание ATA для fee получателя (если не существует)
-    instructions.push(
-        spl_associated_token_account::instruction::create_associated_token_account(
-            &payer, &fee_recipient, &usdc_mint, &spl_token::ID,
-        )
-    );
+    if let Some(response) = sandbox_conflict(&payment) {
+        return Ok(response);
+    }
 
-    // Fee transfer
-    instructions.push(token_instruction::transfer(
-        &spl_token::ID,
-        &from_usdc_account,
-        &to_usdc_account,
-        &payer,
-        &[],
-        fee_amount,
-    )?);
-    log::info!("✅ Fee transfer instruction added");
-
-    // 3. ПОЛУЧАЕМ СВЕЖИЙ BLOCKHASH
-    log::info!("🔧 Getting recent blockhash...");
-    let recent_blockhash = get_recent_blockhash_with_retries().await
-        .map_err(|e| anyhow::anyhow!("Failed to get blockhash: {}", e))?;
-    log::info!("✅ Got blockhash: {}", recent_blockhash);
-
-    // 4. СОЗДАЕМ ОДНУ ТРАНЗАКЦИЮ СО ВСЕМИ ИНСТРУКЦИЯМИ
-    log::info!("🔧 Creating single transaction with {} instructions...", instructions.len());
-    let message = Message::new(&instructions, Some(&payer));
-    let mut transaction = Transaction::new_unsigned(message);
-    transaction.message.recent_blockhash = recent_blockhash;
-
-    log::info!("✅ Single transaction created with {} instructions", instructions.len());
-
-    // 5. СЕРИАЛИЗУЕМ В BASE64
-    log::info!("🔧 Serializing transaction for Solana Pay...");
-    let serialized = bincode::serialize(&transaction)
-        .map_err(|e| anyhow::anyhow!("Failed to serialize transaction: {}", e))?;
-    let base64_transaction = general_purpose::STANDARD.encode(&serialized);
-
-    log::info!("✅ Transaction serialized successfully!");
-    log::info!("   Instructions count: {}", instructions.len());
-    log::info!("   Serialized size: {} bytes", serialized.len());
-
-    Ok(base64_transaction)
-}
-
-// ПРОСТАЯ функция получения blockhash БЕЗ БЛОКИРУЮЩИХ ВЫЗОВОВ
-async fn get_recent_blockhash_with_retries() -> anyhow::Result<solana_sdk::hash::Hash> {
-    use reqwest;
-    use serde_json::{Value, json};
-
-    log::info!("🔗 Getting recent blockhash via HTTP...");
-
-    let rpc_endpoints = [
-        "https://api.mainnet-beta.solana.com",
-        "https://solana-api.projectserum.com",
-        "https://rpc.ankr.com/solana",
-    ];
-
-    for endpoint in &rpc_endpoints {
-        log::info!("🔗 Trying RPC: {}", endpoint);
-
-        for retry in 0..2 {
-            match timeout(Duration::from_secs(10), async {
-                let client = reqwest::Client::new();
-
-                let request_body = json!({
-                    "jsonrpc": "2.0",
-                    "id": 1,
-                    "method": "getLatestBlockhash",
-                    "params": [
-                        {
-                            "commitment": "confirmed"
-                        }
-                    ]
-                });
-
-                let response = client
-                    .post(*endpoint)
-                    .header("Content-Type", "application/json")
-                    .json(&request_body)
-                    .send()
-                    .await?;
-
-                let json_response: Value = response.json().await?;
-
-                if let Some(result) = json_response.get("result") {
-                    if let Some(value) = result.get("value") {
-                        if let Some(blockhash_str) = value.get("blockhash").and_then(|v| v.as_str()) {
-                            let blockhash = blockhash_str.parse::<solana_sdk::hash::Hash>()
-                                .map_err(|e| anyhow::anyhow!("Failed to parse blockhash: {}", e))?;
-                            return Ok(blockhash);
-                        }
-                    }
-                }
+    let fee_payer = sponsorship_service.fee_payer(&payment);
+    let preview = preview_payment_transaction(
+        &payment,
+        &query.account,
+        payment_service.config(),
+        payment_service.rpc().as_ref(),
+        query.part,
+        fee_payer.as_ref(),
+        Some(payment_service.compute_estimator()),
+    ).await;
+    match preview {
+        Ok(preview) => Ok(HttpResponse::Ok()
+            .append_header(("Access-Control-Allow-Origin", "*"))
+            .json(serde_json::json!({"success": true, "data": preview}))),
+        Err(e) => {
+            tracing::warn!(payment_id = %payment_id, error = %e, "Transaction preview failed");
+            Ok(HttpResponse::BadRequest()
+                .append_header(("Access-Control-Allow-Origin", "*"))
+                .json(serde_json::json!({"success": false, "error": e.to_string()})))
+        }
+    }
+}
 
-                anyhow::bail!("Invalid response format")
-            }).await {
-                Ok(Ok(blockhash)) => {
-                    log::info!("✅ Got blockhash from {} (attempt {}): {}", endpoint, retry + 1, blockhash);
-                    return Ok(blockhash);
-                }
-                Ok(Err(e)) => {
-                    log::warn!("⚠️ RPC {} failed (attempt {}): {}", endpoint, retry + 1, e);
-                }
-                Err(_) => {
-                    log::warn!("⚠️ RPC {} timed out (attempt {})", endpoint, retry + 1);
-                }
-            }
+// POST: Пересобрать выданную транзакцию со свежим blockhash (тот же набор инструкций
+// и reference), если кошелек подписал ее слишком поздно
+async fn transaction_refresh(
+    payment_service: web::Data<PaymentService>,
+    sponsorship_service: web::Data<SponsorshipService>,
+    path: web::Path<String>,
+    query: web::Query<RequestTokenQuery>,
+) -> Result<HttpResponse> {
+    let payment_id = path.into_inner();
+    let payment = match payment_service.get_payment(&payment_id).await {
+        Ok(Some(payment)) => payment,
+        Ok(None) => return Ok(HttpResponse::NotFound()
+            .append_header(("Access-Control-Allow-Origin", "*"))
+            .json(serde_json::json!({"error": "Payment not found"}))),
+        Err(e) => return Ok(HttpResponse::InternalServerError()
+            .append_header(("Access-Control-Allow-Origin", "*"))
+            .json(serde_json::json!({"error": e.to_string()}))),
+    };
 
-            if retry < 1 {
-                log::info!("⏰ Waiting 1s before retry...");
-                tokio::time::sleep(Duration::from_millis(1000)).await;
+    // Пересборка — та же выдача транзакции: нужен токен из URL и закрепивший его кошелек
+    let issued = match payment_service.refresh_transaction(&payment, query.t.as_deref()).await {
+        Ok(issued) => issued,
+        Err(e) => {
+            if let Some(response) = request_token_rejection(&e) {
+                tracing::warn!(payment_id = %payment.id, error = %e, "Transaction refresh token rejected");
+                return Ok(response);
             }
+            return Ok(issued_transaction_error(&payment, e, HttpResponse::InternalServerError));
         }
-    }
+    };
+    tracing::info!(
+        payment_id = %payment.id,
+        refreshes = issued.refreshes,
+        limit = payment_service.config().server.transaction_refresh_limit,
+        "Refreshing transaction"
+    );
 
-    log::error!("❌ All RPC endpoints failed!");
-    anyhow::bail!("All RPC endpoints failed after retries")
+    let sponsorship = issued.fee_payer.is_some().then_some(sponsorship_service.get_ref());
+    Ok(transaction_response(
+        &payment_service, &payment, &issued.account, issued.part, issued.locale, issued.swap, sponsorship,
+    ).await)
 }
 
-// Остальные функции остаются без изменений
-async fn get_payment(
+// POST: Отправить транзакцию, подписанную кошельком, от имени плательщика —
+// для кошельков, которые возвращают подписанную транзакцию вместо отправки
+async fn transaction_submit(
     payment_service: web::Data<PaymentService>,
     path: web::Path<String>,
+    req: web::Json<SubmitTransactionRequest>,
 ) -> Result<HttpResponse> {
     let payment_id = path.into_inner();
-    match payment_service.get_payment(&payment_id).await {
-        Ok(Some(payment)) => Ok(HttpResponse::Ok().json(PaymentResponse {
-            success: true, data: Some(payment), error: None,
-        })),
-        Ok(None) => Ok(HttpResponse::NotFound().json(PaymentResponse {
-            success: false, data: None, error: Some("Payment not found".to_string()),
-        })),
-        Err(e) => Ok(HttpResponse::InternalServerError().json(PaymentResponse {
-            success: false, data: None, error: Some(e.to_string()),
-        })),
+    if let Err(errors) = req.validate() {
+        return Ok(validation_failed(&errors));
     }
-}
+    let payment = match payment_service.get_payment(&payment_id).await {
+        Ok(Some(payment)) => payment,
+        Ok(None) => return Ok(HttpResponse::NotFound()
+            .append_header(("Access-Control-Allow-Origin", "*"))
+            .json(serde_json::json!({"error": "Payment not found"}))),
+        Err(e) => return Ok(HttpResponse::InternalServerError()
+            .append_header(("Access-Control-Allow-Origin", "*"))
+            .json(serde_json::json!({"error": e.to_string()}))),
+    };
 
-#[derive(Deserialize)]
-struct VerifyPaymentRequest {
-    signature: String,
+    match payment_service.submit_transaction(&payment, &req.transaction).await {
+        Ok(verification) => Ok(HttpResponse::Ok()
+            .append_header(("Access-Control-Allow-Origin", "*"))
+            .json(verification)),
+        Err(e) => {
+            if let Some(errors) = e.downcast_ref::<ValidationErrors>() {
+                return Ok(validation_failed(errors));
+            }
+            tracing::warn!(payment_id = %payment_id, error = %e, "Transaction submission failed");
+            Ok(issued_transaction_error(&payment, e, HttpResponse::BadGateway))
+        }
+    }
 }
 
-async fn verify_payment(
+// POST: Разбор транзакции для отладки кошельков: подписанты, аккаунты, инструкции с
+// суммами переводов. С `payment_id` — чем транзакция отличается от выданной для платежа
+async fn decode_transaction(
     payment_service: web::Data<PaymentService>,
-    path: web::Path<String>,
-    req: web::Json<VerifyPaymentRequest>,
+    req: web::Json<DecodeTransactionRequest>,
 ) -> Result<HttpResponse> {
-    let payment_id = path.into_inner();
-    let signature = req.signature.clone();
-    match payment_service.verify_payment(&payment_id, &signature).await {
-        Ok(verification) => Ok(HttpResponse::Ok().json(verification)),
-        Err(e) => Ok(HttpResponse::BadRequest().json(serde_json::json!({
-           "success": false, "error": e.to_string()
-       }))),
+    if let Err(errors) = req.validate() {
+        return Ok(validation_failed(&errors));
     }
-}
 
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    dotenv::dotenv().ok();
+    let payment = match req.payment_id.as_deref() {
+        Some(payment_id) => match payment_service.get_payment(payment_id).await {
+            Ok(Some(payment)) => Some(payment),
+            Ok(None) => return Ok(HttpResponse::NotFound()
+                .append_header(("Access-Control-Allow-Origin", "*"))
+                .json(serde_json::json!({"success": false, "error": "Payment not found"}))),
+            Err(e) => return Ok(HttpResponse::InternalServerError()
+                .append_header(("Access-Control-Allow-Origin", "*"))
+                .json(serde_json::json!({"success": false, "error": e.to_string()}))),
+        },
+        None => None,
+    };
 
-    env_logger::init();
-    println!("🦀 Starting CryptoNow Rust Server...");
+    match payment_service.decode_transaction(&req.transaction, payment.as_ref()).await {
+        Ok(decoded) => Ok(HttpResponse::Ok()
+            .append_header(("Access-Control-Allow-Origin", "*"))
+            .json(serde_json::json!({"success": true, "data": decoded}))),
+        Err(e) => {
+            if let Some(errors) = e.downcast_ref::<ValidationErrors>() {
+                return Ok(validation_failed(errors));
+            }
+            let mut response = match e.downcast_ref::<RefreshError>() {
+                Some(RefreshError::NotIssued) => HttpResponse::NotFound(),
+                _ => HttpResponse::BadRequest(),
+            };
+            Ok(response
+                .append_header(("Access-Control-Allow-Origin", "*"))
+                .json(serde_json::json!({"success": false, "error": e.to_string()})))
+        }
+    }
+}
 
-    let config = Config::load().expect("Failed to load config");
-    let payment_service = PaymentService::new(config.clone()).await.expect("Failed to initialize payment service");
+// Ответ на ошибку пересборки/отправки выданной транзакции; `fallback` — для прочих ошибок
+fn issued_transaction_error(
+    payment: &Payment,
+    e: anyhow::Error,
+    fallback: fn() -> actix_web::HttpResponseBuilder,
+) -> HttpResponse {
+    let body = serde_json::json!({"error": e.to_string(), "payment_id": payment.id});
+    let mut response = match e.downcast_ref::<RefreshError>() {
+        Some(RefreshError::NotIssued) => HttpResponse::NotFound(),
+        Some(RefreshError::NotPending) => HttpResponse::Conflict(),
+        Some(RefreshError::TooSoon { retry_after_secs }) => {
+            let mut response = HttpResponse::TooManyRequests();
+            response.append_header(("Retry-After", retry_after_secs.to_string()));
+            response
+        }
+        Some(RefreshError::LimitReached) => HttpResponse::TooManyRequests(),
+        None if e.is::<SandboxError>() => HttpResponse::Conflict(),
+        None => fallback(),
+    };
+    response.append_header(("Access-Control-Allow-Origin", "*")).json(body)
+}
 
-    let host = config.server.host.clone();
-    let port = config.server.port;
+// Ответ 409 на операцию с блокчейном для sandbox платежа
+fn sandbox_conflict(payment: &Payment) -> Option<HttpResponse> {
+    payment.sandbox.then(|| HttpResponse::Conflict()
+        .append_header(("Access-Control-Allow-Origin", "*"))
+        .json(serde_json::json!({
+            "error": SandboxError::OnChain.to_string(),
+            "payment_id": payment.id,
+            "code": "sandbox",
+        })))
+}
 
-    println!("🚀 Server starting on http://{}:{}", host, port);
-    println!("📡 Fee wallet: {}", config.solana.fee_wallet);
-    println!("💰 Fee amount: {} {}", config.solana.fee_amount, config.solana.fee_token);
+// Собрать транзакцию платежа и ответ Solana Pay transaction request
+async fn transaction_response(
+    payment_service: &PaymentService,
+    payment: &Payment,
+    account: &str,
+    part: TransactionPart,
+    locale: Locale,
+    swap: Option<SwapInstructions>,
+    sponsorship: Option<&SponsorshipService>,
+) -> HttpResponse {
+    if let Some(response) = sandbox_conflict(payment) {
+        return response;
+    }
 
-    HttpServer::new(move || {
-        let cors = Cors::default()
-            .allow_any_origin()
-            .allow_any_method()
-            .allow_any_header()
-            .max_age(3600);
+    // Срок общий для очереди и всех вызовов RPC сборки. Кошелек отключился — actix
+    // бросает handler, guard отменяет незавершенные вызовы
+    let deadline = Deadline::after(Duration::from_secs(TRANSACTION_DEADLINE_SECS));
+    let _cancel_on_drop = deadline.cancel_on_drop();
+    let started = std::time::Instant::now();
+    let (config, rpc) = (payment_service.config(), payment_service.rpc());
+    let estimator = Some(payment_service.compute_estimator());
+    let build = async {
+        match (swap, sponsorship) {
+            (Some(swap), _) => create_swap_payment_transaction(payment, account, config, rpc.as_ref(), swap).await,
+            (None, Some(sponsorship)) => create_sponsored_payment_transaction(
+                payment, account, config, rpc.as_ref(), part, sponsorship, estimator,
+            ).await,
+            (None, None) => create_payment_transaction(payment, account, config, rpc.as_ref(), part, estimator).await,
+        }
+    };
+    let built = match deadline.run(payment_service.build_limiter().run(build)).await {
+        Ok(Ok(built)) => Ok(built),
+        Err(e) => Err(e),
+        Ok(Err(e)) => {
+            let stats = payment_service.build_limiter().stats();
+            tracing::warn!(
+                payment_id = %payment.id,
+                in_flight = stats.in_flight,
+                queued = stats.queued,
+                error = %e,
+                "Transaction builder saturated"
+            );
+            return HttpResponse::ServiceUnavailable()
+                .append_header(("Retry-After", e.retry_after_secs().to_string()))
+                .append_header(("Access-Control-Allow-Origin", "*"))
+                .json(serde_json::json!({
+                    "error": e.to_string(),
+                    "payment_id": payment.id,
+                    "code": "builder_saturated",
+                    "retry_after_secs": e.retry_after_secs(),
+                }));
+        }
+    };
+    match built {
+        Ok(Ok(built)) => {
+            tracing::info!(
+                payment_id = %payment.id,
+                amount = payment.amount,
+                token = %payment.token,
+                size_bytes = built.transaction.len(),
+                latency_ms = started.elapsed().as_millis() as u64,
+                "Transaction created"
+            );
+            if let Err(e) = payment_service.record_compute_budget(&payment.id, built.compute_budget).await {
+                tracing::warn!(payment_id = %payment.id, error = %e, "Failed to record compute budget");
+            }
 
-        App::new()
-            .app_data(web::Data::new(payment_service.clone()))
-            .wrap(cors)
-            .wrap(Logger::default())
-            .route("/", web::get().to(index))
+            let mut message = locale.transaction_message(payment, part);
+            for note in &built.notes {
+                message.push_str(". ");
+                message.push_str(note);
+            }
+
+            HttpResponse::Ok()
+                .append_header(("Content-Type", "application/json"))
+                .append_header(("Access-Control-Allow-Origin", "*"))
+                .append_header(("Access-Control-Allow-Methods", "GET, POST, OPTIONS"))
+                .append_header(("Access-Control-Allow-Headers", "Content-Type"))
+                .json(TransactionResponse {
+                    transaction: built.transaction,
+                    message: Some(message),
+                })
+        }
+        Ok(Err(e)) => {
+            tracing::error!(payment_id = %payment.id, error = %e, "Transaction creation failed");
+            error_reporting::capture(
+                ErrorEvent::new(ErrorKind::Transaction, format!("Transaction creation failed: {}", e))
+                    .payment(payment)
+                    .extra("account", account)
+                    .extra("latency_ms", started.elapsed().as_millis() as u64),
+            );
+            HttpResponse::BadRequest()
+                .append_header(("Content-Type", "application/json"))
+                .append_header(("Access-Control-Allow-Origin", "*"))
+                .json(serde_json::json!({
+                    "error": format!("Transaction creation failed: {}", e),
+                    "payment_id": payment.id,
+                    "details": "Check server logs for more information"
+                }))
+        }
+        // Отменена вместе с брошенным handler: ответ уже некому отправить
+        Err(DeadlineError::Cancelled) => HttpResponse::ServiceUnavailable().finish(),
+        Err(DeadlineError::Elapsed) => {
+            tracing::error!(
+                payment_id = %payment.id, latency_ms = started.elapsed().as_millis() as u64, "Transaction creation timed out"
+            );
+            error_reporting::capture(
+                ErrorEvent::new(ErrorKind::Transaction, "Transaction creation timed out")
+                    .payment(payment)
+                    .extra("account", account)
+                    .extra("latency_ms", started.elapsed().as_millis() as u64),
+            );
+            HttpResponse::RequestTimeout()
+                .append_header(("Content-Type", "application/json"))
+                .append_header(("Access-Control-Allow-Origin", "*"))
+                .json(serde_json::json!({
+                    "error": "Transaction creation timed out",
+                    "payment_id": payment.id,
+                    "timeout": format!("{} seconds", TRANSACTION_DEADLINE_SECS)
+                }))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PaymentQuery {
+    /// Поля ответа через запятую. Без параметра — все поля, кроме `qr_code`
+    /// (QR отдается отдельно по `qr_url`), `qr_code` — только если запрошен явно
+    fields: Option<String>,
+    /// Валюта для сумм в фиате (`fiat`): EUR, GBP, ... из `server.display_currencies`
+    display_currency: Option<String>,
+}
+
+// Страницы оплаты опрашивают платеж каждую секунду: с If-None-Match неизмененный
+// платеж отдается как 304 без тела
+async fn get_payment(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<PaymentQuery>,
+) -> Result<HttpResponse> {
+    let payment_id = path.into_inner();
+    let fields: Option<Vec<&str>> = query.fields.as_deref().map(|fields| {
+        fields.split(',').map(str::trim).filter(|f| !f.is_empty()).collect()
+    });
+    let with_qr = fields.as_ref().is_some_and(|f| f.contains(&"qr_code"));
+
+    // Ответ открыт любому, кто знает ID: токен transaction request только у создателя
+    let payment = match payment_service.get_payment(&payment_id).await {
+        Ok(Some(payment)) => payment_service.without_request_token(payment),
+        Ok(None) => return Ok(HttpResponse::NotFound().json(PaymentResponse {
+            success: false, data: None, error: Some("Payment not found".to_string()),
+        })),
+        Err(e) => return Ok(HttpResponse::InternalServerError().json(PaymentResponse {
+            success: false, data: None, error: Some(e.to_string()),
+        })),
+    };
+    let payment = match &query.display_currency {
+        Some(currency) => match payment_service.with_display_currency(payment, currency).await {
+            Ok(payment) => payment,
+            Err(errors) => return Ok(validation_failed(&errors)),
+        },
+        None => payment,
+    };
+
+    let etag = payment_etag(&payment, query.fields.as_deref());
+    if not_modified(&http_req, &etag) {
+        return Ok(HttpResponse::NotModified()
+            .insert_header(header::ETag(etag))
+            .insert_header(header::CacheControl(vec![header::CacheDirective::NoCache]))
+            .finish());
+    }
+    let mut response = HttpResponse::Ok();
+    response
+        .insert_header(header::ETag(etag))
+        .insert_header(header::LastModified(std::time::SystemTime::from(payment.updated_at).into()))
+        .insert_header(header::CacheControl(vec![header::CacheDirective::NoCache]));
+
+    let payment = if with_qr { payment_service.with_qr_code(payment) } else { Ok(payment) };
+    let payment = match payment {
+        Ok(payment) => payment,
+        Err(e) => return Ok(HttpResponse::InternalServerError().json(PaymentResponse {
+            success: false, data: None, error: Some(e.to_string()),
+        })),
+    };
+
+    let Some(fields) = fields else {
+        sign_payload(&mut response, payment_service.payload_signer(), &payment);
+        return Ok(response.json(PaymentResponse {
+            success: true, data: Some(payment), error: None,
+        }));
+    };
+    match select_fields(&payment, &fields) {
+        Ok(data) => {
+            sign_payload(&mut response, payment_service.payload_signer(), &data);
+            Ok(response.json(serde_json::json!({
+                "success": true, "data": data, "error": null
+            })))
+        }
+        Err(e) => match e.downcast_ref::<ValidationErrors>() {
+            Some(errors) => Ok(validation_failed(errors)),
+            None => Ok(HttpResponse::InternalServerError().json(PaymentResponse {
+                success: false, data: None, error: Some(e.to_string()),
+            })),
+        },
+    }
+}
+
+#[derive(Deserialize)]
+struct PaymentQrQuery {
+    /// Вид QR явно: `transaction` или `transfer`
+    kind: Option<QrKind>,
+    /// Поддерживает ли кошелек плательщика transaction request (по умолчанию — да);
+    /// `false` — запасной transfer request QR
+    transaction_requests: Option<bool>,
+}
+
+// GET: QR код платежа (PNG). Вид выбирается по `kind` или по возможностям кошелька
+async fn get_payment_qr(
+    payment_service: web::Data<PaymentService>,
+    path: web::Path<String>,
+    query: web::Query<PaymentQrQuery>,
+) -> Result<HttpResponse> {
+    let kind = query.kind.unwrap_or_else(|| QrKind::for_wallet(query.transaction_requests.unwrap_or(true)));
+    let payment_id = path.into_inner();
+    // QR по ID платежа — без токена transaction request, как и JSON платежа
+    let payment = match payment_service.get_payment(&payment_id).await {
+        Ok(Some(payment)) => payment_service.without_request_token(payment),
+        Ok(None) => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "success": false, "error": "Payment not found"
+        }))),
+        Err(e) => return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false, "error": e.to_string()
+        }))),
+    };
+
+    match payment_service.qr_png(&payment, kind) {
+        // URL платежа не меняется — QR можно кэшировать на клиенте.
+        // Тело отдается потоком срезов закэшированного PNG без копирования
+        Ok(png) => {
+            let chunks: Vec<Result<web::Bytes, actix_web::Error>> = (0..png.len())
+                .step_by(QR_STREAM_CHUNK)
+                .map(|start| Ok(png.slice(start..png.len().min(start + QR_STREAM_CHUNK))))
+                .collect();
+            Ok(HttpResponse::Ok()
+                .content_type("image/png")
+                .insert_header(("Cache-Control", "public, max-age=86400, immutable"))
+                .no_chunking(png.len() as u64)
+                .streaming(futures::stream::iter(chunks)))
+        }
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false, "error": e.to_string()
+        }))),
+    }
+}
+
+// GET: Поток событий платежа (Server-Sent Events) для обратного отсчета и смены статуса
+async fn payment_events(
+    payment_service: web::Data<PaymentService>,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    match sse::payment_events(payment_service.get_ref().clone(), &path.into_inner()).await {
+        Ok(Some(events)) => Ok(HttpResponse::Ok()
+            .content_type("text/event-stream")
+            .insert_header(("Cache-Control", "no-cache"))
+            // Nginx не должен буферизовать поток
+            .insert_header(("X-Accel-Buffering", "no"))
+            .streaming(events)),
+        Ok(None) => Ok(HttpResponse::NotFound().json(PaymentResponse {
+            success: false, data: None, error: Some("Payment not found".to_string()),
+        })),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(PaymentResponse {
+            success: false, data: None, error: Some(e.to_string()),
+        })),
+    }
+}
+
+#[derive(Deserialize)]
+struct PaymentWaitQuery {
+    /// Сколько ждать смены статуса, секунд (по умолчанию 30)
+    timeout: Option<u64>,
+    /// Статус, который клиент уже видел; без параметра — статус на момент запроса
+    status: Option<PaymentStatus>,
+}
+
+impl Validate for PaymentWaitQuery {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        if self.timeout.is_some_and(|timeout| !(1..=sse::MAX_WAIT_SECS).contains(&timeout)) {
+            errors.add("timeout", "out_of_range", format!("timeout must be between 1 and {}", sse::MAX_WAIT_SECS));
+        }
+        errors.into_result()
+    }
+}
+
+// GET: Long-poll — ответ приходит, когда статус платежа сменился или вышел timeout.
+// Простым клиентам не нужны ни WebSocket, ни SSE, ни опрос каждую секунду
+async fn wait_payment(
+    payment_service: web::Data<PaymentService>,
+    path: web::Path<String>,
+    query: web::Query<PaymentWaitQuery>,
+) -> Result<HttpResponse> {
+    if let Err(errors) = query.validate() {
+        return Ok(validation_failed(&errors));
+    }
+    let timeout = std::time::Duration::from_secs(query.timeout.unwrap_or(30));
+    match sse::wait_for_status_change(payment_service.get_ref(), &path.into_inner(), query.status, timeout).await {
+        Ok(Some(payment)) => Ok(HttpResponse::Ok()
+            .insert_header(header::CacheControl(vec![header::CacheDirective::NoStore]))
+            .json(PaymentResponse { success: true, data: Some(payment), error: None })),
+        Ok(None) => Ok(HttpResponse::NotFound().json(PaymentResponse {
+            success: false, data: None, error: Some("Payment not found".to_string()),
+        })),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(PaymentResponse {
+            success: false, data: None, error: Some(e.to_string()),
+        })),
+    }
+}
+
+#[derive(Deserialize)]
+struct PaymentLookupQuery {
+    signature: Option<String>,
+    reference: Option<String>,
+}
+
+impl Validate for PaymentLookupQuery {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        match (&self.signature, &self.reference) {
+            (Some(signature), None) => errors.check_signature("signature", signature),
+            (None, Some(reference)) => errors.check_pubkey("reference", reference),
+            _ => errors.add("signature", "exactly_one", "Pass exactly one of signature or reference"),
+        }
+        errors.into_result()
+    }
+}
+
+// GET: Найти платеж по подписи транзакции или Solana Pay reference (для поддержки).
+// Мерчант видит только свои платежи, администратор — все
+async fn lookup_payment(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+    query: web::Query<PaymentLookupQuery>,
+) -> Result<HttpResponse> {
+    let viewer = resolve_viewer(&http_req, payment_service.config())?;
+    if let Err(errors) = query.validate() {
+        return Ok(validation_failed(&errors));
+    }
+
+    let storage = payment_service.storage();
+    let found = match (&query.signature, &query.reference) {
+        (Some(signature), _) => storage.find_payment_by_signature(signature).await,
+        (_, Some(reference)) => storage.find_payment_by_reference(reference).await,
+        _ => Ok(None),
+    };
+
+    match found {
+        Ok(Some(payment)) if viewer.can_see(&payment) => Ok(HttpResponse::Ok().json(PaymentResponse {
+            success: true, data: Some(payment), error: None,
+        })),
+        Ok(_) => Ok(HttpResponse::NotFound().json(PaymentResponse {
+            success: false, data: None, error: Some("Payment not found".to_string()),
+        })),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(PaymentResponse {
+            success: false, data: None, error: Some(e.to_string()),
+        })),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PaymentStatusRequest {
+    ids: Vec<String>,
+}
+
+impl Validate for PaymentStatusRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        if self.ids.is_empty() || self.ids.len() > MAX_STATUS_BATCH {
+            errors.add(
+                "ids",
+                "out_of_range",
+                format!("ids must have 1 to {} payment IDs, got: {}", MAX_STATUS_BATCH, self.ids.len()),
+            );
+        }
+        errors.into_result()
+    }
+}
+
+// POST: Статусы многих платежей одним запросом (синхронизация открытых счетов POS):
+// ID → статус, подпись и время изменения. Чужие и неизвестные ID — в `not_found`
+async fn payment_statuses(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+    req: web::Json<PaymentStatusRequest>,
+) -> Result<HttpResponse> {
+    let viewer = resolve_viewer(&http_req, payment_service.config())?;
+    if let Err(errors) = req.validate() {
+        return Ok(validation_failed(&errors));
+    }
+
+    match payment_service.get_payments(&req.ids).await {
+        Ok(payments) => {
+            let statuses: BTreeMap<String, PaymentStatusSummary> = payments.into_iter()
+                .filter(|(_, payment)| viewer.can_see(payment))
+                .map(|(id, payment)| (id, PaymentStatusSummary::from(&payment)))
+                .collect();
+            let not_found: Vec<&String> = req.ids.iter()
+                .filter(|id| !statuses.contains_key(id.as_str()))
+                .collect();
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "success": true, "data": statuses, "not_found": not_found
+            })))
+        }
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false, "error": e.to_string()
+        }))),
+    }
+}
+
+// GET: Платежи viewer, новые первыми: ?status=, ?limit=, ?metadata.order_id=123.
+// Мерчант получает свои платежи, администратор — все
+async fn list_payments(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+    query: web::Query<Vec<(String, String)>>,
+) -> Result<HttpResponse> {
+    let viewer = resolve_viewer(&http_req, payment_service.config())?;
+    let filter = match PaymentListFilter::parse(query.iter().map(|(name, value)| (name.as_str(), value.as_str()))) {
+        Ok(filter) => filter,
+        Err(errors) => return Ok(validation_failed(&errors)),
+    };
+
+    match payment_service.storage().list_payments(filter.consistency).await {
+        Ok(payments) => {
+            let mut payments: Vec<Payment> = payments.iter()
+                .filter(|payment| viewer.can_see(payment) && filter.matches(payment))
+                .cloned()
+                .collect();
+            payments.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| a.id.cmp(&b.id)));
+            payments.truncate(filter.limit);
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "success": true, "data": payments
+            })))
+        }
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false, "error": e.to_string()
+        }))),
+    }
+}
+
+#[derive(Deserialize)]
+struct PaymentExportQuery {
+    format: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    /// Выгрузить sandbox платежи (администратору по умолчанию — только боевые)
+    sandbox: Option<bool>,
+    /// По умолчанию — реплика с отставанием до `storage.replica_max_lag_ms`
+    #[serde(default)]
+    consistency: ReadConsistency,
+}
+
+// GET: Выгрузка истории платежей для бухгалтерии (CSV или xlsx) за период по времени создания.
+// Мерчант получает свои платежи, администратор — все боевые
+async fn export_payments(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+    query: web::Query<PaymentExportQuery>,
+) -> Result<HttpResponse> {
+    let viewer = resolve_viewer(&http_req, payment_service.config())?;
+    let range = match ExportRange::parse(query.from.as_deref(), query.to.as_deref()) {
+        Ok(range) => range,
+        Err(errors) => return Ok(validation_failed(&errors)),
+    };
+
+    let mut payments: Vec<Payment> = match payment_service.storage().list_payments(query.consistency).await {
+        Ok(payments) => payments.iter()
+            .filter(|payment| viewer.can_see(payment) && viewer.in_reports(payment, query.sandbox) && range.contains(payment))
+            .cloned()
+            .collect(),
+        Err(e) => return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false, "error": e.to_string()
+        }))),
+    };
+    payments.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+
+    match query.format.as_deref() {
+        None | Some("csv") => Ok(HttpResponse::Ok()
+            .content_type("text/csv; charset=utf-8")
+            .append_header(("Content-Disposition", "attachment; filename=\"payments.csv\""))
+            .streaming(export::csv_stream(payments))),
+        Some("xlsx") => match export::to_xlsx(&payments) {
+            Ok(xlsx) => Ok(HttpResponse::Ok()
+                .content_type("application/vnd.openxmlformats-officedocument.spreadsheetml.sheet")
+                .append_header(("Content-Disposition", "attachment; filename=\"payments.xlsx\""))
+                .body(xlsx)),
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to render payments xlsx export");
+                Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                    "success": false, "error": e.to_string()
+                })))
+            }
+        },
+        Some(other) => Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false, "error": format!("Unsupported export format: {}", other)
+        }))),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VerifyPaymentRequest {
+    signature: String,
+}
+
+impl Validate for VerifyPaymentRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        errors.check_signature("signature", &self.signature);
+        errors.into_result()
+    }
+}
+
+#[derive(Deserialize)]
+struct VerifyQuery {
+    /// `confirmed` или `finalized` вместо `solana.verification_commitment`
+    commitment: Option<String>,
+}
+
+async fn verify_payment(
+    payment_service: web::Data<PaymentService>,
+    path: web::Path<String>,
+    query: web::Query<VerifyQuery>,
+    req: web::Json<VerifyPaymentRequest>,
+) -> Result<HttpResponse> {
+    let payment_id = path.into_inner();
+    let mut errors = req.validate().err().unwrap_or_default();
+    let commitment = match query.commitment.as_deref() {
+        None => None,
+        Some(level @ ("confirmed" | "finalized")) => Some(transaction_commitment(level)),
+        Some(_) => {
+            errors.add("commitment", "invalid", "commitment must be confirmed or finalized");
+            None
+        }
+    };
+    if let Err(errors) = errors.into_result() {
+        return Ok(validation_failed(&errors));
+    }
+    let signature = req.signature.clone();
+    match payment_service.verify_payment_with_commitment(&payment_id, &signature, commitment).await {
+        Ok(verification) => Ok(HttpResponse::Ok().json(verification)),
+        Err(e) => Ok(HttpResponse::BadRequest().json(serde_json::json!({
+           "success": false, "error": e.to_string()
+       }))),
+    }
+}
+
+#[derive(Deserialize)]
+struct ReceiptQuery {
+    format: Option<String>,
+}
+
+// GET: Квитанция для завершенного платежа (JSON или ?format=pdf)
+async fn get_receipt(
+    payment_service: web::Data<PaymentService>,
+    path: web::Path<String>,
+    query: web::Query<ReceiptQuery>,
+) -> Result<HttpResponse> {
+    let payment_id = path.into_inner();
+    // QR по ID платежа — без токена transaction request, как и JSON платежа
+    let payment = match payment_service.get_payment(&payment_id).await {
+        Ok(Some(payment)) => payment_service.without_request_token(payment),
+        Ok(None) => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "success": false, "error": "Payment not found"
+        }))),
+        Err(e) => return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false, "error": e.to_string()
+        }))),
+    };
+
+    let explorer = payment_service.config().explorer();
+    let receipt = match Receipt::from_payment(&payment, &explorer) {
+        Ok(receipt) => receipt,
+        Err(e) => return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "success": false, "error": e.to_string()
+        }))),
+    };
+
+    match query.format.as_deref() {
+        None | Some("json") => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": receipt
+        }))),
+        Some("pdf") => match receipt.to_pdf() {
+            Ok(pdf) => Ok(HttpResponse::Ok()
+                .content_type("application/pdf")
+                .append_header((
+                    "Content-Disposition",
+                    format!("inline; filename=\"receipt-{}.pdf\"", payment_id),
+                ))
+                .body(pdf)),
+            Err(e) => {
+                tracing::error!(payment_id = %payment_id, error = %e, "Failed to render PDF receipt");
+                Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+                    "success": false, "error": e.to_string()
+                })))
+            }
+        },
+        Some(other) => Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false, "error": format!("Unsupported receipt format: {}", other)
+        }))),
+    }
+}
+
+// GET: Метаданные NFT-квитанции (uri в Metaplex): JSON без обертки, его читают кошельки
+async fn get_receipt_nft_metadata(
+    payment_service: web::Data<PaymentService>,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    let payment = match payment_service.get_payment(&path.into_inner()).await {
+        Ok(Some(payment)) => payment,
+        Ok(None) => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "success": false, "error": "Payment not found"
+        }))),
+        Err(e) => return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false, "error": e.to_string()
+        }))),
+    };
+
+    match ReceiptNftMetadata::for_payment(&payment, payment_service.config()) {
+        Ok(metadata) => Ok(HttpResponse::Ok().json(metadata)),
+        Err(e) => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "success": false, "error": e.to_string()
+        }))),
+    }
+}
+
+// GET: Бонусные баллы кошелька. С X-API-Key — счет у этого мерчанта с историей операций,
+// без ключа — балансы у всех мерчантов
+async fn get_loyalty(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    let merchant = resolve_merchant(&http_req, payment_service.config())?;
+    let wallet = path.into_inner();
+    let mut errors = ValidationErrors::new();
+    errors.check_pubkey("wallet", &wallet);
+    if let Err(errors) = errors.into_result() {
+        return Ok(validation_failed(&errors));
+    }
+
+    match payment_service.loyalty().balances(&wallet, merchant.as_ref().map(|m| m.id.as_str())).await {
+        Ok(mut accounts) => {
+            // Операции ссылаются на платежи — их видит только мерчант
+            if merchant.is_none() {
+                accounts.iter_mut().for_each(|account| account.entries.clear());
+            }
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "success": true, "data": accounts
+            })))
+        }
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false, "error": e.to_string()
+        }))),
+    }
+}
+
+#[derive(Deserialize)]
+struct OwnershipChallengeQuery {
+    wallet: String,
+    /// Для vault мультисига: аккаунт мультисига Squads и участник, который подписывает
+    multisig: Option<String>,
+    member: Option<String>,
+}
+
+impl Validate for OwnershipChallengeQuery {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        errors.check_pubkey("wallet", &self.wallet);
+        if let Some(multisig) = &self.multisig {
+            errors.check_pubkey("multisig", multisig);
+        }
+        if let Some(member) = &self.member {
+            errors.check_pubkey("member", member);
+        }
+        errors.into_result()
+    }
+}
+
+// GET: Challenge для подтверждения владения кошельком
+async fn wallet_challenge(
+    payment_service: web::Data<PaymentService>,
+    query: web::Query<OwnershipChallengeQuery>,
+) -> Result<HttpResponse> {
+    if let Err(errors) = query.validate() {
+        return Ok(validation_failed(&errors));
+    }
+    match payment_service
+        .create_ownership_challenge(&query.wallet, query.multisig.as_deref(), query.member.as_deref())
+        .await {
+        Ok(challenge) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": challenge
+        }))),
+        Err(e) => Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false, "error": e.to_string()
+        }))),
+    }
+}
+
+// POST: Подписанный challenge — регистрирует кошелек как подтвержденный
+async fn wallet_verify(
+    payment_service: web::Data<PaymentService>,
+    req: web::Json<OwnershipProofRequest>,
+) -> Result<HttpResponse> {
+    if let Err(errors) = req.validate() {
+        return Ok(validation_failed(&errors));
+    }
+    match payment_service.verify_ownership(&req).await {
+        Ok(verified) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": verified
+        }))),
+        Err(e) => {
+            tracing::warn!(wallet = %req.wallet, error = %e, "Wallet ownership proof rejected");
+            Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "success": false, "error": e.to_string()
+            })))
+        }
+    }
+}
+
+// Ответ на ошибку POS операции
+fn pos_error(e: anyhow::Error) -> HttpResponse {
+    if let Some(errors) = e.downcast_ref::<ValidationErrors>() {
+        return validation_failed(errors);
+    }
+    if let Some(response) = risk_rejection(&e) {
+        return response;
+    }
+    let body = serde_json::json!({"success": false, "error": e.to_string()});
+    match e.downcast_ref::<PosError>() {
+        Some(PosError::SessionNotFound) => HttpResponse::NotFound().json(body),
+        Some(PosError::SessionExpired) => HttpResponse::Gone().json(body),
+        None => HttpResponse::BadRequest().json(body),
+    }
+}
+
+// POST: Открыть сессию POS терминала
+async fn pos_open_session(
+    payment_service: web::Data<PaymentService>,
+    pos_service: web::Data<PosService>,
+    http_req: HttpRequest,
+    req: web::Json<CreatePosSessionRequest>,
+) -> Result<HttpResponse> {
+    let merchant = require_merchant(&http_req, payment_service.config())?;
+
+    match pos_service.open_session(req.into_inner(), &merchant).await {
+        Ok(session) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": session
+        }))),
+        Err(e) => Ok(pos_error(e)),
+    }
+}
+
+// GET: Сессия POS и последние чеки для экрана кассира
+async fn pos_get_session(
+    payment_service: web::Data<PaymentService>,
+    pos_service: web::Data<PosService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    let merchant = require_merchant(&http_req, payment_service.config())?;
+
+    match pos_service.session_view(&path.into_inner(), &merchant).await {
+        Ok(view) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": view
+        }))),
+        Err(e) => Ok(pos_error(e)),
+    }
+}
+
+// POST: Новый чек в сессии POS
+async fn pos_charge(
+    payment_service: web::Data<PaymentService>,
+    pos_service: web::Data<PosService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+    req: web::Json<PosChargeRequest>,
+) -> Result<HttpResponse> {
+    let merchant = require_merchant(&http_req, payment_service.config())?;
+    let session_id = path.into_inner();
+
+    let charged = pos_service.charge(&session_id, req.into_inner(), &merchant).await
+        .and_then(|payment| payment_service.with_qr_code(payment));
+    match charged {
+        Ok(payment) => {
+            tracing::info!(payment_id = %payment.id, session_id = %session_id, "POS charge created");
+            Ok(HttpResponse::Ok().json(PaymentResponse {
+                success: true,
+                data: Some(payment),
+                error: None,
+            }))
+        }
+        Err(e) => Ok(pos_error(e)),
+    }
+}
+
+// Ответ на ошибку операции со статическим QR
+fn static_qr_error(e: anyhow::Error) -> HttpResponse {
+    if let Some(errors) = e.downcast_ref::<ValidationErrors>() {
+        return validation_failed(errors);
+    }
+    if let Some(response) = risk_rejection(&e) {
+        return response;
+    }
+    let body = serde_json::json!({"success": false, "error": e.to_string()});
+    match e.downcast_ref::<StaticQrError>() {
+        Some(StaticQrError::NotFound) => HttpResponse::NotFound().json(body),
+        None => HttpResponse::BadRequest().json(body),
+    }
+}
+
+// POST: Создать статический QR мерчанта (сумма выбирается при сканировании)
+async fn static_qr_create(
+    payment_service: web::Data<PaymentService>,
+    static_qr_service: web::Data<StaticQrService>,
+    http_req: HttpRequest,
+    req: web::Json<CreateStaticQrRequest>,
+) -> Result<HttpResponse> {
+    let merchant = require_merchant(&http_req, payment_service.config())?;
+
+    match static_qr_service.create(req.into_inner(), &merchant).await {
+        Ok(static_qr) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": static_qr
+        }))),
+        Err(e) => Ok(static_qr_error(e)),
+    }
+}
+
+// GET: Статический QR и последние оплаты по нему
+async fn static_qr_get(
+    payment_service: web::Data<PaymentService>,
+    static_qr_service: web::Data<StaticQrService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    let merchant = require_merchant(&http_req, payment_service.config())?;
+
+    match static_qr_service.get_for_merchant(&path.into_inner(), &merchant).await {
+        Ok((static_qr, payments)) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": {"static_qr": static_qr, "payments": payments}
+        }))),
+        Err(e) => Ok(static_qr_error(e)),
+    }
+}
+
+// GET: Метаданные Solana Pay для статического QR
+async fn static_qr_transaction_get(
+    payment_service: web::Data<PaymentService>,
+    static_qr_service: web::Data<StaticQrService>,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    match static_qr_service.get(&path.into_inner()).await {
+        Ok(static_qr) => Ok(HttpResponse::Ok()
+            .append_header(("Access-Control-Allow-Origin", "*"))
+            .json(TransactionRequestGet {
+                icon: payment_service.config().merchant_icon(Some(&static_qr.merchant_id))
+                    .unwrap_or_else(|| actions::DEFAULT_ICON.to_string()),
+                label: static_qr.label,
+            })),
+        Err(e) => Ok(static_qr_error(e)),
+    }
+}
+
+#[derive(Deserialize)]
+struct StaticQrScanQuery {
+    /// Сумма для этого сканирования (иначе — сумма по умолчанию из QR)
+    amount: Option<f64>,
+    #[serde(default)]
+    part: TransactionPart,
+    locale: Option<String>,
+}
+
+// POST: Сканирование статического QR — новый платеж и транзакция для него
+async fn static_qr_transaction_post(
+    static_qr_service: web::Data<StaticQrService>,
+    payment_service: web::Data<PaymentService>,
+    sponsorship_service: web::Data<SponsorshipService>,
+    path: web::Path<String>,
+    query: web::Query<StaticQrScanQuery>,
+    req: web::Json<TransactionRequestPost>,
+) -> Result<HttpResponse> {
+    if let Err(errors) = req.validate() {
+        return Ok(validation_failed(&errors));
+    }
+    let static_qr_id = path.into_inner();
+
+    let payment = match static_qr_service.scan(&static_qr_id, query.amount, query.locale.clone()).await {
+        Ok(payment) => payment,
+        Err(e) => {
+            tracing::warn!(static_qr_id = %static_qr_id, error = %e, "Static QR scan rejected");
+            return Ok(static_qr_error(e));
+        }
+    };
+    tracing::info!(
+        static_qr_id = %static_qr_id, payment_id = %payment.id, amount = payment.amount, token = %payment.token,
+        "Static QR scanned"
+    );
+
+    let payment = match payment_service.screen_payer(payment, &req.account).await {
+        Ok(payment) => payment,
+        Err(e) => return Ok(static_qr_error(e)),
+    };
+
+    let fee_payer = sponsorship_service.fee_payer(&payment);
+    let sponsorship = fee_payer.is_some().then_some(sponsorship_service.get_ref());
+    if let Err(e) = payment_service.remember_transaction(
+        &payment, &req.account, query.part, payment.locale, None, fee_payer.as_ref(),
+    ).await {
+        tracing::warn!(payment_id = %payment.id, error = %e, "Failed to remember issued transaction");
+    }
+    Ok(transaction_response(&payment_service, &payment, &req.account, query.part, payment.locale, None, sponsorship).await)
+}
+
+// Ответ на ошибку операции с пулом платежей
+fn pool_error(e: anyhow::Error) -> HttpResponse {
+    if let Some(errors) = e.downcast_ref::<ValidationErrors>() {
+        return validation_failed(errors);
+    }
+    if let Some(response) = risk_rejection(&e) {
+        return response;
+    }
+    let body = serde_json::json!({"success": false, "error": e.to_string()});
+    match e.downcast_ref::<PoolError>() {
+        Some(PoolError::NotFound) => HttpResponse::NotFound().json(body),
+        Some(PoolError::Exhausted) => HttpResponse::Conflict().json(
+            serde_json::json!({"success": false, "error": e.to_string(), "code": "pool_exhausted"}),
+        ),
+        None => HttpResponse::BadRequest().json(body),
+    }
+}
+
+// POST: Заготовить пул платежей на одну сумму (распродажи: при оформлении заказа
+// платеж только выдается из пула)
+async fn pool_create(
+    payment_service: web::Data<PaymentService>,
+    pool_service: web::Data<PoolService>,
+    http_req: HttpRequest,
+    req: web::Json<CreatePoolRequest>,
+) -> Result<HttpResponse> {
+    let merchant = require_merchant(&http_req, payment_service.config())?;
+
+    match pool_service.create(req.into_inner(), &merchant).await {
+        Ok(pool) => Ok(HttpResponse::Ok().json(serde_json::json!({"success": true, "data": pool}))),
+        Err(e) => {
+            tracing::warn!(merchant = %merchant.id, error = %e, "Payment pool creation failed");
+            Ok(pool_error(e))
+        }
+    }
+}
+
+// GET: Сколько платежей осталось в пуле
+async fn pool_get(
+    payment_service: web::Data<PaymentService>,
+    pool_service: web::Data<PoolService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    let merchant = require_merchant(&http_req, payment_service.config())?;
+
+    match pool_service.get(&path.into_inner(), &merchant).await {
+        Ok(pool) => Ok(HttpResponse::Ok().json(serde_json::json!({"success": true, "data": pool}))),
+        Err(e) => Ok(pool_error(e)),
+    }
+}
+
+// POST: Выдать платеж из пула (ответ как у создания платежа, с QR кодами)
+async fn pool_claim(
+    payment_service: web::Data<PaymentService>,
+    pool_service: web::Data<PoolService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    let merchant = require_merchant(&http_req, payment_service.config())?;
+
+    match pool_service.claim(&path.into_inner(), &merchant).await {
+        Ok(payment) => {
+            let mut response = HttpResponse::Ok();
+            sign_payload(&mut response, payment_service.payload_signer(), &payment);
+            Ok(response.json(PaymentResponse {
+                success: true,
+                data: Some(payment),
+                error: None,
+            }))
+        }
+        Err(e) => Ok(pool_error(e)),
+    }
+}
+
+// DELETE: Удалить пул; невыданные заготовки пропадают
+async fn pool_delete(
+    payment_service: web::Data<PaymentService>,
+    pool_service: web::Data<PoolService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    let merchant = require_merchant(&http_req, payment_service.config())?;
+
+    match pool_service.delete(&path.into_inner(), &merchant).await {
+        Ok(discarded) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": {"discarded": discarded}
+        }))),
+        Err(e) => Ok(pool_error(e)),
+    }
+}
+
+// Ответ на ошибку операции с webhooks
+fn webhook_error(e: anyhow::Error) -> HttpResponse {
+    if let Some(errors) = e.downcast_ref::<ValidationErrors>() {
+        return validation_failed(errors);
+    }
+    let body = serde_json::json!({"success": false, "error": e.to_string()});
+    match e.downcast_ref::<WebhookError>() {
+        Some(WebhookError::EndpointNotFound | WebhookError::DeliveryNotFound) => HttpResponse::NotFound().json(body),
+        None => HttpResponse::BadRequest().json(body),
+    }
+}
+
+// POST: Зарегистрировать webhook endpoint (секрет подписи возвращается один раз)
+async fn webhook_register(
+    payment_service: web::Data<PaymentService>,
+    webhook_service: web::Data<WebhookService>,
+    http_req: HttpRequest,
+    req: web::Json<RegisterWebhookRequest>,
+) -> Result<HttpResponse> {
+    let merchant = require_merchant(&http_req, payment_service.config())?;
+    let api_version = http_req.headers().get(events::API_VERSION_HEADER).and_then(|v| v.to_str().ok());
+
+    match webhook_service.register(req.into_inner(), api_version, &merchant).await {
+        Ok(registered) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": registered
+        }))),
+        Err(e) => Ok(webhook_error(e)),
+    }
+}
+
+#[derive(Deserialize)]
+struct EventSchemaQuery {
+    version: Option<u32>,
+}
+
+// GET: JSON Schema тела webhook доставки (по умолчанию текущей версии)
+async fn event_schema(query: web::Query<EventSchemaQuery>) -> Result<HttpResponse> {
+    let version = query.version.unwrap_or(events::CURRENT_API_VERSION);
+    match events::schema(version) {
+        Some(schema) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "data": {"api_version": version, "supported_versions": events::API_VERSIONS, "schema": schema}
+        }))),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "success": false, "error": format!("Unknown event API version {}", version)
+        }))),
+    }
+}
+
+// GET: Webhook endpoints мерчанта
+async fn webhook_list(
+    payment_service: web::Data<PaymentService>,
+    webhook_service: web::Data<WebhookService>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let merchant = require_merchant(&http_req, payment_service.config())?;
+
+    match webhook_service.list(&merchant).await {
+        Ok(endpoints) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": endpoints
+        }))),
+        Err(e) => Ok(webhook_error(e)),
+    }
+}
+
+// DELETE: Удалить webhook endpoint
+async fn webhook_delete(
+    payment_service: web::Data<PaymentService>,
+    webhook_service: web::Data<WebhookService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    let merchant = require_merchant(&http_req, payment_service.config())?;
+
+    match webhook_service.delete(&path.into_inner(), &merchant).await {
+        Ok(()) => Ok(HttpResponse::Ok().json(serde_json::json!({"success": true}))),
+        Err(e) => Ok(webhook_error(e)),
+    }
+}
+
+// GET: История доставок на endpoint
+async fn webhook_deliveries(
+    payment_service: web::Data<PaymentService>,
+    webhook_service: web::Data<WebhookService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    let merchant = require_merchant(&http_req, payment_service.config())?;
+
+    match webhook_service.deliveries(&path.into_inner(), &merchant).await {
+        Ok(deliveries) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": deliveries
+        }))),
+        Err(e) => Ok(webhook_error(e)),
+    }
+}
+
+// POST: Повторно отправить доставку вручную
+async fn webhook_redeliver(
+    payment_service: web::Data<PaymentService>,
+    webhook_service: web::Data<WebhookService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+    req: web::Json<RedeliverRequest>,
+) -> Result<HttpResponse> {
+    let merchant = require_merchant(&http_req, payment_service.config())?;
+
+    match webhook_service.redeliver(&path.into_inner(), &req.delivery_id, &merchant).await {
+        Ok(delivery) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": delivery
+        }))),
+        Err(e) => Ok(webhook_error(e)),
+    }
+}
+
+// Кто запрашивает данные: X-Admin-Key — администратор (все данные), иначе мерчант по X-API-Key
+fn resolve_viewer(http_req: &HttpRequest, config: &Config) -> Result<Viewer, AuthError> {
+    if http_req.headers().contains_key(ADMIN_KEY_HEADER) {
+        require_admin(http_req, config)?;
+        Ok(Viewer::Admin)
+    } else {
+        Ok(Viewer::Merchant(require_merchant(http_req, config)?.id.clone()))
+    }
+}
+
+// POST: GraphQL для дашбордов (X-API-Key — данные мерчанта, X-Admin-Key — все данные)
+async fn graphql(
+    payment_service: web::Data<PaymentService>,
+    schema: web::Data<DashboardSchema>,
+    http_req: HttpRequest,
+    req: web::Json<async_graphql::Request>,
+) -> Result<HttpResponse> {
+    let viewer = resolve_viewer(&http_req, payment_service.config())?;
+    let response = schema.execute(req.into_inner().data(viewer)).await;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+// GET: Доход от комиссий по токенам, дням и мерчантам
+async fn admin_revenue(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    match payment_service.revenue_report().await {
+        Ok(report) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": report
+        }))),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false, "error": e.to_string()
+        }))),
+    }
+}
+
+#[derive(Deserialize)]
+struct SweepQuery {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+// POST: Перевести накопленные комиссии в холодное хранилище (?dry_run=true — только расчет)
+async fn admin_sweep(
+    payment_service: web::Data<PaymentService>,
+    sweep_service: web::Data<SweepService>,
+    http_req: HttpRequest,
+    query: web::Query<SweepQuery>,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    match sweep_service.run(query.dry_run).await {
+        Ok(record) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": record
+        }))),
+        Err(e) => Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false, "error": e.to_string()
+        }))),
+    }
+}
+
+// GET: Аудит sweep операций
+async fn admin_sweeps(
+    payment_service: web::Data<PaymentService>,
+    sweep_service: web::Data<SweepService>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    match sweep_service.history().await {
+        Ok(records) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": records
+        }))),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false, "error": e.to_string()
+        }))),
+    }
+}
+
+// POST: Рассчитать завершенные платежи с мерчантами (?dry_run=true — только котировки)
+async fn admin_settle(
+    payment_service: web::Data<PaymentService>,
+    settlement_service: web::Data<SettlementService>,
+    http_req: HttpRequest,
+    query: web::Query<SweepQuery>,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    match settlement_service.run(query.dry_run).await {
+        Ok(records) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": records
+        }))),
+        Err(e) => Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false, "error": e.to_string()
+        }))),
+    }
+}
+
+#[derive(Deserialize)]
+struct SettlementsQuery {
+    payment_id: Option<String>,
+}
+
+// GET: Расчеты по платежам. Мерчант видит свои, администратор — все
+async fn settlements(
+    payment_service: web::Data<PaymentService>,
+    settlement_service: web::Data<SettlementService>,
+    http_req: HttpRequest,
+    query: web::Query<SettlementsQuery>,
+) -> Result<HttpResponse> {
+    let viewer = resolve_viewer(&http_req, payment_service.config())?;
+
+    match settlement_service.history(query.payment_id.as_deref()).await {
+        Ok(records) => {
+            let records: Vec<_> = records.into_iter()
+                .filter(|record| match &viewer {
+                    Viewer::Admin => true,
+                    Viewer::Merchant(id) => &record.merchant_id == id,
+                })
+                .collect();
+            Ok(HttpResponse::Ok().json(serde_json::json!({
+                "success": true, "data": records
+            })))
+        }
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false, "error": e.to_string()
+        }))),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChangeStatusRequest {
+    status: PaymentStatus,
+    reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CancelPaymentRequest {
+    reason: Option<String>,
+}
+
+// Ответ на смену статуса платежа; недопустимый переход — 409
+fn status_change_response(result: anyhow::Result<Option<Payment>>, signer: Option<&PayloadSigner>) -> HttpResponse {
+    match result {
+        Ok(Some(payment)) => {
+            let mut response = HttpResponse::Ok();
+            sign_payload(&mut response, signer, &payment);
+            response.json(PaymentResponse { success: true, data: Some(payment), error: None })
+        }
+        Ok(None) => HttpResponse::NotFound().json(PaymentResponse {
+            success: false, data: None, error: Some("Payment not found".to_string()),
+        }),
+        Err(e) => {
+            let body = PaymentResponse { success: false, data: None, error: Some(e.to_string()) };
+            if let Some(errors) = e.downcast_ref::<ValidationErrors>() {
+                return validation_failed(errors);
+            }
+            match e.downcast_ref::<TransitionError>() {
+                Some(TransitionError::Illegal { .. }) => HttpResponse::Conflict().json(body),
+                None if e.is::<SandboxError>() || e.is::<EscrowError>() => HttpResponse::Conflict().json(body),
+                None => HttpResponse::InternalServerError().json(body),
+            }
+        }
+    }
+}
+
+// POST: Завершить sandbox платеж мерчанта без транзакции (интеграционное тестирование)
+async fn simulate_complete(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    let merchant = require_merchant(&http_req, payment_service.config())?;
+
+    let result = payment_service.simulate_completion(&path.into_inner(), &merchant.id).await;
+    Ok(status_change_response(result, payment_service.payload_signer()))
+}
+
+// POST: Отменить неоплаченный платеж мерчанта
+async fn cancel_payment(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+    req: Option<web::Json<CancelPaymentRequest>>,
+) -> Result<HttpResponse> {
+    let merchant = require_merchant(&http_req, payment_service.config())?;
+    let reason = req.and_then(|req| req.into_inner().reason);
+
+    let result = payment_service.change_status(
+        &path.into_inner(), Some(&merchant.id), PaymentStatus::Cancelled, reason.as_deref(),
+    ).await;
+    Ok(status_change_response(result, payment_service.payload_signer()))
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReleasePaymentRequest {
+    /// Вместо кошелька, с которого пришла оплата
+    refund_address: Option<String>,
+}
+
+// POST: Перевести удержанные средства мерчанту (платеж становится completed)
+async fn capture_payment(
+    payment_service: web::Data<PaymentService>,
+    escrow_service: web::Data<EscrowService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    let merchant = require_merchant(&http_req, payment_service.config())?;
+
+    let result = escrow_service.capture(&path.into_inner(), Some(&merchant.id)).await;
+    Ok(status_change_response(result, payment_service.payload_signer()))
+}
+
+// POST: Вернуть удержанные средства плательщику (платеж становится released)
+async fn release_payment(
+    payment_service: web::Data<PaymentService>,
+    escrow_service: web::Data<EscrowService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+    req: Option<web::Json<ReleasePaymentRequest>>,
+) -> Result<HttpResponse> {
+    let merchant = require_merchant(&http_req, payment_service.config())?;
+    let refund_address = req.and_then(|req| req.into_inner().refund_address);
+
+    let result = escrow_service.release(&path.into_inner(), Some(&merchant.id), refund_address.as_deref()).await;
+    Ok(status_change_response(result, payment_service.payload_signer()))
+}
+
+fn disputes_error(e: anyhow::Error) -> HttpResponse {
+    if let Some(errors) = e.downcast_ref::<ValidationErrors>() {
+        return validation_failed(errors);
+    }
+    let body = serde_json::json!({"success": false, "error": e.to_string()});
+    match e.downcast_ref::<DisputeError>() {
+        Some(DisputeError::NotFound) => HttpResponse::NotFound().json(body),
+        Some(_) => HttpResponse::Conflict().json(body),
+        None if e.is::<TransitionError>() || e.is::<EscrowError>() => HttpResponse::Conflict().json(body),
+        None => HttpResponse::InternalServerError().json(body),
+    }
+}
+
+// POST: Открыть спор по оплаченному платежу. Без X-API-Key — от плательщика,
+// с ключом — от мерчанта (только по своему платежу)
+async fn open_dispute(
+    payment_service: web::Data<PaymentService>,
+    dispute_service: web::Data<DisputeService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+    req: web::Json<OpenDisputeRequest>,
+) -> Result<HttpResponse> {
+    let merchant = resolve_merchant(&http_req, payment_service.config())?;
+
+    match dispute_service.open(&path.into_inner(), merchant.as_ref().map(|m| m.id.as_str()), req.into_inner()).await {
+        Ok(Some(dispute)) => Ok(HttpResponse::Created().json(serde_json::json!({
+            "success": true, "data": dispute
+        }))),
+        Ok(None) => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "success": false, "error": "Payment not found"
+        }))),
+        Err(e) => Ok(disputes_error(e)),
+    }
+}
+
+#[derive(Deserialize)]
+struct DisputesQuery {
+    status: Option<DisputeStatus>,
+    payment_id: Option<String>,
+}
+
+// GET: Споры по платежам мерчанта, новые первыми
+async fn merchant_disputes(
+    payment_service: web::Data<PaymentService>,
+    dispute_service: web::Data<DisputeService>,
+    http_req: HttpRequest,
+    query: web::Query<DisputesQuery>,
+) -> Result<HttpResponse> {
+    let merchant = require_merchant(&http_req, payment_service.config())?;
+    let query = query.into_inner();
+    let filter = DisputeFilter {
+        status: query.status,
+        merchant_id: Some(merchant.id.clone()),
+        payment_id: query.payment_id,
+    };
+
+    match dispute_service.list(&filter).await {
+        Ok(disputes) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": disputes
+        }))),
+        Err(e) => Ok(disputes_error(e)),
+    }
+}
+
+// GET: Споры всех мерчантов (?status=open, ?merchant_id=, ?payment_id=)
+async fn admin_disputes(
+    payment_service: web::Data<PaymentService>,
+    dispute_service: web::Data<DisputeService>,
+    http_req: HttpRequest,
+    query: web::Query<DisputeFilter>,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    match dispute_service.list(&query).await {
+        Ok(disputes) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": disputes
+        }))),
+        Err(e) => Ok(disputes_error(e)),
+    }
+}
+
+// GET: Спор с журналом действий
+async fn admin_dispute(
+    payment_service: web::Data<PaymentService>,
+    dispute_service: web::Data<DisputeService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    match dispute_service.get(&path.into_inner()).await {
+        Ok(dispute) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": dispute
+        }))),
+        Err(e) => Ok(disputes_error(e)),
+    }
+}
+
+// POST: Решить спор: refund (возврат плательщику) или deny
+async fn admin_resolve_dispute(
+    payment_service: web::Data<PaymentService>,
+    dispute_service: web::Data<DisputeService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+    req: web::Json<ResolveDisputeRequest>,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    match dispute_service.resolve(&path.into_inner(), req.into_inner()).await {
+        Ok(dispute) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": dispute
+        }))),
+        Err(e) => Ok(disputes_error(e)),
+    }
+}
+
+// POST: Сменить статус платежа вручную (например, Refunded после возврата средств);
+// допускаются только переходы конечного автомата
+async fn admin_change_status(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+    req: web::Json<ChangeStatusRequest>,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    let result = payment_service.change_status(
+        &path.into_inner(), None, req.status, req.reason.as_deref(),
+    ).await;
+    Ok(status_change_response(result, payment_service.payload_signer()))
+}
+
+// GET: Аудит совпадений проверки адресов (?payment_id= — по одному платежу)
+async fn admin_screenings(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+    query: web::Query<SettlementsQuery>,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    match payment_service.screening().history(query.payment_id.as_deref()).await {
+        Ok(records) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": records
+        }))),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false, "error": e.to_string()
+        }))),
+    }
+}
+
+// GET: Очередь сборки транзакций — слоты, глубина очереди и отказы
+async fn admin_transaction_builds(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": true, "data": payment_service.build_limiter().stats()
+    })))
+}
+
+// GET: Состояние узлов RPC пула: circuit breaker и доля ошибок
+async fn admin_rpc_endpoints(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": true, "data": payment_service.rpc().endpoint_stats()
+    })))
+}
+
+// Ответ на ошибку admin операции с лимитами мерчанта
+fn limits_error(e: anyhow::Error) -> HttpResponse {
+    if let Some(errors) = e.downcast_ref::<ValidationErrors>() {
+        return validation_failed(errors);
+    }
+    risk_rejection(&e).unwrap_or_else(|| HttpResponse::InternalServerError().json(serde_json::json!({
+        "success": false, "error": e.to_string()
+    })))
+}
+
+// GET: Лимиты мерчанта, переопределение и использование за 24 часа
+async fn admin_merchant_limits(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    match payment_service.limits().status(&path.into_inner()).await {
+        Ok(status) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": status
+        }))),
+        Err(e) => Ok(limits_error(e)),
+    }
+}
+
+// PUT: Временно переопределить лимиты мерчанта (заменяют лимиты из конфигурации целиком)
+async fn admin_override_limits(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+    req: web::Json<LimitOverrideRequest>,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    match payment_service.limits().set_override(&path.into_inner(), req.into_inner()).await {
+        Ok(limit_override) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": limit_override
+        }))),
+        Err(e) => Ok(limits_error(e)),
+    }
+}
+
+// DELETE: Вернуть лимиты мерчанта из конфигурации
+async fn admin_clear_limits_override(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    match payment_service.limits().clear_override(&path.into_inner()).await {
+        Ok(removed) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "removed": removed
+        }))),
+        Err(e) => Ok(limits_error(e)),
+    }
+}
+
+fn discounts_error(e: anyhow::Error) -> HttpResponse {
+    if let Some(errors) = e.downcast_ref::<ValidationErrors>() {
+        return validation_failed(errors);
+    }
+    let body = serde_json::json!({"success": false, "error": e.to_string()});
+    match e.downcast_ref::<DiscountError>() {
+        Some(DiscountError::NotFound) => HttpResponse::NotFound().json(body),
+        Some(DiscountError::AlreadyExists(_)) => HttpResponse::Conflict().json(body),
+        _ => HttpResponse::InternalServerError().json(body),
+    }
+}
+
+// GET: Коды скидок с числом использований
+async fn admin_discounts(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    match payment_service.discounts().list().await {
+        Ok(discounts) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": discounts
+        }))),
+        Err(e) => Ok(discounts_error(e)),
+    }
+}
+
+// POST: Создать код скидки (процент или фиксированная сумма, срок, лимит использований)
+async fn admin_create_discount(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+    req: web::Json<CreateDiscountRequest>,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    match payment_service.discounts().create(req.into_inner()).await {
+        Ok(discount) => Ok(HttpResponse::Created().json(serde_json::json!({
+            "success": true, "data": discount
+        }))),
+        Err(e) => Ok(discounts_error(e)),
+    }
+}
+
+// GET: Код скидки и платежи, которые его использовали
+async fn admin_discount(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    match payment_service.discounts().details(&path.into_inner()).await {
+        Ok(details) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": details
+        }))),
+        Err(e) => Ok(discounts_error(e)),
+    }
+}
+
+// DELETE: Выключить код скидки (история использований сохраняется)
+async fn admin_deactivate_discount(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    match payment_service.discounts().deactivate(&path.into_inner()).await {
+        Ok(discount) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": discount
+        }))),
+        Err(e) => Ok(discounts_error(e)),
+    }
+}
+
+fn tenants_error(e: anyhow::Error) -> HttpResponse {
+    if let Some(errors) = e.downcast_ref::<ValidationErrors>() {
+        return validation_failed(errors);
+    }
+    let body = serde_json::json!({"success": false, "error": e.to_string()});
+    match e.downcast_ref::<TenantError>() {
+        Some(TenantError::NotFound | TenantError::ApiKeyNotFound) => HttpResponse::NotFound().json(body),
+        Some(TenantError::ApiKeyTaken | TenantError::IdTaken(_) | TenantError::ConfigManaged(_)) => {
+            HttpResponse::Conflict().json(body)
+        }
+        None => HttpResponse::InternalServerError().json(body),
+    }
+}
+
+// GET: Мерчанты с источником настроек (API ключи скрыты)
+async fn admin_tenants(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    match payment_service.tenants().list().await {
+        Ok(tenants) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": tenants
+        }))),
+        Err(e) => Ok(tenants_error(e)),
+    }
+}
+
+// POST: Создать мерчанта или заменить его настройки (кошелек комиссий, токены, оформление)
+async fn admin_upsert_tenant(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+    req: web::Json<MerchantConfig>,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    match payment_service.tenants().upsert(req.into_inner()).await {
+        Ok(tenant) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": tenant
+        }))),
+        Err(e) => Ok(tenants_error(e)),
+    }
+}
+
+// GET: Настройки мерчанта
+async fn admin_tenant(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    match payment_service.tenants().get(&path.into_inner()).await {
+        Ok(tenant) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": tenant
+        }))),
+        Err(e) => Ok(tenants_error(e)),
+    }
+}
+
+// DELETE: Удалить мерчанта, созданного через admin API (его ключ перестает действовать)
+async fn admin_delete_tenant(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    match payment_service.tenants().delete(&path.into_inner()).await {
+        Ok(tenant) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": tenant
+        }))),
+        Err(e) => Ok(tenants_error(e)),
+    }
+}
+
+// POST: Выпустить мерчанту новый API ключ (прежний, в том числе отозванный, перестает действовать)
+async fn admin_rotate_tenant_api_key(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    match payment_service.tenants().rotate_api_key(&path.into_inner()).await {
+        Ok(issued) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": issued
+        }))),
+        Err(e) => Ok(tenants_error(e)),
+    }
+}
+
+fn registration_error(e: anyhow::Error) -> HttpResponse {
+    let body = serde_json::json!({"success": false, "error": e.to_string()});
+    match e.downcast_ref::<RegistrationError>() {
+        Some(RegistrationError::Disabled) => HttpResponse::NotFound().json(body),
+        Some(RegistrationError::InvalidInviteCode) => HttpResponse::Forbidden().json(body),
+        None => tenants_error(e),
+    }
+}
+
+// POST: Самостоятельная регистрация мерчанта; API ключ (и секрет webhook) — только в этом ответе
+async fn register_merchant(
+    registration_service: web::Data<RegistrationService>,
+    req: web::Json<RegisterMerchantRequest>,
+) -> Result<HttpResponse> {
+    match registration_service.register(req.into_inner()).await {
+        Ok(registered) => Ok(HttpResponse::Created().json(serde_json::json!({
+            "success": true, "data": registered
+        }))),
+        Err(e) => Ok(registration_error(e)),
+    }
+}
+
+// POST: Выпустить новый API ключ вместо текущего (текущий перестает действовать сразу)
+async fn rotate_api_key(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let merchant = require_merchant(&http_req, payment_service.config())?;
+
+    match payment_service.tenants().rotate_api_key(&merchant.id).await {
+        Ok(issued) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": issued
+        }))),
+        Err(e) => Ok(tenants_error(e)),
+    }
+}
+
+// DELETE: Отозвать текущий API ключ; новый выпускает администратор
+async fn revoke_api_key(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let merchant = require_merchant(&http_req, payment_service.config())?;
+
+    match payment_service.tenants().revoke_api_key(&merchant.id).await {
+        Ok(tenant) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": tenant
+        }))),
+        Err(e) => Ok(tenants_error(e)),
+    }
+}
+
+// GET: Ключи мерчанта без секретов: права, срок действия, последнее использование
+async fn list_api_keys(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    let merchant = require_merchant(&http_req, payment_service.config())?;
+
+    match payment_service.tenants().api_keys(&merchant.id).await {
+        Ok(keys) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": keys
+        }))),
+        Err(e) => Ok(tenants_error(e)),
+    }
+}
+
+// POST: Выпустить дополнительный ключ (например, create_only для POS терминала); секрет — только в ответе
+async fn create_api_key(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+    req: web::Json<CreateApiKeyRequest>,
+) -> Result<HttpResponse> {
+    let merchant = require_merchant(&http_req, payment_service.config())?;
+
+    match payment_service.tenants().create_api_key(&merchant.id, req.into_inner()).await {
+        Ok(created) => Ok(HttpResponse::Created().json(serde_json::json!({
+            "success": true, "data": created
+        }))),
+        Err(e) => Ok(tenants_error(e)),
+    }
+}
+
+// DELETE: Отозвать дополнительный ключ
+async fn delete_api_key(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    let merchant = require_merchant(&http_req, payment_service.config())?;
+
+    match payment_service.tenants().delete_api_key(&merchant.id, &path.into_inner()).await {
+        Ok(()) => Ok(HttpResponse::Ok().json(serde_json::json!({"success": true}))),
+        Err(e) => Ok(tenants_error(e)),
+    }
+}
+
+#[derive(Deserialize)]
+struct PurgeArchiveQuery {
+    /// Удалить только платежи, пролежавшие в архиве не меньше N дней (по умолчанию — все)
+    #[serde(default)]
+    older_than_days: u32,
+}
+
+// POST: Удалить архивные платежи (необратимо)
+async fn admin_purge_archive(
+    payment_service: web::Data<PaymentService>,
+    retention_service: web::Data<RetentionService>,
+    http_req: HttpRequest,
+    query: web::Query<PurgeArchiveQuery>,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    match retention_service.purge(query.older_than_days).await {
+        Ok(purged) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": { "purged": purged }
+        }))),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false, "error": e.to_string()
+        }))),
+    }
+}
+
+// GET: Снимок всего хранилища (JSON, как файл storage.snapshot_path)
+async fn admin_export_storage(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    let snapshot = payment_service.storage().snapshot().await;
+    Ok(HttpResponse::Ok()
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"storage-{}.json\"", snapshot.created_at.format("%Y%m%dT%H%M%SZ")),
+        ))
+        .json(snapshot))
+}
+
+// GET: Кэш платежей — заполненность, попадания, промахи и доля попаданий
+async fn admin_storage_cache(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": true, "data": payment_service.storage().payment_cache_stats()
+    })))
+}
+
+// GET: Гистограммы задержек по маршрутам (с запуска процесса) и квантили
+async fn admin_latency(
+    payment_service: web::Data<PaymentService>,
+    observability: web::Data<Observability>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": true, "data": observability.histograms().snapshot()
+    })))
+}
+
+// GET: Отставание и счетчики реплики чтения списков и выгрузок
+async fn admin_storage_replica(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": true, "data": payment_service.storage().payment_replica_stats()
+    })))
+}
+
+// POST: Заменить содержимое хранилища снимком (необратимо). Тело ограничено
+// server.max_json_body_bytes
+async fn admin_import_storage(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+    snapshot: web::Json<StorageSnapshot>,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    let snapshot = snapshot.into_inner();
+    let payments = snapshot.payments.len();
+    match payment_service.storage().restore(snapshot).await {
+        Ok(()) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": { "payments": payments }
+        }))),
+        Err(e) => Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "success": false, "error": e.to_string()
+        }))),
+    }
+}
+
+#[derive(Deserialize)]
+struct ReconcileQuery {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+// POST: Завершить ожидающие платежи, оплаченные в блокчейне, но не верифицированные
+// (?dry_run=true — только найти транзакции)
+async fn admin_reconcile(
+    payment_service: web::Data<PaymentService>,
+    reconciliation_service: web::Data<ReconciliationService>,
+    http_req: HttpRequest,
+    query: web::Query<ReconcileQuery>,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    match reconciliation_service.run(query.dry_run).await {
+        Ok(report) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": report
+        }))),
+        Err(e) if e.is::<ReconciliationBusy>() => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "success": false, "error": e.to_string()
+        }))),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false, "error": e.to_string()
+        }))),
+    }
+}
+
+async fn admin_jobs(
+    payment_service: web::Data<PaymentService>,
+    job_scheduler: web::Data<JobScheduler>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": true, "data": job_scheduler.status()
+    })))
+}
+
+/// Выполнить задачу сейчас, вне расписания (и для выключенной задачи)
+async fn admin_run_job(
+    payment_service: web::Data<PaymentService>,
+    job_scheduler: web::Data<JobScheduler>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    let name = path.into_inner();
+    match job_scheduler.run_now(&name).await {
+        Some(JobOutcome::Skipped) => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "success": false, "error": format!("Job {} is already running", name)
+        }))),
+        Some(outcome) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": outcome
+        }))),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "success": false, "error": format!("Unknown job {}", name)
+        }))),
+    }
+}
+
+// GET: Токены реестра с источником (конфигурация, token list, блокчейн)
+async fn admin_tokens(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": true, "data": payment_service.config().tokens.list()
+    })))
+}
+
+// POST: Добавить токен по символу из token list или адресу mint (метаданные из блокчейна)
+async fn admin_add_token(
+    payment_service: web::Data<PaymentService>,
+    http_req: HttpRequest,
+    req: web::Json<AddTokenRequest>,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    let tokens = &payment_service.config().tokens;
+    match tokens.add(req.into_inner(), payment_service.rpc().as_ref()).await {
+        Ok(token) => Ok(HttpResponse::Created().json(serde_json::json!({
+            "success": true, "data": token
+        }))),
+        Err(e) => {
+            if let Some(errors) = e.downcast_ref::<ValidationErrors>() {
+                return Ok(validation_failed(errors));
+            }
+            let body = serde_json::json!({"success": false, "error": e.to_string()});
+            Ok(match e.downcast_ref::<TokenError>() {
+                Some(TokenError::NotFound(_)) => HttpResponse::NotFound().json(body),
+                Some(TokenError::AlreadyRegistered { .. } | TokenError::SymbolTaken(_)) => HttpResponse::Conflict().json(body),
+                Some(TokenError::NotAMint(_)) => HttpResponse::BadRequest().json(body),
+                // Недоступны RPC или token list
+                None => HttpResponse::BadGateway().json(body),
+            })
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AdminDeliveriesQuery {
+    status: Option<DeliveryStatus>,
+    /// Сколько доставок вернуть (по умолчанию 100)
+    limit: Option<usize>,
+}
+
+// GET: Доставки webhook всех мерчантов (?status=failed — только неудачные)
+async fn admin_webhook_deliveries(
+    payment_service: web::Data<PaymentService>,
+    webhook_service: web::Data<WebhookService>,
+    http_req: HttpRequest,
+    query: web::Query<AdminDeliveriesQuery>,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    match webhook_service.all_deliveries(query.status, query.limit.unwrap_or(100)).await {
+        Ok(deliveries) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": deliveries
+        }))),
+        Err(e) => Ok(webhook_error(e)),
+    }
+}
+
+// POST: Повторно отправить доставку webhook любого мерчанта
+async fn admin_webhook_redeliver(
+    payment_service: web::Data<PaymentService>,
+    webhook_service: web::Data<WebhookService>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    require_admin(&http_req, payment_service.config())?;
+
+    match webhook_service.redeliver_any(&path.into_inner()).await {
+        Ok(delivery) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true, "data": delivery
+        }))),
+        Err(e) => Ok(webhook_error(e)),
+    }
+}
+
+const USAGE: &str = "Usage: cryptonow [--config PATH]";
+
+/// Разобрать `--config <path>` (или `--config=<path>`) из аргументов командной строки
+fn parse_config_flag(mut args: impl Iterator<Item = String>) -> Result<Option<String>, String> {
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return match args.next() {
+                Some(path) if !path.starts_with("--") => Ok(Some(path)),
+                _ => Err(format!("--config requires a path argument\n{}", USAGE)),
+            };
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            if path.is_empty() {
+                return Err(format!("--config requires a path argument\n{}", USAGE));
+            }
+            return Ok(Some(path.to_string()));
+        }
+    }
+    Ok(None)
+}
+
+/// Сервисы, работу которых выполняют фоновые задачи
+struct JobServices {
+    payments: PaymentService,
+    retention: RetentionService,
+    reconciliation: ReconciliationService,
+    sweep: SweepService,
+    settlement: SettlementService,
+    escrow: EscrowService,
+    receipt_nft: ReceiptNftService,
+    snapshot: SnapshotService,
+}
+
+/// Фоновые задачи по расписаниям `jobs.<имя>`
+fn build_jobs(config: &Config, leader: LeaderElection, services: &JobServices) -> anyhow::Result<JobScheduler> {
+    let mut scheduler = JobScheduler::new(leader);
+
+    let payments = services.payments.clone();
+    scheduler.add("expiration", &config.jobs["expiration"], Some("payment-expiration"), move || {
+        let payments = payments.clone();
+        async move { payments.expire_due_payments().await.map(|_| ()) }
+    })?;
+    let retention = services.retention.clone();
+    scheduler.add("retention", &config.jobs["retention"], Some("payment-retention"), move || {
+        let retention = retention.clone();
+        async move { retention.archive().await.map(|_| ()) }
+    })?;
+    // Кэш blockhash у каждой реплики свой
+    let rpc = services.payments.rpc().clone();
+    scheduler.add("blockhash", &config.jobs["blockhash"], None, move || {
+        let rpc = rpc.clone();
+        async move { rpc.refresh_blockhash().await.map(|_| ()) }
+    })?;
+    let reconciliation = services.reconciliation.clone();
+    scheduler.add("reconciliation", &config.jobs["reconciliation"], Some("payment-reconciliation"), move || {
+        let reconciliation = reconciliation.clone();
+        async move { reconciliation.run(false).await.map(|_| ()) }
+    })?;
+    let sweep = services.sweep.clone();
+    scheduler.add("sweep", &config.jobs["sweep"], Some("fee-sweep"), move || {
+        let sweep = sweep.clone();
+        async move { sweep.run(false).await.map(|_| ()) }
+    })?;
+    let settlement = services.settlement.clone();
+    scheduler.add("settlement", &config.jobs["settlement"], Some("merchant-settlement"), move || {
+        let settlement = settlement.clone();
+        async move { settlement.run(false).await.map(|_| ()) }
+    })?;
+    let escrow = services.escrow.clone();
+    scheduler.add("escrow", &config.jobs["escrow"], Some("escrow-release"), move || {
+        let escrow = escrow.clone();
+        async move { escrow.release_expired().await.map(|_| ()) }
+    })?;
+    let receipt_nft = services.receipt_nft.clone();
+    scheduler.add("receipt_nft", &config.jobs["receipt_nft"], Some("receipt-nft"), move || {
+        let receipt_nft = receipt_nft.clone();
+        async move { receipt_nft.mint_pending().await.map(|_| ()) }
+    })?;
+    // Хранилище у каждой реплики свое
+    let snapshot = services.snapshot.clone();
+    scheduler.add("storage_snapshot", &config.jobs["storage_snapshot"], None, move || {
+        let snapshot = snapshot.clone();
+        async move { snapshot.write().await.map(|_| ()) }
+    })?;
+
+    Ok(scheduler)
+}
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    dotenv::dotenv().ok();
+
+    let config_path = match parse_config_flag(std::env::args().skip(1)) {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(2);
+        }
+    };
+    let config = Config::load(config_path.as_deref()).expect("Failed to load config");
+    Logger::init(config.server.log_format, &config.server.log_filter).expect("Failed to initialize logging");
+    error_reporting::init(&config.error_reporting).expect("Failed to initialize error reporting");
+    tracing::info!(version = env!("CARGO_PKG_VERSION"), "Starting CryptoNow server");
+    let server_signer = ServerSigner::load(config.solana.server_keypair_path.as_deref())
+        .expect("Failed to load server keypair");
+    let payment_service = PaymentService::new(config.clone()).await.expect("Failed to initialize payment service");
+    // Удержания хранятся на hot wallet
+    let payment_service = match server_signer.as_ref().filter(|_| config.escrow.enabled) {
+        Some(signer) => payment_service.with_escrow_account(signer.pubkey()),
+        None => payment_service,
+    };
+    let snapshot_service = SnapshotService::new(
+        payment_service.storage().clone(),
+        config.storage.snapshot_path.as_deref(),
+    );
+    snapshot_service.load().await.expect("Failed to restore storage snapshot");
+    let leader = LeaderElection::from_config(&config.coordination).await
+        .expect("Failed to initialize lock backend");
+    let sweep_service = SweepService::new(
+        config.clone(),
+        payment_service.rpc().clone(),
+        payment_service.storage().clone(),
+        server_signer.clone(),
+    );
+    let settlement_service = SettlementService::new(
+        config.clone(),
+        payment_service.rpc().clone(),
+        payment_service.storage().clone(),
+        server_signer.clone(),
+    );
+    let escrow_service = EscrowService::new(payment_service.clone(), server_signer.clone());
+    let dispute_service = DisputeService::new(payment_service.clone(), escrow_service.clone());
+    let receipt_nft_service = ReceiptNftService::new(payment_service.clone(), server_signer.clone());
+    let sponsorship_service = SponsorshipService::new(config.clone(), payment_service.storage().clone(), server_signer);
+    let retention_service = RetentionService::new(config.clone(), payment_service.storage().clone());
+    PaymentWatcher::new(payment_service.clone()).spawn_scheduler(leader.clone());
+    let reconciliation_service = ReconciliationService::new(payment_service.clone());
+    let job_services = JobServices {
+        payments: payment_service.clone(),
+        retention: retention_service.clone(),
+        reconciliation: reconciliation_service.clone(),
+        sweep: sweep_service.clone(),
+        settlement: settlement_service.clone(),
+        escrow: escrow_service.clone(),
+        receipt_nft: receipt_nft_service,
+        snapshot: snapshot_service.clone(),
+    };
+    let job_scheduler = build_jobs(&config, leader.clone(), &job_services).expect("Failed to configure jobs");
+    job_scheduler.spawn();
+    let pos_service = PosService::new(payment_service.clone());
+    let static_qr_service = StaticQrService::new(payment_service.clone());
+    let pool_service = PoolService::new(payment_service.clone());
+    let registration_service = RegistrationService::new(payment_service.clone());
+    let webhook_service = payment_service.webhooks().clone();
+    let graphql_schema = build_schema(payment_service.clone());
+    let notification_service = NotificationService::new(config.clone())
+        .expect("Failed to initialize notifications");
+    OutboxDispatcher::new(
+        payment_service.storage().clone(),
+        webhook_service.clone(),
+        notification_service,
+        leader.clone(),
+    ).spawn();
+
+    let host = config.server.host.clone();
+    let port = config.server.port;
+
+    // gRPC API на отдельном порту, тот же PaymentService
+    if let Some(grpc_port) = config.server.grpc_port {
+        let addr = format!("{}:{}", host, grpc_port).parse()
+            .expect("Invalid gRPC listen address");
+        let grpc = GrpcPayments::new(payment_service.clone()).into_server();
+        tracing::info!(%addr, "gRPC API listening");
+        tokio::spawn(async move {
+            if let Err(e) = tonic::transport::Server::builder().add_service(grpc).serve(addr).await {
+                tracing::error!(error = %e, "gRPC server failed");
+            }
+        });
+    }
+
+    // Сертификат из файлов или выпущенный по ACME; подменяется без перезапуска
+    let cert_resolver = CertResolver::default();
+    if let (Some(cert_path), Some(key_path)) = (&config.server.tls_cert_path, &config.server.tls_key_path) {
+        cert_resolver.set(load_certified_key(cert_path, key_path).expect("Failed to load TLS certificate"));
+    }
+    let acme_challenges = AcmeChallenges::default();
+    if config.acme.enabled {
+        AcmeService::new(config.clone(), cert_resolver.clone(), acme_challenges.clone()).spawn();
+    }
+    let tls_config = config.serves_tls().then(|| server_config(cert_resolver.clone()));
+    let scheme = if tls_config.is_some() { "https" } else { "http" };
+
+    // Plain HTTP только редиректит на https адрес и отвечает на ACME HTTP-01
+    let redirect_server = match config.server.http_redirect_port {
+        Some(redirect_port) => {
+            let redirect = HttpsRedirect { base_url: config.base_url() };
+            tracing::info!(%host, port = redirect_port, to = %redirect.base_url, "Redirecting plain HTTP to HTTPS");
+            Some(
+                HttpServer::new(move || {
+                    App::new()
+                        .app_data(web::Data::new(redirect.clone()))
+                        .app_data(web::Data::new(acme_challenges.clone()))
+                        .route("/.well-known/acme-challenge/{token}", web::get().to(acme_challenge))
+                        .default_service(web::to(redirect_to_https))
+                })
+                    .bind(format!("{}:{}", host, redirect_port))?
+                    .run(),
+            )
+        }
+        None => None,
+    };
+
+    // Аудит действующей конфигурации: по строке на секцию, секреты скрыты
+    for (section, settings) in config.audit() {
+        tracing::info!(%section, %settings, "Effective configuration");
+    }
+    tracing::info!(%scheme, %host, port, profile = config.server.profile.as_str(), "Server starting");
+
+    let json_limits = JsonLimits::from_config(&config.server);
+    let rate_limiter = RateLimiter::from_config(&config.server);
+    let observability = Observability::from_config(&config.server);
+    let cors_origins = config.server.cors_origins.clone();
+    let tenants = config.tenants.clone();
+    let server = HttpServer::new(move || {
+        let cors = if cors_origins.iter().any(|origin| origin == "*") {
+            Cors::default().allow_any_origin()
+        } else {
+            cors_origins.iter().fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+                // Blinks встраиваются на любые сайты
+                .allowed_origin_fn(|_, request| actions::is_action_path(request.uri.path()))
+        };
+        let cors = cors
+            .allow_any_method()
+            .allow_any_header()
+            // Страница оплаты на другом домене опрашивает платеж с If-None-Match
+            .expose_headers([header::ETAG, header::LAST_MODIFIED])
+            .expose_headers(["X-Action-Version", "X-Blockchain-Ids", CORRELATION_ID_HEADER])
+            .max_age(3600);
+
+        App::new()
+            .app_data(web::Data::new(payment_service.clone()))
+            .app_data(web::Data::new(sweep_service.clone()))
+            .app_data(web::Data::new(settlement_service.clone()))
+            .app_data(web::Data::new(escrow_service.clone()))
+            .app_data(web::Data::new(dispute_service.clone()))
+            .app_data(web::Data::new(sponsorship_service.clone()))
+            .app_data(web::Data::new(retention_service.clone()))
+            .app_data(web::Data::new(reconciliation_service.clone()))
+            .app_data(web::Data::new(job_scheduler.clone()))
+            .app_data(web::Data::new(pos_service.clone()))
+            .app_data(web::Data::new(static_qr_service.clone()))
+            .app_data(web::Data::new(pool_service.clone()))
+            .app_data(web::Data::new(registration_service.clone()))
+            .app_data(web::Data::new(webhook_service.clone()))
+            .app_data(web::Data::new(graphql_schema.clone()))
+            .app_data(web::Data::new(json_limits))
+            .app_data(json_limits.json_config())
+            .app_data(web::Data::new(rate_limiter.clone()))
+            .app_data(web::Data::new(observability.clone()))
+            .app_data(web::Data::new(tenants.clone()))
+            .wrap(from_fn(authorize))
+            .wrap(from_fn(json_guard))
+            .wrap(from_fn(rate_limit))
+            .wrap(cors)
+            .wrap(from_fn(request_log))
+            .wrap(from_fn(observe))
+            .route("/", web::get().to(index))
+            .route("/actions.json", web::get().to(actions_json))
+            .route("/t/{code}", web::get().to(short_link_get))
+            .route("/t/{code}", web::post().to(short_link_post))
             .service(
                 web::scope("/api")
                     .route("/payment/create", web::post().to(create_payment))
+                    .route("/public-key", web::get().to(public_key))
+                    .route("/actions/pay/{id}", web::get().to(action_get))
+                    .route("/actions/pay/{id}", web::post().to(action_post))
                     .route("/payment/{id}", web::get().to(get_payment))
+                    .route("/payment/{id}/qr", web::get().to(get_payment_qr))
+                    .route("/payment/{id}/events", web::get().to(payment_events))
+                    .route("/payment/{id}/wait", web::get().to(wait_payment))
                     .route("/payment/{id}/transaction", web::get().to(transaction_get))
                     .route("/payment/{id}/transaction", web::post().to(transaction_post))
+                    .route("/payment/{id}/transaction/refresh", web::post().to(transaction_refresh))
+                    .route("/payment/{id}/preview", web::get().to(transaction_preview))
+                    .route("/payment/{id}/submit", web::post().to(transaction_submit))
                     .route("/payment/{id}/verify", web::post().to(verify_payment))
+                    .route("/payment/{id}/receipt", web::get().to(get_receipt))
+                    .route("/payment/{id}/receipt/nft", web::get().to(get_receipt_nft_metadata))
+                    .route("/payment/{id}/cancel", web::post().to(cancel_payment))
+                    .route("/payment/{id}/capture", web::post().to(capture_payment))
+                    .route("/payment/{id}/release", web::post().to(release_payment))
+                    .route("/payment/{id}/dispute", web::post().to(open_dispute))
+                    .route("/payment/{id}/simulate_complete", web::post().to(simulate_complete))
+                    .route("/payments", web::get().to(list_payments))
+                    .route("/debug/decode-transaction", web::post().to(decode_transaction))
+                    .route("/payments/lookup", web::get().to(lookup_payment))
+                    .route("/payments/status", web::post().to(payment_statuses))
+                    .route("/payments/export", web::get().to(export_payments))
+                    .route("/settlements", web::get().to(settlements))
+                    .route("/disputes", web::get().to(merchant_disputes))
+                    .route("/loyalty/{wallet}", web::get().to(get_loyalty))
+                    .route("/merchant/wallet/challenge", web::get().to(wallet_challenge))
+                    .route("/merchant/wallet/verify", web::post().to(wallet_verify))
+                    .route("/pos/session", web::post().to(pos_open_session))
+                    .route("/pos/session/{id}", web::get().to(pos_get_session))
+                    .route("/pos/session/{id}/charge", web::post().to(pos_charge))
+                    .route("/static", web::post().to(static_qr_create))
+                    .route("/static/{id}", web::get().to(static_qr_get))
+                    .route("/static/{id}/transaction", web::get().to(static_qr_transaction_get))
+                    .route("/static/{id}/transaction", web::post().to(static_qr_transaction_post))
+                    .route("/pools", web::post().to(pool_create))
+                    .route("/pools/{id}", web::get().to(pool_get))
+                    .route("/pools/{id}", web::delete().to(pool_delete))
+                    .route("/pools/{id}/claim", web::post().to(pool_claim))
+                    .route("/merchants/register", web::post().to(register_merchant))
+                    .route("/merchants/me/api-key/rotate", web::post().to(rotate_api_key))
+                    .route("/merchants/me/api-key", web::delete().to(revoke_api_key))
+                    .route("/merchants/me/api-keys", web::get().to(list_api_keys))
+                    .route("/merchants/me/api-keys", web::post().to(create_api_key))
+                    .route("/merchants/me/api-keys/{id}", web::delete().to(delete_api_key))
+                    .route("/graphql", web::post().to(graphql))
+                    .route("/webhooks", web::post().to(webhook_register))
+                    .route("/webhooks", web::get().to(webhook_list))
+                    .route("/webhooks/{id}", web::delete().to(webhook_delete))
+                    .route("/webhooks/{id}/deliveries", web::get().to(webhook_deliveries))
+                    .route("/webhooks/{id}/redeliver", web::post().to(webhook_redeliver))
+                    .route("/events/schema", web::get().to(event_schema))
+            )
+            .service(
+                web::scope("/admin")
+                    .route("/revenue", web::get().to(admin_revenue))
+                    .route("/sweep", web::post().to(admin_sweep))
+                    .route("/sweeps", web::get().to(admin_sweeps))
+                    .route("/settle", web::post().to(admin_settle))
+                    .route("/screenings", web::get().to(admin_screenings))
+                    .route("/rpc", web::get().to(admin_rpc_endpoints))
+                    .route("/builds", web::get().to(admin_transaction_builds))
+                    .route("/reconcile", web::post().to(admin_reconcile))
+                    .route("/webhooks/deliveries", web::get().to(admin_webhook_deliveries))
+                    .route("/webhooks/deliveries/{id}/redeliver", web::post().to(admin_webhook_redeliver))
+                    .route("/tokens", web::get().to(admin_tokens))
+                    .route("/tokens", web::post().to(admin_add_token))
+                    .route("/jobs", web::get().to(admin_jobs))
+                    .route("/jobs/{name}/run", web::post().to(admin_run_job))
+                    .route("/payments/{id}/status", web::post().to(admin_change_status))
+                    .route("/merchants", web::get().to(admin_tenants))
+                    .route("/merchants", web::post().to(admin_upsert_tenant))
+                    .route("/merchants/{id}", web::get().to(admin_tenant))
+                    .route("/merchants/{id}", web::delete().to(admin_delete_tenant))
+                    .route("/merchants/{id}/api-key/rotate", web::post().to(admin_rotate_tenant_api_key))
+                    .route("/merchants/{id}/limits", web::get().to(admin_merchant_limits))
+                    .route("/merchants/{id}/limits/override", web::put().to(admin_override_limits))
+                    .route("/merchants/{id}/limits/override", web::delete().to(admin_clear_limits_override))
+                    .route("/discounts", web::get().to(admin_discounts))
+                    .route("/discounts", web::post().to(admin_create_discount))
+                    .route("/discounts/{code}", web::get().to(admin_discount))
+                    .route("/discounts/{code}", web::delete().to(admin_deactivate_discount))
+                    .route("/disputes", web::get().to(admin_disputes))
+                    .route("/disputes/{id}", web::get().to(admin_dispute))
+                    .route("/disputes/{id}/resolve", web::post().to(admin_resolve_dispute))
+                    .route("/archive/purge", web::post().to(admin_purge_archive))
+                    .route("/storage/export", web::get().to(admin_export_storage))
+                    .route("/storage/import", web::post().to(admin_import_storage))
+                    .route("/storage/cache", web::get().to(admin_storage_cache))
+                    .route("/storage/replica", web::get().to(admin_storage_replica))
+                    .route("/latency", web::get().to(admin_latency))
             )
-    })
-        .bind(format!("{}:{}", host, port))?
-        .run()
-        .await
+    });
+
+    let server = server
+        .keep_alive(match config.server.keep_alive_secs {
+            0 => KeepAlive::Disabled,
+            secs => KeepAlive::Timeout(Duration::from_secs(secs)),
+        })
+        .client_request_timeout(Duration::from_millis(config.server.client_request_timeout_ms));
+    let server = match config.server.workers {
+        Some(workers) => server.workers(workers),
+        None => server,
+    };
+
+    let addr = format!("{}:{}", host, port);
+    let server = match tls_config {
+        Some(tls_config) => server.bind_rustls_021(addr, tls_config)?,
+        None => server.bind(addr)?,
+    }
+        .run();
+
+    let result = match redirect_server {
+        Some(redirect_server) => futures::try_join!(server, redirect_server).map(|_| ()),
+        None => server.await,
+    };
+    // Последний снимок после остановки: изменения после задачи storage_snapshot не теряются
+    if let Err(e) = snapshot_service.write().await {
+        tracing::error!(error = %e, "Failed to write storage snapshot on shutdown");
+    }
+    result
 }
\ No newline at end of file
@@ -1,4 +1,3 @@
-use solana_client::rpc_client::RpcClient;
 use solana_sdk::{
     commitment_config::CommitmentConfig,
     pubkey::Pubkey,
@@ -12,13 +11,19 @@ use spl_token::{
 };
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 use anyhow::Result;
 
 use crate::config::{Config, TokenConfig};
+use crate::display::to_base_units;
+use crate::rpc::{SolanaRpc, TransactionStatus};
+
+/// Таймаут поиска транзакции в блокчейне
+const VERIFY_TIMEOUT_SECS: u64 = 10;
 
 #[derive(Clone)]
 pub struct MultichainService {
-    pub solana_client: Arc<RpcClient>,
+    pub rpc: Arc<dyn SolanaRpc>,
     pub config: Config,
 }
 
@@ -29,26 +34,19 @@ pub struct TransferInstruction {
 }
 
 impl MultichainService {
-    pub fn new(config: Config) -> Self {
-        let commitment = CommitmentConfig::confirmed();
-        let solana_client = Arc::new(RpcClient::new_with_commitment(
-            config.solana.rpc_url.clone(),
-            commitment,
-        ));
-
-        Self {
-            solana_client,
-            config,
-        }
+    pub fn new(config: Config, rpc: Arc<dyn SolanaRpc>) -> Self {
+        Self { rpc, config }
     }
 
-    /// Создать инструкции для платежа с комиссией
+    /// Создать инструкции для платежа с комиссией, зафиксированной на платеже
+    /// (`fee`: кошелек, сумма, токен)
     pub async fn create_payment_instructions(
         &self,
         payer: &Pubkey,
         recipient: &Pubkey,
         amount: f64,
         token: &str,
+        (fee_recipient, fee_amount, fee_token): (&Pubkey, f64, &str),
     ) -> Result<Vec<TransferInstruction>> {
         let mut instructions = Vec::new();
 
@@ -61,15 +59,11 @@ impl MultichainService {
         ).await?;
         instructions.push(main_instruction);
 
-        // 2. Комиссия (всегда в USDC на твой кошелек)
-        let fee_recipient = Pubkey::from_str(&self.config.solana.fee_wallet)?;
-        let fee_instruction = self.create_transfer_instruction(
-            payer,
-            &fee_recipient,
-            self.config.solana.fee_amount,
-            &self.config.solana.fee_token,
-        ).await?;
-        instructions.push(fee_instruction);
+        // 2. Комиссия на кошелек, закрепленный за платежом (без комиссии — без перевода)
+        if fee_amount > 0.0 {
+            let fee_instruction = self.create_transfer_instruction(payer, fee_recipient, fee_amount, fee_token).await?;
+            instructions.push(fee_instruction);
+        }
 
         Ok(instructions)
     }
@@ -86,9 +80,9 @@ impl MultichainService {
             .ok_or_else(|| anyhow::anyhow!("Token {} not supported", token))?;
 
         if token == "SOL" {
-            self.create_sol_transfer_instruction(from, to, amount, token_config)
+            self.create_sol_transfer_instruction(from, to, amount, &token_config)
         } else {
-            self.create_spl_transfer_instruction(from, to, amount, token_config).await
+            self.create_spl_transfer_instruction(from, to, amount, &token_config).await
         }
     }
 
@@ -100,7 +94,7 @@ impl MultichainService {
         amount: f64,
         token_config: &TokenConfig,
     ) -> Result<TransferInstruction> {
-        let lamports = (amount * 10_f64.powi(token_config.decimals as i32)) as u64;
+        let lamports = to_base_units(amount, token_config.decimals);
 
         let instruction = system_instruction::transfer(from, to, lamports);
 
@@ -128,7 +122,7 @@ impl MultichainService {
         let to_token_account = spl_associated_token_account::get_associated_token_address(to, &mint);
 
         // Создаем transfer instruction
-        let token_amount = (amount * 10_f64.powi(token_config.decimals as i32)) as u64;
+        let token_amount = to_base_units(amount, token_config.decimals);
 
         let transfer_instruction = token_instruction::transfer(
             &TOKEN_PROGRAM_ID,
@@ -148,53 +142,106 @@ impl MultichainService {
         })
     }
 
-    /// Простая верификация транзакции
+    /// Верификация транзакции: успешна и получатель получил не меньше `min_received`
+    /// базовых единиц токена платежа (на свой кошелек или, для SPL, на токен-аккаунт).
+    /// `fee_transfer_valid` — кошелек комиссии получил комиссию платежа (`expected_fee`:
+    /// кошелек, сумма и токен, зафиксированные на платеже); комиссию можно оплатить
+    /// отдельной транзакцией, поэтому на `is_valid` это не влияет. `commitment` — вместо
+    /// `solana.verification_commitment`
     pub async fn verify_transaction(
         &self,
         signature: &str,
-        _expected_recipient: &Pubkey,
-        _expected_amount: f64,
-        _expected_token: &str,
+        expected_recipient: &Pubkey,
+        commitment: Option<CommitmentConfig>,
+        expected_token: &str,
+        expected_fee: (&Pubkey, f64, &str),
+        min_received: u64,
     ) -> Result<TransactionVerification> {
         let signature = Signature::from_str(signature)?;
 
         // Простая проверка - существует ли транзакция
-        match self.solana_client.get_signature_status(&signature) {
-            Ok(Some(status)) => {
-                if status.is_err() {
-                    Ok(TransactionVerification {
-                        is_valid: false,
-                        details: "Transaction failed".to_string(),
-                        main_transfer_valid: false,
-                        fee_transfer_valid: false,
-                    })
-                } else {
-                    Ok(TransactionVerification {
-                        is_valid: true,
-                        details: "Transaction confirmed".to_string(),
-                        main_transfer_valid: true,
-                        fee_transfer_valid: true,
-                    })
+        let lookup = tokio::time::timeout(
+            Duration::from_secs(VERIFY_TIMEOUT_SECS),
+            async {
+                match commitment {
+                    Some(commitment) => self.rpc.get_transaction_with_commitment(&signature, commitment).await,
+                    None => self.rpc.get_transaction(&signature).await,
+                }
+            },
+        ).await;
+        let invalid = |details: String| TransactionVerification {
+            is_valid: false,
+            details,
+            main_transfer_valid: false,
+            fee_transfer_valid: false,
+            block_time: None,
+            payer: None,
+            fee_payer: None,
+            sources: Vec::new(),
+            received: None,
+        };
+
+        match lookup {
+            Ok(Ok(Some(status))) => match status.error.clone() {
+                Some(error) => Ok(invalid(format!("Transaction failed: {}", error))),
+                None if self.received(&status, expected_recipient, expected_token) < min_received as i128 => {
+                    Ok(invalid(format!(
+                        "Recipient {} received {} of {} {} base units",
+                        expected_recipient,
+                        self.received(&status, expected_recipient, expected_token),
+                        min_received,
+                        expected_token,
+                    )))
                 }
-            }
-            Ok(None) => Ok(TransactionVerification {
-                is_valid: false,
-                details: "Transaction not found".to_string(),
-                main_transfer_valid: false,
-                fee_transfer_valid: false,
-            }),
-            Err(e) => Ok(TransactionVerification {
-                is_valid: false,
-                details: format!("Error checking transaction: {}", e),
-                main_transfer_valid: false,
-                fee_transfer_valid: false,
-            }),
+                None => Ok(TransactionVerification {
+                    is_valid: true,
+                    details: "Transaction confirmed".to_string(),
+                    main_transfer_valid: true,
+                    fee_transfer_valid: self.fee_received(&status, expected_fee),
+                    block_time: status.block_time,
+                    payer: self.payer(&status, expected_token),
+                    fee_payer: status.fee_payer.clone(),
+                    sources: self.sources(&status, expected_token),
+                    received: u64::try_from(self.received(&status, expected_recipient, expected_token)).ok(),
+                }),
+            },
+            Ok(Ok(None)) => Ok(invalid("Transaction not found".to_string())),
+            Ok(Err(e)) => Ok(invalid(format!("Error checking transaction: {}", e))),
+            Err(_) => Ok(invalid("Error checking transaction: RPC timed out".to_string())),
         }
     }
 
-    /// Валидировать Solana адрес
-    pub fn validate_address(&self, address: &str) -> bool {
-        Pubkey::from_str(address).is_ok()
+    /// Кошелек комиссии получил не меньше комиссии платежа
+    fn fee_received(&self, status: &TransactionStatus, (fee_recipient, amount, token): (&Pubkey, f64, &str)) -> bool {
+        let expected = to_base_units(amount, self.config.token_decimals(token)) as i128;
+        expected <= 0 || self.received(status, fee_recipient, token) >= expected
+    }
+
+    /// Владелец, чей баланс токена платежа уменьшился больше всех
+    fn payer(&self, status: &TransactionStatus, token: &str) -> Option<String> {
+        let mint = self.config.get_token_config(token).and_then(|t| t.mint);
+        status.balance_changes.iter()
+            .filter(|change| change.mint == mint && change.delta < 0)
+            .min_by_key(|change| change.delta)
+            .map(|change| change.owner.clone())
+    }
+
+    /// Владельцы, с которых списан токен платежа
+    fn sources(&self, status: &TransactionStatus, token: &str) -> Vec<String> {
+        let mint = self.config.get_token_config(token).and_then(|t| t.mint);
+        let mut sources: Vec<String> = status.balance_changes.iter()
+            .filter(|change| change.mint == mint && change.delta < 0)
+            .map(|change| change.owner.clone())
+            .collect();
+        sources.sort();
+        sources.dedup();
+        sources
+    }
+
+    /// Сколько базовых единиц токена получатель получил в транзакции
+    fn received(&self, status: &TransactionStatus, recipient: &Pubkey, token: &str) -> i128 {
+        let mint = self.config.get_token_config(token).and_then(|t| t.mint);
+        status.received(&recipient.to_string(), mint.as_deref())
     }
 }
 
@@ -204,4 +251,13 @@ pub struct TransactionVerification {
     pub details: String,
     pub main_transfer_valid: bool,
     pub fee_transfer_valid: bool,
+    pub block_time: Option<i64>,
+    /// Кто заплатил (по изменениям балансов токена платежа)
+    pub payer: Option<String>,
+    /// Кто заплатил комиссию сети (первый подписант)
+    pub fee_payer: Option<String>,
+    /// С кого списан токен платежа
+    pub sources: Vec<String>,
+    /// Сколько базовых единиц токена платежа получил получатель
+    pub received: Option<u64>,
 }
\ No newline at end of file
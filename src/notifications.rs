@@ -0,0 +1,144 @@
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde_json::Value;
+use std::time::Duration;
+
+use crate::config::{Config, NotificationChannel, NotificationTarget};
+
+/// Таймаут отправки одного уведомления
+const SEND_TIMEOUT_SECS: u64 = 10;
+
+const TELEGRAM_API_URL: &str = "https://api.telegram.org";
+
+/// Уведомления мерчантам по email и в Telegram о событиях платежей.
+/// Доставка best-effort: одна попытка, ошибки только логируются
+#[derive(Clone)]
+pub struct NotificationService {
+    config: Config,
+    smtp: Option<AsyncSmtpTransport<Tokio1Executor>>,
+    client: reqwest::Client,
+}
+
+impl NotificationService {
+    pub fn new(config: Config) -> anyhow::Result<Self> {
+        let settings = &config.notifications;
+        let smtp = match &settings.smtp_host {
+            Some(host) => {
+                let mut builder = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?
+                    .port(settings.smtp_port)
+                    .timeout(Some(Duration::from_secs(SEND_TIMEOUT_SECS)));
+                if let (Some(username), Some(password)) = (&settings.smtp_username, &settings.smtp_password) {
+                    builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+                }
+                Some(builder.build())
+            }
+            None => None,
+        };
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(SEND_TIMEOUT_SECS))
+            .build()
+            .unwrap_or_default();
+
+        Ok(Self { config, smtp, client })
+    }
+
+    /// Разослать событие по каналам мерчанта, подписанным на этот тип (в фоне)
+    pub fn notify(&self, merchant_id: &str, event_type: &str, payment: &Value) {
+        let Some(merchant) = self.config.find_merchant(merchant_id) else {
+            return;
+        };
+
+        let targets: Vec<NotificationTarget> = merchant.notifications.iter()
+            .filter(|t| t.events.iter().any(|e| e == event_type))
+            .cloned()
+            .collect();
+        if targets.is_empty() {
+            return;
+        }
+
+        let (subject, text) = render(event_type, payment);
+        for target in targets {
+            let service = self.clone();
+            let (subject, text) = (subject.clone(), text.clone());
+            let merchant_id = merchant_id.to_string();
+            tokio::spawn(async move {
+                let result = match &target.channel {
+                    NotificationChannel::Email { to } => service.send_email(to, &subject, &text).await,
+                    NotificationChannel::Telegram { chat_id } => service.send_telegram(chat_id, &text).await,
+                };
+                if let Err(e) = result {
+                    tracing::warn!(channel = ?target.channel, merchant = %merchant_id, error = %e, "Notification failed");
+                }
+            });
+        }
+    }
+
+    async fn send_email(&self, to: &str, subject: &str, text: &str) -> anyhow::Result<()> {
+        let smtp = self.smtp.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("SMTP is not configured"))?;
+        let from = self.config.notifications.email_from.as_deref()
+            .ok_or_else(|| anyhow::anyhow!("notifications.email_from is not configured"))?;
+
+        let message = Message::builder()
+            .from(from.parse::<Mailbox>()?)
+            .to(to.parse::<Mailbox>()?)
+            .subject(subject)
+            .body(text.to_string())?;
+        smtp.send(message).await?;
+        Ok(())
+    }
+
+    async fn send_telegram(&self, chat_id: &str, text: &str) -> anyhow::Result<()> {
+        let token = self.config.notifications.telegram_bot_token.as_deref()
+            .ok_or_else(|| anyhow::anyhow!("Telegram bot token is not configured"))?;
+
+        let response = self.client
+            .post(format!("{}/bot{}/sendMessage", TELEGRAM_API_URL, token))
+            .json(&serde_json::json!({
+                "chat_id": chat_id,
+                "text": text,
+                "disable_web_page_preview": true,
+            }))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            anyhow::bail!("Telegram API responded with {}", response.status());
+        }
+        Ok(())
+    }
+}
+
+/// Тема и текст уведомления из payload события (сериализованный платеж или спор)
+fn render(event_type: &str, payment: &Value) -> (String, String) {
+    let field = |name: &str| payment.get(name).and_then(Value::as_str).unwrap_or_default();
+    let id = field("id");
+
+    let headline = match event_type {
+        "payment.created" => format!("Payment {} created", id),
+        "payment.processing" => format!("Payment {} submitted", id),
+        "payment.completed" => format!("Payment {} completed", id),
+        "payment.failed" => format!("Payment {} failed", id),
+        "payment.expired" => format!("Payment {} expired", id),
+        "payment.cancelled" => format!("Payment {} cancelled", id),
+        "payment.refunded" => format!("Payment {} refunded", id),
+        "payment.held" => format!("Payment {} paid, funds on hold", id),
+        "payment.released" => format!("Payment {} released to the payer", id),
+        "dispute.opened" => format!("Dispute {} opened for payment {}", id, field("payment_id")),
+        "dispute.refunded" => format!("Dispute {} resolved: payment {} refunded", id, field("payment_id")),
+        "dispute.denied" => format!("Dispute {} denied for payment {}", id, field("payment_id")),
+        other => format!("Payment {}: {}", id, other),
+    };
+
+    let mut lines = vec![headline.clone()];
+    if !field("label").is_empty() {
+        lines.push(format!("Label: {}", field("label")));
+    }
+    lines.push(format!("Amount: {}", field("total_display")));
+    if let Some(url) = payment.get("explorer_url").and_then(Value::as_str) {
+        lines.push(format!("Transaction: {}", url));
+    }
+
+    (headline, lines.join("\n"))
+}
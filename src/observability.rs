@@ -0,0 +1,300 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::web;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::ServerConfig;
+
+/// Correlation id запроса: берется из запроса клиента или прокси, иначе создается;
+/// возвращается в ответе и попадает во все строки лога запроса
+pub const CORRELATION_ID_HEADER: &str = "x-request-id";
+
+/// Верхние границы корзин гистограммы задержек, мс; последняя корзина — без границы
+pub const LATENCY_BUCKETS_MS: [u64; 12] = [5, 10, 25, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 30_000];
+
+/// Маршруты, которые держат запрос по замыслу (long polling): в трассировку медленных не попадают
+const LONG_POLL_ROUTES: &[&str] = &["/api/payment/{id}/wait"];
+
+/// Шагов трассировки на запрос; остальные только считаются
+const MAX_TRACE_STEPS: usize = 100;
+
+/// Принимаемая длина correlation id клиента
+const MAX_CORRELATION_ID_LENGTH: usize = 128;
+
+/// Шаг обработки запроса: вызов RPC или операция хранилища
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceStep {
+    /// `rpc` или `storage`
+    pub kind: &'static str,
+    pub name: String,
+    /// От начала запроса до начала шага
+    pub start_ms: u64,
+    pub duration_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+struct TraceLog {
+    steps: Vec<TraceStep>,
+    dropped: usize,
+}
+
+/// Трассировка текущего запроса; клоны пишут в один журнал
+#[derive(Clone)]
+struct RequestTrace {
+    correlation_id: Arc<str>,
+    started: Instant,
+    log: Arc<Mutex<TraceLog>>,
+}
+
+impl RequestTrace {
+    fn log(&self) -> std::sync::MutexGuard<'_, TraceLog> {
+        self.log.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn push(&self, step: TraceStep) {
+        let mut log = self.log();
+        if log.steps.len() < MAX_TRACE_STEPS {
+            log.steps.push(step);
+        } else {
+            log.dropped += 1;
+        }
+    }
+}
+
+tokio::task_local! {
+    static TRACE: RequestTrace;
+}
+
+/// Correlation id запроса, в рамках которого выполняется текущая задача
+pub fn correlation_id() -> Option<String> {
+    TRACE.try_with(|trace| trace.correlation_id.to_string()).ok()
+}
+
+/// Записать шаг, начатый в `started`; вне запроса ничего не делает. Имя строится,
+/// только если запрос трассируется
+pub fn record(kind: &'static str, name: impl FnOnce() -> String, started: Instant, error: Option<&anyhow::Error>) {
+    let _ = TRACE.try_with(|trace| {
+        trace.push(TraceStep {
+            kind,
+            name: name(),
+            start_ms: started.saturating_duration_since(trace.started).as_millis() as u64,
+            duration_ms: started.elapsed().as_millis() as u64,
+            error: error.map(ToString::to_string),
+        });
+    });
+}
+
+/// Шаг, который записывается при сбросе guard
+pub struct StepGuard {
+    kind: &'static str,
+    name: &'static str,
+    started: Instant,
+}
+
+impl Drop for StepGuard {
+    fn drop(&mut self) {
+        record(self.kind, || self.name.to_string(), self.started, None);
+    }
+}
+
+/// Учесть операцию до конца области видимости guard
+pub fn step(kind: &'static str, name: &'static str) -> StepGuard {
+    StepGuard { kind, name, started: Instant::now() }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    /// По корзинам `LATENCY_BUCKETS_MS` и последняя — сверх них
+    buckets: [u64; LATENCY_BUCKETS_MS.len() + 1],
+    count: u64,
+    sum_ms: u64,
+    max_ms: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, latency_ms: u64) {
+        let bucket = LATENCY_BUCKETS_MS.iter().position(|&le| latency_ms <= le).unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum_ms += latency_ms;
+        self.max_ms = self.max_ms.max(latency_ms);
+    }
+
+    /// Оценка квантиля: верхняя граница корзины, но не больше максимума
+    fn quantile(&self, q: f64) -> u64 {
+        let rank = ((self.count as f64) * q).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return LATENCY_BUCKETS_MS.get(bucket).map_or(self.max_ms, |&le| le.min(self.max_ms));
+            }
+        }
+        self.max_ms
+    }
+}
+
+/// Корзина гистограммы: запросов не дольше `le_ms` (накопительно, как в Prometheus);
+/// `le_ms: None` — все запросы
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencyBucket {
+    pub le_ms: Option<u64>,
+    pub count: u64,
+}
+
+/// Задержки маршрута с запуска процесса
+#[derive(Debug, Clone, Serialize)]
+pub struct RouteLatency {
+    /// Метод и шаблон маршрута: `GET /api/payment/{id}`
+    pub route: String,
+    pub count: u64,
+    pub sum_ms: u64,
+    pub max_ms: u64,
+    pub p50_ms: u64,
+    pub p95_ms: u64,
+    pub p99_ms: u64,
+    pub buckets: Vec<LatencyBucket>,
+}
+
+/// Гистограммы задержек по маршрутам (в памяти процесса, у каждой реплики свои)
+#[derive(Debug, Clone, Default)]
+pub struct LatencyHistograms {
+    routes: Arc<Mutex<BTreeMap<String, Histogram>>>,
+}
+
+impl LatencyHistograms {
+    pub fn observe(&self, route: &str, latency: Duration) {
+        let mut routes = self.routes.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(histogram) = routes.get_mut(route) {
+            histogram.observe(latency.as_millis() as u64);
+        } else {
+            let mut histogram = Histogram::default();
+            histogram.observe(latency.as_millis() as u64);
+            routes.insert(route.to_string(), histogram);
+        }
+    }
+
+    /// Маршруты по алфавиту
+    pub fn snapshot(&self) -> Vec<RouteLatency> {
+        let routes = self.routes.lock().unwrap_or_else(|e| e.into_inner());
+        routes.iter()
+            .map(|(route, histogram)| {
+                let mut cumulative = 0;
+                let buckets = histogram.buckets.iter().enumerate()
+                    .map(|(bucket, count)| {
+                        cumulative += count;
+                        LatencyBucket { le_ms: LATENCY_BUCKETS_MS.get(bucket).copied(), count: cumulative }
+                    })
+                    .collect();
+                RouteLatency {
+                    route: route.clone(),
+                    count: histogram.count,
+                    sum_ms: histogram.sum_ms,
+                    max_ms: histogram.max_ms,
+                    p50_ms: histogram.quantile(0.5),
+                    p95_ms: histogram.quantile(0.95),
+                    p99_ms: histogram.quantile(0.99),
+                    buckets,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Гистограммы задержек и порог трассировки медленных запросов
+#[derive(Debug, Clone, Default)]
+pub struct Observability {
+    histograms: LatencyHistograms,
+    /// `None` — медленные запросы не трассируются
+    slow_request: Option<Duration>,
+}
+
+impl Observability {
+    pub fn new(slow_request: Option<Duration>) -> Self {
+        Self { histograms: LatencyHistograms::default(), slow_request }
+    }
+
+    pub fn from_config(config: &ServerConfig) -> Self {
+        Self::new((config.slow_request_ms > 0).then(|| Duration::from_millis(config.slow_request_ms)))
+    }
+
+    pub fn histograms(&self) -> &LatencyHistograms {
+        &self.histograms
+    }
+
+    /// Выполнить запрос с трассировкой: шаги RPC и хранилища внутри `future` попадают
+    /// в журнал запроса. Возвращает результат, журнал шагов и число непоместившихся
+    pub async fn trace<F: std::future::Future>(
+        correlation_id: &str,
+        future: F,
+    ) -> (F::Output, Vec<TraceStep>, usize) {
+        let trace = RequestTrace {
+            correlation_id: correlation_id.into(),
+            started: Instant::now(),
+            log: Arc::default(),
+        };
+        let output = TRACE.scope(trace.clone(), future).await;
+        let log = std::mem::take(&mut *trace.log());
+        (output, log.steps, log.dropped)
+    }
+}
+
+fn accepted_correlation_id(value: &HeaderValue) -> Option<String> {
+    let value = value.to_str().ok()?.trim();
+    let valid = !value.is_empty() && value.len() <= MAX_CORRELATION_ID_LENGTH
+        && value.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'));
+    valid.then(|| value.to_string())
+}
+
+/// Middleware: correlation id, гистограмма задержек маршрута и подробная трассировка
+/// (вызовы RPC, операции хранилища, их длительность) запросов дольше `server.slow_request_ms`
+pub async fn observe(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let observability = req.app_data::<web::Data<Observability>>().cloned();
+    let correlation_id = req.headers().get(CORRELATION_ID_HEADER)
+        .and_then(accepted_correlation_id)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().simple().to_string());
+    let (method, path) = (req.method().to_string(), req.path().to_string());
+
+    let started = Instant::now();
+    let (response, steps, dropped) = Observability::trace(&correlation_id, next.call(req)).await;
+    let latency = started.elapsed();
+
+    let mut response = response?;
+    let route = response.request().match_pattern().unwrap_or_else(|| "unmatched".to_string());
+    if let Some(observability) = observability {
+        observability.histograms.observe(&format!("{} {}", method, route), latency);
+        let slow = observability.slow_request.is_some_and(|threshold| latency >= threshold);
+        if slow && !LONG_POLL_ROUTES.contains(&route.as_str()) {
+            let rpc_calls = steps.iter().filter(|step| step.kind == "rpc").count();
+            let storage_ops = steps.iter().filter(|step| step.kind == "storage").count();
+            tracing::warn!(
+                correlation_id = %correlation_id,
+                method,
+                path,
+                route,
+                status = response.status().as_u16(),
+                latency_ms = latency.as_millis() as u64,
+                rpc_calls,
+                rpc_ms = steps.iter().filter(|step| step.kind == "rpc").map(|step| step.duration_ms).sum::<u64>(),
+                storage_ops,
+                steps_dropped = dropped,
+                trace = %serde_json::to_string(&steps).unwrap_or_default(),
+                "Slow request"
+            );
+        }
+    }
+    if let Ok(value) = HeaderValue::from_str(&correlation_id) {
+        response.headers_mut().insert(HeaderName::from_static(CORRELATION_ID_HEADER), value);
+    }
+    Ok(response)
+}
@@ -0,0 +1,113 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::lock::LeaderElection;
+use crate::notifications::NotificationService;
+use crate::storage::StorageService;
+use crate::webhook::WebhookService;
+
+/// Как часто диспетчер проверяет очередь
+const DISPATCH_INTERVAL_MS: u64 = 500;
+
+/// Lease лидера диспетчера: на каждом тике продлевается
+const LEADER_TTL_MS: u64 = 5_000;
+
+/// Сколько хранить уже отправленные события
+const RETENTION_HOURS: i64 = 24;
+
+/// Событие в outbox: пишется вместе с изменением платежа (под теми же блокировками
+/// хранилища), поэтому не теряется между записью состояния и уведомлением
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxEvent {
+    /// Монотонный номер — порядок отправки
+    pub seq: u64,
+    pub merchant_id: String,
+    pub event_type: String,
+    /// См. `events::idempotency_key`; пустой у событий, записанных до появления ключей
+    #[serde(default)]
+    pub idempotency_key: String,
+    pub payload: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub dispatched_at: Option<DateTime<Utc>>,
+}
+
+/// Фоновый диспетчер outbox: доставка at-least-once, дубликаты отсекаются по ключу
+/// идемпотентности. Событие помечается отправленным только после того, как webhook
+/// доставки записаны; повторная публикация того же ключа (сбой между публикацией и
+/// отметкой, два диспетчера при смене лидера) новых доставок не создает.
+/// Email/Telegram уведомления отправляются вслед за webhooks без повторов
+#[derive(Clone)]
+pub struct OutboxDispatcher {
+    storage: StorageService,
+    webhooks: WebhookService,
+    notifications: NotificationService,
+    leader: LeaderElection,
+}
+
+impl OutboxDispatcher {
+    pub fn new(
+        storage: StorageService,
+        webhooks: WebhookService,
+        notifications: NotificationService,
+        leader: LeaderElection,
+    ) -> Self {
+        Self { storage, webhooks, notifications, leader }
+    }
+
+    /// Запустить фоновую разборку очереди (только на реплике-лидере)
+    pub fn spawn(&self) {
+        let dispatcher = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_millis(DISPATCH_INTERVAL_MS));
+            loop {
+                interval.tick().await;
+                let ttl = std::time::Duration::from_millis(LEADER_TTL_MS);
+                if !dispatcher.leader.is_leader("outbox-dispatcher", ttl).await {
+                    continue;
+                }
+                if let Err(e) = dispatcher.drain().await {
+                    tracing::error!(error = %e, "Outbox dispatch failed");
+                }
+            }
+        });
+    }
+
+    /// Отправить все неотправленные события по порядку
+    pub async fn drain(&self) -> anyhow::Result<usize> {
+        let pending = self.storage.pending_outbox_events().await?;
+        let mut dispatched = 0;
+
+        for event in pending {
+            if self.storage.outbox_key_dispatched(&event).await? {
+                tracing::info!(
+                    seq = event.seq, event_type = %event.event_type, idempotency_key = %event.idempotency_key,
+                    "Duplicate outbox event skipped"
+                );
+                self.storage.mark_outbox_dispatched(event.seq).await?;
+                continue;
+            }
+            let published = self.webhooks
+                .publish_event(&event.merchant_id, &event.event_type, event.payload.clone(), &event.idempotency_key)
+                .await;
+            match published {
+                Ok(()) => {
+                    self.storage.mark_outbox_dispatched(event.seq).await?;
+                    self.notifications.notify(&event.merchant_id, &event.event_type, &event.payload);
+                    dispatched += 1;
+                }
+                Err(e) => {
+                    tracing::warn!(seq = event.seq, event_type = %event.event_type, error = %e, "Outbox event not dispatched");
+                    self.storage.mark_outbox_failed(event.seq, e.to_string()).await?;
+                    // Сохраняем порядок: следующие события ждут этого
+                    break;
+                }
+            }
+        }
+
+        self.storage.prune_outbox(Utc::now() - Duration::hours(RETENTION_HOURS)).await?;
+
+        Ok(dispatched)
+    }
+}
@@ -0,0 +1,247 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{pubkey, pubkey::Pubkey, signature::Signature};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::rpc::SolanaRpc;
+use crate::storage::StorageService;
+use crate::validation::{Validate, ValidationErrors};
+
+/// Время жизни challenge
+const CHALLENGE_TTL_MINUTES: i64 = 10;
+
+/// Максимум одновременно ожидающих challenge (после очистки просроченных)
+const MAX_PENDING_CHALLENGES: usize = 10_000;
+
+/// Программа мультисига Squads v4
+pub const SQUADS_V4_PROGRAM_ID: Pubkey = pubkey!("SQDS4ep65T869zMMBKyuUq6aD6EgTu8psMjkvj52pCf");
+
+/// Смещение `rent_collector` в аккаунте мультисига Squads v4: дискриминатор Anchor,
+/// create_key, config_authority, threshold, time_lock, transaction_index, stale_transaction_index
+const SQUADS_RENT_COLLECTOR_OFFSET: usize = 8 + 32 + 32 + 2 + 4 + 8 + 8;
+
+/// Подтверждение владения кошельком получателя (подпись ed25519). Vault мультисига
+/// подписать не может — за него подписывает участник мультисига
+#[derive(Clone)]
+pub struct OwnershipService {
+    challenges: Arc<RwLock<HashMap<String, OwnershipChallenge>>>,
+    storage: StorageService,
+    rpc: Arc<dyn SolanaRpc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OwnershipChallenge {
+    pub wallet: String,
+    pub nonce: String,
+    /// Для vault: мультисиг Squads, которому принадлежит vault
+    pub multisig: Option<String>,
+    /// Для vault: участник мультисига, который подписывает challenge
+    pub member: Option<String>,
+    pub message: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OwnershipProofRequest {
+    pub wallet: String,
+    /// Nonce из выданного challenge
+    pub nonce: String,
+    /// Подпись сообщения challenge в base58 (как возвращает signMessage кошелька)
+    pub signature: String,
+}
+
+impl Validate for OwnershipProofRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        errors.check_pubkey("wallet", &self.wallet);
+        if self.nonce.trim().is_empty() {
+            errors.add("nonce", "required", "nonce is required");
+        }
+        errors.check_signature("signature", &self.signature);
+        errors.into_result()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerifiedRecipient {
+    pub wallet: String,
+    pub signature: String,
+    /// Участник мультисига, подписавший challenge за vault
+    #[serde(default)]
+    pub member: Option<String>,
+    pub verified_at: DateTime<Utc>,
+}
+
+impl OwnershipService {
+    pub fn new(storage: StorageService, rpc: Arc<dyn SolanaRpc>) -> Self {
+        Self {
+            challenges: Arc::new(RwLock::new(HashMap::new())),
+            storage,
+            rpc,
+        }
+    }
+
+    /// Выдать challenge для подписи кошельком. У PDA нет приватного ключа: для vault
+    /// Squads нужны `multisig` и `member` — challenge подписывает участник мультисига
+    pub async fn create_challenge(
+        &self,
+        wallet: &str,
+        multisig: Option<&str>,
+        member: Option<&str>,
+    ) -> anyhow::Result<OwnershipChallenge> {
+        let pubkey = Pubkey::from_str(wallet)
+            .map_err(|e| anyhow::anyhow!("Invalid wallet address: {}", e))?;
+        let vault = match (pubkey.is_on_curve(), multisig, member) {
+            (true, None, None) => None,
+            (true, _, _) => anyhow::bail!("multisig and member are only used for multisig vault addresses"),
+            (false, Some(multisig), Some(member)) => {
+                let multisig = Pubkey::from_str(multisig)
+                    .map_err(|e| anyhow::anyhow!("Invalid multisig address: {}", e))?;
+                let member = Pubkey::from_str(member)
+                    .map_err(|e| anyhow::anyhow!("Invalid member address: {}", e))?;
+                if squads_vault_index(&multisig, &pubkey).is_none() {
+                    anyhow::bail!("{} is not a vault of Squads multisig {}", pubkey, multisig);
+                }
+                if !member.is_on_curve() {
+                    anyhow::bail!("Multisig member {} cannot sign a challenge", member);
+                }
+                Some((multisig, member))
+            }
+            (false, _, _) => anyhow::bail!(
+                "{} is a program-derived address (e.g. a multisig vault) and cannot sign a challenge; \
+                 pass multisig and member to have a multisig member sign for the vault",
+                pubkey
+            ),
+        };
+
+        let now = Utc::now();
+        let nonce = Uuid::new_v4().simple().to_string();
+        let signer = match vault {
+            Some((multisig, member)) => format!("\nMultisig: {}\nMember: {}", multisig, member),
+            None => String::new(),
+        };
+        let challenge = OwnershipChallenge {
+            wallet: pubkey.to_string(),
+            message: format!(
+                "CryptoNow wallet verification\nWallet: {}{}\nNonce: {}\nIssued: {}",
+                pubkey,
+                signer,
+                nonce,
+                now.to_rfc3339()
+            ),
+            nonce,
+            multisig: vault.map(|(multisig, _)| multisig.to_string()),
+            member: vault.map(|(_, member)| member.to_string()),
+            expires_at: now + Duration::minutes(CHALLENGE_TTL_MINUTES),
+        };
+
+        // Challenge хранятся по nonce: чужой запрос не отменяет уже выданный
+        let mut challenges = self.challenges.write().await;
+        challenges.retain(|_, pending| pending.expires_at > now);
+        if challenges.len() >= MAX_PENDING_CHALLENGES {
+            anyhow::bail!("Too many pending wallet challenges, try again later");
+        }
+        challenges.insert(challenge.nonce.clone(), challenge.clone());
+
+        Ok(challenge)
+    }
+
+    /// Проверить подпись challenge и добавить кошелек в реестр подтвержденных
+    pub async fn verify_proof(&self, request: &OwnershipProofRequest) -> anyhow::Result<VerifiedRecipient> {
+        let pubkey = Pubkey::from_str(&request.wallet)
+            .map_err(|e| anyhow::anyhow!("Invalid wallet address: {}", e))?;
+        let signature = Signature::from_str(&request.signature)
+            .map_err(|e| anyhow::anyhow!("Invalid signature encoding: {}", e))?;
+
+        // Challenge одноразовый — удаляем сразу
+        let challenge = {
+            let mut challenges = self.challenges.write().await;
+            match challenges.get(&request.nonce) {
+                Some(pending) if pending.wallet == pubkey.to_string() => challenges.remove(&request.nonce),
+                _ => None,
+            }
+        }
+        .ok_or_else(|| anyhow::anyhow!("No active challenge {} for wallet {}", request.nonce, pubkey))?;
+
+        if Utc::now() > challenge.expires_at {
+            anyhow::bail!("Challenge expired, request a new one");
+        }
+
+        // За vault подписывает участник мультисига
+        let signer = match &challenge.member {
+            Some(member) => Pubkey::from_str(member)?,
+            None => pubkey,
+        };
+        if !signature.verify(signer.as_ref(), challenge.message.as_bytes()) {
+            anyhow::bail!("Signature does not match challenge for wallet {}", pubkey);
+        }
+        if let Some(multisig) = &challenge.multisig {
+            self.ensure_multisig_member(&Pubkey::from_str(multisig)?, &signer).await?;
+        }
+
+        let verified = VerifiedRecipient {
+            wallet: pubkey.to_string(),
+            signature: request.signature.clone(),
+            member: challenge.member.clone(),
+            verified_at: Utc::now(),
+        };
+        self.storage.save_verified_recipient(&verified).await?;
+
+        tracing::info!(wallet = %pubkey, "Wallet ownership verified");
+
+        Ok(verified)
+    }
+
+    /// Проверить по аккаунту мультисига в сети, что `member` — его участник
+    async fn ensure_multisig_member(&self, multisig: &Pubkey, member: &Pubkey) -> anyhow::Result<()> {
+        let account = self.rpc.get_account(multisig).await?
+            .ok_or_else(|| anyhow::anyhow!("Multisig account {} not found", multisig))?;
+        if account.owner != SQUADS_V4_PROGRAM_ID {
+            anyhow::bail!("{} is not a Squads multisig account (owner {})", multisig, account.owner);
+        }
+        let members = squads_members(&account.data)
+            .ok_or_else(|| anyhow::anyhow!("Failed to decode Squads multisig account {}", multisig))?;
+        if !members.contains(member) {
+            anyhow::bail!("{} is not a member of multisig {}", member, multisig);
+        }
+        Ok(())
+    }
+
+    /// Подтвержден ли кошелек получателя
+    pub async fn is_verified(&self, wallet: &str) -> anyhow::Result<bool> {
+        Ok(self.storage.get_verified_recipient(wallet).await?.is_some())
+    }
+}
+
+/// Индекс vault мультисига Squads v4, если `vault` — один из его vault PDA
+fn squads_vault_index(multisig: &Pubkey, vault: &Pubkey) -> Option<u8> {
+    (0..=u8::MAX).find(|index| {
+        let seeds: &[&[u8]] = &[b"multisig", multisig.as_ref(), b"vault", &[*index]];
+        Pubkey::find_program_address(seeds, &SQUADS_V4_PROGRAM_ID).0 == *vault
+    })
+}
+
+/// Участники из аккаунта мультисига Squads v4 (borsh): после `rent_collector: Option<Pubkey>`
+/// и `bump` идет `members: Vec<{ key: Pubkey, permissions: u8 }>`
+fn squads_members(data: &[u8]) -> Option<Vec<Pubkey>> {
+    let mut offset = SQUADS_RENT_COLLECTOR_OFFSET;
+    offset += match data.get(offset)? {
+        0 => 1,
+        1 => 1 + 32,
+        _ => return None,
+    };
+    offset += 1;
+    let count = u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?) as usize;
+    offset += 4;
+    (0..count)
+        .map(|i| {
+            let start = offset + i * 33;
+            Some(Pubkey::new_from_array(data.get(start..start + 32)?.try_into().ok()?))
+        })
+        .collect()
+}
@@ -0,0 +1,111 @@
+use serde::Serialize;
+use serde_json::Value;
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{read_keypair_file, Keypair, Signature},
+    signer::Signer,
+};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Подпись `data` ответа или `data` события webhook (base58)
+pub const PAYLOAD_SIGNATURE_HEADER: &str = "X-CryptoNow-Payload-Signature";
+
+/// Публичный ключ, которым сделана подпись (base58): помогает при смене ключа
+pub const SIGNING_KEY_HEADER: &str = "X-CryptoNow-Signing-Key";
+
+/// Канонический JSON: ключи объектов по возрастанию, без пробелов. Подписываются
+/// именно эти байты, клиент приводит полученный `data` к той же форме
+pub fn canonical_json(value: &Value) -> String {
+    let mut out = String::new();
+    write_canonical(value, &mut out);
+    out
+}
+
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(&map[key], out);
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
+/// Проверить подпись `signature` (base58) канонического JSON `value` ключом `public_key`
+pub fn verify(public_key: &str, value: &Value, signature: &str) -> bool {
+    let (Ok(public_key), Ok(signature)) = (Pubkey::from_str(public_key), Signature::from_str(signature)) else {
+        return false;
+    };
+    signature.verify(public_key.as_ref(), canonical_json(value).as_bytes())
+}
+
+/// Ed25519 ключ подписи данных платежей: ответы API и webhooks несут отделенную
+/// подпись канонического JSON, клиенты проверяют ее ключом из `GET /api/public-key`
+#[derive(Clone)]
+pub struct PayloadSigner {
+    keypair: Arc<Keypair>,
+}
+
+impl std::fmt::Debug for PayloadSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Секретный ключ никогда не попадает в логи
+        f.debug_struct("PayloadSigner").field("public_key", &self.public_key()).finish()
+    }
+}
+
+impl PayloadSigner {
+    pub fn new(keypair: Keypair) -> Self {
+        Self { keypair: Arc::new(keypair) }
+    }
+
+    /// Загрузить ключ из JSON файла в формате solana-keygen
+    pub fn from_file(path: &str) -> anyhow::Result<Self> {
+        let keypair = read_keypair_file(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read payload signing key {}: {}", path, e))?;
+
+        tracing::info!(public_key = %keypair.pubkey(), "Payload signing key loaded");
+
+        Ok(Self::new(keypair))
+    }
+
+    /// Загрузить ключ, если путь указан в конфигурации
+    pub fn load(path: Option<&str>) -> anyhow::Result<Option<Self>> {
+        path.map(Self::from_file).transpose()
+    }
+
+    /// Публичный ключ в base58
+    pub fn public_key(&self) -> String {
+        self.keypair.pubkey().to_string()
+    }
+
+    /// Подпись канонического JSON `value` в base58
+    pub fn sign_value(&self, value: &Value) -> String {
+        self.keypair.sign_message(canonical_json(value).as_bytes()).to_string()
+    }
+
+    /// Подпись сериализованного `payload` (например, платежа в `data` ответа)
+    pub fn sign<T: Serialize>(&self, payload: &T) -> anyhow::Result<String> {
+        Ok(self.sign_value(&serde_json::to_value(payload)?))
+    }
+}
@@ -1,58 +1,622 @@
+use base64::{Engine as _, engine::general_purpose};
+use bytes::Bytes;
 use serde::{Deserialize, Serialize};
+use solana_sdk::commitment_config::CommitmentConfig;
 use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::VersionedTransaction;
+use std::collections::BTreeMap;
 use std::str::FromStr;
-use uuid::Uuid;
+use std::sync::Arc;
+use url::form_urlencoded::byte_serialize;
 use chrono::{DateTime, Utc, Duration};
 
-use crate::config::Config;
-use crate::multichain::MultichainService;
-use crate::qr::QrService;
-use crate::storage::StorageService;
+use crate::actions;
+use crate::backpressure::BuildLimiter;
+use crate::cache::PaymentCache;
+use crate::compute_budget::{ComputeBudget, ComputeEstimator};
+use crate::config::{constant_time_eq, Config, MerchantConfig};
+use crate::deeplink::WalletLinks;
+use crate::display::{decimal_base_units, format_token_amount, to_base_units};
+use crate::i18n::Locale;
+use crate::ids::{self, IdGenerator};
+use crate::discounts::{AppliedDiscount, DiscountService};
+use crate::escrow::PaymentHold;
+use crate::limits::LimitService;
+use crate::loyalty::{LoyaltyRedemption, LoyaltyService, RedeemPointsRequest};
+use crate::fees::{FeeCalculator, FeeWalletSelector};
+use crate::multichain::{MultichainService, TransactionVerification};
+use crate::ownership::{OwnershipChallenge, OwnershipProofRequest, OwnershipService, VerifiedRecipient};
+use crate::payload_signer::PayloadSigner;
+use crate::price::{FiatAmounts, PriceOracle};
+use crate::qr::{QrKind, QrService};
+use crate::receipt_nft::ReceiptNft;
+use crate::replica::{PaymentReplica, ReadConsistency};
+use crate::recipient::{self, RecipientKind};
+use crate::rpc::{HttpSolanaRpc, SolanaRpc};
+use crate::screening::{ScreeningFlag, ScreeningRole, ScreeningService};
+use crate::storage::{RevenueReport, StorageService, VersionConflict};
+use crate::swap::{PaymentSwap, SwapInstructions, SwapService};
+use crate::tenants::TenantService;
+use crate::transaction::{
+    decode_transaction, expected_message, message_differences, DecodedTransaction, IssuedComparison, IssuedTransaction,
+    RefreshError, TransactionPart,
+};
+use crate::validation::ValidationErrors;
+use crate::webhook::WebhookService;
+
+/// Максимальная длина memo в байтах
+pub const MAX_MEMO_LENGTH: usize = 256;
+
+/// Максимальная сумма одного платежа
+pub const MAX_PAYMENT_AMOUNT: f64 = 1_000_000.0;
+
+/// Максимальные чаевые в базисных пунктах (50%)
+pub const MAX_TIP_BPS: u16 = 5_000;
+
+/// Максимальное число предустановленных вариантов чаевых
+pub const MAX_TIP_PRESETS: usize = 5;
+
+/// Максимальное число ключей metadata платежа
+pub const MAX_METADATA_KEYS: usize = 20;
+
+/// Максимальная длина ключа metadata
+pub const MAX_METADATA_KEY_LENGTH: usize = 40;
+
+/// Максимальная длина значения metadata
+pub const MAX_METADATA_VALUE_LENGTH: usize = 500;
+
+/// Максимальная длина `success_url` и `cancel_url`
+pub const MAX_REDIRECT_URL_LENGTH: usize = 2048;
+
+/// Максимальное число адресов в `allowed_payers`
+pub const MAX_ALLOWED_PAYERS: usize = 20;
+
+/// Максимум ID в запросе `POST /api/payments/status`
+pub const MAX_STATUS_BATCH: usize = 500;
+
+/// Платежей в ответе `GET /api/payments` по умолчанию и максимум
+pub const DEFAULT_PAYMENT_LIST_LIMIT: usize = 100;
+pub const MAX_PAYMENT_LIST_LIMIT: usize = 1_000;
+
+/// Повторные отправки транзакции, пока RPC узел еще не видит ее blockhash
+const SUBMIT_BLOCKHASH_RETRIES: u32 = 3;
+
+/// Пауза перед повторной отправкой
+const SUBMIT_RETRY_DELAY_MS: u64 = 500;
+
+/// Попыток `update_payment`, если платеж параллельно изменили
+const MAX_UPDATE_ATTEMPTS: u32 = 3;
+
+/// Символов токена transaction request URL (base32, ~130 бит)
+const REQUEST_TOKEN_LENGTH: usize = 26;
+
+/// Сколько ждет оплаты новый платеж
+const PAYMENT_LIFETIME_MINUTES: i64 = 30;
 
 #[derive(Clone)]
 pub struct PaymentService {
     multichain: MultichainService,
     qr_service: QrService,
     storage: StorageService,
+    ownership: OwnershipService,
+    fee_wallets: FeeWalletSelector,
+    fee_calculator: FeeCalculator,
+    oracle: PriceOracle,
+    swaps: SwapService,
+    compute: ComputeEstimator,
+    builds: BuildLimiter,
+    ids: Arc<dyn IdGenerator>,
+    screening: ScreeningService,
+    limits: LimitService,
+    discounts: DiscountService,
+    loyalty: LoyaltyService,
+    tenants: TenantService,
+    /// Счет удержания для платежей с `hold` (hot wallet); `None` — удержание выключено
+    escrow_account: Option<Pubkey>,
+    webhooks: WebhookService,
+    /// Подпись данных платежей в ответах; `None` — `server.signing_key_path` не задан
+    payload_signer: Option<PayloadSigner>,
     config: Config,
 }
 
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct CreatePaymentRequest {
     pub recipient: String,
+    /// Сумма в токенах; не указывается вместе с `amount_base_units`
+    #[serde(default)]
     pub amount: f64,
+    /// Точная сумма в базовых единицах токена (lamports для SOL, 1 USDC = 1000000)
+    #[serde(default)]
+    pub amount_base_units: Option<u64>,
     pub token: String,
     pub label: Option<String>,
     pub message: Option<String>,
+    /// Текст для SPL Memo инструкции (сверка on-chain транзакций со счетами)
+    pub memo: Option<String>,
+    /// Включить чаевые: допустимые варианты в базисных пунктах (например, [1000, 1500, 2000])
+    pub tip_presets_bps: Option<Vec<u16>>,
+    /// Язык label/message для кошелька плательщика (`en`, `ru`, `ru-RU`, ...)
+    pub locale: Option<String>,
+    /// Произвольные данные мерчанта (`order_id`, `customer_id`, ...): возвращаются во всех
+    /// ответах, webhooks и выгрузках, по ним фильтрует `GET /api/payments?metadata.<ключ>=`
+    #[serde(default)]
+    pub metadata: Option<BTreeMap<String, String>>,
+    /// Комиссия этого платежа вместо политики (только для мерчантов с `fee_override`)
+    #[serde(default)]
+    pub fee_amount: Option<f64>,
+    /// Токен комиссии этого платежа; по умолчанию — токен комиссии мерчанта
+    #[serde(default)]
+    pub fee_token: Option<String>,
+    /// Куда вернуть покупателя после оплаты (страница подтверждения заказа)
+    #[serde(default)]
+    pub success_url: Option<String>,
+    /// Куда вернуть покупателя, если платеж истек, отменен или не прошел
+    #[serde(default)]
+    pub cancel_url: Option<String>,
+    /// Код скидки (`POST /admin/discounts`): сумма платежа уменьшается на скидку
+    #[serde(default)]
+    pub discount_code: Option<String>,
+    /// Оплатить часть суммы бонусными баллами кошелька (после скидки по коду)
+    #[serde(default)]
+    pub redeem_points: Option<RedeemPointsRequest>,
+    /// Кто может оплатить счет (например, казначейство контрагента); пусто — любой кошелек
+    #[serde(default)]
+    pub allowed_payers: Option<Vec<String>>,
+    /// Удержать оплату на счете сервера до `capture` (мерчанту) или `release` (плательщику)
+    #[serde(default)]
+    pub hold: bool,
+    /// Срок удержания после оплаты, секунды; по умолчанию — `escrow.default_hold_secs`
+    #[serde(default)]
+    pub hold_secs: Option<u64>,
+    /// Intent: сумму выбирает плательщик в этих пределах (пожертвования, оплата по счету
+    /// с переплатой); не указывается вместе с `amount` и `amount_base_units`
+    #[serde(default)]
+    pub amount_range: Option<AmountRange>,
 }
 
-#[derive(Debug, Serialize, Clone)]
+/// Пределы суммы intent платежа в токенах, включительно
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AmountRange {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl AmountRange {
+    /// Пределы в базовых единицах токена
+    pub fn base_units(&self, decimals: u8) -> (u64, u64) {
+        (to_base_units(self.min, decimals), to_base_units(self.max, decimals))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Payment {
     pub id: String,
+    /// Мерчант, создавший платеж (None — анонимный запрос без API ключа)
+    pub merchant_id: Option<String>,
+    /// Тестовый платеж sandbox ключа: не касается блокчейна, завершается через
+    /// `simulate_complete` и не попадает в выручку и выгрузки
+    pub sandbox: bool,
     pub recipient: String,
+    /// Кошелек или vault мультисига (PDA)
+    pub recipient_kind: RecipientKind,
+    /// Для intent — сумма, выбранная плательщиком (до выбора — минимум), после оплаты —
+    /// фактически полученная
     pub amount: f64,
+    /// Точная сумма перевода в базовых единицах токена: по ней строится транзакция,
+    /// `amount` — для показа и отчетов
+    pub amount_base_units: u64,
+    /// Пределы суммы intent платежа (`None` — сумма фиксирована)
+    #[serde(default)]
+    pub amount_range: Option<AmountRange>,
+    /// Сколько получатель фактически получил в токене платежа (с чаевыми), по транзакции
+    /// оплаты; `None` — платеж не оплачен в сети
+    #[serde(default)]
+    pub paid_amount: Option<f64>,
+    /// Скидка по коду: `amount` уже за ее вычетом
+    pub discount: Option<AppliedDiscount>,
+    /// Часть суммы, оплаченная баллами: `amount` уже за ее вычетом
+    pub loyalty: Option<LoyaltyRedemption>,
+    /// Удержание: оплата идет на счет сервера, а не получателю
+    pub hold: Option<PaymentHold>,
+    /// Кошельки, которым выдается транзакция и с которых принимается оплата; пусто — любые
+    pub allowed_payers: Vec<String>,
     pub token: String,
     pub fee_recipient: String,
     pub fee_amount: f64,
     pub fee_token: String,
+    /// Комиссия в USD (если комиссия привязана к доллару)
+    pub fee_usd: Option<f64>,
+    /// Курс fee_token к USD, зафиксированный при создании
+    pub fee_usd_rate: Option<f64>,
+    /// Комиссия задана мерчантом в запросе, а не политикой
+    pub fee_overridden: bool,
     pub label: String,
     pub message: String,
+    pub memo: Option<String>,
+    /// Язык строк, показываемых кошельком
+    pub locale: Locale,
+    /// Допустимые варианты чаевых (пусто — чаевые выключены)
+    pub tip_presets_bps: Vec<u16>,
+    /// Данные мерчанта из запроса на создание
+    pub metadata: BTreeMap<String, String>,
+    pub success_url: Option<String>,
+    pub cancel_url: Option<String>,
+    /// Куда перенаправить браузер покупателя: `success_url` после оплаты, `cancel_url` после
+    /// истечения, отмены или сбоя; пока платеж открыт — `None`
+    pub redirect_url: Option<String>,
+    /// Чаевые, выбранные плательщиком при запросе транзакции
+    pub tip_bps: Option<u16>,
+    /// Сумма чаевых в токене платежа (отдельный перевод получателю)
+    pub tip_amount: Option<f64>,
+    /// Суммы для показа пользователю: точность токена, разделители тысяч
+    pub amount_display: String,
+    pub fee_display: String,
+    pub tip_display: Option<String>,
+    /// Итог к оплате; для разных токенов — по каждому токену через « + »
+    pub total_display: String,
     pub url: String,
-    pub qr_code: String,
+    /// Код короткой ссылки `/t/{code}` в `url` (при `server.short_links`)
+    pub short_code: Option<String>,
+    /// Токен `?t=` в `url` и `action_url` (при `server.transaction_request_tokens`).
+    /// В JSON не попадает: токен есть только в ссылках ответа на создание платежа
+    #[serde(default, skip_serializing)]
+    pub request_token: Option<RequestToken>,
+    /// Universal links кошельков на страницу оплаты (если задан `server.checkout_url`)
+    pub wallet_links: Option<WalletLinks>,
+    /// Solana Pay reference: уникальный ключ в транзакции платежа, по нему платеж
+    /// находится в блокчейне (`getSignaturesForAddress`) и через `/api/payments/lookup`
+    pub reference: String,
+    /// QR код (data URL) — не хранится, рисуется по запросу через кэш QrService
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub qr_code: Option<String>,
+    /// PNG с QR кодом (`GET /api/payment/{id}/qr`)
+    pub qr_url: String,
+    /// Запасной Solana Pay transfer request для кошельков без transaction request:
+    /// сумма без чаевых и комиссии, платеж находится по `reference`
+    pub transfer_url: String,
+    /// QR код `transfer_url` (data URL), рисуется вместе с `qr_code`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transfer_qr_code: Option<String>,
+    /// PNG с QR кодом `transfer_url` (`GET /api/payment/{id}/qr?kind=transfer`)
+    pub transfer_qr_url: String,
+    /// Solana Action платежа: ссылка раскрывается в Blink в X и кошельках
+    pub action_url: String,
+    /// Суммы в фиатной валюте по текущему курсу — только в ответе на `?display_currency=`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fiat: Option<FiatAmounts>,
     pub status: PaymentStatus,
+    /// Время последней смены статуса
+    pub status_changed_at: DateTime<Utc>,
+    /// Переходы статуса по порядку
+    pub status_history: Vec<StatusChange>,
     pub created_at: DateTime<Utc>,
+    /// Время последнего сохранения (проставляет storage); по нему строится ETag
+    pub updated_at: DateTime<Utc>,
+    /// Номер сохранения (проставляет storage): копию, прочитанную до чужого сохранения,
+    /// записать нельзя
+    pub version: u64,
     pub expires_at: DateTime<Utc>,
+    /// Секунд до истечения по часам сервера (0 — истек или уже не ожидает оплаты)
+    pub expires_in_seconds: i64,
+    /// Время сервера на момент ответа: UI считает отсчет от него, а не от часов клиента
+    pub server_time: DateTime<Utc>,
     pub signature: Option<String>,
     pub verified_at: Option<DateTime<Utc>>,
+    /// Время блока с транзакцией оплаты
+    pub block_time: Option<DateTime<Utc>>,
+    /// Кошелек, с которого пришла оплата: сюда возвращается удержание и выпускается
+    /// NFT-квитанция
+    pub payer: Option<String>,
+    /// Ссылка на транзакцию в обозревателе (для завершенных платежей)
+    pub explorer_url: Option<String>,
+    /// Когда платеж ушел в архив по политике хранения (см. `RetentionConfig`)
+    pub archived_at: Option<DateTime<Utc>>,
+    /// Оценка в USD по курсам на момент завершения (None — не завершен или оракул недоступен)
+    pub usd_valuation: Option<UsdValuation>,
+    /// Своп, которым плательщик платит другим токеном (последняя выданная транзакция)
+    pub swap: Option<PaymentSwap>,
+    /// Совпадения проверки адресов, с которыми платеж пропущен для ручного разбора
+    pub screening_flags: Vec<ScreeningFlag>,
+    /// NFT-квитанция плательщику (см. `ReceiptNftConfig`)
+    pub receipt_nft: Option<ReceiptNft>,
+}
+
+/// Курсы токенов к USD, зафиксированные оракулом при завершении платежа, и суммы по ним
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsdValuation {
+    /// Курс токена платежа
+    pub token_usd_rate: f64,
+    /// Курс токена комиссии
+    pub fee_token_usd_rate: f64,
+    pub amount_usd: f64,
+    pub tip_usd: Option<f64>,
+    pub fee_usd: f64,
+    pub priced_at: DateTime<Utc>,
+}
+
+impl Payment {
+    /// Пересчитать `expires_in_seconds` и `server_time` на текущий момент
+    pub fn refresh_countdown(&mut self) {
+        let now = Utc::now();
+        self.server_time = now;
+        self.expires_in_seconds = match self.status.is_open() {
+            true => (self.expires_at - now).num_seconds().max(0),
+            false => 0,
+        };
+    }
+
+    /// Проверить токен из transaction request URL; платежи без токена открыты
+    pub fn check_request_token(&self, token: Option<&str>) -> Result<(), RequestTokenError> {
+        match (&self.request_token, token) {
+            (None, _) => Ok(()),
+            (Some(expected), Some(token)) if constant_time_eq(expected.token.as_bytes(), token.as_bytes()) => Ok(()),
+            (Some(_), _) => Err(RequestTokenError::Invalid),
+        }
+    }
+
+    /// Может ли `account` оплатить платеж (`allowed_payers`)
+    pub fn allows_payer(&self, account: &str) -> bool {
+        self.allowed_payers.is_empty() || self.allowed_payers.iter().any(|payer| payer == account)
+    }
+
+    /// Куда переводится оплата: счет удержания или получатель
+    pub fn destination(&self) -> &str {
+        self.hold.as_ref().map_or(&self.recipient, |hold| &hold.escrow_account)
+    }
+
+    /// Solana Pay URL для QR нужного вида
+    pub fn qr_target(&self, kind: QrKind) -> &str {
+        match kind {
+            QrKind::Transaction => &self.url,
+            QrKind::Transfer => &self.transfer_url,
+        }
+    }
+
+    /// Перевести платеж в статус `to`, если переход допустим, с отметкой времени в истории.
+    /// Сохранение и событие — `PaymentService::transition`
+    pub fn transition(&mut self, to: PaymentStatus, reason: Option<&str>) -> Result<(), TransitionError> {
+        if !self.status.can_transition_to(to) {
+            return Err(TransitionError::Illegal { from: self.status, to });
+        }
+        let now = Utc::now();
+        self.status_history.push(StatusChange {
+            from: self.status,
+            to,
+            at: now,
+            reason: reason.map(str::to_string),
+        });
+        self.status = to;
+        self.status_changed_at = now;
+        if to == PaymentStatus::Completed {
+            self.verified_at = Some(now);
+        }
+        if to == PaymentStatus::Held {
+            if let Some(hold) = self.hold.as_mut() {
+                hold.expires_at = Some(now + Duration::seconds(hold.hold_secs as i64));
+            }
+        }
+        self.redirect_url = match to {
+            // Для покупателя удержанный платеж уже оплачен
+            PaymentStatus::Completed | PaymentStatus::Held => self.success_url.clone(),
+            PaymentStatus::Failed | PaymentStatus::Expired | PaymentStatus::Cancelled => self.cancel_url.clone(),
+            // Возврат средств — уже после ухода покупателя со страницы оплаты
+            PaymentStatus::Refunded | PaymentStatus::Released => self.redirect_url.take(),
+            PaymentStatus::Pending | PaymentStatus::Processing => None,
+        };
+        self.refresh_countdown();
+        Ok(())
+    }
+}
+
+/// Краткий статус платежа для массовой синхронизации (`POST /api/payments/status`)
+#[derive(Debug, Clone, Serialize)]
+pub struct PaymentStatusSummary {
+    pub status: PaymentStatus,
+    pub signature: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<&Payment> for PaymentStatusSummary {
+    fn from(payment: &Payment) -> Self {
+        Self {
+            status: payment.status,
+            signature: payment.signature.clone(),
+            updated_at: payment.updated_at,
+        }
+    }
+}
+
+/// Фильтр списка платежей: `status`, `limit` и `metadata.<ключ>=<значение>` (все условия сразу),
+/// плюс `consistency` — читать реплику или основную таблицу
+#[derive(Debug, Clone, Default)]
+pub struct PaymentListFilter {
+    pub status: Option<PaymentStatus>,
+    pub metadata: BTreeMap<String, String>,
+    pub limit: usize,
+    pub consistency: ReadConsistency,
+}
+
+impl PaymentListFilter {
+    /// Параметры query string; неизвестные параметры — ошибка, а не молча пустой фильтр
+    pub fn parse<'a>(params: impl IntoIterator<Item = (&'a str, &'a str)>) -> Result<Self, ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        let mut filter = Self { limit: DEFAULT_PAYMENT_LIST_LIMIT, ..Self::default() };
+
+        for (name, value) in params {
+            if let Some(key) = name.strip_prefix("metadata.") {
+                filter.metadata.insert(key.to_string(), value.to_string());
+                continue;
+            }
+            match name {
+                "status" => match serde_json::from_value(serde_json::Value::String(value.to_string())) {
+                    Ok(status) => filter.status = Some(status),
+                    Err(_) => errors.add("status", "invalid", format!("Unknown payment status: {}", value)),
+                },
+                "limit" => match value.parse() {
+                    Ok(limit) if (1..=MAX_PAYMENT_LIST_LIMIT).contains(&limit) => filter.limit = limit,
+                    _ => errors.add(
+                        "limit",
+                        "out_of_range",
+                        format!("limit must be between 1 and {}", MAX_PAYMENT_LIST_LIMIT),
+                    ),
+                },
+                "consistency" => match serde_json::from_value(serde_json::Value::String(value.to_string())) {
+                    Ok(consistency) => filter.consistency = consistency,
+                    Err(_) => errors.add("consistency", "invalid", "consistency must be strong or eventual"),
+                },
+                other => errors.add(other, "unknown_parameter", format!("Unknown query parameter: {}", other)),
+            }
+        }
+        errors.into_result().map(|_| filter)
+    }
+
+    pub fn matches(&self, payment: &Payment) -> bool {
+        self.status.is_none_or(|status| payment.status == status)
+            && self.metadata.iter().all(|(key, value)| payment.metadata.get(key) == Some(value))
+    }
+}
+
+/// Только запрошенные поля платежа (`?fields=id,status`). `qr_code`, `transfer_qr_code`
+/// и `fiat` есть, только если они были добавлены в платеж (`with_qr_code`, `with_display_currency`)
+pub fn select_fields(payment: &Payment, fields: &[&str]) -> anyhow::Result<serde_json::Value> {
+    let serde_json::Value::Object(mut all) = serde_json::to_value(payment)? else {
+        anyhow::bail!("Payment is not serialized as an object");
+    };
+
+    let mut errors = ValidationErrors::new();
+    let mut selected = serde_json::Map::new();
+    for &field in fields {
+        match all.remove(field) {
+            Some(value) => {
+                selected.insert(field.to_string(), value);
+            }
+            None if field == "qr_code" || field == "transfer_qr_code" || field == "fiat" => {
+                selected.insert(field.to_string(), serde_json::Value::Null);
+            }
+            None if selected.contains_key(field) => {}
+            None => errors.add("fields", "unknown_field", format!("Unknown payment field: {}", field)),
+        }
+    }
+    errors.into_result()?;
+
+    Ok(serde_json::Value::Object(selected))
 }
 
-#[derive(Debug, Serialize, Clone)]
+/// Статус платежа. Переходы — только через `Payment::transition`:
+/// Pending → Processing → Completed/Failed/Expired, Pending → Cancelled, Completed → Refunded;
+/// с удержанием: Pending/Processing → Held → Completed (capture) или Released (release)
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum PaymentStatus {
+    /// Ожидает оплаты
     Pending,
+    /// Транзакция оплаты отправлена, ждем подтверждения
+    Processing,
     Completed,
-    Expired,
     Failed,
+    Expired,
+    /// Отменен мерчантом до оплаты
+    Cancelled,
+    /// Средства возвращены плательщику
+    Refunded,
+    /// Оплачен, средства удержаны на счете сервера до capture или release
+    Held,
+    /// Удержанные средства возвращены плательщику
+    Released,
+}
+
+impl PaymentStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PaymentStatus::Pending => "pending",
+            PaymentStatus::Processing => "processing",
+            PaymentStatus::Completed => "completed",
+            PaymentStatus::Failed => "failed",
+            PaymentStatus::Expired => "expired",
+            PaymentStatus::Cancelled => "cancelled",
+            PaymentStatus::Refunded => "refunded",
+            PaymentStatus::Held => "held",
+            PaymentStatus::Released => "released",
+        }
+    }
+
+    /// Платеж еще можно оплатить: транзакция выдается, отправляется и верифицируется
+    pub fn is_open(&self) -> bool {
+        matches!(self, PaymentStatus::Pending | PaymentStatus::Processing)
+    }
+
+    /// Допустимые переходы конечного автомата
+    pub fn can_transition_to(&self, to: PaymentStatus) -> bool {
+        use PaymentStatus::*;
+        matches!(
+            (self, to),
+            (Pending, Processing | Completed | Failed | Expired | Cancelled | Held)
+                | (Processing, Completed | Failed | Expired | Held)
+                | (Completed, Refunded)
+                | (Held, Completed | Released)
+        )
+    }
+
+    /// Событие outbox при переходе в статус
+    pub fn event_type(&self) -> String {
+        format!("payment.{}", self.as_str())
+    }
+}
+
+impl std::fmt::Display for PaymentStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Переход статуса в истории платежа
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusChange {
+    pub from: PaymentStatus,
+    pub to: PaymentStatus,
+    pub at: DateTime<Utc>,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TransitionError {
+    #[error("Payment cannot move from {from} to {to}")]
+    Illegal { from: PaymentStatus, to: PaymentStatus },
+}
+
+/// Одноразовый токен transaction request URL. Первый кошелек, получивший по нему
+/// транзакцию, закрепляет токен за собой: другим кошелькам транзакция больше не выдается
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestToken {
+    pub token: String,
+    /// Кошелек, которому по токену выдана транзакция
+    pub claimed_by: Option<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RequestTokenError {
+    /// Токена нет или он не совпадает — ответ как для несуществующего платежа
+    #[error("Payment not found")]
+    Invalid,
+    #[error("Transaction for this payment was already requested by another wallet")]
+    Claimed,
+}
+
+/// Транзакция запрошена кошельком не из `allowed_payers` платежа
+#[derive(Debug, thiserror::Error)]
+#[error("Account {0} is not allowed to pay this payment")]
+pub struct PayerNotAllowed(pub String);
+
+/// Операция не сочетается с режимом платежа (sandbox или боевой)
+#[derive(Debug, thiserror::Error)]
+pub enum SandboxError {
+    #[error("Sandbox payments are not paid on chain; use POST /api/payment/{{id}}/simulate_complete")]
+    OnChain,
+    #[error("Only sandbox payments can be completed by simulation")]
+    NotSandbox,
 }
 
 #[derive(Debug, Serialize)]
@@ -68,19 +632,57 @@ pub struct VerificationResult {
     pub status: PaymentStatus,
     pub verified: bool,
     pub signature: Option<String>,
+    pub explorer_url: Option<String>,
+    pub memo: Option<String>,
     pub details: String,
 }
 
 impl PaymentService {
     pub async fn new(config: Config) -> anyhow::Result<Self> {
-        let multichain = MultichainService::new(config.clone());
+        let rpc = Arc::new(HttpSolanaRpc::new(&config)?);
+        Self::with_rpc(config, rpc).await
+    }
+
+    /// Сервис с заданной реализацией RPC (в тестах — `MockSolanaRpc`)
+    pub async fn with_rpc(config: Config, rpc: Arc<dyn SolanaRpc>) -> anyhow::Result<Self> {
+        let multichain = MultichainService::new(config.clone(), rpc);
         let qr_service = QrService::new();
-        let storage = StorageService::new();
+        let storage = StorageService::new()
+            .with_payment_cache(PaymentCache::from_config(&config.storage))
+            .with_payment_replica(PaymentReplica::from_config(&config.storage))
+            .with_tenants(config.tenants.clone());
+        let ownership = OwnershipService::new(storage.clone(), multichain.rpc.clone());
+        let fee_wallets = FeeWalletSelector::new(&config.solana);
+        let oracle = PriceOracle::new(config.clone());
+        let fee_calculator = FeeCalculator::new(config.clone(), oracle.clone());
+        let payload_signer = PayloadSigner::load(config.server.signing_key_path.as_deref())?;
+        let webhooks = WebhookService::new(storage.clone()).with_payload_signer(payload_signer.clone());
+        let screening = ScreeningService::from_config(config.clone(), storage.clone())?;
+        let limits = LimitService::new(config.clone(), storage.clone());
+        let discounts = DiscountService::new(config.clone(), storage.clone());
+        let loyalty = LoyaltyService::new(config.clone(), storage.clone());
+        let tenants = TenantService::new(config.clone(), storage.clone());
 
         Ok(Self {
             multichain,
             qr_service,
             storage,
+            ownership,
+            fee_wallets,
+            fee_calculator,
+            oracle,
+            swaps: SwapService::new(config.clone()),
+            compute: ComputeEstimator::new(config.clone()),
+            builds: BuildLimiter::from_config(&config.server),
+            ids: ids::from_config(&config.server),
+            screening,
+            limits,
+            discounts,
+            loyalty,
+            tenants,
+            escrow_account: None,
+            webhooks,
+            payload_signer,
             config,
         })
     }
@@ -89,78 +691,855 @@ impl PaymentService {
     pub async fn create_payment_with_fee(
         &self,
         request: CreatePaymentRequest,
+        merchant: Option<&MerchantConfig>,
     ) -> anyhow::Result<Payment> {
         // Валидация входных данных
         self.validate_payment_request(&request)?;
 
-        // Генерируем уникальный ID
-        let payment_id = format!("pay_{}", Uuid::new_v4().simple());
+        // Получатель должен подтвердить владение кошельком (если включено)
+        self.ensure_recipient_verified(&request.recipient).await?;
 
-        // Создаем Solana Pay URL с комиссией
-        let (url, qr_code) = self.create_solana_pay_url(&request, &payment_id).await?;
+        self.issue_payment(request, merchant).await
+    }
+
+    /// Проверить адрес получателя в сети: кошелек или vault мультисига (по политике
+    /// `off_curve_recipients`), но не токен-аккаунт и не аккаунт программы
+    pub async fn check_recipient(&self, recipient: &str) -> anyhow::Result<RecipientKind> {
+        let pubkey = Pubkey::from_str(recipient)
+            .map_err(|e| anyhow::anyhow!("Invalid recipient address: {}", e))?;
+        recipient::classify(self.multichain.rpc.as_ref(), &pubkey, self.config.solana.off_curve_recipients).await
+            .map_err(|e| {
+                let mut errors = ValidationErrors::new();
+                errors.add("recipient", e.code(), e.to_string());
+                errors.into()
+            })
+    }
+
+    /// Проверка владения кошельком получателя (если включена в конфиге)
+    pub async fn ensure_recipient_verified(&self, recipient: &str) -> anyhow::Result<()> {
+        if self.config.solana.require_verified_recipient
+            && !self.ownership.is_verified(recipient).await?
+        {
+            anyhow::bail!(
+                "Recipient wallet {} is not verified. Prove ownership via /api/merchant/wallet/challenge first",
+                recipient
+            );
+        }
+        Ok(())
+    }
+
+    /// Создать платеж для заранее проверенного получателя (POS сессия, статический QR):
+    /// владение кошельком проверено при их создании, поэтому проверяются только поля запроса
+    pub async fn create_session_payment(
+        &self,
+        request: CreatePaymentRequest,
+        merchant: Option<&MerchantConfig>,
+    ) -> anyhow::Result<Payment> {
+        self.validate_payment_request(&request)?;
+        self.issue_payment(request, merchant).await
+    }
+
+    /// Выпустить платеж по уже проверенному запросу
+    async fn issue_payment(
+        &self,
+        request: CreatePaymentRequest,
+        merchant: Option<&MerchantConfig>,
+    ) -> anyhow::Result<Payment> {
+        let mut payment = self.build_payment(request, merchant).await?;
+
+        // Код и баллы засчитываются до сохранения: условия перепроверяются под блокировкой.
+        // Если баллы списать не удалось или платеж не сохранился, все возвращается
+        if let Some(discount) = &payment.discount {
+            let merchant_id = merchant.map(|m| m.id.as_str());
+            self.discounts.redeem(discount, &payment.id, merchant_id, &payment.token).await?;
+        }
+        if let Err(e) = self.redeem_points_and_save(&mut payment, merchant).await {
+            if let Some(discount) = &payment.discount {
+                if let Err(release) = self.discounts.release(discount, &payment.id).await {
+                    tracing::error!(payment_id = %payment.id, code = %discount.code, error = %release, "Failed to release discount");
+                }
+            }
+            return Err(e);
+        }
+
+        tracing::info!(
+            payment_id = %payment.id,
+            merchant = payment.merchant_id.as_deref(),
+            amount = payment.amount,
+            amount_base_units = payment.amount_base_units,
+            token = %payment.token,
+            fee_amount = payment.fee_amount,
+            fee_token = %payment.fee_token,
+            fee_overridden = payment.fee_overridden,
+            sandbox = payment.sandbox,
+            "Payment created"
+        );
+
+        Ok(payment)
+    }
+
+    /// Списать баллы в счет платежа и сохранить его; если платеж не сохранился — баллы возвращаются
+    async fn redeem_points_and_save(&self, payment: &mut Payment, merchant: Option<&MerchantConfig>) -> anyhow::Result<()> {
+        let points = payment.loyalty.clone().zip(merchant);
+        if let Some((redemption, merchant)) = &points {
+            self.loyalty.redeem(redemption, &payment.id, &merchant.id).await?;
+        }
+        let saved = self.storage.save_payment_with_event(payment, "payment.created").await;
+        if let (Err(_), Some((redemption, merchant))) = (&saved, &points) {
+            if let Err(e) = self.loyalty.release(redemption, &payment.id, &merchant.id).await {
+                tracing::error!(payment_id = %payment.id, wallet = %redemption.wallet, error = %e, "Failed to release loyalty points");
+            }
+        }
+        saved
+    }
+
+    /// Собрать платеж по проверенному запросу: проверки мерчанта и получателя, комиссия,
+    /// ссылки. Скидка и баллы только рассчитываются, в storage ничего не пишется
+    async fn build_payment(
+        &self,
+        request: CreatePaymentRequest,
+        merchant: Option<&MerchantConfig>,
+    ) -> anyhow::Result<Payment> {
+        // Мерчант может принимать только часть поддерживаемых токенов
+        if let Some(merchant) = merchant.filter(|merchant| !merchant.accepts_token(&request.token)) {
+            let mut errors = ValidationErrors::new();
+            errors.add(
+                "token",
+                "not_accepted",
+                format!("Token {} is not accepted by this merchant. Accepted tokens: {}", request.token, merchant.tokens.join(", ")),
+            );
+            return Err(errors.into());
+        }
+
+        let payment_id = self.storage.allocate_payment_id(self.ids.as_ref()).await?;
+
+        // Сумма к переводу — целые базовые единицы; `amount` выводится из них
+        let decimals = self.config.token_decimals(&request.token);
+        let amount_base_units = request.amount_base_units
+            .or(request.amount_range.map(|range| range.base_units(decimals).0))
+            .unwrap_or_else(|| to_base_units(request.amount, decimals));
+
+        // Скидка по коду; использование засчитывается при сохранении платежа
+        let discount = match &request.discount_code {
+            Some(code) => Some(self.discounts.quote(code, merchant, &request.token, amount_base_units).await?),
+            None => None,
+        };
+        let amount_base_units = amount_base_units - discount.as_ref().map_or(0, |d| d.discount_base_units);
+        // Оплата баллами; баллы списываются при сохранении платежа
+        let loyalty = match &request.redeem_points {
+            Some(redeem) => Some(self.loyalty.quote(redeem, merchant, &request.token, amount_base_units).await?),
+            None => None,
+        };
+        let amount_base_units = amount_base_units - loyalty.as_ref().map_or(0, |l| l.amount_base_units);
+        let amount = amount_base_units as f64 / 10_f64.powi(decimals as i32);
+
+        // Риск-лимиты мерчанта: число и объем платежей за 24 часа
+        if let Some(merchant) = merchant {
+            self.limits.check(merchant, &request.token, amount).await?;
+        }
+
+        // Sandbox платеж не обращается к блокчейну и не проходит проверку адресов
+        let sandbox = merchant.is_some_and(|merchant| merchant.sandbox);
+        let hold = match (request.hold, self.escrow_account) {
+            (true, _) if sandbox => {
+                let mut errors = ValidationErrors::new();
+                errors.add("hold", "not_allowed", "Sandbox payments cannot be held");
+                return Err(errors.into());
+            }
+            (true, Some(escrow_account)) => Some(PaymentHold {
+                escrow_account: escrow_account.to_string(),
+                hold_secs: request.hold_secs.unwrap_or(self.config.escrow.default_hold_secs),
+                expires_at: None,
+                pending_action: None,
+                signature: None,
+            }),
+            _ => None,
+        };
+        let (recipient_kind, recipient_flag) = if sandbox {
+            (RecipientKind::Wallet, None)
+        } else {
+            // Кошелек или vault мультисига; аккаунты программ не принимают платежи
+            let recipient_kind = self.check_recipient(&request.recipient).await?;
+            // Получатель из denylist или санкционного списка: отказ или пометка платежа
+            let flag = self.screening.check(&payment_id, ScreeningRole::Recipient, &request.recipient).await?;
+            (recipient_kind, flag)
+        };
+
+        // Комиссия из запроса (в границах политики мерчанта), иначе фиксированная или по курсу USD
+        let fee_override = self.fee_calculator
+            .override_fee(merchant, request.fee_amount, request.fee_token.as_deref())?;
+        let fee_overridden = fee_override.is_some();
+        let fee = match fee_override {
+            Some(fee) => fee,
+            None => self.fee_calculator.calculate(merchant).await?,
+        };
+
+        // Язык проверен при валидации запроса
+        let locale: Locale = request.locale.as_deref()
+            .and_then(|l| l.parse().ok())
+            .unwrap_or_default();
 
         // Создаем объект платежа
         let now = Utc::now();
-        let payment = Payment {
+        let mut payment = Payment {
             id: payment_id.clone(),
+            merchant_id: merchant.map(|m| m.id.clone()),
+            sandbox,
             recipient: request.recipient.clone(),
-            amount: request.amount,
+            recipient_kind,
+            amount,
+            amount_base_units,
+            amount_range: request.amount_range,
+            paid_amount: None,
+            discount,
+            loyalty,
+            hold,
+            allowed_payers: request.allowed_payers.clone().unwrap_or_default(),
             token: request.token.clone(),
-            fee_recipient: self.config.solana.fee_wallet.clone(),
-            fee_amount: self.config.solana.fee_amount,
-            fee_token: self.config.solana.fee_token.clone(),
-            label: request.label.unwrap_or_else(|| format!("Payment {}", request.token)),
+            // Кошелек для комиссии фиксируется на платеже и используется до верификации
+            fee_recipient: match merchant.and_then(|m| m.fee_wallet.as_ref()) {
+                Some(fee_wallet) => fee_wallet.clone(),
+                None => self.fee_wallets.next().to_string(),
+            },
+            fee_amount: fee.amount,
+            fee_token: fee.token.clone(),
+            fee_usd: fee.usd_value,
+            fee_usd_rate: fee.usd_rate,
+            fee_overridden,
+            label: request.label
+                .or_else(|| merchant.and_then(|m| m.branding.as_ref()?.display_name.clone()))
+                .unwrap_or_else(|| locale.payment_label(&request.token)),
             message: request.message.unwrap_or_else(|| {
-                format!("{} {} + {} {} fee",
-                        request.amount, request.token,
-                        self.config.solana.fee_amount, self.config.solana.fee_token)
+                locale.payment_message(amount, &request.token, fee.amount, &fee.token)
             }),
-            url,
-            qr_code,
+            memo: request.memo.clone(),
+            locale,
+            tip_presets_bps: request.tip_presets_bps.clone().unwrap_or_default(),
+            metadata: request.metadata.clone().unwrap_or_default(),
+            success_url: request.success_url.clone(),
+            cancel_url: request.cancel_url.clone(),
+            redirect_url: None,
+            tip_bps: None,
+            tip_amount: None,
+            amount_display: String::new(),
+            fee_display: String::new(),
+            tip_display: None,
+            total_display: String::new(),
+            // Ссылки и reference — в `assign_links`
+            url: String::new(),
+            short_code: None,
+            action_url: String::new(),
+            request_token: None,
+            wallet_links: None,
+            reference: String::new(),
+            qr_code: None,
+            qr_url: String::new(),
+            transfer_url: String::new(),
+            transfer_qr_code: None,
+            transfer_qr_url: String::new(),
+            fiat: None,
             status: PaymentStatus::Pending,
+            status_changed_at: now,
+            status_history: Vec::new(),
             created_at: now,
-            expires_at: now + Duration::minutes(30),
+            updated_at: now,
+            version: 0,
+            expires_at: now + Duration::minutes(PAYMENT_LIFETIME_MINUTES),
+            expires_in_seconds: 0,
+            server_time: now,
             signature: None,
             verified_at: None,
+            block_time: None,
+            payer: None,
+            explorer_url: None,
+            archived_at: None,
+            usd_valuation: None,
+            swap: None,
+            screening_flags: recipient_flag.into_iter().collect(),
+            receipt_nft: None,
         };
 
-        // Сохраняем в storage
-        self.storage.save_payment(&payment_id, &payment).await?;
-
-        log::info!("Payment created: {} for {} {} + {} {} fee",
-            payment_id, request.amount, request.token,
-            self.config.solana.fee_amount, self.config.solana.fee_token);
+        self.assign_links(&mut payment).await?;
+        self.update_display_amounts(&mut payment);
+        payment.refresh_countdown();
 
         Ok(payment)
     }
 
-    /// Создать Solana Pay URL с комиссией
-    async fn create_solana_pay_url(
+    /// Reference, короткая ссылка, токен `?t=` и все URL платежа по его ID
+    async fn assign_links(&self, payment: &mut Payment) -> anyhow::Result<()> {
+        payment.reference = Keypair::new().pubkey().to_string();
+
+        // Создаем Solana Pay URL с комиссией
+        payment.short_code = match self.config.server.short_links {
+            true => Some(self.storage.allocate_short_code(self.config.server.short_link_length).await?),
+            false => None,
+        };
+        payment.request_token = self.config.server.transaction_request_tokens
+            .then(|| RequestToken { token: ids::short_link_code(REQUEST_TOKEN_LENGTH), claimed_by: None });
+        let token = payment.request_token.as_ref().map(|t| t.token.as_str());
+        payment.url = self.create_solana_pay_url(&payment.id, payment.short_code.as_deref(), token);
+        payment.action_url = actions::action_url(&self.config, &payment.id, token);
+
+        payment.wallet_links = match &self.config.server.checkout_url {
+            Some(checkout_url) => Some(WalletLinks::for_payment(checkout_url, &payment.id)?),
+            None => None,
+        };
+        payment.qr_url = format!("{}/api/payment/{}/qr", self.config.base_url(), payment.id);
+        payment.transfer_qr_url = format!("{}/api/payment/{}/qr?kind=transfer", self.config.base_url(), payment.id);
+        payment.transfer_url = self.create_transfer_url(payment);
+        Ok(())
+    }
+
+    /// Заготовки платежей для пула (`POST /api/pools`): запрос и получатель проверяются
+    /// один раз, риск-лимиты — на все `count` платежей сразу. У каждой заготовки свои ID,
+    /// reference, ссылки и QR коды. В таблицу платежей заготовки попадают только при
+    /// выдаче (`issue_pooled_payment`)
+    pub async fn prepare_pooled_payments(
         &self,
-        request: &CreatePaymentRequest,
-        payment_id: &str,
-    ) -> anyhow::Result<(String, String)> {
-        // Формируем URL на основе конфигурации
-        let protocol = if self.config.server.ssl { "https" } else { "http" };
-        let base_url = format!("{}://{}", protocol, self.config.server.domain);
+        request: CreatePaymentRequest,
+        merchant: &MerchantConfig,
+        count: usize,
+    ) -> anyhow::Result<Vec<Payment>> {
+        // Скидка и баллы относятся к конкретному покупателю, а не к партии платежей
+        let mut errors = ValidationErrors::new();
+        if request.discount_code.is_some() {
+            errors.add("payment.discount_code", "not_allowed", "Pooled payments cannot use discount codes");
+        }
+        if request.redeem_points.is_some() {
+            errors.add("payment.redeem_points", "not_allowed", "Pooled payments cannot redeem loyalty points");
+        }
+        errors.into_result()?;
+        self.validate_payment_request(&request)?;
+        self.ensure_recipient_verified(&request.recipient).await?;
+
+        let token = request.token.clone();
+        let template = self.build_payment(request, Some(merchant)).await?;
+        self.limits.check_batch(merchant, &token, template.amount, count).await?;
+        let mut payments = Vec::with_capacity(count);
+        for _ in 0..count {
+            let mut payment = template.clone();
+            if !payments.is_empty() {
+                payment.id = self.storage.allocate_payment_id(self.ids.as_ref()).await?;
+                self.assign_links(&mut payment).await?;
+            }
+            payments.push(self.with_qr_code(payment)?);
+        }
+        Ok(payments)
+    }
+
+    /// Выдать заготовку из пула: сроки отсчитываются с момента выдачи, платеж сохраняется
+    /// как созданный. QR коды остаются в ответе, но не хранятся
+    pub async fn issue_pooled_payment(&self, mut payment: Payment) -> anyhow::Result<Payment> {
+        let now = Utc::now();
+        payment.created_at = now;
+        payment.status_changed_at = now;
+        payment.expires_at = now + Duration::minutes(PAYMENT_LIFETIME_MINUTES);
+
+        let qr_code = payment.qr_code.take();
+        let transfer_qr_code = payment.transfer_qr_code.take();
+        // Версия 0: ID, совпавший с уже сохраненным платежом, даст VersionConflict
+        self.storage.save_payment_with_event(&mut payment, "payment.created").await?;
+        payment.qr_code = qr_code;
+        payment.transfer_qr_code = transfer_qr_code;
+
+        tracing::info!(
+            payment_id = %payment.id,
+            merchant = payment.merchant_id.as_deref(),
+            amount = payment.amount,
+            token = %payment.token,
+            "Pooled payment issued"
+        );
+
+        Ok(payment)
+    }
+
+    /// Платеж для ответов не создателю (страница оплаты, опрос статуса, QR по ID):
+    /// `url` и `action_url` без токена `?t=`
+    pub fn without_request_token(&self, mut payment: Payment) -> Payment {
+        if payment.request_token.take().is_some() {
+            payment.url = self.create_solana_pay_url(&payment.id, payment.short_code.as_deref(), None);
+            payment.action_url = actions::action_url(&self.config, &payment.id, None);
+        }
+        payment
+    }
 
+    /// Создать Solana Pay URL с комиссией
+    fn create_solana_pay_url(&self, payment_id: &str, short_code: Option<&str>, token: Option<&str>) -> String {
         // Создаем правильный Solana Pay Transaction Request URL
-        let transaction_request_url = format!(
-            "solana:{}/api/payment/{}/transaction",
-            base_url, payment_id
+        let link = match short_code {
+            Some(short_code) => format!("{}/t/{}", self.config.base_url(), short_code),
+            None => format!("{}/api/payment/{}/transaction", self.config.base_url(), payment_id),
+        };
+        // Ссылку с query параметрами спецификация требует передавать URL-encoded
+        let transaction_request_url = match token {
+            Some(token) => format!("solana:{}", byte_serialize(format!("{}?t={}", link, token).as_bytes()).collect::<String>()),
+            None => format!("solana:{}", link),
+        };
+
+        tracing::debug!(url = %transaction_request_url, "Transaction request URL generated");
+
+        transaction_request_url
+    }
+
+    /// Solana Pay transfer request: `solana:<получатель>?amount=...&spl-token=...&reference=...`
+    fn create_transfer_url(&self, payment: &Payment) -> String {
+        let mut url = url::Url::parse(&format!("solana:{}", payment.destination())).expect("solana: URL is valid");
+        {
+            let mut query = url.query_pairs_mut();
+            // Без суммы кошелек спрашивает ее у плательщика (intent)
+            if payment.amount_range.is_none() {
+                let decimals = self.config.token_decimals(&payment.token);
+                query.append_pair("amount", &decimal_base_units(payment.amount_base_units as u128, decimals));
+            }
+            if let Some(mint) = self.config.get_token_config(&payment.token).and_then(|t| t.mint) {
+                query.append_pair("spl-token", &mint);
+            }
+            query
+                .append_pair("reference", &payment.reference)
+                .append_pair("label", &payment.label)
+                .append_pair("message", &payment.message);
+            if let Some(memo) = &payment.memo {
+                query.append_pair("memo", memo);
+            }
+        }
+        url.into()
+    }
+
+    /// Платеж с обоими QR кодами для ответа клиенту
+    pub fn with_qr_code(&self, mut payment: Payment) -> anyhow::Result<Payment> {
+        payment.qr_code = Some(self.qr_service.generate_qr_code(&payment.url)?);
+        payment.transfer_qr_code = Some(self.qr_service.generate_qr_code(&payment.transfer_url)?);
+        Ok(payment)
+    }
+
+    /// Платеж с суммами в валюте `currency` для ответа клиенту. Недоступный оракул
+    /// не мешает показу платежа: `fiat` остается пустым
+    pub async fn with_display_currency(&self, mut payment: Payment, currency: &str) -> Result<Payment, ValidationErrors> {
+        let currency = currency.trim().to_uppercase();
+        if !self.config.server.display_currencies.contains(&currency) {
+            let mut errors = ValidationErrors::new();
+            errors.add(
+                "display_currency",
+                "unsupported",
+                format!("Unsupported display currency {}, supported: {}", currency, self.config.server.display_currencies.join(", ")),
+            );
+            return Err(errors);
+        }
+        let (token_rate, fee_token_rate) = tokio::join!(
+            self.oracle.fiat_price(&payment.token, &currency),
+            self.oracle.fiat_price(&payment.fee_token, &currency),
         );
+        match (token_rate, fee_token_rate) {
+            (Ok(token_rate), Ok(fee_token_rate)) => {
+                payment.fiat = Some(FiatAmounts::convert(&payment, &currency, token_rate, fee_token_rate));
+            }
+            (Err(e), _) | (_, Err(e)) => {
+                tracing::warn!(payment_id = %payment.id, currency = %currency, error = %e, "Display conversion is unavailable");
+            }
+        }
+        Ok(payment)
+    }
 
-        // Генерируем QR код
-        let qr_code = self.qr_service.generate_qr_code(&transaction_request_url)?;
+    /// PNG с QR кодом платежа нужного вида
+    pub fn qr_png(&self, payment: &Payment, kind: QrKind) -> anyhow::Result<Bytes> {
+        self.qr_service.png(payment.qr_target(kind))
+    }
 
-        log::info!("Generated QR URL: {}", transaction_request_url);
+    /// Хранилище (общее с другими сервисами)
+    pub fn storage(&self) -> &StorageService {
+        &self.storage
+    }
 
-        Ok((transaction_request_url, qr_code))
+    /// Ключ подписи данных платежей (`server.signing_key_path`)
+    pub fn payload_signer(&self) -> Option<&PayloadSigner> {
+        self.payload_signer.as_ref()
     }
 
-    /// Получить информацию о платеже
+    /// Webhook уведомления мерчантов
+    pub fn webhooks(&self) -> &WebhookService {
+        &self.webhooks
+    }
+
+    /// Solana RPC (общий с другими сервисами)
+    pub fn rpc(&self) -> &Arc<dyn SolanaRpc> {
+        &self.multichain.rpc
+    }
+
+    /// Текущая конфигурация сервера
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Платеж по ID; короткий код можно ввести в любом регистре, код с ошибкой не найдется
     pub async fn get_payment(&self, payment_id: &str) -> anyhow::Result<Option<Payment>> {
-        self.storage.get_payment(payment_id).await
+        match self.ids.normalize(payment_id) {
+            Some(payment_id) => self.storage.get_payment(&payment_id).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Платежи по списку ID в паре с ID из запроса; неизвестные и некорректные ID пропускаются
+    pub async fn get_payments(&self, payment_ids: &[String]) -> anyhow::Result<Vec<(String, Payment)>> {
+        let requested: Vec<(&String, String)> = payment_ids.iter()
+            .filter_map(|id| Some((id, self.ids.normalize(id)?)))
+            .collect();
+        let canonical: Vec<String> = requested.iter().map(|(_, id)| id.clone()).collect();
+        let payments = self.storage.get_payments(&canonical).await?;
+        Ok(requested.into_iter()
+            .filter_map(|(id, canonical)| {
+                let payment = payments.iter().find(|payment| payment.id == canonical)?;
+                Some((id.clone(), payment.clone()))
+            })
+            .collect())
+    }
+
+    /// Платеж по коду короткой ссылки `/t/{code}`
+    pub async fn get_payment_by_short_code(&self, short_code: &str) -> anyhow::Result<Option<Payment>> {
+        match ids::normalize_short_link(short_code) {
+            Some(short_code) => self.storage.find_payment_by_short_code(&short_code).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Заменить генератор ID новых платежей
+    pub fn with_id_generator(mut self, ids: Arc<dyn IdGenerator>) -> Self {
+        self.ids = ids;
+        self
+    }
+
+    /// Включить платежи с удержанием на счете `account` (hot wallet)
+    pub fn with_escrow_account(mut self, account: Pubkey) -> Self {
+        self.escrow_account = Some(account);
+        self
+    }
+
+    /// Запомнить транзакцию, выданную кошельку, чтобы ее можно было пересобрать
+    pub async fn remember_transaction(
+        &self,
+        payment: &Payment,
+        account: &str,
+        part: TransactionPart,
+        locale: Locale,
+        swap: Option<&SwapInstructions>,
+        fee_payer: Option<&Pubkey>,
+    ) -> anyhow::Result<()> {
+        self.storage.save_issued_transaction(&IssuedTransaction {
+            payment_id: payment.id.clone(),
+            account: account.to_string(),
+            part,
+            locale,
+            swap: swap.cloned(),
+            fee_payer: fee_payer.map(Pubkey::to_string),
+            compute_budget: None,
+            issued_at: Utc::now(),
+            refreshes: 0,
+            refreshed_at: None,
+        }).await
+    }
+
+    /// Запомнить compute бюджет собранной транзакции (для сверки при отправке)
+    pub async fn record_compute_budget(&self, payment_id: &str, compute_budget: Option<ComputeBudget>) -> anyhow::Result<()> {
+        self.storage.set_issued_compute_budget(payment_id, compute_budget).await
+    }
+
+    /// Оценка compute units транзакций (общий кэш)
+    pub fn compute_estimator(&self) -> &ComputeEstimator {
+        &self.compute
+    }
+
+    /// Ограничение параллельных сборок транзакций (общая очередь)
+    pub fn build_limiter(&self) -> &BuildLimiter {
+        &self.builds
+    }
+
+    /// Параметры выданной транзакции ожидающего платежа для пересборки со свежим blockhash.
+    /// Нужен токен из transaction request URL; транзакция, выданная не тому кошельку, за
+    /// которым закреплен токен, не пересобирается. Пересборки ограничены
+    /// `server.transaction_refresh_secs`/`transaction_refresh_limit`
+    pub async fn refresh_transaction(&self, payment: &Payment, token: Option<&str>) -> anyhow::Result<IssuedTransaction> {
+        payment.check_request_token(token)?;
+        if !payment.status.is_open() || payment.expires_at <= Utc::now() {
+            return Err(RefreshError::NotPending.into());
+        }
+        let claimed_by = payment.request_token.as_ref().and_then(|t| t.claimed_by.as_deref());
+        if let Some(claimed_by) = claimed_by {
+            let issued = self.storage.get_issued_transaction(&payment.id).await?
+                .ok_or(RefreshError::NotIssued)?;
+            if issued.account != claimed_by {
+                return Err(RequestTokenError::Claimed.into());
+            }
+        }
+        self.storage.claim_transaction_refresh(
+            &payment.id,
+            Duration::seconds(self.config.server.transaction_refresh_secs as i64),
+            self.config.server.transaction_refresh_limit,
+        ).await
+    }
+
+    /// Отправить транзакцию, подписанную кошельком, от имени плательщика (с preflight) и
+    /// верифицировать платеж. Транзакция должна совпадать с выданной сервером — те же
+    /// инструкции и reference — и быть подписана всеми подписантами
+    pub async fn submit_transaction(&self, payment: &Payment, transaction: &str) -> anyhow::Result<VerificationResult> {
+        if payment.sandbox {
+            return Err(SandboxError::OnChain.into());
+        }
+        if !payment.status.is_open() {
+            return Err(RefreshError::NotPending.into());
+        }
+        let issued = self.storage.get_issued_transaction(&payment.id).await?
+            .ok_or(RefreshError::NotIssued)?;
+
+        let invalid = |code: &'static str, message: String| {
+            let mut errors = ValidationErrors::new();
+            errors.add("transaction", code, message);
+            anyhow::Error::from(errors)
+        };
+        let transaction = parse_wallet_transaction(transaction)?;
+
+        let expected = expected_message(
+            payment, &issued, &self.config, self.rpc().as_ref(), *transaction.message.recent_blockhash(),
+        ).await?;
+        if transaction.message != expected {
+            return Err(invalid("mismatch", "Transaction does not match the one issued for this payment".to_string()));
+        }
+        if transaction.verify_with_results().contains(&false) {
+            return Err(invalid("unsigned", "Transaction is not signed by all required signers".to_string()));
+        }
+
+        let mut attempt = 0;
+        let signature = loop {
+            match self.rpc().send_transaction(&transaction).await {
+                Ok(signature) => break signature,
+                // Узел может отставать и еще не знать blockhash, выданный другим узлом
+                Err(e) if attempt < SUBMIT_BLOCKHASH_RETRIES && is_blockhash_not_found(&e) => {
+                    attempt += 1;
+                    tracing::warn!(
+                        payment_id = %payment.id, attempt, retries = SUBMIT_BLOCKHASH_RETRIES,
+                        "Transaction blockhash not found, retrying"
+                    );
+                    tokio::time::sleep(std::time::Duration::from_millis(SUBMIT_RETRY_DELAY_MS)).await;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        tracing::info!(payment_id = %payment.id, %signature, "Transaction submitted");
+
+        let reason = format!("Transaction {} submitted", signature);
+        self.update_payment(&payment.id, |current| {
+            if current.status != PaymentStatus::Pending {
+                return Ok(false);
+            }
+            current.transition(PaymentStatus::Processing, Some(&reason))?;
+            Ok(true)
+        }).await?;
+        self.verify_payment(&payment.id, &signature.to_string()).await
+    }
+
+    /// Закрепить токен transaction request URL за кошельком `account`. Повторный запрос
+    /// того же кошелька проходит (кошельки повторяют POST), другого — [`RequestTokenError::Claimed`]
+    pub async fn claim_request_token(&self, payment: Payment, token: Option<&str>, account: &str) -> anyhow::Result<Payment> {
+        payment.check_request_token(token)?;
+        if payment.request_token.as_ref().is_none_or(|t| t.claimed_by.as_deref() == Some(account)) {
+            return Ok(payment);
+        }
+        let updated = self.update_payment(&payment.id, |payment| {
+            let Some(request_token) = &mut payment.request_token else {
+                return Ok(false);
+            };
+            match &request_token.claimed_by {
+                Some(claimed_by) if claimed_by == account => Ok(false),
+                Some(_) => Err(RequestTokenError::Claimed.into()),
+                None => {
+                    request_token.claimed_by = Some(account.to_string());
+                    Ok(true)
+                }
+            }
+        }).await?;
+        tracing::info!(payment_id = %payment.id, %account, "Transaction request token claimed");
+        Ok(updated.unwrap_or(payment))
+    }
+
+    /// Разобрать транзакцию кошелька для отладки: инструкции, переводы и подписи. С
+    /// `payment` — еще и сверка с выданной для платежа транзакцией, как при отправке
+    pub async fn decode_transaction(
+        &self,
+        transaction: &str,
+        payment: Option<&Payment>,
+    ) -> anyhow::Result<DecodedTransaction> {
+        let transaction = parse_wallet_transaction(transaction)?;
+        let mut decoded = decode_transaction(&transaction, &self.config, self.rpc().as_ref()).await?;
+
+        if let Some(payment) = payment {
+            let issued = self.storage.get_issued_transaction(&payment.id).await?
+                .ok_or(RefreshError::NotIssued)?;
+            let expected = expected_message(
+                payment, &issued, &self.config, self.rpc().as_ref(), *transaction.message.recent_blockhash(),
+            ).await?;
+            let differences = message_differences(&transaction.message, &expected);
+            decoded.issued = Some(IssuedComparison {
+                payment_id: payment.id.clone(),
+                matches: differences.is_empty(),
+                differences,
+            });
+        }
+        Ok(decoded)
+    }
+
+    /// Перевести платеж в новый статус и сохранить его вместе с событием `payment.<статус>`.
+    /// Копию, устаревшую с момента чтения, не сохраняет ([`VersionConflict`]); для
+    /// чтения-изменения-записи с повторами — `update_payment`
+    pub async fn transition(&self, payment: &mut Payment, to: PaymentStatus, reason: Option<&str>) -> anyhow::Result<()> {
+        let from = payment.status;
+        payment.transition(to, reason)?;
+        self.storage.save_payment_with_event(payment, &to.event_type()).await?;
+        tracing::info!(payment_id = %payment.id, from = %from, to = %to, "Payment status changed");
+        self.after_status_change(payment).await;
+        Ok(())
+    }
+
+    /// Изменить платеж: прочитать свежую копию, применить `apply` и сохранить. Если между
+    /// чтением и записью платеж сохранил другой запрос или задача ([`VersionConflict`]),
+    /// все повторяется с новой копией, не более `MAX_UPDATE_ATTEMPTS` раз.
+    /// `apply` возвращает `false`, если менять нечего. Смена статуса сохраняется вместе
+    /// с событием `payment.<статус>`. `None` — платеж не найден
+    pub async fn update_payment<F>(&self, payment_id: &str, mut apply: F) -> anyhow::Result<Option<Payment>>
+    where
+        F: FnMut(&mut Payment) -> anyhow::Result<bool>,
+    {
+        let mut attempt = 1;
+        loop {
+            let Some(mut payment) = self.storage.get_payment(payment_id).await? else {
+                return Ok(None);
+            };
+            let from = payment.status;
+            if !apply(&mut payment)? {
+                return Ok(Some(payment));
+            }
+            let to = payment.status;
+            let saved = if to != from {
+                self.storage.save_payment_with_event(&mut payment, &to.event_type()).await
+            } else {
+                self.storage.save_payment(payment_id, &mut payment).await
+            };
+            match saved {
+                Ok(()) => {
+                    if to != from {
+                        tracing::info!(payment_id, from = %from, to = %to, "Payment status changed");
+                        self.after_status_change(&payment).await;
+                    }
+                    return Ok(Some(payment));
+                }
+                Err(e) if attempt < MAX_UPDATE_ATTEMPTS && e.is::<VersionConflict>() => {
+                    tracing::warn!(payment_id, attempt, error = %e, "Payment changed concurrently, retrying update");
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Побочные эффекты сохраненной смены статуса. Их сбой не отменяет смену
+    async fn after_status_change(&self, payment: &Payment) {
+        if let Err(e) = self.loyalty.on_status_change(payment).await {
+            tracing::error!(payment_id = %payment.id, status = %payment.status, error = %e, "Failed to update loyalty points");
+        }
+    }
+
+    /// Почему транзакция не принимается по `allowed_payers` платежа: комиссию сети и токен
+    /// платежа списывают только разрешенные кошельки (и спонсор gasless транзакции)
+    async fn payer_violation(&self, payment: &Payment, verification: &TransactionVerification) -> anyhow::Result<Option<String>> {
+        if payment.allowed_payers.is_empty() {
+            return Ok(None);
+        }
+        let sponsor = self.storage.get_issued_transaction(&payment.id).await?.and_then(|issued| issued.fee_payer);
+        let allowed = |account: &str| payment.allows_payer(account) || sponsor.as_deref() == Some(account);
+        if let Some(fee_payer) = verification.fee_payer.as_deref().filter(|account| !allowed(account)) {
+            return Ok(Some(format!("Fee payer {} is not an allowed payer", fee_payer)));
+        }
+        // В свопе токен платежа списывается с пулов — проверяется только fee payer
+        if payment.swap.is_none() {
+            if let Some(source) = verification.sources.iter().find(|account| !allowed(account)) {
+                return Ok(Some(format!("Source account {} is not an allowed payer", source)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Сменить статус платежа по запросу мерчанта (только свои платежи) или администратора
+    /// (`merchant_id = None`). `None` — платеж не найден
+    pub async fn change_status(
+        &self,
+        payment_id: &str,
+        merchant_id: Option<&str>,
+        to: PaymentStatus,
+        reason: Option<&str>,
+    ) -> anyhow::Result<Option<Payment>> {
+        let Some(payment) = self.storage.get_payment(payment_id).await? else {
+            return Ok(None);
+        };
+        if merchant_id.is_some_and(|id| payment.merchant_id.as_deref() != Some(id)) {
+            return Ok(None);
+        }
+        self.update_payment(payment_id, |payment| {
+            payment.transition(to, reason)?;
+            Ok(true)
+        }).await
+    }
+
+    /// Завершить sandbox платеж без транзакции (интеграционное тестирование мерчанта):
+    /// обычный переход в `completed` с событием и вебхуком. `None` — платеж не найден
+    /// или принадлежит другому мерчанту
+    pub async fn simulate_completion(&self, payment_id: &str, merchant_id: &str) -> anyhow::Result<Option<Payment>> {
+        let Some(payment) = self.get_payment(payment_id).await? else {
+            return Ok(None);
+        };
+        if payment.merchant_id.as_deref() != Some(merchant_id) {
+            return Ok(None);
+        }
+        if !payment.sandbox {
+            return Err(SandboxError::NotSandbox.into());
+        }
+        let signature = format!("sandbox_{}", uuid::Uuid::new_v4().simple());
+        self.update_payment(payment_id, |payment| {
+            payment.transition(PaymentStatus::Completed, Some("Simulated in sandbox"))?;
+            payment.signature = Some(signature.clone());
+            Ok(true)
+        }).await
+    }
+
+    /// Пометить платеж истекшим, если срок вышел, а оплаты не было
+    pub async fn expire_if_due(&self, payment_id: &str) -> anyhow::Result<Option<Payment>> {
+        self.update_payment(payment_id, |payment| {
+            if !payment.status.is_open() || Utc::now() < payment.expires_at {
+                return Ok(false);
+            }
+            payment.transition(PaymentStatus::Expired, Some("Payment window elapsed"))?;
+            Ok(true)
+        }).await
+    }
+
+    /// Пометить истекшими все платежи, срок которых вышел (задача `expiration`)
+    pub async fn expire_due_payments(&self) -> anyhow::Result<usize> {
+        let now = Utc::now();
+        let due: Vec<String> = self.storage.get_all_payments().await?
+            .into_values()
+            .filter(|payment| payment.status.is_open() && payment.expires_at <= now)
+            .map(|payment| payment.id)
+            .collect();
+        let mut expired = 0;
+        for payment_id in due {
+            match self.expire_if_due(&payment_id).await {
+                Ok(Some(payment)) if payment.status == PaymentStatus::Expired => expired += 1,
+                Ok(_) => {}
+                // Платеж мог смениться параллельно (оплачен в последний момент)
+                Err(e) => tracing::warn!(payment_id = %payment_id, error = %e, "Failed to expire payment"),
+            }
+        }
+        if expired > 0 {
+            tracing::info!(expired, "Expired unpaid payments");
+        }
+        Ok(expired)
     }
 
     /// Верифицировать платеж по подписи транзакции
@@ -168,109 +1547,644 @@ impl PaymentService {
         &self,
         payment_id: &str,
         signature: &str,
+    ) -> anyhow::Result<VerificationResult> {
+        self.verify_payment_with_commitment(payment_id, signature, None).await
+    }
+
+    /// Верифицировать платеж, найдя транзакцию с `commitment` вместо
+    /// `solana.verification_commitment` (например, `finalized` по запросу мерчанта)
+    pub async fn verify_payment_with_commitment(
+        &self,
+        payment_id: &str,
+        signature: &str,
+        commitment: Option<CommitmentConfig>,
     ) -> anyhow::Result<VerificationResult> {
         // Получаем платеж
         let mut payment = self.storage.get_payment(payment_id).await?
             .ok_or_else(|| anyhow::anyhow!("Payment not found"))?;
 
-        // Проверяем не истек ли платеж
-        if Utc::now() > payment.expires_at {
-            payment.status = PaymentStatus::Expired;
-            self.storage.save_payment(payment_id, &payment).await?;
-
-            return Ok(VerificationResult {
-                success: false,
-                status: PaymentStatus::Expired,
-                verified: false,
-                signature: None,
-                details: "Payment has expired".to_string(),
-            });
-        }
-
         // Если уже верифицирован
-        if matches!(payment.status, PaymentStatus::Completed) {
+        if matches!(payment.status, PaymentStatus::Completed | PaymentStatus::Held) {
             return Ok(VerificationResult {
                 success: true,
-                status: PaymentStatus::Completed,
+                status: payment.status,
                 verified: true,
                 signature: payment.signature.clone(),
+                explorer_url: payment.explorer_url.clone(),
+                memo: payment.memo.clone(),
                 details: "Already verified".to_string(),
             });
         }
+        if payment.sandbox {
+            return Err(SandboxError::OnChain.into());
+        }
+
+        // Проверяем не истек ли платеж
+        if payment.status.is_open() && Utc::now() > payment.expires_at {
+            payment = self.expire_if_due(payment_id).await?
+                .ok_or_else(|| anyhow::anyhow!("Payment not found"))?;
+        }
+        if !payment.status.is_open() {
+            return Ok(VerificationResult {
+                success: false,
+                status: payment.status,
+                verified: false,
+                signature: None,
+                explorer_url: None,
+                memo: payment.memo.clone(),
+                details: format!("Payment is {}", payment.status),
+            });
+        }
 
         // Верифицируем в блокчейне
-        let recipient = Pubkey::from_str(&payment.recipient)?;
-        let verification = self.multichain.verify_transaction(
+        let recipient = Pubkey::from_str(payment.destination())?;
+        let fee_recipient = Pubkey::from_str(&payment.fee_recipient)?;
+        // Получатель (или счет удержания) должен получить всю сумму с чаевыми
+        let decimals = self.config.token_decimals(&payment.token);
+        let expected = payment.amount_base_units + payment.tip_amount.map_or(0, |tip| to_base_units(tip, decimals));
+        let held_amount = payment.hold.as_ref().map(|_| expected);
+        // Intent: любая сумма в пределах, в том числе не та, что выбрана в transaction request
+        let range = payment.amount_range.map(|range| range.base_units(decimals));
+        let mut verification = self.multichain.verify_transaction(
             signature,
             &recipient,
-            payment.amount,
+            commitment,
             &payment.token,
+            (&fee_recipient, payment.fee_amount, &payment.fee_token),
+            payment.swap.as_ref().map(|swap| swap.recipient_amount)
+                .or(held_amount)
+                .or(range.map(|(min, _)| min))
+                .unwrap_or(expected),
         ).await?;
-
+        if let (true, Some((_, max))) = (verification.is_valid, range) {
+            let received = verification.received.unwrap_or_default();
+            if received > max {
+                verification.is_valid = false;
+                verification.details = format!(
+                    "Recipient received {} {} base units, more than the maximum of {}",
+                    received, payment.token, max,
+                );
+            }
+        }
         if verification.is_valid {
-            // Обновляем статус платежа
-            payment.status = PaymentStatus::Completed;
-            payment.signature = Some(signature.to_string());
-            payment.verified_at = Some(Utc::now());
+            if let Some(details) = self.payer_violation(&payment, &verification).await? {
+                verification.is_valid = false;
+                verification.details = details;
+            }
+        }
 
-            self.storage.save_payment(payment_id, &payment).await?;
+        if verification.is_valid {
+            // Обновляем статус платежа. Свежая копия: пока шла проверка, платеж мог
+            // сохранить другой запрос (например, параллельная верификация той же подписи)
+            let usd_valuation = self.usd_valuation(&payment).await;
+            let block_time = verification.block_time.and_then(|ts| DateTime::from_timestamp(ts, 0));
+            let explorer_url = self.config.explorer().transaction_url(signature);
+            let reason = format!("Transaction {} verified", signature);
+            let scale = 10_f64.powi(decimals as i32);
+            let paid_amount = verification.received.map(|received| received as f64 / scale);
+            // Кошелек плательщика: возврат удержания и NFT-квитанция
+            let payer = match &verification.payer {
+                Some(payer) => Some(payer.clone()),
+                None => self.storage.get_issued_transaction(payment_id).await?.map(|issued| issued.account),
+            };
+            let payment = self.update_payment(payment_id, |payment| {
+                if matches!(payment.status, PaymentStatus::Completed | PaymentStatus::Held) {
+                    return Ok(false);
+                }
+                let to = match payment.hold {
+                    Some(_) => PaymentStatus::Held,
+                    None => PaymentStatus::Completed,
+                };
+                payment.transition(to, Some(&reason))?;
+                payment.paid_amount = paid_amount;
+                // Сумма intent платежа — та, что пришла на самом деле
+                if let (Some(_), Some(received)) = (payment.amount_range, verification.received) {
+                    payment.amount_base_units = received;
+                    payment.amount = received as f64 / scale;
+                    self.update_display_amounts(payment);
+                }
+                payment.signature = Some(signature.to_string());
+                payment.block_time = block_time;
+                payment.payer = payer.clone();
+                payment.explorer_url = Some(explorer_url.clone());
+                payment.usd_valuation = usd_valuation.clone();
+                Ok(true)
+            }).await?.ok_or_else(|| anyhow::anyhow!("Payment not found"))?;
 
-            log::info!("Payment {} verified successfully with signature {}",
-                payment_id, signature);
+            tracing::info!(
+                payment_id,
+                signature,
+                amount = payment.amount,
+                token = %payment.token,
+                fee_amount = payment.fee_amount,
+                fee_transfer_valid = verification.fee_transfer_valid,
+                "Payment verified"
+            );
 
             Ok(VerificationResult {
                 success: true,
-                status: PaymentStatus::Completed,
+                status: payment.status,
                 verified: true,
                 signature: Some(signature.to_string()),
+                explorer_url: payment.explorer_url.clone(),
+                memo: payment.memo.clone(),
                 details: verification.details,
             })
         } else {
-            log::warn!("Payment {} verification failed: {}",
-                payment_id, verification.details);
+            tracing::warn!(payment_id, signature, details = %verification.details, "Payment verification failed");
 
             Ok(VerificationResult {
                 success: false,
-                status: PaymentStatus::Pending,
+                status: payment.status,
                 verified: false,
                 signature: None,
+                explorer_url: None,
+                memo: payment.memo.clone(),
                 details: verification.details,
             })
         }
     }
 
-    /// Валидация запроса на создание платежа
-    fn validate_payment_request(&self, request: &CreatePaymentRequest) -> anyhow::Result<()> {
+    /// Курсы токенов платежа на текущий момент. Недоступный оракул не мешает завершению
+    /// платежа: оценка остается пустой
+    async fn usd_valuation(&self, payment: &Payment) -> Option<UsdValuation> {
+        let (token_rate, fee_rate) = tokio::join!(
+            self.oracle.usd_price(&payment.token),
+            self.oracle.usd_price(&payment.fee_token),
+        );
+        match (token_rate, fee_rate) {
+            (Ok(token_usd_rate), Ok(fee_token_usd_rate)) => Some(UsdValuation {
+                token_usd_rate,
+                fee_token_usd_rate,
+                amount_usd: payment.amount * token_usd_rate,
+                tip_usd: payment.tip_amount.map(|tip| tip * token_usd_rate),
+                fee_usd: payment.fee_amount * fee_token_usd_rate,
+                priced_at: Utc::now(),
+            }),
+            (Err(e), _) | (_, Err(e)) => {
+                tracing::warn!(payment_id = %payment.id, error = %e, "USD valuation is unavailable");
+                None
+            }
+        }
+    }
+
+    /// Валидация запроса на создание платежа (все ошибки по полям сразу)
+    fn validate_payment_request(&self, request: &CreatePaymentRequest) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+
         // Проверяем адрес получателя
-        if !self.multichain.validate_address(&request.recipient) {
-            anyhow::bail!("Invalid recipient address: {}", request.recipient);
+        errors.check_pubkey("recipient", &request.recipient);
+
+        // Удержание: только при включенном escrow, срок — в его пределах
+        if request.hold && self.escrow_account.is_none() {
+            errors.add("hold", "not_enabled", "Hold payments are not enabled on this server");
+        }
+        match request.hold_secs {
+            Some(_) if !request.hold => errors.add("hold_secs", "conflict", "hold_secs requires hold"),
+            Some(secs) if secs == 0 || secs > self.config.escrow.max_hold_secs => errors.add(
+                "hold_secs",
+                "out_of_range",
+                format!("hold_secs must be between 1 and {}", self.config.escrow.max_hold_secs),
+            ),
+            _ => {}
         }
 
-        // Проверяем сумму
-        if request.amount <= 0.0 {
-            anyhow::bail!("Amount must be positive, got: {}", request.amount);
+        // Проверяем сумму и разумные лимиты: в токенах или в базовых единицах, не обе сразу
+        let decimals = self.config.token_decimals(&request.token);
+        if let Some(range) = &request.amount_range {
+            validate_amount_range(request, range, decimals, &mut errors);
+        } else {
+            match request.amount_base_units {
+                Some(_) if request.amount != 0.0 => errors.add(
+                    "amount_base_units",
+                    "conflict",
+                    "Specify either amount or amount_base_units, not both",
+                ),
+                Some(0) => errors.add("amount_base_units", "too_small", "Amount must be positive, got: 0"),
+                Some(base_units) if base_units as f64 / 10_f64.powi(decimals as i32) > MAX_PAYMENT_AMOUNT => {
+                    errors.add("amount_base_units", "too_large", format!("Amount too large: {} base units", base_units));
+                }
+                Some(_) => {}
+                None if !request.amount.is_finite() || request.amount <= 0.0 => {
+                    errors.add("amount", "too_small", format!("Amount must be positive, got: {}", request.amount));
+                }
+                None if request.amount > MAX_PAYMENT_AMOUNT => {
+                    errors.add("amount", "too_large", format!("Amount too large: {}", request.amount));
+                }
+                None if to_base_units(request.amount, decimals) == 0 => errors.add(
+                    "amount",
+                    "too_small",
+                    format!("Amount {} is below the smallest unit of {}", request.amount, request.token),
+                ),
+                None => {}
+            }
         }
 
         // Проверяем поддерживается ли токен
         if !self.config.is_token_supported(&request.token) {
             let supported = self.config.get_supported_tokens();
-            anyhow::bail!(
-                "Token {} not supported. Supported tokens: {}",
-                request.token,
-                supported.join(", ")
+            errors.add(
+                "token",
+                "unsupported",
+                format!("Token {} not supported. Supported tokens: {}", request.token, supported.join(", ")),
             );
         }
 
-        // Проверяем разумные лимиты
-        if request.amount > 1_000_000.0 {
-            anyhow::bail!("Amount too large: {}", request.amount);
+        // Memo должен помещаться в транзакцию
+        if let Some(memo) = &request.memo {
+            if memo.is_empty() {
+                errors.add("memo", "too_short", "Memo must not be empty");
+            } else if memo.len() > MAX_MEMO_LENGTH {
+                errors.add(
+                    "memo",
+                    "too_long",
+                    format!("Memo must be at most {} bytes, got: {}", MAX_MEMO_LENGTH, memo.len()),
+                );
+            }
         }
 
-        Ok(())
+        // Язык строк для кошелька
+        if let Some(locale) = &request.locale {
+            if let Err(e) = locale.parse::<Locale>() {
+                errors.add("locale", "unsupported", e);
+            }
+        }
+
+        // Данные мерчанта
+        if let Some(metadata) = &request.metadata {
+            if metadata.len() > MAX_METADATA_KEYS {
+                errors.add(
+                    "metadata",
+                    "too_many_keys",
+                    format!("Metadata must have at most {} keys, got: {}", MAX_METADATA_KEYS, metadata.len()),
+                );
+            }
+            for (key, value) in metadata {
+                let valid_key = key.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+                if key.is_empty() || key.len() > MAX_METADATA_KEY_LENGTH || !valid_key {
+                    errors.add(
+                        "metadata",
+                        "invalid_key",
+                        format!(
+                            "Metadata key must be 1 to {} characters of A-Z, a-z, 0-9, '_', '-', '.', got: {:?}",
+                            MAX_METADATA_KEY_LENGTH, key
+                        ),
+                    );
+                } else if value.chars().count() > MAX_METADATA_VALUE_LENGTH {
+                    errors.add(
+                        "metadata",
+                        "too_long",
+                        format!("Metadata value of {} must be at most {} characters", key, MAX_METADATA_VALUE_LENGTH),
+                    );
+                }
+            }
+        }
+
+        // Допустимые плательщики
+        if let Some(payers) = &request.allowed_payers {
+            if payers.is_empty() || payers.len() > MAX_ALLOWED_PAYERS {
+                errors.add(
+                    "allowed_payers",
+                    "out_of_range",
+                    format!("allowed_payers must have 1 to {} addresses, got: {}", MAX_ALLOWED_PAYERS, payers.len()),
+                );
+            }
+            for (i, payer) in payers.iter().enumerate() {
+                errors.check_pubkey(&format!("allowed_payers[{}]", i), payer);
+            }
+        }
+
+        // Адреса возврата покупателя
+        for (field, url) in [("success_url", &request.success_url), ("cancel_url", &request.cancel_url)] {
+            let Some(url) = url else { continue };
+            if url.len() > MAX_REDIRECT_URL_LENGTH {
+                errors.add(field, "too_long", format!("URL must be at most {} bytes", MAX_REDIRECT_URL_LENGTH));
+                continue;
+            }
+            match url::Url::parse(url) {
+                Ok(url) => {
+                    let is_local = matches!(url.host_str(), Some("localhost") | Some("127.0.0.1"));
+                    if url.scheme() != "https" && !(url.scheme() == "http" && is_local) {
+                        errors.add(field, "insecure_url", "Redirect URL must use https");
+                    }
+                }
+                Err(e) => errors.add(field, "invalid_url", format!("Not a valid URL: {}", e)),
+            }
+        }
+
+        // Варианты чаевых
+        if let Some(presets) = &request.tip_presets_bps {
+            if presets.is_empty() || presets.len() > MAX_TIP_PRESETS {
+                errors.add(
+                    "tip_presets_bps",
+                    "out_of_range",
+                    format!("Provide 1 to {} tip presets", MAX_TIP_PRESETS),
+                );
+            } else if presets.iter().any(|&bps| bps == 0 || bps > MAX_TIP_BPS) {
+                errors.add(
+                    "tip_presets_bps",
+                    "out_of_range",
+                    format!("Tip presets must be between 1 and {} bps", MAX_TIP_BPS),
+                );
+            }
+        }
+
+        errors.into_result()
     }
 
-    /// Очистка просроченных платежей
-    pub async fn cleanup_expired_payments(&self) -> anyhow::Result<usize> {
-        self.storage.cleanup_expired_payments().await
+    /// Проверить плательщика перед выдачей транзакции. Адрес из списка отклоняется
+    /// (`ScreeningError::Blocked`) или помечается на платеже
+    pub async fn screen_payer(&self, payment: Payment, payer: &str) -> anyhow::Result<Payment> {
+        let Some(flag) = self.screening.check(&payment.id, ScreeningRole::Payer, payer).await? else {
+            return Ok(payment);
+        };
+        let updated = self.update_payment(&payment.id, |payment| {
+            let known = payment.screening_flags.iter()
+                .any(|existing| existing.role == flag.role && existing.address == flag.address);
+            if !known {
+                payment.screening_flags.push(flag.clone());
+            }
+            Ok(!known)
+        }).await?;
+        Ok(updated.unwrap_or(payment))
+    }
+
+    /// Проверка адресов сторон платежа и аудит совпадений
+    pub fn screening(&self) -> &ScreeningService {
+        &self.screening
     }
-}
\ No newline at end of file
+
+    /// Риск-лимиты мерчантов
+    pub fn limits(&self) -> &LimitService {
+        &self.limits
+    }
+
+    /// Коды скидок
+    pub fn discounts(&self) -> &DiscountService {
+        &self.discounts
+    }
+
+    /// Мерчанты (tenants) и их настройки
+    pub fn tenants(&self) -> &TenantService {
+        &self.tenants
+    }
+
+    /// Бонусные баллы
+    pub fn loyalty(&self) -> &LoyaltyService {
+        &self.loyalty
+    }
+
+    /// Зафиксировать сумму intent платежа, выбранную плательщиком при запросе транзакции.
+    /// У платежа с фиксированной суммой `amount` не передается
+    pub async fn apply_amount(&self, payment: Payment, amount: Option<f64>) -> anyhow::Result<Payment> {
+        let mut errors = ValidationErrors::new();
+        let (range, amount) = match (payment.amount_range, amount) {
+            (None, None) => return Ok(payment),
+            (None, Some(_)) => {
+                errors.add("amount", "not_allowed", "Amount is fixed for this payment");
+                return Err(errors.into());
+            }
+            (Some(range), None) => {
+                errors.add(
+                    "amount",
+                    "required",
+                    format!("Choose an amount between {} and {} {}", range.min, range.max, payment.token),
+                );
+                return Err(errors.into());
+            }
+            (Some(range), Some(amount)) => (range, amount),
+        };
+        if payment.status != PaymentStatus::Pending {
+            return Ok(payment);
+        }
+
+        // Пределы сравниваются в базовых единицах — так же, как при верификации
+        let decimals = self.config.token_decimals(&payment.token);
+        let (min, max) = range.base_units(decimals);
+        let amount_base_units = match amount.is_finite() && amount > 0.0 {
+            true => to_base_units(amount, decimals),
+            false => 0,
+        };
+        if amount_base_units < min || amount_base_units > max {
+            errors.add(
+                "amount",
+                "out_of_range",
+                format!("Amount must be between {} and {} {}, got: {}", range.min, range.max, payment.token, amount),
+            );
+            return Err(errors.into());
+        }
+        if amount_base_units == payment.amount_base_units {
+            return Ok(payment);
+        }
+
+        let updated = self.update_payment(&payment.id, |payment| {
+            if payment.status != PaymentStatus::Pending {
+                anyhow::bail!("Payment is not pending");
+            }
+            payment.amount_base_units = amount_base_units;
+            payment.amount = amount_base_units as f64 / 10_f64.powi(decimals as i32);
+            self.update_display_amounts(payment);
+            Ok(true)
+        }).await?;
+
+        Ok(updated.unwrap_or(payment))
+    }
+
+    /// Зафиксировать чаевые, выбранные плательщиком при запросе транзакции.
+    /// `None` или 0 — без чаевых
+    pub async fn apply_tip(&self, payment: Payment, tip_bps: Option<u16>) -> anyhow::Result<Payment> {
+        let tip_bps = tip_bps.filter(|&bps| bps > 0);
+        if tip_bps == payment.tip_bps || payment.status != PaymentStatus::Pending {
+            return Ok(payment);
+        }
+
+        let tip_amount = match tip_bps {
+            Some(bps) => {
+                if !payment.tip_presets_bps.contains(&bps) {
+                    let mut errors = ValidationErrors::new();
+                    if payment.tip_presets_bps.is_empty() {
+                        errors.add("tip_bps", "not_allowed", "Tipping is not enabled for this payment");
+                    } else {
+                        errors.add(
+                            "tip_bps",
+                            "not_allowed",
+                            format!("Tip must be one of {:?} bps", payment.tip_presets_bps),
+                        );
+                    }
+                    return Err(errors.into());
+                }
+
+                // Округляем вниз до точности токена, чтобы не взять с плательщика лишнего
+                let decimals = self.config.token_decimals(&payment.token);
+                let tip_base_units = (payment.amount_base_units as u128 * bps as u128 / 10_000) as u64;
+                Some(tip_base_units as f64 / 10_f64.powi(decimals as i32))
+            }
+            None => None,
+        };
+
+        let updated = self.update_payment(&payment.id, |payment| {
+            if payment.status != PaymentStatus::Pending {
+                anyhow::bail!("Payment is not pending");
+            }
+            payment.tip_bps = tip_bps;
+            payment.tip_amount = tip_amount;
+            self.update_display_amounts(payment);
+            Ok(true)
+        }).await?;
+
+        Ok(updated.unwrap_or(payment))
+    }
+
+    /// Подготовить своп для оплаты токеном `pay_with` (символ или mint): котировка Jupiter
+    /// на сумму платежа с чаевыми (и комиссию, если она в том же токене). Своп фиксируется
+    /// на платеже — при верификации проверяется сумма, полученная мерчантом
+    pub async fn prepare_swap(
+        &self,
+        payment: Payment,
+        payer: &Pubkey,
+        pay_with: &str,
+    ) -> anyhow::Result<(Payment, SwapInstructions)> {
+        let mut errors = ValidationErrors::new();
+        if !self.swaps.enabled() {
+            errors.add("pay_with", "not_allowed", "Paying with another token is not enabled");
+            return Err(errors.into());
+        }
+        if payment.status != PaymentStatus::Pending {
+            anyhow::bail!("Payment is not pending");
+        }
+        let input_mint = match self.swaps.resolve_mint(pay_with) {
+            Ok(mint) => mint,
+            Err(e) => {
+                errors.add("pay_with", "invalid", e.to_string());
+                return Err(errors.into());
+            }
+        };
+        let output_mint = self.swaps.resolve_mint(&payment.token)?;
+        if input_mint == output_mint {
+            errors.add("pay_with", "same_token", format!("Payment is already in {}", payment.token));
+            return Err(errors.into());
+        }
+
+        // Суммы в базовых единицах — так же, как в переводах транзакции
+        let decimals = self.config.token_decimals(&payment.token);
+        let base_units = |amount: f64| to_base_units(amount, decimals);
+        let recipient_amount = payment.amount_base_units + payment.tip_amount.map_or(0, base_units);
+        let fee_amount = if payment.fee_token == payment.token { base_units(payment.fee_amount) } else { 0 };
+
+        let quote = self.swaps.quote_exact_out(&input_mint, &output_mint, recipient_amount + fee_amount).await?;
+        let instructions = self.swaps.swap_instructions(&quote, payer, None).await?;
+
+        let swap = PaymentSwap {
+            input_mint: input_mint.to_string(),
+            max_in_amount: quote.max_in_amount,
+            out_amount: quote.out_amount,
+            recipient_amount,
+            slippage_bps: self.config.swap.slippage_bps,
+            quoted_at: Utc::now(),
+        };
+        let updated = self.update_payment(&payment.id, |payment| {
+            if payment.status != PaymentStatus::Pending {
+                anyhow::bail!("Payment is not pending");
+            }
+            payment.swap = Some(swap.clone());
+            Ok(true)
+        }).await?;
+
+        Ok((updated.unwrap_or(payment), instructions))
+    }
+
+    /// Пересчитать строковые представления сумм платежа
+    fn update_display_amounts(&self, payment: &mut Payment) {
+        let decimals = self.config.token_decimals(&payment.token);
+        let fee_decimals = self.config.token_decimals(&payment.fee_token);
+        let tip = payment.tip_amount.unwrap_or(0.0);
+
+        payment.amount_display = format_token_amount(payment.amount, decimals, &payment.token);
+        payment.fee_display = format_token_amount(payment.fee_amount, fee_decimals, &payment.fee_token);
+        payment.tip_display = payment.tip_amount
+            .map(|tip| format_token_amount(tip, decimals, &payment.token));
+        payment.total_display = if payment.fee_token == payment.token {
+            format_token_amount(payment.amount + tip + payment.fee_amount, decimals, &payment.token)
+        } else {
+            format!(
+                "{} + {}",
+                format_token_amount(payment.amount + tip, decimals, &payment.token),
+                payment.fee_display
+            )
+        };
+    }
+
+    /// Выдать challenge для подтверждения владения кошельком (для vault Squads —
+    /// мультисиг и участник, который подписывает за vault)
+    pub async fn create_ownership_challenge(
+        &self,
+        wallet: &str,
+        multisig: Option<&str>,
+        member: Option<&str>,
+    ) -> anyhow::Result<OwnershipChallenge> {
+        self.ownership.create_challenge(wallet, multisig, member).await
+    }
+
+    /// Проверить подписанный challenge и зарегистрировать кошелек
+    pub async fn verify_ownership(&self, request: &OwnershipProofRequest) -> anyhow::Result<VerifiedRecipient> {
+        self.ownership.verify_proof(request).await
+    }
+
+    /// Отчет о собранных комиссиях
+    pub async fn revenue_report(&self) -> anyhow::Result<RevenueReport> {
+        self.storage.get_revenue_report().await
+    }
+}
+
+/// Пределы intent платежа: положительные, не больше `MAX_PAYMENT_AMOUNT`, без скидок, баллов, чаевых и удержания
+fn validate_amount_range(request: &CreatePaymentRequest, range: &AmountRange, decimals: u8, errors: &mut ValidationErrors) {
+    if request.amount != 0.0 || request.amount_base_units.is_some() {
+        errors.add("amount_range", "conflict", "Specify either amount or amount_range, not both");
+    }
+    if !range.min.is_finite() || !range.max.is_finite() || range.min <= 0.0 {
+        errors.add("amount_range.min", "too_small", format!("Minimum amount must be positive, got: {}", range.min));
+    } else if to_base_units(range.min, decimals) == 0 {
+        errors.add(
+            "amount_range.min",
+            "too_small",
+            format!("Amount {} is below the smallest unit of {}", range.min, request.token),
+        );
+    } else if range.max < range.min {
+        errors.add(
+            "amount_range.max",
+            "too_small",
+            format!("Maximum amount {} is below the minimum {}", range.max, range.min),
+        );
+    } else if range.max > MAX_PAYMENT_AMOUNT {
+        errors.add("amount_range.max", "too_large", format!("Amount too large: {}", range.max));
+    }
+    let fixed_only = [
+        ("discount_code", request.discount_code.is_some()),
+        ("redeem_points", request.redeem_points.is_some()),
+        ("tip_presets_bps", request.tip_presets_bps.is_some()),
+        ("hold", request.hold),
+    ];
+    for (field, set) in fixed_only {
+        if set {
+            errors.add(field, "not_allowed", format!("{} requires a fixed amount", field));
+        }
+    }
+}
+
+/// Транзакция кошелька: base64 сериализованной транзакции с корректными индексами аккаунтов
+fn parse_wallet_transaction(transaction: &str) -> Result<VersionedTransaction, ValidationErrors> {
+    general_purpose::STANDARD.decode(transaction.trim())
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| bincode::deserialize::<VersionedTransaction>(&bytes).map_err(|e| e.to_string()))
+        .and_then(|transaction| transaction.sanitize().map(|_| transaction).map_err(|e| e.to_string()))
+        .map_err(|e| {
+            let mut errors = ValidationErrors::new();
+            errors.add("transaction", "invalid", format!("Transaction must be a base64 serialized transaction: {}", e));
+            errors
+        })
+}
+
+fn is_blockhash_not_found(error: &anyhow::Error) -> bool {
+    let message = error.to_string();
+    message.contains("BlockhashNotFound") || message.contains("Blockhash not found")
+}
@@ -0,0 +1,292 @@
+use base64::{Engine as _, engine::general_purpose};
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::compute_budget::{ComputeBudget, MAX_COMPUTE_UNIT_LIMIT};
+use crate::config::{InstructionPluginConfig, PluginAccountConfig, PluginPosition};
+use crate::payment::{Payment, MAX_MEMO_LENGTH};
+use crate::transaction::{memo_instruction, OptionalInstructions, TransactionPart};
+
+/// Данные сборки транзакции, доступные плагину
+pub struct DecoratorContext<'a> {
+    pub payment: &'a Payment,
+    pub payer: &'a Pubkey,
+    /// Плательщик комиссии сети: спонсор (gasless) или сам плательщик
+    pub fee_payer: &'a Pubkey,
+    pub part: TransactionPart,
+    /// Бюджет compute units задан вне плагинов (маршрут свопа)
+    pub compute_budget_set: bool,
+}
+
+/// Плагин сборщика транзакций: добавляет свои инструкции в каждую транзакцию платежа.
+///
+/// Инструкции должны зависеть только от контекста: при приеме подписанной транзакции
+/// она пересобирается и сверяется с выданной целиком
+pub trait InstructionDecorator: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn position(&self) -> PluginPosition;
+
+    /// Обязательный плагин не выбрасывается, чтобы уложиться в лимит размера, и его
+    /// ошибка прерывает сборку; ошибка необязательного только пропускает его
+    fn required(&self) -> bool {
+        false
+    }
+
+    fn instructions(&self, context: &DecoratorContext<'_>) -> anyhow::Result<Vec<Instruction>>;
+}
+
+/// Плагины в порядке применения. Клоны делят один список
+#[derive(Clone, Default)]
+pub struct InstructionPlugins {
+    decorators: Arc<Vec<Arc<dyn InstructionDecorator>>>,
+}
+
+impl std::fmt::Debug for InstructionPlugins {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.decorators.iter().map(|decorator| decorator.name())).finish()
+    }
+}
+
+impl InstructionPlugins {
+    /// Встроенные плагины из `[[instruction_plugins]]`
+    pub fn from_config(configs: &[InstructionPluginConfig]) -> anyhow::Result<Self> {
+        let mut plugins = Self::default();
+        for (i, config) in configs.iter().enumerate() {
+            let decorator = build(config)
+                .map_err(|e| anyhow::anyhow!("instruction_plugins[{}] ({}): {}", i, config.name(), e))?;
+            plugins = plugins.with(decorator);
+        }
+        Ok(plugins)
+    }
+
+    /// Список с еще одним плагином в конце (плагины, написанные в коде, регистрируются
+    /// до создания сервисов: каждый сервис держит свой клон `Config`)
+    pub fn with(self, decorator: Arc<dyn InstructionDecorator>) -> Self {
+        let mut decorators = self.decorators.as_ref().clone();
+        decorators.push(decorator);
+        Self { decorators: Arc::new(decorators) }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.decorators.is_empty()
+    }
+
+    /// Инструкции платежа с инструкциями плагинов: `start`, `before`, инструкции платежа,
+    /// `after`. Возвращает и то, что можно выбросить при превышении размера, в порядке
+    /// выбрасывания: необязательные плагины с конца списка, затем memo платежа
+    pub fn decorate(
+        &self,
+        context: &DecoratorContext<'_>,
+        payment_instructions: Vec<Instruction>,
+        memo_index: Option<usize>,
+    ) -> anyhow::Result<(Vec<Instruction>, Vec<OptionalInstructions>)> {
+        let mut instructions = Vec::new();
+        let mut optional = Vec::new();
+        let mut memo = None;
+        for position in [PluginPosition::Start, PluginPosition::Before, PluginPosition::After] {
+            if position == PluginPosition::After {
+                let offset = instructions.len();
+                memo = memo_index.map(|index| OptionalInstructions {
+                    range: offset + index..offset + index + 1,
+                    note: "Memo omitted to fit the transaction size limit".to_string(),
+                });
+                instructions.extend(payment_instructions.iter().cloned());
+            }
+            for decorator in self.decorators.iter().filter(|decorator| decorator.position() == position) {
+                let added = match decorator.instructions(context) {
+                    Ok(added) => added,
+                    Err(e) if decorator.required() => {
+                        anyhow::bail!("Instruction plugin {} failed: {}", decorator.name(), e)
+                    }
+                    Err(e) => {
+                        tracing::warn!(
+                            payment_id = %context.payment.id, plugin = decorator.name(), error = %e,
+                            "Instruction plugin skipped"
+                        );
+                        continue;
+                    }
+                };
+                let start = instructions.len();
+                instructions.extend(added);
+                if !decorator.required() && instructions.len() > start {
+                    optional.push(OptionalInstructions {
+                        range: start..instructions.len(),
+                        note: format!("{} omitted to fit the transaction size limit", decorator.name()),
+                    });
+                }
+            }
+        }
+        optional.reverse();
+        optional.extend(memo);
+        Ok((instructions, optional))
+    }
+}
+
+/// Участник платежа или фиксированный адрес в инструкции плагина
+#[derive(Debug, Clone, Copy)]
+enum AccountSource {
+    Payer,
+    FeePayer,
+    Recipient,
+    FeeRecipient,
+    Reference,
+    Fixed(Pubkey),
+}
+
+impl AccountSource {
+    fn parse(account: &PluginAccountConfig) -> anyhow::Result<Self> {
+        let source = match account.pubkey.as_str() {
+            "payer" => Self::Payer,
+            "fee_payer" => Self::FeePayer,
+            "recipient" => Self::Recipient,
+            "fee_recipient" => Self::FeeRecipient,
+            "reference" => Self::Reference,
+            address => Self::Fixed(
+                Pubkey::from_str(address).map_err(|e| anyhow::anyhow!("invalid account {}: {}", address, e))?,
+            ),
+        };
+        if account.signer && !matches!(source, Self::Payer | Self::FeePayer) {
+            anyhow::bail!("account {} cannot sign: only payer and fee_payer sign payment transactions", account.pubkey);
+        }
+        Ok(source)
+    }
+
+    fn resolve(self, context: &DecoratorContext<'_>) -> anyhow::Result<Pubkey> {
+        let parse = |address: &str| {
+            Pubkey::from_str(address).map_err(|e| anyhow::anyhow!("Invalid address {}: {}", address, e))
+        };
+        match self {
+            Self::Payer => Ok(*context.payer),
+            Self::FeePayer => Ok(*context.fee_payer),
+            Self::Recipient => parse(context.payment.destination()),
+            Self::FeeRecipient => parse(&context.payment.fee_recipient),
+            Self::Reference => parse(&context.payment.reference),
+            Self::Fixed(pubkey) => Ok(pubkey),
+        }
+    }
+}
+
+struct MemoPlugin {
+    name: String,
+    text: String,
+    position: PluginPosition,
+    required: bool,
+}
+
+impl InstructionDecorator for MemoPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn position(&self) -> PluginPosition {
+        self.position
+    }
+
+    fn required(&self) -> bool {
+        self.required
+    }
+
+    fn instructions(&self, context: &DecoratorContext<'_>) -> anyhow::Result<Vec<Instruction>> {
+        let payment = context.payment;
+        let text = self.text
+            .replace("{payment_id}", &payment.id)
+            .replace("{reference}", &payment.reference)
+            .replace("{merchant_id}", payment.merchant_id.as_deref().unwrap_or(""));
+        Ok(vec![memo_instruction(&text, context.payer)])
+    }
+}
+
+struct ComputeBudgetPlugin {
+    name: String,
+    budget: ComputeBudget,
+}
+
+impl InstructionDecorator for ComputeBudgetPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn position(&self) -> PluginPosition {
+        PluginPosition::Start
+    }
+
+    fn required(&self) -> bool {
+        true
+    }
+
+    fn instructions(&self, context: &DecoratorContext<'_>) -> anyhow::Result<Vec<Instruction>> {
+        // Второй SetComputeUnitLimit сеть отклоняет
+        if context.compute_budget_set {
+            return Ok(Vec::new());
+        }
+        Ok(self.budget.instructions())
+    }
+}
+
+struct ProgramPlugin {
+    name: String,
+    program_id: Pubkey,
+    accounts: Vec<(AccountSource, bool, bool)>,
+    data: Vec<u8>,
+    position: PluginPosition,
+    required: bool,
+}
+
+impl InstructionDecorator for ProgramPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn position(&self) -> PluginPosition {
+        self.position
+    }
+
+    fn required(&self) -> bool {
+        self.required
+    }
+
+    fn instructions(&self, context: &DecoratorContext<'_>) -> anyhow::Result<Vec<Instruction>> {
+        let mut accounts = Vec::with_capacity(self.accounts.len());
+        for &(source, signer, writable) in &self.accounts {
+            let pubkey = source.resolve(context)?;
+            accounts.push(match writable {
+                true => AccountMeta::new(pubkey, signer),
+                false => AccountMeta::new_readonly(pubkey, signer),
+            });
+        }
+        Ok(vec![Instruction { program_id: self.program_id, accounts, data: self.data.clone() }])
+    }
+}
+
+fn build(config: &InstructionPluginConfig) -> anyhow::Result<Arc<dyn InstructionDecorator>> {
+    Ok(match config {
+        InstructionPluginConfig::Memo { name, text, position, required } => {
+            if text.is_empty() || text.len() > MAX_MEMO_LENGTH {
+                anyhow::bail!("text must be 1 to {} bytes", MAX_MEMO_LENGTH);
+            }
+            Arc::new(MemoPlugin { name: name.clone(), text: text.clone(), position: *position, required: *required })
+        }
+        InstructionPluginConfig::ComputeBudget { name, unit_limit, unit_price_micro_lamports } => {
+            if *unit_limit == 0 || *unit_limit > MAX_COMPUTE_UNIT_LIMIT {
+                anyhow::bail!("unit_limit must be between 1 and {}", MAX_COMPUTE_UNIT_LIMIT);
+            }
+            Arc::new(ComputeBudgetPlugin {
+                name: name.clone(),
+                budget: ComputeBudget { unit_limit: *unit_limit, unit_price_micro_lamports: *unit_price_micro_lamports },
+            })
+        }
+        InstructionPluginConfig::Program { name, program_id, accounts, data, position, required } => {
+            let program_id = Pubkey::from_str(program_id)
+                .map_err(|e| anyhow::anyhow!("invalid program_id {}: {}", program_id, e))?;
+            let accounts = accounts.iter()
+                .map(|account| Ok((AccountSource::parse(account)?, account.signer, account.writable)))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let data = general_purpose::STANDARD.decode(data)
+                .map_err(|e| anyhow::anyhow!("data is not valid base64: {}", e))?;
+            Arc::new(ProgramPlugin { name: name.clone(), program_id, accounts, data, position: *position, required: *required })
+        }
+    })
+}
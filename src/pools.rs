@@ -0,0 +1,159 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use uuid::Uuid;
+
+use crate::config::MerchantConfig;
+use crate::payment::{CreatePaymentRequest, Payment, PaymentService};
+use crate::storage::StorageService;
+use crate::validation::ValidationErrors;
+
+/// Сколько платежей можно заготовить одним запросом
+pub const MAX_POOL_SIZE: usize = 500;
+
+/// Пул заранее выпущенных платежей на одну сумму (распродажа, продажа билетов). При
+/// оформлении заказа платеж выдается из пула одной операцией storage: ID, ссылки и QR коды
+/// уже готовы, а сроки платежа отсчитываются с момента выдачи
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentPool {
+    pub id: String,
+    pub merchant_id: String,
+    pub recipient: String,
+    pub token: String,
+    pub amount: f64,
+    pub amount_base_units: u64,
+    /// Заготовлено платежей за все время
+    pub generated: usize,
+    /// Выдано при оформлении заказов
+    pub claimed: usize,
+    pub created_at: DateTime<Utc>,
+    /// Невыданные заготовки в порядке выдачи
+    pub payments: VecDeque<Payment>,
+}
+
+impl PaymentPool {
+    /// Состояние пула для ответа API (без самих заготовок)
+    pub fn summary(&self) -> PoolSummary {
+        PoolSummary {
+            id: self.id.clone(),
+            merchant_id: self.merchant_id.clone(),
+            recipient: self.recipient.clone(),
+            token: self.token.clone(),
+            amount: self.amount,
+            amount_base_units: self.amount_base_units,
+            available: self.payments.len(),
+            generated: self.generated,
+            claimed: self.claimed,
+            created_at: self.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolSummary {
+    pub id: String,
+    pub merchant_id: String,
+    pub recipient: String,
+    pub token: String,
+    pub amount: f64,
+    pub amount_base_units: u64,
+    /// Осталось заготовок
+    pub available: usize,
+    pub generated: usize,
+    pub claimed: usize,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PoolError {
+    #[error("Payment pool not found")]
+    NotFound,
+    #[error("Payment pool is exhausted, create a new pool or create payments directly")]
+    Exhausted,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CreatePoolRequest {
+    /// Сколько платежей заготовить
+    pub count: usize,
+    /// Параметры каждого платежа пула, как в `POST /api/payment/create`
+    pub payment: CreatePaymentRequest,
+}
+
+fn check_count(count: usize) -> Result<(), ValidationErrors> {
+    let mut errors = ValidationErrors::new();
+    if count == 0 || count > MAX_POOL_SIZE {
+        errors.add("count", "out_of_range", format!("count must be between 1 and {}", MAX_POOL_SIZE));
+    }
+    errors.into_result()
+}
+
+#[derive(Clone)]
+pub struct PoolService {
+    payments: PaymentService,
+    storage: StorageService,
+}
+
+impl PoolService {
+    pub fn new(payments: PaymentService) -> Self {
+        let storage = payments.storage().clone();
+        Self { payments, storage }
+    }
+
+    /// Создать пул и заготовить `count` платежей
+    pub async fn create(&self, request: CreatePoolRequest, merchant: &MerchantConfig) -> anyhow::Result<PoolSummary> {
+        check_count(request.count)?;
+        let payments = self.payments.prepare_pooled_payments(request.payment, merchant, request.count).await?;
+        let first = payments.first().expect("count is positive");
+
+        let mut pool = PaymentPool {
+            id: format!("pool_{}", Uuid::new_v4().simple()),
+            merchant_id: merchant.id.clone(),
+            recipient: first.recipient.clone(),
+            token: first.token.clone(),
+            amount: first.amount,
+            amount_base_units: first.amount_base_units,
+            generated: payments.len(),
+            claimed: 0,
+            created_at: Utc::now(),
+            payments: VecDeque::new(),
+        };
+        pool.payments.extend(payments);
+        self.storage.save_payment_pool(&pool).await?;
+
+        tracing::info!(
+            pool_id = %pool.id, merchant = %merchant.id, count = pool.generated, amount = pool.amount, token = %pool.token,
+            "Payment pool created"
+        );
+
+        Ok(pool.summary())
+    }
+
+    /// Пул мерчанта; чужой пул не отличается от несуществующего
+    pub async fn get(&self, id: &str, merchant: &MerchantConfig) -> anyhow::Result<PoolSummary> {
+        self.storage.get_payment_pool(id).await?
+            .filter(|pool| pool.merchant_id == merchant.id)
+            .ok_or_else(|| PoolError::NotFound.into())
+    }
+
+    /// Выдать платеж из пула при оформлении заказа
+    pub async fn claim(&self, id: &str, merchant: &MerchantConfig) -> anyhow::Result<Payment> {
+        let pool = self.get(id, merchant).await?;
+        // Риск-лимиты считаются по выданным платежам
+        self.payments.limits().check(merchant, &pool.token, pool.amount).await?;
+
+        let payment = self.storage.claim_pool_payment(id).await?
+            .ok_or(PoolError::Exhausted)?;
+        self.payments.issue_pooled_payment(payment).await
+    }
+
+    /// Удалить пул вместе с невыданными заготовками; возвращает их число
+    pub async fn delete(&self, id: &str, merchant: &MerchantConfig) -> anyhow::Result<usize> {
+        self.get(id, merchant).await?;
+        let pool = self.storage.delete_payment_pool(id).await?
+            .ok_or(PoolError::NotFound)?;
+        tracing::info!(pool_id = %id, discarded = pool.payments.len(), "Payment pool deleted");
+        Ok(pool.payments.len())
+    }
+}
@@ -0,0 +1,216 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+use crate::config::MerchantConfig;
+use crate::payment::{CreatePaymentRequest, Payment, PaymentService};
+use crate::storage::StorageService;
+use crate::validation::{Validate, ValidationErrors};
+
+/// Сессия закрывается после такого простоя
+const SESSION_IDLE_HOURS: i64 = 12;
+
+/// Сколько последних чеков хранится в сессии для экрана кассира
+pub const MAX_RECENT_CHARGES: usize = 50;
+
+/// Сессия POS терминала: настройки мерчанта проверяются один раз при открытии,
+/// дальше чеки создаются без повторных проверок получателя
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PosSession {
+    pub id: String,
+    pub merchant_id: String,
+    pub terminal: Option<String>,
+    pub recipient: String,
+    pub token: String,
+    pub label: String,
+    pub created_at: DateTime<Utc>,
+    pub last_activity_at: DateTime<Utc>,
+    /// ID последних платежей, новые первыми
+    pub charges: Vec<String>,
+}
+
+impl PosSession {
+    pub fn is_expired(&self) -> bool {
+        Utc::now() > self.last_activity_at + Duration::hours(SESSION_IDLE_HOURS)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PosError {
+    #[error("POS session not found")]
+    SessionNotFound,
+    #[error("POS session expired, open a new one")]
+    SessionExpired,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CreatePosSessionRequest {
+    pub recipient: String,
+    pub token: String,
+    pub label: Option<String>,
+    /// Имя терминала/кассы для отображения
+    pub terminal: Option<String>,
+}
+
+impl Validate for CreatePosSessionRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        errors.check_pubkey("recipient", &self.recipient);
+        if self.token.is_empty() {
+            errors.add("token", "required", "Token is required");
+        }
+        errors.into_result()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PosChargeRequest {
+    pub amount: f64,
+    pub message: Option<String>,
+    pub memo: Option<String>,
+    /// Язык строк для кошелька покупателя
+    pub locale: Option<String>,
+    /// Данные мерчанта для чека (номер заказа и т.п.)
+    #[serde(default)]
+    pub metadata: Option<BTreeMap<String, String>>,
+}
+
+/// Сессия с актуальными статусами последних чеков
+#[derive(Debug, Serialize)]
+pub struct PosSessionView {
+    #[serde(flatten)]
+    pub session: PosSession,
+    pub recent_charges: Vec<Payment>,
+}
+
+#[derive(Clone)]
+pub struct PosService {
+    payments: PaymentService,
+    storage: StorageService,
+}
+
+impl PosService {
+    pub fn new(payments: PaymentService) -> Self {
+        let storage = payments.storage().clone();
+        Self { payments, storage }
+    }
+
+    /// Открыть сессию терминала для мерчанта
+    pub async fn open_session(
+        &self,
+        request: CreatePosSessionRequest,
+        merchant: &MerchantConfig,
+    ) -> anyhow::Result<PosSession> {
+        request.validate()?;
+
+        let config = self.payments.config();
+        if !config.is_token_supported(&request.token) {
+            let mut errors = ValidationErrors::new();
+            errors.add(
+                "token",
+                "unsupported",
+                format!(
+                    "Token {} not supported. Supported tokens: {}",
+                    request.token,
+                    config.get_supported_tokens().join(", ")
+                ),
+            );
+            return Err(errors.into());
+        }
+        self.payments.check_recipient(&request.recipient).await?;
+        self.payments.ensure_recipient_verified(&request.recipient).await?;
+
+        let now = Utc::now();
+        let session = PosSession {
+            id: format!("pos_{}", Uuid::new_v4().simple()),
+            merchant_id: merchant.id.clone(),
+            terminal: request.terminal,
+            recipient: request.recipient,
+            label: request.label.unwrap_or_else(|| merchant.name.clone()),
+            token: request.token,
+            created_at: now,
+            last_activity_at: now,
+            charges: Vec::new(),
+        };
+        self.storage.save_pos_session(&session).await?;
+
+        tracing::info!(session_id = %session.id, merchant = %merchant.id, "POS session opened");
+
+        Ok(session)
+    }
+
+    /// Создать чек в рамках сессии
+    pub async fn charge(
+        &self,
+        session_id: &str,
+        request: PosChargeRequest,
+        merchant: &MerchantConfig,
+    ) -> anyhow::Result<Payment> {
+        let session = self.active_session(session_id, merchant).await?;
+
+        let payment = self.payments.create_session_payment(
+            CreatePaymentRequest {
+                recipient: session.recipient.clone(),
+                amount: request.amount,
+                amount_base_units: None,
+                token: session.token.clone(),
+                label: Some(session.label.clone()),
+                message: request.message,
+                memo: request.memo,
+                tip_presets_bps: None,
+                locale: request.locale,
+                metadata: request.metadata,
+                fee_amount: None,
+                fee_token: None,
+                success_url: None,
+                cancel_url: None,
+                discount_code: None,
+                redeem_points: None,
+                allowed_payers: None,
+                hold: false,
+                hold_secs: None,
+                amount_range: None,
+            },
+            Some(merchant),
+        ).await?;
+
+        self.storage.push_pos_charge(session_id, &payment.id, MAX_RECENT_CHARGES).await?;
+
+        Ok(payment)
+    }
+
+    /// Сессия с последними чеками
+    pub async fn session_view(
+        &self,
+        session_id: &str,
+        merchant: &MerchantConfig,
+    ) -> anyhow::Result<PosSessionView> {
+        let session = self.active_session(session_id, merchant).await?;
+
+        let mut recent_charges = Vec::with_capacity(session.charges.len());
+        for payment_id in &session.charges {
+            if let Some(payment) = self.storage.get_payment(payment_id).await? {
+                recent_charges.push(payment);
+            }
+        }
+
+        Ok(PosSessionView { session, recent_charges })
+    }
+
+    /// Сессия мерчанта; чужие и просроченные сессии не видны
+    async fn active_session(&self, session_id: &str, merchant: &MerchantConfig) -> anyhow::Result<PosSession> {
+        let session = self.storage.get_pos_session(session_id).await?
+            .filter(|s| s.merchant_id == merchant.id)
+            .ok_or(PosError::SessionNotFound)?;
+
+        if session.is_expired() {
+            self.storage.delete_pos_session(session_id).await?;
+            return Err(PosError::SessionExpired.into());
+        }
+
+        Ok(session)
+    }
+}
@@ -0,0 +1,190 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use crate::config::{Config, TokenConfig};
+use crate::display::format_amount;
+use crate::payment::Payment;
+
+/// Mint wrapped SOL — используется ценовым API для SOL
+pub const WRAPPED_SOL_MINT: &str = "So11111111111111111111111111111111111111112";
+
+/// Сколько секунд держим цену в кэше
+const PRICE_CACHE_TTL_SECONDS: i64 = 60;
+
+/// Валюты без дробной части
+const ZERO_DECIMAL_CURRENCIES: &[&str] = &["JPY", "KRW", "VND", "CLP", "ISK"];
+
+/// Кэш цен: mint → (цена в USD, время получения)
+type PriceCache = Arc<RwLock<HashMap<String, (f64, DateTime<Utc>)>>>;
+
+/// Курсы валют к USD и время получения
+type FxCache = Arc<RwLock<Option<(HashMap<String, f64>, DateTime<Utc>)>>>;
+
+/// Курс токена к USD (Jupiter Price API) с кэшированием
+#[derive(Debug, Clone)]
+pub struct PriceOracle {
+    client: reqwest::Client,
+    api_url: String,
+    config: Config,
+    cache: PriceCache,
+    fx_cache: FxCache,
+}
+
+impl PriceOracle {
+    pub fn new(config: Config) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            api_url: config.solana.price_api_url.clone(),
+            config,
+            cache: Arc::new(RwLock::new(HashMap::new())),
+            fx_cache: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    /// Цена одного токена в USD
+    pub async fn usd_price(&self, symbol: &str) -> anyhow::Result<f64> {
+        let token = self.config.get_token_config(symbol)
+            .ok_or_else(|| anyhow::anyhow!("Token {} not supported", symbol))?;
+        let mint = price_mint(&token);
+
+        if let Some((price, fetched_at)) = self.cache.read().await.get(mint) {
+            if Utc::now() - *fetched_at < Duration::seconds(PRICE_CACHE_TTL_SECONDS) {
+                return Ok(*price);
+            }
+        }
+
+        let price = self.fetch_price(mint).await?;
+        self.cache.write().await.insert(mint.to_string(), (price, Utc::now()));
+
+        tracing::debug!(token = symbol, price_usd = price, "Price fetched");
+        Ok(price)
+    }
+
+    /// Сколько единиц валюты `currency` стоит 1 USD (курсы всех валют обновляются раз в минуту)
+    pub async fn fx_rate(&self, currency: &str) -> anyhow::Result<f64> {
+        if currency == "USD" {
+            return Ok(1.0);
+        }
+        let cached = self.fx_cache.read().await.clone()
+            .filter(|(_, fetched_at)| Utc::now() - *fetched_at < Duration::seconds(PRICE_CACHE_TTL_SECONDS));
+        let rates = match cached {
+            Some((rates, _)) => rates,
+            None => {
+                let rates = self.fetch_fx_rates().await?;
+                *self.fx_cache.write().await = Some((rates.clone(), Utc::now()));
+                tracing::debug!(currencies = rates.len(), "FX rates fetched");
+                rates
+            }
+        };
+        rates.get(currency).copied()
+            .ok_or_else(|| anyhow::anyhow!("FX rate for {} not found in oracle response", currency))
+    }
+
+    /// Цена одного токена в валюте `currency`
+    pub async fn fiat_price(&self, symbol: &str, currency: &str) -> anyhow::Result<f64> {
+        let (usd_price, fx_rate) = tokio::try_join!(self.usd_price(symbol), self.fx_rate(currency))?;
+        Ok(usd_price * fx_rate)
+    }
+
+    async fn fetch_fx_rates(&self) -> anyhow::Result<HashMap<String, f64>> {
+        let response: Value = self.client
+            .get(&self.config.solana.fx_api_url)
+            .timeout(std::time::Duration::from_secs(5))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let rates: HashMap<String, f64> = response
+            .get("rates")
+            .and_then(Value::as_object)
+            .ok_or_else(|| anyhow::anyhow!("FX oracle response has no rates"))?
+            .iter()
+            .filter_map(|(code, rate)| rate.as_f64().map(|rate| (code.to_uppercase(), rate)))
+            .filter(|(_, rate)| *rate > 0.0 && rate.is_finite())
+            .collect();
+        Ok(rates)
+    }
+
+    async fn fetch_price(&self, mint: &str) -> anyhow::Result<f64> {
+        let response: Value = self.client
+            .get(&self.api_url)
+            .query(&[("ids", mint)])
+            .timeout(std::time::Duration::from_secs(5))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        // Цена приходит строкой или числом в зависимости от версии API
+        let price = response
+            .get("data")
+            .and_then(|data| data.get(mint))
+            .and_then(|entry| entry.get("price"))
+            .and_then(|price| match price {
+                Value::String(s) => s.parse().ok(),
+                other => other.as_f64(),
+            })
+            .ok_or_else(|| anyhow::anyhow!("Price for {} not found in oracle response", mint))?;
+
+        if !(price > 0.0 && price.is_finite()) {
+            anyhow::bail!("Oracle returned invalid price for {}: {}", mint, price);
+        }
+
+        Ok(price)
+    }
+}
+
+fn price_mint(token: &TokenConfig) -> &str {
+    token.mint.as_deref().unwrap_or(WRAPPED_SOL_MINT)
+}
+
+/// Суммы платежа в фиатной валюте — только для показа, к оплате всегда суммы в токенах
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FiatAmounts {
+    /// Код валюты ISO 4217
+    pub currency: String,
+    /// Цена единицы токена платежа и токена комиссии в валюте
+    pub token_rate: f64,
+    pub fee_token_rate: f64,
+    pub amount: f64,
+    pub tip: Option<f64>,
+    pub fee: f64,
+    pub total: f64,
+    /// `12.50 EUR`
+    pub amount_display: String,
+    pub fee_display: String,
+    pub tip_display: Option<String>,
+    pub total_display: String,
+}
+
+impl FiatAmounts {
+    /// Пересчет сумм платежа по курсам токенов к валюте
+    pub fn convert(payment: &Payment, currency: &str, token_rate: f64, fee_token_rate: f64) -> Self {
+        let decimals = if ZERO_DECIMAL_CURRENCIES.contains(&currency) { 0 } else { 2 };
+        let display = |amount: f64| format!("{} {}", format_amount(amount, decimals), currency);
+        let amount = payment.amount * token_rate;
+        let tip = payment.tip_amount.map(|tip| tip * token_rate);
+        let fee = payment.fee_amount * fee_token_rate;
+        let total = amount + tip.unwrap_or_default() + fee;
+        Self {
+            currency: currency.to_string(),
+            token_rate,
+            fee_token_rate,
+            amount,
+            tip,
+            fee,
+            total,
+            amount_display: display(amount),
+            fee_display: display(fee),
+            tip_display: tip.map(display),
+            total_display: display(total),
+        }
+    }
+}
@@ -1,73 +1,116 @@
 // src/qr.rs
 use qrcode::{QrCode, EcLevel};
-use image::{ImageBuffer, Rgb, RgbImage};
 use base64::{Engine as _, engine::general_purpose};
+use bytes::Bytes;
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
 
+/// Размер модуля QR в пикселях
+const MODULE_SIZE: u32 = 10;
+
+/// Рамка вокруг кода в модулях (quiet zone)
+const BORDER_MODULES: u32 = 4;
+
+/// Сколько последних QR кодов держать в памяти
+const CACHE_CAPACITY: usize = 1024;
+
+/// Какой QR показать плательщику
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QrKind {
+    /// Solana Pay transaction request: транзакцию собирает сервер (комиссия, чаевые, свопы)
+    #[default]
+    Transaction,
+    /// Solana Pay transfer request: получатель и сумма прямо в URL — для кошельков
+    /// без transaction request. Комиссия в перевод не входит
+    Transfer,
+}
+
+impl QrKind {
+    /// QR по возможностям кошелька, о которых сообщил клиент
+    pub fn for_wallet(transaction_requests: bool) -> Self {
+        if transaction_requests { QrKind::Transaction } else { QrKind::Transfer }
+    }
+}
+
+/// QR коды платежных URL. PNG кэшируется по URL: один платеж запрашивают многократно
 #[derive(Debug, Clone)]
-pub struct QrService;
+pub struct QrService {
+    cache: Arc<Mutex<LruCache<String, Bytes>>>,
+}
+
+impl Default for QrService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl QrService {
     pub fn new() -> Self {
-        Self
+        let capacity = NonZeroUsize::new(CACHE_CAPACITY).expect("cache capacity is non-zero");
+        Self { cache: Arc::new(Mutex::new(LruCache::new(capacity))) }
     }
 
-    /// Генерировать QR код в формате base64 data URL
-    pub fn generate_qr_code(&self, data: &str) -> anyhow::Result<String> {
-        // Создаем QR код
-        let code = QrCode::with_error_correction_level(data, EcLevel::M)?;
+    /// PNG с QR кодом (из кэша или отрисованный). `Bytes` отдается в ответ без копирования
+    pub fn png(&self, data: &str) -> anyhow::Result<Bytes> {
+        if let Some(png) = self.cache.lock().unwrap_or_else(|e| e.into_inner()).get(data) {
+            return Ok(png.clone());
+        }
 
-        // Настройки изображения
-        let size = 10; // Размер пикселя
-        let border = 4; // Размер рамки
+        // Рисуем без блокировки кэша; гонка двух запросов даст лишь повторную отрисовку
+        let png = Bytes::from(render_png(data)?);
+        self.cache.lock().unwrap_or_else(|e| e.into_inner()).put(data.to_string(), png.clone());
+        Ok(png)
+    }
 
-        // Размеры
-        let width = code.width();
-        let img_size = (width + 2 * border) * size;
+    /// Генерировать QR код в формате base64 data URL
+    pub fn generate_qr_code(&self, data: &str) -> anyhow::Result<String> {
+        let png = self.png(data)?;
+        Ok(format!("data:image/png;base64,{}", general_purpose::STANDARD.encode(&png)))
+    }
+}
 
-        // Создаем изображение
-        let mut img: RgbImage = ImageBuffer::new(img_size as u32, img_size as u32);
+/// Отрисовать QR код в PNG (без кэша). Вместо попиксельного рисования квадратов
+/// модули строки заливаются отрезками, а готовая строка пикселей копируется блоком
+pub fn render_png(data: &str) -> anyhow::Result<Vec<u8>> {
+    let code = QrCode::with_error_correction_level(data, EcLevel::M)?;
 
-        // Заполняем белым фоном
-        for pixel in img.pixels_mut() {
-            *pixel = Rgb([255, 255, 255]);
-        }
+    let width = code.width();
+    let module = MODULE_SIZE as usize;
+    let border = BORDER_MODULES as usize;
+    let img_size = (width + 2 * border) * module;
 
-        // Рисуем QR код
-        for y in 0..width {
-            for x in 0..width {
-                if code[(x, y)] == qrcode::Color::Dark {
-                    // Рисуем черный квадрат
-                    for dy in 0..size {
-                        for dx in 0..size {
-                            let px = (border + x) * size + dx;
-                            let py = (border + y) * size + dy;
-                            if px < img_size && py < img_size {
-                                img.put_pixel(px as u32, py as u32, Rgb([0, 0, 0]));
-                            }
-                        }
-                    }
-                }
+    let mut pixels = vec![255u8; img_size * img_size];
+    for (y, row) in code.to_colors().chunks(width).enumerate() {
+        let row_start = (border + y) * module * img_size;
+        let line = &mut pixels[row_start..row_start + img_size];
+        for (x, color) in row.iter().enumerate() {
+            if *color == qrcode::Color::Dark {
+                let px = (border + x) * module;
+                line[px..px + module].fill(0);
             }
         }
-
-        // Конвертируем в PNG bytes
-        let mut png_bytes = Vec::new();
-        {
-            use image::codecs::png::PngEncoder;
-            use image::ImageEncoder;
-
-            let encoder = PngEncoder::new(&mut png_bytes);
-            encoder.write_image(
-                img.as_raw(),
-                img_size as u32,
-                img_size as u32,
-                image::ColorType::Rgb8,
-            )?;
+        for dy in 1..module {
+            pixels.copy_within(row_start..row_start + img_size, row_start + dy * img_size);
         }
+    }
+    let img_size = img_size as u32;
 
-        // Кодируем в base64
-        let base64_string = general_purpose::STANDARD.encode(&png_bytes);
+    // Конвертируем в PNG bytes (оттенки серого — меньше данных для кодирования)
+    let mut png_bytes = Vec::new();
+    {
+        use image::codecs::png::PngEncoder;
+        use image::ImageEncoder;
 
-        Ok(format!("data:image/png;base64,{}", base64_string))
+        PngEncoder::new(&mut png_bytes).write_image(
+            &pixels,
+            img_size,
+            img_size,
+            image::ColorType::L8,
+        )?;
     }
-}
\ No newline at end of file
+
+    Ok(png_bytes)
+}
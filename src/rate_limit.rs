@@ -0,0 +1,98 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, HttpResponse};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::ServerConfig;
+
+/// Длина окна лимита, секунды
+const WINDOW_SECS: u64 = 60;
+
+/// Итог проверки лимита
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateDecision {
+    Allowed,
+    /// Лимит исчерпан до конца окна
+    Limited { retry_after_secs: u64 },
+}
+
+/// Лимит запросов с одного IP клиента: фиксированное окно в минуту, счетчики в памяти
+/// процесса (у каждой реплики свои)
+#[derive(Clone)]
+pub struct RateLimiter {
+    per_minute: u32,
+    /// Клиент → (номер окна, запросов в окне)
+    windows: Arc<Mutex<HashMap<String, (u64, u32)>>>,
+}
+
+impl RateLimiter {
+    pub fn new(per_minute: u32) -> Self {
+        Self { per_minute, windows: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    pub fn from_config(config: &ServerConfig) -> Self {
+        Self::new(config.rate_limit_per_minute)
+    }
+
+    /// Учесть запрос клиента в момент `now_secs` (unix время)
+    pub fn check_at(&self, client: &str, now_secs: u64) -> RateDecision {
+        if self.per_minute == 0 {
+            return RateDecision::Allowed;
+        }
+        let window = now_secs / WINDOW_SECS;
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        // Счетчики прошлых окон больше не нужны
+        if windows.len() > 10_000 {
+            windows.retain(|_, (started, _)| *started == window);
+        }
+        let entry = windows.entry(client.to_string()).or_insert((window, 0));
+        if entry.0 != window {
+            *entry = (window, 0);
+        }
+        if entry.1 >= self.per_minute {
+            return RateDecision::Limited { retry_after_secs: (window + 1) * WINDOW_SECS - now_secs };
+        }
+        entry.1 += 1;
+        RateDecision::Allowed
+    }
+
+    pub fn check(&self, client: &str) -> RateDecision {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+        self.check_at(client, now)
+    }
+}
+
+/// 429 с временем до конца окна
+fn too_many_requests(limit: u32, retry_after_secs: u64) -> HttpResponse {
+    HttpResponse::TooManyRequests()
+        .append_header(("Retry-After", retry_after_secs.to_string()))
+        .json(serde_json::json!({
+            "success": false,
+            "error": format!("Too many requests: limit is {} per minute", limit),
+            "code": "rate_limited",
+            "retry_after_secs": retry_after_secs,
+        }))
+}
+
+/// Middleware: лимит запросов по IP клиента. За reverse proxy IP берется из
+/// `Forwarded`/`X-Forwarded-For` — прокси должен перезаписывать эти заголовки
+pub async fn rate_limit(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let Some(limiter) = req.app_data::<web::Data<RateLimiter>>().cloned() else {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    };
+    let client = req.connection_info().realip_remote_addr().unwrap_or("unknown").to_string();
+    match limiter.check(&client) {
+        RateDecision::Allowed => next.call(req).await.map(ServiceResponse::map_into_left_body),
+        RateDecision::Limited { retry_after_secs } => {
+            tracing::warn!(%client, path = req.path(), "Rate limit exceeded");
+            let response = too_many_requests(limiter.per_minute, retry_after_secs);
+            Ok(req.into_response(response).map_into_right_body())
+        }
+    }
+}
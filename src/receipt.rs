@@ -0,0 +1,127 @@
+use chrono::{DateTime, Utc};
+use printpdf::{BuiltinFont, Mm, PdfDocument};
+use serde::Serialize;
+
+use crate::explorer::Explorer;
+use crate::payment::{Payment, PaymentStatus, UsdValuation};
+
+/// Квитанция об оплате для покупателя
+#[derive(Debug, Clone, Serialize)]
+pub struct Receipt {
+    pub payment_id: String,
+    pub merchant: ReceiptMerchant,
+    pub amount: f64,
+    pub token: String,
+    pub tip_amount: Option<f64>,
+    pub fee_amount: f64,
+    pub fee_token: String,
+    pub memo: Option<String>,
+    /// Оценка в USD на момент оплаты
+    pub usd_valuation: Option<UsdValuation>,
+    pub signature: String,
+    pub block_time: Option<DateTime<Utc>>,
+    pub verified_at: Option<DateTime<Utc>>,
+    pub explorer_url: String,
+    pub issued_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReceiptMerchant {
+    pub name: String,
+    pub wallet: String,
+}
+
+impl Receipt {
+    /// Квитанция доступна только для завершенного платежа
+    pub fn from_payment(payment: &Payment, explorer: &Explorer) -> anyhow::Result<Self> {
+        if !matches!(payment.status, PaymentStatus::Completed) {
+            anyhow::bail!("Receipt is only available for completed payments");
+        }
+        let signature = payment.signature.clone()
+            .ok_or_else(|| anyhow::anyhow!("Completed payment has no signature"))?;
+
+        Ok(Self {
+            payment_id: payment.id.clone(),
+            merchant: ReceiptMerchant {
+                name: payment.label.clone(),
+                wallet: payment.recipient.clone(),
+            },
+            amount: payment.amount,
+            token: payment.token.clone(),
+            tip_amount: payment.tip_amount,
+            fee_amount: payment.fee_amount,
+            fee_token: payment.fee_token.clone(),
+            memo: payment.memo.clone(),
+            usd_valuation: payment.usd_valuation.clone(),
+            explorer_url: payment.explorer_url.clone()
+                .unwrap_or_else(|| explorer.transaction_url(&signature)),
+            signature,
+            block_time: payment.block_time,
+            verified_at: payment.verified_at,
+            issued_at: Utc::now(),
+        })
+    }
+
+    /// Строки квитанции для текстового/PDF представления
+    fn lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("Payment ID: {}", self.payment_id),
+            format!("Merchant: {}", self.merchant.name),
+            format!("Merchant wallet: {}", self.merchant.wallet),
+            format!("Amount: {} {}", self.amount, self.token),
+        ];
+        if let Some(tip) = self.tip_amount {
+            lines.push(format!("Tip: {} {}", tip, self.token));
+        }
+        lines.push(format!("Fee: {} {}", self.fee_amount, self.fee_token));
+        if let Some(valuation) = &self.usd_valuation {
+            lines.push(format!(
+                "USD value: {:.2} (1 {} = {} USD)",
+                valuation.amount_usd + valuation.tip_usd.unwrap_or(0.0),
+                self.token,
+                valuation.token_usd_rate,
+            ));
+            lines.push(format!(
+                "Fee USD value: {:.2} (1 {} = {} USD)",
+                valuation.fee_usd, self.fee_token, valuation.fee_token_usd_rate,
+            ));
+            lines.push(format!("Rates as of: {}", valuation.priced_at.to_rfc3339()));
+        }
+        if let Some(memo) = &self.memo {
+            lines.push(format!("Memo: {}", memo));
+        }
+        lines.push(format!("Signature: {}", self.signature));
+        if let Some(block_time) = self.block_time {
+            lines.push(format!("Block time: {}", block_time.to_rfc3339()));
+        }
+        if let Some(verified_at) = self.verified_at {
+            lines.push(format!("Verified at: {}", verified_at.to_rfc3339()));
+        }
+        lines.push(format!("Explorer: {}", self.explorer_url));
+        lines.push(format!("Issued at: {}", self.issued_at.to_rfc3339()));
+        lines
+    }
+
+    /// Отрендерить квитанцию в PDF (A4, встроенный шрифт)
+    pub fn to_pdf(&self) -> anyhow::Result<Vec<u8>> {
+        let (doc, page, layer) = PdfDocument::new(
+            format!("Receipt {}", self.payment_id),
+            Mm(210.0),
+            Mm(297.0),
+            "Receipt",
+        );
+        let title_font = doc.add_builtin_font(BuiltinFont::HelveticaBold)?;
+        let font = doc.add_builtin_font(BuiltinFont::Helvetica)?;
+        let layer = doc.get_page(page).get_layer(layer);
+
+        layer.use_text("Payment receipt", 20.0, Mm(20.0), Mm(270.0), &title_font);
+
+        let mut y = 255.0;
+        for line in self.lines() {
+            layer.use_text(line, 10.0, Mm(20.0), Mm(y), &font);
+            y -= 8.0;
+        }
+
+        Ok(doc.save_to_bytes()?)
+    }
+}
@@ -0,0 +1,335 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use solana_program::program_pack::Pack;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    message::{v0, VersionedMessage},
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::Keypair,
+    signer::Signer,
+    system_instruction, system_program,
+    transaction::VersionedTransaction,
+};
+use std::str::FromStr;
+
+use crate::config::{Config, ReceiptNftKind};
+use crate::payment::{Payment, PaymentService, PaymentStatus};
+use crate::receipt::Receipt;
+use crate::signer::ServerSigner;
+use crate::tokens::TOKEN_METADATA_PROGRAM_ID;
+
+pub const BUBBLEGUM_PROGRAM_ID: &str = "BGUMAp9Gq7iTEuizy4pqaxsTyUCBK68MDfK752saRPUY";
+pub const SPL_NOOP_PROGRAM_ID: &str = "noopb9bkMVfRPU8AsbpTUg8AQkHtKwMYZiFUjNRtMmV";
+pub const SPL_ACCOUNT_COMPRESSION_PROGRAM_ID: &str = "cmtDvXumGCrqC1Age74AVPhSRVXJMd8PJS91L8KbNCK";
+
+/// Квитанции выпускаются только для платежей, завершенных не раньше (часы)
+const MAX_AGE_HOURS: i64 = 24;
+
+/// Ограничения метаданных Metaplex
+const MAX_NAME_LENGTH: usize = 32;
+const MAX_URI_LENGTH: usize = 200;
+
+/// Дискриминатор `CreateMetadataAccountV3` в Token Metadata
+const CREATE_METADATA_V3: u8 = 33;
+/// Дискриминатор Anchor инструкции `mint_v1` в Bubblegum
+const BUBBLEGUM_MINT_V1: [u8; 8] = [145, 98, 192, 118, 184, 147, 118, 104];
+/// Смещение `num_minted` в `TreeConfig`: дискриминатор, создатель, делегат, емкость
+const TREE_NUM_MINTED_OFFSET: usize = 8 + 32 + 32 + 8;
+
+/// NFT-квитанция платежа
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReceiptNft {
+    pub kind: ReceiptNftKind,
+    /// Адрес mint (`nft`) или ID ассета (`cnft`) выпущенной квитанции
+    pub mint: Option<String>,
+    pub signature: Option<String>,
+    pub minted_at: Option<DateTime<Utc>>,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+}
+
+/// Атрибут в метаданных NFT
+#[derive(Debug, Clone, Serialize)]
+pub struct NftAttribute {
+    pub trait_type: String,
+    pub value: String,
+}
+
+/// JSON метаданных по стандарту Metaplex (`uri` квитанции)
+#[derive(Debug, Clone, Serialize)]
+pub struct ReceiptNftMetadata {
+    pub name: String,
+    pub symbol: String,
+    pub description: String,
+    /// Квитанция на сервере
+    pub external_url: String,
+    pub attributes: Vec<NftAttribute>,
+}
+
+impl ReceiptNftMetadata {
+    /// Метаданные завершенного платежа
+    pub fn for_payment(payment: &Payment, config: &Config) -> anyhow::Result<Self> {
+        let receipt = Receipt::from_payment(payment, &config.explorer())?;
+        let paid_at = receipt.block_time.or(receipt.verified_at).unwrap_or(payment.status_changed_at);
+        let attribute = |trait_type: &str, value: String| NftAttribute { trait_type: trait_type.to_string(), value };
+
+        let mut attributes = vec![
+            attribute("Payment ID", receipt.payment_id.clone()),
+            attribute("Merchant", receipt.merchant.name.clone()),
+            attribute("Amount", format!("{} {}", receipt.amount, receipt.token)),
+        ];
+        if let Some(tip) = receipt.tip_amount {
+            attributes.push(attribute("Tip", format!("{} {}", tip, receipt.token)));
+        }
+        attributes.push(attribute("Fee", format!("{} {}", receipt.fee_amount, receipt.fee_token)));
+        if let Some(memo) = &receipt.memo {
+            attributes.push(attribute("Memo", memo.clone()));
+        }
+        attributes.push(attribute("Transaction", receipt.signature.clone()));
+        attributes.push(attribute("Paid at", paid_at.to_rfc3339()));
+
+        Ok(Self {
+            name: nft_name(&payment.id),
+            symbol: config.receipt_nft.symbol.clone(),
+            description: format!("Receipt for {} paid to {}", payment.total_display, receipt.merchant.name),
+            external_url: format!("{}/api/payment/{}/receipt", config.base_url(), payment.id),
+            attributes,
+        })
+    }
+}
+
+/// Имя NFT в пределах 32 байт Metaplex
+fn nft_name(payment_id: &str) -> String {
+    let mut name = String::from("Receipt ");
+    for c in payment_id.chars() {
+        if name.len() + c.len_utf8() > MAX_NAME_LENGTH {
+            break;
+        }
+        name.push(c);
+    }
+    name
+}
+
+/// Выпуск NFT-квитанций плательщикам завершенных платежей (задача `receipt_nft`)
+#[derive(Clone)]
+pub struct ReceiptNftService {
+    payments: PaymentService,
+    signer: Option<ServerSigner>,
+}
+
+impl ReceiptNftService {
+    pub fn new(payments: PaymentService, signer: Option<ServerSigner>) -> Self {
+        Self { payments, signer }
+    }
+
+    /// Выпустить квитанции по недавно завершенным платежам мерчантов с `receipt_nft`
+    pub async fn mint_pending(&self) -> anyhow::Result<usize> {
+        let config = self.payments.config();
+        if !config.receipt_nft.enabled {
+            return Ok(0);
+        }
+        let since = Utc::now() - Duration::hours(MAX_AGE_HOURS);
+        let max_attempts = config.receipt_nft.max_attempts;
+        let due: Vec<Payment> = self.payments.storage().get_all_payments().await?
+            .into_values()
+            .filter(|payment| payment.status == PaymentStatus::Completed && !payment.sandbox)
+            .filter(|payment| payment.status_changed_at >= since && payment.payer.is_some())
+            .filter(|payment| payment.merchant_id.as_deref()
+                .and_then(|id| config.find_merchant(id))
+                .is_some_and(|merchant| merchant.receipt_nft))
+            .filter(|payment| payment.receipt_nft.as_ref()
+                .is_none_or(|nft| nft.minted_at.is_none() && nft.attempts < max_attempts))
+            .collect();
+
+        let mut minted = 0;
+        for payment in due {
+            match self.mint(&payment).await {
+                Ok(()) => minted += 1,
+                Err(e) => tracing::warn!(payment_id = %payment.id, error = %e, "Failed to mint receipt NFT"),
+            }
+        }
+        if minted > 0 {
+            tracing::info!(minted, "Minted receipt NFTs");
+        }
+        Ok(minted)
+    }
+
+    /// Выпустить квитанцию на кошелек плательщика; результат попытки сохраняется в платеже
+    async fn mint(&self, payment: &Payment) -> anyhow::Result<()> {
+        let kind = self.payments.config().receipt_nft.kind;
+        let attempts = payment.receipt_nft.as_ref().map_or(0, |nft| nft.attempts) + 1;
+        let result = self.send(payment, kind).await;
+
+        let record = match &result {
+            Ok((mint, signature)) => ReceiptNft {
+                kind,
+                mint: Some(mint.to_string()),
+                signature: Some(signature.clone()),
+                minted_at: Some(Utc::now()),
+                attempts,
+                last_error: None,
+            },
+            Err(e) => ReceiptNft {
+                kind,
+                mint: None,
+                signature: None,
+                minted_at: None,
+                attempts,
+                last_error: Some(e.to_string()),
+            },
+        };
+        self.payments.update_payment(&payment.id, |payment| {
+            payment.receipt_nft = Some(record.clone());
+            Ok(true)
+        }).await?;
+
+        let (mint, signature) = result?;
+        tracing::info!(payment_id = %payment.id, %mint, %signature, ?kind, "Receipt NFT minted");
+        Ok(())
+    }
+
+    /// Адрес квитанции и подпись транзакции выпуска
+    async fn send(&self, payment: &Payment, kind: ReceiptNftKind) -> anyhow::Result<(Pubkey, String)> {
+        let signer = self.signer.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Receipt NFTs require the server keypair"))?;
+        let config = self.payments.config();
+        let owner = Pubkey::from_str(payment.payer.as_deref().unwrap_or_default())?;
+        let metadata = ReceiptNftMetadata::for_payment(payment, config)?;
+        let uri = format!("{}/api/payment/{}/receipt/nft", config.base_url(), payment.id);
+        if uri.len() > MAX_URI_LENGTH {
+            anyhow::bail!("Metadata URI is longer than {} bytes: {}", MAX_URI_LENGTH, uri);
+        }
+
+        let hot_wallet = signer.pubkey();
+        let rpc = self.payments.rpc();
+        let mint_keypair = Keypair::new();
+        let (address, instructions, signers) = match kind {
+            ReceiptNftKind::Nft => (
+                mint_keypair.pubkey(),
+                mint_instructions(&hot_wallet, &mint_keypair.pubkey(), &owner, &metadata, &uri)?,
+                vec![signer.keypair(), &mint_keypair],
+            ),
+            ReceiptNftKind::Cnft => {
+                let tree = Pubkey::from_str(config.receipt_nft.merkle_tree.as_deref().unwrap_or_default())?;
+                let bubblegum = Pubkey::from_str(BUBBLEGUM_PROGRAM_ID)?;
+                let (tree_config, _) = Pubkey::find_program_address(&[tree.as_ref()], &bubblegum);
+                // ID ассета — PDA от номера листа, который займет квитанция
+                let num_minted = rpc.get_account(&tree_config).await?
+                    .and_then(|account| {
+                        let bytes = account.data.get(TREE_NUM_MINTED_OFFSET..TREE_NUM_MINTED_OFFSET + 8)?;
+                        Some(u64::from_le_bytes(bytes.try_into().ok()?))
+                    })
+                    .ok_or_else(|| anyhow::anyhow!("Bubblegum tree config {} not found", tree_config))?;
+                let (asset_id, _) = Pubkey::find_program_address(
+                    &[b"asset", tree.as_ref(), &num_minted.to_le_bytes()],
+                    &bubblegum,
+                );
+                let instruction = compressed_mint_instruction(&hot_wallet, &tree, &owner, &metadata, &uri)?;
+                (asset_id, vec![instruction], vec![signer.keypair()])
+            }
+        };
+
+        let blockhash = rpc.get_latest_blockhash().await?;
+        let message = v0::Message::try_compile(&hot_wallet, &instructions, &[], blockhash)?;
+        let transaction = VersionedTransaction::try_new(VersionedMessage::V0(message), &signers)?;
+        let signature = rpc.send_transaction(&transaction).await?;
+        Ok((address, signature.to_string()))
+    }
+}
+
+/// Обычный NFT: mint с 0 знаков, один токен владельцу, метаданные Metaplex и снятие
+/// права выпуска (supply навсегда 1)
+fn mint_instructions(
+    hot_wallet: &Pubkey,
+    mint: &Pubkey,
+    owner: &Pubkey,
+    metadata: &ReceiptNftMetadata,
+    uri: &str,
+) -> anyhow::Result<Vec<Instruction>> {
+    let token_program = spl_token::id();
+    let owner_account = spl_associated_token_account::get_associated_token_address(owner, mint);
+    let metadata_program = Pubkey::from_str(TOKEN_METADATA_PROGRAM_ID)?;
+    let (metadata_account, _) = Pubkey::find_program_address(
+        &[b"metadata", metadata_program.as_ref(), mint.as_ref()],
+        &metadata_program,
+    );
+
+    // DataV2 без создателей, коллекции и uses; квитанция неизменяема
+    let mut data = vec![CREATE_METADATA_V3];
+    put_metadata_strings(&mut data, metadata, uri);
+    data.extend_from_slice(&0u16.to_le_bytes());
+    data.extend_from_slice(&[0, 0, 0]);
+    data.push(0);
+    data.push(0);
+
+    Ok(vec![
+        system_instruction::create_account(
+            hot_wallet,
+            mint,
+            Rent::default().minimum_balance(spl_token::state::Mint::LEN),
+            spl_token::state::Mint::LEN as u64,
+            &token_program,
+        ),
+        spl_token::instruction::initialize_mint2(&token_program, mint, hot_wallet, None, 0)?,
+        spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+            hot_wallet, owner, mint, &token_program,
+        ),
+        spl_token::instruction::mint_to(&token_program, mint, &owner_account, hot_wallet, &[], 1)?,
+        Instruction::new_with_bytes(metadata_program, &data, vec![
+            AccountMeta::new(metadata_account, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(*hot_wallet, true),
+            AccountMeta::new(*hot_wallet, true),
+            AccountMeta::new_readonly(*hot_wallet, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ]),
+        spl_token::instruction::set_authority(
+            &token_program,
+            mint,
+            None,
+            spl_token::instruction::AuthorityType::MintTokens,
+            hot_wallet,
+            &[],
+        )?,
+    ])
+}
+
+/// Сжатый NFT: `mint_v1` Bubblegum в дерево, где hot wallet — создатель или делегат
+fn compressed_mint_instruction(
+    hot_wallet: &Pubkey,
+    tree: &Pubkey,
+    owner: &Pubkey,
+    metadata: &ReceiptNftMetadata,
+    uri: &str,
+) -> anyhow::Result<Instruction> {
+    let bubblegum = Pubkey::from_str(BUBBLEGUM_PROGRAM_ID)?;
+    let (tree_config, _) = Pubkey::find_program_address(&[tree.as_ref()], &bubblegum);
+
+    // MetadataArgs: без продаж и роялти, неизменяемый, NonFungible, без коллекции и создателей
+    let mut data = BUBBLEGUM_MINT_V1.to_vec();
+    put_metadata_strings(&mut data, metadata, uri);
+    data.extend_from_slice(&0u16.to_le_bytes());
+    data.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 0]);
+    data.extend_from_slice(&0u32.to_le_bytes());
+
+    Ok(Instruction::new_with_bytes(bubblegum, &data, vec![
+        AccountMeta::new(tree_config, false),
+        AccountMeta::new_readonly(*owner, false),
+        AccountMeta::new_readonly(*owner, false),
+        AccountMeta::new(*tree, false),
+        AccountMeta::new(*hot_wallet, true),
+        AccountMeta::new_readonly(*hot_wallet, true),
+        AccountMeta::new_readonly(Pubkey::from_str(SPL_NOOP_PROGRAM_ID)?, false),
+        AccountMeta::new_readonly(Pubkey::from_str(SPL_ACCOUNT_COMPRESSION_PROGRAM_ID)?, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ]))
+}
+
+/// Имя, символ и URI в кодировке borsh (длина u32 и байты)
+fn put_metadata_strings(data: &mut Vec<u8>, metadata: &ReceiptNftMetadata, uri: &str) {
+    for value in [metadata.name.as_str(), metadata.symbol.as_str(), uri] {
+        data.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        data.extend_from_slice(value.as_bytes());
+    }
+}
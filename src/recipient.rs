@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+use solana_sdk::{program_pack::Pack, pubkey::Pubkey, system_program};
+use std::time::Duration;
+
+use crate::config::OffCurvePolicy;
+use crate::rpc::SolanaRpc;
+
+/// Таймаут проверки аккаунта получателя
+const RECIPIENT_CHECK_TIMEOUT_SECS: u64 = 5;
+
+/// Тип адреса получателя
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecipientKind {
+    /// Обычный кошелек (ключ на кривой ed25519)
+    #[default]
+    Wallet,
+    /// PDA вне кривой — хранилище мультисига (например, vault Squads).
+    /// Подписать не может, но принимает SOL и владеет своими ATA
+    Vault,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RecipientError {
+    #[error("Recipient {address} is off-curve (a program-derived address); off-curve recipients are not accepted")]
+    OffCurve { address: String },
+    #[error("Recipient {address} is a token account; use its owner wallet {owner} instead")]
+    TokenAccount { address: String, owner: String },
+    #[error("Recipient {address} is an account of program {program}, not a wallet. For a multisig (e.g. Squads) use its vault address")]
+    ProgramAccount { address: String, program: String },
+}
+
+impl RecipientError {
+    /// Код ошибки валидации поля получателя
+    pub fn code(&self) -> &'static str {
+        match self {
+            RecipientError::OffCurve { .. } => "off_curve",
+            RecipientError::TokenAccount { .. } => "token_account",
+            RecipientError::ProgramAccount { .. } => "program_account",
+        }
+    }
+}
+
+/// Определить тип получателя. Адрес, принадлежащий программе (сам аккаунт мультисига,
+/// токен-аккаунт и т.п.), отклоняется: SOL туда не перевести, а ATA от такого адреса
+/// заблокирует средства; адрес вне кривой проверяется по `off_curve`.
+/// Если RPC недоступен — тип определяется только по кривой
+pub async fn classify(
+    rpc: &dyn SolanaRpc,
+    recipient: &Pubkey,
+    off_curve: OffCurvePolicy,
+) -> Result<RecipientKind, RecipientError> {
+    let kind = if recipient.is_on_curve() { RecipientKind::Wallet } else { RecipientKind::Vault };
+    if kind == RecipientKind::Vault {
+        match off_curve {
+            OffCurvePolicy::Reject => return Err(RecipientError::OffCurve { address: recipient.to_string() }),
+            OffCurvePolicy::Warn => tracing::warn!(%recipient, "Recipient is off-curve (PDA or multisig vault)"),
+            OffCurvePolicy::Allow => {}
+        }
+    }
+
+    let account = match tokio::time::timeout(
+        Duration::from_secs(RECIPIENT_CHECK_TIMEOUT_SECS),
+        rpc.get_account(recipient),
+    ).await {
+        Ok(Ok(account)) => account,
+        Ok(Err(e)) => {
+            tracing::warn!(%recipient, ?kind, error = %e, "Failed to check recipient account, assuming kind by curve");
+            return Ok(kind);
+        }
+        Err(_) => {
+            tracing::warn!(%recipient, ?kind, "Recipient account check timed out, assuming kind by curve");
+            return Ok(kind);
+        }
+    };
+
+    match account {
+        // Новый адрес или обычный системный аккаунт (vault Squads тоже принадлежит System Program)
+        None => Ok(kind),
+        Some(account) if account.owner == system_program::ID => Ok(kind),
+        Some(account) if account.owner == spl_token::ID => match spl_token::state::Account::unpack(&account.data) {
+            Ok(token_account) => Err(RecipientError::TokenAccount {
+                address: recipient.to_string(),
+                owner: token_account.owner.to_string(),
+            }),
+            Err(_) => Err(RecipientError::ProgramAccount {
+                address: recipient.to_string(),
+                program: account.owner.to_string(),
+            }),
+        },
+        Some(account) => Err(RecipientError::ProgramAccount {
+            address: recipient.to_string(),
+            program: account.owner.to_string(),
+        }),
+    }
+}
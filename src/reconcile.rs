@@ -0,0 +1,113 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::payment::{Payment, PaymentService, PaymentStatus};
+
+/// Платеж, оплаченный в блокчейне, но не верифицированный сервером
+#[derive(Debug, Clone, Serialize)]
+pub struct ReconciledPayment {
+    pub payment_id: String,
+    pub signature: String,
+}
+
+/// Платеж, который не удалось проверить
+#[derive(Debug, Clone, Serialize)]
+pub struct ReconciliationFailure {
+    pub payment_id: String,
+    pub error: String,
+}
+
+/// Итог сверки
+#[derive(Debug, Clone, Serialize)]
+pub struct ReconciliationReport {
+    pub dry_run: bool,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    /// Проверено ожидающих платежей
+    pub scanned: usize,
+    /// Завершены сверкой (в dry run — были бы завершены)
+    pub completed: Vec<ReconciledPayment>,
+    pub failed: Vec<ReconciliationFailure>,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Reconciliation is already running")]
+pub struct ReconciliationBusy;
+
+/// Сверка ожидающих платежей с блокчейном: транзакции с reference платежа, которые
+/// кошелек не прислал на верификацию (например, упал до POST подписи), завершают платеж
+#[derive(Clone)]
+pub struct ReconciliationService {
+    payments: PaymentService,
+    running: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl ReconciliationService {
+    pub fn new(payments: PaymentService) -> Self {
+        Self { payments, running: Arc::new(tokio::sync::Mutex::new(())) }
+    }
+
+    /// Проверить все ожидающие неистекшие платежи. С `dry_run` только найти транзакции
+    /// оплаты, не меняя платежи. Одновременно выполняется одна сверка
+    pub async fn run(&self, dry_run: bool) -> anyhow::Result<ReconciliationReport> {
+        let _running = self.running.try_lock().map_err(|_| ReconciliationBusy)?;
+        let started_at = Utc::now();
+        let pending: Vec<Payment> = self.payments.storage().get_all_payments().await?
+            .into_values()
+            .filter(|payment| payment.status == PaymentStatus::Pending && payment.expires_at > started_at && !payment.sandbox)
+            .collect();
+
+        let mut report = ReconciliationReport {
+            dry_run,
+            started_at,
+            finished_at: started_at,
+            scanned: pending.len(),
+            completed: Vec::new(),
+            failed: Vec::new(),
+        };
+        for payment in &pending {
+            match self.reconcile(payment, dry_run).await {
+                Ok(Some(signature)) => report.completed.push(ReconciledPayment {
+                    payment_id: payment.id.clone(),
+                    signature,
+                }),
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::warn!(payment_id = %payment.id, error = %e, "Payment reconciliation failed");
+                    report.failed.push(ReconciliationFailure { payment_id: payment.id.clone(), error: e.to_string() });
+                }
+            }
+        }
+        report.finished_at = Utc::now();
+
+        tracing::info!(
+            dry_run,
+            scanned = report.scanned,
+            completed = report.completed.len(),
+            failed = report.failed.len(),
+            "Payments reconciled"
+        );
+        Ok(report)
+    }
+
+    /// Подпись транзакции, которой оплачен платеж; старые транзакции проверяются первыми
+    async fn reconcile(&self, payment: &Payment, dry_run: bool) -> anyhow::Result<Option<String>> {
+        let reference = Pubkey::from_str(&payment.reference)?;
+        let signatures = self.payments.rpc().get_signatures_for_address(&reference).await?;
+        for signature in signatures.iter().rev() {
+            let signature = signature.to_string();
+            if dry_run {
+                // Неуспешные транзакции RPC уже отбросил
+                return Ok(Some(signature));
+            }
+            if self.payments.verify_payment(&payment.id, &signature).await?.verified {
+                tracing::info!(payment_id = %payment.id, %signature, "Payment completed by reconciliation");
+                return Ok(Some(signature));
+            }
+        }
+        Ok(None)
+    }
+}
@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::{constant_time_eq, MerchantBranding, MerchantConfig};
+use crate::payment::PaymentService;
+use crate::tenants::{generate_api_key, Tenant};
+use crate::validation::{Validate, ValidationErrors};
+use crate::webhook::{RegisterWebhookRequest, RegisteredWebhook, WebhookService};
+
+#[derive(Debug, thiserror::Error)]
+pub enum RegistrationError {
+    #[error("Merchant registration is disabled")]
+    Disabled,
+    #[error("Invalid invite code")]
+    InvalidInviteCode,
+}
+
+/// Заявка на регистрацию мерчанта. Комиссии, лимиты и остальные настройки задает
+/// администратор через `POST /admin/merchants`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegisterMerchantRequest {
+    /// ID мерчанта: буквы, цифры, `-` и `_`
+    pub id: String,
+    pub name: String,
+    /// Обязателен, если в `[registration]` заданы `invite_codes`
+    #[serde(default)]
+    pub invite_code: Option<String>,
+    #[serde(default)]
+    pub fee_wallet: Option<String>,
+    /// Принимаемые токены; пусто — все поддерживаемые
+    #[serde(default)]
+    pub tokens: Vec<String>,
+    #[serde(default)]
+    pub branding: Option<MerchantBranding>,
+    /// Сразу зарегистрировать webhook endpoint
+    #[serde(default)]
+    pub webhook: Option<RegisterWebhookRequest>,
+}
+
+/// Ответ регистрации: API ключ и секрет webhook показываются только здесь
+#[derive(Debug, Serialize)]
+pub struct RegisteredMerchant {
+    pub merchant: Tenant,
+    pub api_key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webhook: Option<RegisteredWebhook>,
+}
+
+/// Самостоятельная регистрация мерчантов (`[registration]`)
+#[derive(Clone)]
+pub struct RegistrationService {
+    payments: PaymentService,
+    webhooks: WebhookService,
+}
+
+impl RegistrationService {
+    pub fn new(payments: PaymentService) -> Self {
+        let webhooks = payments.webhooks().clone();
+        Self { payments, webhooks }
+    }
+
+    /// Создать мерчанта с новым API ключом и, если задан, webhook endpoint
+    pub async fn register(&self, request: RegisterMerchantRequest) -> anyhow::Result<RegisteredMerchant> {
+        let registration = &self.payments.config().registration;
+        if !registration.enabled {
+            return Err(RegistrationError::Disabled.into());
+        }
+        if !registration.invite_codes.is_empty() {
+            let code = request.invite_code.as_deref().unwrap_or_default();
+            if !registration.invite_codes.iter().any(|invite| constant_time_eq(invite.as_bytes(), code.as_bytes())) {
+                return Err(RegistrationError::InvalidInviteCode.into());
+            }
+        }
+
+        let mut errors = ValidationErrors::new();
+        if request.name.trim().is_empty() {
+            errors.add("name", "too_short", "Merchant name must not be empty");
+        }
+        if let Some(Err(webhook_errors)) = request.webhook.as_ref().map(Validate::validate) {
+            for error in webhook_errors.0 {
+                errors.add(&format!("webhook.{}", error.field), error.code, error.message);
+            }
+        }
+        errors.into_result()?;
+
+        let api_key = generate_api_key();
+        let merchant = MerchantConfig {
+            id: request.id,
+            name: request.name.trim().to_string(),
+            api_key: api_key.clone(),
+            api_keys: Vec::new(),
+            sandbox: false,
+            fee_amount: None,
+            fee_usd: None,
+            fee_token: None,
+            notifications: Vec::new(),
+            settlement: None,
+            limits: None,
+            fee_override: None,
+            receipt_nft: false,
+            loyalty: None,
+            fee_wallet: request.fee_wallet,
+            tokens: request.tokens,
+            branding: request.branding,
+        };
+        let tenant = self.payments.tenants().create(merchant).await?;
+
+        let webhook = match request.webhook {
+            Some(webhook) => {
+                let merchant = self.payments.config().find_merchant(&tenant.merchant.id)
+                    .ok_or_else(|| anyhow::anyhow!("Merchant {} disappeared during registration", tenant.merchant.id))?;
+                match self.webhooks.register(webhook, None, &merchant).await {
+                    Ok(registered) => Some(registered),
+                    Err(e) => {
+                        // Без ответа ключ мерчанта никто не узнает: регистрация откатывается
+                        self.payments.tenants().delete(&tenant.merchant.id).await?;
+                        return Err(e);
+                    }
+                }
+            }
+            None => None,
+        };
+
+        tracing::info!(merchant = %tenant.merchant.id, webhook = webhook.is_some(), "Merchant registered");
+        Ok(RegisteredMerchant { merchant: tenant, api_key, webhook })
+    }
+}
@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::StorageConfig;
+use crate::payment::Payment;
+
+/// Требование к свежести чтения списка платежей
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadConsistency {
+    /// Из основной таблицы: видны все записи на момент запроса
+    Strong,
+    /// Из реплики с отставанием не больше `storage.replica_max_lag_ms`
+    #[default]
+    Eventual,
+}
+
+/// Счетчики реплики чтения
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplicaStats {
+    pub enabled: bool,
+    pub max_lag_ms: u64,
+    /// Возраст текущей копии; `None` — копии еще нет
+    pub lag_ms: Option<u64>,
+    pub payments: usize,
+    /// Чтения, обслуженные репликой
+    pub reads: u64,
+    /// Копирования основной таблицы
+    pub refreshes: u64,
+}
+
+struct ReplicaCopy {
+    payments: Arc<Vec<Payment>>,
+    taken_at: Instant,
+}
+
+struct Inner {
+    max_lag: Duration,
+    copy: Mutex<Option<ReplicaCopy>>,
+    /// Обновляет копию один запрос, остальные ждут и читают ее
+    refresh: tokio::sync::Mutex<()>,
+    reads: AtomicU64,
+    refreshes: AtomicU64,
+}
+
+/// Реплика таблицы платежей для списков, выгрузок и дашбордов: общая копия, которая
+/// обновляется не чаще раза в `max_lag`. Тяжелые запросы не держат блокировку основной
+/// таблицы, которую ждут создание и верификация платежей
+#[derive(Clone)]
+pub struct PaymentReplica {
+    inner: Option<Arc<Inner>>,
+}
+
+impl std::fmt::Debug for PaymentReplica {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PaymentReplica").field("enabled", &self.inner.is_some()).finish()
+    }
+}
+
+impl Default for PaymentReplica {
+    fn default() -> Self {
+        Self::disabled()
+    }
+}
+
+impl PaymentReplica {
+    /// Реплика с отставанием не больше `max_lag`; нулевое — реплика выключена
+    pub fn new(max_lag: Duration) -> Self {
+        if max_lag.is_zero() {
+            return Self::disabled();
+        }
+        Self {
+            inner: Some(Arc::new(Inner {
+                max_lag,
+                copy: Mutex::new(None),
+                refresh: tokio::sync::Mutex::new(()),
+                reads: AtomicU64::new(0),
+                refreshes: AtomicU64::new(0),
+            })),
+        }
+    }
+
+    /// Без реплики: все чтения идут в основную таблицу
+    pub fn disabled() -> Self {
+        Self { inner: None }
+    }
+
+    pub fn from_config(config: &StorageConfig) -> Self {
+        Self::new(Duration::from_millis(config.replica_max_lag_ms))
+    }
+
+    fn copy(inner: &Inner) -> std::sync::MutexGuard<'_, Option<ReplicaCopy>> {
+        inner.copy.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Копия, отстающая не больше допустимого
+    pub fn get(&self) -> Option<Arc<Vec<Payment>>> {
+        let inner = self.inner.as_ref()?;
+        let payments = Self::copy(inner).as_ref()
+            .filter(|copy| copy.taken_at.elapsed() <= inner.max_lag)
+            .map(|copy| copy.payments.clone())?;
+        inner.reads.fetch_add(1, Ordering::Relaxed);
+        Some(payments)
+    }
+
+    /// Дождаться права обновить копию. Пока ждали, ее мог обновить другой запрос —
+    /// после получения стоит повторить [`PaymentReplica::get`]
+    pub async fn lock_refresh(&self) -> Option<tokio::sync::MutexGuard<'_, ()>> {
+        Some(self.inner.as_ref()?.refresh.lock().await)
+    }
+
+    /// Заменить копию свежим чтением основной таблицы
+    pub fn put(&self, payments: Arc<Vec<Payment>>) {
+        if let Some(inner) = &self.inner {
+            *Self::copy(inner) = Some(ReplicaCopy { payments, taken_at: Instant::now() });
+            inner.refreshes.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Сбросить копию (содержимое хранилища заменено целиком)
+    pub fn clear(&self) {
+        if let Some(inner) = &self.inner {
+            *Self::copy(inner) = None;
+        }
+    }
+
+    pub fn stats(&self) -> ReplicaStats {
+        let Some(inner) = &self.inner else {
+            return ReplicaStats { enabled: false, max_lag_ms: 0, lag_ms: None, payments: 0, reads: 0, refreshes: 0 };
+        };
+        let copy = Self::copy(inner);
+        ReplicaStats {
+            enabled: true,
+            max_lag_ms: inner.max_lag.as_millis() as u64,
+            lag_ms: copy.as_ref().map(|copy| copy.taken_at.elapsed().as_millis() as u64),
+            payments: copy.as_ref().map_or(0, |copy| copy.payments.len()),
+            reads: inner.reads.load(Ordering::Relaxed),
+            refreshes: inner.refreshes.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use chrono::{Duration, Utc};
+
+use crate::config::Config;
+use crate::storage::StorageService;
+
+/// Политика хранения платежей: архивация по сроку вместо удаления.
+/// Архивные платежи доступны как обычно; удаляются только через admin API
+#[derive(Clone)]
+pub struct RetentionService {
+    storage: StorageService,
+    config: Config,
+}
+
+impl RetentionService {
+    pub fn new(config: Config, storage: StorageService) -> Self {
+        Self { storage, config }
+    }
+
+    /// Архивировать платежи, срок хранения которых вышел. Возвращает число архивированных
+    pub async fn archive(&self) -> anyhow::Result<usize> {
+        let now = Utc::now();
+        let retention = &self.config.retention;
+        self.storage.archive_payments(
+            now - Duration::days(retention.completed_days as i64),
+            now - Duration::days(retention.expired_days as i64),
+        ).await
+    }
+
+    /// Удалить архивные платежи, пролежавшие в архиве не меньше `older_than_days`
+    pub async fn purge(&self, older_than_days: u32) -> anyhow::Result<usize> {
+        self.storage.purge_archived_payments(Utc::now() - Duration::days(older_than_days as i64)).await
+    }
+}
@@ -0,0 +1,869 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{json, Value};
+use futures::future::BoxFuture;
+use reqwest::header::{HeaderName, HeaderValue};
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClientConfig};
+use solana_client::rpc_config::RpcSimulateTransactionConfig;
+use solana_client::rpc_custom_error::JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY;
+use solana_client::rpc_request::{RpcError, RpcRequest};
+use solana_sdk::{
+    account::Account,
+    commitment_config::CommitmentConfig,
+    hash::Hash,
+    pubkey::Pubkey,
+    signature::Signature,
+    transaction::{Transaction, VersionedTransaction},
+};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use solana_rpc_client::http_sender::HttpSender;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::{Config, RpcEndpoint};
+use crate::deadline::{bounded, DeadlineError};
+use crate::error_reporting::{self, ErrorEvent, ErrorKind};
+use crate::explorer::Cluster;
+use crate::observability;
+
+/// Таймаут одного HTTP запроса к RPC
+const RPC_TIMEOUT_SECS: u64 = 10;
+
+/// Попыток получить blockhash на каждом RPC
+const BLOCKHASH_ATTEMPTS: usize = 2;
+
+/// Сколько секунд полученный blockhash отдается из кэша (blockhash действителен ~60 с;
+/// задача `blockhash` обновляет его заранее)
+const BLOCKHASH_CACHE_SECS: u64 = 10;
+
+/// Сбоев узла подряд, после которых он исключается из пула
+const BREAKER_FAILURE_THRESHOLD: u32 = 3;
+
+/// Первая пауза исключенного узла; каждая неудачная проба удваивает ее
+const BREAKER_BASE_BACKOFF_SECS: u64 = 5;
+
+/// Предельная пауза исключенного узла
+const BREAKER_MAX_BACKOFF_SECS: u64 = 300;
+
+/// Пробный запрос без результата дольше этого считается брошенным (вызывающий код
+/// отменил future) — узел получает новую пробу
+const BREAKER_PROBE_TIMEOUT_SECS: u64 = 60;
+
+/// Резервные публичные RPC mainnet для получения blockhash
+const PUBLIC_MAINNET_RPC_ENDPOINTS: &[&str] = &[
+    "https://api.mainnet-beta.solana.com",
+    "https://solana-api.projectserum.com",
+    "https://rpc.ankr.com/solana",
+];
+
+/// Транзакция, найденная в блокчейне
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransactionStatus {
+    pub slot: u64,
+    /// Unix timestamp блока, если узел его знает
+    pub block_time: Option<i64>,
+    /// Ошибка исполнения; `None` — транзакция успешна
+    pub error: Option<String>,
+    /// Изменения балансов, внесенные транзакцией
+    pub balance_changes: Vec<BalanceChange>,
+    /// Первый подписант: платит комиссию сети
+    pub fee_payer: Option<String>,
+}
+
+/// Изменение баланса владельца в базовых единицах (лампорты или единицы SPL токена)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BalanceChange {
+    /// Адрес кошелька (для SOL — сам аккаунт, для SPL — владелец токен-аккаунта)
+    pub owner: String,
+    /// Mint SPL токена; `None` — SOL
+    pub mint: Option<String>,
+    pub delta: i128,
+}
+
+impl TransactionStatus {
+    /// Сколько `owner` получил токена (`None` — SOL) в этой транзакции
+    pub fn received(&self, owner: &str, mint: Option<&str>) -> i128 {
+        self.balance_changes.iter()
+            .filter(|change| change.owner == owner && change.mint.as_deref() == mint)
+            .map(|change| change.delta)
+            .sum()
+    }
+}
+
+/// Результат симуляции транзакции
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SimulationResult {
+    /// Ошибка исполнения; `None` — транзакция прошла бы
+    pub error: Option<String>,
+    pub logs: Vec<String>,
+    pub units_consumed: Option<u64>,
+}
+
+/// Все обращения к Solana RPC. Сервисы получают реализацию через `Arc<dyn SolanaRpc>`,
+/// поэтому в тестах RPC подменяется [`MockSolanaRpc`]
+#[async_trait]
+pub trait SolanaRpc: Send + Sync {
+    async fn get_latest_blockhash(&self) -> anyhow::Result<Hash>;
+
+    /// Получить свежий blockhash в обход кэша (фоновое обновление)
+    async fn refresh_blockhash(&self) -> anyhow::Result<Hash> {
+        self.get_latest_blockhash().await
+    }
+
+    /// Аккаунт по адресу; `None` — аккаунт не существует
+    async fn get_account(&self, pubkey: &Pubkey) -> anyhow::Result<Option<Account>>;
+
+    /// Транзакция по подписи; `None` — не найдена (еще не подтверждена или не существует)
+    async fn get_transaction(&self, signature: &Signature) -> anyhow::Result<Option<TransactionStatus>>;
+
+    /// Транзакция по подписи с заданным commitment вместо `solana.verification_commitment`
+    async fn get_transaction_with_commitment(
+        &self,
+        signature: &Signature,
+        _commitment: CommitmentConfig,
+    ) -> anyhow::Result<Option<TransactionStatus>> {
+        self.get_transaction(signature).await
+    }
+
+    /// Успешные транзакции с участием адреса, новые первыми
+    async fn get_signatures_for_address(&self, address: &Pubkey) -> anyhow::Result<Vec<Signature>>;
+
+    /// Симулировать транзакцию без подписей (blockhash подменяется актуальным)
+    async fn simulate(&self, transaction: &Transaction) -> anyhow::Result<SimulationResult>;
+
+    /// Отправить подписанную транзакцию и дождаться подтверждения
+    async fn send_transaction(&self, transaction: &VersionedTransaction) -> anyhow::Result<Signature>;
+
+    /// Состояние и ошибки узлов пула (для admin API)
+    fn endpoint_stats(&self) -> Vec<RpcEndpointStats> {
+        Vec::new()
+    }
+}
+
+/// Состояние circuit breaker узла
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    /// Узел принимает запросы
+    Closed,
+    /// Узел исключен до `retry_at`
+    Open,
+    /// Пауза вышла, выполняется пробный запрос
+    HalfOpen,
+}
+
+/// Счетчики узла пула с момента запуска
+#[derive(Debug, Clone, Serialize)]
+pub struct RpcEndpointStats {
+    /// Адрес без ключа API
+    pub name: String,
+    pub state: BreakerState,
+    pub requests: u64,
+    pub failures: u64,
+    pub timeouts: u64,
+    /// Доля неудачных запросов
+    pub error_rate: f64,
+    pub consecutive_failures: u32,
+    /// Когда исключенный узел получит пробный запрос
+    pub retry_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+/// Бюджет запросов узла: не больше `limit` в секунду
+struct RateBudget {
+    limit: u32,
+    window: Mutex<(Instant, u32)>,
+}
+
+impl RateBudget {
+    fn new(limit: u32) -> Self {
+        Self { limit, window: Mutex::new((Instant::now(), 0)) }
+    }
+
+    /// Занять запрос; `Err` — бюджет исчерпан, через сколько он обновится
+    fn try_acquire(&self) -> Result<(), Duration> {
+        let mut window = self.window.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let elapsed = now.duration_since(window.0);
+        if elapsed >= Duration::from_secs(1) {
+            *window = (now, 0);
+        }
+        if window.1 >= self.limit {
+            return Err(Duration::from_secs(1).saturating_sub(elapsed));
+        }
+        window.1 += 1;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct BreakerInner {
+    consecutive_failures: u32,
+    /// Узел исключен до этого момента
+    open_until: Option<Instant>,
+    backoff: Duration,
+    /// Когда начат выполняющийся пробный запрос
+    probe_started: Option<Instant>,
+    requests: u64,
+    failures: u64,
+    timeouts: u64,
+    last_error: Option<String>,
+}
+
+/// Circuit breaker узла: после нескольких сбоев подряд узел исключается с экспоненциальной
+/// паузой, по ее истечении получает один пробный запрос (half-open)
+#[derive(Default)]
+struct CircuitBreaker {
+    inner: Mutex<BreakerInner>,
+}
+
+impl CircuitBreaker {
+    fn lock(&self) -> std::sync::MutexGuard<'_, BreakerInner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Можно ли отправить запрос на узел сейчас
+    fn allow(&self) -> bool {
+        let mut inner = self.lock();
+        let now = Instant::now();
+        let probing = inner.probe_started
+            .is_some_and(|started| now.duration_since(started) < Duration::from_secs(BREAKER_PROBE_TIMEOUT_SECS));
+        match inner.open_until {
+            None => true,
+            Some(until) if now < until || probing => false,
+            Some(_) => {
+                inner.probe_started = Some(now);
+                true
+            }
+        }
+    }
+
+    fn record_success(&self) {
+        let mut inner = self.lock();
+        inner.requests += 1;
+        inner.consecutive_failures = 0;
+        inner.open_until = None;
+        inner.backoff = Duration::ZERO;
+        inner.probe_started = None;
+    }
+
+    /// Запрос брошен по сроку или отмене до ответа узла: проба не состоялась
+    fn release_probe(&self) {
+        self.lock().probe_started = None;
+    }
+
+    /// Ответ узла получен, но это ошибка запроса, а не сбой узла
+    fn record_rejected(&self) {
+        let mut inner = self.lock();
+        inner.requests += 1;
+        inner.probe_started = None;
+        if inner.open_until.is_some() {
+            // Узел отвечает — проба удалась
+            inner.consecutive_failures = 0;
+            inner.open_until = None;
+            inner.backoff = Duration::ZERO;
+        }
+    }
+
+    /// Сбой узла. `Some(пауза)` — узел исключен
+    fn record_failure(&self, error: &anyhow::Error, timeout: bool) -> Option<Duration> {
+        let mut inner = self.lock();
+        inner.requests += 1;
+        inner.failures += 1;
+        inner.timeouts += u64::from(timeout);
+        inner.consecutive_failures += 1;
+        inner.last_error = Some(error.to_string());
+
+        let backoff = if inner.probe_started.is_some() {
+            (inner.backoff * 2).min(Duration::from_secs(BREAKER_MAX_BACKOFF_SECS))
+        } else if inner.open_until.is_none() && inner.consecutive_failures >= BREAKER_FAILURE_THRESHOLD {
+            Duration::from_secs(BREAKER_BASE_BACKOFF_SECS)
+        } else {
+            return None;
+        };
+        inner.probe_started = None;
+        inner.backoff = backoff;
+        inner.open_until = Some(Instant::now() + backoff);
+        Some(backoff)
+    }
+
+    fn stats(&self, name: &str) -> RpcEndpointStats {
+        let inner = self.lock();
+        let now = Instant::now();
+        let state = match inner.open_until {
+            None => BreakerState::Closed,
+            Some(until) if until > now && inner.probe_started.is_none() => BreakerState::Open,
+            Some(_) => BreakerState::HalfOpen,
+        };
+        RpcEndpointStats {
+            name: name.to_string(),
+            state,
+            requests: inner.requests,
+            failures: inner.failures,
+            timeouts: inner.timeouts,
+            error_rate: if inner.requests == 0 { 0.0 } else { inner.failures as f64 / inner.requests as f64 },
+            consecutive_failures: inner.consecutive_failures,
+            retry_at: inner.open_until
+                .filter(|_| state == BreakerState::Open)
+                .and_then(|until| chrono::Duration::from_std(until - now).ok())
+                .map(|wait| Utc::now() + wait),
+            last_error: inner.last_error.clone(),
+        }
+    }
+}
+
+/// Сбой узла (сеть, таймаут, HTTP статус), а не отказ в исполнении запроса.
+/// `Some(true)` — таймаут
+fn endpoint_fault(error: &anyhow::Error) -> Option<bool> {
+    match error.downcast_ref::<ClientError>().map(ClientError::kind) {
+        Some(ClientErrorKind::Reqwest(e)) => Some(e.is_timeout()),
+        Some(ClientErrorKind::Io(e)) => Some(e.kind() == std::io::ErrorKind::TimedOut),
+        // Транспортные ошибки, которые клиент передает строкой (например, запрос версии узла)
+        Some(ClientErrorKind::RpcError(RpcError::RpcRequestError(message))) => Some(message.contains("timed out")),
+        Some(ClientErrorKind::RpcError(RpcError::RpcResponseError { code: JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY, .. })) => Some(false),
+        _ => None,
+    }
+}
+
+/// Узел пула
+struct PoolEndpoint {
+    /// Адрес без ключа API — для логов
+    name: String,
+    client: RpcClient,
+    weight: u32,
+    budget: Option<RateBudget>,
+    breaker: CircuitBreaker,
+}
+
+impl PoolEndpoint {
+    fn connect(endpoint: &RpcEndpoint, commitment: CommitmentConfig) -> anyhow::Result<Self> {
+        let mut url = url::Url::parse(&endpoint.url)?;
+        let name = format!("{}{}", url.origin().ascii_serialization(), url.path().trim_end_matches('/'));
+
+        let mut headers = HttpSender::default_headers();
+        for (name, value) in &endpoint.headers {
+            headers.insert(HeaderName::from_bytes(name.as_bytes())?, HeaderValue::from_str(value)?);
+        }
+        match (&endpoint.api_key, &endpoint.api_key_header) {
+            (Some(key), Some(header)) => {
+                headers.insert(HeaderName::from_bytes(header.as_bytes())?, HeaderValue::from_str(key)?);
+            }
+            (Some(key), None) => {
+                url.query_pairs_mut().append_pair("api-key", key);
+            }
+            (None, _) => {}
+        }
+
+        let timeout = Duration::from_secs(RPC_TIMEOUT_SECS);
+        let http = reqwest::Client::builder()
+            .default_headers(headers)
+            .timeout(timeout)
+            .pool_idle_timeout(timeout)
+            .build()?;
+        Ok(Self {
+            name,
+            client: RpcClient::new_sender(
+                HttpSender::new_with_client(url, http),
+                RpcClientConfig::with_commitment(commitment),
+            ),
+            weight: endpoint.weight.max(1),
+            budget: endpoint.max_requests_per_sec.map(RateBudget::new),
+            breaker: CircuitBreaker::default(),
+        })
+    }
+
+    /// Запрос брошен по сроку или отмене запроса клиента — узел не виноват,
+    /// следующие узлы не пробуются
+    fn abandon(&self, method: &str, error: DeadlineError) -> anyhow::Error {
+        self.breaker.release_probe();
+        tracing::debug!(endpoint = %self.name, method, error = %error, "RPC request abandoned");
+        error.into()
+    }
+
+    /// Учесть результат запроса в circuit breaker
+    fn record<T>(&self, method: &str, result: &anyhow::Result<T>) {
+        let Err(e) = result else {
+            self.breaker.record_success();
+            return;
+        };
+        match endpoint_fault(e) {
+            Some(timeout) => {
+                if let Some(backoff) = self.breaker.record_failure(e, timeout) {
+                    tracing::warn!(
+                        endpoint = %self.name, method, backoff_secs = backoff.as_secs(), error = %e, "RPC endpoint ejected"
+                    );
+                    error_reporting::capture(
+                        ErrorEvent::new(ErrorKind::Rpc, format!("RPC endpoint ejected: {}", e))
+                            .tag("endpoint", &self.name)
+                            .tag("method", method)
+                            .extra("backoff_secs", backoff.as_secs()),
+                    );
+                }
+            }
+            None => self.breaker.record_rejected(),
+        }
+    }
+}
+
+/// Commitment для getTransaction и подписок на транзакции: `processed` они не
+/// поддерживают, поэтому все, кроме `finalized`, — `confirmed`
+pub fn transaction_commitment(level: &str) -> CommitmentConfig {
+    match CommitmentConfig::from_str(level) {
+        Ok(commitment) if commitment.is_finalized() => CommitmentConfig::finalized(),
+        _ => CommitmentConfig::confirmed(),
+    }
+}
+
+/// Пул JSON-RPC узлов из конфига: запросы распределяются по весам с учетом бюджетов
+/// запросов, при ошибке узла — следующий. Для mainnet blockhash при сбое всех узлов
+/// берется с публичных RPC
+pub struct HttpSolanaRpc {
+    endpoints: Vec<PoolEndpoint>,
+    fallbacks: Vec<PoolEndpoint>,
+    counter: AtomicUsize,
+    commitment: CommitmentConfig,
+    blockhash_commitment: CommitmentConfig,
+    verification_commitment: CommitmentConfig,
+    /// Последний полученный blockhash и время получения
+    blockhash: Mutex<Option<(Hash, Instant)>>,
+}
+
+impl HttpSolanaRpc {
+    pub fn new(config: &Config) -> anyhow::Result<Self> {
+        let commitment = CommitmentConfig::from_str(&config.solana.commitment)
+            .unwrap_or_else(|_| CommitmentConfig::confirmed());
+        let endpoints = config.solana.rpc_endpoints.iter()
+            .map(|endpoint| PoolEndpoint::connect(endpoint, commitment))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        // Blockhash другого кластера (devnet, локальный валидатор) невалиден
+        let fallbacks = if config.solana.cluster == Cluster::MainnetBeta {
+            PUBLIC_MAINNET_RPC_ENDPOINTS.iter()
+                .filter(|url| !config.solana.rpc_endpoints.iter().any(|endpoint| endpoint.url == **url))
+                .map(|url| PoolEndpoint::connect(&RpcEndpoint::new(url), commitment))
+                .collect::<anyhow::Result<Vec<_>>>()?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            endpoints,
+            fallbacks,
+            counter: AtomicUsize::new(0),
+            commitment,
+            blockhash_commitment: CommitmentConfig::from_str(&config.solana.blockhash_commitment)
+                .unwrap_or(commitment),
+            verification_commitment: transaction_commitment(&config.solana.verification_commitment),
+            blockhash: Mutex::new(None),
+        })
+    }
+
+    /// Узлы пула в порядке попыток: первый выбирается взвешенным round-robin, дальше по кругу
+    fn ordered(&self) -> impl Iterator<Item = &PoolEndpoint> {
+        let total: usize = self.endpoints.iter().map(|endpoint| endpoint.weight as usize).sum();
+        let mut slot = self.counter.fetch_add(1, Ordering::Relaxed) % total.max(1);
+        let first = self.endpoints.iter()
+            .position(|endpoint| {
+                let hit = slot < endpoint.weight as usize;
+                slot = slot.saturating_sub(endpoint.weight as usize);
+                hit
+            })
+            .unwrap_or(0);
+        self.endpoints.iter().cycle().skip(first).take(self.endpoints.len())
+    }
+
+    /// Выполнить запрос на первом доступном узле. Исключенные узлы и узлы с исчерпанным
+    /// бюджетом пропускаются; если исчерпан бюджет всех — ждем ближайшего обновления
+    async fn call<T>(
+        &self,
+        method: &str,
+        request: impl for<'a> Fn(&'a RpcClient) -> BoxFuture<'a, anyhow::Result<T>>,
+    ) -> anyhow::Result<T> {
+        loop {
+            let mut last_error = None;
+            let mut throttled: Option<Duration> = None;
+            let mut ejected = false;
+            for endpoint in self.ordered() {
+                if let Some(Err(wait)) = endpoint.budget.as_ref().map(RateBudget::try_acquire) {
+                    throttled = Some(throttled.map_or(wait, |shortest| shortest.min(wait)));
+                    continue;
+                }
+                if !endpoint.breaker.allow() {
+                    ejected = true;
+                    continue;
+                }
+                let started = Instant::now();
+                let result = bounded(request(&endpoint.client)).await
+                    .map_err(|e| endpoint.abandon(method, e))?;
+                let latency_ms = started.elapsed().as_millis() as u64;
+                endpoint.record(method, &result);
+                observability::record("rpc", || format!("{} {}", method, endpoint.name), started, result.as_ref().err());
+                match result {
+                    Ok(result) => {
+                        tracing::debug!(endpoint = %endpoint.name, method, latency_ms, "RPC request");
+                        return Ok(result);
+                    }
+                    Err(e) => {
+                        tracing::warn!(endpoint = %endpoint.name, method, latency_ms, error = %e, "RPC request failed");
+                        last_error = Some(e);
+                    }
+                }
+            }
+            match (last_error, throttled) {
+                (Some(e), _) => return Err(e),
+                (None, Some(wait)) => bounded(tokio::time::sleep(wait)).await?,
+                (None, None) if ejected => anyhow::bail!("All RPC endpoints are temporarily ejected after failures"),
+                (None, None) => anyhow::bail!("No RPC endpoints configured"),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl SolanaRpc for HttpSolanaRpc {
+    async fn get_latest_blockhash(&self) -> anyhow::Result<Hash> {
+        let cached = *self.blockhash.lock().unwrap_or_else(|e| e.into_inner());
+        match cached {
+            Some((blockhash, fetched_at)) if fetched_at.elapsed() < Duration::from_secs(BLOCKHASH_CACHE_SECS) => Ok(blockhash),
+            _ => self.refresh_blockhash().await,
+        }
+    }
+
+    async fn refresh_blockhash(&self) -> anyhow::Result<Hash> {
+        for endpoint in self.ordered().chain(&self.fallbacks) {
+            for attempt in 1..=BLOCKHASH_ATTEMPTS {
+                if let Some(Err(wait)) = endpoint.budget.as_ref().map(RateBudget::try_acquire) {
+                    tracing::debug!(
+                        endpoint = %endpoint.name, wait_ms = wait.as_millis() as u64, "RPC endpoint is over its request budget"
+                    );
+                    break;
+                }
+                if !endpoint.breaker.allow() {
+                    tracing::debug!(endpoint = %endpoint.name, "RPC endpoint is ejected, skipping");
+                    break;
+                }
+                let started = Instant::now();
+                let result = bounded(endpoint.client.get_latest_blockhash_with_commitment(self.blockhash_commitment)).await
+                    .map_err(|e| endpoint.abandon("getLatestBlockhash", e))?
+                    .map(|(blockhash, _)| blockhash)
+                    .map_err(anyhow::Error::from);
+                let latency_ms = started.elapsed().as_millis() as u64;
+                endpoint.record("getLatestBlockhash", &result);
+                match result {
+                    Ok(blockhash) => {
+                        *self.blockhash.lock().unwrap_or_else(|e| e.into_inner()) = Some((blockhash, Instant::now()));
+                        return Ok(blockhash);
+                    }
+                    Err(e) => tracing::warn!(
+                        endpoint = %endpoint.name, method = "getLatestBlockhash", attempt, latency_ms, error = %e,
+                        "RPC request failed"
+                    ),
+                }
+                if attempt < BLOCKHASH_ATTEMPTS {
+                    bounded(tokio::time::sleep(Duration::from_secs(1))).await?;
+                }
+            }
+        }
+        error_reporting::capture(
+            ErrorEvent::new(ErrorKind::Rpc, "All RPC endpoints failed to return a blockhash")
+                .tag("method", "getLatestBlockhash"),
+        );
+        anyhow::bail!("All RPC endpoints failed after retries")
+    }
+
+    async fn get_account(&self, pubkey: &Pubkey) -> anyhow::Result<Option<Account>> {
+        let (pubkey, commitment) = (*pubkey, self.commitment);
+        self.call("getAccountInfo", move |client| Box::pin(async move {
+            Ok(client.get_account_with_commitment(&pubkey, commitment).await?.value)
+        })).await
+    }
+
+    async fn get_transaction(&self, signature: &Signature) -> anyhow::Result<Option<TransactionStatus>> {
+        self.get_transaction_with_commitment(signature, self.verification_commitment).await
+    }
+
+    async fn get_transaction_with_commitment(
+        &self,
+        signature: &Signature,
+        commitment: CommitmentConfig,
+    ) -> anyhow::Result<Option<TransactionStatus>> {
+        // getTransaction не поддерживает processed
+        let commitment = if commitment.is_finalized() { "finalized" } else { "confirmed" };
+        let params = json!([signature.to_string(), {
+            "encoding": "json",
+            "commitment": commitment,
+            "maxSupportedTransactionVersion": 0,
+        }]);
+        let result: Option<Value> = self.call("getTransaction", move |client| {
+            let params = params.clone();
+            Box::pin(async move { Ok(client.send(RpcRequest::GetTransaction, params).await?) })
+        }).await?;
+
+        let Some(result) = result else {
+            return Ok(None);
+        };
+        let slot = result.get("slot").and_then(Value::as_u64)
+            .ok_or_else(|| anyhow::anyhow!("Invalid getTransaction response: missing slot"))?;
+        let meta = result.get("meta")
+            .ok_or_else(|| anyhow::anyhow!("Invalid getTransaction response: missing meta"))?;
+
+        Ok(Some(TransactionStatus {
+            slot,
+            block_time: result.get("blockTime").and_then(Value::as_i64),
+            error: meta.get("err").filter(|e| !e.is_null()).map(Value::to_string),
+            balance_changes: balance_changes(&result, meta),
+            fee_payer: result.pointer("/transaction/message/accountKeys/0").and_then(Value::as_str).map(str::to_string),
+        }))
+    }
+
+    async fn get_signatures_for_address(&self, address: &Pubkey) -> anyhow::Result<Vec<Signature>> {
+        // getSignaturesForAddress не поддерживает processed
+        let commitment = if self.commitment.is_finalized() {
+            CommitmentConfig::finalized()
+        } else {
+            CommitmentConfig::confirmed()
+        };
+        let address = *address;
+        let statuses = self.call("getSignaturesForAddress", move |client| {
+            let config = GetConfirmedSignaturesForAddress2Config { commitment: Some(commitment), ..Default::default() };
+            Box::pin(async move { Ok(client.get_signatures_for_address_with_config(&address, config).await?) })
+        }).await?;
+        Ok(statuses.into_iter()
+            .filter(|status| status.err.is_none())
+            .filter_map(|status| Signature::from_str(&status.signature).ok())
+            .collect())
+    }
+
+    async fn simulate(&self, transaction: &Transaction) -> anyhow::Result<SimulationResult> {
+        let config = RpcSimulateTransactionConfig {
+            sig_verify: false,
+            replace_recent_blockhash: true,
+            commitment: Some(self.commitment),
+            ..Default::default()
+        };
+        let result = self.call("simulateTransaction", move |client| {
+            let (transaction, config) = (transaction.clone(), config.clone());
+            Box::pin(async move { Ok(client.simulate_transaction_with_config(&transaction, config).await?.value) })
+        }).await?;
+
+        Ok(SimulationResult {
+            error: result.err.map(|e| e.to_string()),
+            logs: result.logs.unwrap_or_default(),
+            units_consumed: result.units_consumed,
+        })
+    }
+
+    async fn send_transaction(&self, transaction: &VersionedTransaction) -> anyhow::Result<Signature> {
+        // Повтор на другом узле дублирует отправку — подпись та же, сеть примет одну
+        self.call("sendTransaction", move |client| {
+            let transaction = transaction.clone();
+            Box::pin(async move { Ok(client.send_and_confirm_transaction(&transaction).await?) })
+        }).await
+    }
+
+    fn endpoint_stats(&self) -> Vec<RpcEndpointStats> {
+        self.endpoints.iter().chain(&self.fallbacks)
+            .map(|endpoint| endpoint.breaker.stats(&endpoint.name))
+            .collect()
+    }
+}
+
+/// Изменения SOL и SPL балансов из `meta` ответа getTransaction (encoding `json`)
+fn balance_changes(result: &Value, meta: &Value) -> Vec<BalanceChange> {
+    let strings = |value: Option<&Value>| -> Vec<String> {
+        value.and_then(Value::as_array)
+            .map(|items| items.iter().filter_map(Value::as_str).map(str::to_string).collect())
+            .unwrap_or_default()
+    };
+    let numbers = |key: &str| -> Vec<i128> {
+        meta.get(key).and_then(Value::as_array)
+            .map(|items| items.iter().filter_map(Value::as_u64).map(i128::from).collect())
+            .unwrap_or_default()
+    };
+
+    // Ключи v0 транзакции: статические, затем загруженные из lookup таблиц
+    let mut keys = strings(result.pointer("/transaction/message/accountKeys"));
+    keys.extend(strings(meta.pointer("/loadedAddresses/writable")));
+    keys.extend(strings(meta.pointer("/loadedAddresses/readonly")));
+
+    let mut changes: Vec<BalanceChange> = keys.iter()
+        .zip(numbers("preBalances").into_iter().zip(numbers("postBalances")))
+        .filter(|(_, (pre, post))| pre != post)
+        .map(|(key, (pre, post))| BalanceChange { owner: key.clone(), mint: None, delta: post - pre })
+        .collect();
+
+    let token_balances = |key: &str| -> Vec<(u64, String, String, i128)> {
+        meta.get(key).and_then(Value::as_array).into_iter().flatten()
+            .filter_map(|balance| Some((
+                balance.get("accountIndex")?.as_u64()?,
+                balance.get("owner")?.as_str()?.to_string(),
+                balance.get("mint")?.as_str()?.to_string(),
+                balance.pointer("/uiTokenAmount/amount")?.as_str()?.parse().ok()?,
+            )))
+            .collect()
+    };
+    let pre = token_balances("preTokenBalances");
+    let post = token_balances("postTokenBalances");
+    for (index, owner, mint, amount) in &post {
+        let before = pre.iter()
+            .find(|(i, ..)| i == index)
+            .map_or(0, |(.., amount)| *amount);
+        changes.push(BalanceChange { owner: owner.clone(), mint: Some(mint.clone()), delta: amount - before });
+    }
+    // Токен-аккаунт закрыт транзакцией — весь баланс ушел
+    for (index, owner, mint, amount) in &pre {
+        if !post.iter().any(|(i, ..)| i == index) {
+            changes.push(BalanceChange { owner: owner.clone(), mint: Some(mint.clone()), delta: -amount });
+        }
+    }
+
+    changes
+}
+
+/// Сбой, который [`MockSolanaRpc`] вернет на вызовы метода
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MockFailure {
+    /// Ответ не приходит — таймауты вызывающего кода должны сработать
+    Timeout,
+    /// Ответ узла не разбирается
+    Malformed,
+    /// Узел вернул ошибку
+    Error(String),
+}
+
+#[derive(Default)]
+struct MockState {
+    blockhash: Hash,
+    accounts: HashMap<Pubkey, Account>,
+    transactions: HashMap<Signature, TransactionStatus>,
+    signatures: HashMap<Pubkey, Vec<Signature>>,
+    simulation: SimulationResult,
+    failures: HashMap<&'static str, MockFailure>,
+    sent: Vec<VersionedTransaction>,
+    /// Подтвержденные, но еще не finalized транзакции
+    unfinalized: HashSet<Signature>,
+}
+
+/// RPC без сети для тестов: ответы задаются заранее, сбои внедряются через [`MockSolanaRpc::fail`].
+/// Неизвестный аккаунт или подпись — «не найдено»
+#[derive(Default)]
+pub struct MockSolanaRpc {
+    state: Mutex<MockState>,
+}
+
+impl MockSolanaRpc {
+    pub fn new() -> Self {
+        let mock = Self::default();
+        mock.state().blockhash = Hash::new_unique();
+        mock
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn set_blockhash(&self, blockhash: Hash) {
+        self.state().blockhash = blockhash;
+    }
+
+    pub fn set_account(&self, pubkey: Pubkey, account: Account) {
+        self.state().accounts.insert(pubkey, account);
+    }
+
+    pub fn set_transaction(&self, signature: Signature, status: TransactionStatus) {
+        self.state().transactions.insert(signature, status);
+    }
+
+    /// Транзакция подтверждена (`confirmed`), но еще не finalized: с `finalized` не находится
+    pub fn set_unfinalized(&self, signature: Signature, status: TransactionStatus) {
+        let mut state = self.state();
+        state.transactions.insert(signature, status);
+        state.unfinalized.insert(signature);
+    }
+
+    /// Транзакции с участием адреса (для `get_signatures_for_address`), новые первыми
+    pub fn set_signatures(&self, address: Pubkey, signatures: Vec<Signature>) {
+        self.state().signatures.insert(address, signatures);
+    }
+
+    pub fn set_simulation(&self, result: SimulationResult) {
+        self.state().simulation = result;
+    }
+
+    /// Сбой для последующих вызовов метода трейта (`"get_latest_blockhash"`, `"get_account"`, ...)
+    pub fn fail(&self, method: &'static str, failure: MockFailure) {
+        self.state().failures.insert(method, failure);
+    }
+
+    /// Вернуть нормальные ответы всех методов
+    pub fn clear_failures(&self) {
+        self.state().failures.clear();
+    }
+
+    /// Транзакции, отправленные через `send_transaction`
+    pub fn sent_transactions(&self) -> Vec<VersionedTransaction> {
+        self.state().sent.clone()
+    }
+
+    async fn check_failure(&self, method: &str) -> anyhow::Result<()> {
+        let failure = self.state().failures.get(method).cloned();
+        match failure {
+            None => Ok(()),
+            Some(MockFailure::Timeout) => std::future::pending().await,
+            Some(MockFailure::Malformed) => anyhow::bail!("Invalid {} response format", method),
+            Some(MockFailure::Error(message)) => anyhow::bail!("RPC error: {}", message),
+        }
+    }
+}
+
+#[async_trait]
+impl SolanaRpc for MockSolanaRpc {
+    async fn get_latest_blockhash(&self) -> anyhow::Result<Hash> {
+        self.check_failure("get_latest_blockhash").await?;
+        Ok(self.state().blockhash)
+    }
+
+    async fn get_account(&self, pubkey: &Pubkey) -> anyhow::Result<Option<Account>> {
+        self.check_failure("get_account").await?;
+        Ok(self.state().accounts.get(pubkey).cloned())
+    }
+
+    async fn get_transaction(&self, signature: &Signature) -> anyhow::Result<Option<TransactionStatus>> {
+        self.check_failure("get_transaction").await?;
+        Ok(self.state().transactions.get(signature).cloned())
+    }
+
+    async fn get_transaction_with_commitment(
+        &self,
+        signature: &Signature,
+        commitment: CommitmentConfig,
+    ) -> anyhow::Result<Option<TransactionStatus>> {
+        if commitment.is_finalized() && self.state().unfinalized.contains(signature) {
+            self.check_failure("get_transaction").await?;
+            return Ok(None);
+        }
+        self.get_transaction(signature).await
+    }
+
+    async fn get_signatures_for_address(&self, address: &Pubkey) -> anyhow::Result<Vec<Signature>> {
+        self.check_failure("get_signatures_for_address").await?;
+        Ok(self.state().signatures.get(address).cloned().unwrap_or_default())
+    }
+
+    async fn simulate(&self, _transaction: &Transaction) -> anyhow::Result<SimulationResult> {
+        self.check_failure("simulate").await?;
+        Ok(self.state().simulation.clone())
+    }
+
+    async fn send_transaction(&self, transaction: &VersionedTransaction) -> anyhow::Result<Signature> {
+        self.check_failure("send_transaction").await?;
+        let signature = transaction.signatures.first().copied().unwrap_or_default();
+        self.state().sent.push(transaction.clone());
+        Ok(signature)
+    }
+}
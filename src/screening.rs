@@ -0,0 +1,245 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::config::{Config, ScreeningAction};
+use crate::storage::StorageService;
+
+/// Таймаут запросов к внешнему сервису проверки
+const SCREENING_API_TIMEOUT_SECS: u64 = 5;
+
+/// Источник проверки адресов: локальный список, санкционный API и т.п.
+#[async_trait]
+pub trait AddressScreener: Send + Sync {
+    /// Имя источника для аудита
+    fn name(&self) -> &str;
+
+    /// Причина, по которой адрес нельзя принимать; `None` — адрес чист
+    async fn screen(&self, address: &str) -> anyhow::Result<Option<String>>;
+}
+
+/// Чья сторона платежа проверялась
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScreeningRole {
+    Recipient,
+    Payer,
+}
+
+/// Совпадение, с которым платеж пропущен (`screening.action = "flag"`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScreeningFlag {
+    pub role: ScreeningRole,
+    pub address: String,
+    pub screener: String,
+    pub reason: String,
+    pub flagged_at: DateTime<Utc>,
+}
+
+/// Совпадение при проверке (аудит)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScreeningRecord {
+    pub id: String,
+    pub payment_id: String,
+    pub role: ScreeningRole,
+    pub address: String,
+    pub screener: String,
+    pub reason: String,
+    pub action: ScreeningAction,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ScreeningError {
+    #[error("Address {address} is blocked by screening: {reason}")]
+    Blocked { address: String, reason: String },
+}
+
+/// Локальный denylist: адрес и необязательная причина в строке, `#` — комментарий
+pub struct DenylistScreener {
+    addresses: HashMap<String, String>,
+}
+
+impl DenylistScreener {
+    pub fn parse(contents: &str) -> Self {
+        let addresses = contents.lines()
+            .map(|line| line.split('#').next().unwrap_or_default().trim())
+            .filter(|line| !line.is_empty())
+            .map(|line| match line.split_once(char::is_whitespace) {
+                Some((address, reason)) => (address.to_string(), reason.trim().to_string()),
+                None => (line.to_string(), "Address is on the denylist".to_string()),
+            })
+            .collect();
+        Self { addresses }
+    }
+
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read screening denylist {}: {}", path, e))?;
+        Ok(Self::parse(&contents))
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+}
+
+#[async_trait]
+impl AddressScreener for DenylistScreener {
+    fn name(&self) -> &str {
+        "denylist"
+    }
+
+    async fn screen(&self, address: &str) -> anyhow::Result<Option<String>> {
+        Ok(self.addresses.get(address).cloned())
+    }
+}
+
+/// Chainalysis Sanctions API: `GET {api_url}/{address}`, совпадения — в `identifications`
+pub struct ChainalysisScreener {
+    client: reqwest::Client,
+    api_url: String,
+    api_key: String,
+}
+
+#[derive(Deserialize)]
+struct ChainalysisResponse {
+    #[serde(default)]
+    identifications: Vec<ChainalysisIdentification>,
+}
+
+#[derive(Deserialize)]
+struct ChainalysisIdentification {
+    category: Option<String>,
+    name: Option<String>,
+}
+
+impl ChainalysisScreener {
+    pub fn new(api_url: &str, api_key: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            api_url: api_url.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl AddressScreener for ChainalysisScreener {
+    fn name(&self) -> &str {
+        "chainalysis"
+    }
+
+    async fn screen(&self, address: &str) -> anyhow::Result<Option<String>> {
+        let response: ChainalysisResponse = self.client
+            .get(format!("{}/{}", self.api_url, address))
+            .header("X-API-Key", &self.api_key)
+            .header("Accept", "application/json")
+            .timeout(Duration::from_secs(SCREENING_API_TIMEOUT_SECS))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let matches: Vec<String> = response.identifications.into_iter()
+            .map(|id| match (id.category, id.name) {
+                (Some(category), Some(name)) => format!("{}: {}", category, name),
+                (category, name) => category.or(name).unwrap_or_else(|| "identified".to_string()),
+            })
+            .collect();
+        Ok((!matches.is_empty()).then(|| matches.join("; ")))
+    }
+}
+
+/// Проверка адресов сторон платежа по всем настроенным источникам.
+/// Совпадения пишутся в аудит; платеж отклоняется или помечается по `screening.action`
+#[derive(Clone)]
+pub struct ScreeningService {
+    screeners: Vec<Arc<dyn AddressScreener>>,
+    storage: StorageService,
+    config: Config,
+}
+
+impl ScreeningService {
+    /// Источники из конфигурации; denylist читается один раз при старте
+    pub fn from_config(config: Config, storage: StorageService) -> anyhow::Result<Self> {
+        let mut screeners: Vec<Arc<dyn AddressScreener>> = Vec::new();
+        if config.screening.enabled {
+            if let Some(path) = &config.screening.denylist_path {
+                let denylist = DenylistScreener::load(path)?;
+                tracing::info!(addresses = denylist.len(), "Screening denylist loaded");
+                screeners.push(Arc::new(denylist));
+            }
+            if let Some(api_key) = &config.screening.chainalysis_api_key {
+                screeners.push(Arc::new(ChainalysisScreener::new(&config.screening.chainalysis_api_url, api_key)));
+            }
+        }
+        Ok(Self { screeners, storage, config })
+    }
+
+    /// Сервис с заданными источниками (например, собственной реализацией `AddressScreener`)
+    pub fn with_screeners(config: Config, storage: StorageService, screeners: Vec<Arc<dyn AddressScreener>>) -> Self {
+        Self { screeners, storage, config }
+    }
+
+    /// Проверить адрес стороны платежа. `Err(ScreeningError::Blocked)` — платеж отклоняется,
+    /// `Some(flag)` — пропускается с пометкой
+    pub async fn check(
+        &self,
+        payment_id: &str,
+        role: ScreeningRole,
+        address: &str,
+    ) -> anyhow::Result<Option<ScreeningFlag>> {
+        for screener in &self.screeners {
+            let reason = match screener.screen(address).await {
+                Ok(Some(reason)) => reason,
+                Ok(None) => continue,
+                Err(e) if self.config.screening.fail_open => {
+                    tracing::warn!(address, screener = screener.name(), error = %e, "Screening failed, skipping");
+                    continue;
+                }
+                Err(e) => format!("Screening is unavailable: {}", e),
+            };
+
+            let action = self.config.screening.action;
+            let record = ScreeningRecord {
+                id: format!("screen_{}", Uuid::new_v4().simple()),
+                payment_id: payment_id.to_string(),
+                role,
+                address: address.to_string(),
+                screener: screener.name().to_string(),
+                reason: reason.clone(),
+                action,
+                created_at: Utc::now(),
+            };
+            self.storage.save_screening(&record).await?;
+            tracing::warn!(payment_id, ?role, address, %reason, "Screening hit");
+
+            return match action {
+                ScreeningAction::Reject => Err(ScreeningError::Blocked { address: record.address, reason }.into()),
+                ScreeningAction::Flag => Ok(Some(ScreeningFlag {
+                    role,
+                    address: record.address,
+                    screener: record.screener,
+                    reason,
+                    flagged_at: record.created_at,
+                })),
+            };
+        }
+        Ok(None)
+    }
+
+    /// Аудит совпадений, новые первыми; `payment_id` — только по одному платежу
+    pub async fn history(&self, payment_id: Option<&str>) -> anyhow::Result<Vec<ScreeningRecord>> {
+        self.storage.list_screenings(payment_id).await
+    }
+}
@@ -0,0 +1,286 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    instruction::Instruction,
+    message::{v0, VersionedMessage},
+    pubkey::Pubkey,
+    system_instruction,
+    transaction::VersionedTransaction,
+};
+use std::str::FromStr;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::config::{Config, MerchantSettlement, TokenConfig};
+use crate::display::to_base_units;
+use crate::payment::{Payment, PaymentStatus};
+use crate::price::WRAPPED_SOL_MINT;
+use crate::rpc::SolanaRpc;
+use crate::signer::ServerSigner;
+use crate::storage::StorageService;
+use crate::swap::SwapService;
+use crate::transaction::load_lookup_tables;
+
+/// Расчеты с мерчантами: выручка завершенного платежа конвертируется в токен расчетов
+/// мерчанта (своп Jupiter) и переводится на его адрес одной транзакцией hot wallet
+#[derive(Clone)]
+pub struct SettlementService {
+    rpc: Arc<dyn SolanaRpc>,
+    storage: StorageService,
+    signer: Option<ServerSigner>,
+    swaps: SwapService,
+    config: Config,
+}
+
+/// Попытка расчета по платежу (аудит)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettlementRecord {
+    pub id: String,
+    pub payment_id: String,
+    pub merchant_id: String,
+    /// Токен выручки (токен платежа)
+    pub from_token: String,
+    pub to_token: String,
+    pub to_address: String,
+    /// Выручка в токене платежа (сумма и чаевые)
+    pub amount_in: f64,
+    /// Минимум токена расчетов с учетом проскальзывания
+    pub amount_out_min: f64,
+    pub dry_run: bool,
+    pub status: SettlementStatus,
+    pub signature: Option<String>,
+    pub details: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SettlementStatus {
+    /// Dry run: расчет был бы выполнен
+    Planned,
+    Completed,
+    Failed,
+}
+
+impl SettlementService {
+    pub fn new(
+        config: Config,
+        rpc: Arc<dyn SolanaRpc>,
+        storage: StorageService,
+        signer: Option<ServerSigner>,
+    ) -> Self {
+        Self { rpc, storage, signer, swaps: SwapService::new(config.clone()), config }
+    }
+
+    /// Рассчитать все ожидающие платежи (или только посчитать при `dry_run`)
+    pub async fn run(&self, dry_run: bool) -> anyhow::Result<Vec<SettlementRecord>> {
+        let signer = self.signer.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Settlement requires server keypair (SERVER_KEYPAIR_PATH)"))?;
+
+        let mut records = Vec::new();
+        for (payment, target) in self.pending().await? {
+            let record = self.settle(&payment, &target, signer, dry_run).await;
+            self.storage.save_settlement(&record).await?;
+            records.push(record);
+        }
+        Ok(records)
+    }
+
+    /// История расчетов, новые первыми; `payment_id` — только по одному платежу
+    pub async fn history(&self, payment_id: Option<&str>) -> anyhow::Result<Vec<SettlementRecord>> {
+        self.storage.list_settlements(payment_id).await
+    }
+
+    /// Завершенные платежи мерчантов с настроенными расчетами, еще не рассчитанные
+    async fn pending(&self) -> anyhow::Result<Vec<(Payment, MerchantSettlement)>> {
+        let since = Utc::now() - Duration::hours(self.config.settlement.max_age_hours as i64);
+        let history = self.storage.list_settlements(None).await?;
+
+        let mut pending: Vec<_> = self.storage.get_all_payments().await?
+            .into_values()
+            .filter(|payment| matches!(payment.status, PaymentStatus::Completed) && !payment.sandbox)
+            .filter(|payment| payment.verified_at.is_some_and(|at| at >= since))
+            .filter_map(|payment| {
+                let merchant = self.config.find_merchant(payment.merchant_id.as_deref()?)?;
+                Some((payment, merchant.settlement.clone()?))
+            })
+            .filter(|(payment, _)| {
+                let attempts = history.iter().filter(|r| r.payment_id == payment.id && !r.dry_run);
+                let (mut failed, mut completed) = (0, false);
+                for record in attempts {
+                    match record.status {
+                        SettlementStatus::Completed => completed = true,
+                        SettlementStatus::Failed => failed += 1,
+                        SettlementStatus::Planned => {}
+                    }
+                }
+                !completed && failed < self.config.settlement.max_attempts
+            })
+            .collect();
+        pending.sort_by_key(|(payment, _)| payment.verified_at);
+        Ok(pending)
+    }
+
+    async fn settle(
+        &self,
+        payment: &Payment,
+        target: &MerchantSettlement,
+        signer: &ServerSigner,
+        dry_run: bool,
+    ) -> SettlementRecord {
+        let mut record = SettlementRecord {
+            id: format!("settle_{}", Uuid::new_v4().simple()),
+            payment_id: payment.id.clone(),
+            merchant_id: payment.merchant_id.clone().unwrap_or_default(),
+            from_token: payment.token.clone(),
+            to_token: target.token.clone(),
+            to_address: target.address.clone(),
+            amount_in: payment.amount + payment.tip_amount.unwrap_or(0.0),
+            amount_out_min: 0.0,
+            dry_run,
+            status: SettlementStatus::Failed,
+            signature: None,
+            details: String::new(),
+            created_at: Utc::now(),
+        };
+
+        let result = async {
+            let (instructions, lookup_tables, min_out) = self.instructions(payment, target, signer).await?;
+            record.amount_out_min = min_out;
+            if dry_run {
+                return Ok(None);
+            }
+
+            let hot_wallet = signer.pubkey();
+            let lookup_tables = load_lookup_tables(self.rpc.as_ref(), &lookup_tables).await?;
+            let blockhash = self.rpc.get_latest_blockhash().await?;
+            let message = v0::Message::try_compile(&hot_wallet, &instructions, &lookup_tables, blockhash)?;
+            let transaction = VersionedTransaction::try_new(VersionedMessage::V0(message), &[signer.keypair()])?;
+            self.rpc.send_transaction(&transaction).await.map(Some)
+        }.await;
+
+        match result {
+            Ok(None) => {
+                record.status = SettlementStatus::Planned;
+                record.details = format!(
+                    "Would settle {} {} as at least {} {} to {}",
+                    record.amount_in, record.from_token, record.amount_out_min, record.to_token, record.to_address
+                );
+            }
+            Ok(Some(signature)) => {
+                record.status = SettlementStatus::Completed;
+                record.signature = Some(signature.to_string());
+                record.details = format!(
+                    "Settled {} {} as at least {} {} to {}",
+                    record.amount_in, record.from_token, record.amount_out_min, record.to_token, record.to_address
+                );
+                tracing::info!(payment_id = %payment.id, %signature, merchant = %record.merchant_id, "Payment settled");
+            }
+            Err(e) => {
+                record.details = format!("Settlement failed: {}", e);
+                tracing::error!(payment_id = %payment.id, merchant = %record.merchant_id, error = %e, "Settlement failed");
+            }
+        }
+        record
+    }
+
+    /// Инструкции расчета, lookup таблицы свопа и минимум токена расчетов
+    async fn instructions(
+        &self,
+        payment: &Payment,
+        target: &MerchantSettlement,
+        signer: &ServerSigner,
+    ) -> anyhow::Result<(Vec<Instruction>, Vec<Pubkey>, f64)> {
+        let from = &self.token(&payment.token)?;
+        let to = &self.token(&target.token)?;
+        let hot_wallet = signer.pubkey();
+        let recipient = Pubkey::from_str(&payment.recipient)?;
+        let destination = Pubkey::from_str(&target.address)?;
+
+        // Выручка — сумма и чаевые, в базовых единицах как в переводах платежа
+        let scale = 10_f64.powi(from.decimals as i32);
+        let amount = payment.amount_base_units + payment.tip_amount.map_or(0, |tip| to_base_units(tip, from.decimals));
+
+        let from_mint = mint(from)?;
+        let to_mint = mint(to)?;
+        let mut instructions = Vec::new();
+
+        // Выручка не в hot wallet: забираем ее по delegate (для SOL delegate невозможен)
+        let delegated = recipient != hot_wallet;
+        if delegated && from.mint.is_none() {
+            anyhow::bail!("SOL proceeds can only be settled when the hot wallet {} is the payment recipient", hot_wallet);
+        }
+        let source = spl_associated_token_account::get_associated_token_address(&recipient, &from_mint);
+
+        if from_mint == to_mint {
+            instructions.extend(transfer(from, &from_mint, &hot_wallet, &source, delegated, &destination, amount)?);
+            return Ok((instructions, Vec::new(), amount as f64 / scale));
+        }
+
+        if delegated {
+            instructions.extend(transfer(from, &from_mint, &hot_wallet, &source, true, &hot_wallet, amount)?);
+        }
+
+        let quote = self.swaps.quote_exact_in(&from_mint, &to_mint, amount).await?;
+        let to_scale = 10_f64.powi(to.decimals as i32);
+        let min_out = quote.min_out_amount as f64 / to_scale;
+
+        if to.mint.is_some() {
+            // Результат свопа сразу на токен-аккаунт адреса расчетов
+            let destination_ata = spl_associated_token_account::get_associated_token_address(&destination, &to_mint);
+            instructions.push(
+                spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+                    &hot_wallet, &destination, &to_mint, &spl_token::ID,
+                ),
+            );
+            let swap = self.swaps.swap_instructions(&quote, &hot_wallet, Some(&destination_ata)).await?;
+            instructions.extend(swap.instructions);
+            Ok((instructions, swap.lookup_tables, min_out))
+        } else {
+            // SOL разворачивается в hot wallet — переводим гарантированный минимум
+            let swap = self.swaps.swap_instructions(&quote, &hot_wallet, None).await?;
+            instructions.extend(swap.instructions);
+            instructions.push(system_instruction::transfer(&hot_wallet, &destination, quote.min_out_amount));
+            Ok((instructions, swap.lookup_tables, min_out))
+        }
+    }
+
+    fn token(&self, symbol: &str) -> anyhow::Result<TokenConfig> {
+        self.config.get_token_config(symbol)
+            .ok_or_else(|| anyhow::anyhow!("Token {} not supported", symbol))
+    }
+}
+
+pub(crate) fn mint(token: &TokenConfig) -> anyhow::Result<Pubkey> {
+    Ok(Pubkey::from_str(token.mint.as_deref().unwrap_or(WRAPPED_SOL_MINT))?)
+}
+
+/// Перевод `amount` от hot wallet (или по delegate с `source`) владельцу `to`
+pub(crate) fn transfer(
+    token: &TokenConfig,
+    mint: &Pubkey,
+    hot_wallet: &Pubkey,
+    source: &Pubkey,
+    delegated: bool,
+    to: &Pubkey,
+    amount: u64,
+) -> anyhow::Result<Vec<Instruction>> {
+    if token.mint.is_none() {
+        return Ok(vec![system_instruction::transfer(hot_wallet, to, amount)]);
+    }
+
+    let from = if delegated {
+        *source
+    } else {
+        spl_associated_token_account::get_associated_token_address(hot_wallet, mint)
+    };
+    let to_ata = spl_associated_token_account::get_associated_token_address(to, mint);
+    Ok(vec![
+        spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+            hot_wallet, to, mint, &spl_token::ID,
+        ),
+        spl_token::instruction::transfer_checked(
+            &spl_token::ID, &from, mint, &to_ata, hot_wallet, &[], amount, token.decimals,
+        )?,
+    ])
+}
@@ -0,0 +1,44 @@
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{read_keypair_file, Keypair},
+    signer::Signer,
+};
+use std::sync::Arc;
+
+/// Ключ сервера (hot wallet) для операций, которые сервер подписывает сам
+#[derive(Clone)]
+pub struct ServerSigner {
+    keypair: Arc<Keypair>,
+}
+
+impl std::fmt::Debug for ServerSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Секретный ключ никогда не попадает в логи
+        f.debug_struct("ServerSigner").field("pubkey", &self.pubkey()).finish()
+    }
+}
+
+impl ServerSigner {
+    /// Загрузить keypair из JSON файла в формате solana-keygen
+    pub fn from_file(path: &str) -> anyhow::Result<Self> {
+        let keypair = read_keypair_file(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read server keypair {}: {}", path, e))?;
+
+        tracing::info!(pubkey = %keypair.pubkey(), "Server signer loaded");
+
+        Ok(Self { keypair: Arc::new(keypair) })
+    }
+
+    /// Загрузить ключ, если путь указан в конфигурации
+    pub fn load(path: Option<&str>) -> anyhow::Result<Option<Self>> {
+        path.map(Self::from_file).transpose()
+    }
+
+    pub fn pubkey(&self) -> Pubkey {
+        self.keypair.pubkey()
+    }
+
+    pub fn keypair(&self) -> &Keypair {
+        &self.keypair
+    }
+}
@@ -0,0 +1,55 @@
+use std::path::{Path, PathBuf};
+
+use crate::storage::{StorageService, StorageSnapshot};
+
+/// Снимки in-memory хранилища на диск: запись по задаче `storage_snapshot` и при
+/// остановке, восстановление при старте
+#[derive(Clone)]
+pub struct SnapshotService {
+    storage: StorageService,
+    path: Option<PathBuf>,
+}
+
+impl SnapshotService {
+    pub fn new(storage: StorageService, path: Option<&str>) -> Self {
+        Self { storage, path: path.map(PathBuf::from) }
+    }
+
+    /// Записать снимок в `storage.snapshot_path`. Файл заменяется атомарно: сначала
+    /// пишется `<path>.tmp`, затем переименовывается. Возвращает число платежей
+    pub async fn write(&self) -> anyhow::Result<usize> {
+        let Some(path) = &self.path else {
+            return Ok(0);
+        };
+        let snapshot = self.storage.snapshot().await;
+        let payments = snapshot.payments.len();
+        let body = serde_json::to_vec(&snapshot)?;
+        let tmp = tmp_path(path);
+        tokio::fs::write(&tmp, body).await?;
+        tokio::fs::rename(&tmp, path).await?;
+        tracing::debug!(path = %path.display(), payments, "Storage snapshot written");
+        Ok(payments)
+    }
+
+    /// Восстановить хранилище из файла снимка, если он есть. `false` — снимка нет
+    pub async fn load(&self) -> anyhow::Result<bool> {
+        let Some(path) = &self.path else {
+            return Ok(false);
+        };
+        let body = match tokio::fs::read(path).await {
+            Ok(body) => body,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        let snapshot: StorageSnapshot = serde_json::from_slice(&body)?;
+        self.storage.restore(snapshot).await?;
+        tracing::info!(path = %path.display(), "Storage loaded from snapshot");
+        Ok(true)
+    }
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    PathBuf::from(tmp)
+}
@@ -0,0 +1,85 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{message::Message, pubkey::Pubkey, rent::Rent};
+
+use crate::compute_budget::ComputeBudget;
+use crate::config::Config;
+use crate::payment::Payment;
+use crate::signer::ServerSigner;
+use crate::storage::StorageService;
+
+/// Комиссия сети за одну подпись
+pub const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+
+/// Размер SPL токен-аккаунта (rent создаваемых ATA)
+pub const TOKEN_ACCOUNT_LEN: usize = 165;
+
+/// Gasless платежи: hot wallet — fee payer транзакции платежа и плательщик rent
+/// создаваемых токен-аккаунтов. Расход ограничен на платеж и за сутки
+#[derive(Clone)]
+pub struct SponsorshipService {
+    storage: StorageService,
+    signer: Option<ServerSigner>,
+    config: Config,
+}
+
+/// Резерв расхода hot wallet на транзакцию платежа (максимум — ATA могут уже существовать)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sponsorship {
+    pub payment_id: String,
+    pub lamports: u64,
+    pub reserved_at: DateTime<Utc>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SponsorshipError {
+    #[error("Sponsoring this transaction costs up to {required} lamports, over the per-payment cap of {cap}")]
+    PaymentCapExceeded { required: u64, cap: u64 },
+    #[error("Daily sponsorship limit reached, network fees cannot be covered right now")]
+    DailyLimitReached,
+}
+
+impl SponsorshipService {
+    pub fn new(config: Config, storage: StorageService, signer: Option<ServerSigner>) -> Self {
+        Self { storage, signer, config }
+    }
+
+    /// Fee payer транзакции платежа, если она спонсируется: режим включен и платеж
+    /// в SPL токене (для оплаты в SOL плательщик все равно должен иметь SOL)
+    pub fn fee_payer(&self, payment: &Payment) -> Option<Pubkey> {
+        let signer = self.signer.as_ref()?;
+        (self.config.sponsorship.enabled && payment.token != "SOL").then(|| signer.pubkey())
+    }
+
+    /// Hot wallet, подписывающий транзакции как fee payer
+    pub fn signer(&self) -> anyhow::Result<&ServerSigner> {
+        self.signer.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Sponsorship requires server keypair (SERVER_KEYPAIR_PATH)"))
+    }
+
+    /// Зарезервировать расход на транзакцию платежа. Пересборка транзакции
+    /// заменяет резерв платежа, а не добавляет новый
+    pub async fn reserve(&self, payment_id: &str, lamports: u64) -> anyhow::Result<()> {
+        let cap = self.config.sponsorship.max_lamports_per_payment;
+        if lamports > cap {
+            return Err(SponsorshipError::PaymentCapExceeded { required: lamports, cap }.into());
+        }
+        self.storage.reserve_sponsorship(
+            &Sponsorship { payment_id: payment_id.to_string(), lamports, reserved_at: Utc::now() },
+            Utc::now() - Duration::hours(24),
+            self.config.sponsorship.daily_limit_lamports,
+        ).await
+    }
+}
+
+/// Максимальный расход fee payer на сообщение: подписи, priority fee и rent создаваемых ATA
+pub fn max_cost(message: &Message, compute_budget: Option<&ComputeBudget>) -> u64 {
+    let ata_program = spl_associated_token_account::id();
+    let created_accounts = message.instructions.iter()
+        .filter(|instruction| message.account_keys.get(instruction.program_id_index as usize) == Some(&ata_program))
+        .count() as u64;
+
+    LAMPORTS_PER_SIGNATURE * message.header.num_required_signatures as u64
+        + compute_budget.map_or(0, ComputeBudget::priority_fee_lamports)
+        + Rent::default().minimum_balance(TOKEN_ACCOUNT_LEN) * created_accounts
+}
@@ -0,0 +1,167 @@
+use bytes::Bytes;
+use chrono::Utc;
+use futures::Stream;
+use serde::Serialize;
+use std::convert::Infallible;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::payment::{Payment, PaymentService, PaymentStatus};
+
+/// Интервал комментариев keep-alive, чтобы прокси не закрывали простаивающий поток
+const KEEP_ALIVE_SECS: u64 = 15;
+
+/// Максимальное ожидание long-poll запроса: прокси обычно рвут ответ через 60 секунд
+pub const MAX_WAIT_SECS: u64 = 55;
+
+/// Событие `expiring_soon`: отсчет по часам сервера
+#[derive(Serialize)]
+struct ExpiringSoon<'a> {
+    id: &'a str,
+    expires_at: chrono::DateTime<Utc>,
+    expires_in_seconds: i64,
+    server_time: chrono::DateTime<Utc>,
+}
+
+/// Событие `redirect`: куда отправить браузер покупателя
+#[derive(Serialize)]
+struct Redirect<'a> {
+    id: &'a str,
+    status: PaymentStatus,
+    url: &'a str,
+}
+
+fn event(name: &str, data: &impl Serialize) -> Result<Bytes, Infallible> {
+    let data = serde_json::to_string(data).unwrap_or_else(|_| "null".to_string());
+    Ok(Bytes::from(format!("event: {}\ndata: {}\n\n", name, data)))
+}
+
+/// Событие `payment`: поток открыт любому, кто знает ID, — ссылки без токена `?t=`
+fn payment_event(service: &PaymentService, payment: &Payment) -> Result<Bytes, Infallible> {
+    event("payment", &service.without_request_token(payment.clone()))
+}
+
+/// Server-Sent Events платежа для страницы оплаты:
+/// `payment` — состояние при подключении и после каждой смены статуса,
+/// `expiring_soon` — один раз за `server.expiring_soon_secs` до истечения,
+/// `redirect` — последним событием, если мерчант задал `success_url`/`cancel_url`.
+/// Когда срок выходит без оплаты, платеж помечается истекшим. Поток закрывается
+/// на платеже в конечном статусе. `None` — платеж не найден
+pub async fn payment_events(
+    service: PaymentService,
+    payment_id: &str,
+) -> anyhow::Result<Option<impl Stream<Item = Result<Bytes, Infallible>>>> {
+    // Подписываемся до чтения текущего состояния, чтобы не пропустить изменение между ними
+    let mut updates = service.storage().subscribe_payments();
+    let Some(mut payment) = service.get_payment(payment_id).await? else {
+        return Ok(None);
+    };
+    let threshold = Duration::from_secs(service.config().server.expiring_soon_secs);
+
+    Ok(Some(async_stream::stream! {
+        yield payment_event(&service, &payment);
+
+        let mut warned = threshold.is_zero();
+        let mut keep_alive = tokio::time::interval(Duration::from_secs(KEEP_ALIVE_SECS));
+        keep_alive.tick().await;
+
+        while payment.status.is_open() {
+            let remaining = (payment.expires_at - Utc::now()).to_std().unwrap_or_default();
+            let warn_in = (!warned).then(|| remaining.saturating_sub(threshold));
+
+            tokio::select! {
+                update = updates.recv() => {
+                    let updated = match update {
+                        Ok(updated) if updated.id == payment.id => updated,
+                        Ok(_) => continue,
+                        // Пропустили часть обновлений — перечитываем состояние
+                        Err(RecvError::Lagged(_)) => match service.get_payment(&payment.id).await {
+                            Ok(Some(updated)) => updated,
+                            _ => break,
+                        },
+                        Err(RecvError::Closed) => break,
+                    };
+                    let changed = updated.status != payment.status;
+                    payment = updated;
+                    if changed {
+                        yield payment_event(&service, &payment);
+                    }
+                }
+                _ = tokio::time::sleep(warn_in.unwrap_or_default()), if warn_in.is_some() => {
+                    warned = true;
+                    payment.refresh_countdown();
+                    yield event("expiring_soon", &ExpiringSoon {
+                        id: &payment.id,
+                        expires_at: payment.expires_at,
+                        expires_in_seconds: payment.expires_in_seconds,
+                        server_time: payment.server_time,
+                    });
+                }
+                _ = tokio::time::sleep(remaining) => {
+                    // Смена статуса придет через подписку; если ее не будет — завершим сами
+                    match service.expire_if_due(&payment.id).await {
+                        Ok(Some(current)) if !current.status.is_open() => {
+                            payment = current;
+                            yield payment_event(&service, &payment);
+                        }
+                        Ok(Some(_)) => {}
+                        Ok(None) => break,
+                        Err(e) => {
+                            tracing::warn!(payment_id = %payment.id, error = %e, "Failed to expire payment");
+                            break;
+                        }
+                    }
+                }
+                _ = keep_alive.tick() => {
+                    yield Ok(Bytes::from_static(b": keep-alive\n\n"));
+                }
+            }
+        }
+
+        if let Some(url) = &payment.redirect_url {
+            yield event("redirect", &Redirect { id: &payment.id, status: payment.status, url });
+        }
+    }))
+}
+
+/// Long-poll: дождаться, пока статус платежа станет отличным от `seen`
+/// (по умолчанию — статус на момент запроса), или выйдет `timeout`. Возвращает текущее
+/// состояние в любом случае (ссылки без токена `?t=`); открытый платеж с истекшим
+/// сроком помечается истекшим. `None` — платеж не найден
+pub async fn wait_for_status_change(
+    service: &PaymentService,
+    payment_id: &str,
+    seen: Option<PaymentStatus>,
+    timeout: Duration,
+) -> anyhow::Result<Option<Payment>> {
+    // Подписываемся до чтения текущего состояния, чтобы не пропустить изменение между ними
+    let mut updates = service.storage().subscribe_payments();
+    let Some(mut payment) = service.get_payment(payment_id).await? else {
+        return Ok(None);
+    };
+    let seen = seen.unwrap_or(payment.status);
+    let deadline = tokio::time::Instant::now() + timeout;
+
+    while payment.status == seen && payment.status.is_open() {
+        let remaining = (payment.expires_at - Utc::now()).to_std().unwrap_or_default();
+        tokio::select! {
+            update = updates.recv() => match update {
+                Ok(updated) if updated.id == payment.id => payment = updated,
+                Ok(_) => {}
+                // Пропустили часть обновлений — перечитываем состояние
+                Err(RecvError::Lagged(_)) => match service.get_payment(&payment.id).await? {
+                    Some(updated) => payment = updated,
+                    None => return Ok(None),
+                },
+                Err(RecvError::Closed) => break,
+            },
+            _ = tokio::time::sleep(remaining) => match service.expire_if_due(&payment.id).await? {
+                Some(current) => payment = current,
+                None => return Ok(None),
+            },
+            _ = tokio::time::sleep_until(deadline) => break,
+        }
+    }
+    payment.refresh_countdown();
+    Ok(Some(service.without_request_token(payment)))
+}
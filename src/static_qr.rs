@@ -0,0 +1,228 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::config::MerchantConfig;
+use crate::payment::{CreatePaymentRequest, Payment, PaymentService, MAX_PAYMENT_AMOUNT};
+use crate::qr::QrService;
+use crate::storage::StorageService;
+use crate::validation::{Validate, ValidationErrors};
+
+/// Сколько последних оплат хранится у статического QR
+pub const MAX_RECENT_SCANS: usize = 50;
+
+/// Многоразовый QR мерчанта без фиксированной суммы (копилка, чаевые, пожертвования).
+/// Сумма выбирается сервером при каждом сканировании, на каждое сканирование — отдельный платеж
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StaticQr {
+    pub id: String,
+    pub merchant_id: String,
+    pub recipient: String,
+    pub token: String,
+    pub label: String,
+    /// Сумма по умолчанию, если в URL сканирования нет `amount`
+    pub default_amount: Option<f64>,
+    pub min_amount: Option<f64>,
+    pub max_amount: Option<f64>,
+    pub url: String,
+    pub qr_code: String,
+    pub created_at: DateTime<Utc>,
+    /// ID платежей, созданных сканированиями, новые первыми
+    pub payments: Vec<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum StaticQrError {
+    #[error("Static QR not found")]
+    NotFound,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CreateStaticQrRequest {
+    pub recipient: String,
+    pub token: String,
+    pub label: Option<String>,
+    pub default_amount: Option<f64>,
+    pub min_amount: Option<f64>,
+    pub max_amount: Option<f64>,
+}
+
+impl Validate for CreateStaticQrRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        errors.check_pubkey("recipient", &self.recipient);
+        if self.token.is_empty() {
+            errors.add("token", "required", "Token is required");
+        }
+
+        for (field, value) in [
+            ("default_amount", self.default_amount),
+            ("min_amount", self.min_amount),
+            ("max_amount", self.max_amount),
+        ] {
+            if let Some(value) = value {
+                if !value.is_finite() || value <= 0.0 || value > MAX_PAYMENT_AMOUNT {
+                    errors.add(field, "out_of_range", format!("Amount must be in (0, {}], got: {}", MAX_PAYMENT_AMOUNT, value));
+                }
+            }
+        }
+        if let (Some(min), Some(max)) = (self.min_amount, self.max_amount) {
+            if min > max {
+                errors.add("min_amount", "out_of_range", "min_amount must not exceed max_amount");
+            }
+        }
+        if let Some(amount) = self.default_amount {
+            if self.min_amount.is_some_and(|min| amount < min) || self.max_amount.is_some_and(|max| amount > max) {
+                errors.add("default_amount", "out_of_range", "default_amount must be within min_amount..max_amount");
+            }
+        }
+
+        errors.into_result()
+    }
+}
+
+#[derive(Clone)]
+pub struct StaticQrService {
+    payments: PaymentService,
+    storage: StorageService,
+    qr_service: QrService,
+}
+
+impl StaticQrService {
+    pub fn new(payments: PaymentService) -> Self {
+        let storage = payments.storage().clone();
+        Self { payments, storage, qr_service: QrService::new() }
+    }
+
+    /// Создать статический QR для мерчанта
+    pub async fn create(
+        &self,
+        request: CreateStaticQrRequest,
+        merchant: &MerchantConfig,
+    ) -> anyhow::Result<StaticQr> {
+        request.validate()?;
+
+        let config = self.payments.config();
+        if !config.is_token_supported(&request.token) {
+            let mut errors = ValidationErrors::new();
+            errors.add(
+                "token",
+                "unsupported",
+                format!(
+                    "Token {} not supported. Supported tokens: {}",
+                    request.token,
+                    config.get_supported_tokens().join(", ")
+                ),
+            );
+            return Err(errors.into());
+        }
+        self.payments.check_recipient(&request.recipient).await?;
+        self.payments.ensure_recipient_verified(&request.recipient).await?;
+
+        let id = format!("sqr_{}", Uuid::new_v4().simple());
+        let url = format!("solana:{}/api/static/{}/transaction", config.base_url(), id);
+        let qr_code = self.qr_service.generate_qr_code(&url)?;
+
+        let static_qr = StaticQr {
+            id,
+            merchant_id: merchant.id.clone(),
+            recipient: request.recipient,
+            label: request.label.unwrap_or_else(|| merchant.name.clone()),
+            token: request.token,
+            default_amount: request.default_amount,
+            min_amount: request.min_amount,
+            max_amount: request.max_amount,
+            url,
+            qr_code,
+            created_at: Utc::now(),
+            payments: Vec::new(),
+        };
+        self.storage.save_static_qr(&static_qr).await?;
+
+        tracing::info!(static_qr_id = %static_qr.id, merchant = %merchant.id, "Static QR created");
+
+        Ok(static_qr)
+    }
+
+    /// Статический QR (публичные данные для GET transaction request)
+    pub async fn get(&self, id: &str) -> anyhow::Result<StaticQr> {
+        self.storage.get_static_qr(id).await?
+            .ok_or_else(|| StaticQrError::NotFound.into())
+    }
+
+    /// Статический QR мерчанта с последними оплатами
+    pub async fn get_for_merchant(
+        &self,
+        id: &str,
+        merchant: &MerchantConfig,
+    ) -> anyhow::Result<(StaticQr, Vec<Payment>)> {
+        let static_qr = self.get(id).await?;
+        if static_qr.merchant_id != merchant.id {
+            return Err(StaticQrError::NotFound.into());
+        }
+
+        let mut payments = Vec::with_capacity(static_qr.payments.len());
+        for payment_id in &static_qr.payments {
+            if let Some(payment) = self.storage.get_payment(payment_id).await? {
+                payments.push(payment);
+            }
+        }
+
+        Ok((static_qr, payments))
+    }
+
+    /// Сканирование: выбрать сумму и выпустить отдельный платеж
+    pub async fn scan(&self, id: &str, amount: Option<f64>, locale: Option<String>) -> anyhow::Result<Payment> {
+        let static_qr = self.get(id).await?;
+
+        let amount = amount.or(static_qr.default_amount).ok_or_else(|| {
+            let mut errors = ValidationErrors::new();
+            errors.add("amount", "required", "This QR has no default amount, pass ?amount=");
+            errors
+        })?;
+
+        let mut errors = ValidationErrors::new();
+        if let Some(min) = static_qr.min_amount.filter(|min| amount < *min) {
+            errors.add("amount", "too_small", format!("Amount must be at least {}", min));
+        }
+        if let Some(max) = static_qr.max_amount.filter(|max| amount > *max) {
+            errors.add("amount", "too_large", format!("Amount must be at most {}", max));
+        }
+        errors.into_result()?;
+
+        // Настройки мерчанта могли измениться после создания QR
+        let config = self.payments.config();
+        let merchant = config.find_merchant(&static_qr.merchant_id);
+
+        let payment = self.payments.create_session_payment(
+            CreatePaymentRequest {
+                recipient: static_qr.recipient.clone(),
+                amount,
+                amount_base_units: None,
+                token: static_qr.token.clone(),
+                label: Some(static_qr.label.clone()),
+                message: None,
+                memo: None,
+                tip_presets_bps: None,
+                locale,
+                metadata: None,
+                fee_amount: None,
+                fee_token: None,
+                success_url: None,
+                cancel_url: None,
+                discount_code: None,
+                redeem_points: None,
+                allowed_payers: None,
+                hold: false,
+                hold_secs: None,
+                amount_range: None,
+            },
+            merchant.as_deref(),
+        ).await?;
+
+        self.storage.push_static_qr_payment(id, &payment.id, MAX_RECENT_SCANS).await?;
+
+        Ok(payment)
+    }
+}
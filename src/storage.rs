@@ -1,70 +1,1226 @@
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use tokio::sync::RwLock;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 
-use crate::payment::Payment;
+use crate::cache::{CacheStats, PaymentCache};
+use crate::observability;
+use crate::replica::{PaymentReplica, ReadConsistency, ReplicaStats};
+
+use crate::compute_budget::ComputeBudget;
+use crate::config::{MerchantApiKey, MerchantConfig};
+use crate::discounts::{DiscountCode, DiscountError, DiscountRedemption};
+use crate::disputes::{Dispute, DisputeStatus};
+use crate::loyalty::{LoyaltyAccount, LoyaltyEntry, LoyaltyError};
+use crate::events;
+use crate::ids::{short_link_code, IdGenerator};
+use crate::limits::{LimitOverride, MerchantUsage};
+use crate::outbox::OutboxEvent;
+use crate::ownership::VerifiedRecipient;
+use crate::payment::{Payment, PaymentStatus, RequestToken};
+use crate::pools::{PaymentPool, PoolSummary};
+use crate::pos::PosSession;
+use crate::screening::ScreeningRecord;
+use crate::static_qr::StaticQr;
+use crate::settlement::SettlementRecord;
+use crate::sponsorship::{Sponsorship, SponsorshipError};
+use crate::sweep::SweepRecord;
+use crate::tenants::{Tenant, TenantRegistry};
+use crate::transaction::{IssuedTransaction, RefreshError};
+use crate::webhook::{DeliveryAttempt, DeliveryStatus, WebhookDelivery, WebhookEndpoint};
+
+/// Сколько раз генерировать ID платежа при совпадениях
+const MAX_ID_ATTEMPTS: usize = 10;
+
+/// Емкость канала обновлений платежей (отстающие подписчики получают Lagged)
+const PAYMENT_UPDATES_CAPACITY: usize = 1024;
+
+/// Платеж изменен другим запросом или задачей после чтения: сохранение устаревшей копии
+/// отклонено, чтобы не затереть чужую запись (см. `PaymentService::update_payment`)
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Payment {payment_id} was modified concurrently (read version {expected}, stored version {actual})")]
+pub struct VersionConflict {
+    pub payment_id: String,
+    pub expected: u64,
+    pub actual: u64,
+}
+
+/// Платежи и вторичные индексы (подпись, reference и код короткой ссылки -> id платежа)
+#[derive(Debug, Default)]
+struct PaymentTable {
+    by_id: HashMap<String, Payment>,
+    by_signature: HashMap<String, String>,
+    by_reference: HashMap<String, String>,
+    by_short_code: HashMap<String, String>,
+}
+
+impl PaymentTable {
+    fn insert(&mut self, payment: &Payment) {
+        self.remove(&payment.id);
+        if let Some(signature) = &payment.signature {
+            self.by_signature.insert(signature.clone(), payment.id.clone());
+        }
+        self.by_reference.insert(payment.reference.clone(), payment.id.clone());
+        if let Some(short_code) = &payment.short_code {
+            self.by_short_code.insert(short_code.clone(), payment.id.clone());
+        }
+        self.by_id.insert(payment.id.clone(), payment.clone());
+    }
+
+    fn remove(&mut self, payment_id: &str) -> Option<Payment> {
+        let payment = self.by_id.remove(payment_id)?;
+        if let Some(signature) = &payment.signature {
+            self.by_signature.remove(signature);
+        }
+        self.by_reference.remove(&payment.reference);
+        if let Some(short_code) = &payment.short_code {
+            self.by_short_code.remove(short_code);
+        }
+        Some(payment)
+    }
+
+    /// Копия платежа с обратным отсчетом на текущий момент
+    fn get(&self, payment_id: &str) -> Option<Payment> {
+        let mut payment = self.by_id.get(payment_id)?.clone();
+        payment.refresh_countdown();
+        Some(payment)
+    }
+
+    fn find(&self, index: &HashMap<String, String>, key: &str) -> Option<Payment> {
+        index.get(key).and_then(|id| self.get(id))
+    }
+
+    /// Записать копию, прочитанную с версией `payment.version` (новый платеж — 0), если
+    /// с тех пор платеж не сохраняли. Копия получает следующую версию
+    fn compare_and_swap(&mut self, payment: &mut Payment) -> Result<(), VersionConflict> {
+        let actual = self.by_id.get(&payment.id).map_or(0, |stored| stored.version);
+        if actual != payment.version {
+            return Err(VersionConflict { payment_id: payment.id.clone(), expected: payment.version, actual });
+        }
+        payment.version += 1;
+        payment.refresh_countdown();
+        payment.updated_at = Utc::now();
+        self.insert(payment);
+        Ok(())
+    }
+}
+
+/// Версия формата снимка хранилища
+pub const SNAPSHOT_FORMAT_VERSION: u32 = 1;
+
+/// Снимок in-memory хранилища: все таблицы для экспорта, импорта и восстановления
+/// после перезапуска
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageSnapshot {
+    pub format_version: u32,
+    pub created_at: DateTime<Utc>,
+    pub payments: Vec<Payment>,
+    pub verified_recipients: HashMap<String, VerifiedRecipient>,
+    pub sweeps: Vec<SweepRecord>,
+    pub settlements: Vec<SettlementRecord>,
+    pub screenings: Vec<ScreeningRecord>,
+    pub limit_overrides: HashMap<String, LimitOverride>,
+    pub discounts: HashMap<String, DiscountCode>,
+    pub discount_redemptions: Vec<DiscountRedemption>,
+    pub disputes: HashMap<String, Dispute>,
+    pub loyalty_accounts: Vec<LoyaltyAccount>,
+    pub issued_transactions: HashMap<String, IssuedTransaction>,
+    pub sponsorships: HashMap<String, Sponsorship>,
+    pub pos_sessions: HashMap<String, PosSession>,
+    pub static_qrs: HashMap<String, StaticQr>,
+    /// Пулы платежей с невыданными заготовками
+    #[serde(default)]
+    pub payment_pools: HashMap<String, PaymentPool>,
+    pub webhooks: HashMap<String, WebhookEndpoint>,
+    /// Секреты подписи endpoints по ID: в JSON endpoint секрет не попадает
+    pub webhook_secrets: HashMap<String, String>,
+    /// Токены transaction request по ID платежа (и заготовок пулов): в JSON платежа
+    /// токен не попадает
+    #[serde(default)]
+    pub request_tokens: HashMap<String, RequestToken>,
+    pub webhook_deliveries: HashMap<String, WebhookDelivery>,
+    pub outbox: BTreeMap<u64, OutboxEvent>,
+    pub outbox_seq: u64,
+    /// Мерчанты, созданные или измененные через admin API
+    #[serde(default)]
+    pub tenants: Vec<Tenant>,
+}
 
 #[derive(Debug, Clone)]
 pub struct StorageService {
-    payments: std::sync::Arc<RwLock<HashMap<String, Payment>>>,
+    payments: std::sync::Arc<RwLock<PaymentTable>>,
+    verified_recipients: std::sync::Arc<RwLock<HashMap<String, VerifiedRecipient>>>,
+    sweeps: std::sync::Arc<RwLock<Vec<SweepRecord>>>,
+    settlements: std::sync::Arc<RwLock<Vec<SettlementRecord>>>,
+    screenings: std::sync::Arc<RwLock<Vec<ScreeningRecord>>>,
+    limit_overrides: std::sync::Arc<RwLock<HashMap<String, LimitOverride>>>,
+    discounts: std::sync::Arc<RwLock<HashMap<String, DiscountCode>>>,
+    discount_redemptions: std::sync::Arc<RwLock<Vec<DiscountRedemption>>>,
+    disputes: std::sync::Arc<RwLock<HashMap<String, Dispute>>>,
+    /// Бонусные счета по (мерчант, кошелек)
+    loyalty_accounts: std::sync::Arc<RwLock<HashMap<(String, String), LoyaltyAccount>>>,
+    issued_transactions: std::sync::Arc<RwLock<HashMap<String, IssuedTransaction>>>,
+    sponsorships: std::sync::Arc<RwLock<HashMap<String, Sponsorship>>>,
+    pos_sessions: std::sync::Arc<RwLock<HashMap<String, PosSession>>>,
+    static_qrs: std::sync::Arc<RwLock<HashMap<String, StaticQr>>>,
+    payment_pools: std::sync::Arc<RwLock<HashMap<String, PaymentPool>>>,
+    webhooks: std::sync::Arc<RwLock<HashMap<String, WebhookEndpoint>>>,
+    webhook_deliveries: std::sync::Arc<RwLock<HashMap<String, WebhookDelivery>>>,
+    outbox: std::sync::Arc<RwLock<BTreeMap<u64, OutboxEvent>>>,
+    outbox_seq: std::sync::Arc<std::sync::atomic::AtomicU64>,
+    /// Каждое сохранение платежа публикуется подписчикам (стримы статуса)
+    payment_updates: tokio::sync::broadcast::Sender<Payment>,
+    /// Кэш чтений платежа по ID; любая запись в таблицу платежей его обновляет
+    payment_cache: PaymentCache,
+    /// Копия таблицы платежей для списков и выгрузок
+    payment_replica: PaymentReplica,
+    /// Мерчанты: общий с `Config::tenants` реестр, по которому авторизуются запросы
+    tenants: TenantRegistry,
+}
+
+impl Default for StorageService {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl StorageService {
     pub fn new() -> Self {
         Self {
-            payments: std::sync::Arc::new(RwLock::new(HashMap::new())),
+            payments: std::sync::Arc::new(RwLock::new(PaymentTable::default())),
+            verified_recipients: std::sync::Arc::new(RwLock::new(HashMap::new())),
+            sweeps: std::sync::Arc::new(RwLock::new(Vec::new())),
+            settlements: std::sync::Arc::new(RwLock::new(Vec::new())),
+            screenings: std::sync::Arc::new(RwLock::new(Vec::new())),
+            limit_overrides: std::sync::Arc::new(RwLock::new(HashMap::new())),
+            discounts: std::sync::Arc::new(RwLock::new(HashMap::new())),
+            discount_redemptions: std::sync::Arc::new(RwLock::new(Vec::new())),
+            disputes: std::sync::Arc::new(RwLock::new(HashMap::new())),
+            loyalty_accounts: std::sync::Arc::new(RwLock::new(HashMap::new())),
+            issued_transactions: std::sync::Arc::new(RwLock::new(HashMap::new())),
+            sponsorships: std::sync::Arc::new(RwLock::new(HashMap::new())),
+            pos_sessions: std::sync::Arc::new(RwLock::new(HashMap::new())),
+            static_qrs: std::sync::Arc::new(RwLock::new(HashMap::new())),
+            payment_pools: std::sync::Arc::new(RwLock::new(HashMap::new())),
+            webhooks: std::sync::Arc::new(RwLock::new(HashMap::new())),
+            webhook_deliveries: std::sync::Arc::new(RwLock::new(HashMap::new())),
+            outbox: std::sync::Arc::new(RwLock::new(BTreeMap::new())),
+            outbox_seq: std::sync::Arc::new(std::sync::atomic::AtomicU64::new(0)),
+            payment_updates: tokio::sync::broadcast::channel(PAYMENT_UPDATES_CAPACITY).0,
+            payment_cache: PaymentCache::disabled(),
+            payment_replica: PaymentReplica::disabled(),
+            tenants: TenantRegistry::default(),
+        }
+    }
+
+    /// Хранилище с кэшем чтений платежей по ID
+    pub fn with_payment_cache(mut self, cache: PaymentCache) -> Self {
+        self.payment_cache = cache;
+        self
+    }
+
+    /// Хранилище с репликой чтения для списков и выгрузок платежей
+    pub fn with_payment_replica(mut self, replica: PaymentReplica) -> Self {
+        self.payment_replica = replica;
+        self
+    }
+
+    /// Отставание и число чтений реплики
+    pub fn payment_replica_stats(&self) -> ReplicaStats {
+        self.payment_replica.stats()
+    }
+
+    /// Хранилище настроек мерчантов в реестре `tenants` (обычно `Config::tenants`)
+    pub fn with_tenants(mut self, tenants: TenantRegistry) -> Self {
+        self.tenants = tenants;
+        self
+    }
+
+    /// Попадания и промахи кэша платежей
+    pub fn payment_cache_stats(&self) -> CacheStats {
+        self.payment_cache.stats()
+    }
+
+    /// Снимок всех таблиц (блокировки берутся на чтение в порядке полей, outbox последним)
+    pub async fn snapshot(&self) -> StorageSnapshot {
+        let payments = self.payments.read().await;
+        let verified_recipients = self.verified_recipients.read().await;
+        let sweeps = self.sweeps.read().await;
+        let settlements = self.settlements.read().await;
+        let screenings = self.screenings.read().await;
+        let limit_overrides = self.limit_overrides.read().await;
+        let discounts = self.discounts.read().await;
+        let discount_redemptions = self.discount_redemptions.read().await;
+        let disputes = self.disputes.read().await;
+        let loyalty_accounts = self.loyalty_accounts.read().await;
+        let issued_transactions = self.issued_transactions.read().await;
+        let sponsorships = self.sponsorships.read().await;
+        let pos_sessions = self.pos_sessions.read().await;
+        let static_qrs = self.static_qrs.read().await;
+        let payment_pools = self.payment_pools.read().await;
+        let webhooks = self.webhooks.read().await;
+        let webhook_deliveries = self.webhook_deliveries.read().await;
+        let outbox = self.outbox.read().await;
+
+        StorageSnapshot {
+            format_version: SNAPSHOT_FORMAT_VERSION,
+            created_at: Utc::now(),
+            payments: payments.by_id.values().cloned().collect(),
+            verified_recipients: verified_recipients.clone(),
+            sweeps: sweeps.clone(),
+            settlements: settlements.clone(),
+            screenings: screenings.clone(),
+            limit_overrides: limit_overrides.clone(),
+            discounts: discounts.clone(),
+            discount_redemptions: discount_redemptions.clone(),
+            disputes: disputes.clone(),
+            loyalty_accounts: loyalty_accounts.values().cloned().collect(),
+            issued_transactions: issued_transactions.clone(),
+            sponsorships: sponsorships.clone(),
+            pos_sessions: pos_sessions.clone(),
+            static_qrs: static_qrs.clone(),
+            payment_pools: payment_pools.clone(),
+            webhooks: webhooks.clone(),
+            webhook_secrets: webhooks.iter().map(|(id, endpoint)| (id.clone(), endpoint.secret.clone())).collect(),
+            request_tokens: payments.by_id.values()
+                .chain(payment_pools.values().flat_map(|pool| pool.payments.iter()))
+                .filter_map(|payment| Some((payment.id.clone(), payment.request_token.clone()?)))
+                .collect(),
+            webhook_deliveries: webhook_deliveries.clone(),
+            outbox: outbox.clone(),
+            outbox_seq: self.outbox_seq.load(std::sync::atomic::Ordering::SeqCst),
+            tenants: self.tenants.stored(),
         }
     }
 
-    /// Сохранить платеж
-    pub async fn save_payment(&self, payment_id: &str, payment: &Payment) -> anyhow::Result<()> {
+    /// Заменить содержимое всех таблиц снимком. Подписчики стримов статуса не уведомляются
+    pub async fn restore(&self, snapshot: StorageSnapshot) -> anyhow::Result<()> {
+        if snapshot.format_version != SNAPSHOT_FORMAT_VERSION {
+            anyhow::bail!(
+                "Unsupported snapshot format version {} (expected {})",
+                snapshot.format_version, SNAPSHOT_FORMAT_VERSION,
+            );
+        }
+        let mut payments = self.payments.write().await;
+        let mut verified_recipients = self.verified_recipients.write().await;
+        let mut sweeps = self.sweeps.write().await;
+        let mut settlements = self.settlements.write().await;
+        let mut screenings = self.screenings.write().await;
+        let mut limit_overrides = self.limit_overrides.write().await;
+        let mut discounts = self.discounts.write().await;
+        let mut discount_redemptions = self.discount_redemptions.write().await;
+        let mut disputes = self.disputes.write().await;
+        let mut loyalty_accounts = self.loyalty_accounts.write().await;
+        let mut issued_transactions = self.issued_transactions.write().await;
+        let mut sponsorships = self.sponsorships.write().await;
+        let mut pos_sessions = self.pos_sessions.write().await;
+        let mut static_qrs = self.static_qrs.write().await;
+        let mut payment_pools = self.payment_pools.write().await;
+        let mut webhooks = self.webhooks.write().await;
+        let mut webhook_deliveries = self.webhook_deliveries.write().await;
+        let mut outbox = self.outbox.write().await;
+
+        *payments = PaymentTable::default();
+        self.payment_cache.clear();
+        self.payment_replica.clear();
+        let mut request_tokens = snapshot.request_tokens;
+        let mut with_request_token = |payment: &mut Payment| {
+            if let Some(request_token) = request_tokens.remove(&payment.id) {
+                payment.request_token = Some(request_token);
+            }
+        };
+        for mut payment in snapshot.payments {
+            with_request_token(&mut payment);
+            payments.insert(&payment);
+        }
+        let mut snapshot_pools = snapshot.payment_pools;
+        for pool in snapshot_pools.values_mut() {
+            pool.payments.iter_mut().for_each(&mut with_request_token);
+        }
+        *verified_recipients = snapshot.verified_recipients;
+        *sweeps = snapshot.sweeps;
+        *settlements = snapshot.settlements;
+        *screenings = snapshot.screenings;
+        *limit_overrides = snapshot.limit_overrides;
+        *discounts = snapshot.discounts;
+        *discount_redemptions = snapshot.discount_redemptions;
+        *disputes = snapshot.disputes;
+        *loyalty_accounts = snapshot.loyalty_accounts.into_iter()
+            .map(|account| ((account.merchant_id.clone(), account.wallet.clone()), account))
+            .collect();
+        *issued_transactions = snapshot.issued_transactions;
+        *sponsorships = snapshot.sponsorships;
+        *pos_sessions = snapshot.pos_sessions;
+        *static_qrs = snapshot.static_qrs;
+        *payment_pools = snapshot_pools;
+        let mut secrets = snapshot.webhook_secrets;
+        *webhooks = snapshot.webhooks.into_iter()
+            .map(|(id, mut endpoint)| {
+                endpoint.secret = secrets.remove(&id).unwrap_or_default();
+                (id, endpoint)
+            })
+            .collect();
+        *webhook_deliveries = snapshot.webhook_deliveries;
+        // Номера новых событий продолжают последовательность снимка
+        let last_seq = snapshot.outbox.keys().next_back().copied().unwrap_or(0);
+        *outbox = snapshot.outbox;
+        self.outbox_seq.store(snapshot.outbox_seq.max(last_seq), std::sync::atomic::Ordering::SeqCst);
+        self.tenants.restore(snapshot.tenants);
+
+        tracing::info!(payments = payments.by_id.len(), created_at = %snapshot.created_at, "Storage restored from snapshot");
+        Ok(())
+    }
+
+    /// Сохранить платеж, если его не изменили после чтения (иначе [`VersionConflict`]);
+    /// `payment` получает новую версию
+    pub async fn save_payment(&self, payment_id: &str, payment: &mut Payment) -> anyhow::Result<()> {
+        let _step = observability::step("storage", "save_payment");
         let mut payments = self.payments.write().await;
-        payments.insert(payment_id.to_string(), payment.clone());
+        payments.compare_and_swap(payment)?;
+        self.payment_cache.put(payment);
+        // Ошибка только если подписчиков нет
+        let _ = self.payment_updates.send(payment.clone());
+
+        tracing::debug!(payment_id, "Payment saved to storage");
+        Ok(())
+    }
+
+    /// Сохранить платеж и событие о нем в outbox атомарно (обе блокировки взяты
+    /// до записи) с той же проверкой версии, что и `save_payment`. Для платежей
+    /// без мерчанта событие не создается, событие с уже записанным ключом
+    /// идемпотентности — тоже (платеж при этом сохраняется)
+    pub async fn save_payment_with_event(&self, payment: &mut Payment, event_type: &str) -> anyhow::Result<()> {
+        let _step = observability::step("storage", "save_payment_with_event");
+        // Порядок блокировок: payments, затем outbox
+        let mut payments = self.payments.write().await;
+        let mut outbox = self.outbox.write().await;
+
+        payments.compare_and_swap(payment)?;
+        self.payment_cache.put(payment);
+        let event = match &payment.merchant_id {
+            Some(merchant_id) => Some((merchant_id.clone(), events::payment_payload(payment)?)),
+            None => None,
+        };
+        if let Some((merchant_id, payload)) = event {
+            let idempotency_key = events::idempotency_key(event_type, &payment.id);
+            if outbox.values().any(|event| event.idempotency_key == idempotency_key) {
+                tracing::warn!(payment_id = %payment.id, event_type, "Duplicate payment event suppressed");
+            } else {
+                let seq = self.outbox_seq.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+                outbox.insert(seq, OutboxEvent {
+                    seq,
+                    merchant_id,
+                    event_type: event_type.to_string(),
+                    idempotency_key,
+                    payload,
+                    created_at: Utc::now(),
+                    attempts: 0,
+                    last_error: None,
+                    dispatched_at: None,
+                });
+            }
+        }
 
-        log::debug!("Payment {} saved to storage", payment_id);
+        let _ = self.payment_updates.send(payment.clone());
+
+        tracing::debug!(payment_id = %payment.id, event_type, "Payment saved to storage with event");
         Ok(())
     }
 
+    /// Свободный ID для нового платежа: при совпадении с существующим генерируется новый
+    pub async fn allocate_payment_id(&self, ids: &dyn IdGenerator) -> anyhow::Result<String> {
+        let payments = self.payments.read().await;
+        for _ in 0..MAX_ID_ATTEMPTS {
+            let id = ids.generate();
+            if !payments.by_id.contains_key(&id) {
+                return Ok(id);
+            }
+            tracing::warn!(payment_id = %id, "Payment ID collision, regenerating");
+        }
+        anyhow::bail!("Failed to allocate a unique payment ID after {} attempts", MAX_ID_ATTEMPTS)
+    }
+
+    /// Свободный код короткой ссылки
+    pub async fn allocate_short_code(&self, length: usize) -> anyhow::Result<String> {
+        let payments = self.payments.read().await;
+        for _ in 0..MAX_ID_ATTEMPTS {
+            let code = short_link_code(length);
+            if !payments.by_short_code.contains_key(&code) {
+                return Ok(code);
+            }
+            tracing::warn!(short_code = %code, "Short link collision, regenerating");
+        }
+        anyhow::bail!("Failed to allocate a unique short link after {} attempts", MAX_ID_ATTEMPTS)
+    }
+
+    /// Платеж по коду короткой ссылки (в канонической записи)
+    pub async fn find_payment_by_short_code(&self, short_code: &str) -> anyhow::Result<Option<Payment>> {
+        let _step = observability::step("storage", "find_payment_by_short_code");
+        let payments = self.payments.read().await;
+        Ok(payments.find(&payments.by_short_code, short_code))
+    }
+
+    /// Подписаться на все последующие сохранения платежей
+    pub fn subscribe_payments(&self) -> tokio::sync::broadcast::Receiver<Payment> {
+        self.payment_updates.subscribe()
+    }
+
     /// Получить платеж
     pub async fn get_payment(&self, payment_id: &str) -> anyhow::Result<Option<Payment>> {
+        let _step = observability::step("storage", "get_payment");
+        if let Some(payment) = self.payment_cache.get(payment_id) {
+            return Ok(Some(payment));
+        }
+        let payments = self.payments.read().await;
+        let payment = payments.get(payment_id);
+        // Запись в кэш под блокировкой чтения: параллельное сохранение ждет и затем
+        // перезаписывает кэш свежей версией
+        if let Some(payment) = &payment {
+            self.payment_cache.put(payment);
+        }
+        Ok(payment)
+    }
+
+    /// Платежи по списку ID (одна блокировка на весь список); ненайденные пропускаются
+    pub async fn get_payments(&self, payment_ids: &[String]) -> anyhow::Result<Vec<Payment>> {
+        let _step = observability::step("storage", "get_payments");
         let payments = self.payments.read().await;
-        Ok(payments.get(payment_id).cloned())
+        Ok(payment_ids.iter().filter_map(|id| payments.get(id)).collect())
+    }
+
+    /// Платеж, оплаченный транзакцией с этой подписью
+    pub async fn find_payment_by_signature(&self, signature: &str) -> anyhow::Result<Option<Payment>> {
+        let _step = observability::step("storage", "find_payment_by_signature");
+        let payments = self.payments.read().await;
+        Ok(payments.find(&payments.by_signature, signature))
+    }
+
+    /// Платеж по Solana Pay reference из его транзакции
+    pub async fn find_payment_by_reference(&self, reference: &str) -> anyhow::Result<Option<Payment>> {
+        let _step = observability::step("storage", "find_payment_by_reference");
+        let payments = self.payments.read().await;
+        Ok(payments.find(&payments.by_reference, reference))
     }
 
     /// Удалить платеж
     pub async fn delete_payment(&self, payment_id: &str) -> anyhow::Result<bool> {
+        let _step = observability::step("storage", "delete_payment");
         let mut payments = self.payments.write().await;
+        self.issued_transactions.write().await.remove(payment_id);
+        self.payment_cache.invalidate(payment_id);
         Ok(payments.remove(payment_id).is_some())
     }
 
     /// Получить все платежи (для отладки)
     pub async fn get_all_payments(&self) -> anyhow::Result<HashMap<String, Payment>> {
+        let _step = observability::step("storage", "get_all_payments");
+        let payments = self.payments.read().await;
+        Ok(payments.by_id.keys().filter_map(|id| Some((id.clone(), payments.get(id)?))).collect())
+    }
+
+    /// Все платежи для списков, выгрузок и дашбордов. `Eventual` читает реплику, если
+    /// ее копия достаточно свежая; иначе копия обновляется одним запросом из основной
+    /// таблицы. `Strong` и выключенная реплика — всегда основная таблица
+    pub async fn list_payments(&self, consistency: ReadConsistency) -> anyhow::Result<std::sync::Arc<Vec<Payment>>> {
+        let _step = observability::step("storage", "list_payments");
+        if consistency == ReadConsistency::Strong {
+            return Ok(std::sync::Arc::new(self.read_all_payments().await));
+        }
+        if let Some(payments) = self.payment_replica.get() {
+            return Ok(payments);
+        }
+        let Some(_refresh) = self.payment_replica.lock_refresh().await else {
+            return Ok(std::sync::Arc::new(self.read_all_payments().await));
+        };
+        if let Some(payments) = self.payment_replica.get() {
+            return Ok(payments);
+        }
+        let payments = std::sync::Arc::new(self.read_all_payments().await);
+        self.payment_replica.put(payments.clone());
+        Ok(payments)
+    }
+
+    async fn read_all_payments(&self) -> Vec<Payment> {
         let payments = self.payments.read().await;
-        Ok(payments.clone())
+        payments.by_id.keys().filter_map(|id| payments.get(id)).collect()
     }
 
-    /// Очистить просроченные платежи
-    pub async fn cleanup_expired_payments(&self) -> anyhow::Result<usize> {
+    /// Архивировать завершенные платежи, оплаченные до `completed_before`, и неоплаченные
+    /// (истекшие, ожидающие, неуспешные), срок которых вышел до `expired_before`
+    pub async fn archive_payments(
+        &self,
+        completed_before: DateTime<Utc>,
+        expired_before: DateTime<Utc>,
+    ) -> anyhow::Result<usize> {
         let mut payments = self.payments.write().await;
         let now = Utc::now();
+        let mut count = 0;
+
+        for payment in payments.by_id.values_mut().filter(|p| p.archived_at.is_none()) {
+            let due = match payment.status {
+                PaymentStatus::Completed | PaymentStatus::Refunded | PaymentStatus::Released => {
+                    payment.verified_at.unwrap_or(payment.created_at) < completed_before
+                }
+                // Средства еще на hot wallet
+                PaymentStatus::Held => false,
+                _ => payment.expires_at < expired_before,
+            };
+            if due {
+                payment.archived_at = Some(now);
+                payment.updated_at = now;
+                payment.version += 1;
+                self.payment_cache.invalidate(&payment.id);
+                count += 1;
+            }
+        }
+
+        if count > 0 {
+            tracing::info!(count, "Payments archived");
+        }
+        Ok(count)
+    }
 
-        let expired_keys: Vec<String> = payments
-            .iter()
-            .filter(|(_, payment)| now > payment.expires_at)
-            .map(|(key, _)| key.clone())
+    /// Удалить архивные платежи, заархивированные до `archived_before`
+    pub async fn purge_archived_payments(&self, archived_before: DateTime<Utc>) -> anyhow::Result<usize> {
+        let mut payments = self.payments.write().await;
+
+        let purged: Vec<String> = payments.by_id
+            .values()
+            .filter(|p| p.archived_at.is_some_and(|at| at < archived_before))
+            .map(|p| p.id.clone())
             .collect();
+        let mut issued = self.issued_transactions.write().await;
+        for payment_id in &purged {
+            payments.remove(payment_id);
+            issued.remove(payment_id);
+            self.payment_cache.invalidate(payment_id);
+        }
+
+        if !purged.is_empty() {
+            tracing::info!(count = purged.len(), "Archived payments purged");
+        }
+        Ok(purged.len())
+    }
 
-        let count = expired_keys.len();
+    /// Запомнить транзакцию, выданную кошельку. Счетчик пересборок платежа сохраняется
+    pub async fn save_issued_transaction(&self, issued: &IssuedTransaction) -> anyhow::Result<()> {
+        let _step = observability::step("storage", "save_issued_transaction");
+        let mut transactions = self.issued_transactions.write().await;
+        let mut issued = issued.clone();
+        if let Some(previous) = transactions.get(&issued.payment_id) {
+            issued.refreshes = previous.refreshes;
+            issued.refreshed_at = previous.refreshed_at;
+        }
+        transactions.insert(issued.payment_id.clone(), issued);
+        Ok(())
+    }
 
-        for key in expired_keys {
-            payments.remove(&key);
+    /// Транзакция, последней выданная кошельку по платежу
+    pub async fn get_issued_transaction(&self, payment_id: &str) -> anyhow::Result<Option<IssuedTransaction>> {
+        let _step = observability::step("storage", "get_issued_transaction");
+        let transactions = self.issued_transactions.read().await;
+        Ok(transactions.get(payment_id).cloned())
+    }
+
+    /// Запомнить compute бюджет, с которым собрана выданная транзакция
+    pub async fn set_issued_compute_budget(
+        &self,
+        payment_id: &str,
+        compute_budget: Option<ComputeBudget>,
+    ) -> anyhow::Result<()> {
+        if let Some(issued) = self.issued_transactions.write().await.get_mut(payment_id) {
+            issued.compute_budget = compute_budget;
         }
+        Ok(())
+    }
 
-        if count > 0 {
-            log::info!("Cleaned up {} expired payments", count);
+    /// Учесть пересборку транзакции платежа: не чаще `min_interval` и не больше `limit` раз
+    pub async fn claim_transaction_refresh(
+        &self,
+        payment_id: &str,
+        min_interval: chrono::Duration,
+        limit: u32,
+    ) -> anyhow::Result<IssuedTransaction> {
+        let _step = observability::step("storage", "claim_transaction_refresh");
+        let mut transactions = self.issued_transactions.write().await;
+        let issued = transactions.get_mut(payment_id).ok_or(RefreshError::NotIssued)?;
+        if issued.refreshes >= limit {
+            return Err(RefreshError::LimitReached.into());
         }
 
-        Ok(count)
+        let now = Utc::now();
+        let allowed_at = issued.refreshed_at.unwrap_or(issued.issued_at) + min_interval;
+        if allowed_at > now {
+            let retry_after_secs = ((allowed_at - now).num_milliseconds() as u64).div_ceil(1000);
+            return Err(RefreshError::TooSoon { retry_after_secs }.into());
+        }
+
+        issued.refreshes += 1;
+        issued.refreshed_at = Some(now);
+        Ok(issued.clone())
+    }
+
+    /// Зарезервировать спонсирование платежа, если резервы с `since` (кроме прежнего
+    /// резерва этого платежа) вместе с ним не превышают `limit`. Старые резервы удаляются
+    pub async fn reserve_sponsorship(
+        &self,
+        sponsorship: &Sponsorship,
+        since: DateTime<Utc>,
+        limit: u64,
+    ) -> anyhow::Result<()> {
+        let mut sponsorships = self.sponsorships.write().await;
+        sponsorships.retain(|_, s| s.reserved_at >= since);
+
+        let reserved: u64 = sponsorships.values()
+            .filter(|s| s.payment_id != sponsorship.payment_id)
+            .map(|s| s.lamports)
+            .sum();
+        if reserved + sponsorship.lamports > limit {
+            return Err(SponsorshipError::DailyLimitReached.into());
+        }
+        sponsorships.insert(sponsorship.payment_id.clone(), sponsorship.clone());
+        Ok(())
+    }
+
+    /// Сохранить подтвержденный кошелек получателя
+    pub async fn save_verified_recipient(&self, recipient: &VerifiedRecipient) -> anyhow::Result<()> {
+        let mut recipients = self.verified_recipients.write().await;
+        recipients.insert(recipient.wallet.clone(), recipient.clone());
+        Ok(())
+    }
+
+    /// Получить подтвержденный кошелек получателя
+    pub async fn get_verified_recipient(&self, wallet: &str) -> anyhow::Result<Option<VerifiedRecipient>> {
+        let recipients = self.verified_recipients.read().await;
+        Ok(recipients.get(wallet).cloned())
+    }
+
+    /// Сохранить запись аудита sweep
+    pub async fn save_sweep(&self, record: &SweepRecord) -> anyhow::Result<()> {
+        let mut sweeps = self.sweeps.write().await;
+        sweeps.push(record.clone());
+        Ok(())
+    }
+
+    /// Все sweep операции, новые первыми
+    pub async fn list_sweeps(&self) -> anyhow::Result<Vec<SweepRecord>> {
+        let sweeps = self.sweeps.read().await;
+        Ok(sweeps.iter().rev().cloned().collect())
+    }
+
+    /// Сохранить попытку расчета с мерчантом
+    pub async fn save_settlement(&self, record: &SettlementRecord) -> anyhow::Result<()> {
+        let mut settlements = self.settlements.write().await;
+        settlements.push(record.clone());
+        Ok(())
+    }
+
+    /// Попытки расчетов, новые первыми; с `payment_id` — только по этому платежу
+    pub async fn list_settlements(&self, payment_id: Option<&str>) -> anyhow::Result<Vec<SettlementRecord>> {
+        let settlements = self.settlements.read().await;
+        Ok(settlements.iter().rev()
+            .filter(|record| payment_id.is_none_or(|id| record.payment_id == id))
+            .cloned()
+            .collect())
+    }
+
+    /// Сохранить совпадение при проверке адреса
+    pub async fn save_screening(&self, record: &ScreeningRecord) -> anyhow::Result<()> {
+        let mut screenings = self.screenings.write().await;
+        screenings.push(record.clone());
+        Ok(())
+    }
+
+    /// Совпадения проверок, новые первыми; с `payment_id` — только по этому платежу
+    pub async fn list_screenings(&self, payment_id: Option<&str>) -> anyhow::Result<Vec<ScreeningRecord>> {
+        let screenings = self.screenings.read().await;
+        Ok(screenings.iter().rev()
+            .filter(|record| payment_id.is_none_or(|id| record.payment_id == id))
+            .cloned()
+            .collect())
+    }
+
+    /// Сохранить POS сессию
+    pub async fn save_pos_session(&self, session: &PosSession) -> anyhow::Result<()> {
+        let mut sessions = self.pos_sessions.write().await;
+        sessions.insert(session.id.clone(), session.clone());
+        Ok(())
+    }
+
+    /// Получить POS сессию
+    pub async fn get_pos_session(&self, session_id: &str) -> anyhow::Result<Option<PosSession>> {
+        let sessions = self.pos_sessions.read().await;
+        Ok(sessions.get(session_id).cloned())
+    }
+
+    /// Удалить POS сессию
+    pub async fn delete_pos_session(&self, session_id: &str) -> anyhow::Result<bool> {
+        let mut sessions = self.pos_sessions.write().await;
+        Ok(sessions.remove(session_id).is_some())
+    }
+
+    /// Добавить чек в сессию (атомарно, храним только последние `limit`)
+    pub async fn push_pos_charge(&self, session_id: &str, payment_id: &str, limit: usize) -> anyhow::Result<()> {
+        let mut sessions = self.pos_sessions.write().await;
+        let session = sessions.get_mut(session_id)
+            .ok_or_else(|| anyhow::anyhow!("POS session not found"))?;
+
+        session.charges.insert(0, payment_id.to_string());
+        session.charges.truncate(limit);
+        session.last_activity_at = Utc::now();
+        Ok(())
+    }
+
+    /// Сохранить статический QR
+    pub async fn save_static_qr(&self, static_qr: &StaticQr) -> anyhow::Result<()> {
+        let mut static_qrs = self.static_qrs.write().await;
+        static_qrs.insert(static_qr.id.clone(), static_qr.clone());
+        Ok(())
+    }
+
+    /// Получить статический QR
+    pub async fn get_static_qr(&self, id: &str) -> anyhow::Result<Option<StaticQr>> {
+        let static_qrs = self.static_qrs.read().await;
+        Ok(static_qrs.get(id).cloned())
+    }
+
+    /// Добавить платеж сканирования статического QR (храним только последние `limit`)
+    pub async fn push_static_qr_payment(&self, id: &str, payment_id: &str, limit: usize) -> anyhow::Result<()> {
+        let mut static_qrs = self.static_qrs.write().await;
+        let static_qr = static_qrs.get_mut(id)
+            .ok_or_else(|| anyhow::anyhow!("Static QR not found"))?;
+
+        static_qr.payments.insert(0, payment_id.to_string());
+        static_qr.payments.truncate(limit);
+        Ok(())
+    }
+
+    /// Сохранить пул платежей
+    pub async fn save_payment_pool(&self, pool: &PaymentPool) -> anyhow::Result<()> {
+        let mut pools = self.payment_pools.write().await;
+        pools.insert(pool.id.clone(), pool.clone());
+        Ok(())
+    }
+
+    /// Состояние пула платежей (без заготовок)
+    pub async fn get_payment_pool(&self, id: &str) -> anyhow::Result<Option<PoolSummary>> {
+        let pools = self.payment_pools.read().await;
+        Ok(pools.get(id).map(PaymentPool::summary))
+    }
+
+    /// Забрать следующую заготовку пула (атомарно: одну заготовку получает один заказ)
+    pub async fn claim_pool_payment(&self, id: &str) -> anyhow::Result<Option<Payment>> {
+        let _step = observability::step("storage", "claim_pool_payment");
+        let mut pools = self.payment_pools.write().await;
+        let Some(pool) = pools.get_mut(id) else {
+            return Ok(None);
+        };
+        let payment = pool.payments.pop_front();
+        if payment.is_some() {
+            pool.claimed += 1;
+        }
+        Ok(payment)
+    }
+
+    /// Удалить пул платежей вместе с заготовками
+    pub async fn delete_payment_pool(&self, id: &str) -> anyhow::Result<Option<PaymentPool>> {
+        let mut pools = self.payment_pools.write().await;
+        Ok(pools.remove(id))
+    }
+
+    /// Сохранить webhook endpoint
+    pub async fn save_webhook(&self, endpoint: &WebhookEndpoint) -> anyhow::Result<()> {
+        let mut webhooks = self.webhooks.write().await;
+        webhooks.insert(endpoint.id.clone(), endpoint.clone());
+        Ok(())
+    }
+
+    /// Получить webhook endpoint
+    pub async fn get_webhook(&self, endpoint_id: &str) -> anyhow::Result<Option<WebhookEndpoint>> {
+        let webhooks = self.webhooks.read().await;
+        Ok(webhooks.get(endpoint_id).cloned())
+    }
+
+    /// Webhook endpoints мерчанта, старые первыми
+    pub async fn list_webhooks(&self, merchant_id: &str) -> anyhow::Result<Vec<WebhookEndpoint>> {
+        let webhooks = self.webhooks.read().await;
+        let mut endpoints: Vec<WebhookEndpoint> = webhooks.values()
+            .filter(|e| e.merchant_id == merchant_id)
+            .cloned()
+            .collect();
+        endpoints.sort_by_key(|e| e.created_at);
+        Ok(endpoints)
+    }
+
+    /// Удалить webhook endpoint вместе с историей доставок
+    pub async fn delete_webhook(&self, endpoint_id: &str) -> anyhow::Result<bool> {
+        let removed = self.webhooks.write().await.remove(endpoint_id).is_some();
+        self.webhook_deliveries.write().await.retain(|_, d| d.endpoint_id != endpoint_id);
+        Ok(removed)
+    }
+
+    /// Сохранить доставку webhook, если у endpoint еще нет доставки события с тем же ключом
+    /// идемпотентности (проверка и запись под одной блокировкой). `false` — дубликат
+    pub async fn save_webhook_delivery_once(&self, delivery: &WebhookDelivery) -> anyhow::Result<bool> {
+        let mut deliveries = self.webhook_deliveries.write().await;
+        let duplicate = deliveries.values().any(|other| {
+            other.endpoint_id == delivery.endpoint_id && other.idempotency_key == delivery.idempotency_key
+        });
+        if duplicate {
+            return Ok(false);
+        }
+        deliveries.insert(delivery.id.clone(), delivery.clone());
+        Ok(true)
+    }
+
+    /// Получить доставку webhook
+    pub async fn get_webhook_delivery(&self, delivery_id: &str) -> anyhow::Result<Option<WebhookDelivery>> {
+        let deliveries = self.webhook_deliveries.read().await;
+        Ok(deliveries.get(delivery_id).cloned())
+    }
+
+    /// Доставки на endpoint, новые первыми
+    pub async fn list_webhook_deliveries(&self, endpoint_id: &str) -> anyhow::Result<Vec<WebhookDelivery>> {
+        let deliveries = self.webhook_deliveries.read().await;
+        let mut list: Vec<WebhookDelivery> = deliveries.values()
+            .filter(|d| d.endpoint_id == endpoint_id)
+            .cloned()
+            .collect();
+        list.sort_by_key(|d| std::cmp::Reverse(d.created_at));
+        Ok(list)
+    }
+
+    /// Все доставки webhook, новые первыми
+    pub async fn list_all_webhook_deliveries(&self) -> anyhow::Result<Vec<WebhookDelivery>> {
+        let deliveries = self.webhook_deliveries.read().await;
+        let mut list: Vec<WebhookDelivery> = deliveries.values().cloned().collect();
+        list.sort_by_key(|d| std::cmp::Reverse(d.created_at));
+        Ok(list)
+    }
+
+    /// Записать попытку доставки (атомарно) и вернуть обновленную доставку
+    pub async fn record_webhook_attempt(
+        &self,
+        delivery_id: &str,
+        attempt: DeliveryAttempt,
+        delivered: bool,
+    ) -> anyhow::Result<WebhookDelivery> {
+        let mut deliveries = self.webhook_deliveries.write().await;
+        let delivery = deliveries.get_mut(delivery_id)
+            .ok_or_else(|| anyhow::anyhow!("Webhook delivery not found"))?;
+
+        delivery.attempts.push(attempt);
+        delivery.status = if delivered { DeliveryStatus::Delivered } else { DeliveryStatus::Failed };
+        Ok(delivery.clone())
+    }
+
+    /// Неотправленные события outbox по порядку
+    pub async fn pending_outbox_events(&self) -> anyhow::Result<Vec<OutboxEvent>> {
+        let outbox = self.outbox.read().await;
+        Ok(outbox.values().filter(|e| e.dispatched_at.is_none()).cloned().collect())
+    }
+
+    /// Отправлено ли уже другое событие outbox с тем же ключом идемпотентности
+    pub async fn outbox_key_dispatched(&self, event: &OutboxEvent) -> anyhow::Result<bool> {
+        if event.idempotency_key.is_empty() {
+            return Ok(false);
+        }
+        let outbox = self.outbox.read().await;
+        Ok(outbox.values().any(|other| {
+            other.seq != event.seq && other.idempotency_key == event.idempotency_key && other.dispatched_at.is_some()
+        }))
+    }
+
+    /// Пометить событие outbox отправленным
+    pub async fn mark_outbox_dispatched(&self, seq: u64) -> anyhow::Result<()> {
+        let mut outbox = self.outbox.write().await;
+        if let Some(event) = outbox.get_mut(&seq) {
+            event.attempts += 1;
+            event.last_error = None;
+            event.dispatched_at = Some(Utc::now());
+        }
+        Ok(())
+    }
+
+    /// Записать неудачную попытку отправки события outbox
+    pub async fn mark_outbox_failed(&self, seq: u64, error: String) -> anyhow::Result<()> {
+        let mut outbox = self.outbox.write().await;
+        if let Some(event) = outbox.get_mut(&seq) {
+            event.attempts += 1;
+            event.last_error = Some(error);
+        }
+        Ok(())
+    }
+
+    /// Удалить отправленные события старше `before`
+    pub async fn prune_outbox(&self, before: chrono::DateTime<Utc>) -> anyhow::Result<usize> {
+        let mut outbox = self.outbox.write().await;
+        let count = outbox.len();
+        outbox.retain(|_, e| e.dispatched_at.is_none_or(|at| at >= before));
+        Ok(count - outbox.len())
+    }
+
+    /// Посчитать доход от комиссий
+    pub async fn get_revenue_report(&self) -> anyhow::Result<RevenueReport> {
+        let payments = self.payments.read().await;
+        let mut report = RevenueReport::default();
+
+        for payment in payments.by_id.values() {
+            // Sandbox платежи не приносят комиссий
+            if !matches!(payment.status, crate::payment::PaymentStatus::Completed) || payment.sandbox {
+                continue;
+            }
+            report.completed_payments += 1;
+
+            let fee = payment.fee_amount;
+            let token = payment.fee_token.clone();
+            let day = payment.verified_at
+                .unwrap_or(payment.created_at)
+                .format("%Y-%m-%d")
+                .to_string();
+            let merchant = payment.merchant_id.clone().unwrap_or_else(|| "default".to_string());
+
+            *report.by_token.entry(token.clone()).or_default() += fee;
+            *report.by_day.entry(day).or_default().entry(token.clone()).or_default() += fee;
+            *report.by_merchant.entry(merchant).or_default().entry(token).or_default() += fee;
+        }
+
+        Ok(report)
+    }
+
+    /// Платежи мерчанта, созданные начиная с `since`: число (любой статус) и объем
+    /// по токенам (ожидающие, в обработке и завершенные)
+    pub async fn merchant_usage(&self, merchant_id: &str, since: DateTime<Utc>) -> anyhow::Result<MerchantUsage> {
+        let payments = self.payments.read().await;
+        let mut usage = MerchantUsage::default();
+
+        for payment in payments.by_id.values() {
+            if payment.merchant_id.as_deref() != Some(merchant_id) || payment.created_at < since {
+                continue;
+            }
+            usage.payments += 1;
+            usage.oldest_created_at = Some(match usage.oldest_created_at {
+                Some(oldest) => oldest.min(payment.created_at),
+                None => payment.created_at,
+            });
+            if matches!(payment.status, PaymentStatus::Pending | PaymentStatus::Processing | PaymentStatus::Completed) {
+                *usage.volume.entry(payment.token.clone()).or_default() += payment.amount;
+            }
+        }
+
+        Ok(usage)
+    }
+
+    /// Создать или заменить настройки мерчанта
+    pub async fn save_tenant(&self, merchant: MerchantConfig) -> anyhow::Result<Tenant> {
+        Ok(self.tenants.upsert(merchant)?)
+    }
+
+    /// Добавить нового мерчанта (самостоятельная регистрация)
+    pub async fn insert_tenant(&self, merchant: MerchantConfig) -> anyhow::Result<Tenant> {
+        Ok(self.tenants.insert(merchant)?)
+    }
+
+    /// Заменить API ключ мерчанта
+    pub async fn set_tenant_api_key(&self, merchant_id: &str, api_key: String) -> anyhow::Result<Tenant> {
+        Ok(self.tenants.set_api_key(merchant_id, api_key)?)
+    }
+
+    /// Добавить мерчанту дополнительный API ключ
+    pub async fn add_tenant_api_key(&self, merchant_id: &str, key: MerchantApiKey) -> anyhow::Result<Tenant> {
+        Ok(self.tenants.add_api_key(merchant_id, key)?)
+    }
+
+    /// Удалить дополнительный API ключ мерчанта
+    pub async fn remove_tenant_api_key(&self, merchant_id: &str, key_id: &str) -> anyhow::Result<Tenant> {
+        Ok(self.tenants.remove_api_key(merchant_id, key_id)?)
+    }
+
+    /// Последний успешный запрос с ключом мерчанта
+    pub fn api_key_last_used(&self, merchant_id: &str, key_id: &str) -> Option<DateTime<Utc>> {
+        self.tenants.last_used(merchant_id, key_id)
+    }
+
+    pub async fn list_tenants(&self) -> anyhow::Result<Vec<Tenant>> {
+        Ok(self.tenants.list())
+    }
+
+    /// Удалить мерчанта, созданного через admin API
+    pub async fn delete_tenant(&self, merchant_id: &str) -> anyhow::Result<Tenant> {
+        Ok(self.tenants.remove(merchant_id)?)
+    }
+
+    /// Сохранить переопределение лимитов мерчанта
+    pub async fn save_limit_override(&self, limit_override: &LimitOverride) -> anyhow::Result<()> {
+        let mut overrides = self.limit_overrides.write().await;
+        overrides.insert(limit_override.merchant_id.clone(), limit_override.clone());
+        Ok(())
+    }
+
+    pub async fn get_limit_override(&self, merchant_id: &str) -> anyhow::Result<Option<LimitOverride>> {
+        let overrides = self.limit_overrides.read().await;
+        Ok(overrides.get(merchant_id).cloned())
+    }
+
+    /// Удалить переопределение лимитов; `false` — его не было
+    pub async fn delete_limit_override(&self, merchant_id: &str) -> anyhow::Result<bool> {
+        let mut overrides = self.limit_overrides.write().await;
+        Ok(overrides.remove(merchant_id).is_some())
+    }
+
+    /// Сохранить новый код скидки; `false` — такой код уже есть
+    pub async fn insert_discount(&self, discount: &DiscountCode) -> anyhow::Result<bool> {
+        let mut discounts = self.discounts.write().await;
+        if discounts.contains_key(&discount.code) {
+            return Ok(false);
+        }
+        discounts.insert(discount.code.clone(), discount.clone());
+        Ok(true)
+    }
+
+    pub async fn get_discount(&self, code: &str) -> anyhow::Result<Option<DiscountCode>> {
+        let discounts = self.discounts.read().await;
+        Ok(discounts.get(code).cloned())
+    }
+
+    pub async fn list_discounts(&self) -> anyhow::Result<Vec<DiscountCode>> {
+        let discounts = self.discounts.read().await;
+        Ok(discounts.values().cloned().collect())
+    }
+
+    /// Выключить код скидки; `None` — кода нет
+    pub async fn deactivate_discount(&self, code: &str) -> anyhow::Result<Option<DiscountCode>> {
+        let mut discounts = self.discounts.write().await;
+        Ok(discounts.get_mut(code).map(|discount| {
+            discount.active = false;
+            discount.clone()
+        }))
+    }
+
+    /// Засчитать использование кода, если он еще применим (проверка и счетчик под одной
+    /// блокировкой)
+    pub async fn redeem_discount(
+        &self,
+        redemption: DiscountRedemption,
+    ) -> anyhow::Result<Result<DiscountCode, DiscountError>> {
+        let mut discounts = self.discounts.write().await;
+        let Some(discount) = discounts.get_mut(&redemption.code) else {
+            return Ok(Err(DiscountError::NotFound));
+        };
+        if let Err(e) = discount.check(redemption.merchant_id.as_deref(), &redemption.token, redemption.redeemed_at) {
+            return Ok(Err(e));
+        }
+        discount.uses += 1;
+        let discount = discount.clone();
+        self.discount_redemptions.write().await.push(redemption);
+        Ok(Ok(discount))
+    }
+
+    /// Отменить использование кода платежом, который так и не был сохранен
+    pub async fn release_discount(&self, code: &str, payment_id: &str) -> anyhow::Result<bool> {
+        let mut discounts = self.discounts.write().await;
+        let mut redemptions = self.discount_redemptions.write().await;
+        let Some(index) = redemptions.iter().rposition(|r| r.code == code && r.payment_id == payment_id) else {
+            return Ok(false);
+        };
+        redemptions.remove(index);
+        if let Some(discount) = discounts.get_mut(code) {
+            discount.uses = discount.uses.saturating_sub(1);
+        }
+        Ok(true)
+    }
+
+    /// Использования кода по порядку
+    pub async fn discount_redemptions(&self, code: &str) -> anyhow::Result<Vec<DiscountRedemption>> {
+        let redemptions = self.discount_redemptions.read().await;
+        Ok(redemptions.iter().filter(|redemption| redemption.code == code).cloned().collect())
+    }
+
+    /// Сохранить спор вместе с событием `dispute.*` в outbox. `expected` — статус
+    /// сохраненной копии (`None` — новый спор, у платежа не должно быть другого открытого).
+    /// При конфликте ничего не сохраняется и возвращается ID мешающего спора
+    pub async fn save_dispute_with_event(
+        &self,
+        dispute: &Dispute,
+        payment: &Payment,
+        expected: Option<DisputeStatus>,
+    ) -> anyhow::Result<Option<String>> {
+        // Порядок блокировок: disputes, затем outbox
+        let mut disputes = self.disputes.write().await;
+        let mut outbox = self.outbox.write().await;
+
+        let conflict = match expected {
+            None => disputes.values()
+                .find(|other| other.payment_id == dispute.payment_id && other.status == DisputeStatus::Open)
+                .map(|other| other.id.clone()),
+            Some(expected) => disputes.get(&dispute.id)
+                .filter(|stored| stored.status != expected)
+                .map(|stored| stored.id.clone()),
+        };
+        if conflict.is_some() {
+            return Ok(conflict);
+        }
+        disputes.insert(dispute.id.clone(), dispute.clone());
+
+        if let Some(merchant_id) = &dispute.merchant_id {
+            let event_type = dispute.status.event_type();
+            let seq = self.outbox_seq.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+            outbox.insert(seq, OutboxEvent {
+                seq,
+                merchant_id: merchant_id.clone(),
+                idempotency_key: events::idempotency_key(&event_type, &dispute.id),
+                event_type,
+                payload: events::dispute_payload(dispute, payment)?,
+                created_at: Utc::now(),
+                attempts: 0,
+                last_error: None,
+                dispatched_at: None,
+            });
+        }
+        tracing::debug!(dispute_id = %dispute.id, status = %dispute.status, "Dispute saved to storage with event");
+        Ok(None)
+    }
+
+    pub async fn get_dispute(&self, dispute_id: &str) -> anyhow::Result<Option<Dispute>> {
+        let disputes = self.disputes.read().await;
+        Ok(disputes.get(dispute_id).cloned())
+    }
+
+    pub async fn list_disputes(&self) -> anyhow::Result<Vec<Dispute>> {
+        let disputes = self.disputes.read().await;
+        Ok(disputes.values().cloned().collect())
+    }
+
+    /// Открытый спор по платежу
+    pub async fn open_dispute(&self, payment_id: &str) -> anyhow::Result<Option<Dispute>> {
+        let disputes = self.disputes.read().await;
+        Ok(disputes.values()
+            .find(|dispute| dispute.payment_id == payment_id && dispute.status == DisputeStatus::Open)
+            .cloned())
+    }
+
+    /// Провести операцию по бонусному счету (проверка баланса и запись под одной блокировкой)
+    pub async fn apply_loyalty_entry(
+        &self,
+        merchant_id: &str,
+        wallet: &str,
+        entry: LoyaltyEntry,
+    ) -> anyhow::Result<Result<LoyaltyAccount, LoyaltyError>> {
+        let mut accounts = self.loyalty_accounts.write().await;
+        let account = accounts.entry((merchant_id.to_string(), wallet.to_string()))
+            .or_insert_with(|| LoyaltyAccount::new(merchant_id, wallet));
+        Ok(account.apply(entry).map(|()| account.clone()))
+    }
+
+    pub async fn get_loyalty_account(&self, merchant_id: &str, wallet: &str) -> anyhow::Result<Option<LoyaltyAccount>> {
+        let accounts = self.loyalty_accounts.read().await;
+        Ok(accounts.get(&(merchant_id.to_string(), wallet.to_string())).cloned())
+    }
+
+    /// Бонусные счета кошелька у всех мерчантов
+    pub async fn loyalty_accounts(&self, wallet: &str) -> anyhow::Result<Vec<LoyaltyAccount>> {
+        let accounts = self.loyalty_accounts.read().await;
+        Ok(accounts.values().filter(|account| account.wallet == wallet).cloned().collect())
     }
 
     /// Получить статистику
@@ -72,14 +1228,14 @@ impl StorageService {
         let payments = self.payments.read().await;
         let now = Utc::now();
 
-        let total = payments.len();
-        let pending = payments.values()
+        let total = payments.by_id.len();
+        let pending = payments.by_id.values()
             .filter(|p| matches!(p.status, crate::payment::PaymentStatus::Pending) && now <= p.expires_at)
             .count();
-        let completed = payments.values()
+        let completed = payments.by_id.values()
             .filter(|p| matches!(p.status, crate::payment::PaymentStatus::Completed))
             .count();
-        let expired = payments.values()
+        let expired = payments.by_id.values()
             .filter(|p| now > p.expires_at)
             .count();
 
@@ -92,6 +1248,18 @@ impl StorageService {
     }
 }
 
+/// Собранные комиссии по завершенным платежам
+#[derive(Debug, Default, serde::Serialize)]
+pub struct RevenueReport {
+    pub completed_payments: usize,
+    /// Токен → сумма комиссий
+    pub by_token: BTreeMap<String, f64>,
+    /// День (UTC) → токен → сумма
+    pub by_day: BTreeMap<String, BTreeMap<String, f64>>,
+    /// Мерчант → токен → сумма
+    pub by_merchant: BTreeMap<String, BTreeMap<String, f64>>,
+}
+
 #[derive(Debug, serde::Serialize)]
 pub struct StorageStats {
     pub total: usize,
@@ -0,0 +1,226 @@
+use base64::{Engine as _, engine::general_purpose};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::price::WRAPPED_SOL_MINT;
+
+/// Таймаут запросов к Jupiter
+const SWAP_API_TIMEOUT_SECS: u64 = 10;
+
+/// Своп, которым плательщик оплачивает платеж другим токеном
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentSwap {
+    /// Mint токена, которым платит плательщик
+    pub input_mint: String,
+    /// Сколько входного токена спишется максимум (базовые единицы, с учетом проскальзывания)
+    pub max_in_amount: u64,
+    /// Сколько токена платежа своп выдает плательщику (базовые единицы)
+    pub out_amount: u64,
+    /// Сколько токена платежа должен получить мерчант (сумма и чаевые, базовые единицы) —
+    /// проверяется при верификации
+    pub recipient_amount: u64,
+    pub slippage_bps: u16,
+    pub quoted_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Котировка Jupiter (базовые единицы)
+#[derive(Debug, Clone)]
+pub struct SwapQuote {
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub in_amount: u64,
+    pub out_amount: u64,
+    /// Максимум входного токена с учетом проскальзывания
+    pub max_in_amount: u64,
+    /// Минимум выходного токена с учетом проскальзывания
+    pub min_out_amount: u64,
+    /// Ответ quote целиком — Jupiter требует его обратно в swap-instructions
+    raw: Value,
+}
+
+/// Инструкции свопа и lookup таблицы, без которых маршрут не помещается в транзакцию
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SwapInstructions {
+    pub instructions: Vec<Instruction>,
+    pub lookup_tables: Vec<Pubkey>,
+}
+
+/// Клиент Jupiter Swap API
+#[derive(Debug, Clone)]
+pub struct SwapService {
+    client: reqwest::Client,
+    config: Config,
+}
+
+impl SwapService {
+    pub fn new(config: Config) -> Self {
+        Self { client: reqwest::Client::new(), config }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.swap.enabled
+    }
+
+    /// Mint входного токена: символ из `supported_tokens` или адрес mint
+    pub fn resolve_mint(&self, token: &str) -> anyhow::Result<Pubkey> {
+        if let Some(config) = self.config.get_token_config(token) {
+            return Ok(Pubkey::from_str(config.mint.as_deref().unwrap_or(WRAPPED_SOL_MINT))?);
+        }
+        Pubkey::from_str(token).map_err(|_| anyhow::anyhow!("Unknown token or invalid mint address: {}", token))
+    }
+
+    /// Котировка на получение ровно `out_amount` выходного токена
+    pub async fn quote_exact_out(
+        &self,
+        input_mint: &Pubkey,
+        output_mint: &Pubkey,
+        out_amount: u64,
+    ) -> anyhow::Result<SwapQuote> {
+        let raw = self.quote(input_mint, output_mint, out_amount, "ExactOut").await?;
+        // Для ExactOut otherAmountThreshold — максимум входного токена
+        let max_in_amount = quote_amount(&raw, "otherAmountThreshold")?;
+        let quoted_out = quote_amount(&raw, "outAmount")?;
+        if quoted_out < out_amount {
+            anyhow::bail!("Jupiter quote returns {} instead of {} base units", quoted_out, out_amount);
+        }
+
+        Ok(SwapQuote {
+            input_mint: *input_mint,
+            output_mint: *output_mint,
+            in_amount: quote_amount(&raw, "inAmount")?,
+            out_amount,
+            max_in_amount,
+            min_out_amount: out_amount,
+            raw,
+        })
+    }
+
+    /// Котировка на обмен ровно `in_amount` входного токена
+    pub async fn quote_exact_in(
+        &self,
+        input_mint: &Pubkey,
+        output_mint: &Pubkey,
+        in_amount: u64,
+    ) -> anyhow::Result<SwapQuote> {
+        let raw = self.quote(input_mint, output_mint, in_amount, "ExactIn").await?;
+
+        Ok(SwapQuote {
+            input_mint: *input_mint,
+            output_mint: *output_mint,
+            in_amount,
+            out_amount: quote_amount(&raw, "outAmount")?,
+            max_in_amount: in_amount,
+            // Для ExactIn otherAmountThreshold — минимум выходного токена
+            min_out_amount: quote_amount(&raw, "otherAmountThreshold")?,
+            raw,
+        })
+    }
+
+    async fn quote(&self, input_mint: &Pubkey, output_mint: &Pubkey, amount: u64, mode: &str) -> anyhow::Result<Value> {
+        Ok(self.client
+            .get(format!("{}/quote", self.config.swap.api_url.trim_end_matches('/')))
+            .query(&[
+                ("inputMint", input_mint.to_string()),
+                ("outputMint", output_mint.to_string()),
+                ("amount", amount.to_string()),
+                ("swapMode", mode.to_string()),
+                ("slippageBps", self.config.swap.slippage_bps.to_string()),
+            ])
+            .timeout(Duration::from_secs(SWAP_API_TIMEOUT_SECS))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    /// Инструкции свопа от имени `user`. Результат приходит на `destination` (токен-аккаунт)
+    /// или остается у `user` (SOL разворачивается)
+    pub async fn swap_instructions(
+        &self,
+        quote: &SwapQuote,
+        user: &Pubkey,
+        destination: Option<&Pubkey>,
+    ) -> anyhow::Result<SwapInstructions> {
+        let mut request = json!({
+            "quoteResponse": quote.raw,
+            "userPublicKey": user.to_string(),
+            "wrapAndUnwrapSol": true,
+        });
+        if let Some(destination) = destination {
+            request["destinationTokenAccount"] = json!(destination.to_string());
+        }
+        let response: Value = self.client
+            .post(format!("{}/swap-instructions", self.config.swap.api_url.trim_end_matches('/')))
+            .json(&request)
+            .timeout(Duration::from_secs(SWAP_API_TIMEOUT_SECS))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        if let Some(error) = response.get("error").and_then(Value::as_str) {
+            anyhow::bail!("Jupiter swap-instructions failed: {}", error);
+        }
+
+        let mut instructions = Vec::new();
+        for key in ["computeBudgetInstructions", "setupInstructions"] {
+            for value in response.get(key).and_then(Value::as_array).into_iter().flatten() {
+                instructions.push(parse_instruction(value)?);
+            }
+        }
+        let swap = response.get("swapInstruction")
+            .ok_or_else(|| anyhow::anyhow!("Invalid Jupiter response: missing swapInstruction"))?;
+        instructions.push(parse_instruction(swap)?);
+        if let Some(cleanup) = response.get("cleanupInstruction").filter(|v| !v.is_null()) {
+            instructions.push(parse_instruction(cleanup)?);
+        }
+
+        let lookup_tables = response.get("addressLookupTableAddresses")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .map(Pubkey::from_str)
+            .collect::<Result<_, _>>()?;
+
+        Ok(SwapInstructions { instructions, lookup_tables })
+    }
+}
+
+fn quote_amount(raw: &Value, key: &str) -> anyhow::Result<u64> {
+    raw.get(key)
+        .and_then(Value::as_str)
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| anyhow::anyhow!("Invalid Jupiter quote: missing {}", key))
+}
+
+/// Инструкция в формате Jupiter: programId, accounts[{pubkey, isSigner, isWritable}], data (base64)
+fn parse_instruction(value: &Value) -> anyhow::Result<Instruction> {
+    let field = |key: &str| value.get(key).ok_or_else(|| anyhow::anyhow!("Invalid Jupiter instruction: missing {}", key));
+
+    let program_id = Pubkey::from_str(field("programId")?.as_str().unwrap_or_default())?;
+    let accounts = field("accounts")?.as_array()
+        .ok_or_else(|| anyhow::anyhow!("Invalid Jupiter instruction: accounts is not an array"))?
+        .iter()
+        .map(|account| {
+            let pubkey = Pubkey::from_str(account.get("pubkey").and_then(Value::as_str).unwrap_or_default())?;
+            let is_signer = account.get("isSigner").and_then(Value::as_bool).unwrap_or(false);
+            let is_writable = account.get("isWritable").and_then(Value::as_bool).unwrap_or(false);
+            Ok(if is_writable {
+                AccountMeta::new(pubkey, is_signer)
+            } else {
+                AccountMeta::new_readonly(pubkey, is_signer)
+            })
+        })
+        .collect::<anyhow::Result<_>>()?;
+    let data = general_purpose::STANDARD.decode(field("data")?.as_str().unwrap_or_default())?;
+
+    Ok(Instruction { program_id, accounts, data })
+}
@@ -0,0 +1,170 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    program_pack::Pack,
+    pubkey::Pubkey,
+    transaction::{Transaction, VersionedTransaction},
+};
+use std::str::FromStr;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::rpc::SolanaRpc;
+use crate::signer::ServerSigner;
+use crate::storage::StorageService;
+
+/// Перевод накопленных комиссий с fee кошелька сервера в холодное хранилище
+#[derive(Clone)]
+pub struct SweepService {
+    rpc: Arc<dyn SolanaRpc>,
+    storage: StorageService,
+    signer: Option<ServerSigner>,
+    config: Config,
+}
+
+/// Запись аудита sweep операции
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SweepRecord {
+    pub id: String,
+    pub token: String,
+    pub from: String,
+    pub to: String,
+    /// Сумма в токенах
+    pub amount: f64,
+    pub dry_run: bool,
+    pub status: SweepStatus,
+    pub signature: Option<String>,
+    pub details: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SweepStatus {
+    /// Баланс ниже порога — переводить нечего
+    Skipped,
+    /// Dry run: перевод был бы выполнен
+    Planned,
+    Completed,
+    Failed,
+}
+
+impl SweepService {
+    pub fn new(
+        config: Config,
+        rpc: Arc<dyn SolanaRpc>,
+        storage: StorageService,
+        signer: Option<ServerSigner>,
+    ) -> Self {
+        Self { rpc, storage, signer, config }
+    }
+
+    /// Выполнить sweep (или только посчитать при `dry_run`)
+    pub async fn run(&self, dry_run: bool) -> anyhow::Result<SweepRecord> {
+        let cold_wallet = self.config.sweep.cold_wallet.as_deref()
+            .ok_or_else(|| anyhow::anyhow!("Sweep is not configured: set sweep.cold_wallet"))?;
+        let cold_wallet = Pubkey::from_str(cold_wallet)?;
+        let signer = self.signer.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Sweep requires server keypair (SERVER_KEYPAIR_PATH)"))?;
+
+        let fee_wallet = signer.pubkey();
+        if !self.config.solana.fee_wallets.iter().any(|w| w.address == fee_wallet.to_string()) {
+            anyhow::bail!("Server keypair {} is not one of the configured fee wallets", fee_wallet);
+        }
+
+        let token = self.config.get_token_config(&self.config.sweep.token)
+            .ok_or_else(|| anyhow::anyhow!("Sweep token {} not supported", self.config.sweep.token))?;
+        let mint = Pubkey::from_str(
+            token.mint.as_deref()
+                .ok_or_else(|| anyhow::anyhow!("Sweep supports SPL tokens only, got {}", token.symbol))?
+        )?;
+
+        let from_ata = spl_associated_token_account::get_associated_token_address(&fee_wallet, &mint);
+        let to_ata = spl_associated_token_account::get_associated_token_address(&cold_wallet, &mint);
+
+        // Несуществующий ATA — нулевой баланс
+        let balance = match self.rpc.get_account(&from_ata).await {
+            Ok(Some(account)) => spl_token::state::Account::unpack(&account.data)?.amount,
+            Ok(None) => 0,
+            Err(e) => {
+                tracing::debug!(ata = %from_ata, error = %e, "Fee ATA balance unavailable, assuming 0");
+                0
+            }
+        };
+        let scale = 10_f64.powi(token.decimals as i32);
+        let amount = balance as f64 / scale;
+
+        let mut record = SweepRecord {
+            id: format!("sweep_{}", Uuid::new_v4().simple()),
+            token: token.symbol.clone(),
+            from: fee_wallet.to_string(),
+            to: cold_wallet.to_string(),
+            amount,
+            dry_run,
+            status: SweepStatus::Skipped,
+            signature: None,
+            details: String::new(),
+            created_at: Utc::now(),
+        };
+
+        if balance == 0 || amount < self.config.sweep.threshold {
+            record.details = format!(
+                "Balance {} {} is below threshold {}",
+                amount, token.symbol, self.config.sweep.threshold
+            );
+        } else if dry_run {
+            record.status = SweepStatus::Planned;
+            record.details = format!("Would sweep {} {} to {}", amount, token.symbol, cold_wallet);
+        } else {
+            let instructions = vec![
+                spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+                    &fee_wallet, &cold_wallet, &mint, &spl_token::ID,
+                ),
+                spl_token::instruction::transfer_checked(
+                    &spl_token::ID,
+                    &from_ata,
+                    &mint,
+                    &to_ata,
+                    &fee_wallet,
+                    &[],
+                    balance,
+                    token.decimals,
+                )?,
+            ];
+
+            let result = async {
+                let blockhash = self.rpc.get_latest_blockhash().await?;
+                let transaction = Transaction::new_signed_with_payer(
+                    &instructions,
+                    Some(&fee_wallet),
+                    &[signer.keypair()],
+                    blockhash,
+                );
+                self.rpc.send_transaction(&VersionedTransaction::from(transaction)).await
+            }.await;
+
+            match result {
+                Ok(signature) => {
+                    record.status = SweepStatus::Completed;
+                    record.signature = Some(signature.to_string());
+                    record.details = format!("Swept {} {} to {}", amount, token.symbol, cold_wallet);
+                    tracing::info!(amount, token = %token.symbol, %signature, "Fee sweep completed");
+                }
+                Err(e) => {
+                    record.status = SweepStatus::Failed;
+                    record.details = format!("Sweep transaction failed: {}", e);
+                    tracing::error!(error = %e, "Fee sweep failed");
+                }
+            }
+        }
+
+        self.storage.save_sweep(&record).await?;
+        Ok(record)
+    }
+
+    /// История sweep операций (аудит)
+    pub async fn history(&self) -> anyhow::Result<Vec<SweepRecord>> {
+        self.storage.list_sweeps().await
+    }
+}
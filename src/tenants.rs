@@ -0,0 +1,507 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use uuid::Uuid;
+
+use crate::auth::{ApiKeyGrant, ApiKeyScope, PRIMARY_KEY_ID};
+use crate::config::{constant_time_eq, Config, MerchantApiKey, MerchantConfig, REDACTED};
+use crate::storage::StorageService;
+use crate::validation::ValidationErrors;
+
+/// Максимальная длина ID мерчанта
+pub const MAX_TENANT_ID_LENGTH: usize = 64;
+
+/// Дополнительных API ключей на мерчанта
+pub const MAX_API_KEYS_PER_MERCHANT: usize = 20;
+
+/// Максимальная длина описания API ключа
+const MAX_KEY_DESCRIPTION_LENGTH: usize = 200;
+
+/// Последнее использование ключей по (мерчант, ID ключа)
+type KeyUsage = HashMap<(String, String), DateTime<Utc>>;
+
+/// Новый API ключ мерчанта (192 случайных бита)
+pub fn generate_api_key() -> String {
+    format!("cn_live_{}", hex::encode(rand::random::<[u8; 24]>()))
+}
+
+/// Откуда настройки мерчанта
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TenantSource {
+    /// `[[merchants]]` конфигурации без изменений
+    Config,
+    /// Создан или изменен через admin API, хранится в хранилище
+    Api,
+}
+
+/// Мерчант реестра
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tenant {
+    #[serde(flatten)]
+    pub merchant: MerchantConfig,
+    pub source: TenantSource,
+    /// Время последнего изменения через admin API (`None` — из конфигурации)
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl Tenant {
+    /// Копия для ответов API: API ключи скрыты
+    pub fn redacted(mut self) -> Self {
+        self.merchant.api_key = REDACTED.to_string();
+        for key in &mut self.merchant.api_keys {
+            key.api_key = REDACTED.to_string();
+        }
+        self
+    }
+}
+
+/// Новый API ключ мерчанта; показывается только в этом ответе
+#[derive(Debug, Clone, Serialize)]
+pub struct IssuedApiKey {
+    pub merchant_id: String,
+    pub api_key: String,
+}
+
+/// API ключ мерчанта без секрета, для списка ключей
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiKeyInfo {
+    pub id: String,
+    pub scope: ApiKeyScope,
+    pub description: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Последний успешный запрос с ключом (с запуска процесса, у каждой реплики свое)
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub expired: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CreateApiKeyRequest {
+    pub scope: ApiKeyScope,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Без срока ключ действует до отзыва
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Созданный дополнительный ключ; секрет показывается только в этом ответе
+#[derive(Debug, Clone, Serialize)]
+pub struct CreatedApiKey {
+    #[serde(flatten)]
+    pub key: ApiKeyInfo,
+    pub api_key: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TenantError {
+    #[error("Merchant not found")]
+    NotFound,
+    #[error("API key is already used by another merchant")]
+    ApiKeyTaken,
+    #[error("Merchant ID {0} is already taken")]
+    IdTaken(String),
+    #[error("API key not found")]
+    ApiKeyNotFound,
+    #[error("Merchant {0} is defined in the configuration file and cannot be deleted")]
+    ConfigManaged(String),
+}
+
+#[derive(Debug, Clone)]
+struct RegisteredTenant {
+    merchant: Arc<MerchantConfig>,
+    source: TenantSource,
+    updated_at: Option<DateTime<Utc>>,
+}
+
+impl RegisteredTenant {
+    fn from_config(merchant: &MerchantConfig) -> Self {
+        Self { merchant: Arc::new(merchant.clone()), source: TenantSource::Config, updated_at: None }
+    }
+
+    fn to_tenant(&self) -> Tenant {
+        Tenant { merchant: (*self.merchant).clone(), source: self.source, updated_at: self.updated_at }
+    }
+}
+
+/// Реестр мерчантов (tenants): настройки каждого API ключа — кошелек комиссий,
+/// политика комиссий, токены, уведомления и оформление. Заполняется из `[[merchants]]`
+/// и дополняется через admin API; измененные записи попадают в снимки хранилища.
+/// Клоны делят одно состояние
+#[derive(Debug, Clone, Default)]
+pub struct TenantRegistry {
+    tenants: Arc<RwLock<Vec<RegisteredTenant>>>,
+    /// Мерчанты конфигурации: основа при восстановлении из снимка
+    seed: Arc<Vec<MerchantConfig>>,
+    last_used: Arc<Mutex<KeyUsage>>,
+}
+
+impl TenantRegistry {
+    pub fn new(merchants: &[MerchantConfig]) -> Self {
+        Self {
+            tenants: Arc::new(RwLock::new(merchants.iter().map(RegisteredTenant::from_config).collect())),
+            seed: Arc::new(merchants.to_vec()),
+            last_used: Arc::default(),
+        }
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, Vec<RegisteredTenant>> {
+        self.tenants.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, Vec<RegisteredTenant>> {
+        self.tenants.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Мерчант по ID
+    pub fn get(&self, id: &str) -> Option<Arc<MerchantConfig>> {
+        self.read().iter().find(|t| t.merchant.id == id).map(|t| t.merchant.clone())
+    }
+
+    /// Мерчант по любому его API ключу (сравнение за постоянное время); срок и права
+    /// ключа не проверяются, см. `auth::authorize_key`
+    pub fn find_by_api_key(&self, api_key: &str) -> Option<Arc<MerchantConfig>> {
+        self.authenticate(api_key).map(|grant| grant.merchant)
+    }
+
+    /// Мерчант и ключ по секрету ключа
+    pub fn authenticate(&self, api_key: &str) -> Option<ApiKeyGrant> {
+        let tenants = self.read();
+        for tenant in tenants.iter() {
+            let merchant = &tenant.merchant;
+            if constant_time_eq(merchant.api_key.as_bytes(), api_key.as_bytes()) {
+                return Some(ApiKeyGrant {
+                    merchant: merchant.clone(),
+                    key_id: PRIMARY_KEY_ID.to_string(),
+                    scope: ApiKeyScope::Admin,
+                    expires_at: None,
+                });
+            }
+            if let Some(key) = merchant.api_keys.iter().find(|key| constant_time_eq(key.api_key.as_bytes(), api_key.as_bytes())) {
+                return Some(ApiKeyGrant {
+                    merchant: merchant.clone(),
+                    key_id: key.id.clone(),
+                    scope: key.scope,
+                    expires_at: key.expires_at,
+                });
+            }
+        }
+        None
+    }
+
+    /// Отметить успешный запрос с ключом
+    pub fn record_use(&self, merchant_id: &str, key_id: &str) {
+        let mut last_used = self.last_used.lock().unwrap_or_else(|e| e.into_inner());
+        last_used.insert((merchant_id.to_string(), key_id.to_string()), Utc::now());
+    }
+
+    pub fn last_used(&self, merchant_id: &str, key_id: &str) -> Option<DateTime<Utc>> {
+        let last_used = self.last_used.lock().unwrap_or_else(|e| e.into_inner());
+        last_used.get(&(merchant_id.to_string(), key_id.to_string())).copied()
+    }
+
+    /// Все мерчанты в порядке добавления
+    pub fn list(&self) -> Vec<Tenant> {
+        self.read().iter().map(RegisteredTenant::to_tenant).collect()
+    }
+
+    /// Мерчанты, созданные или измененные через admin API (для снимка хранилища)
+    pub fn stored(&self) -> Vec<Tenant> {
+        self.read().iter()
+            .filter(|t| t.source == TenantSource::Api)
+            .map(RegisteredTenant::to_tenant)
+            .collect()
+    }
+
+    /// Создать или заменить мерчанта; API ключ не должен принадлежать другому мерчанту
+    pub fn upsert(&self, merchant: MerchantConfig) -> Result<Tenant, TenantError> {
+        let mut tenants = self.write();
+        if tenants.iter().any(|t| t.merchant.id != merchant.id && t.merchant.shares_api_key_with(&merchant)) {
+            return Err(TenantError::ApiKeyTaken);
+        }
+        let registered = RegisteredTenant {
+            merchant: Arc::new(merchant),
+            source: TenantSource::Api,
+            updated_at: Some(Utc::now()),
+        };
+        match tenants.iter_mut().find(|t| t.merchant.id == registered.merchant.id) {
+            Some(existing) => *existing = registered.clone(),
+            None => tenants.push(registered.clone()),
+        }
+        Ok(registered.to_tenant())
+    }
+
+    /// Добавить нового мерчанта; ID и API ключ не должны быть заняты
+    pub fn insert(&self, merchant: MerchantConfig) -> Result<Tenant, TenantError> {
+        let mut tenants = self.write();
+        if tenants.iter().any(|t| t.merchant.id == merchant.id) {
+            return Err(TenantError::IdTaken(merchant.id));
+        }
+        if tenants.iter().any(|t| t.merchant.shares_api_key_with(&merchant)) {
+            return Err(TenantError::ApiKeyTaken);
+        }
+        let registered = RegisteredTenant {
+            merchant: Arc::new(merchant),
+            source: TenantSource::Api,
+            updated_at: Some(Utc::now()),
+        };
+        tenants.push(registered.clone());
+        Ok(registered.to_tenant())
+    }
+
+    /// Заменить основной API ключ мерчанта, не трогая остальные настройки; прежний ключ
+    /// перестает действовать сразу
+    pub fn set_api_key(&self, id: &str, api_key: String) -> Result<Tenant, TenantError> {
+        self.modify(id, |merchant| {
+            merchant.api_key = api_key;
+            Ok(())
+        })
+    }
+
+    /// Добавить мерчанту дополнительный ключ
+    pub fn add_api_key(&self, id: &str, key: MerchantApiKey) -> Result<Tenant, TenantError> {
+        self.modify(id, |merchant| {
+            merchant.api_keys.push(key);
+            Ok(())
+        })
+    }
+
+    /// Удалить дополнительный ключ мерчанта
+    pub fn remove_api_key(&self, id: &str, key_id: &str) -> Result<Tenant, TenantError> {
+        self.modify(id, |merchant| {
+            let index = merchant.api_keys.iter().position(|key| key.id == key_id).ok_or(TenantError::ApiKeyNotFound)?;
+            merchant.api_keys.remove(index);
+            Ok(())
+        })
+    }
+
+    /// Изменить копию настроек мерчанта и заменить ими запись (под одной блокировкой);
+    /// ключи измененной копии не должны совпадать с ключами других мерчантов
+    fn modify(
+        &self,
+        id: &str,
+        change: impl FnOnce(&mut MerchantConfig) -> Result<(), TenantError>,
+    ) -> Result<Tenant, TenantError> {
+        let mut tenants = self.write();
+        let index = tenants.iter().position(|t| t.merchant.id == id).ok_or(TenantError::NotFound)?;
+        let mut merchant = (*tenants[index].merchant).clone();
+        change(&mut merchant)?;
+        if tenants.iter().any(|t| t.merchant.id != id && t.merchant.shares_api_key_with(&merchant)) {
+            return Err(TenantError::ApiKeyTaken);
+        }
+        tenants[index] = RegisteredTenant {
+            merchant: Arc::new(merchant),
+            source: TenantSource::Api,
+            updated_at: Some(Utc::now()),
+        };
+        Ok(tenants[index].to_tenant())
+    }
+
+    /// Удалить мерчанта, созданного через admin API
+    pub fn remove(&self, id: &str) -> Result<Tenant, TenantError> {
+        if self.seed.iter().any(|m| m.id == id) {
+            return Err(TenantError::ConfigManaged(id.to_string()));
+        }
+        let mut tenants = self.write();
+        let index = tenants.iter().position(|t| t.merchant.id == id).ok_or(TenantError::NotFound)?;
+        Ok(tenants.remove(index).to_tenant())
+    }
+
+    /// Мерчанты конфигурации и сохраненные записи снимка поверх них
+    pub fn restore(&self, stored: Vec<Tenant>) {
+        let mut tenants: Vec<RegisteredTenant> = self.seed.iter().map(RegisteredTenant::from_config).collect();
+        for tenant in stored {
+            let registered = RegisteredTenant {
+                merchant: Arc::new(tenant.merchant),
+                source: TenantSource::Api,
+                updated_at: tenant.updated_at,
+            };
+            match tenants.iter_mut().find(|t| t.merchant.id == registered.merchant.id) {
+                Some(existing) => *existing = registered,
+                None => tenants.push(registered),
+            }
+        }
+        *self.write() = tenants;
+    }
+}
+
+/// Мерчанты (tenants) для admin API: настройки проверяются так же, как `[[merchants]]`
+/// при загрузке конфигурации
+#[derive(Debug, Clone)]
+pub struct TenantService {
+    storage: StorageService,
+    config: Config,
+}
+
+impl TenantService {
+    pub fn new(config: Config, storage: StorageService) -> Self {
+        Self { storage, config }
+    }
+
+    /// Все мерчанты (API ключи скрыты)
+    pub async fn list(&self) -> anyhow::Result<Vec<Tenant>> {
+        Ok(self.storage.list_tenants().await?.into_iter().map(Tenant::redacted).collect())
+    }
+
+    /// Мерчант по ID (API ключ скрыт)
+    pub async fn get(&self, id: &str) -> anyhow::Result<Tenant> {
+        self.storage.list_tenants().await?
+            .into_iter()
+            .find(|tenant| tenant.merchant.id == id)
+            .map(Tenant::redacted)
+            .ok_or_else(|| TenantError::NotFound.into())
+    }
+
+    /// Создать мерчанта или заменить настройки мерчанта с тем же ID. Ошибки настроек —
+    /// `ValidationErrors` поля `merchant`
+    pub async fn upsert(&self, mut merchant: MerchantConfig) -> anyhow::Result<Tenant> {
+        merchant.id = merchant.id.trim().to_string();
+        self.check(&merchant)?;
+
+        let tenant = self.storage.save_tenant(merchant).await?;
+        tracing::info!(merchant = %tenant.merchant.id, "Merchant settings saved");
+        Ok(tenant.redacted())
+    }
+
+    /// Создать нового мерчанта; занятый ID — `TenantError::IdTaken`
+    pub async fn create(&self, mut merchant: MerchantConfig) -> anyhow::Result<Tenant> {
+        merchant.id = merchant.id.trim().to_string();
+        self.check(&merchant)?;
+
+        let tenant = self.storage.insert_tenant(merchant).await?;
+        tracing::info!(merchant = %tenant.merchant.id, "Merchant created");
+        Ok(tenant.redacted())
+    }
+
+    /// Выпустить мерчанту новый API ключ; прежний перестает действовать сразу
+    pub async fn rotate_api_key(&self, id: &str) -> anyhow::Result<IssuedApiKey> {
+        let api_key = generate_api_key();
+        self.storage.set_tenant_api_key(id, api_key.clone()).await?;
+        tracing::info!(merchant = id, "Merchant API key rotated");
+        Ok(IssuedApiKey { merchant_id: id.to_string(), api_key })
+    }
+
+    /// Отозвать API ключ мерчанта (например, при утечке): ключ заменяется случайным,
+    /// который нигде не показывается. Новый ключ выпускает администратор
+    pub async fn revoke_api_key(&self, id: &str) -> anyhow::Result<Tenant> {
+        let revoked = format!("revoked_{}", hex::encode(rand::random::<[u8; 32]>()));
+        let tenant = self.storage.set_tenant_api_key(id, revoked).await?;
+        tracing::warn!(merchant = id, "Merchant API key revoked");
+        Ok(tenant.redacted())
+    }
+
+    /// Основной и дополнительные ключи мерчанта без секретов
+    pub async fn api_keys(&self, id: &str) -> anyhow::Result<Vec<ApiKeyInfo>> {
+        let merchant = self.storage.list_tenants().await?
+            .into_iter()
+            .find(|tenant| tenant.merchant.id == id)
+            .ok_or(TenantError::NotFound)?
+            .merchant;
+        let now = Utc::now();
+        let mut keys = vec![ApiKeyInfo {
+            id: PRIMARY_KEY_ID.to_string(),
+            scope: ApiKeyScope::Admin,
+            description: None,
+            created_at: None,
+            expires_at: None,
+            last_used_at: self.storage.api_key_last_used(id, PRIMARY_KEY_ID),
+            expired: false,
+        }];
+        for key in merchant.api_keys {
+            keys.push(ApiKeyInfo {
+                last_used_at: self.storage.api_key_last_used(id, &key.id),
+                expired: key.expires_at.is_some_and(|expires_at| expires_at <= now),
+                id: key.id,
+                scope: key.scope,
+                description: key.description,
+                created_at: key.created_at,
+                expires_at: key.expires_at,
+            });
+        }
+        Ok(keys)
+    }
+
+    /// Выпустить дополнительный ключ с ограниченными правами
+    pub async fn create_api_key(&self, id: &str, request: CreateApiKeyRequest) -> anyhow::Result<CreatedApiKey> {
+        let now = Utc::now();
+        let mut errors = ValidationErrors::new();
+        if request.description.as_ref().is_some_and(|description| description.len() > MAX_KEY_DESCRIPTION_LENGTH) {
+            errors.add(
+                "description",
+                "too_long",
+                format!("Description must be at most {} bytes", MAX_KEY_DESCRIPTION_LENGTH),
+            );
+        }
+        if request.expires_at.is_some_and(|expires_at| expires_at <= now) {
+            errors.add("expires_at", "in_past", "Expiry must be in the future");
+        }
+        let existing = self.api_keys(id).await?.len() - 1;
+        if existing >= MAX_API_KEYS_PER_MERCHANT {
+            errors.add(
+                "api_keys",
+                "too_many",
+                format!("At most {} additional API keys per merchant", MAX_API_KEYS_PER_MERCHANT),
+            );
+        }
+        errors.into_result()?;
+
+        let key = MerchantApiKey {
+            id: format!("key_{}", &Uuid::new_v4().simple().to_string()[..12]),
+            api_key: generate_api_key(),
+            scope: request.scope,
+            description: request.description,
+            expires_at: request.expires_at,
+            created_at: Some(now),
+        };
+        self.storage.add_tenant_api_key(id, key.clone()).await?;
+        tracing::info!(merchant = id, key_id = %key.id, scope = %key.scope, expires_at = ?key.expires_at, "API key created");
+
+        Ok(CreatedApiKey {
+            key: ApiKeyInfo {
+                id: key.id,
+                scope: key.scope,
+                description: key.description,
+                created_at: key.created_at,
+                expires_at: key.expires_at,
+                last_used_at: None,
+                expired: false,
+            },
+            api_key: key.api_key,
+        })
+    }
+
+    /// Отозвать дополнительный ключ; основной отзывается `revoke_api_key`
+    pub async fn delete_api_key(&self, id: &str, key_id: &str) -> anyhow::Result<()> {
+        self.storage.remove_tenant_api_key(id, key_id).await?;
+        tracing::warn!(merchant = id, key_id, "API key deleted");
+        Ok(())
+    }
+
+    /// ID и настройки мерчанта; ошибки настроек — в поле `merchant`
+    fn check(&self, merchant: &MerchantConfig) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        if merchant.id.is_empty() || merchant.id.len() > MAX_TENANT_ID_LENGTH
+            || !merchant.id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            errors.add(
+                "id",
+                "invalid",
+                format!("Merchant ID must be 1 to {} letters, digits, '-' or '_'", MAX_TENANT_ID_LENGTH),
+            );
+        }
+        for error in self.config.merchant_errors(merchant) {
+            errors.add("merchant", "invalid", error);
+        }
+        errors.into_result()
+    }
+
+    /// Удалить мерчанта, созданного через admin API
+    pub async fn delete(&self, id: &str) -> anyhow::Result<Tenant> {
+        let tenant = self.storage.delete_tenant(id).await?;
+        tracing::info!(merchant = %tenant.merchant.id, "Merchant deleted");
+        Ok(tenant.redacted())
+    }
+}
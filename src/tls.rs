@@ -0,0 +1,90 @@
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::{self, CertifiedKey};
+use rustls::{Certificate, PrivateKey, ServerConfig};
+use std::io::BufReader;
+use std::sync::{Arc, RwLock};
+
+/// Текущий сертификат сервера; подменяется без перезапуска (ACME продление).
+/// Пока сертификата нет, TLS handshake отклоняется
+#[derive(Clone, Default)]
+pub struct CertResolver {
+    current: Arc<RwLock<Option<Arc<CertifiedKey>>>>,
+}
+
+impl CertResolver {
+    pub fn set(&self, key: CertifiedKey) {
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(key));
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        self.current.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+/// TLS конфигурация сервера с подменяемым сертификатом.
+/// ALPN (h2, http/1.1) actix добавляет сам
+pub fn server_config(resolver: CertResolver) -> ServerConfig {
+    ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_cert_resolver(Arc::new(resolver))
+}
+
+/// Сертификат (цепочка) и приватный ключ из PEM файлов
+pub fn load_certified_key(cert_path: &str, key_path: &str) -> anyhow::Result<CertifiedKey> {
+    let cert_pem = std::fs::read(cert_path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", cert_path, e))?;
+    let key_pem = std::fs::read(key_path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", key_path, e))?;
+    certified_key_from_pem(&cert_pem, &key_pem)
+        .map_err(|e| anyhow::anyhow!("{} / {}: {}", cert_path, key_path, e))
+}
+
+/// Сертификат и ключ из PEM в памяти
+pub fn certified_key_from_pem(cert_pem: &[u8], key_pem: &[u8]) -> anyhow::Result<CertifiedKey> {
+    let certs = read_pem(cert_pem)?
+        .into_iter()
+        .filter_map(|item| match item {
+            rustls_pemfile::Item::X509Certificate(der) => Some(Certificate(der)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if certs.is_empty() {
+        anyhow::bail!("No certificates found");
+    }
+
+    let key = read_pem(key_pem)?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::PKCS8Key(der)
+            | rustls_pemfile::Item::RSAKey(der)
+            | rustls_pemfile::Item::ECKey(der) => Some(PrivateKey(der)),
+            _ => None,
+        })
+        .ok_or_else(|| anyhow::anyhow!("No private key found"))?;
+
+    let signing_key = sign::any_supported_type(&key)
+        .map_err(|e| anyhow::anyhow!("Unsupported private key: {}", e))?;
+    Ok(CertifiedKey::new(certs, signing_key))
+}
+
+fn read_pem(pem: &[u8]) -> anyhow::Result<Vec<rustls_pemfile::Item>> {
+    rustls_pemfile::read_all(&mut BufReader::new(pem)).map_err(|e| anyhow::anyhow!("Invalid PEM: {}", e))
+}
+
+/// Публичный https адрес для редиректа с plain HTTP
+#[derive(Clone)]
+pub struct HttpsRedirect {
+    pub base_url: String,
+}
+
+/// Постоянный редирект на тот же путь по https
+pub async fn redirect_to_https(req: HttpRequest, redirect: web::Data<HttpsRedirect>) -> HttpResponse {
+    let path = req.uri().path_and_query().map(|p| p.as_str()).unwrap_or("/");
+    HttpResponse::PermanentRedirect()
+        .insert_header((header::LOCATION, format!("{}{}", redirect.base_url, path)))
+        .finish()
+}
@@ -0,0 +1,300 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+use crate::config::TokenConfig;
+use crate::rpc::SolanaRpc;
+use crate::validation::ValidationErrors;
+
+/// Программа Metaplex Token Metadata
+pub const TOKEN_METADATA_PROGRAM_ID: &str = "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s";
+
+/// Программа Token-2022
+const TOKEN_2022_PROGRAM_ID: &str = "TokenzQdBNbLqP5VEhdkAS14EPSu53ajcEvtmaCkS5w";
+
+/// Сколько держим в кэше скачанный token list
+const TOKEN_LIST_TTL_SECONDS: i64 = 3600;
+
+/// Таймаут запросов token list и JSON метаданных (логотип)
+const FETCH_TIMEOUT_SECS: u64 = 5;
+
+/// Максимальная длина символа токена
+pub const MAX_SYMBOL_LENGTH: usize = 10;
+
+/// Откуда токен попал в реестр
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenSource {
+    /// `solana.supported_tokens`
+    Config,
+    /// Solana token list (`solana.token_list_url`)
+    TokenList,
+    /// Metaplex метаданные mint в блокчейне
+    OnChain,
+}
+
+/// Токен реестра
+#[derive(Debug, Clone, Serialize)]
+pub struct RegisteredToken {
+    #[serde(flatten)]
+    pub token: TokenConfig,
+    pub source: TokenSource,
+    /// Время добавления через admin API (`None` — из конфигурации)
+    pub added_at: Option<DateTime<Utc>>,
+}
+
+/// Запрос на добавление токена: символ из token list или адрес mint
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AddTokenRequest {
+    pub token: String,
+    /// Свой символ вместо найденного (например, если в метаданных его нет)
+    pub symbol: Option<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TokenError {
+    #[error("Token {0} was not found in the token list or on chain")]
+    NotFound(String),
+    #[error("Token {mint} is already registered as {symbol}")]
+    AlreadyRegistered { mint: String, symbol: String },
+    #[error("Token symbol {0} is already taken by another mint")]
+    SymbolTaken(String),
+    #[error("Account {0} is not a token mint")]
+    NotAMint(String),
+}
+
+/// Запись Solana token list (`{"tokens": [...]}`)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TokenListEntry {
+    address: String,
+    symbol: String,
+    name: String,
+    decimals: u8,
+    #[serde(rename = "logoURI")]
+    logo_uri: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenList {
+    tokens: Vec<TokenListEntry>,
+}
+
+/// Скачанный token list и время загрузки
+type TokenListCache = Arc<tokio::sync::RwLock<Option<(Vec<TokenListEntry>, DateTime<Utc>)>>>;
+
+/// Реестр токенов, которыми можно платить: символ ↔ mint и точность. Заполняется из
+/// `solana.supported_tokens` и дополняется во время работы (admin API): токен ищется
+/// в Solana token list, затем в Metaplex метаданных mint. Клоны делят одно состояние
+#[derive(Debug, Clone, Default)]
+pub struct TokenRegistry {
+    tokens: Arc<RwLock<Vec<RegisteredToken>>>,
+    token_list_url: Option<String>,
+    client: reqwest::Client,
+    token_list: TokenListCache,
+    /// Найденные в блокчейне токены по mint
+    on_chain: Arc<tokio::sync::RwLock<HashMap<String, TokenConfig>>>,
+}
+
+impl TokenRegistry {
+    pub fn new(tokens: &[TokenConfig], token_list_url: Option<String>) -> Self {
+        let tokens = tokens.iter()
+            .map(|token| RegisteredToken { token: token.clone(), source: TokenSource::Config, added_at: None })
+            .collect();
+        Self {
+            tokens: Arc::new(RwLock::new(tokens)),
+            token_list_url,
+            ..Self::default()
+        }
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, Vec<RegisteredToken>> {
+        self.tokens.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Токен по символу
+    pub fn get(&self, symbol: &str) -> Option<TokenConfig> {
+        self.read().iter().find(|t| t.token.symbol == symbol).map(|t| t.token.clone())
+    }
+
+    /// Токен по адресу mint
+    pub fn by_mint(&self, mint: &str) -> Option<TokenConfig> {
+        self.read().iter().find(|t| t.token.mint.as_deref() == Some(mint)).map(|t| t.token.clone())
+    }
+
+    /// Символы в порядке добавления
+    pub fn symbols(&self) -> Vec<String> {
+        self.read().iter().map(|t| t.token.symbol.clone()).collect()
+    }
+
+    /// Все токены с источником
+    pub fn list(&self) -> Vec<RegisteredToken> {
+        self.read().clone()
+    }
+
+    /// Найти токен без добавления: в реестре, в token list (символ или mint), затем
+    /// в блокчейне по mint
+    pub async fn resolve(&self, query: &str, rpc: &dyn SolanaRpc) -> anyhow::Result<(TokenConfig, TokenSource)> {
+        let query = query.trim();
+        if let Some(registered) = self.read().iter()
+            .find(|t| t.token.symbol == query || t.token.mint.as_deref() == Some(query))
+        {
+            return Ok((registered.token.clone(), registered.source));
+        }
+        if let Some(token) = self.find_in_token_list(query).await? {
+            return Ok((token, TokenSource::TokenList));
+        }
+        let Ok(mint) = Pubkey::from_str(query) else {
+            return Err(TokenError::NotFound(query.to_string()).into());
+        };
+        if let Some(token) = self.on_chain.read().await.get(query) {
+            return Ok((token.clone(), TokenSource::OnChain));
+        }
+        let token = self.fetch_from_chain(&mint, rpc).await?;
+        self.on_chain.write().await.insert(query.to_string(), token.clone());
+        Ok((token, TokenSource::OnChain))
+    }
+
+    /// Найти и добавить токен (admin API). Символ приводится к верхнему регистру
+    pub async fn add(&self, request: AddTokenRequest, rpc: &dyn SolanaRpc) -> anyhow::Result<RegisteredToken> {
+        let query = request.token.trim();
+        if let Some(existing) = self.read().iter()
+            .find(|t| t.token.symbol == query || t.token.mint.as_deref() == Some(query))
+        {
+            return Err(TokenError::AlreadyRegistered {
+                mint: query.to_string(),
+                symbol: existing.token.symbol.clone(),
+            }.into());
+        }
+        let (mut token, source) = self.resolve(query, rpc).await?;
+        if let Some(symbol) = request.symbol {
+            token.symbol = symbol;
+        }
+        token.symbol = token.symbol.trim().to_uppercase();
+
+        let mut errors = ValidationErrors::new();
+        if token.symbol.is_empty() || token.symbol.len() > MAX_SYMBOL_LENGTH
+            || !token.symbol.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            errors.add(
+                "symbol",
+                "invalid",
+                format!("Symbol must be 1 to {} letters or digits, got '{}'", MAX_SYMBOL_LENGTH, token.symbol),
+            );
+            return Err(errors.into());
+        }
+
+        let mut tokens = self.tokens.write().unwrap_or_else(|e| e.into_inner());
+        if let Some(existing) = tokens.iter().find(|t| t.token.mint.is_some() && t.token.mint == token.mint) {
+            return Err(TokenError::AlreadyRegistered {
+                mint: token.mint.clone().unwrap_or_default(),
+                symbol: existing.token.symbol.clone(),
+            }.into());
+        }
+        if tokens.iter().any(|t| t.token.symbol == token.symbol) {
+            return Err(TokenError::SymbolTaken(token.symbol).into());
+        }
+        let registered = RegisteredToken { token, source, added_at: Some(Utc::now()) };
+        tokens.push(registered.clone());
+        tracing::info!(
+            symbol = %registered.token.symbol,
+            mint = registered.token.mint.as_deref(),
+            source = ?registered.source,
+            "Token added to registry"
+        );
+        Ok(registered)
+    }
+
+    /// Токен из token list: точное совпадение mint или символ без учета регистра
+    async fn find_in_token_list(&self, query: &str) -> anyhow::Result<Option<TokenConfig>> {
+        let Some(url) = &self.token_list_url else {
+            return Ok(None);
+        };
+        let cached = self.token_list.read().await.as_ref()
+            .filter(|(_, fetched_at)| Utc::now() - *fetched_at < Duration::seconds(TOKEN_LIST_TTL_SECONDS))
+            .map(|(entries, _)| entries.clone());
+        let entries = match cached {
+            Some(entries) => entries,
+            None => {
+                let list: TokenList = self.client.get(url)
+                    .timeout(std::time::Duration::from_secs(FETCH_TIMEOUT_SECS))
+                    .send().await?
+                    .error_for_status()?
+                    .json().await?;
+                tracing::debug!(tokens = list.tokens.len(), "Token list fetched");
+                *self.token_list.write().await = Some((list.tokens.clone(), Utc::now()));
+                list.tokens
+            }
+        };
+        let entry = entries.iter().find(|entry| entry.address == query)
+            .or_else(|| entries.iter().find(|entry| entry.symbol.eq_ignore_ascii_case(query)));
+        Ok(entry.map(|entry| TokenConfig {
+            symbol: entry.symbol.clone(),
+            mint: Some(entry.address.clone()),
+            decimals: entry.decimals,
+            name: entry.name.clone(),
+            logo_uri: entry.logo_uri.clone(),
+        }))
+    }
+
+    /// Токен из аккаунта mint (точность) и его Metaplex метаданных (имя, символ, логотип).
+    /// Без метаданных символ и имя пустые
+    async fn fetch_from_chain(&self, mint: &Pubkey, rpc: &dyn SolanaRpc) -> anyhow::Result<TokenConfig> {
+        let account = rpc.get_account(mint).await?
+            .ok_or_else(|| TokenError::NotFound(mint.to_string()))?;
+        let token_2022 = Pubkey::from_str(TOKEN_2022_PROGRAM_ID)?;
+        if account.owner != spl_token::id() && account.owner != token_2022 {
+            return Err(TokenError::NotAMint(mint.to_string()).into());
+        }
+        // Mint: mint_authority (36 байт), supply (8), decimals (1), is_initialized (1)
+        let decimals = *account.data.get(44)
+            .ok_or_else(|| TokenError::NotAMint(mint.to_string()))?;
+
+        let program = Pubkey::from_str(TOKEN_METADATA_PROGRAM_ID)?;
+        let (metadata_address, _) = Pubkey::find_program_address(
+            &[b"metadata", program.as_ref(), mint.as_ref()],
+            &program,
+        );
+        let metadata = rpc.get_account(&metadata_address).await?
+            .and_then(|account| parse_metadata(&account.data));
+        let (name, symbol, uri) = metadata.unwrap_or_default();
+        let logo_uri = match uri.is_empty() {
+            true => None,
+            false => self.fetch_logo(&uri).await,
+        };
+        Ok(TokenConfig { symbol, mint: Some(mint.to_string()), decimals, name, logo_uri })
+    }
+
+    /// Поле `image` JSON метаданных по `uri`; ошибки не мешают добавить токен
+    async fn fetch_logo(&self, uri: &str) -> Option<String> {
+        let response = self.client.get(uri)
+            .timeout(std::time::Duration::from_secs(FETCH_TIMEOUT_SECS))
+            .send().await
+            .and_then(|response| response.error_for_status());
+        let json: serde_json::Value = match response {
+            Ok(response) => response.json().await.ok()?,
+            Err(e) => {
+                tracing::debug!(uri, error = %e, "Failed to fetch token metadata JSON");
+                return None;
+            }
+        };
+        json["image"].as_str().map(str::to_string)
+    }
+}
+
+/// Имя, символ и URI из аккаунта Metaplex метаданных: key (1 байт), update_authority (32),
+/// mint (32), затем три borsh строки, дополненные нулями
+fn parse_metadata(data: &[u8]) -> Option<(String, String, String)> {
+    let mut offset = 1 + 32 + 32;
+    let mut next = || {
+        let len = u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?) as usize;
+        let bytes = data.get(offset + 4..offset + 4 + len)?;
+        offset += 4 + len;
+        Some(String::from_utf8_lossy(bytes).trim_end_matches('\0').trim().to_string())
+    };
+    Some((next()?, next()?, next()?))
+}
@@ -0,0 +1,1191 @@
+use base64::{Engine as _, engine::general_purpose};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    address_lookup_table::{state::AddressLookupTable, AddressLookupTableAccount},
+    hash::Hash,
+    instruction::{AccountMeta, Instruction},
+    message::{v0, Message, VersionedMessage},
+    packet::PACKET_DATA_SIZE,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::Signature,
+    system_instruction::{self, SystemInstruction},
+    system_program,
+    transaction::{Transaction, VersionedTransaction},
+};
+use spl_token::instruction::{self as token_instruction, TokenInstruction};
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::str::FromStr;
+use tokio::time::{timeout, Duration};
+
+use crate::compute_budget::{ComputeBudget, ComputeEstimator};
+use crate::config::{Config, RentPolicy};
+use crate::display::to_base_units;
+use crate::i18n::Locale;
+use crate::payment;
+use crate::plugins::DecoratorContext;
+use crate::rpc::SolanaRpc;
+use crate::sponsorship::{self, SponsorshipService};
+use crate::swap::SwapInstructions;
+
+/// Таймаут проверки аккаунта получателя
+const RENT_CHECK_TIMEOUT_SECS: u64 = 5;
+
+/// Запас SOL плательщика на сетевую комиссию, ниже которого wSOL разворачивается
+const UNWRAP_FEE_RESERVE_LAMPORTS: u64 = 10_000;
+
+/// Таймаут получения blockhash (вместе с резервными RPC)
+const BLOCKHASH_TIMEOUT_SECS: u64 = 15;
+
+/// Таймаут загрузки lookup таблицы маршрута свопа
+const LOOKUP_TABLE_TIMEOUT_SECS: u64 = 5;
+
+/// SPL Memo program v2
+pub const MEMO_PROGRAM_ID: Pubkey = solana_sdk::pubkey!("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr");
+
+/// Какую часть платежа собрать в транзакцию.
+///
+/// Если полная транзакция не помещается в лимит размера, кошелек может
+/// запросить основной перевод и комиссию двумя отдельными транзакциями.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionPart {
+    /// Основной перевод, комиссия и memo в одной транзакции
+    #[default]
+    Full,
+    /// Только основной перевод (и memo)
+    Main,
+    /// Только комиссия
+    Fee,
+}
+
+/// Запрос транзакции, выданной кошельку: по нему транзакция пересобирается
+/// со свежим blockhash (те же инструкции, тот же reference)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssuedTransaction {
+    pub payment_id: String,
+    pub account: String,
+    pub part: TransactionPart,
+    pub locale: Locale,
+    /// Инструкции свопа выданной транзакции — котировка не перезапрашивается
+    pub swap: Option<SwapInstructions>,
+    /// Hot wallet, спонсирующий комиссию (gasless транзакция)
+    pub fee_payer: Option<String>,
+    /// Compute бюджет последней собранной транзакции
+    pub compute_budget: Option<ComputeBudget>,
+    pub issued_at: chrono::DateTime<chrono::Utc>,
+    /// Сколько раз транзакция пересобиралась
+    pub refreshes: u32,
+    pub refreshed_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RefreshError {
+    #[error("No transaction was issued for this payment yet")]
+    NotIssued,
+    #[error("Payment is no longer pending")]
+    NotPending,
+    #[error("Transaction was refreshed recently, retry in {retry_after_secs}s")]
+    TooSoon { retry_after_secs: u64 },
+    #[error("Transaction refresh limit reached, create a new payment")]
+    LimitReached,
+}
+
+/// Результат сборки транзакции для Solana Pay
+#[derive(Debug, Clone, Serialize)]
+pub struct BuiltTransaction {
+    /// Неподписанная транзакция в base64
+    pub transaction: String,
+    /// Предупреждения для плательщика (например, доплата до rent-exempt минимума)
+    pub notes: Vec<String>,
+    /// Лимит compute units и priority fee, если они добавлены в транзакцию
+    pub compute_budget: Option<ComputeBudget>,
+}
+
+/// Инструкции, которые выбрасываются, если транзакция не помещается в лимит размера
+#[derive(Debug, Clone)]
+pub struct OptionalInstructions {
+    pub range: Range<usize>,
+    /// Пояснение плательщику, если инструкции выброшены
+    pub note: String,
+}
+
+/// Сдвинуть диапазоны после вставки `offset` инструкций в начало
+fn shift_optional(optional: &mut [OptionalInstructions], offset: usize) {
+    for omitted in optional {
+        omitted.range = omitted.range.start + offset..omitted.range.end + offset;
+    }
+}
+
+/// Параметры сборки транзакции платежа
+#[derive(Default, Clone, Copy)]
+struct BuildOptions<'a> {
+    /// Спонсор комиссии вместо плательщика
+    fee_payer: Option<&'a Pubkey>,
+    /// Без blockhash берется свежий
+    recent_blockhash: Option<Hash>,
+    /// Бюджет выданной транзакции (при пересборке для сверки)
+    compute_budget: Option<ComputeBudget>,
+    /// Оценка бюджета симуляцией, если готового нет
+    estimator: Option<&'a ComputeEstimator>,
+}
+
+// ПРАВИЛЬНАЯ функция создания транзакции с двумя переводами
+// ПРАВИЛЬНАЯ функция создания ОДНОЙ транзакции с несколькими инструкциями
+pub async fn create_payment_transaction(
+    payment: &payment::Payment,
+    payer_str: &str,
+    config: &Config,
+    rpc: &dyn SolanaRpc,
+    part: TransactionPart,
+    estimator: Option<&ComputeEstimator>,
+) -> anyhow::Result<BuiltTransaction> {
+    let (serialized, notes, compute_budget) = build_payment_transaction(
+        payment, payer_str, config, rpc, part, BuildOptions { estimator, ..Default::default() },
+    ).await?;
+
+    // 6. СЕРИАЛИЗУЕМ В BASE64
+    let base64_transaction = general_purpose::STANDARD.encode(&serialized);
+    tracing::debug!(payment_id = %payment.id, size_bytes = serialized.len(), "Transaction serialized");
+
+    Ok(BuiltTransaction {
+        transaction: base64_transaction,
+        notes,
+        compute_budget,
+    })
+}
+
+/// Gasless транзакция платежа: fee payer — hot wallet, он же платит rent создаваемых
+/// токен-аккаунтов. Расход резервируется в лимитах спонсирования, транзакция возвращается
+/// подписанной hot wallet — кошелек плательщика только добавляет свою подпись
+pub async fn create_sponsored_payment_transaction(
+    payment: &payment::Payment,
+    payer_str: &str,
+    config: &Config,
+    rpc: &dyn SolanaRpc,
+    part: TransactionPart,
+    sponsorship: &SponsorshipService,
+    estimator: Option<&ComputeEstimator>,
+) -> anyhow::Result<BuiltTransaction> {
+    let signer = sponsorship.signer()?;
+    let fee_payer = signer.pubkey();
+    if payer_str == fee_payer.to_string() {
+        anyhow::bail!("Payer must not be the sponsoring hot wallet");
+    }
+
+    let options = BuildOptions { fee_payer: Some(&fee_payer), estimator, ..Default::default() };
+    let (serialized, mut notes, compute_budget) = build_payment_transaction(
+        payment, payer_str, config, rpc, part, options,
+    ).await?;
+    let mut transaction: Transaction = bincode::deserialize(&serialized)?;
+    let lamports = sponsorship::max_cost(&transaction.message, compute_budget.as_ref());
+    sponsorship.reserve(&payment.id, lamports).await?;
+
+    let recent_blockhash = transaction.message.recent_blockhash;
+    transaction.try_partial_sign(&[signer.keypair()], recent_blockhash)
+        .map_err(|e| anyhow::anyhow!("Failed to sign as fee payer: {}", e))?;
+    tracing::info!(payment_id = %payment.id, lamports, "Network fee sponsored");
+    notes.push("Network fee is covered by the merchant".to_string());
+
+    let serialized = bincode::serialize(&transaction)
+        .map_err(|e| anyhow::anyhow!("Failed to serialize transaction: {}", e))?;
+    Ok(BuiltTransaction {
+        transaction: general_purpose::STANDARD.encode(&serialized),
+        notes,
+        compute_budget,
+    })
+}
+
+/// Неподписанная транзакция платежа и ее compute бюджет
+async fn build_payment_transaction(
+    payment: &payment::Payment,
+    payer_str: &str,
+    config: &Config,
+    rpc: &dyn SolanaRpc,
+    part: TransactionPart,
+    options: BuildOptions<'_>,
+) -> anyhow::Result<(Vec<u8>, Vec<String>, Option<ComputeBudget>)> {
+    let payer = Pubkey::from_str(payer_str)
+        .map_err(|e| anyhow::anyhow!("Invalid payer address: {}", e))?;
+    let mut notes = Vec::new();
+    let (mut instructions, memo_index) = payment_instructions(payment, &payer, config, rpc, part, &mut notes).await?;
+
+    // Rent новых токен-аккаунтов платит спонсор: у плательщика может не быть SOL
+    let fee_payer = options.fee_payer.copied().unwrap_or(payer);
+    for instruction in instructions.iter_mut().filter(|i| i.program_id == spl_associated_token_account::id()) {
+        instruction.accounts[0] = AccountMeta::new(fee_payer, true);
+    }
+
+    // Инструкции плагинов оператора — до оценки бюджета, чтобы симуляция их учла
+    let context = DecoratorContext { payment, payer: &payer, fee_payer: &fee_payer, part, compute_budget_set: false };
+    let (instructions, mut optional) = config.decorators.decorate(&context, instructions, memo_index)?;
+
+    // 3. ПОЛУЧАЕМ СВЕЖИЙ BLOCKHASH
+    let recent_blockhash = match options.recent_blockhash {
+        Some(blockhash) => blockhash,
+        None => latest_blockhash(rpc).await?,
+    };
+
+    // Лимит compute units по симуляции и priority fee — в начало транзакции
+    let compute_budget = match (options.compute_budget, options.estimator) {
+        (Some(budget), _) => Some(budget),
+        (None, Some(estimator)) => estimator.estimate(rpc, &instructions, &fee_payer).await,
+        (None, None) => None,
+    };
+    let instructions = match &compute_budget {
+        Some(budget) => {
+            tracing::debug!(
+                unit_limit = budget.unit_limit,
+                unit_price_micro_lamports = budget.unit_price_micro_lamports,
+                "Compute budget applied"
+            );
+            let mut budgeted = budget.instructions();
+            shift_optional(&mut optional, budgeted.len());
+            budgeted.extend(instructions);
+            budgeted
+        }
+        None => instructions,
+    };
+
+    // 4. СОЗДАЕМ ОДНУ ТРАНЗАКЦИЮ СО ВСЕМИ ИНСТРУКЦИЯМИ
+    tracing::debug!(instructions = instructions.len(), "Creating transaction");
+    let serialized = fit_transaction(instructions, &optional, part, &mut notes, |instructions| {
+        serialize_transaction(instructions, &fee_payer, recent_blockhash)
+    })?;
+    Ok((serialized, notes, compute_budget))
+}
+
+/// Разбор транзакции платежа до подписи: что и кому переведет плательщик, какие
+/// токен-аккаунты создадутся и сколько это стоит. Для экрана оплаты в checkout
+#[derive(Debug, Clone, Serialize)]
+pub struct TransactionPreview {
+    pub payment_id: String,
+    pub part: TransactionPart,
+    pub payer: String,
+    /// Платит комиссию сети и rent новых токен-аккаунтов: плательщик или спонсор
+    pub fee_payer: String,
+    pub instructions: Vec<PreviewInstruction>,
+    pub network_fee: NetworkFee,
+    /// Rent токен-аккаунтов, которых еще нет в сети
+    pub rent_lamports: u64,
+    /// Итого спишется с плательщика по токенам; в SOL — с комиссией сети и rent, если их платит он
+    pub debits: BTreeMap<String, f64>,
+    pub size_bytes: usize,
+    pub notes: Vec<String>,
+}
+
+/// Оценка комиссии сети
+#[derive(Debug, Clone, Serialize)]
+pub struct NetworkFee {
+    pub signatures: u8,
+    pub signature_lamports: u64,
+    pub priority_lamports: u64,
+    pub compute_budget: Option<ComputeBudget>,
+    pub total_lamports: u64,
+}
+
+/// Инструкция транзакции в читаемом виде. Адреса переводов — владельцы кошельков,
+/// если токен-аккаунт удалось сопоставить с участником платежа
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PreviewInstruction {
+    Transfer { token: String, amount: f64, from: String, to: String },
+    CreateTokenAccount { token: String, owner: String, account: String, exists: bool, rent_lamports: u64 },
+    /// Перевод SOL на свой wSOL аккаунт
+    WrapSol { amount: f64 },
+    SyncNative { account: String },
+    CloseTokenAccount { account: String },
+    Memo { text: String },
+    ComputeBudget,
+    Program { program_id: String },
+}
+
+/// Токен-аккаунты участников платежа: ATA → (владелец, символ токена)
+struct KnownAccounts<'a> {
+    config: &'a Config,
+    accounts: Vec<(Pubkey, Pubkey, String)>,
+}
+
+impl<'a> KnownAccounts<'a> {
+    fn new(config: &'a Config, owners: &[Pubkey]) -> Self {
+        let mut accounts = Vec::new();
+        for registered in config.tokens.list() {
+            let token = registered.token;
+            let Some(mint) = token.mint.as_deref().and_then(|mint| Pubkey::from_str(mint).ok()) else {
+                continue;
+            };
+            for owner in owners {
+                let account = spl_associated_token_account::get_associated_token_address(owner, &mint);
+                accounts.push((account, *owner, token.symbol.clone()));
+            }
+        }
+        Self { config, accounts }
+    }
+
+    fn find(&self, account: &Pubkey) -> Option<(Pubkey, &str)> {
+        self.accounts.iter().find(|(known, ..)| known == account).map(|(_, owner, symbol)| (*owner, symbol.as_str()))
+    }
+
+    /// Символ токена по mint (неизвестный — сам mint)
+    fn symbol(&self, mint: &Pubkey) -> String {
+        let mint = mint.to_string();
+        self.config.tokens.by_mint(&mint).map_or(mint, |token| token.symbol)
+    }
+
+    fn decimals(&self, symbol: &str) -> u8 {
+        self.config.token_decimals(symbol)
+    }
+}
+
+/// Собрать транзакцию платежа так же, как для кошелька, и разобрать ее без выдачи.
+/// `fee_payer` — спонсор комиссии сети (gasless), иначе платит плательщик
+pub async fn preview_payment_transaction(
+    payment: &payment::Payment,
+    payer_str: &str,
+    config: &Config,
+    rpc: &dyn SolanaRpc,
+    part: TransactionPart,
+    fee_payer: Option<&Pubkey>,
+    estimator: Option<&ComputeEstimator>,
+) -> anyhow::Result<TransactionPreview> {
+    let payer = Pubkey::from_str(payer_str)
+        .map_err(|e| anyhow::anyhow!("Invalid payer address: {}", e))?;
+    let options = BuildOptions { fee_payer, estimator, ..Default::default() };
+    let (serialized, notes, compute_budget) = build_payment_transaction(
+        payment, payer_str, config, rpc, part, options,
+    ).await?;
+    let transaction: Transaction = bincode::deserialize(&serialized)?;
+    let message = &transaction.message;
+
+    let owners = [payer, Pubkey::from_str(&payment.recipient)?, Pubkey::from_str(&payment.fee_recipient)?];
+    let known = KnownAccounts::new(config, &owners);
+    let wsol_account = spl_associated_token_account::get_associated_token_address(&payer, &spl_token::native_mint::id());
+    let token_account_rent = Rent::default().minimum_balance(sponsorship::TOKEN_ACCOUNT_LEN);
+    let owner_of = |account: &Pubkey| known.find(account).map_or(*account, |(owner, _)| owner).to_string();
+
+    let mut instructions = Vec::with_capacity(message.instructions.len());
+    let mut debits: BTreeMap<String, u128> = BTreeMap::new();
+    let mut rent_lamports = 0;
+    for compiled in &message.instructions {
+        let program_id = message.account_keys[compiled.program_id_index as usize];
+        let accounts: Vec<Pubkey> = compiled.accounts.iter().map(|&index| message.account_keys[index as usize]).collect();
+        let data = compiled.data.as_slice();
+
+        let instruction = if program_id == system_program::ID {
+            match bincode::deserialize(data) {
+                Ok(SystemInstruction::Transfer { lamports }) if accounts[1] == wsol_account => {
+                    PreviewInstruction::WrapSol { amount: lamports as f64 / 1_000_000_000.0 }
+                }
+                Ok(SystemInstruction::Transfer { lamports }) => {
+                    if accounts[0] == payer {
+                        *debits.entry("SOL".to_string()).or_default() += lamports as u128;
+                    }
+                    PreviewInstruction::Transfer {
+                        token: "SOL".to_string(),
+                        amount: lamports as f64 / 1_000_000_000.0,
+                        from: accounts[0].to_string(),
+                        to: accounts[1].to_string(),
+                    }
+                }
+                _ => PreviewInstruction::Program { program_id: program_id.to_string() },
+            }
+        } else if program_id == spl_token::ID {
+            match TokenInstruction::unpack(data) {
+                Ok(TokenInstruction::Transfer { amount }) => {
+                    let symbol = known.find(&accounts[1]).or_else(|| known.find(&accounts[0]))
+                        .map_or_else(|| "unknown".to_string(), |(_, symbol)| symbol.to_string());
+                    if accounts[2] == payer {
+                        *debits.entry(symbol.clone()).or_default() += amount as u128;
+                    }
+                    PreviewInstruction::Transfer {
+                        amount: amount as f64 / 10_f64.powi(known.decimals(&symbol) as i32),
+                        token: symbol,
+                        from: owner_of(&accounts[0]),
+                        to: owner_of(&accounts[1]),
+                    }
+                }
+                Ok(TokenInstruction::SyncNative) => PreviewInstruction::SyncNative { account: accounts[0].to_string() },
+                Ok(TokenInstruction::CloseAccount) => {
+                    PreviewInstruction::CloseTokenAccount { account: accounts[0].to_string() }
+                }
+                _ => PreviewInstruction::Program { program_id: program_id.to_string() },
+            }
+        } else if program_id == spl_associated_token_account::id() {
+            // Создание идемпотентное: rent платится, только если аккаунта еще нет
+            let (account, owner, mint) = (accounts[1], accounts[2], accounts[3]);
+            let exists = matches!(
+                timeout(Duration::from_secs(RENT_CHECK_TIMEOUT_SECS), rpc.get_account(&account)).await,
+                Ok(Ok(Some(_)))
+            );
+            let rent = if exists { 0 } else { token_account_rent };
+            rent_lamports += rent;
+            PreviewInstruction::CreateTokenAccount {
+                token: known.symbol(&mint),
+                owner: owner.to_string(),
+                account: account.to_string(),
+                exists,
+                rent_lamports: rent,
+            }
+        } else if program_id == MEMO_PROGRAM_ID {
+            PreviewInstruction::Memo { text: String::from_utf8_lossy(data).into_owned() }
+        } else if program_id == solana_sdk::compute_budget::id() {
+            PreviewInstruction::ComputeBudget
+        } else {
+            PreviewInstruction::Program { program_id: program_id.to_string() }
+        };
+        instructions.push(instruction);
+    }
+
+    let signatures = message.header.num_required_signatures;
+    let signature_lamports = sponsorship::LAMPORTS_PER_SIGNATURE * signatures as u64;
+    let priority_lamports = compute_budget.as_ref().map_or(0, ComputeBudget::priority_fee_lamports);
+    let network_fee = NetworkFee {
+        signatures,
+        signature_lamports,
+        priority_lamports,
+        compute_budget,
+        total_lamports: signature_lamports + priority_lamports,
+    };
+    let fee_payer = fee_payer.copied().unwrap_or(payer);
+    if fee_payer == payer {
+        *debits.entry("SOL".to_string()).or_default() += (network_fee.total_lamports + rent_lamports) as u128;
+    }
+
+    Ok(TransactionPreview {
+        payment_id: payment.id.clone(),
+        part,
+        payer: payer.to_string(),
+        fee_payer: fee_payer.to_string(),
+        instructions,
+        network_fee,
+        rent_lamports,
+        debits: debits.into_iter()
+            .map(|(symbol, base_units)| {
+                let amount = base_units as f64 / 10_f64.powi(known.decimals(&symbol) as i32);
+                (symbol, amount)
+            })
+            .collect(),
+        size_bytes: serialized.len(),
+        notes,
+    })
+}
+
+/// Подписант транзакции и есть ли его действительная подпись
+#[derive(Debug, Clone, Serialize)]
+pub struct DecodedSigner {
+    pub address: String,
+    pub signed: bool,
+}
+
+/// Инструкция транзакции: аккаунты как в сообщении и разбор в виде превью платежа
+#[derive(Debug, Clone, Serialize)]
+pub struct DecodedInstruction {
+    pub program_id: String,
+    pub accounts: Vec<String>,
+    pub parsed: PreviewInstruction,
+}
+
+/// Сверка с транзакцией, выданной для платежа, — та же, что при отправке через сервер
+#[derive(Debug, Clone, Serialize)]
+pub struct IssuedComparison {
+    pub payment_id: String,
+    pub matches: bool,
+    /// Части сообщения, отличающиеся от выданного: `version`, `header`, `account_keys`,
+    /// `instructions`, `instruction <номер>`, `address_table_lookups`
+    pub differences: Vec<String>,
+}
+
+/// Транзакция кошелька в читаемом виде для отладки
+#[derive(Debug, Clone, Serialize)]
+pub struct DecodedTransaction {
+    /// `legacy` или `v0`
+    pub version: String,
+    pub fee_payer: String,
+    pub recent_blockhash: String,
+    pub signers: Vec<DecodedSigner>,
+    /// Аккаунты сообщения, для v0 — вместе с адресами из lookup таблиц
+    pub account_keys: Vec<String>,
+    pub instructions: Vec<DecodedInstruction>,
+    pub size_bytes: usize,
+    pub issued: Option<IssuedComparison>,
+}
+
+/// Токен-аккаунты и mint, прочитанные через RPC при разборе (по разу на адрес)
+struct TokenAccounts<'a> {
+    config: &'a Config,
+    rpc: &'a dyn SolanaRpc,
+    accounts: BTreeMap<Pubkey, Option<spl_token::state::Account>>,
+}
+
+impl TokenAccounts<'_> {
+    async fn get(&mut self, address: &Pubkey) -> Option<spl_token::state::Account> {
+        if let Some(account) = self.accounts.get(address) {
+            return *account;
+        }
+        let account = match timeout(Duration::from_secs(RENT_CHECK_TIMEOUT_SECS), self.rpc.get_account(address)).await {
+            Ok(Ok(Some(account))) => spl_token::state::Account::unpack(&account.data).ok(),
+            _ => None,
+        };
+        self.accounts.insert(*address, account);
+        account
+    }
+
+    /// Владелец токен-аккаунта, иначе сам адрес
+    async fn owner(&mut self, address: &Pubkey) -> String {
+        self.get(address).await.map_or(*address, |account| account.owner).to_string()
+    }
+
+    /// Символ и decimals токена: из реестра токенов, иначе mint и decimals его аккаунта
+    async fn token(&self, mint: &Pubkey) -> (String, u8) {
+        if let Some(token) = self.config.tokens.by_mint(&mint.to_string()) {
+            return (token.symbol, token.decimals);
+        }
+        let decimals = match timeout(Duration::from_secs(RENT_CHECK_TIMEOUT_SECS), self.rpc.get_account(mint)).await {
+            Ok(Ok(Some(account))) => spl_token::state::Mint::unpack(&account.data).map_or(0, |mint| mint.decimals),
+            _ => 0,
+        };
+        (mint.to_string(), decimals)
+    }
+}
+
+async fn decode_instruction(
+    program_id: &Pubkey,
+    accounts: &[Pubkey],
+    data: &[u8],
+    tokens: &mut TokenAccounts<'_>,
+) -> PreviewInstruction {
+    let other = || PreviewInstruction::Program { program_id: program_id.to_string() };
+
+    if *program_id == system_program::ID {
+        match (bincode::deserialize(data), accounts) {
+            (Ok(SystemInstruction::Transfer { lamports }), [from, to, ..]) => PreviewInstruction::Transfer {
+                token: "SOL".to_string(),
+                amount: lamports as f64 / 1_000_000_000.0,
+                from: from.to_string(),
+                to: to.to_string(),
+            },
+            _ => other(),
+        }
+    } else if *program_id == spl_token::ID {
+        match (TokenInstruction::unpack(data), accounts) {
+            (Ok(TokenInstruction::Transfer { amount }), [source, destination, ..]) => {
+                let mint = match tokens.get(source).await {
+                    Some(account) => Some(account.mint),
+                    None => tokens.get(destination).await.map(|account| account.mint),
+                };
+                let (token, decimals) = match mint {
+                    Some(mint) => tokens.token(&mint).await,
+                    None => ("unknown".to_string(), 0),
+                };
+                PreviewInstruction::Transfer {
+                    token,
+                    amount: amount as f64 / 10_f64.powi(decimals as i32),
+                    from: tokens.owner(source).await,
+                    to: tokens.owner(destination).await,
+                }
+            }
+            (Ok(TokenInstruction::TransferChecked { amount, decimals }), [source, mint, destination, ..]) => {
+                PreviewInstruction::Transfer {
+                    token: tokens.token(mint).await.0,
+                    amount: amount as f64 / 10_f64.powi(decimals as i32),
+                    from: tokens.owner(source).await,
+                    to: tokens.owner(destination).await,
+                }
+            }
+            (Ok(TokenInstruction::SyncNative), [account, ..]) => {
+                PreviewInstruction::SyncNative { account: account.to_string() }
+            }
+            (Ok(TokenInstruction::CloseAccount), [account, ..]) => {
+                PreviewInstruction::CloseTokenAccount { account: account.to_string() }
+            }
+            _ => other(),
+        }
+    } else if *program_id == spl_associated_token_account::id() {
+        match accounts {
+            [_, account, owner, mint, ..] => {
+                let exists = tokens.get(account).await.is_some();
+                PreviewInstruction::CreateTokenAccount {
+                    token: tokens.token(mint).await.0,
+                    owner: owner.to_string(),
+                    account: account.to_string(),
+                    exists,
+                    rent_lamports: if exists { 0 } else { Rent::default().minimum_balance(sponsorship::TOKEN_ACCOUNT_LEN) },
+                }
+            }
+            _ => other(),
+        }
+    } else if *program_id == MEMO_PROGRAM_ID {
+        PreviewInstruction::Memo { text: String::from_utf8_lossy(data).into_owned() }
+    } else if *program_id == solana_sdk::compute_budget::id() {
+        PreviewInstruction::ComputeBudget
+    } else {
+        other()
+    }
+}
+
+/// Разобрать транзакцию кошелька: подписанты, аккаунты (с адресами lookup таблиц v0) и
+/// инструкции в том же виде, что превью платежа. Токен-аккаунты переводов читаются через
+/// RPC, чтобы показать токен и владельцев; не найденные остаются адресами
+pub async fn decode_transaction(
+    transaction: &VersionedTransaction,
+    config: &Config,
+    rpc: &dyn SolanaRpc,
+) -> anyhow::Result<DecodedTransaction> {
+    let message = &transaction.message;
+    let mut account_keys = message.static_account_keys().to_vec();
+    if let Some(lookups) = message.address_table_lookups().filter(|lookups| !lookups.is_empty()) {
+        let keys: Vec<Pubkey> = lookups.iter().map(|lookup| lookup.account_key).collect();
+        let tables = load_lookup_tables(rpc, &keys).await?;
+        // Сначала записываемые адреса всех таблиц, затем только для чтения
+        for writable in [true, false] {
+            for (lookup, table) in lookups.iter().zip(&tables) {
+                let indexes = if writable { &lookup.writable_indexes } else { &lookup.readonly_indexes };
+                for &index in indexes {
+                    account_keys.push(*table.addresses.get(index as usize).ok_or_else(|| {
+                        anyhow::anyhow!("Lookup table {} has no address at index {}", table.key, index)
+                    })?);
+                }
+            }
+        }
+    }
+
+    let signed = transaction.verify_with_results();
+    let signers = message.static_account_keys().iter()
+        .take(message.header().num_required_signatures as usize)
+        .enumerate()
+        .map(|(i, address)| DecodedSigner { address: address.to_string(), signed: signed.get(i) == Some(&true) })
+        .collect();
+
+    let mut tokens = TokenAccounts { config, rpc, accounts: BTreeMap::new() };
+    let mut instructions = Vec::with_capacity(message.instructions().len());
+    for compiled in message.instructions() {
+        let program_id = *account_keys.get(compiled.program_id_index as usize)
+            .ok_or_else(|| anyhow::anyhow!("Program index {} is out of range", compiled.program_id_index))?;
+        let accounts: Vec<Pubkey> = compiled.accounts.iter()
+            .filter_map(|&index| account_keys.get(index as usize).copied())
+            .collect();
+        instructions.push(DecodedInstruction {
+            program_id: program_id.to_string(),
+            accounts: accounts.iter().map(Pubkey::to_string).collect(),
+            parsed: decode_instruction(&program_id, &accounts, &compiled.data, &mut tokens).await,
+        });
+    }
+
+    Ok(DecodedTransaction {
+        version: match message {
+            VersionedMessage::Legacy(_) => "legacy".to_string(),
+            VersionedMessage::V0(_) => "v0".to_string(),
+        },
+        fee_payer: message.static_account_keys().first().map(Pubkey::to_string).unwrap_or_default(),
+        recent_blockhash: message.recent_blockhash().to_string(),
+        signers,
+        account_keys: account_keys.iter().map(Pubkey::to_string).collect(),
+        instructions,
+        size_bytes: bincode::serialized_size(transaction).map_or(0, |size| size as usize),
+        issued: None,
+    })
+}
+
+/// Чем сообщение транзакции отличается от выданного сервером
+pub fn message_differences(actual: &VersionedMessage, expected: &VersionedMessage) -> Vec<String> {
+    let mut differences = Vec::new();
+    if std::mem::discriminant(actual) != std::mem::discriminant(expected) {
+        differences.push("version".to_string());
+    }
+    if actual.header() != expected.header() {
+        differences.push("header".to_string());
+    }
+    if actual.static_account_keys() != expected.static_account_keys() {
+        differences.push("account_keys".to_string());
+    }
+    if actual.address_table_lookups() != expected.address_table_lookups() {
+        differences.push("address_table_lookups".to_string());
+    }
+    let (actual, expected) = (actual.instructions(), expected.instructions());
+    if actual.len() != expected.len() {
+        differences.push("instructions".to_string());
+    }
+    differences.extend(
+        actual.iter().zip(expected).enumerate()
+            .filter(|(_, (actual, expected))| actual != expected)
+            .map(|(i, _)| format!("instruction {}", i)),
+    );
+    differences
+}
+
+/// Транзакция платежа со свопом впереди: плательщик платит другим токеном,
+/// своп дает ему ровно нужную сумму токена платежа, дальше — обычные переводы и комиссия.
+/// Маршруты Jupiter используют lookup таблицы, поэтому транзакция версии 0
+pub async fn create_swap_payment_transaction(
+    payment: &payment::Payment,
+    payer_str: &str,
+    config: &Config,
+    rpc: &dyn SolanaRpc,
+    swap: SwapInstructions,
+) -> anyhow::Result<BuiltTransaction> {
+    let (serialized, notes) = build_swap_transaction(payment, payer_str, config, rpc, swap, None).await?;
+
+    // Бюджет маршрута задает Jupiter (computeBudgetInstructions)
+    Ok(BuiltTransaction {
+        transaction: general_purpose::STANDARD.encode(&serialized),
+        notes,
+        compute_budget: None,
+    })
+}
+
+/// Неподписанная транзакция платежа со свопом; без `recent_blockhash` берется свежий
+async fn build_swap_transaction(
+    payment: &payment::Payment,
+    payer_str: &str,
+    config: &Config,
+    rpc: &dyn SolanaRpc,
+    swap: SwapInstructions,
+    recent_blockhash: Option<Hash>,
+) -> anyhow::Result<(Vec<u8>, Vec<String>)> {
+    let payer = Pubkey::from_str(payer_str)
+        .map_err(|e| anyhow::anyhow!("Invalid payer address: {}", e))?;
+    let mut notes = Vec::new();
+    let (transfers, memo_index) = payment_instructions(
+        payment, &payer, config, rpc, TransactionPart::Full, &mut notes,
+    ).await?;
+
+    // Бюджет compute units задает маршрут свопа
+    let context = DecoratorContext {
+        payment, payer: &payer, fee_payer: &payer, part: TransactionPart::Full, compute_budget_set: true,
+    };
+    let (transfers, mut optional) = config.decorators.decorate(&context, transfers, memo_index)?;
+    shift_optional(&mut optional, swap.instructions.len());
+    let mut instructions = swap.instructions;
+    instructions.extend(transfers);
+
+    let lookup_tables = load_lookup_tables(rpc, &swap.lookup_tables).await?;
+    let recent_blockhash = match recent_blockhash {
+        Some(blockhash) => blockhash,
+        None => latest_blockhash(rpc).await?,
+    };
+    tracing::debug!(instructions = instructions.len(), "Creating swap transaction");
+    let serialized = fit_transaction(instructions, &optional, TransactionPart::Full, &mut notes, |instructions| {
+        let message = v0::Message::try_compile(&payer, instructions, &lookup_tables, recent_blockhash)
+            .map_err(|e| anyhow::anyhow!("Failed to compile swap transaction: {}", e))?;
+        let message = VersionedMessage::V0(message);
+        let transaction = VersionedTransaction {
+            signatures: vec![Signature::default(); message.header().num_required_signatures as usize],
+            message,
+        };
+        bincode::serialize(&transaction)
+            .map_err(|e| anyhow::anyhow!("Failed to serialize transaction: {}", e))
+    })?;
+    Ok((serialized, notes))
+}
+
+/// Сообщение, которое сервер выдал бы по `issued` с blockhash `recent_blockhash`.
+/// Подписанная кошельком транзакция должна совпадать с ним целиком
+pub async fn expected_message(
+    payment: &payment::Payment,
+    issued: &IssuedTransaction,
+    config: &Config,
+    rpc: &dyn SolanaRpc,
+    recent_blockhash: Hash,
+) -> anyhow::Result<VersionedMessage> {
+    let fee_payer = issued.fee_payer.as_deref().map(Pubkey::from_str).transpose()?;
+    let serialized = match &issued.swap {
+        Some(swap) => {
+            build_swap_transaction(payment, &issued.account, config, rpc, swap.clone(), Some(recent_blockhash)).await?.0
+        }
+        None => {
+            let options = BuildOptions {
+                fee_payer: fee_payer.as_ref(),
+                recent_blockhash: Some(recent_blockhash),
+                compute_budget: issued.compute_budget,
+                estimator: None,
+            };
+            build_payment_transaction(payment, &issued.account, config, rpc, issued.part, options).await?.0
+        }
+    };
+    let transaction: VersionedTransaction = bincode::deserialize(&serialized)?;
+    Ok(transaction.message)
+}
+
+/// Загрузить lookup таблицы маршрута свопа для компиляции v0 сообщения
+pub async fn load_lookup_tables(
+    rpc: &dyn SolanaRpc,
+    keys: &[Pubkey],
+) -> anyhow::Result<Vec<AddressLookupTableAccount>> {
+    let mut tables = Vec::with_capacity(keys.len());
+    for key in keys {
+        let account = timeout(Duration::from_secs(LOOKUP_TABLE_TIMEOUT_SECS), rpc.get_account(key)).await
+            .map_err(|_| anyhow::anyhow!("Failed to load lookup table {}: timed out", key))??
+            .ok_or_else(|| anyhow::anyhow!("Lookup table {} not found", key))?;
+        let table = AddressLookupTable::deserialize(&account.data)
+            .map_err(|e| anyhow::anyhow!("Invalid lookup table {}: {}", key, e))?;
+        tables.push(AddressLookupTableAccount { key: *key, addresses: table.addresses.to_vec() });
+    }
+    Ok(tables)
+}
+
+/// Инструкции переводов платежа (основной, чаевые, комиссия, memo) и индекс memo
+async fn payment_instructions(
+    payment: &payment::Payment,
+    payer: &Pubkey,
+    config: &Config,
+    rpc: &dyn SolanaRpc,
+    part: TransactionPart,
+    notes: &mut Vec<String>,
+) -> anyhow::Result<(Vec<Instruction>, Option<usize>)> {
+    let payer = *payer;
+    // С удержанием сумма и чаевые идут на счет сервера
+    let recipient = Pubkey::from_str(payment.destination())
+        .map_err(|e| anyhow::anyhow!("Invalid recipient address: {}", e))?;
+    let fee_recipient = Pubkey::from_str(&payment.fee_recipient)
+        .map_err(|e| anyhow::anyhow!("Invalid fee recipient address: {}", e))?;
+    let reference = Pubkey::from_str(&payment.reference)
+        .map_err(|e| anyhow::anyhow!("Invalid payment reference: {}", e))?;
+
+    tracing::debug!(payment_id = %payment.id, %payer, %recipient, %fee_recipient, "Building payment instructions");
+
+    let mut instructions = Vec::new();
+
+    // 1. ОСНОВНОЙ ПЛАТЕЖ
+    if part == TransactionPart::Fee {
+        tracing::debug!(payment_id = %payment.id, "Skipping main transfer (fee-only part)");
+    } else if payment.token == "SOL" {
+        let mut lamports = payment.amount_base_units;
+
+        // Новый аккаунт получателя должен получить хотя бы rent-exempt минимум
+        if let Some(top_up) = check_rent_exemption(rpc, &recipient, lamports).await? {
+            match config.solana.rent_policy {
+                RentPolicy::Reject => anyhow::bail!(
+                    "Recipient account {} does not exist and {} lamports is below the rent-exempt minimum of {} lamports",
+                    recipient, lamports, lamports + top_up
+                ),
+                RentPolicy::TopUp => {
+                    tracing::warn!(payment_id = %payment.id, top_up_lamports = top_up, "Topping up SOL transfer for rent exemption");
+                    lamports += top_up;
+                    notes.push(format!(
+                        "Includes {} SOL top-up so the new recipient account is rent-exempt",
+                        top_up as f64 / 1_000_000_000.0
+                    ));
+                }
+            }
+        }
+
+        instructions.push(system_instruction::transfer(&payer, &recipient, lamports));
+        tracing::debug!(payment_id = %payment.id, lamports, "SOL transfer added");
+    } else {
+        push_spl_transfer(&mut instructions, config, &payer, &recipient, payment.amount_base_units, &payment.token, true)?;
+        tracing::debug!(payment_id = %payment.id, base_units = payment.amount_base_units, token = %payment.token, "Token transfer added");
+    }
+    if part != TransactionPart::Fee {
+        add_reference(&mut instructions, &reference);
+    }
+
+    // 1.1 ЧАЕВЫЕ — отдельный перевод получателю в токене платежа
+    match payment.tip_amount {
+        Some(tip) if tip > 0.0 && part != TransactionPart::Fee => {
+            tracing::debug!(payment_id = %payment.id, tip, token = %payment.token, "Tip transfer added");
+            if payment.token == "SOL" {
+                let tip_lamports = to_base_units(tip, config.token_decimals("SOL"));
+                instructions.push(system_instruction::transfer(&payer, &recipient, tip_lamports));
+            } else {
+                // ATA получателя уже создается основным переводом
+                let tip_units = to_base_units(tip, config.token_decimals(&payment.token));
+                push_spl_transfer(&mut instructions, config, &payer, &recipient, tip_units, &payment.token, false)?;
+            }
+        }
+        _ => {}
+    }
+
+    // 2. КОМИССИЯ (в fee_token платежа)
+    if part == TransactionPart::Main {
+        tracing::debug!(payment_id = %payment.id, "Skipping fee transfer (main-only part)");
+    } else if payment.fee_amount <= 0.0 {
+        // Платеж без комиссии (например, промо мерчанта): переводить нечего
+        if part == TransactionPart::Fee {
+            anyhow::bail!("Payment {} has no fee to pay", payment.id);
+        }
+        tracing::debug!(payment_id = %payment.id, "Skipping fee transfer (zero fee)");
+    } else if payment.fee_token == "SOL" {
+        let fee_lamports = to_base_units(payment.fee_amount, config.token_decimals("SOL"));
+        tracing::debug!(payment_id = %payment.id, fee_lamports, "Fee transfer added");
+        instructions.push(system_instruction::transfer(&payer, &fee_recipient, fee_lamports));
+    } else {
+        tracing::debug!(
+            payment_id = %payment.id, fee_amount = payment.fee_amount, fee_token = %payment.fee_token, "Fee transfer added"
+        );
+        let fee_units = to_base_units(payment.fee_amount, config.token_decimals(&payment.fee_token));
+        push_spl_transfer(&mut instructions, config, &payer, &fee_recipient, fee_units, &payment.fee_token, true)?;
+    }
+    if part == TransactionPart::Fee {
+        add_reference(&mut instructions, &reference);
+    }
+
+    // 2.1 wSOL: обернуть недостающее для перевода wSOL или развернуть wSOL под перевод SOL
+    add_wrapped_sol(&mut instructions, rpc, &payer, notes).await?;
+
+    // 2.2 MEMO для сверки со счетом (опциональная инструкция — последняя)
+    let memo_index = match &payment.memo {
+        Some(memo) if part != TransactionPart::Fee => {
+            instructions.push(memo_instruction(memo, &payer));
+            tracing::debug!(payment_id = %payment.id, memo, "Memo added");
+            Some(instructions.len() - 1)
+        }
+        _ => None,
+    };
+
+    Ok((instructions, memo_index))
+}
+
+async fn latest_blockhash(rpc: &dyn SolanaRpc) -> anyhow::Result<Hash> {
+    let recent_blockhash = timeout(Duration::from_secs(BLOCKHASH_TIMEOUT_SECS), rpc.get_latest_blockhash()).await
+        .map_err(|_| anyhow::anyhow!("Failed to get blockhash: timed out"))?
+        .map_err(|e| anyhow::anyhow!("Failed to get blockhash: {}", e))?;
+    tracing::debug!(%recent_blockhash, "Got blockhash");
+    Ok(recent_blockhash)
+}
+
+/// Сериализовать транзакцию в пределах лимита размера; при превышении по порядку
+/// выбрасываются `optional` (необязательные плагины, затем memo), пока транзакция не поместится
+fn fit_transaction(
+    instructions: Vec<Instruction>,
+    optional: &[OptionalInstructions],
+    part: TransactionPart,
+    notes: &mut Vec<String>,
+    serialize: impl Fn(&[Instruction]) -> anyhow::Result<Vec<u8>>,
+) -> anyhow::Result<Vec<u8>> {
+    let mut serialized = serialize(&instructions)?;
+
+    // 5. ПРОВЕРЯЕМ РАЗМЕР: лимит пакета 1232 байта, иначе кошелек отклонит транзакцию
+    let mut dropped = vec![false; instructions.len()];
+    for omitted in optional {
+        if serialized.len() <= PACKET_DATA_SIZE {
+            break;
+        }
+        dropped[omitted.range.clone()].fill(true);
+        let kept: Vec<Instruction> = instructions.iter().zip(&dropped)
+            .filter(|(_, &dropped)| !dropped)
+            .map(|(instruction, _)| instruction.clone())
+            .collect();
+        let reduced = serialize(&kept)?;
+        tracing::warn!(
+            size_bytes = serialized.len(),
+            saved_bytes = serialized.len() - reduced.len(),
+            limit = PACKET_DATA_SIZE,
+            note = %omitted.note,
+            "Transaction is too large, dropping optional instructions"
+        );
+        serialized = reduced;
+        notes.push(omitted.note.clone());
+    }
+
+    if serialized.len() > PACKET_DATA_SIZE {
+        if part == TransactionPart::Full {
+            anyhow::bail!(
+                "Transaction is {} bytes, exceeding the {} byte limit. \
+                 Request the payment and the fee separately with ?part=main and ?part=fee",
+                serialized.len(), PACKET_DATA_SIZE
+            );
+        }
+        anyhow::bail!(
+            "Transaction is {} bytes, exceeding the {} byte limit",
+            serialized.len(), PACKET_DATA_SIZE
+        );
+    }
+
+    let kept = dropped.iter().filter(|&&dropped| !dropped).count();
+    tracing::debug!(instructions = kept, size_bytes = serialized.len(), "Transaction created");
+    Ok(serialized)
+}
+
+/// Собрать неподписанную транзакцию и сериализовать ее (с местами под подписи)
+fn serialize_transaction(
+    instructions: &[Instruction],
+    payer: &Pubkey,
+    recent_blockhash: Hash,
+) -> anyhow::Result<Vec<u8>> {
+    let message = Message::new(instructions, Some(payer));
+    let mut transaction = Transaction::new_unsigned(message);
+    transaction.message.recent_blockhash = recent_blockhash;
+
+    bincode::serialize(&transaction)
+        .map_err(|e| anyhow::anyhow!("Failed to serialize transaction: {}", e))
+}
+
+/// Solana Pay reference: read-only ключ в последней (переводной) инструкции,
+/// по нему транзакция платежа находится через `getSignaturesForAddress`
+fn add_reference(instructions: &mut [Instruction], reference: &Pubkey) {
+    if let Some(transfer) = instructions.last_mut() {
+        transfer.accounts.push(solana_sdk::instruction::AccountMeta::new_readonly(*reference, false));
+    }
+}
+
+/// SPL Memo инструкция, подписанная плательщиком
+pub fn memo_instruction(memo: &str, signer: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: MEMO_PROGRAM_ID,
+        accounts: vec![solana_sdk::instruction::AccountMeta::new_readonly(*signer, true)],
+        data: memo.as_bytes().to_vec(),
+    }
+}
+
+/// Добавить SPL перевод `base_units` (и создание ATA получателя, если `create_ata`)
+fn push_spl_transfer(
+    instructions: &mut Vec<Instruction>,
+    config: &Config,
+    payer: &Pubkey,
+    to: &Pubkey,
+    base_units: u64,
+    token: &str,
+    create_ata: bool,
+) -> anyhow::Result<()> {
+    let token_config = config.get_token_config(token)
+        .ok_or_else(|| anyhow::anyhow!("Unsupported token: {}", token))?;
+    let mint = Pubkey::from_str(
+        token_config.mint.as_deref()
+            .ok_or_else(|| anyhow::anyhow!("No mint address for token {}", token))?
+    )?;
+
+    let from_token_account = spl_associated_token_account::get_associated_token_address(payer, &mint);
+    let to_token_account = spl_associated_token_account::get_associated_token_address(to, &mint);
+
+    tracing::debug!(base_units, token, "Token transfer");
+
+    // Создание ATA для получателя (если не существует; idempotent — не падает, если уже есть)
+    if create_ata {
+        instructions.push(
+            spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+                payer, to, &mint, &spl_token::ID,
+            )
+        );
+    }
+
+    instructions.push(token_instruction::transfer(
+        &spl_token::ID,
+        &from_token_account,
+        &to_token_account,
+        payer,
+        &[],
+        base_units,
+    )?);
+
+    Ok(())
+}
+
+/// Обертка SOL для плательщика. Платеж в wSOL (`mint` = native mint): недостающее
+/// переводится из SOL на wSOL аккаунт плательщика (`sync_native`), созданный для
+/// платежа аккаунт закрывается в конце. Платеж в SOL, на который не хватает SOL, но хватает
+/// wSOL: аккаунт wSOL закрывается в начале и лампорты возвращаются плательщику.
+/// Балансы читаются через RPC. Если он недоступен, платеж в SOL собирается без разворота,
+/// а платеж в wSOL — не собирается: неизвестно, создает ли транзакция аккаунт wSOL,
+/// и закрыть можно только созданный ею
+async fn add_wrapped_sol(
+    instructions: &mut Vec<Instruction>,
+    rpc: &dyn SolanaRpc,
+    payer: &Pubkey,
+    notes: &mut Vec<String>,
+) -> anyhow::Result<()> {
+    let native_mint = spl_token::native_mint::id();
+    let wsol_account = spl_associated_token_account::get_associated_token_address(payer, &native_mint);
+
+    let mut wsol_needed = 0u64;
+    let mut lamports_needed = 0u64;
+    for instruction in instructions.iter() {
+        let source = instruction.accounts.first().map(|meta| meta.pubkey);
+        if instruction.program_id == spl_token::ID && source == Some(wsol_account) {
+            if let Ok(TokenInstruction::Transfer { amount } | TokenInstruction::TransferChecked { amount, .. }) =
+                TokenInstruction::unpack(&instruction.data)
+            {
+                wsol_needed += amount;
+            }
+        } else if instruction.program_id == system_program::ID && source == Some(*payer) {
+            if let Ok(SystemInstruction::Transfer { lamports }) = bincode::deserialize(&instruction.data) {
+                lamports_needed += lamports;
+            }
+        }
+    }
+    if wsol_needed == 0 && lamports_needed == 0 {
+        return Ok(());
+    }
+
+    let lookup = async {
+        let wallet = rpc.get_account(payer).await?;
+        let wrapped = rpc.get_account(&wsol_account).await?;
+        anyhow::Ok((wallet.map_or(0, |account| account.lamports), wrapped))
+    };
+    let balances = match timeout(Duration::from_secs(RENT_CHECK_TIMEOUT_SECS), lookup).await {
+        Ok(result) => result,
+        Err(_) => Err(anyhow::anyhow!("timed out")),
+    };
+    let (lamports, wrapped) = match balances {
+        Ok(balances) => balances,
+        Err(e) if wsol_needed > 0 => {
+            return Err(e.context(format!("Failed to read wSOL balances of {}", payer)));
+        }
+        Err(e) => {
+            tracing::warn!(%payer, error = %e, "Failed to read payer balances, skipping wSOL unwrap");
+            return Ok(());
+        }
+    };
+    let wrapped_exists = wrapped.is_some();
+    let wrapped_balance = wrapped.as_ref()
+        .and_then(|account| spl_token::state::Account::unpack(&account.data).ok())
+        .map_or(0, |account| account.amount);
+
+    if wsol_needed > 0 {
+        let shortfall = wsol_needed.saturating_sub(wrapped_balance);
+        if shortfall == 0 {
+            return Ok(());
+        }
+        tracing::debug!(%payer, lamports = shortfall, "Wrapping SOL into wSOL");
+        let wrap = [
+            spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+                payer, payer, &native_mint, &spl_token::ID,
+            ),
+            system_instruction::transfer(payer, &wsol_account, shortfall),
+            token_instruction::sync_native(&spl_token::ID, &wsol_account)?,
+        ];
+        instructions.splice(0..0, wrap);
+        // Временный аккаунт закрывается — остаток и rent возвращаются плательщику
+        if !wrapped_exists {
+            instructions.push(token_instruction::close_account(&spl_token::ID, &wsol_account, payer, payer, &[])?);
+        }
+        notes.push(format!("Wraps {} SOL into wSOL for this payment", shortfall as f64 / 1_000_000_000.0));
+    } else if wrapped_balance > 0
+        && lamports < lamports_needed.saturating_add(UNWRAP_FEE_RESERVE_LAMPORTS)
+        && lamports.saturating_add(wrapped_balance) >= lamports_needed.saturating_add(UNWRAP_FEE_RESERVE_LAMPORTS)
+    {
+        tracing::debug!(%payer, lamports = wrapped_balance, "Unwrapping wSOL to pay in SOL");
+        instructions.insert(0, token_instruction::close_account(&spl_token::ID, &wsol_account, payer, payer, &[])?);
+        notes.push(format!("Unwraps {} wSOL to pay in SOL", wrapped_balance as f64 / 1_000_000_000.0));
+    }
+    Ok(())
+}
+
+/// Проверить, не останется ли получатель ниже rent-exempt минимума.
+///
+/// Возвращает `Some(недостающие лампорты)`, если аккаунт получателя не существует
+/// и перевода не хватает на rent-exempt минимум. Если RPC недоступен — не блокируем платеж.
+async fn check_rent_exemption(
+    rpc: &dyn SolanaRpc,
+    recipient: &Pubkey,
+    lamports: u64,
+) -> anyhow::Result<Option<u64>> {
+    let minimum = Rent::default().minimum_balance(0);
+    if lamports >= minimum {
+        return Ok(None);
+    }
+
+    match timeout(Duration::from_secs(RENT_CHECK_TIMEOUT_SECS), rpc.get_account(recipient)).await {
+        Ok(Ok(None)) => Ok(Some(minimum - lamports)),
+        Ok(Ok(Some(_))) => Ok(None),
+        Ok(Err(e)) => {
+            tracing::warn!(%recipient, error = %e, "Failed to check recipient account, skipping rent check");
+            Ok(None)
+        }
+        Err(_) => {
+            tracing::warn!(%recipient, "Recipient account check timed out, skipping rent check");
+            Ok(None)
+        }
+    }
+}
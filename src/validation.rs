@@ -0,0 +1,73 @@
+use serde::Serialize;
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use std::str::FromStr;
+
+/// Ошибка валидации конкретного поля запроса
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub code: &'static str,
+    pub message: String,
+}
+
+/// Набор ошибок валидации — отдается клиенту целиком, а не по одной
+#[derive(Debug, Clone, Default, thiserror::Error)]
+#[error("Validation failed: {}", .0.iter().map(|e| format!("{}: {}", e.field, e.message)).collect::<Vec<_>>().join("; "))]
+pub struct ValidationErrors(pub Vec<FieldError>);
+
+impl ValidationErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, field: &str, code: &'static str, message: impl Into<String>) {
+        self.0.push(FieldError {
+            field: field.to_string(),
+            code,
+            message: message.into(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn errors(&self) -> &[FieldError] {
+        &self.0
+    }
+
+    /// Ok, если ошибок нет
+    pub fn into_result(self) -> Result<(), ValidationErrors> {
+        if self.is_empty() { Ok(()) } else { Err(self) }
+    }
+
+    /// Проверить, что поле — валидный Solana адрес
+    pub fn check_pubkey(&mut self, field: &str, value: &str) {
+        if value.is_empty() {
+            self.add(field, "required", "Address is required");
+        } else {
+            match Pubkey::from_str(value) {
+                Err(_) => self.add(field, "invalid_pubkey", format!("Not a valid Solana address: {}", value)),
+                // Нулевой ключ (System Program) — заглушка, а не чей-то адрес
+                Ok(pubkey) if pubkey == Pubkey::default() => {
+                    self.add(field, "default_pubkey", "The all-zeros address cannot be used")
+                }
+                Ok(_) => {}
+            }
+        }
+    }
+
+    /// Проверить, что поле — валидная подпись в base58
+    pub fn check_signature(&mut self, field: &str, value: &str) {
+        if value.is_empty() {
+            self.add(field, "required", "Signature is required");
+        } else if Signature::from_str(value).is_err() {
+            self.add(field, "invalid_signature", "Not a valid base58 transaction signature");
+        }
+    }
+}
+
+/// Запросы, которые можно проверить без контекста сервиса
+pub trait Validate {
+    fn validate(&self) -> Result<(), ValidationErrors>;
+}
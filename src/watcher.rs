@@ -0,0 +1,207 @@
+use futures::stream::{self, BoxStream, SelectAll};
+use futures::StreamExt;
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::rpc_config::{RpcTransactionLogsConfig, RpcTransactionLogsFilter};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::lock::LeaderElection;
+use crate::payment::{Payment, PaymentService};
+use crate::rpc::transaction_commitment;
+
+/// Уведомление подписки: платеж и подпись транзакции; `None` — подписка закрылась
+type LogsEvent = (String, Option<String>);
+
+/// Поиск транзакций открытых платежей по reference: подписка на логи через Solana
+/// PubSub (статус меняется через слот-другой после попадания транзакции в блок),
+/// а пока WebSocket недоступен — опрос `getSignaturesForAddress`
+#[derive(Clone)]
+pub struct PaymentWatcher {
+    service: PaymentService,
+}
+
+impl PaymentWatcher {
+    pub fn new(service: PaymentService) -> Self {
+        Self { service }
+    }
+
+    /// Один проход опроса: верифицировать найденные транзакции всех открытых платежей.
+    /// Возвращает число завершенных платежей
+    pub async fn poll_once(&self) -> anyhow::Result<usize> {
+        let mut completed = 0;
+        for payment in self.open_payments().await? {
+            match self.poll_payment(&payment).await {
+                Ok(true) => completed += 1,
+                Ok(false) => {}
+                Err(e) => tracing::warn!(payment_id = %payment.id, error = %e, "Payment polling failed"),
+            }
+        }
+        Ok(completed)
+    }
+
+    async fn open_payments(&self) -> anyhow::Result<Vec<Payment>> {
+        Ok(self.service.storage().get_all_payments().await?
+            .into_values()
+            .filter(|payment| payment.status.is_open() && !payment.sandbox)
+            .collect())
+    }
+
+    /// Проверить транзакции с reference платежа, старые первыми
+    async fn poll_payment(&self, payment: &Payment) -> anyhow::Result<bool> {
+        let reference = Pubkey::from_str(&payment.reference)?;
+        let signatures = self.service.rpc().get_signatures_for_address(&reference).await?;
+        for signature in signatures.iter().rev() {
+            if self.verify(&payment.id, &signature.to_string()).await? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// `true` — платеж завершен этой транзакцией
+    async fn verify(&self, payment_id: &str, signature: &str) -> anyhow::Result<bool> {
+        let result = self.service.verify_payment(payment_id, signature).await?;
+        if result.verified {
+            tracing::info!(payment_id, signature, "Payment completed by watcher");
+        }
+        Ok(result.verified)
+    }
+
+    /// Сессия WebSocket: подписка на логи с reference каждого открытого платежа.
+    /// `Ok` — реплика перестала быть лидером, `Err` — соединение потеряно
+    async fn watch(&self, leader: &LeaderElection, ttl: Duration) -> anyhow::Result<()> {
+        let config = &self.service.config().watcher;
+        // Транзакцию с processed еще не вернет getTransaction при верификации
+        let commitment = transaction_commitment(&self.service.config().solana.watcher_commitment);
+
+        let client = PubsubClient::new(&config.ws_url).await?;
+        tracing::info!(endpoint = %config.ws_url, "Payment watcher connected");
+
+        let mut updates = self.service.storage().subscribe_payments();
+        let mut streams: SelectAll<BoxStream<'_, LogsEvent>> = SelectAll::new();
+        let mut subscriptions = HashMap::new();
+
+        let subscribe = |payment: &Payment| {
+            let client = &client;
+            let (id, reference) = (payment.id.clone(), payment.reference.clone());
+            async move {
+                let filter = RpcTransactionLogsFilter::Mentions(vec![reference]);
+                let config = RpcTransactionLogsConfig { commitment: Some(commitment) };
+                let (notifications, unsubscribe) = client.logs_subscribe(filter, config).await?;
+                let events = notifications
+                    .filter_map(|response| async move {
+                        response.value.err.is_none().then_some(Some(response.value.signature))
+                    })
+                    .chain(stream::once(async { None }))
+                    .map({
+                        let id = id.clone();
+                        move |signature| (id.clone(), signature)
+                    });
+                anyhow::Ok((id, events.boxed(), unsubscribe))
+            }
+        };
+
+        for payment in self.open_payments().await? {
+            let (id, events, unsubscribe) = subscribe(&payment).await?;
+            streams.push(events);
+            subscriptions.insert(id, unsubscribe);
+        }
+        // Транзакции, попавшие в блок до подписки
+        self.poll_once().await?;
+
+        let mut renew = tokio::time::interval(Duration::from_secs(config.poll_interval_secs));
+        loop {
+            tokio::select! {
+                Some((id, signature)) = streams.next(), if !streams.is_empty() => match signature {
+                    Some(signature) => {
+                        if let Err(e) = self.verify(&id, &signature).await {
+                            tracing::warn!(payment_id = %id, %signature, error = %e, "Watcher verification failed");
+                        }
+                    }
+                    None if subscriptions.contains_key(&id) => {
+                        anyhow::bail!("Logs subscription of payment {} closed", id);
+                    }
+                    None => {}
+                },
+                update = updates.recv() => match update {
+                    Ok(payment) if payment.status.is_open() => {
+                        if !subscriptions.contains_key(&payment.id) {
+                            let (id, events, unsubscribe) = subscribe(&payment).await?;
+                            streams.push(events);
+                            subscriptions.insert(id, unsubscribe);
+                        }
+                    }
+                    Ok(payment) => {
+                        if let Some(unsubscribe) = subscriptions.remove(&payment.id) {
+                            unsubscribe().await;
+                        }
+                    }
+                    // Обновления пропущены — сверяем подписки с открытыми платежами
+                    Err(RecvError::Lagged(_)) => {
+                        for payment in self.open_payments().await? {
+                            if !subscriptions.contains_key(&payment.id) {
+                                let (id, events, unsubscribe) = subscribe(&payment).await?;
+                                streams.push(events);
+                                subscriptions.insert(id, unsubscribe);
+                            }
+                        }
+                        self.poll_once().await?;
+                    }
+                    Err(RecvError::Closed) => return Ok(()),
+                },
+                _ = renew.tick() => {
+                    if !leader.is_leader("payment-watcher", ttl).await {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Запустить поиск транзакций, если он включен (`watcher.enabled`).
+    /// Подписки держит только реплика-лидер
+    pub fn spawn_scheduler(&self, leader: LeaderElection) {
+        let config = self.service.config().watcher.clone();
+        if !config.enabled {
+            return;
+        }
+
+        let watcher = self.clone();
+        tokio::spawn(async move {
+            let poll_interval = Duration::from_secs(config.poll_interval_secs);
+            // Lease переживает один пропущенный тик
+            let ttl = poll_interval * 2;
+            let mut interval = tokio::time::interval(poll_interval);
+            loop {
+                interval.tick().await;
+                if !leader.is_leader("payment-watcher", ttl).await {
+                    continue;
+                }
+                match watcher.watch(&leader, ttl).await {
+                    Ok(()) => continue,
+                    Err(e) => tracing::warn!(
+                        polling_secs = config.reconnect_secs, error = %e, "Payment watcher WebSocket failed, polling"
+                    ),
+                }
+
+                // Пока WebSocket недоступен — опрос до следующей попытки подключения
+                let reconnect_at = tokio::time::Instant::now() + Duration::from_secs(config.reconnect_secs);
+                while tokio::time::Instant::now() < reconnect_at {
+                    if !leader.is_leader("payment-watcher", ttl).await {
+                        break;
+                    }
+                    if let Err(e) = watcher.poll_once().await {
+                        tracing::error!(error = %e, "Payment polling failed");
+                    }
+                    interval.tick().await;
+                }
+            }
+        });
+
+        tracing::info!(endpoint = %config.ws_url, "Payment watcher started");
+    }
+}
+
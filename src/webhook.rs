@@ -0,0 +1,466 @@
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+use crate::config::{MerchantConfig, NOTIFICATION_EVENTS};
+use crate::events::{self, API_VERSION_HEADER, IDEMPOTENCY_KEY_HEADER};
+use crate::payload_signer::{PayloadSigner, PAYLOAD_SIGNATURE_HEADER, SIGNING_KEY_HEADER};
+use crate::storage::StorageService;
+use crate::validation::{Validate, ValidationErrors};
+
+/// Заголовок с подписью доставки: `v1=<hex(HMAC-SHA256(secret, "{timestamp}.{body}"))>`
+pub const SIGNATURE_HEADER: &str = "X-CryptoNow-Signature";
+
+/// Заголовок с unix временем подписи (защита от replay на стороне мерчанта)
+pub const TIMESTAMP_HEADER: &str = "X-CryptoNow-Timestamp";
+
+/// Автоматических попыток доставки на одно событие
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Таймаут одного HTTP запроса доставки
+const DELIVERY_TIMEOUT_SECS: u64 = 10;
+
+/// Максимум webhook endpoints на мерчанта
+pub const MAX_ENDPOINTS_PER_MERCHANT: usize = 10;
+
+/// Максимум значений в каждом условии фильтра
+pub const MAX_FILTER_VALUES: usize = 20;
+
+/// Какие события доставлять на endpoint; проверяется на сервере до создания доставки.
+/// Пустое условие пропускает все, заданные условия должны выполниться все сразу
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhookFilter {
+    /// Типы событий (`payment.completed`, ...)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<String>,
+    /// Токены платежа (`USDC`, ...), без учета регистра
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tokens: Vec<String>,
+    /// Значения metadata платежа (`store_id` → `berlin-1`)
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+    /// Начало memo платежа: сверка заказов по номеру в memo (`store-12/`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo_prefix: Option<String>,
+}
+
+impl WebhookFilter {
+    /// Доставлять ли событие `event_type` с данными платежа `data`
+    pub fn matches(&self, event_type: &str, data: &serde_json::Value) -> bool {
+        let events = self.events.is_empty() || self.events.iter().any(|event| event == event_type);
+        let tokens = self.tokens.is_empty() || data["token"].as_str()
+            .is_some_and(|token| self.tokens.iter().any(|wanted| wanted.eq_ignore_ascii_case(token)));
+        let metadata = self.metadata.iter()
+            .all(|(key, value)| data["metadata"][key].as_str() == Some(value.as_str()));
+        let memo = self.memo_prefix.as_deref()
+            .is_none_or(|prefix| data["memo"].as_str().is_some_and(|memo| memo.starts_with(prefix)));
+        events && tokens && metadata && memo
+    }
+
+    fn validate(&self, errors: &mut ValidationErrors) {
+        for event in &self.events {
+            if !NOTIFICATION_EVENTS.contains(&event.as_str()) {
+                errors.add(
+                    "filter.events",
+                    "unsupported",
+                    format!("Unknown event {}, expected one of: {}", event, NOTIFICATION_EVENTS.join(", ")),
+                );
+            }
+        }
+        if self.tokens.iter().any(|token| token.trim().is_empty()) {
+            errors.add("filter.tokens", "too_short", "Token must not be empty");
+        }
+        if self.metadata.keys().any(|key| key.is_empty()) {
+            errors.add("filter.metadata", "invalid_key", "Metadata key must not be empty");
+        }
+        if self.memo_prefix.as_deref().is_some_and(str::is_empty) {
+            errors.add("filter.memo_prefix", "too_short", "Memo prefix must not be empty");
+        }
+        for (field, count) in [
+            ("filter.events", self.events.len()),
+            ("filter.tokens", self.tokens.len()),
+            ("filter.metadata", self.metadata.len()),
+        ] {
+            if count > MAX_FILTER_VALUES {
+                errors.add(field, "too_many", format!("At most {} values, got: {}", MAX_FILTER_VALUES, count));
+            }
+        }
+    }
+}
+
+/// Зарегистрированный мерчантом endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookEndpoint {
+    pub id: String,
+    pub merchant_id: String,
+    pub url: String,
+    pub description: Option<String>,
+    /// Секрет подписи отдается только при регистрации (в снимке хранилища — отдельно)
+    #[serde(skip_serializing, default)]
+    pub secret: String,
+    /// Версия схемы событий, выбранная при регистрации
+    pub api_version: u32,
+    pub filter: WebhookFilter,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Тело доставки; формат `data` задает `api_version` (см. `GET /api/events/schema`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookEvent {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub api_version: u32,
+    pub created_at: DateTime<Utc>,
+    pub data: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+/// Одна попытка доставки
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveryAttempt {
+    pub attempted_at: DateTime<Utc>,
+    pub status_code: Option<u16>,
+    pub error: Option<String>,
+    pub duration_ms: u64,
+    /// Ручная повторная отправка через API
+    pub manual: bool,
+}
+
+/// Доставка события на endpoint со всеми попытками
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub endpoint_id: String,
+    pub merchant_id: String,
+    pub event: WebhookEvent,
+    /// Отправляется в `X-CryptoNow-Idempotency-Key`; у endpoint одна доставка на ключ
+    #[serde(default)]
+    pub idempotency_key: String,
+    pub status: DeliveryStatus,
+    pub attempts: Vec<DeliveryAttempt>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WebhookError {
+    #[error("Webhook endpoint not found")]
+    EndpointNotFound,
+    #[error("Webhook delivery not found")]
+    DeliveryNotFound,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegisterWebhookRequest {
+    pub url: String,
+    pub description: Option<String>,
+    /// Без фильтра доставляются все события мерчанта
+    #[serde(default)]
+    pub filter: WebhookFilter,
+}
+
+impl Validate for RegisterWebhookRequest {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        match url::Url::parse(&self.url) {
+            Ok(url) => {
+                let is_local = matches!(url.host_str(), Some("localhost") | Some("127.0.0.1"));
+                if url.scheme() != "https" && !(url.scheme() == "http" && is_local) {
+                    errors.add("url", "insecure_url", "Webhook URL must use https");
+                }
+            }
+            Err(e) => errors.add("url", "invalid_url", format!("Not a valid URL: {}", e)),
+        }
+        self.filter.validate(&mut errors);
+        errors.into_result()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RedeliverRequest {
+    pub delivery_id: String,
+}
+
+/// Зарегистрированный endpoint вместе с секретом (показывается один раз)
+#[derive(Debug, Serialize)]
+pub struct RegisteredWebhook {
+    #[serde(flatten)]
+    pub endpoint: WebhookEndpoint,
+    pub secret: String,
+}
+
+/// Webhook уведомления мерчантам о событиях платежей
+#[derive(Clone)]
+pub struct WebhookService {
+    storage: StorageService,
+    client: reqwest::Client,
+    /// Подпись `data` события ключом сервера (дополнительно к HMAC endpoint)
+    payload_signer: Option<PayloadSigner>,
+}
+
+impl WebhookService {
+    pub fn new(storage: StorageService) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(DELIVERY_TIMEOUT_SECS))
+            .build()
+            .unwrap_or_default();
+
+        Self { storage, client, payload_signer: None }
+    }
+
+    /// События подписываются еще и ключом сервера: получатель проверяет данные без
+    /// секрета endpoint
+    pub fn with_payload_signer(mut self, payload_signer: Option<PayloadSigner>) -> Self {
+        self.payload_signer = payload_signer;
+        self
+    }
+
+    /// Зарегистрировать endpoint мерчанта; `api_version` — из заголовка
+    /// `X-CryptoNow-Api-Version` (без него — текущая версия)
+    pub async fn register(
+        &self,
+        request: RegisterWebhookRequest,
+        api_version: Option<&str>,
+        merchant: &MerchantConfig,
+    ) -> anyhow::Result<RegisteredWebhook> {
+        request.validate()?;
+        let api_version = events::negotiate(api_version)?;
+
+        if self.storage.list_webhooks(&merchant.id).await?.len() >= MAX_ENDPOINTS_PER_MERCHANT {
+            anyhow::bail!("At most {} webhook endpoints per merchant", MAX_ENDPOINTS_PER_MERCHANT);
+        }
+
+        let secret = format!("whsec_{}", hex::encode(rand::random::<[u8; 32]>()));
+        let endpoint = WebhookEndpoint {
+            id: format!("wh_{}", Uuid::new_v4().simple()),
+            merchant_id: merchant.id.clone(),
+            url: request.url,
+            description: request.description,
+            secret: secret.clone(),
+            api_version,
+            filter: request.filter,
+            created_at: Utc::now(),
+        };
+        self.storage.save_webhook(&endpoint).await?;
+
+        tracing::info!(webhook_id = %endpoint.id, merchant = %merchant.id, url = %endpoint.url, api_version, "Webhook registered");
+
+        Ok(RegisteredWebhook { endpoint, secret })
+    }
+
+    pub async fn list(&self, merchant: &MerchantConfig) -> anyhow::Result<Vec<WebhookEndpoint>> {
+        self.storage.list_webhooks(&merchant.id).await
+    }
+
+    pub async fn delete(&self, endpoint_id: &str, merchant: &MerchantConfig) -> anyhow::Result<()> {
+        self.endpoint(endpoint_id, merchant).await?;
+        self.storage.delete_webhook(endpoint_id).await?;
+        tracing::info!(webhook_id = endpoint_id, merchant = %merchant.id, "Webhook deleted");
+        Ok(())
+    }
+
+    /// История доставок на endpoint, новые первыми
+    pub async fn deliveries(&self, endpoint_id: &str, merchant: &MerchantConfig) -> anyhow::Result<Vec<WebhookDelivery>> {
+        self.endpoint(endpoint_id, merchant).await?;
+        self.storage.list_webhook_deliveries(endpoint_id).await
+    }
+
+    /// Ручная повторная отправка доставки (одна попытка, результат сразу)
+    pub async fn redeliver(
+        &self,
+        endpoint_id: &str,
+        delivery_id: &str,
+        merchant: &MerchantConfig,
+    ) -> anyhow::Result<WebhookDelivery> {
+        let endpoint = self.endpoint(endpoint_id, merchant).await?;
+        let delivery = self.storage.get_webhook_delivery(delivery_id).await?
+            .filter(|d| d.endpoint_id == endpoint.id)
+            .ok_or(WebhookError::DeliveryNotFound)?;
+
+        self.attempt(&endpoint, &delivery, true).await
+    }
+
+    /// Доставки всех мерчантов (для оператора), новые первыми
+    pub async fn all_deliveries(&self, status: Option<DeliveryStatus>, limit: usize) -> anyhow::Result<Vec<WebhookDelivery>> {
+        let mut deliveries = self.storage.list_all_webhook_deliveries().await?;
+        deliveries.retain(|d| status.is_none_or(|status| d.status == status));
+        deliveries.truncate(limit);
+        Ok(deliveries)
+    }
+
+    /// Повторная отправка доставки оператором, без проверки мерчанта
+    pub async fn redeliver_any(&self, delivery_id: &str) -> anyhow::Result<WebhookDelivery> {
+        let delivery = self.storage.get_webhook_delivery(delivery_id).await?
+            .ok_or(WebhookError::DeliveryNotFound)?;
+        let endpoint = self.storage.get_webhook(&delivery.endpoint_id).await?
+            .ok_or(WebhookError::EndpointNotFound)?;
+
+        tracing::info!(delivery_id, webhook_id = %endpoint.id, merchant = %endpoint.merchant_id, "Webhook redelivered by operator");
+        self.attempt(&endpoint, &delivery, true).await
+    }
+
+    /// Разослать разовое событие (ключ идемпотентности случайный)
+    pub async fn publish(&self, merchant_id: &str, event_type: &str, data: serde_json::Value) -> anyhow::Result<()> {
+        self.publish_event(merchant_id, event_type, data, "").await
+    }
+
+    /// Разослать событие всем endpoints мерчанта (доставка в фоне с повторами).
+    /// Каждый endpoint получает данные в своей версии схемы; ID события и ключ
+    /// идемпотентности общие. Endpoints, которым событие с этим ключом уже разослано,
+    /// пропускаются; пустой ключ заменяется случайным
+    pub async fn publish_event(
+        &self,
+        merchant_id: &str,
+        event_type: &str,
+        data: serde_json::Value,
+        idempotency_key: &str,
+    ) -> anyhow::Result<()> {
+        let endpoints = self.storage.list_webhooks(merchant_id).await?;
+        if endpoints.is_empty() {
+            return Ok(());
+        }
+
+        let event_id = format!("evt_{}", Uuid::new_v4().simple());
+        let idempotency_key = match idempotency_key {
+            "" => format!("idk_{}", Uuid::new_v4().simple()),
+            key => key.to_string(),
+        };
+        let created_at = Utc::now();
+
+        for endpoint in endpoints {
+            if !endpoint.filter.matches(event_type, &data) {
+                tracing::debug!(webhook_id = %endpoint.id, event_type, "Event filtered out for webhook");
+                continue;
+            }
+            let event = WebhookEvent {
+                id: event_id.clone(),
+                event_type: event_type.to_string(),
+                api_version: endpoint.api_version,
+                created_at,
+                data: events::render(endpoint.api_version, &data)?,
+            };
+            let delivery = WebhookDelivery {
+                id: format!("whd_{}", Uuid::new_v4().simple()),
+                endpoint_id: endpoint.id.clone(),
+                merchant_id: merchant_id.to_string(),
+                event,
+                idempotency_key: idempotency_key.clone(),
+                status: DeliveryStatus::Pending,
+                attempts: Vec::new(),
+                created_at: Utc::now(),
+            };
+            if !self.storage.save_webhook_delivery_once(&delivery).await? {
+                tracing::info!(webhook_id = %endpoint.id, event_type, %idempotency_key, "Duplicate event not redelivered");
+                continue;
+            }
+
+            let service = self.clone();
+            tokio::spawn(async move {
+                service.deliver_with_retries(endpoint, delivery).await;
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Автоматическая доставка: экспоненциальная пауза между попытками
+    async fn deliver_with_retries(&self, endpoint: WebhookEndpoint, delivery: WebhookDelivery) {
+        for attempt in 0..MAX_ATTEMPTS {
+            if attempt > 0 {
+                tokio::time::sleep(Duration::from_secs(2u64.pow(attempt))).await;
+            }
+            match self.attempt(&endpoint, &delivery, false).await {
+                Ok(updated) if updated.status == DeliveryStatus::Delivered => return,
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::error!(delivery_id = %delivery.id, error = %e, "Webhook delivery bookkeeping failed");
+                    return;
+                }
+            }
+        }
+        tracing::warn!(delivery_id = %delivery.id, url = %endpoint.url, attempts = MAX_ATTEMPTS, "Webhook delivery failed permanently");
+    }
+
+    /// Одна подписанная отправка события и запись результата
+    async fn attempt(
+        &self,
+        endpoint: &WebhookEndpoint,
+        delivery: &WebhookDelivery,
+        manual: bool,
+    ) -> anyhow::Result<WebhookDelivery> {
+        let body = serde_json::to_string(&delivery.event)?;
+        let timestamp = Utc::now().timestamp();
+        let signature = sign(&endpoint.secret, timestamp, &body);
+
+        let mut request = self.client
+            .post(&endpoint.url)
+            .header("Content-Type", "application/json")
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(SIGNATURE_HEADER, format!("v1={}", signature))
+            .header(API_VERSION_HEADER, delivery.event.api_version.to_string());
+        if !delivery.idempotency_key.is_empty() {
+            request = request.header(IDEMPOTENCY_KEY_HEADER, &delivery.idempotency_key);
+        }
+        if let Some(signer) = &self.payload_signer {
+            request = request
+                .header(PAYLOAD_SIGNATURE_HEADER, signer.sign_value(&delivery.event.data))
+                .header(SIGNING_KEY_HEADER, signer.public_key());
+        }
+        let started = Instant::now();
+        let result = request.body(body).send().await;
+
+        let (status_code, error) = match result {
+            Ok(response) if response.status().is_success() => (Some(response.status().as_u16()), None),
+            Ok(response) => (
+                Some(response.status().as_u16()),
+                Some(format!("Endpoint responded with {}", response.status())),
+            ),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        let attempt = DeliveryAttempt {
+            attempted_at: Utc::now(),
+            status_code,
+            error,
+            duration_ms: started.elapsed().as_millis() as u64,
+            manual,
+        };
+
+        let delivered = attempt.error.is_none();
+        if !delivered {
+            tracing::warn!(delivery_id = %delivery.id, url = %endpoint.url, error = ?attempt.error, "Webhook delivery attempt failed");
+        }
+
+        self.storage.record_webhook_attempt(&delivery.id, attempt, delivered).await
+    }
+
+    /// Endpoint мерчанта; чужие не видны
+    async fn endpoint(&self, endpoint_id: &str, merchant: &MerchantConfig) -> anyhow::Result<WebhookEndpoint> {
+        self.storage.get_webhook(endpoint_id).await?
+            .filter(|e| e.merchant_id == merchant.id)
+            .ok_or_else(|| WebhookError::EndpointNotFound.into())
+    }
+}
+
+/// HMAC-SHA256 подпись `{timestamp}.{body}` в hex
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
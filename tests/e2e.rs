@@ -0,0 +1,285 @@
+//! Сквозные тесты полного цикла платежа против локального `solana-test-validator`:
+//! create → transaction → подпись и отправка плательщиком → verify.
+//!
+//! Требует `solana-test-validator` в PATH. Запуск:
+//! `cargo test --features e2e --test e2e -- --test-threads=1`
+#![cfg(feature = "e2e")]
+
+use serde_json::{json, Value};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::native_token::LAMPORTS_PER_SOL;
+use solana_sdk::program_pack::Pack;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::system_instruction;
+use solana_sdk::transaction::Transaction;
+use spl_associated_token_account::get_associated_token_address;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Тестовый SPL токен: создается в каждом тесте, комиссия берется в нем же
+const TEST_TOKEN: &str = "TEST";
+const TEST_DECIMALS: u8 = 6;
+const FEE_AMOUNT: f64 = 0.5;
+
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Дочерний процесс, который завершается вместе с тестом
+struct Process {
+    child: Child,
+    dir: PathBuf,
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("cryptonow-e2e-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).expect("create temp dir");
+    dir
+}
+
+async fn wait_until<F, Fut>(what: &str, mut ready: F)
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = bool>,
+{
+    let started = Instant::now();
+    while !ready().await {
+        assert!(started.elapsed() < STARTUP_TIMEOUT, "{} did not start in {:?}", what, STARTUP_TIMEOUT);
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+}
+
+/// Локальный валидатор, сервер и профинансированный плательщик с тестовым токеном
+struct Harness {
+    rpc: RpcClient,
+    http: reqwest::Client,
+    base_url: String,
+    payer: Keypair,
+    mint: Pubkey,
+    fee_wallet: Pubkey,
+    _server: Process,
+    _validator: Process,
+}
+
+impl Harness {
+    /// `port_base` разводит порты тестов: валидатор занимает `port_base..port_base + 50`
+    async fn start(name: &str, port_base: u16) -> Self {
+        let rpc_port = port_base;
+        let validator_dir = temp_dir(&format!("{}-ledger", name));
+        let child = Command::new("solana-test-validator")
+            .arg("--reset")
+            .arg("--quiet")
+            .arg("--ledger").arg(&validator_dir)
+            .args(["--rpc-port", &rpc_port.to_string()])
+            .args(["--faucet-port", &(port_base + 2).to_string()])
+            .args(["--gossip-port", &(port_base + 3).to_string()])
+            .args(["--dynamic-port-range", &format!("{}-{}", port_base + 10, port_base + 50)])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("solana-test-validator must be installed and in PATH");
+        let validator = Process { child, dir: validator_dir };
+
+        let rpc_url = format!("http://127.0.0.1:{}", rpc_port);
+        let rpc = RpcClient::new_with_commitment(rpc_url.clone(), CommitmentConfig::confirmed());
+        wait_until("solana-test-validator", || async { rpc.get_health().await.is_ok() }).await;
+
+        let payer = Keypair::new();
+        airdrop(&rpc, &payer.pubkey(), 10 * LAMPORTS_PER_SOL).await;
+        let mint = create_mint(&rpc, &payer, 1_000_000 * 10u64.pow(TEST_DECIMALS as u32)).await;
+
+        let fee_wallet = Keypair::new().pubkey();
+        let server_port = port_base + 60;
+        let server_dir = temp_dir(&format!("{}-server", name));
+        let config_path = server_dir.join("config.toml");
+        std::fs::write(&config_path, format!(
+            r#"
+[server]
+host = "127.0.0.1"
+port = {server_port}
+domain = "127.0.0.1:{server_port}"
+ssl = false
+
+[solana]
+rpc_url = "{rpc_url}"
+commitment = "confirmed"
+cluster = "devnet"
+fee_wallet = "{fee_wallet}"
+fee_amount = {FEE_AMOUNT}
+fee_token = "{TEST_TOKEN}"
+rent_policy = "reject"
+
+[[solana.supported_tokens]]
+symbol = "SOL"
+decimals = 9
+name = "Solana"
+
+[[solana.supported_tokens]]
+symbol = "{TEST_TOKEN}"
+mint = "{mint}"
+decimals = {TEST_DECIMALS}
+name = "Test Token"
+"#,
+        )).expect("write server config");
+
+        // Окружение разработчика (.env, переменные) не должно перекрывать тестовый конфиг
+        let child = Command::new(env!("CARGO_BIN_EXE_crypto-server"))
+            .arg("--config").arg(&config_path)
+            .current_dir(&server_dir)
+            .env_clear()
+            .env("PATH", std::env::var_os("PATH").unwrap_or_default())
+            .env("RUST_LOG", "warn")
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("start crypto-server");
+        let server = Process { child, dir: server_dir };
+
+        let base_url = format!("http://127.0.0.1:{}", server_port);
+        let http = reqwest::Client::new();
+        wait_until("crypto-server", || async { http.get(&base_url).send().await.is_ok() }).await;
+
+        Self { rpc, http, base_url, payer, mint, fee_wallet, _server: server, _validator: validator }
+    }
+
+    async fn post(&self, path: &str, body: Value) -> (reqwest::StatusCode, Value) {
+        let response = self.http.post(format!("{}{}", self.base_url, path))
+            .json(&body)
+            .send()
+            .await
+            .expect("request to crypto-server");
+        let status = response.status();
+        (status, response.json().await.expect("JSON response"))
+    }
+
+    /// Полный цикл: создать платеж, получить транзакцию, подписать, отправить и подтвердить
+    async fn pay(&self, recipient: &Pubkey, token: &str, amount: f64) -> Value {
+        let (status, created) = self.post("/api/payment/create", json!({
+            "recipient": recipient.to_string(),
+            "amount": amount,
+            "token": token,
+        })).await;
+        assert!(status.is_success(), "create failed: {}", created);
+        let payment_id = created["data"]["id"].as_str().expect("payment id").to_string();
+        assert_eq!(created["data"]["status"], "pending");
+
+        let (status, built) = self.post(
+            &format!("/api/payment/{}/transaction", payment_id),
+            json!({ "account": self.payer.pubkey().to_string() }),
+        ).await;
+        assert!(status.is_success(), "transaction failed: {}", built);
+
+        use base64::Engine as _;
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(built["transaction"].as_str().expect("transaction"))
+            .expect("base64 transaction");
+        let mut transaction: Transaction = bincode::deserialize(&bytes).expect("bincode transaction");
+        assert_eq!(transaction.message.account_keys[0], self.payer.pubkey(), "payer must be fee payer");
+        let blockhash = transaction.message.recent_blockhash;
+        transaction.partial_sign(&[&self.payer], blockhash);
+        let signature = self.rpc.send_and_confirm_transaction(&transaction).await
+            .expect("payment transaction confirmed");
+
+        let (status, verified) = self.post(
+            &format!("/api/payment/{}/verify", payment_id),
+            json!({ "signature": signature.to_string() }),
+        ).await;
+        assert!(status.is_success(), "verify failed: {}", verified);
+        assert_eq!(verified["success"], true, "{}", verified);
+        assert_eq!(verified["status"], "completed", "{}", verified);
+
+        let payment: Value = self.http.get(format!("{}/api/payment/{}", self.base_url, payment_id))
+            .send().await.expect("get payment")
+            .json().await.expect("JSON response");
+        assert_eq!(payment["data"]["status"], "completed", "{}", payment);
+        assert_eq!(payment["data"]["signature"], signature.to_string());
+        payment["data"].clone()
+    }
+
+    async fn token_balance(&self, owner: &Pubkey) -> u64 {
+        let ata = get_associated_token_address(owner, &self.mint);
+        let balance = self.rpc.get_token_account_balance(&ata).await.expect("token account exists");
+        balance.amount.parse().expect("token amount")
+    }
+}
+
+async fn airdrop(rpc: &RpcClient, to: &Pubkey, lamports: u64) {
+    let signature = rpc.request_airdrop(to, lamports).await.expect("airdrop");
+    wait_until("airdrop", || async {
+        rpc.confirm_transaction(&signature).await.unwrap_or(false)
+    }).await;
+}
+
+/// Mint тестового токена (authority — плательщик) и его ATA с `supply` базовых единиц
+async fn create_mint(rpc: &RpcClient, payer: &Keypair, supply: u64) -> Pubkey {
+    let mint = Keypair::new();
+    let rent = rpc.get_minimum_balance_for_rent_exemption(spl_token::state::Mint::LEN).await
+        .expect("mint rent");
+    let payer_ata = get_associated_token_address(&payer.pubkey(), &mint.pubkey());
+
+    let instructions = [
+        system_instruction::create_account(
+            &payer.pubkey(), &mint.pubkey(), rent, spl_token::state::Mint::LEN as u64, &spl_token::ID,
+        ),
+        spl_token::instruction::initialize_mint(
+            &spl_token::ID, &mint.pubkey(), &payer.pubkey(), None, TEST_DECIMALS,
+        ).expect("initialize_mint"),
+        spl_associated_token_account::instruction::create_associated_token_account(
+            &payer.pubkey(), &payer.pubkey(), &mint.pubkey(), &spl_token::ID,
+        ),
+        spl_token::instruction::mint_to(
+            &spl_token::ID, &mint.pubkey(), &payer_ata, &payer.pubkey(), &[], supply,
+        ).expect("mint_to"),
+    ];
+
+    let blockhash = rpc.get_latest_blockhash().await.expect("blockhash");
+    let transaction = Transaction::new_signed_with_payer(
+        &instructions, Some(&payer.pubkey()), &[payer, &mint], blockhash,
+    );
+    rpc.send_and_confirm_transaction(&transaction).await.expect("create mint");
+    mint.pubkey()
+}
+
+fn base_units(amount: f64) -> u64 {
+    (amount * 10f64.powi(TEST_DECIMALS as i32)).round() as u64
+}
+
+#[tokio::test]
+async fn sol_payment_lifecycle() {
+    let harness = Harness::start("sol", 18000).await;
+    let recipient = Keypair::new().pubkey();
+
+    let payment = harness.pay(&recipient, "SOL", 1.0).await;
+    assert_eq!(payment["token"], "SOL");
+
+    let received = harness.rpc.get_balance(&recipient).await.expect("recipient balance");
+    assert_eq!(received, LAMPORTS_PER_SOL);
+    // ATA кошелька комиссий создается транзакцией платежа
+    assert_eq!(harness.token_balance(&harness.fee_wallet).await, base_units(FEE_AMOUNT));
+}
+
+#[tokio::test]
+async fn spl_payment_creates_recipient_ata() {
+    let harness = Harness::start("spl", 19000).await;
+    let recipient = Keypair::new().pubkey();
+    let recipient_ata = get_associated_token_address(&recipient, &harness.mint);
+    assert!(harness.rpc.get_account(&recipient_ata).await.is_err(), "recipient ATA must not exist yet");
+
+    harness.pay(&recipient, TEST_TOKEN, 2.5).await;
+    assert_eq!(harness.token_balance(&recipient).await, base_units(2.5));
+    assert_eq!(harness.token_balance(&harness.fee_wallet).await, base_units(FEE_AMOUNT));
+
+    // ATA уже существуют: повторный платеж тому же получателю не должен падать на их создании
+    harness.pay(&recipient, TEST_TOKEN, 1.25).await;
+    assert_eq!(harness.token_balance(&recipient).await, base_units(3.75));
+    assert_eq!(harness.token_balance(&harness.fee_wallet).await, base_units(2.0 * FEE_AMOUNT));
+}
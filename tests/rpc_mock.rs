@@ -0,0 +1,4388 @@
+//! Сборка транзакций и верификация платежей без сети: RPC подменен `MockSolanaRpc`
+
+use actix_web::http::Method;
+use base64::Engine as _;
+use crypto_server::actions::{self, ActionGetResponse};
+use crypto_server::auth::{authorize_key, ApiKeyScope, AuthError};
+use crypto_server::backpressure::{BuildLimiter, Saturated};
+use crypto_server::body_limits::{json_depth, json_guard, JsonLimits};
+use crypto_server::cache::{not_modified, payment_etag, PaymentCache};
+use crypto_server::config::{Config, LogFormat, MerchantConfig, MerchantLimits, PluginPosition, Profile, RpcEndpoint};
+use crypto_server::deadline::{Deadline, DeadlineError};
+use crypto_server::discounts::{CreateDiscountRequest, DiscountError};
+use crypto_server::display::to_base_units;
+use crypto_server::disputes::{
+    DisputeError, DisputeEvidence, DisputeFilter, DisputeReason, DisputeResolution, DisputeService, DisputeStatus,
+    OpenDisputeRequest, ResolveDisputeRequest,
+};
+use crypto_server::error_reporting::{self, ErrorEvent, ErrorKind, ErrorReporter, SentryDsn, SentryReporter};
+use crypto_server::escrow::{EscrowError, EscrowService, HoldAction};
+use crypto_server::events;
+use crypto_server::export::{self, ExportRange};
+use crypto_server::graphql::Viewer;
+use crypto_server::ids::{IdGenerator, ShortCodes, UuidIds};
+use crypto_server::jobs::{JobOutcome, JobScheduler, Schedule};
+use crypto_server::lock::LeaderElection;
+use crypto_server::logging::Logger;
+use crypto_server::limits::{LimitError, LimitOverrideRequest};
+use crypto_server::loyalty::{LoyaltyEntry, LoyaltyEntryKind, RedeemPointsRequest};
+use crypto_server::notifications::NotificationService;
+use crypto_server::observability::{self, LatencyHistograms, Observability};
+use crypto_server::outbox::OutboxDispatcher;
+use crypto_server::ownership::{OwnershipChallenge, OwnershipProofRequest, SQUADS_V4_PROGRAM_ID};
+use crypto_server::payload_signer::{self, PayloadSigner};
+use crypto_server::payment::{
+    select_fields, AmountRange, CreatePaymentRequest, Payment, PaymentListFilter, PaymentService, PaymentStatus, SandboxError,
+    PaymentStatusSummary, RequestTokenError, TransitionError,
+};
+use crypto_server::plugins::{DecoratorContext, InstructionDecorator};
+use crypto_server::pools::{CreatePoolRequest, PoolError, PoolService};
+use crypto_server::price::FiatAmounts;
+use crypto_server::qr::{self, QrKind};
+use crypto_server::rate_limit::{RateDecision, RateLimiter};
+use crypto_server::rpc::{
+    transaction_commitment, BalanceChange, BreakerState, HttpSolanaRpc, MockFailure, MockSolanaRpc, SimulationResult,
+    SolanaRpc, TransactionStatus,
+};
+use crypto_server::receipt::Receipt;
+use crypto_server::receipt_nft::{ReceiptNftMetadata, ReceiptNftService};
+use crypto_server::recipient::RecipientKind;
+use crypto_server::replica::{PaymentReplica, ReadConsistency};
+use crypto_server::reconcile::ReconciliationService;
+use crypto_server::retention::RetentionService;
+use crypto_server::registration::{RegisterMerchantRequest, RegistrationError, RegistrationService};
+use crypto_server::screening::{ScreeningError, ScreeningRole};
+use crypto_server::settlement::{SettlementService, SettlementStatus};
+use crypto_server::signer::ServerSigner;
+use crypto_server::snapshot::SnapshotService;
+use crypto_server::sse;
+use crypto_server::sponsorship::{SponsorshipError, SponsorshipService};
+use crypto_server::storage::VersionConflict;
+use crypto_server::tenants::{CreateApiKeyRequest, TenantError, TenantSource};
+use crypto_server::tokens::{AddTokenRequest, TokenError, TokenSource, TOKEN_METADATA_PROGRAM_ID};
+use crypto_server::transaction::{
+    create_payment_transaction, create_sponsored_payment_transaction, preview_payment_transaction,
+    PreviewInstruction, RefreshError, TransactionPart,
+};
+use solana_sdk::account::Account;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use crypto_server::validation::ValidationErrors;
+use crypto_server::watcher::PaymentWatcher;
+use crypto_server::webhook::{DeliveryStatus, RegisterWebhookRequest, WebhookError, WebhookFilter, WebhookService};
+use solana_sdk::message::VersionedMessage;
+use solana_sdk::program_pack::Pack;
+use solana_sdk::signature::{write_keypair_file, Keypair, Signature, Signer};
+use solana_sdk::transaction::{Transaction, VersionedTransaction};
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const FEE_WALLET: &str = "9E9ME8Xjrnnz5tyLqPWUbXVbPjXusEp9NdjKeugDjW5t";
+
+fn config(rent_policy: &str) -> Config {
+    config_with(rent_policy, "", "")
+}
+
+/// Номер файла конфига: тесты идут параллельно в одном процессе
+static CONFIG_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// Конфиг с дополнительными ключами `[solana]` и секциями в конце файла
+fn config_with(rent_policy: &str, solana: &str, sections: &str) -> Config {
+    let path = std::env::temp_dir().join(format!(
+        "cryptonow-rpc-mock-{}-{}.toml", std::process::id(), CONFIG_FILES.fetch_add(1, Ordering::Relaxed),
+    ));
+    std::fs::write(&path, format!(
+        r#"
+[solana]
+rpc_url = "http://127.0.0.1:1"
+cluster = "devnet"
+fee_wallet = "{FEE_WALLET}"
+fee_amount = 0.001
+fee_token = "SOL"
+rent_policy = "{rent_policy}"
+{solana}
+{sections}
+"#,
+    )).expect("write config");
+    let config = Config::load(path.to_str());
+    let _ = std::fs::remove_file(&path);
+    config.expect("valid config")
+}
+
+async fn service(rent_policy: &str) -> (PaymentService, Arc<MockSolanaRpc>) {
+    let rpc = Arc::new(MockSolanaRpc::new());
+    let service = PaymentService::with_rpc(config(rent_policy), rpc.clone()).await.expect("service");
+    (service, rpc)
+}
+
+async fn create_payment(service: &PaymentService, recipient: &Pubkey, amount: f64) -> Payment {
+    service.create_payment_with_fee(CreatePaymentRequest {
+        recipient: recipient.to_string(),
+        amount,
+        amount_base_units: None,
+        token: "SOL".to_string(),
+        label: None,
+        message: None,
+        memo: None,
+        tip_presets_bps: None,
+        locale: None,
+        metadata: None,
+        fee_amount: None,
+        fee_token: None,
+        success_url: None,
+        cancel_url: None,
+        discount_code: None,
+        redeem_points: None,
+        allowed_payers: None,
+        hold: false,
+        hold_secs: None,
+        amount_range: None,
+    }, None).await.expect("payment created")
+}
+
+async fn build(service: &PaymentService, payment: &Payment) -> anyhow::Result<Transaction> {
+    let payer = Pubkey::new_unique().to_string();
+    let built = create_payment_transaction(
+        payment, &payer, service.config(), service.rpc().as_ref(), TransactionPart::Full, None,
+    ).await?;
+    let bytes = base64::engine::general_purpose::STANDARD.decode(built.transaction)?;
+    Ok(bincode::deserialize(&bytes)?)
+}
+
+/// Успешная транзакция, в которой получатель и кошелек комиссии получили суммы платежа
+fn paid_transaction(service: &PaymentService, payment: &Payment) -> TransactionStatus {
+    let config = service.config();
+    let mint = |token: &str| config.get_token_config(token).and_then(|token| token.mint);
+    let tip = payment.tip_amount.map_or(0, |tip| to_base_units(tip, config.token_decimals(&payment.token)));
+    let fee = to_base_units(payment.fee_amount, config.token_decimals(&payment.fee_token));
+    TransactionStatus {
+        slot: 42,
+        balance_changes: vec![
+            BalanceChange {
+                owner: payment.destination().to_string(),
+                mint: mint(&payment.token),
+                delta: (payment.amount_base_units + tip) as i128,
+            },
+            BalanceChange { owner: payment.fee_recipient.clone(), mint: mint(&payment.fee_token), delta: fee as i128 },
+        ],
+        ..TransactionStatus::default()
+    }
+}
+
+#[tokio::test]
+async fn transaction_uses_rpc_blockhash() {
+    let (service, rpc) = service("reject").await;
+    let blockhash = Hash::new_unique();
+    rpc.set_blockhash(blockhash);
+
+    let payment = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    let transaction = build(&service, &payment).await.expect("transaction built");
+
+    assert_eq!(transaction.message.recent_blockhash, blockhash);
+    // Основной перевод и комиссия
+    assert_eq!(transaction.message.instructions.len(), 2);
+    let reference: Pubkey = payment.reference.parse().unwrap();
+    assert!(transaction.message.account_keys.contains(&reference));
+}
+
+#[tokio::test]
+async fn compute_budget_follows_simulation() {
+    let (service, rpc) = service("reject").await;
+    rpc.set_blockhash(Hash::new_unique());
+    let payment = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    let budgeted = |units: u64| {
+        rpc.set_simulation(SimulationResult { units_consumed: Some(units), ..SimulationResult::default() });
+        create_payment_transaction(
+            &payment, &payment.recipient, service.config(), service.rpc().as_ref(),
+            TransactionPart::Full, Some(service.compute_estimator()),
+        )
+    };
+
+    let built = budgeted(10_000).await.expect("transaction built");
+    let budget = built.compute_budget.expect("compute budget");
+    // 10% запаса к измеренным units
+    assert_eq!(budget.unit_limit, 11_000);
+    assert_eq!(budget.unit_price_micro_lamports, 1_000);
+    let transaction: Transaction = bincode::deserialize(
+        &base64::engine::general_purpose::STANDARD.decode(built.transaction).unwrap(),
+    ).unwrap();
+    // Лимит и цена, затем перевод и комиссия
+    assert_eq!(transaction.message.instructions.len(), 4);
+    let program = transaction.message.program_id(0).unwrap();
+    assert_eq!(*program, solana_sdk::compute_budget::id());
+
+    // Та же форма инструкций — оценка берется из кэша
+    let cached = budgeted(50_000).await.expect("transaction built");
+    assert_eq!(cached.compute_budget, Some(budget));
+}
+
+#[tokio::test]
+async fn small_transfer_to_missing_account_is_rejected() {
+    let (service, rpc) = service("reject").await;
+    let recipient = Pubkey::new_unique();
+    let payment = create_payment(&service, &recipient, 0.0001).await;
+
+    let error = build(&service, &payment).await.expect_err("below rent-exempt minimum");
+    assert!(error.to_string().contains("rent-exempt"), "{}", error);
+
+    // Существующему аккаунту rent-exempt минимум не нужен
+    rpc.set_account(recipient, Account { lamports: 1_000_000, ..Account::default() });
+    build(&service, &payment).await.expect("recipient exists");
+}
+
+#[tokio::test(start_paused = true)]
+async fn rent_check_is_skipped_when_rpc_fails() {
+    let (service, rpc) = service("reject").await;
+    let payment = create_payment(&service, &Pubkey::new_unique(), 0.0001).await;
+
+    rpc.fail("get_account", MockFailure::Malformed);
+    build(&service, &payment).await.expect("malformed response does not block payment");
+
+    rpc.fail("get_account", MockFailure::Timeout);
+    build(&service, &payment).await.expect("timeout does not block payment");
+}
+
+#[tokio::test(start_paused = true)]
+async fn blockhash_failures_are_reported() {
+    let (service, rpc) = service("reject").await;
+    let payment = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+
+    rpc.fail("get_latest_blockhash", MockFailure::Error("node is behind".to_string()));
+    let error = build(&service, &payment).await.expect_err("RPC error");
+    assert!(error.to_string().contains("node is behind"), "{}", error);
+
+    rpc.fail("get_latest_blockhash", MockFailure::Timeout);
+    let error = build(&service, &payment).await.expect_err("RPC timeout");
+    assert!(error.to_string().contains("timed out"), "{}", error);
+
+    rpc.clear_failures();
+    build(&service, &payment).await.expect("RPC recovered");
+}
+
+#[tokio::test]
+async fn transaction_refresh_is_rate_limited() {
+    let (service, _rpc) = service("reject").await;
+    let payment = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    let refresh_error = |result: anyhow::Result<_>| result.expect_err("refresh rejected").downcast::<RefreshError>().expect("refresh error");
+
+    let token = payment.request_token.clone().expect("request token").token;
+    let token = Some(token.as_str());
+    assert!(matches!(refresh_error(service.refresh_transaction(&payment, token).await), RefreshError::NotIssued));
+
+    let payer = Pubkey::new_unique().to_string();
+    service.remember_transaction(&payment, &payer, TransactionPart::Main, payment.locale, None, None).await.expect("remembered");
+    // Без токена из URL пересборка не выдается и не расходует лимит кошелька
+    let error = service.refresh_transaction(&payment, None).await.expect_err("no token");
+    assert!(matches!(error.downcast_ref::<RequestTokenError>(), Some(RequestTokenError::Invalid)));
+    // Токен закреплен за другим кошельком — чужую транзакцию не пересобрать
+    let intruder = service.claim_request_token(payment.clone(), token, &Pubkey::new_unique().to_string()).await
+        .expect("claimed");
+    let error = service.refresh_transaction(&intruder, token).await.expect_err("claimed by another wallet");
+    assert!(matches!(error.downcast_ref::<RequestTokenError>(), Some(RequestTokenError::Claimed)));
+
+    // Сразу после выдачи пересборка еще не разрешена
+    let payment = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    let token = payment.request_token.clone().expect("request token").token;
+    let token = Some(token.as_str());
+    let payment = service.claim_request_token(payment.clone(), token, &payer).await.expect("claimed");
+    service.remember_transaction(&payment, &payer, TransactionPart::Main, payment.locale, None, None).await.expect("remembered");
+    assert!(matches!(
+        refresh_error(service.refresh_transaction(&payment, token).await),
+        RefreshError::TooSoon { retry_after_secs } if retry_after_secs > 0
+    ));
+}
+
+#[tokio::test]
+async fn submit_relays_only_the_issued_transaction() {
+    let (service, rpc) = service("reject").await;
+    rpc.set_blockhash(Hash::new_unique());
+    let payment = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    let payer = Keypair::new();
+    let account = payer.pubkey().to_string();
+
+    service.remember_transaction(&payment, &account, TransactionPart::Full, payment.locale, None, None).await.expect("remembered");
+    let built = create_payment_transaction(
+        &payment, &account, service.config(), service.rpc().as_ref(), TransactionPart::Full, None,
+    ).await.expect("transaction built");
+    let unsigned: Transaction = bincode::deserialize(
+        &base64::engine::general_purpose::STANDARD.decode(built.transaction).unwrap(),
+    ).unwrap();
+    let sign = |message: VersionedMessage| {
+        let signed = VersionedTransaction::try_new(message, &[&payer]).expect("signed");
+        base64::engine::general_purpose::STANDARD.encode(bincode::serialize(&signed).unwrap())
+    };
+
+    // Лишний перевод в сообщении — не та транзакция, что выдавал сервер
+    let mut tampered = unsigned.message.clone();
+    tampered.instructions.push(tampered.instructions[0].clone());
+    let error = service.submit_transaction(&payment, &sign(VersionedMessage::Legacy(tampered))).await
+        .expect_err("tampered transaction rejected");
+    let errors = error.downcast_ref::<ValidationErrors>().expect("validation error");
+    assert_eq!(errors.errors()[0].code, "mismatch");
+    assert!(rpc.sent_transactions().is_empty());
+
+    service.submit_transaction(&payment, &sign(VersionedMessage::Legacy(unsigned.message))).await
+        .expect("submitted");
+    let sent = rpc.sent_transactions();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].signatures[0], payer.sign_message(&sent[0].message.serialize()));
+    // Транзакция отправлена, но еще не подтверждена
+    let payment = service.get_payment(&payment.id).await.unwrap().expect("payment");
+    assert_eq!(payment.status, PaymentStatus::Processing);
+}
+
+#[tokio::test]
+async fn sponsored_transaction_is_presigned_by_hot_wallet() {
+    let hot_wallet = Keypair::new();
+    let keypair_path = std::env::temp_dir().join(format!("cryptonow-rpc-mock-{}-hot.json", std::process::id()));
+    write_keypair_file(&hot_wallet, &keypair_path).expect("write keypair");
+    let config = config_with(
+        "reject",
+        &format!("server_keypair_path = {:?}", keypair_path.to_str().unwrap()),
+        "[sponsorship]\nenabled = true\nmax_lamports_per_payment = 5000000\ndaily_limit_lamports = 6000000",
+    );
+    let signer = ServerSigner::from_file(keypair_path.to_str().unwrap()).expect("signer");
+    let _ = std::fs::remove_file(&keypair_path);
+
+    let rpc = Arc::new(MockSolanaRpc::new());
+    rpc.set_blockhash(Hash::new_unique());
+    let service = PaymentService::with_rpc(config.clone(), rpc.clone()).await.expect("service");
+    let sponsorship = SponsorshipService::new(config, service.storage().clone(), Some(signer));
+    let payment = service.create_payment_with_fee(CreatePaymentRequest {
+        recipient: Pubkey::new_unique().to_string(),
+        amount: 1.0,
+        amount_base_units: None,
+        token: "USDC".to_string(),
+        label: None,
+        message: None,
+        memo: None,
+        tip_presets_bps: None,
+        locale: None,
+        metadata: None,
+        fee_amount: None,
+        fee_token: None,
+        success_url: None,
+        cancel_url: None,
+        discount_code: None,
+        redeem_points: None,
+        allowed_payers: None,
+        hold: false,
+        hold_secs: None,
+        amount_range: None,
+    }, None).await.expect("payment created");
+    assert_eq!(sponsorship.fee_payer(&payment), Some(hot_wallet.pubkey()));
+
+    let payer = Pubkey::new_unique();
+    let built = create_sponsored_payment_transaction(
+        &payment, &payer.to_string(), service.config(), service.rpc().as_ref(),
+        TransactionPart::Full, &sponsorship, None,
+    ).await.expect("transaction built");
+    let transaction: Transaction = bincode::deserialize(
+        &base64::engine::general_purpose::STANDARD.decode(built.transaction).unwrap(),
+    ).unwrap();
+
+    // Hot wallet — fee payer и уже подписал, подпись плательщика — за кошельком
+    let message = transaction.message_data();
+    assert_eq!(transaction.message.account_keys[0], hot_wallet.pubkey());
+    assert!(transaction.signatures[0].verify(hot_wallet.pubkey().as_ref(), &message));
+    assert_eq!(transaction.signatures[1], Signature::default());
+    // Rent токен-аккаунта получателя тоже платит hot wallet
+    let create_ata = transaction.message.instructions.iter()
+        .find(|i| transaction.message.account_keys[i.program_id_index as usize] == spl_associated_token_account::id())
+        .expect("ATA creation");
+    assert_eq!(transaction.message.account_keys[create_ata.accounts[0] as usize], hot_wallet.pubkey());
+
+    // Резерв другого платежа не помещается в суточный лимит, пересборка этого — заменяет прежний
+    let error = sponsorship.reserve("pay_other", 5_000_000).await.expect_err("daily limit");
+    assert!(matches!(error.downcast_ref::<SponsorshipError>(), Some(SponsorshipError::DailyLimitReached)));
+    sponsorship.reserve(&payment.id, 5_000_000).await.expect("reservation replaced");
+    let error = sponsorship.reserve(&payment.id, 5_000_001).await.expect_err("per-payment cap");
+    assert!(matches!(error.downcast_ref::<SponsorshipError>(), Some(SponsorshipError::PaymentCapExceeded { .. })));
+}
+
+#[tokio::test]
+async fn multisig_vault_recipients_receive_tokens_in_their_ata() {
+    let (service, rpc) = service("reject").await;
+    let request = |recipient: &Pubkey| CreatePaymentRequest {
+        recipient: recipient.to_string(),
+        amount: 1.0,
+        amount_base_units: None,
+        token: "USDC".to_string(),
+        label: None,
+        message: None,
+        memo: None,
+        tip_presets_bps: None,
+        locale: None,
+        metadata: None,
+        fee_amount: None,
+        fee_token: None,
+        success_url: None,
+        cancel_url: None,
+        discount_code: None,
+        redeem_points: None,
+        allowed_payers: None,
+        hold: false,
+        hold_secs: None,
+        amount_range: None,
+    };
+
+    // Vault мультисига — PDA вне кривой, принадлежит System Program
+    let (vault, _) = Pubkey::find_program_address(&[b"multisig", b"vault"], &Pubkey::new_unique());
+    let payment = service.create_payment_with_fee(request(&vault), None).await.expect("payment created");
+    assert_eq!(payment.recipient_kind, RecipientKind::Vault);
+    let transaction = build(&service, &payment).await.expect("transaction built");
+    let config = service.config().get_token_config("USDC").unwrap();
+    let mint: Pubkey = config.mint.as_deref().unwrap().parse().unwrap();
+    let vault_ata = spl_associated_token_account::get_associated_token_address(&vault, &mint);
+    assert!(transaction.message.account_keys.contains(&vault_ata));
+    assert!(service.create_ownership_challenge(&vault.to_string(), None, None).await.is_err());
+
+    // Сам аккаунт мультисига и токен-аккаунт платежи не принимают
+    let rejected = |result: anyhow::Result<Payment>, code: &str| {
+        let error = result.expect_err("recipient rejected");
+        let errors = error.downcast_ref::<ValidationErrors>().expect("validation error");
+        errors.0.iter().any(|e| e.field == "recipient" && e.code == code)
+    };
+    let multisig = Pubkey::new_unique();
+    rpc.set_account(multisig, Account { lamports: 1_000_000, owner: Pubkey::new_unique(), ..Account::default() });
+    assert!(rejected(service.create_payment_with_fee(request(&multisig), None).await, "program_account"));
+
+    let token_account = Pubkey::new_unique();
+    let mut data = vec![0; spl_token::state::Account::LEN];
+    spl_token::state::Account::pack(spl_token::state::Account {
+        mint,
+        owner: vault,
+        state: spl_token::state::AccountState::Initialized,
+        ..Default::default()
+    }, &mut data).unwrap();
+    rpc.set_account(token_account, Account { lamports: 2_039_280, owner: spl_token::ID, data, ..Account::default() });
+    assert!(rejected(service.create_payment_with_fee(request(&token_account), None).await, "token_account"));
+    assert!(rejected(service.create_payment_with_fee(request(&Pubkey::default()), None).await, "default_pubkey"));
+
+    // Строгая политика: адреса вне кривой не принимаются вовсе
+    let strict = PaymentService::with_rpc(config_with("reject", "off_curve_recipients = \"reject\"", ""), rpc.clone()).await
+        .expect("service");
+    assert!(rejected(strict.create_payment_with_fee(request(&vault), None).await, "off_curve"));
+    let wallet = Keypair::new().pubkey();
+    assert_eq!(strict.create_payment_with_fee(request(&wallet), None).await.expect("wallet").recipient_kind, RecipientKind::Wallet);
+}
+
+#[tokio::test]
+async fn wallet_challenges_are_single_use_and_not_replaced_by_later_ones() {
+    let (service, _rpc) = service("reject").await;
+    let wallet = Keypair::new();
+    let proof = |challenge: &OwnershipChallenge, signer: &Keypair| OwnershipProofRequest {
+        wallet: wallet.pubkey().to_string(),
+        nonce: challenge.nonce.clone(),
+        signature: signer.sign_message(challenge.message.as_bytes()).to_string(),
+    };
+
+    // Повторный запрос challenge (в том числе чужой) не отменяет уже выданный
+    let first = service.create_ownership_challenge(&wallet.pubkey().to_string(), None, None).await.expect("challenge");
+    let second = service.create_ownership_challenge(&wallet.pubkey().to_string(), None, None).await.expect("challenge");
+    assert_ne!(first.nonce, second.nonce);
+    service.verify_ownership(&proof(&first, &wallet)).await.expect("first challenge verified");
+    assert!(service.verify_ownership(&proof(&first, &wallet)).await.is_err());
+
+    // Чужая подпись и nonce другого кошелька не принимаются
+    assert!(service.verify_ownership(&proof(&second, &Keypair::new())).await.is_err());
+    let other = service.create_ownership_challenge(&Keypair::new().pubkey().to_string(), None, None).await.expect("challenge");
+    let mut foreign = proof(&other, &wallet);
+    foreign.signature = wallet.sign_message(other.message.as_bytes()).to_string();
+    assert!(service.verify_ownership(&foreign).await.is_err());
+}
+
+#[tokio::test]
+async fn multisig_vault_is_verified_by_member_signature() {
+    let rpc = Arc::new(MockSolanaRpc::new());
+    let service = PaymentService::with_rpc(config_with("reject", "require_verified_recipient = true", ""), rpc.clone()).await
+        .expect("service");
+    let member = Keypair::new();
+    let multisig = Pubkey::new_unique();
+    let (vault, _) = Pubkey::find_program_address(&[b"multisig", multisig.as_ref(), b"vault", &[0]], &SQUADS_V4_PROGRAM_ID);
+
+    // Аккаунт мультисига Squads v4: заголовок, rent_collector = None, bump, members
+    let mut data = vec![0u8; 8 + 32 + 32 + 2 + 4 + 8 + 8];
+    data.extend_from_slice(&[0, 255]);
+    data.extend_from_slice(&1u32.to_le_bytes());
+    data.extend_from_slice(member.pubkey().as_ref());
+    data.push(7);
+    rpc.set_account(multisig, Account { lamports: 1_000_000, owner: SQUADS_V4_PROGRAM_ID, data, ..Account::default() });
+    let payment_request = |recipient: &Pubkey| CreatePaymentRequest {
+        recipient: recipient.to_string(),
+        amount: 1.0,
+        amount_base_units: None,
+        token: "USDC".to_string(),
+        label: None,
+        message: None,
+        memo: None,
+        tip_presets_bps: None,
+        locale: None,
+        metadata: None,
+        fee_amount: None,
+        fee_token: None,
+        success_url: None,
+        cancel_url: None,
+        discount_code: None,
+        redeem_points: None,
+        allowed_payers: None,
+        hold: false,
+        hold_secs: None,
+        amount_range: None,
+    };
+
+    let vault_str = vault.to_string();
+    let multisig_str = multisig.to_string();
+    let member_str = member.pubkey().to_string();
+    assert!(service.create_ownership_challenge(&vault_str, None, None).await.is_err());
+    assert!(service.create_ownership_challenge(&vault_str, Some(&Pubkey::new_unique().to_string()), Some(&member_str)).await.is_err());
+    let proof = |challenge: &OwnershipChallenge, signer: &Keypair| OwnershipProofRequest {
+        wallet: vault_str.clone(),
+        nonce: challenge.nonce.clone(),
+        signature: signer.sign_message(challenge.message.as_bytes()).to_string(),
+    };
+
+    // Подпись не участника мультисига не принимается
+    let outsider = Keypair::new();
+    let outsider_str = outsider.pubkey().to_string();
+    let challenge = service.create_ownership_challenge(&vault_str, Some(&multisig_str), Some(&outsider_str)).await
+        .expect("challenge");
+    assert!(service.verify_ownership(&proof(&challenge, &outsider)).await.is_err());
+    assert!(service.create_payment_with_fee(payment_request(&vault), None).await.is_err());
+
+    let challenge = service.create_ownership_challenge(&vault_str, Some(&multisig_str), Some(&member_str)).await
+        .expect("challenge");
+    assert!(challenge.message.contains(&multisig_str));
+    assert!(service.verify_ownership(&proof(&challenge, &Keypair::new())).await.is_err());
+    let challenge = service.create_ownership_challenge(&vault_str, Some(&multisig_str), Some(&member_str)).await
+        .expect("challenge");
+    let verified = service.verify_ownership(&proof(&challenge, &member)).await.expect("vault verified");
+    assert_eq!(verified.member.as_deref(), Some(member_str.as_str()));
+    service.create_payment_with_fee(payment_request(&vault), None).await.expect("payment to verified vault");
+}
+
+#[tokio::test]
+async fn wrapped_sol_is_wrapped_or_unwrapped_for_payer() {
+    let config = config_with("reject", "", r#"
+[[solana.supported_tokens]]
+symbol = "SOL"
+decimals = 9
+name = "Solana"
+
+[[solana.supported_tokens]]
+symbol = "USDC"
+mint = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"
+decimals = 6
+name = "USD Coin"
+
+[[solana.supported_tokens]]
+symbol = "WSOL"
+mint = "So11111111111111111111111111111111111111112"
+decimals = 9
+name = "Wrapped SOL"
+"#);
+    let rpc = Arc::new(MockSolanaRpc::new());
+    let service = PaymentService::with_rpc(config, rpc.clone()).await.expect("service");
+    let payer = Pubkey::new_unique();
+    let wsol_account = spl_associated_token_account::get_associated_token_address(&payer, &spl_token::native_mint::id());
+    let build_for_payer = |payment: Payment| {
+        let service = service.clone();
+        async move {
+            let built = create_payment_transaction(
+                &payment, &payer.to_string(), service.config(), service.rpc().as_ref(), TransactionPart::Full, None,
+            ).await.expect("transaction built");
+            let bytes = base64::engine::general_purpose::STANDARD.decode(built.transaction).unwrap();
+            let transaction: Transaction = bincode::deserialize(&bytes).unwrap();
+            let programs: Vec<Pubkey> = transaction.message.instructions.iter()
+                .map(|i| transaction.message.account_keys[i.program_id_index as usize])
+                .collect();
+            (transaction, programs, built.notes)
+        }
+    };
+    let is_close = |transaction: &Transaction, index: usize| {
+        let instruction = &transaction.message.instructions[index];
+        transaction.message.account_keys[instruction.program_id_index as usize] == spl_token::ID
+            && matches!(spl_token::instruction::TokenInstruction::unpack(&instruction.data),
+                Ok(spl_token::instruction::TokenInstruction::CloseAccount))
+    };
+
+    // Платеж в wSOL без wSOL у плательщика: временный аккаунт создается, пополняется и закрывается
+    let recipient = Keypair::new().pubkey();
+    let request = |token: &str| CreatePaymentRequest {
+        recipient: recipient.to_string(),
+        amount: 1.0,
+        amount_base_units: None,
+        token: token.to_string(),
+        label: None,
+        message: None,
+        memo: None,
+        tip_presets_bps: None,
+        locale: None,
+        metadata: None,
+        fee_amount: None,
+        fee_token: None,
+        success_url: None,
+        cancel_url: None,
+        discount_code: None,
+        redeem_points: None,
+        allowed_payers: None,
+        hold: false,
+        hold_secs: None,
+        amount_range: None,
+    };
+    let payment = service.create_payment_with_fee(request("WSOL"), None).await.expect("payment created");
+    let (transaction, programs, notes) = build_for_payer(payment).await;
+    assert_eq!(programs[0], spl_associated_token_account::id());
+    assert_eq!(programs[1], solana_sdk::system_program::ID);
+    assert!(is_close(&transaction, programs.len() - 1));
+    assert!(notes.iter().any(|note| note.contains("Wraps 1 SOL")));
+
+    // Платеж в SOL, когда у плательщика есть только wSOL: аккаунт разворачивается первым
+    let mut data = vec![0; spl_token::state::Account::LEN];
+    spl_token::state::Account::pack(spl_token::state::Account {
+        mint: spl_token::native_mint::id(),
+        owner: payer,
+        amount: 2_000_000_000,
+        state: spl_token::state::AccountState::Initialized,
+        ..Default::default()
+    }, &mut data).unwrap();
+    rpc.set_account(wsol_account, Account { lamports: 2_002_039_280, owner: spl_token::ID, data, ..Account::default() });
+    let payment = service.create_payment_with_fee(request("SOL"), None).await.expect("payment created");
+    let (transaction, _, notes) = build_for_payer(payment).await;
+    assert!(is_close(&transaction, 0));
+    assert!(notes.iter().any(|note| note.contains("Unwraps 2 wSOL")));
+
+    // wSOL хватает — ничего не оборачивается и аккаунт плательщика остается
+    let payment = service.create_payment_with_fee(request("WSOL"), None).await.expect("payment created");
+    let (transaction, programs, _) = build_for_payer(payment).await;
+    // Только ATA получателя
+    assert_eq!(programs.iter().filter(|&&program| program == spl_associated_token_account::id()).count(), 1);
+    assert!(!(0..programs.len()).any(|index| is_close(&transaction, index)));
+
+    // Балансы не прочитать — существующий аккаунт wSOL нельзя закрыть вслепую, сборка падает
+    rpc.fail("get_account", MockFailure::Error("node is behind".to_string()));
+    let payment = service.create_payment_with_fee(request("WSOL"), None).await.expect("payment created");
+    let error = create_payment_transaction(
+        &payment, &payer.to_string(), service.config(), service.rpc().as_ref(), TransactionPart::Full, None,
+    ).await.expect_err("balance lookup failed");
+    assert!(format!("{:#}", error).contains("wSOL balances"));
+}
+
+#[tokio::test]
+async fn screening_rejects_or_flags_denylisted_addresses() {
+    let (blocked_recipient, blocked_payer) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let denylist = std::env::temp_dir().join(format!("cryptonow-denylist-{}.txt", std::process::id()));
+    std::fs::write(&denylist, format!(
+        "# OFAC\n{blocked_recipient} SDN list\n{blocked_payer}\n",
+    )).unwrap();
+    let screening = |action: &str| config_with("reject", "", &format!(
+        "[screening]\nenabled = true\naction = \"{action}\"\ndenylist_path = {:?}\n", denylist.to_str().unwrap(),
+    ));
+
+    let rpc = Arc::new(MockSolanaRpc::new());
+    let service = PaymentService::with_rpc(screening("reject"), rpc.clone()).await.expect("service");
+    let request = |recipient: &Pubkey| CreatePaymentRequest {
+        recipient: recipient.to_string(),
+        amount: 1.0,
+        amount_base_units: None,
+        token: "SOL".to_string(),
+        label: None,
+        message: None,
+        memo: None,
+        tip_presets_bps: None,
+        locale: None,
+        metadata: None,
+        fee_amount: None,
+        fee_token: None,
+        success_url: None,
+        cancel_url: None,
+        discount_code: None,
+        redeem_points: None,
+        allowed_payers: None,
+        hold: false,
+        hold_secs: None,
+        amount_range: None,
+    };
+    let error = service.create_payment_with_fee(request(&blocked_recipient), None).await
+        .expect_err("recipient is denylisted");
+    match error.downcast_ref::<ScreeningError>() {
+        Some(ScreeningError::Blocked { reason, .. }) => assert_eq!(reason, "SDN list"),
+        None => panic!("unexpected error: {}", error),
+    }
+    let payment = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    assert!(service.screen_payer(payment, &blocked_payer.to_string()).await.is_err());
+
+    // В режиме flag платеж пропускается с пометкой, совпадения остаются в аудите
+    let service = PaymentService::with_rpc(screening("flag"), rpc).await.expect("service");
+    let payment = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    let payment = service.screen_payer(payment, &blocked_payer.to_string()).await.expect("flagged");
+    let payment = service.screen_payer(payment, &blocked_payer.to_string()).await.expect("flagged");
+    let _ = std::fs::remove_file(&denylist);
+
+    assert_eq!(payment.screening_flags.len(), 1);
+    assert_eq!(payment.screening_flags[0].role, ScreeningRole::Payer);
+    let stored = service.get_payment(&payment.id).await.unwrap().expect("payment");
+    assert_eq!(stored.screening_flags.len(), 1);
+    let audit = service.screening().history(Some(&payment.id)).await.unwrap();
+    assert_eq!(audit.len(), 2);
+    assert_eq!(audit[0].reason, "Address is on the denylist");
+}
+
+#[tokio::test]
+async fn merchant_limits_cap_daily_payments_and_volume() {
+    let config = config_with("reject", "", r#"
+[[merchants]]
+id = "shop"
+name = "Shop"
+api_key = "shop-api-key-0123456789"
+[merchants.limits]
+max_daily_payments = 3
+max_daily_volume = { SOL = 2.5 }
+"#);
+    let rpc = Arc::new(MockSolanaRpc::new());
+    let service = PaymentService::with_rpc(config, rpc).await.expect("service");
+    let shop = service.config().find_merchant("shop");
+    let merchant = shop.as_deref();
+    let request = |amount: f64| CreatePaymentRequest {
+        recipient: Pubkey::new_unique().to_string(),
+        amount,
+        amount_base_units: None,
+        token: "SOL".to_string(),
+        label: None,
+        message: None,
+        memo: None,
+        tip_presets_bps: None,
+        locale: None,
+        metadata: None,
+        fee_amount: None,
+        fee_token: None,
+        success_url: None,
+        cancel_url: None,
+        discount_code: None,
+        redeem_points: None,
+        allowed_payers: None,
+        hold: false,
+        hold_secs: None,
+        amount_range: None,
+    };
+
+    service.create_payment_with_fee(request(1.0), merchant).await.expect("first payment");
+    service.create_payment_with_fee(request(1.0), merchant).await.expect("second payment");
+    let error = service.create_payment_with_fee(request(1.0), merchant).await.expect_err("volume limit");
+    assert!(matches!(error.downcast_ref::<LimitError>(), Some(LimitError::VolumeExceeded { .. })));
+    service.create_payment_with_fee(request(0.5), merchant).await.expect("fits the volume");
+    let error = service.create_payment_with_fee(request(0.1), merchant).await.expect_err("count limit");
+    match error.downcast_ref::<LimitError>() {
+        Some(LimitError::TooManyPayments { limit, retry_after_secs }) => {
+            assert_eq!(*limit, 3);
+            assert!(*retry_after_secs > 23 * 3600);
+        }
+        _ => panic!("unexpected error: {}", error),
+    }
+    // Анонимные платежи лимитами мерчанта не ограничены
+    create_payment(&service, &Pubkey::new_unique(), 10.0).await;
+
+    // Переопределение заменяет лимиты целиком, после удаления снова действуют настроенные
+    service.limits().set_override("shop", LimitOverrideRequest {
+        limits: MerchantLimits { max_daily_payments: Some(10), ..Default::default() },
+        reason: Some("Black Friday".to_string()),
+        expires_at: None,
+    }).await.expect("override");
+    service.create_payment_with_fee(request(5.0), merchant).await.expect("override lifts limits");
+    let status = service.limits().status("shop").await.unwrap();
+    assert_eq!(status.usage.payments, 4);
+    assert_eq!(status.usage.volume.get("SOL"), Some(&7.5));
+
+    assert!(service.limits().clear_override("shop").await.unwrap());
+    assert!(service.create_payment_with_fee(request(0.1), merchant).await.is_err());
+}
+
+#[tokio::test]
+async fn verify_completes_confirmed_transaction() {
+    let (service, rpc) = service("reject").await;
+    let payment = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    let paid = paid_transaction(&service, &payment);
+
+    // Успешная транзакция не засчитывается, если получатель не получил сумму платежа
+    let mut elsewhere = paid.clone();
+    elsewhere.balance_changes[0].owner = Pubkey::new_unique().to_string();
+    let mut short = paid.clone();
+    short.balance_changes[0].delta -= 1;
+    for status in [TransactionStatus { slot: 42, ..TransactionStatus::default() }, elsewhere, short] {
+        let signature = Signature::new_unique();
+        rpc.set_transaction(signature, status);
+        let result = service.verify_payment(&payment.id, &signature.to_string()).await.expect("result");
+        assert!(!result.verified);
+        assert!(result.details.contains(" of 1000000000 SOL base units"), "{}", result.details);
+    }
+
+    let signature = Signature::new_unique();
+    rpc.set_transaction(signature, TransactionStatus { block_time: Some(1_700_000_000), ..paid });
+    let result = service.verify_payment(&payment.id, &signature.to_string()).await.expect("verified");
+    assert!(result.verified, "{}", result.details);
+
+    let payment = service.get_payment(&payment.id).await.unwrap().expect("payment");
+    assert!(matches!(payment.status, PaymentStatus::Completed));
+    assert_eq!(payment.signature, Some(signature.to_string()));
+    assert_eq!(payment.block_time.map(|t| t.timestamp()), Some(1_700_000_000));
+
+    let storage = service.storage();
+    let by_signature = storage.find_payment_by_signature(&signature.to_string()).await.unwrap();
+    assert_eq!(by_signature.map(|p| p.id), Some(payment.id.clone()));
+    let by_reference = storage.find_payment_by_reference(&payment.reference).await.unwrap();
+    assert_eq!(by_reference.map(|p| p.id), Some(payment.id));
+}
+
+#[tokio::test]
+async fn watcher_polling_completes_payment_by_reference() {
+    let (service, rpc) = service("reject").await;
+    let payment = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    let other = create_payment(&service, &Pubkey::new_unique(), 2.0).await;
+    let watcher = PaymentWatcher::new(service.clone());
+    assert_eq!(watcher.poll_once().await.expect("polled"), 0);
+
+    // Новые первыми: сначала проверяется старая неуспешная транзакция
+    let (failed, landed) = (Signature::new_unique(), Signature::new_unique());
+    rpc.set_transaction(failed, TransactionStatus {
+        slot: 41,
+        error: Some("InstructionError".to_string()),
+        ..TransactionStatus::default()
+    });
+    rpc.set_transaction(landed, paid_transaction(&service, &payment));
+    rpc.set_signatures(payment.reference.parse().unwrap(), vec![landed, failed]);
+
+    assert_eq!(watcher.poll_once().await.expect("polled"), 1);
+    let completed = service.get_payment(&payment.id).await.unwrap().expect("payment");
+    assert_eq!(completed.status, PaymentStatus::Completed);
+    assert_eq!(completed.signature, Some(landed.to_string()));
+    let untouched = service.get_payment(&other.id).await.unwrap().expect("payment");
+    assert_eq!(untouched.status, PaymentStatus::Pending);
+
+    // Сбой RPC не мешает следующему проходу
+    rpc.fail("get_signatures_for_address", MockFailure::Error("unavailable".to_string()));
+    assert_eq!(watcher.poll_once().await.expect("polled"), 0);
+}
+
+#[tokio::test]
+async fn failing_rpc_endpoint_is_ejected() {
+    let rpc = HttpSolanaRpc::new(&config("reject")).expect("rpc pool");
+    let account = Pubkey::new_unique();
+    for _ in 0..3 {
+        let error = rpc.get_account(&account).await.expect_err("endpoint is down");
+        assert!(!error.to_string().contains("ejected"), "{}", error);
+    }
+
+    let stats = rpc.endpoint_stats();
+    assert_eq!(stats.len(), 1);
+    assert_eq!(stats[0].state, BreakerState::Open);
+    assert_eq!((stats[0].requests, stats[0].failures), (3, 3));
+    assert_eq!(stats[0].error_rate, 1.0);
+    assert!(stats[0].retry_at.is_some() && stats[0].last_error.is_some());
+
+    // Пока узел исключен, запросы не ждут его таймаута
+    let error = rpc.get_account(&account).await.expect_err("endpoint is ejected");
+    assert!(error.to_string().contains("ejected"), "{}", error);
+    assert_eq!(rpc.endpoint_stats()[0].requests, 3);
+}
+
+#[tokio::test]
+async fn payments_count_down_by_server_clock_and_warn_before_expiry() {
+    use futures::StreamExt;
+
+    let config = config_with("reject", "", "[server]\nexpiring_soon_secs = 3600\n");
+    let service = PaymentService::with_rpc(config, Arc::new(MockSolanaRpc::new())).await.expect("service");
+    let payment = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    assert!((1..=30 * 60).contains(&payment.expires_in_seconds), "{}", payment.expires_in_seconds);
+    assert!((chrono::Utc::now() - payment.server_time).num_seconds() < 5);
+    let json = serde_json::to_value(&payment).unwrap();
+    assert!(json["expires_in_seconds"].is_i64() && json["server_time"].is_string());
+
+    // Срок меньше порога — `expiring_soon` приходит сразу после состояния
+    let events = sse::payment_events(service.clone(), &payment.id).await.unwrap().expect("stream");
+    let mut events = Box::pin(events.map(|chunk| String::from_utf8(chunk.unwrap().to_vec()).unwrap()));
+    assert!(events.next().await.unwrap().starts_with("event: payment\n"));
+    let warning = events.next().await.unwrap();
+    assert!(warning.starts_with("event: expiring_soon\n"), "{}", warning);
+    assert!(warning.contains("\"expires_in_seconds\":") && warning.contains("\"server_time\":"), "{}", warning);
+
+    // У закрытого платежа отсчета нет
+    let cancelled = service.change_status(&payment.id, None, PaymentStatus::Cancelled, None).await.unwrap().unwrap();
+    assert_eq!(cancelled.expires_in_seconds, 0);
+    assert!(events.next().await.unwrap().contains("\"status\":\"cancelled\""));
+}
+
+#[tokio::test]
+async fn old_payments_are_archived_by_retention_policy_and_purged_by_admin() {
+    let config = config_with("reject", "", "[retention]\ncompleted_days = 30\nexpired_days = 1\n");
+    let service = PaymentService::with_rpc(config.clone(), Arc::new(MockSolanaRpc::new())).await.expect("service");
+    let retention = RetentionService::new(config, service.storage().clone());
+    let days_ago = |days: i64| chrono::Utc::now() - chrono::Duration::days(days);
+
+    let mut old_paid = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    service.transition(&mut old_paid, PaymentStatus::Processing, None).await.unwrap();
+    service.transition(&mut old_paid, PaymentStatus::Completed, None).await.unwrap();
+    old_paid.created_at = days_ago(31);
+    old_paid.verified_at = Some(days_ago(31));
+    service.storage().save_payment(&old_paid.id.clone(), &mut old_paid).await.unwrap();
+    let mut recent_paid = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    recent_paid.created_at = days_ago(2);
+    recent_paid.verified_at = Some(days_ago(2));
+    recent_paid.status = PaymentStatus::Completed;
+    service.storage().save_payment(&recent_paid.id.clone(), &mut recent_paid).await.unwrap();
+    let mut expired = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    expired.expires_at = days_ago(2);
+    service.storage().save_payment(&expired.id.clone(), &mut expired).await.unwrap();
+    let pending = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+
+    // Архивация не удаляет: платеж читается как обычно, с отметкой архива
+    assert_eq!(retention.archive().await.unwrap(), 2);
+    assert_eq!(retention.archive().await.unwrap(), 0);
+    let archived = |id: String| {
+        let service = service.clone();
+        async move { service.get_payment(&id).await.unwrap().map(|p| p.archived_at.is_some()) }
+    };
+    assert_eq!(archived(old_paid.id.clone()).await, Some(true));
+    assert_eq!(archived(expired.id.clone()).await, Some(true));
+    assert_eq!(archived(recent_paid.id.clone()).await, Some(false));
+    assert_eq!(archived(pending.id.clone()).await, Some(false));
+
+    // Purge удаляет только то, что пролежало в архиве дольше порога
+    assert_eq!(retention.purge(1).await.unwrap(), 0);
+    assert_eq!(retention.purge(0).await.unwrap(), 2);
+    assert_eq!(archived(old_paid.id.clone()).await, None);
+    assert_eq!(archived(expired.id.clone()).await, None);
+    assert_eq!(archived(pending.id.clone()).await, Some(false));
+}
+
+#[tokio::test]
+async fn payment_history_is_exported_as_csv_or_xlsx_within_a_date_range() {
+    use futures::StreamExt;
+
+    let (service, rpc) = service("reject").await;
+    let mut old = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    old.created_at = "2026-01-31T23:00:00Z".parse().unwrap();
+    service.storage().save_payment(&old.id.clone(), &mut old).await.unwrap();
+    let paid = create_payment(&service, &Pubkey::new_unique(), 2.5).await;
+    let signature = Signature::new_unique();
+    rpc.set_transaction(signature, paid_transaction(&service, &paid));
+    service.verify_payment(&paid.id, &signature.to_string()).await.expect("verified");
+    let paid = service.get_payment(&paid.id).await.unwrap().unwrap();
+
+    let invalid = ExportRange::parse(Some("2026-02-30"), Some("2026-01-01")).expect_err("invalid range");
+    assert_eq!(invalid.errors()[0].field, "from");
+    assert_eq!(invalid.errors()[0].code, "invalid_date");
+    assert!(ExportRange::parse(Some("2026-02-01"), Some("2026-01-01")).is_err());
+    // Дата в `to` включает весь день
+    let january = ExportRange::parse(None, Some("2026-01-31")).expect("range");
+    assert!(january.contains(&old) && !january.contains(&paid));
+    let recent = ExportRange::parse(Some("2026-02-01"), None).expect("range");
+    assert!(!recent.contains(&old) && recent.contains(&paid));
+
+    let chunks: Vec<_> = export::csv_stream(vec![old.clone(), paid.clone()]).collect().await;
+    let csv: Vec<u8> = chunks.into_iter().flat_map(|chunk| chunk.unwrap().to_vec()).collect();
+    let mut reader = csv::Reader::from_reader(csv.as_slice());
+    let headers = reader.headers().unwrap().clone();
+    let rows: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+    assert_eq!(rows.len(), 2);
+    let column = |row: &csv::StringRecord, name: &str| {
+        row[headers.iter().position(|header| header == name).expect(name)].to_string()
+    };
+    assert_eq!(column(&rows[0], "status"), "pending");
+    assert_eq!(column(&rows[0], "signature"), "");
+    assert_eq!(column(&rows[1], "id"), paid.id);
+    assert_eq!(column(&rows[1], "amount"), "2.5");
+    assert_eq!(column(&rows[1], "status"), "completed");
+    assert_eq!(column(&rows[1], "signature"), signature.to_string());
+    assert_eq!(column(&rows[1], "fee_amount"), "0.001");
+
+    // Книга xlsx — zip-архив
+    let workbook = export::to_xlsx(&[old, paid]).expect("xlsx");
+    assert!(workbook.starts_with(b"PK"));
+}
+
+#[tokio::test]
+async fn usd_rates_are_snapshotted_at_completion_for_receipts_and_exports() {
+    use futures::StreamExt;
+    use std::io::{Read, Write};
+
+    // Ценовой API: SOL по 150.5 USD
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/price", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut buffer = [0u8; 4096];
+            let _ = stream.read(&mut buffer);
+            let body = r#"{"data":{"So11111111111111111111111111111111111111112":{"price":"150.5"}}}"#;
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(), body,
+            );
+        }
+    });
+    let rpc = Arc::new(MockSolanaRpc::new());
+    let config = config_with("reject", &format!("price_api_url = \"{}\"\n", url), "");
+    let service = PaymentService::with_rpc(config, rpc.clone()).await.expect("service");
+
+    let payment = create_payment(&service, &Pubkey::new_unique(), 2.0).await;
+    assert!(payment.usd_valuation.is_none());
+    let signature = Signature::new_unique();
+    rpc.set_transaction(signature, paid_transaction(&service, &payment));
+    service.verify_payment(&payment.id, &signature.to_string()).await.expect("verified");
+    let payment = service.get_payment(&payment.id).await.unwrap().unwrap();
+
+    let valuation = payment.usd_valuation.clone().expect("valuation recorded");
+    assert_eq!((valuation.token_usd_rate, valuation.fee_token_usd_rate), (150.5, 150.5));
+    assert!((valuation.amount_usd - 301.0).abs() < 1e-9);
+    assert!((valuation.fee_usd - 0.1505).abs() < 1e-9);
+    assert_eq!(valuation.tip_usd, None);
+
+    let receipt = Receipt::from_payment(&payment, &service.config().explorer()).expect("receipt");
+    assert_eq!(receipt.usd_valuation.map(|v| v.token_usd_rate), Some(150.5));
+
+    let chunks: Vec<_> = export::csv_stream(vec![payment]).collect().await;
+    let csv: Vec<u8> = chunks.into_iter().flat_map(|chunk| chunk.unwrap().to_vec()).collect();
+    let mut reader = csv::Reader::from_reader(csv.as_slice());
+    let headers = reader.headers().unwrap().clone();
+    let row = reader.records().next().unwrap().unwrap();
+    let column = |name: &str| row[headers.iter().position(|header| header == name).expect(name)].to_string();
+    assert_eq!(column("token_usd_rate"), "150.5");
+    assert_eq!(column("amount_usd_at_completion"), "301");
+}
+
+#[tokio::test]
+async fn settlement_swaps_amount_and_tip_into_the_merchant_token() {
+    use std::io::{Read, Write};
+
+    // Jupiter: котировка на любую сумму и пустая инструкция свопа; строки запросов — в тест
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (received, requests) = std::sync::mpsc::channel::<String>();
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 4096];
+            loop {
+                let read = stream.read(&mut buffer).unwrap_or(0);
+                request.extend_from_slice(&buffer[..read]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                let complete = text.split_once("\r\n\r\n").is_some_and(|(head, body)| {
+                    let length = head.lines()
+                        .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().to_string()))
+                        .and_then(|v| v.parse::<usize>().ok())
+                        .unwrap_or(0);
+                    body.len() >= length
+                });
+                if read == 0 || complete {
+                    break;
+                }
+            }
+            let request = String::from_utf8_lossy(&request).to_string();
+            let body = match request.starts_with("GET /quote") {
+                true => json!({"inAmount": "0", "outAmount": "7000000", "otherAmountThreshold": "6965000"}),
+                false => json!({
+                    "swapInstruction": {"programId": Pubkey::new_unique().to_string(), "accounts": [], "data": ""},
+                    "addressLookupTableAddresses": [],
+                }),
+            }.to_string();
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(), body,
+            );
+            let _ = received.send(request.lines().next().unwrap_or_default().to_string());
+        }
+    });
+
+    let hot_wallet = Keypair::new();
+    let keypair_path = std::env::temp_dir().join(format!("cryptonow-rpc-mock-{}-settlement.json", std::process::id()));
+    write_keypair_file(&hot_wallet, &keypair_path).expect("write keypair");
+    let destination = Pubkey::new_unique();
+    let config = config_with(
+        "reject",
+        &format!("server_keypair_path = {:?}", keypair_path.to_str().unwrap()),
+        &format!(r#"
+[swap]
+enabled = true
+api_url = "{url}"
+
+[[merchants]]
+id = "shop"
+name = "Shop"
+api_key = "live-key-0123456789"
+
+[merchants.settlement]
+token = "SOL"
+address = "{destination}"
+"#),
+    );
+    let rpc = Arc::new(MockSolanaRpc::new());
+    let service = PaymentService::with_rpc(config.clone(), rpc.clone()).await.expect("service");
+    let signer = ServerSigner::load(keypair_path.to_str()).unwrap();
+    let _ = std::fs::remove_file(&keypair_path);
+    let settlement = SettlementService::new(config, rpc.clone(), service.storage().clone(), signer);
+
+    // 1 USDC и 0.000249 USDC чаевых на hot wallet (0.000249 * 1e6 < 249 в f64)
+    let merchant = service.config().merchants[0].clone();
+    let mut payment = service.create_payment_with_fee(CreatePaymentRequest {
+        recipient: hot_wallet.pubkey().to_string(),
+        amount: 1.0,
+        amount_base_units: None,
+        token: "USDC".to_string(),
+        label: None,
+        message: None,
+        memo: None,
+        tip_presets_bps: None,
+        locale: None,
+        metadata: None,
+        fee_amount: None,
+        fee_token: None,
+        success_url: None,
+        cancel_url: None,
+        discount_code: None,
+        redeem_points: None,
+        allowed_payers: None,
+        hold: false,
+        hold_secs: None,
+        amount_range: None,
+    }, Some(&merchant)).await.expect("payment");
+    payment.tip_amount = Some(0.000249);
+    service.storage().save_payment(&payment.id.clone(), &mut payment).await.unwrap();
+    let signature = Signature::new_unique();
+    rpc.set_transaction(signature, paid_transaction(&service, &payment));
+    service.verify_payment(&payment.id, &signature.to_string()).await.expect("verified");
+
+    let records = settlement.run(false).await.expect("settled");
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].status, SettlementStatus::Completed, "{}", records[0].details);
+    assert_eq!((records[0].payment_id.as_str(), records[0].to_token.as_str()), (payment.id.as_str(), "SOL"));
+    assert_eq!(records[0].amount_out_min, 0.006965);
+    let quote = requests.recv_timeout(Duration::from_secs(5)).expect("quote requested");
+    assert!(quote.contains("swapMode=ExactIn") && quote.contains("amount=1000249&"), "{}", quote);
+
+    // Своп и перевод гарантированного минимума на адрес расчетов — одной транзакцией hot wallet
+    let sent = rpc.sent_transactions();
+    assert_eq!(sent.len(), 1);
+    let keys = sent[0].message.static_account_keys();
+    assert_eq!(keys[0], hot_wallet.pubkey());
+    assert!(keys.contains(&destination));
+
+    // Рассчитанный платеж повторно не берется
+    assert!(settlement.run(false).await.expect("nothing pending").is_empty());
+    assert_eq!(settlement.history(Some(&payment.id)).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn payments_carry_wallet_universal_links_to_the_checkout_page() {
+    let checkout = config_with("reject", "", "[server]\ncheckout_url = \"https://pay.shop.example/checkout/{id}?lang=en\"\n");
+    let service = PaymentService::with_rpc(checkout.clone(), Arc::new(MockSolanaRpc::new())).await.expect("service");
+    let payment = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+
+    let links = payment.wallet_links.clone().expect("wallet links");
+    let page = format!("https://pay.shop.example/checkout/{}?lang=en", payment.id);
+    assert_eq!(links.checkout_url, page);
+    let encoded = format!("https%3A%2F%2Fpay.shop.example%2Fcheckout%2F{}%3Flang%3Den", payment.id);
+    assert_eq!(links.phantom, format!("https://phantom.app/ul/browse/{}?ref=https%3A%2F%2Fpay.shop.example", encoded));
+    assert_eq!(links.solflare, format!("https://solflare.com/ul/v1/browse/{}?ref=https%3A%2F%2Fpay.shop.example", encoded));
+    // Ссылки дополняют solana: URI, а не заменяют его
+    assert!(payment.url.starts_with("solana:"), "{}", payment.url);
+
+    // Без страницы оплаты ссылок нет; шаблон без {id} не проходит проверку конфига
+    let plain = PaymentService::with_rpc(config("reject"), Arc::new(MockSolanaRpc::new())).await.expect("service");
+    assert!(create_payment(&plain, &Pubkey::new_unique(), 1.0).await.wallet_links.is_none());
+    let mut invalid = checkout;
+    invalid.server.checkout_url = Some("https://pay.shop.example/checkout".to_string());
+    let error = invalid.validate().expect_err("placeholder required");
+    assert!(error.to_string().contains("server.checkout_url must contain {id}"), "{}", error);
+}
+
+#[actix_web::test]
+async fn json_bodies_are_limited_in_size_depth_and_fields() {
+    use actix_web::{test, web, App, HttpResponse};
+
+    let limits = JsonLimits { max_bytes: 1024, max_depth: 4 };
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(limits))
+            .app_data(limits.json_config())
+            .wrap(actix_web::middleware::from_fn(json_guard))
+            .route("/", web::post().to(|request: web::Json<CreatePaymentRequest>| async move {
+                HttpResponse::Ok().body(request.token.clone())
+            })),
+    ).await;
+    let post = |body: String| test::TestRequest::post()
+        .uri("/")
+        .insert_header(("Content-Type", "application/json"))
+        .set_payload(body)
+        .to_request();
+    let valid = json!({"recipient": FEE_WALLET, "amount": 1.0, "token": "SOL"});
+
+    let response = test::call_service(&app, post(valid.to_string())).await;
+    assert_eq!(response.status(), 200);
+
+    let large = json!({"recipient": FEE_WALLET, "amount": 1.0, "token": "SOL", "memo": "x".repeat(2048)});
+    let response = test::call_service(&app, post(large.to_string())).await;
+    assert_eq!(response.status(), 413);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!((body["code"].as_str(), body["limit"].as_u64()), (Some("payload_too_large"), Some(1024)));
+
+    // Скобки внутри строк вложенностью не считаются
+    let mut deep = valid.clone();
+    deep["memo"] = json!("[[[[[[{{{{");
+    deep["metadata"] = json!({"a": [[[["too deep"]]]]});
+    let response = test::call_service(&app, post(deep.to_string())).await;
+    assert_eq!(response.status(), 400);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(body["code"], "json_too_deep");
+
+    let mut unknown = valid.clone();
+    unknown["amount_override"] = json!(0.01);
+    let response = test::call_service(&app, post(unknown.to_string())).await;
+    assert_eq!(response.status(), 400);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(body["code"], "unknown_field");
+
+    assert_eq!(json_depth(br#"{"a": "[[[", "b": [1, {"c": 2}]}"#, 10), 3);
+}
+
+#[tokio::test]
+async fn metadata_is_stored_and_filterable() {
+    let (service, _rpc) = service("reject").await;
+    let request = |metadata: &[(&str, &str)]| CreatePaymentRequest {
+        recipient: Pubkey::new_unique().to_string(),
+        amount: 1.0,
+        amount_base_units: None,
+        token: "SOL".to_string(),
+        label: None,
+        message: None,
+        memo: None,
+        tip_presets_bps: None,
+        locale: None,
+        metadata: Some(metadata.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()),
+        fee_amount: None,
+        fee_token: None,
+        success_url: None,
+        cancel_url: None,
+        discount_code: None,
+        redeem_points: None,
+        allowed_payers: None,
+        hold: false,
+        hold_secs: None,
+        amount_range: None,
+    };
+
+    let error = service.create_payment_with_fee(request(&[("order id", "1")]), None).await
+        .expect_err("invalid key");
+    assert_eq!(error.downcast_ref::<ValidationErrors>().expect("validation").errors()[0].code, "invalid_key");
+
+    let first = service.create_payment_with_fee(request(&[("order_id", "123"), ("customer_id", "c1")]), None).await
+        .expect("payment");
+    service.create_payment_with_fee(request(&[("order_id", "124")]), None).await.expect("payment");
+    let stored = service.get_payment(&first.id).await.unwrap().expect("payment");
+    assert_eq!(stored.metadata.get("customer_id").map(String::as_str), Some("c1"));
+
+    let filter = PaymentListFilter::parse([("metadata.order_id", "123"), ("status", "pending")]).expect("filter");
+    let found: Vec<_> = service.storage().get_all_payments().await.unwrap()
+        .into_values()
+        .filter(|payment| filter.matches(payment))
+        .map(|payment| payment.id)
+        .collect();
+    assert_eq!(found, [first.id]);
+    assert!(PaymentListFilter::parse([("order_id", "123")]).is_err());
+    assert!(PaymentListFilter::parse([("status", "paid")]).is_err());
+}
+
+#[tokio::test]
+async fn status_changes_follow_state_machine() {
+    let (service, rpc) = service("reject").await;
+    let illegal = |result: anyhow::Result<Option<Payment>>| {
+        matches!(result.expect_err("illegal transition").downcast_ref(), Some(TransitionError::Illegal { .. }))
+    };
+
+    let cancelled = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    let cancelled = service.change_status(&cancelled.id, None, PaymentStatus::Cancelled, Some("Order withdrawn")).await
+        .unwrap().expect("payment");
+    assert_eq!(cancelled.status, PaymentStatus::Cancelled);
+    assert_eq!(cancelled.expires_in_seconds, 0);
+    let stored = service.get_payment(&cancelled.id).await.unwrap().expect("payment");
+    assert!(stored.updated_at >= stored.status_changed_at);
+    assert!(illegal(service.change_status(&cancelled.id, None, PaymentStatus::Completed, None).await));
+    let result = service.verify_payment(&cancelled.id, &Signature::new_unique().to_string()).await.unwrap();
+    assert!(!result.verified);
+    assert_eq!(result.status, PaymentStatus::Cancelled);
+
+    let payment = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    assert!(illegal(service.change_status(&payment.id, None, PaymentStatus::Refunded, None).await));
+    let signature = Signature::new_unique();
+    rpc.set_transaction(signature, paid_transaction(&service, &payment));
+    service.verify_payment(&payment.id, &signature.to_string()).await.expect("verified");
+    let refunded = service.change_status(&payment.id, None, PaymentStatus::Refunded, Some("Returned")).await
+        .unwrap().expect("payment");
+    assert!(illegal(service.change_status(&payment.id, None, PaymentStatus::Expired, None).await));
+
+    let history: Vec<_> = refunded.status_history.iter().map(|change| (change.from, change.to)).collect();
+    assert_eq!(history, [
+        (PaymentStatus::Pending, PaymentStatus::Completed),
+        (PaymentStatus::Completed, PaymentStatus::Refunded),
+    ]);
+    assert_eq!(refunded.status_changed_at, refunded.status_history[1].at);
+    assert_eq!(refunded.verified_at, Some(refunded.status_history[0].at));
+    assert_eq!(refunded.status_history[1].reason.as_deref(), Some("Returned"));
+}
+
+#[tokio::test]
+async fn polled_payments_are_not_modified_until_saved_again() {
+    use actix_web::http::header::{EntityTag, IfNoneMatch};
+    use actix_web::test::TestRequest;
+
+    let (service, _rpc) = service("reject").await;
+    let payment = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    let polled = |etag: &EntityTag| TestRequest::default()
+        .insert_header(IfNoneMatch::Items(vec![EntityTag::new_strong(etag.tag().to_string())]))
+        .to_http_request();
+
+    let etag = payment_etag(&payment, None);
+    assert!(etag.weak);
+    assert_eq!(payment_etag(&service.get_payment(&payment.id).await.unwrap().unwrap(), None), etag);
+    assert!(not_modified(&polled(&etag), &etag));
+    assert!(!not_modified(&TestRequest::default().to_http_request(), &etag));
+    assert!(not_modified(&TestRequest::default().insert_header(IfNoneMatch::Any).to_http_request(), &etag));
+    // Другой набор полей — другой ответ
+    let partial = payment_etag(&payment, Some("id,status"));
+    assert_ne!(partial, etag);
+    assert!(!not_modified(&polled(&etag), &partial));
+
+    // Любое сохранение меняет ETag: опрос получает новое состояние
+    let cancelled = service.change_status(&payment.id, None, PaymentStatus::Cancelled, None).await.unwrap().unwrap();
+    assert!(cancelled.updated_at > payment.updated_at);
+    let changed = payment_etag(&cancelled, None);
+    assert_ne!(changed, etag);
+    assert!(!not_modified(&polled(&etag), &changed));
+}
+
+#[tokio::test]
+async fn wait_returns_on_status_change_or_timeout() {
+    let (service, _rpc) = service("reject").await;
+    let payment = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+
+    let unchanged = sse::wait_for_status_change(&service, &payment.id, None, Duration::from_millis(50)).await
+        .unwrap().expect("payment");
+    assert_eq!(unchanged.status, PaymentStatus::Pending);
+
+    let waiter = {
+        let (service, id) = (service.clone(), payment.id.clone());
+        tokio::spawn(async move {
+            sse::wait_for_status_change(&service, &id, Some(PaymentStatus::Pending), Duration::from_secs(30)).await
+        })
+    };
+    service.change_status(&payment.id, None, PaymentStatus::Cancelled, None).await.unwrap();
+    let changed = tokio::time::timeout(Duration::from_secs(5), waiter).await
+        .expect("wait returns on change").unwrap().unwrap().expect("payment");
+    assert_eq!(changed.status, PaymentStatus::Cancelled);
+
+    // Клиент пропустил смену между запросами — ответ сразу
+    let missed = tokio::time::timeout(
+        Duration::from_secs(5),
+        sse::wait_for_status_change(&service, &payment.id, Some(PaymentStatus::Pending), Duration::from_secs(30)),
+    ).await.expect("no wait").unwrap().expect("payment");
+    assert_eq!(missed.status, PaymentStatus::Cancelled);
+    assert!(sse::wait_for_status_change(&service, "pay_missing", None, Duration::from_millis(50)).await.unwrap().is_none());
+}
+
+#[tokio::test(start_paused = true)]
+async fn verify_keeps_payment_pending_on_failures() {
+    let (service, rpc) = service("reject").await;
+    let payment = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+
+    let failed = Signature::new_unique();
+    rpc.set_transaction(failed, TransactionStatus {
+        slot: 42,
+        block_time: None,
+        error: Some("InsufficientFundsForRent".to_string()),
+        ..TransactionStatus::default()
+    });
+    let cases = [
+        (failed, None, "Transaction failed"),
+        (Signature::new_unique(), None, "not found"),
+        (Signature::new_unique(), Some(MockFailure::Malformed), "Invalid get_transaction response"),
+        (Signature::new_unique(), Some(MockFailure::Timeout), "timed out"),
+    ];
+
+    for (signature, failure, expected) in cases {
+        rpc.clear_failures();
+        if let Some(failure) = failure {
+            rpc.fail("get_transaction", failure);
+        }
+        let result = service.verify_payment(&payment.id, &signature.to_string()).await.expect("result");
+        assert!(!result.verified);
+        assert!(result.details.contains(expected), "{}: {}", expected, result.details);
+    }
+
+    let payment = service.get_payment(&payment.id).await.unwrap().expect("payment");
+    assert!(matches!(payment.status, PaymentStatus::Pending));
+}
+
+#[test]
+fn json_log_lines_carry_structured_fields() {
+    let mut fields = serde_json::Map::new();
+    fields.insert("payment_id".into(), json!("pay_1"));
+    fields.insert("amount".into(), json!(1.5));
+
+    let line = Logger::new(LogFormat::Json, "info").format_line("INFO", "crypto_server::payment", "Payment created", &fields);
+    let record: serde_json::Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(record["level"], "INFO");
+    assert_eq!(record["message"], "Payment created");
+    assert_eq!(record["payment_id"], "pay_1");
+    assert_eq!(record["amount"], 1.5);
+    assert!(record["timestamp"].is_string());
+
+    let line = Logger::new(LogFormat::Text, "info").format_line("INFO", "crypto_server::payment", "Payment created", &fields);
+    assert!(line.ends_with("Payment created amount=1.5 payment_id=pay_1"), "{}", line);
+}
+
+/// Получатель, запоминающий события
+#[derive(Default)]
+struct RecordingReporter {
+    events: Mutex<Vec<ErrorEvent>>,
+}
+
+#[async_trait::async_trait]
+impl ErrorReporter for RecordingReporter {
+    async fn report(&self, event: &ErrorEvent) -> anyhow::Result<()> {
+        self.events.lock().unwrap().push(event.clone());
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn errors_are_reported_with_payment_context_and_release() {
+    let config = config_with("reject", "", "[error_reporting]\nsentry_dsn = \"https://key@sentry.example.com/42\"");
+    let reporting = &config.error_reporting;
+    assert_eq!(reporting.environment, "devnet");
+    assert_eq!(reporting.release, concat!("crypto-server@", env!("CARGO_PKG_VERSION")));
+
+    let dsn = SentryDsn::parse("https://key@sentry.example.com/prefix/42").unwrap();
+    assert_eq!(dsn.store_url, "https://sentry.example.com/prefix/api/42/store/");
+    assert_eq!(dsn.public_key, "key");
+    assert!(SentryDsn::parse("https://sentry.example.com/42").is_err());
+
+    let (service, _rpc) = service("reject").await;
+    let payment = create_payment(&service, &Keypair::new().pubkey(), 1.0).await;
+    let reporter = SentryReporter::new(
+        reporting.sentry_dsn.as_deref().unwrap(), &reporting.environment, &reporting.release,
+    ).unwrap();
+    let event = reporter.payload(
+        &ErrorEvent::new(ErrorKind::Transaction, "Transaction creation failed").payment(&payment),
+    );
+    assert_eq!(event["level"], "error");
+    assert_eq!(event["release"], reporting.release.as_str());
+    assert_eq!(event["environment"], "devnet");
+    assert_eq!(event["tags"]["kind"], "transaction");
+    assert_eq!(event["tags"]["payment_id"], payment.id.as_str());
+    assert_eq!(event["tags"]["token"], "SOL");
+    assert_eq!(event["extra"]["amount"], 1.0);
+
+    // Паника в любом потоке отправляется до продолжения раскрутки
+    let recorder = Arc::new(RecordingReporter::default());
+    error_reporting::install(recorder.clone()).unwrap();
+    assert!(std::thread::Builder::new()
+        .name("worker".to_string())
+        .spawn(|| panic!("boom"))
+        .unwrap()
+        .join()
+        .is_err());
+    let events = recorder.events.lock().unwrap();
+    let panic = events.iter().find(|event| event.kind == ErrorKind::Panic).expect("panic reported");
+    assert_eq!(panic.message, "boom");
+    assert_eq!(panic.tags["thread"], "worker");
+    assert!(panic.extra["location"].as_str().unwrap().contains("rpc_mock.rs"));
+}
+
+#[tokio::test]
+async fn trusted_merchants_override_fee_within_policy() {
+    let config = config_with("reject", "", r#"
+[[merchants]]
+id = "promo"
+name = "Promo"
+api_key = "promo-api-key-0123456789"
+[merchants.fee_override]
+max_fee_amount = 0.01
+fee_tokens = ["USDC"]
+
+[[merchants]]
+id = "plain"
+name = "Plain"
+api_key = "plain-api-key-0123456789"
+"#);
+    let rpc = Arc::new(MockSolanaRpc::new());
+    rpc.set_blockhash(Hash::new_unique());
+    let service = PaymentService::with_rpc(config, rpc.clone()).await.expect("service");
+    let (promo, plain) = (service.config().find_merchant("promo"), service.config().find_merchant("plain"));
+    let (promo, plain) = (promo.as_deref(), plain.as_deref());
+    let request = |fee_amount: Option<f64>, fee_token: Option<&str>| CreatePaymentRequest {
+        recipient: Pubkey::new_unique().to_string(),
+        amount: 1.0,
+        amount_base_units: None,
+        token: "SOL".to_string(),
+        label: None,
+        message: None,
+        memo: None,
+        tip_presets_bps: None,
+        locale: None,
+        metadata: None,
+        fee_amount,
+        fee_token: fee_token.map(str::to_string),
+        success_url: None,
+        cancel_url: None,
+        discount_code: None,
+        redeem_points: None,
+        allowed_payers: None,
+        hold: false,
+        hold_secs: None,
+        amount_range: None,
+    };
+    let rejected = |result: anyhow::Result<Payment>| {
+        let error = result.expect_err("rejected");
+        let errors = error.downcast_ref::<ValidationErrors>().expect("validation error");
+        (errors.errors()[0].field.clone(), errors.errors()[0].code)
+    };
+
+    assert_eq!(rejected(service.create_payment_with_fee(request(Some(0.0), None), plain).await), ("fee_amount".into(), "not_allowed"));
+    assert_eq!(rejected(service.create_payment_with_fee(request(Some(0.0), None), None).await), ("fee_amount".into(), "not_allowed"));
+    assert_eq!(rejected(service.create_payment_with_fee(request(Some(0.5), None), promo).await), ("fee_amount".into(), "too_large"));
+    assert_eq!(rejected(service.create_payment_with_fee(request(Some(-1.0), None), promo).await), ("fee_amount".into(), "too_small"));
+    assert_eq!(rejected(service.create_payment_with_fee(request(None, Some("USDC")), promo).await), ("fee_amount".into(), "required"));
+    assert_eq!(rejected(service.create_payment_with_fee(request(Some(0.01), Some("BONK")), promo).await), ("fee_token".into(), "not_allowed"));
+
+    let usdc_fee = service.create_payment_with_fee(request(Some(0.01), Some("USDC")), promo).await.expect("payment");
+    assert_eq!((usdc_fee.fee_amount, usdc_fee.fee_token.as_str()), (0.01, "USDC"));
+    assert!(usdc_fee.fee_overridden);
+
+    // Промо без комиссии: транзакция только с основным переводом
+    let free = service.create_payment_with_fee(request(Some(0.0), None), promo).await.expect("payment");
+    assert_eq!((free.fee_amount, free.fee_token.as_str()), (0.0, "SOL"));
+    assert!(free.fee_overridden);
+    let stored = service.get_payment(&free.id).await.unwrap().expect("payment");
+    assert_eq!(stored.fee_amount, 0.0);
+    let transaction = build(&service, &free).await.expect("transaction built");
+    assert_eq!(transaction.message.instructions.len(), 1);
+    assert!(create_payment_transaction(
+        &free, &Pubkey::new_unique().to_string(), service.config(), service.rpc().as_ref(), TransactionPart::Fee, None,
+    ).await.is_err());
+
+    let signature = Signature::new_unique();
+    rpc.set_transaction(signature, paid_transaction(&service, &free));
+    let result = service.verify_payment(&free.id, &signature.to_string()).await.expect("verified");
+    assert!(result.verified, "{}", result.details);
+
+    let regular = service.create_payment_with_fee(request(None, None), promo).await.expect("payment");
+    assert!(!regular.fee_overridden);
+    assert_eq!(regular.fee_amount, 0.001);
+}
+
+#[tokio::test]
+async fn reconciliation_completes_paid_but_unverified_payments() {
+    let (service, rpc) = service("reject").await;
+    let paid = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    let unpaid = create_payment(&service, &Pubkey::new_unique(), 2.0).await;
+    let signature = Signature::new_unique();
+    rpc.set_transaction(signature, paid_transaction(&service, &paid));
+    rpc.set_signatures(paid.reference.parse().unwrap(), vec![signature]);
+    let reconciliation = ReconciliationService::new(service.clone());
+
+    // Dry run только находит транзакцию
+    let report = reconciliation.run(true).await.expect("dry run");
+    assert_eq!(report.scanned, 2);
+    assert_eq!(report.completed.len(), 1);
+    assert_eq!(report.completed[0].payment_id, paid.id);
+    assert_eq!(service.get_payment(&paid.id).await.unwrap().unwrap().status, PaymentStatus::Pending);
+
+    let report = reconciliation.run(false).await.expect("reconciled");
+    assert_eq!((report.scanned, report.completed.len()), (2, 1));
+    assert_eq!(report.completed[0].signature, signature.to_string());
+    let completed = service.get_payment(&paid.id).await.unwrap().unwrap();
+    assert_eq!(completed.status, PaymentStatus::Completed);
+    assert_eq!(completed.signature, Some(signature.to_string()));
+    assert_eq!(service.get_payment(&unpaid.id).await.unwrap().unwrap().status, PaymentStatus::Pending);
+
+    // Сбой RPC по платежу попадает в отчет, остальные проверяются
+    rpc.fail("get_signatures_for_address", MockFailure::Error("unavailable".to_string()));
+    let report = reconciliation.run(false).await.expect("report");
+    assert_eq!(report.scanned, 1);
+    assert_eq!(report.failed.len(), 1);
+    assert_eq!(report.failed[0].payment_id, unpaid.id);
+}
+
+#[tokio::test]
+async fn preview_breaks_down_transfers_accounts_and_costs() {
+    let (service, rpc) = service("reject").await;
+    rpc.set_blockhash(Hash::new_unique());
+    let payer = Pubkey::new_unique();
+    let recipient = Pubkey::new_unique();
+    let payment = service.create_payment_with_fee(CreatePaymentRequest {
+        recipient: recipient.to_string(),
+        amount: 2.5,
+        amount_base_units: None,
+        token: "USDC".to_string(),
+        label: None,
+        message: None,
+        memo: Some("INV-1".to_string()),
+        tip_presets_bps: None,
+        locale: None,
+        metadata: None,
+        fee_amount: None,
+        fee_token: None,
+        success_url: None,
+        cancel_url: None,
+        discount_code: None,
+        redeem_points: None,
+        allowed_payers: None,
+        hold: false,
+        hold_secs: None,
+        amount_range: None,
+    }, None).await.expect("payment");
+
+    let preview = preview_payment_transaction(
+        &payment, &payer.to_string(), service.config(), service.rpc().as_ref(), TransactionPart::Full, None, None,
+    ).await.expect("preview");
+    assert_eq!(preview.fee_payer, payer.to_string());
+    assert_eq!(preview.instructions.len(), 4);
+    assert!(matches!(
+        &preview.instructions[0],
+        PreviewInstruction::CreateTokenAccount { token, owner, exists: false, rent_lamports: 2_039_280, .. }
+            if token == "USDC" && *owner == recipient.to_string()
+    ));
+    assert!(matches!(
+        &preview.instructions[1],
+        PreviewInstruction::Transfer { token, amount, from, to }
+            if token == "USDC" && *amount == 2.5 && *from == payer.to_string() && *to == recipient.to_string()
+    ));
+    assert!(matches!(
+        &preview.instructions[2],
+        PreviewInstruction::Transfer { token, amount, to, .. } if token == "SOL" && *amount == 0.001 && *to == payment.fee_recipient
+    ));
+    assert!(matches!(&preview.instructions[3], PreviewInstruction::Memo { text } if text == "INV-1"));
+
+    assert_eq!(preview.network_fee.signatures, 1);
+    assert_eq!(preview.network_fee.total_lamports, 5_000);
+    assert_eq!(preview.rent_lamports, 2_039_280);
+    assert_eq!(preview.debits["USDC"], 2.5);
+    assert_eq!(preview.debits["SOL"], (1_000_000 + 5_000 + 2_039_280) as f64 / 1e9);
+    assert!(preview.size_bytes > 0);
+
+    // Существующий токен-аккаунт получателя не создается за счет плательщика
+    let usdc_mint: Pubkey = service.config().get_token_config("USDC").unwrap().mint.as_deref().unwrap().parse().unwrap();
+    let recipient_ata = spl_associated_token_account::get_associated_token_address(&recipient, &usdc_mint);
+    rpc.set_account(recipient_ata, Account { lamports: 2_039_280, owner: spl_token::ID, ..Account::default() });
+    let preview = preview_payment_transaction(
+        &payment, &payer.to_string(), service.config(), service.rpc().as_ref(), TransactionPart::Main, None, None,
+    ).await.expect("preview");
+    assert!(matches!(&preview.instructions[0], PreviewInstruction::CreateTokenAccount { exists: true, rent_lamports: 0, .. }));
+    assert_eq!(preview.rent_lamports, 0);
+    assert_eq!(preview.debits["SOL"], 5_000.0 / 1e9);
+}
+
+#[test]
+fn profiles_set_defaults_and_audit_hides_secrets() {
+    let dev = config_with("reject", "", "[server]\nprofile = \"dev\"\nport = 3101\n");
+    assert_eq!(dev.server.profile, Profile::Dev);
+    assert_eq!(dev.server.cors_origins, vec!["*".to_string()]);
+    assert_eq!(dev.server.rate_limit_per_minute, 0);
+
+    let prod = config_with("reject", "", r#"
+[server]
+domain = "pay.example.com"
+ssl = true
+checkout_url = "https://shop.example.com/pay/{id}"
+admin_api_key = "admin-secret-key"
+
+[[solana.rpc_endpoints]]
+url = "https://rpc.example.com/?api-key=rpc-secret"
+"#);
+    assert_eq!(prod.server.profile, Profile::Prod);
+    assert_eq!(prod.server.cors_origins, vec!["https://pay.example.com", "https://shop.example.com"]);
+    assert_eq!(prod.server.rate_limit_per_minute, 600);
+
+    let audit = serde_json::Value::Object(prod.audit());
+    let text = audit.to_string();
+    assert!(!text.contains("admin-secret-key") && !text.contains("rpc-secret"), "{}", text);
+    assert_eq!(audit["server"]["admin_api_key"], "[redacted]");
+    assert_eq!(audit["solana"]["rpc_endpoints"][0]["url"], "https://rpc.example.com/?api-key=%5Bredacted%5D");
+    assert_eq!(audit["server"]["domain"], "pay.example.com");
+
+    let limiter = RateLimiter::new(2);
+    assert_eq!(limiter.check_at("10.0.0.1", 120), RateDecision::Allowed);
+    assert_eq!(limiter.check_at("10.0.0.1", 130), RateDecision::Allowed);
+    assert_eq!(limiter.check_at("10.0.0.1", 135), RateDecision::Limited { retry_after_secs: 45 });
+    assert_eq!(limiter.check_at("10.0.0.2", 135), RateDecision::Allowed);
+    assert_eq!(limiter.check_at("10.0.0.1", 180), RateDecision::Allowed);
+}
+
+#[tokio::test]
+async fn transaction_builds_queue_and_shed_load_when_saturated() {
+    let limiter = BuildLimiter::new(1, 1, Duration::from_millis(200));
+    let (release, released) = tokio::sync::oneshot::channel::<()>();
+    let running = tokio::spawn({
+        let limiter = limiter.clone();
+        async move { limiter.run(async { released.await.ok() }).await }
+    });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    // Второй запрос ждет в очереди, третий получает отказ сразу
+    let queued = tokio::spawn({
+        let limiter = limiter.clone();
+        async move { limiter.run(async { "built" }).await }
+    });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let stats = limiter.stats();
+    assert_eq!((stats.in_flight, stats.queued), (1, 1));
+    match limiter.run(async {}).await {
+        Err(e @ Saturated::QueueFull { .. }) => assert_eq!(e.retry_after_secs(), 1),
+        other => panic!("expected a full queue, got {:?}", other),
+    }
+
+    release.send(()).unwrap();
+    assert!(running.await.unwrap().is_ok());
+    assert_eq!(queued.await.unwrap().unwrap(), "built");
+
+    // Слот не освобождается дольше таймаута очереди
+    let (_hold, held) = tokio::sync::oneshot::channel::<()>();
+    let blocking = tokio::spawn({
+        let limiter = limiter.clone();
+        async move { limiter.run(async { held.await.ok() }).await }
+    });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(matches!(limiter.run(async {}).await, Err(Saturated::QueueTimeout { .. })));
+    blocking.abort();
+
+    let stats = limiter.stats();
+    assert_eq!((stats.queued, stats.peak_queued, stats.admitted_total, stats.rejected_total), (0, 1, 3, 2));
+}
+
+#[tokio::test]
+async fn short_payment_ids_are_checked_and_tolerate_dictation() {
+    let rpc = Arc::new(MockSolanaRpc::new());
+    let config = config_with("reject", "", "[server]\npayment_id_format = \"short\"\nshort_id_length = 6\n");
+    let service = PaymentService::with_rpc(config, rpc).await.expect("service");
+    let payment = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    let code = payment.id.strip_prefix("pay_").expect("prefixed id");
+    assert_eq!(code.len(), 6);
+    assert!(code.chars().all(|c| c.is_ascii_digit() || c.is_ascii_uppercase()), "{}", payment.id);
+
+    // Регистр и похожие символы не важны
+    let spoken = payment.id.to_lowercase().replace('0', "o").replace('1', "l");
+    assert_eq!(service.get_payment(&spoken).await.unwrap().unwrap().id, payment.id);
+
+    // Любая замена одного символа ломает контрольный символ
+    let codes = ShortCodes::new(6);
+    for position in 0..code.len() {
+        for replacement in "0123456789ABCDEFGHJKMNPQRSTVWXYZ".chars() {
+            let mut typo: Vec<char> = code.chars().collect();
+            if typo[position] == replacement {
+                continue;
+            }
+            typo[position] = replacement;
+            let typo: String = typo.into_iter().collect();
+            assert_eq!(codes.normalize(&format!("pay_{}", typo)), None, "{}", typo);
+        }
+    }
+
+    // Платежи с UUID ищутся как раньше
+    let legacy = UuidIds.generate();
+    assert_eq!(codes.normalize(&legacy), Some(legacy.clone()));
+    assert_eq!(legacy.len(), 4 + 32);
+
+    // Генератор с постоянным ID: второй платеж получает отказ после повторных попыток
+    struct Fixed;
+    impl IdGenerator for Fixed {
+        fn generate(&self) -> String {
+            "pay_FIXED".to_string()
+        }
+    }
+    let service = service.with_id_generator(Arc::new(Fixed));
+    assert_eq!(service.storage().allocate_payment_id(&Fixed).await.unwrap(), "pay_FIXED");
+    create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    let error = service.storage().allocate_payment_id(&Fixed).await.expect_err("collision");
+    assert!(error.to_string().contains("unique payment ID"), "{}", error);
+}
+
+#[tokio::test]
+async fn sandbox_payments_complete_by_simulation_off_chain() {
+    let rpc = Arc::new(MockSolanaRpc::new());
+    let config = config_with("reject", "", r#"
+[[merchants]]
+id = "shop-sandbox"
+name = "Shop (sandbox)"
+api_key = "sandbox-key-0123456789"
+sandbox = true
+
+[[merchants]]
+id = "shop"
+name = "Shop"
+api_key = "live-key-0123456789"
+"#);
+    let service = PaymentService::with_rpc(config, rpc.clone()).await.expect("service");
+    let request = || CreatePaymentRequest {
+        recipient: Pubkey::new_unique().to_string(),
+        amount: 2.0,
+        amount_base_units: None,
+        token: "SOL".to_string(),
+        label: None,
+        message: None,
+        memo: None,
+        tip_presets_bps: None,
+        locale: None,
+        metadata: None,
+        fee_amount: None,
+        fee_token: None,
+        success_url: None,
+        cancel_url: None,
+        discount_code: None,
+        redeem_points: None,
+        allowed_payers: None,
+        hold: false,
+        hold_secs: None,
+        amount_range: None,
+    };
+    let merchants = &service.config().merchants;
+    // RPC недоступен: sandbox платеж создается без обращения к блокчейну
+    rpc.fail("get_account", MockFailure::Malformed);
+    let sandbox = service.create_payment_with_fee(request(), Some(&merchants[0])).await.expect("sandbox payment");
+    assert!(sandbox.sandbox);
+    rpc.clear_failures();
+    let live = service.create_payment_with_fee(request(), Some(&merchants[1])).await.expect("live payment");
+    assert!(!live.sandbox);
+
+    let error = service.verify_payment(&sandbox.id, &Signature::new_unique().to_string()).await.expect_err("off chain");
+    assert!(error.is::<SandboxError>(), "{}", error);
+
+    assert!(service.simulate_completion(&sandbox.id, "shop").await.unwrap().is_none());
+    let error = service.simulate_completion(&live.id, "shop").await.expect_err("live payment");
+    assert!(matches!(error.downcast_ref::<SandboxError>(), Some(SandboxError::NotSandbox)));
+    let completed = service.simulate_completion(&sandbox.id, "shop-sandbox").await.unwrap().expect("owned");
+    assert_eq!(completed.status, PaymentStatus::Completed);
+    assert!(completed.signature.as_deref().is_some_and(|s| s.starts_with("sandbox_")));
+
+    // Выручка и сводки администратора — только боевые платежи
+    service.change_status(&live.id, None, PaymentStatus::Completed, None).await.unwrap();
+    let report = service.revenue_report().await.unwrap();
+    assert_eq!(report.completed_payments, 1);
+    let completed = service.get_payment(&sandbox.id).await.unwrap().unwrap();
+    assert!(!Viewer::Admin.in_reports(&completed, None));
+    assert!(Viewer::Admin.in_reports(&completed, Some(true)));
+    assert!(Viewer::Merchant("shop-sandbox".to_string()).in_reports(&completed, None));
+}
+
+#[tokio::test]
+async fn webhook_events_follow_versioned_schema() {
+    assert_eq!(events::negotiate(None).unwrap(), events::CURRENT_API_VERSION);
+    assert_eq!(events::negotiate(Some(" 1 ")).unwrap(), 1);
+    assert_eq!(events::negotiate(Some("v1")).unwrap(), 1);
+    for unsupported in ["2", "0", "latest"] {
+        let errors = events::negotiate(Some(unsupported)).expect_err(unsupported);
+        assert_eq!(errors.errors()[0].field, "api_version");
+        assert_eq!(errors.errors()[0].code, "unsupported");
+    }
+    assert!(events::schema(2).is_none());
+
+    let rpc = Arc::new(MockSolanaRpc::new());
+    let config = config_with("reject", "", r#"
+[[merchants]]
+id = "shop"
+name = "Shop"
+api_key = "live-key-0123456789"
+"#);
+    let service = PaymentService::with_rpc(config, rpc).await.expect("service");
+    let merchant = &service.config().merchants[0];
+    let request = CreatePaymentRequest {
+        recipient: Pubkey::new_unique().to_string(),
+        amount: 2.0,
+        amount_base_units: None,
+        token: "SOL".to_string(),
+        label: Some("Order 42".to_string()),
+        message: None,
+        memo: None,
+        tip_presets_bps: None,
+        locale: None,
+        metadata: None,
+        fee_amount: None,
+        fee_token: None,
+        success_url: None,
+        cancel_url: None,
+        discount_code: None,
+        redeem_points: None,
+        allowed_payers: None,
+        hold: false,
+        hold_secs: None,
+        amount_range: None,
+    };
+    let payment = service.create_payment_with_fee(request, Some(merchant)).await.expect("payment");
+
+    // Событие в outbox содержит ровно поля схемы v1, а не весь `Payment`
+    let outbox = service.storage().pending_outbox_events().await.unwrap();
+    let event = outbox.iter().find(|e| e.payload["id"] == payment.id.as_str()).expect("outbox event");
+    let schema = events::schema(1).unwrap();
+    let data = &schema["properties"]["data"]["oneOf"][0];
+    let mut declared: Vec<&str> = data["properties"].as_object().unwrap().keys().map(String::as_str).collect();
+    let mut required: Vec<&str> = data["required"].as_array().unwrap().iter().filter_map(|k| k.as_str()).collect();
+    let mut sent: Vec<&str> = event.payload.as_object().unwrap().keys().map(String::as_str).collect();
+    declared.sort_unstable();
+    required.sort_unstable();
+    sent.sort_unstable();
+    assert_eq!(sent, declared);
+    assert_eq!(sent, required);
+    assert_eq!(event.payload["status"], "pending");
+    assert_eq!(event.payload["label"], "Order 42");
+    let types = schema["properties"]["type"]["enum"].as_array().unwrap();
+    assert!(types.iter().any(|t| t == event.event_type.as_str()), "{}", event.event_type);
+    assert_eq!(events::render(1, &event.payload).unwrap(), event.payload);
+    assert!(events::render(2, &event.payload).is_err());
+}
+
+#[tokio::test]
+async fn payments_carry_transfer_request_fallback_qr() {
+    let rpc = Arc::new(MockSolanaRpc::new());
+    let service = PaymentService::with_rpc(config_with("reject", "", ""), rpc).await.expect("service");
+    let recipient = Pubkey::new_unique();
+    let payment = service.create_payment_with_fee(CreatePaymentRequest {
+        recipient: recipient.to_string(),
+        amount: 12.5,
+        amount_base_units: None,
+        token: "USDC".to_string(),
+        label: Some("Coffee & cake".to_string()),
+        message: None,
+        memo: Some("order 7".to_string()),
+        tip_presets_bps: None,
+        locale: None,
+        metadata: None,
+        fee_amount: None,
+        fee_token: None,
+        success_url: None,
+        cancel_url: None,
+        discount_code: None,
+        redeem_points: None,
+        allowed_payers: None,
+        hold: false,
+        hold_secs: None,
+        amount_range: None,
+    }, None).await.expect("payment");
+
+    // Transfer request: получатель, сумма без комиссии, mint и reference прямо в URL
+    let url = url::Url::parse(&payment.transfer_url).expect("transfer url");
+    assert_eq!(url.scheme(), "solana");
+    assert_eq!(url.path(), recipient.to_string());
+    let params: std::collections::HashMap<String, String> = url.query_pairs().into_owned().collect();
+    assert_eq!(params["amount"], "12.5");
+    let mint = service.config().get_token_config("USDC").and_then(|t| t.mint.clone()).unwrap();
+    assert_eq!(params["spl-token"], mint);
+    assert_eq!(params["reference"], payment.reference);
+    assert_eq!(params["label"], "Coffee & cake");
+    assert_eq!(params["memo"], "order 7");
+    assert!(payment.transfer_qr_url.ends_with(&format!("/api/payment/{}/qr?kind=transfer", payment.id)));
+
+    let sol = create_payment(&service, &recipient, 0.000_000_001).await;
+    let params: std::collections::HashMap<String, String> =
+        url::Url::parse(&sol.transfer_url).unwrap().query_pairs().into_owned().collect();
+    assert_eq!(params["amount"], "0.000000001");
+    assert!(!params.contains_key("spl-token"));
+
+    // Оба QR в ответе; PNG выбирается по возможностям кошелька
+    let with_qr = service.with_qr_code(payment.clone()).unwrap();
+    assert!(with_qr.qr_code.as_deref().is_some_and(|qr| qr.starts_with("data:image/png;base64,")));
+    assert!(with_qr.transfer_qr_code.is_some());
+    assert_ne!(with_qr.qr_code, with_qr.transfer_qr_code);
+    assert_eq!(QrKind::for_wallet(true), QrKind::Transaction);
+    assert_eq!(QrKind::for_wallet(false), QrKind::Transfer);
+    let transfer_png = service.qr_png(&payment, QrKind::Transfer).unwrap();
+    assert_eq!(transfer_png, qr::render_png(&payment.transfer_url).unwrap());
+    assert_ne!(transfer_png, service.qr_png(&payment, QrKind::Transaction).unwrap());
+}
+
+#[tokio::test]
+async fn payment_amounts_convert_to_display_currency() {
+    let rpc = Arc::new(MockSolanaRpc::new());
+    let config = config_with(
+        "reject",
+        "price_api_url = \"http://127.0.0.1:1/price\"\nfx_api_url = \"http://127.0.0.1:1/fx\"\n",
+        "[server]\ndisplay_currencies = [\"EUR\", \"JPY\"]\n",
+    );
+    let service = PaymentService::with_rpc(config, rpc).await.expect("service");
+    let mut payment = create_payment(&service, &Pubkey::new_unique(), 1.5).await;
+    assert!(payment.fiat.is_none());
+
+    let errors = service.with_display_currency(payment.clone(), "GBP").await.expect_err("not configured");
+    assert_eq!(errors.errors()[0].field, "display_currency");
+    assert_eq!(errors.errors()[0].code, "unsupported");
+    // Оракул недоступен: платеж отдается без пересчета
+    let unpriced = service.with_display_currency(payment.clone(), "eur").await.expect("currency accepted");
+    assert!(unpriced.fiat.is_none());
+
+    // 1.5 SOL + 0.25 SOL чаевых по 130.4 EUR, комиссия 1 USDC по 0.92 EUR
+    payment.tip_amount = Some(0.25);
+    (payment.fee_amount, payment.fee_token) = (1.0, "USDC".to_string());
+    let fiat = FiatAmounts::convert(&payment, "EUR", 130.4, 0.92);
+    assert_eq!(fiat.amount_display, "195.60 EUR");
+    assert_eq!(fiat.tip_display.as_deref(), Some("32.60 EUR"));
+    assert_eq!(fiat.fee_display, "0.92 EUR");
+    assert_eq!(fiat.total_display, "229.12 EUR");
+    assert!((fiat.total - 229.12).abs() < 1e-9);
+    let yen = FiatAmounts::convert(&payment, "JPY", 21_000.0, 150.0);
+    assert_eq!(yen.total_display, "36,900 JPY");
+
+    payment.fiat = Some(fiat);
+    let selected = select_fields(&payment, &["id", "fiat"]).unwrap();
+    assert_eq!(selected["fiat"]["currency"], "EUR");
+    assert_eq!(select_fields(&unpriced, &["fiat"]).unwrap()["fiat"], serde_json::Value::Null);
+}
+
+#[tokio::test]
+async fn operators_list_and_resend_webhook_deliveries_of_any_merchant() {
+    let config = config_with("reject", "", r#"
+[[merchants]]
+id = "shop"
+name = "Shop"
+api_key = "live-key-0123456789"
+"#);
+    let service = PaymentService::with_rpc(config, Arc::new(MockSolanaRpc::new())).await.expect("service");
+    let webhooks = WebhookService::new(service.storage().clone());
+    let merchant = &service.config().merchants[0];
+    let request = RegisterWebhookRequest {
+        url: "http://127.0.0.1:1/hook".to_string(),
+        description: None,
+        filter: WebhookFilter::default(),
+    };
+    let registered = webhooks.register(request, None, merchant).await.expect("registered");
+    webhooks.publish("shop", "payment.completed", json!({"id": "pay_1"})).await.unwrap();
+
+    // Первая автоматическая попытка падает: endpoint недоступен
+    let failed = loop {
+        let failed = webhooks.all_deliveries(Some(DeliveryStatus::Failed), 10).await.unwrap();
+        if !failed.is_empty() {
+            break failed;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    };
+    assert_eq!(failed[0].endpoint_id, registered.endpoint.id);
+    assert!(webhooks.all_deliveries(Some(DeliveryStatus::Delivered), 10).await.unwrap().is_empty());
+
+    let resent = webhooks.redeliver_any(&failed[0].id).await.expect("resent");
+    assert!(resent.attempts.last().is_some_and(|attempt| attempt.manual && attempt.error.is_some()));
+    let missing = webhooks.redeliver_any("whd_missing").await.expect_err("unknown delivery");
+    assert!(matches!(missing.downcast_ref::<WebhookError>(), Some(WebhookError::DeliveryNotFound)));
+}
+
+#[tokio::test]
+async fn jobs_follow_cron_schedules_and_never_overlap() {
+    let at = |s: &str| chrono::DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&chrono::Utc);
+    let next = |schedule: &str, after: &str| schedule.parse::<Schedule>().unwrap().next_after(at(after)).unwrap();
+    assert_eq!(next("*/15 * * * *", "2026-01-01T10:07:30Z"), at("2026-01-01T10:15:00Z"));
+    assert_eq!(next("*/30 * * * * *", "2026-01-01T10:07:30Z"), at("2026-01-01T10:08:00Z"));
+    assert_eq!(next("0 3 * * 1-5", "2026-01-02T04:00:00Z"), at("2026-01-05T03:00:00Z"));
+    assert_eq!(next("@every 90s", "2026-01-01T10:00:00Z"), at("2026-01-01T10:01:30Z"));
+    for invalid in ["* * *", "61 * * * *", "@every 0s", "*/0 * * * *", "@daily"] {
+        assert!(invalid.parse::<Schedule>().is_err(), "{}", invalid);
+    }
+
+    // Дефолты: retention из interval_secs, blockhash выключен; jobs.<имя> перекрывает
+    let config = config_with(
+        "reject", "",
+        "[jobs.expiration]\nschedule = \"@every 10s\"\n[jobs.blockhash]\nenabled = true\n",
+    );
+    assert_eq!(config.jobs["expiration"].schedule, "@every 10s");
+    assert!(config.jobs["blockhash"].enabled);
+    assert_eq!(config.jobs["retention"].schedule, "@every 3600s");
+    assert!(!config.jobs["sweep"].enabled);
+
+    // Просроченный неоплаченный платеж переводится в expired
+    let rpc = Arc::new(MockSolanaRpc::new());
+    let service = PaymentService::with_rpc(config.clone(), rpc).await.expect("service");
+    let mut overdue = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    let fresh = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    overdue.expires_at = chrono::Utc::now() - chrono::Duration::minutes(1);
+    service.storage().save_payment(&overdue.id.clone(), &mut overdue).await.unwrap();
+    assert_eq!(service.expire_due_payments().await.unwrap(), 1);
+    assert_eq!(service.get_payment(&overdue.id).await.unwrap().unwrap().status, PaymentStatus::Expired);
+    assert_eq!(service.get_payment(&fresh.id).await.unwrap().unwrap().status, PaymentStatus::Pending);
+
+    // Пока идет запуск, следующий пропускается
+    let leader = LeaderElection::from_config(&config.coordination).await.unwrap();
+    let mut scheduler = JobScheduler::new(leader);
+    scheduler.add("expiration", &config.jobs["expiration"], None, || async {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        anyhow::bail!("RPC unavailable")
+    }).unwrap();
+    let (first, second) = tokio::join!(scheduler.run_now("expiration"), async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        scheduler.run_now("expiration").await
+    });
+    assert_eq!(first, Some(JobOutcome::Failed("RPC unavailable".to_string())));
+    assert_eq!(second, Some(JobOutcome::Skipped));
+    assert_eq!(scheduler.run_now("unknown").await, None);
+
+    let status = &scheduler.status()[0];
+    assert_eq!((status.runs, status.failures, status.skipped), (1, 1, 1));
+    assert_eq!(status.last_error.as_deref(), Some("RPC unavailable"));
+    assert!(!status.running);
+}
+
+#[tokio::test]
+async fn short_links_resolve_to_payments_case_insensitively() {
+    let rpc = Arc::new(MockSolanaRpc::new());
+    let short = config_with(
+        "reject", "", "[server]\nshort_links = true\nshort_link_length = 6\ntransaction_request_tokens = false\n",
+    );
+    let service = PaymentService::with_rpc(short, rpc.clone()).await.expect("service");
+    let payment = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+
+    let code = payment.short_code.clone().expect("short code");
+    assert_eq!(code.len(), 6);
+    assert!(code.chars().all(|c| c.is_ascii_digit() || c.is_ascii_uppercase()), "{}", code);
+    assert_eq!(payment.url, format!("solana:{}/t/{}", service.config().base_url(), code));
+    let long_url = format!("solana:{}/api/payment/{}/transaction", service.config().base_url(), payment.id);
+    assert!(payment.url.len() + 30 < long_url.len(), "{} vs {}", payment.url, long_url);
+
+    // Регистр и похожие символы не важны
+    let typed = code.to_lowercase().replace('0', "o").replace('1', "l");
+    assert_eq!(service.get_payment_by_short_code(&typed).await.unwrap().unwrap().id, payment.id);
+    let other = if code == "ZZZZZZ" { "YYYYYY" } else { "ZZZZZZ" };
+    assert!(service.get_payment_by_short_code(other).await.unwrap().is_none());
+    assert!(service.get_payment_by_short_code("bad-code!").await.unwrap().is_none());
+
+    // Без short_links — прежний URL и нет кода
+    let plain = config_with("reject", "", "[server]\ntransaction_request_tokens = false\n");
+    let plain = PaymentService::with_rpc(plain, rpc).await.expect("service");
+    let payment = create_payment(&plain, &Pubkey::new_unique(), 1.0).await;
+    assert_eq!(payment.short_code, None);
+    assert!(payment.url.ends_with(&format!("/api/payment/{}/transaction", payment.id)), "{}", payment.url);
+}
+
+#[tokio::test]
+async fn rpc_pool_authenticates_and_spreads_requests_by_weight_and_budget() {
+    use std::io::{Read, Write};
+
+    // JSON-RPC узел: заголовки запросов getAccountInfo — в тест, ответ — пустой аккаунт
+    // или HTTP 500. getVersion клиент запрашивает перед первым вызовом
+    let node = |healthy: bool| {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (received, requests) = std::sync::mpsc::channel::<String>();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request = Vec::new();
+                let mut buffer = [0u8; 4096];
+                loop {
+                    let read = stream.read(&mut buffer).unwrap_or(0);
+                    request.extend_from_slice(&buffer[..read]);
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    let complete = text.split_once("\r\n\r\n").is_some_and(|(head, body)| {
+                        let length = head.lines()
+                            .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().to_string()))
+                            .and_then(|v| v.parse::<usize>().ok())
+                            .unwrap_or(0);
+                        body.len() >= length
+                    });
+                    if read == 0 || complete {
+                        break;
+                    }
+                }
+                let request = String::from_utf8_lossy(&request).to_string();
+                let (head, body) = request.split_once("\r\n\r\n").unwrap_or_default();
+                let result = match body.contains("getVersion") {
+                    true => json!({"solana-core": "1.18.26", "feature-set": 0}),
+                    false => json!({"context": {"slot": 1}, "value": null}),
+                };
+                let (status, body) = match healthy {
+                    true => ("200 OK", json!({"jsonrpc": "2.0", "id": 1, "result": result}).to_string()),
+                    false => ("500 Internal Server Error", String::new()),
+                };
+                if !request.contains("getVersion") {
+                    let _ = received.send(head.to_lowercase());
+                }
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status, body.len(), body,
+                );
+            }
+        });
+        (url, requests)
+    };
+
+    // Helius: ключ в ?api-key=; Triton: ключ в заголовке, вес 3 и бюджет 2 запроса в секунду
+    let (helius_url, helius) = node(true);
+    let (triton_url, triton) = node(true);
+    let mut pooled = config("reject");
+    pooled.solana.rpc_endpoints = vec![
+        RpcEndpoint { api_key: Some("helius-key".to_string()), ..RpcEndpoint::new(&helius_url) },
+        RpcEndpoint {
+            api_key: Some("triton-key".to_string()),
+            api_key_header: Some("x-token".to_string()),
+            headers: [("x-client".to_string(), "cryptonow".to_string())].into(),
+            weight: 3,
+            max_requests_per_sec: Some(2),
+            ..RpcEndpoint::new(&triton_url)
+        },
+    ];
+    let rpc = HttpSolanaRpc::new(&pooled).expect("rpc pool");
+    for _ in 0..4 {
+        assert!(rpc.get_account(&Pubkey::new_unique()).await.expect("account lookup").is_none());
+    }
+
+    // Из четырех запросов Triton получил бы три, но бюджет пропускает два — третий ушел на Helius
+    let stats = rpc.endpoint_stats();
+    assert_eq!(stats.iter().map(|stats| stats.requests).collect::<Vec<_>>(), vec![2, 2]);
+    assert!(stats.iter().all(|stats| !stats.name.contains("key")));
+    let helius: Vec<String> = helius.try_iter().collect();
+    let triton: Vec<String> = triton.try_iter().collect();
+    assert_eq!((helius.len(), triton.len()), (2, 2));
+    assert!(helius.iter().all(|head| head.starts_with("post /?api-key=helius-key ") && !head.contains("x-token")));
+    assert!(triton.iter().all(|head| {
+        head.contains("\r\nx-token: triton-key") && head.contains("\r\nx-client: cryptonow") && !head.contains("api-key")
+    }));
+
+    // Сбойный узел: запрос повторяется на следующем
+    let (broken_url, _broken) = node(false);
+    let (healthy_url, _healthy) = node(true);
+    let mut failover = config("reject");
+    failover.solana.rpc_endpoints = vec![RpcEndpoint::new(&broken_url), RpcEndpoint::new(&healthy_url)];
+    let rpc = HttpSolanaRpc::new(&failover).expect("rpc pool");
+    assert!(rpc.get_account(&Pubkey::new_unique()).await.expect("served by the healthy node").is_none());
+    let stats = rpc.endpoint_stats();
+    assert_eq!((stats[0].failures, stats[1].failures, stats[1].requests), (1, 0, 1));
+}
+
+#[tokio::test]
+async fn request_deadline_cancels_in_flight_rpc_calls() {
+    // Узел принимает соединения и не отвечает
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        let held: Vec<_> = listener.incoming().collect();
+        drop(held);
+    });
+    let mut silent = config("reject");
+    silent.solana.rpc_endpoints = vec![RpcEndpoint::new(&url), RpcEndpoint::new(&url)];
+    let rpc = HttpSolanaRpc::new(&silent).expect("rpc pool");
+    let account = Pubkey::new_unique();
+
+    // Срок истекает задолго до таймаута HTTP запроса, второй узел не пробуется
+    let started = std::time::Instant::now();
+    let deadline = Deadline::after(Duration::from_millis(300));
+    let result = deadline.run(rpc.get_account(&account)).await.expect("run returns");
+    let error = result.expect_err("deadline exceeded");
+    assert_eq!(error.downcast_ref::<DeadlineError>(), Some(&DeadlineError::Elapsed));
+    assert!(started.elapsed() < Duration::from_secs(2), "{:?}", started.elapsed());
+    // Брошенный запрос не считается сбоем узла
+    assert!(rpc.endpoint_stats().iter().all(|stats| stats.requests == 0 && stats.state == BreakerState::Closed));
+
+    // Отмена прерывает ожидание ответа сразу
+    let deadline = Deadline::after(Duration::from_secs(30));
+    let cancel = deadline.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        cancel.cancel();
+    });
+    let started = std::time::Instant::now();
+    let error = deadline.run(rpc.get_account(&account)).await.expect("run returns").expect_err("cancelled");
+    assert_eq!(error.downcast_ref::<DeadlineError>(), Some(&DeadlineError::Cancelled));
+    assert!(started.elapsed() < Duration::from_secs(2), "{:?}", started.elapsed());
+
+    // Guard отменяет работу, когда handler брошен
+    let deadline = Deadline::after(Duration::from_secs(30));
+    drop(deadline.cancel_on_drop());
+    assert_eq!(deadline.bound(std::future::pending::<()>()).await, Err(DeadlineError::Cancelled));
+    assert!(Deadline::current().is_none());
+}
+
+#[tokio::test]
+async fn terminal_status_carries_merchant_redirect_url() {
+    use futures::StreamExt;
+
+    let (service, _rpc) = service("reject").await;
+    let request = |success_url: Option<&str>, cancel_url: Option<&str>| CreatePaymentRequest {
+        recipient: Pubkey::new_unique().to_string(),
+        amount: 1.0,
+        amount_base_units: None,
+        token: "SOL".to_string(),
+        label: None,
+        message: None,
+        memo: None,
+        tip_presets_bps: None,
+        locale: None,
+        metadata: None,
+        fee_amount: None,
+        fee_token: None,
+        success_url: success_url.map(str::to_string),
+        cancel_url: cancel_url.map(str::to_string),
+        discount_code: None,
+        redeem_points: None,
+        allowed_payers: None,
+        hold: false,
+        hold_secs: None,
+        amount_range: None,
+    };
+
+    let error = service.create_payment_with_fee(request(Some("http://shop.example/ok"), Some("not a url")), None).await
+        .expect_err("invalid redirect URLs");
+    let errors = error.downcast_ref::<ValidationErrors>().expect("validation errors");
+    let codes: Vec<(&str, &str)> = errors.errors().iter().map(|e| (e.field.as_str(), e.code)).collect();
+    assert_eq!(codes, vec![("success_url", "insecure_url"), ("cancel_url", "invalid_url")]);
+
+    let success = "https://shop.example/orders/42/thanks";
+    let cancel = "https://shop.example/orders/42/cart";
+    let mut paid = service.create_payment_with_fee(request(Some(success), Some(cancel)), None).await.expect("payment");
+    assert_eq!((paid.success_url.as_deref(), paid.redirect_url.as_deref()), (Some(success), None));
+    service.transition(&mut paid, PaymentStatus::Processing, None).await.unwrap();
+    assert_eq!(paid.redirect_url, None);
+    service.transition(&mut paid, PaymentStatus::Completed, None).await.unwrap();
+    assert_eq!(paid.redirect_url.as_deref(), Some(success));
+
+    let cancelled = service.create_payment_with_fee(request(Some(success), Some(cancel)), None).await.expect("payment");
+    let cancelled = service.change_status(&cancelled.id, None, PaymentStatus::Cancelled, None).await.unwrap().unwrap();
+    assert_eq!(cancelled.redirect_url.as_deref(), Some(cancel));
+
+    // Страница оплаты получает адрес последним событием потока
+    let events = sse::payment_events(service.clone(), &paid.id).await.unwrap().expect("stream");
+    let events: Vec<String> = events
+        .map(|chunk| String::from_utf8(chunk.unwrap().to_vec()).unwrap())
+        .collect().await;
+    assert_eq!(events.len(), 2, "{:?}", events);
+    assert!(events[1].starts_with("event: redirect\n"), "{}", events[1]);
+    assert!(events[1].contains(&format!("\"url\":\"{}\"", success)), "{}", events[1]);
+
+    // Без адресов возврата событие не отправляется
+    let plain = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    let plain = service.change_status(&plain.id, None, PaymentStatus::Cancelled, None).await.unwrap().unwrap();
+    assert_eq!(plain.redirect_url, None);
+    let events = sse::payment_events(service.clone(), &plain.id).await.unwrap().expect("stream");
+    assert_eq!(events.count().await, 1);
+}
+
+#[tokio::test]
+async fn webhook_filters_drop_unwanted_events_before_delivery() {
+    let config = config_with("reject", "", r#"
+[[merchants]]
+id = "chain"
+name = "Coffee Chain"
+api_key = "live-key-0123456789"
+"#);
+    let service = PaymentService::with_rpc(config, Arc::new(MockSolanaRpc::new())).await.expect("service");
+    let webhooks = WebhookService::new(service.storage().clone());
+    let merchant = &service.config().merchants[0];
+    let register = |filter: serde_json::Value| {
+        let request: RegisterWebhookRequest = serde_json::from_value(json!({
+            "url": "http://127.0.0.1:1/hook",
+            "filter": filter,
+        })).expect("request");
+        webhooks.register(request, None, merchant)
+    };
+
+    let everything = register(json!({})).await.unwrap().endpoint;
+    let berlin_usdc = register(json!({
+        "events": ["payment.completed"],
+        "tokens": ["usdc"],
+        "metadata": {"store_id": "berlin-1"},
+    })).await.unwrap().endpoint;
+    let orders = register(json!({"memo_prefix": "order-"})).await.unwrap().endpoint;
+    assert_eq!(berlin_usdc.filter.tokens, vec!["usdc".to_string()]);
+
+    let payment = |token: &str, store: &str, memo: Option<&str>| json!({
+        "id": "pay_1", "token": token, "metadata": {"store_id": store}, "memo": memo,
+    });
+    webhooks.publish("chain", "payment.completed", payment("USDC", "berlin-1", Some("order-42"))).await.unwrap();
+    webhooks.publish("chain", "payment.created", payment("USDC", "berlin-1", None)).await.unwrap();
+    webhooks.publish("chain", "payment.completed", payment("SOL", "berlin-1", None)).await.unwrap();
+    webhooks.publish("chain", "payment.completed", payment("USDC", "paris-2", Some("refill"))).await.unwrap();
+
+    for (endpoint, expected) in [(&everything, 4), (&berlin_usdc, 1), (&orders, 1)] {
+        assert_eq!(webhooks.deliveries(&endpoint.id, merchant).await.unwrap().len(), expected, "{:?}", endpoint.filter);
+    }
+
+    let error = register(json!({"events": ["payment.shipped"], "memo_prefix": ""})).await.expect_err("invalid filter");
+    let errors = error.downcast_ref::<ValidationErrors>().expect("validation errors");
+    let fields: Vec<&str> = errors.errors().iter().map(|e| e.field.as_str()).collect();
+    assert_eq!(fields, vec!["filter.events", "filter.memo_prefix"]);
+    let unknown = serde_json::from_value::<RegisterWebhookRequest>(json!({
+        "url": "https://shop.example/hook", "filter": {"store": "x"},
+    }));
+    assert!(unknown.is_err());
+}
+
+#[tokio::test]
+async fn stale_payment_copies_are_rejected_instead_of_overwriting() {
+    let (service, _rpc) = service("reject").await;
+    let payment = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    assert_eq!(payment.version, 1);
+
+    // Верификация прочитала платеж, затем задача expiration успела его закрыть
+    let mut stale = service.get_payment(&payment.id).await.unwrap().unwrap();
+    let mut overdue = stale.clone();
+    overdue.expires_at = chrono::Utc::now() - chrono::Duration::minutes(1);
+    service.storage().save_payment(&payment.id, &mut overdue).await.unwrap();
+    assert_eq!(overdue.version, 2);
+    assert_eq!(service.expire_due_payments().await.unwrap(), 1);
+
+    let error = service.transition(&mut stale, PaymentStatus::Completed, None).await.expect_err("stale copy");
+    let conflict = error.downcast_ref::<VersionConflict>().expect("version conflict");
+    assert_eq!((conflict.expected, conflict.actual), (1, 3));
+    let stored = service.get_payment(&payment.id).await.unwrap().unwrap();
+    assert_eq!((stored.status, stored.version), (PaymentStatus::Expired, 3));
+
+    // update_payment перечитывает платеж и повторяет изменение поверх чужой записи
+    let fresh = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    let storage = service.storage().clone();
+    let mut calls = 0;
+    let updated = service.update_payment(&fresh.id, |payment| {
+        calls += 1;
+        if calls == 1 {
+            let mut concurrent = payment.clone();
+            concurrent.metadata.insert("order".to_string(), "42".to_string());
+            futures::executor::block_on(storage.save_payment(&fresh.id, &mut concurrent))?;
+        }
+        payment.transition(PaymentStatus::Cancelled, Some("Cancelled by customer"))?;
+        Ok(true)
+    }).await.unwrap().unwrap();
+    assert_eq!(calls, 2);
+    assert_eq!(updated.status, PaymentStatus::Cancelled);
+    assert_eq!(updated.metadata.get("order").map(String::as_str), Some("42"));
+    assert_eq!(updated.version, 3);
+}
+
+#[tokio::test]
+async fn token_registry_adds_mints_from_on_chain_metadata() {
+    let (service, rpc) = service("reject").await;
+    let tokens = &service.config().tokens;
+    assert_eq!(service.config().get_supported_tokens(), vec!["SOL", "USDC", "USDT"]);
+    let (usdc, source) = tokens.resolve("USDC", rpc.as_ref()).await.unwrap();
+    assert_eq!((usdc.decimals, source), (6, TokenSource::Config));
+
+    // Mint с точностью 5 и метаданными Metaplex (строки дополнены нулями, как в программе)
+    let mint = Pubkey::new_unique();
+    let mut mint_data = vec![0u8; 82];
+    mint_data[44] = 5;
+    mint_data[45] = 1;
+    rpc.set_account(mint, Account { lamports: 1, data: mint_data, owner: spl_token::id(), executable: false, rent_epoch: 0 });
+    let program: Pubkey = TOKEN_METADATA_PROGRAM_ID.parse().unwrap();
+    let (metadata, _) = Pubkey::find_program_address(&[b"metadata", program.as_ref(), mint.as_ref()], &program);
+    let mut metadata_data = vec![4u8];
+    metadata_data.extend_from_slice(Pubkey::new_unique().as_ref());
+    metadata_data.extend_from_slice(mint.as_ref());
+    for (value, padded) in [("Bonk", 32), ("bonk", 10), ("", 200)] {
+        let mut bytes = value.as_bytes().to_vec();
+        bytes.resize(padded, 0);
+        metadata_data.extend_from_slice(&(padded as u32).to_le_bytes());
+        metadata_data.extend_from_slice(&bytes);
+    }
+    rpc.set_account(metadata, Account { lamports: 1, data: metadata_data, owner: program, executable: false, rent_epoch: 0 });
+
+    let request = |token: &str, symbol: Option<&str>| AddTokenRequest {
+        token: token.to_string(),
+        symbol: symbol.map(str::to_string),
+    };
+    let added = tokens.add(request(&mint.to_string(), None), rpc.as_ref()).await.expect("token added");
+    assert_eq!(added.source, TokenSource::OnChain);
+    assert_eq!((added.token.symbol.as_str(), added.token.name.as_str(), added.token.decimals), ("BONK", "Bonk", 5));
+    assert!(service.config().is_token_supported("BONK"));
+    assert_eq!(service.config().token_decimals("BONK"), 5);
+
+    // Токен сразу доступен для платежей
+    let payment = service.create_payment_with_fee(CreatePaymentRequest {
+        recipient: Pubkey::new_unique().to_string(),
+        amount: 2.5,
+        amount_base_units: None,
+        token: "BONK".to_string(),
+        label: None,
+        message: None,
+        memo: None,
+        tip_presets_bps: None,
+        locale: None,
+        metadata: None,
+        fee_amount: None,
+        fee_token: None,
+        success_url: None,
+        cancel_url: None,
+        discount_code: None,
+        redeem_points: None,
+        allowed_payers: None,
+        hold: false,
+        hold_secs: None,
+        amount_range: None,
+    }, None).await.expect("payment");
+    assert!(payment.transfer_url.contains(&format!("spl-token={}", mint)), "{}", payment.transfer_url);
+
+    let error = |result: anyhow::Result<_>| result.expect_err("rejected").to_string();
+    assert!(error(tokens.add(request(&mint.to_string(), Some("BNK")), rpc.as_ref()).await).contains("already registered"));
+    assert!(error(tokens.add(request("USDC", None), rpc.as_ref()).await).contains("already registered"));
+    let not_found = tokens.add(request("WIF", None), rpc.as_ref()).await.expect_err("no token list");
+    assert!(matches!(not_found.downcast_ref::<TokenError>(), Some(TokenError::NotFound(_))));
+    let wallet = tokens.add(request(&Pubkey::new_unique().to_string(), None), rpc.as_ref()).await.expect_err("no account");
+    assert!(matches!(wallet.downcast_ref::<TokenError>(), Some(TokenError::NotFound(_))));
+
+    // Символ другого токена занят; без символа в метаданных нужен свой
+    let other = Pubkey::new_unique();
+    let mut other_data = vec![0u8; 82];
+    other_data[44] = 9;
+    rpc.set_account(other, Account { lamports: 1, data: other_data, owner: spl_token::id(), executable: false, rent_epoch: 0 });
+    let taken = tokens.add(request(&other.to_string(), Some("bonk")), rpc.as_ref()).await.expect_err("symbol taken");
+    assert!(matches!(taken.downcast_ref::<TokenError>(), Some(TokenError::SymbolTaken(_))));
+    let unnamed = tokens.add(request(&other.to_string(), None), rpc.as_ref()).await.expect_err("no symbol");
+    assert!(unnamed.downcast_ref::<ValidationErrors>().is_some());
+    let named = tokens.add(request(&other.to_string(), Some("Meme")), rpc.as_ref()).await.unwrap();
+    assert_eq!((named.token.symbol.as_str(), named.token.decimals), ("MEME", 9));
+    assert_eq!(service.config().get_supported_tokens(), vec!["SOL", "USDC", "USDT", "BONK", "MEME"]);
+}
+
+#[tokio::test]
+async fn base_unit_amounts_reach_the_transaction_exactly() {
+    let (service, rpc) = service("reject").await;
+    let request = |amount: f64, amount_base_units: Option<u64>, token: &str| CreatePaymentRequest {
+        recipient: Pubkey::new_unique().to_string(),
+        amount,
+        amount_base_units,
+        token: token.to_string(),
+        label: None,
+        message: None,
+        memo: None,
+        tip_presets_bps: None,
+        locale: None,
+        metadata: None,
+        fee_amount: None,
+        fee_token: None,
+        success_url: None,
+        cancel_url: None,
+        discount_code: None,
+        redeem_points: None,
+        allowed_payers: None,
+        hold: false,
+        hold_secs: None,
+        amount_range: None,
+    };
+
+    // 0.1 + 0.2 SOL не представимо в f64 точно — в базовых единицах сумма ровно та, что задана
+    let sol = service.create_payment_with_fee(request(0.0, Some(300_000_001), "SOL"), None).await.expect("payment");
+    assert_eq!((sol.amount_base_units, sol.amount), (300_000_001, 0.300000001));
+    assert!(sol.transfer_url.contains("amount=0.300000001"), "{}", sol.transfer_url);
+
+    rpc.set_blockhash(Hash::new_unique());
+    let transaction = build(&service, &sol).await.expect("transaction built");
+    let lamports: Vec<u64> = transaction.message.instructions.iter()
+        .filter(|ix| transaction.message.account_keys[ix.program_id_index as usize] == solana_sdk::system_program::id())
+        .map(|ix| u64::from_le_bytes(ix.data[4..12].try_into().unwrap()))
+        .collect();
+    assert_eq!(lamports.first(), Some(&300_000_001));
+    // Чаевые и комиссия округляются до базовых единиц, а не отбрасываются (0.00013 * 1e9 < 130000 в f64)
+    let mut tipped = sol.clone();
+    (tipped.tip_amount, tipped.fee_amount) = (Some(0.00013), 0.00013);
+    let transaction = build(&service, &tipped).await.expect("transaction built");
+    let lamports: Vec<u64> = transaction.message.instructions.iter()
+        .filter(|ix| transaction.message.account_keys[ix.program_id_index as usize] == solana_sdk::system_program::id())
+        .map(|ix| u64::from_le_bytes(ix.data[4..12].try_into().unwrap()))
+        .collect();
+    assert_eq!(lamports, vec![300_000_001, 130_000, 130_000]);
+
+    let usdc = service.create_payment_with_fee(request(0.0, Some(1_234_567), "USDC"), None).await.expect("payment");
+    assert_eq!((usdc.amount, usdc.amount_display.as_str()), (1.234567, "1.234567 USDC"));
+    let from_float = service.create_payment_with_fee(request(0.29, None, "USDC"), None).await.expect("payment");
+    assert_eq!(from_float.amount_base_units, 290_000);
+
+    let codes = |requests: Vec<CreatePaymentRequest>| {
+        let service = service.clone();
+        async move {
+            let mut codes = Vec::new();
+            for request in requests {
+                let error = service.create_payment_with_fee(request, None).await.expect_err("invalid amount");
+                let errors = error.downcast_ref::<ValidationErrors>().expect("validation errors");
+                codes.extend(errors.errors().iter().map(|e| (e.field.clone(), e.code)));
+            }
+            codes
+        }
+    };
+    let codes = codes(vec![
+        request(1.0, Some(1_000_000), "USDC"),
+        request(0.0, Some(0), "USDC"),
+        request(0.0, Some(u64::MAX), "USDC"),
+        request(0.0000001, None, "USDC"),
+        request(0.0, None, "USDC"),
+    ]).await;
+    let codes: Vec<(&str, &str)> = codes.iter().map(|(field, code)| (field.as_str(), *code)).collect();
+    assert_eq!(codes, vec![
+        ("amount_base_units", "conflict"),
+        ("amount_base_units", "too_small"),
+        ("amount_base_units", "too_large"),
+        ("amount", "too_small"),
+        ("amount", "too_small"),
+    ]);
+
+    let json: CreatePaymentRequest = serde_json::from_value(json!({
+        "recipient": Pubkey::new_unique().to_string(), "amount_base_units": 18_446_744_073_709_551_615u64, "token": "SOL",
+    })).expect("request without amount");
+    assert_eq!((json.amount, json.amount_base_units), (0.0, Some(u64::MAX)));
+}
+
+#[tokio::test]
+async fn discount_codes_reduce_amount_and_count_redemptions() {
+    let (service, _rpc) = service("reject").await;
+    let discounts = service.discounts();
+    let create = |body: serde_json::Value| async move {
+        discounts.create(serde_json::from_value::<CreateDiscountRequest>(body).expect("request")).await
+    };
+    let request = |amount: f64, token: &str, code: &str| CreatePaymentRequest {
+        recipient: Pubkey::new_unique().to_string(),
+        amount,
+        amount_base_units: None,
+        token: token.to_string(),
+        label: None,
+        message: None,
+        memo: None,
+        tip_presets_bps: None,
+        locale: None,
+        metadata: None,
+        fee_amount: None,
+        fee_token: None,
+        success_url: None,
+        cancel_url: None,
+        discount_code: Some(code.to_string()),
+        redeem_points: None,
+        allowed_payers: None,
+        hold: false,
+        hold_secs: None,
+        amount_range: None,
+    };
+    let rejection = |result: anyhow::Result<Payment>| {
+        let error = result.expect_err("discount rejected");
+        let errors = error.downcast_ref::<ValidationErrors>().expect("validation errors");
+        errors.errors().iter().map(|e| format!("{}:{}", e.field, e.code)).collect::<Vec<_>>().join(",")
+    };
+
+    create(json!({"code": "spring-10", "type": "percentage", "percent_bps": 1000, "max_uses": 2})).await.unwrap();
+    create(json!({"code": "FIVE", "type": "fixed", "amount": 5.0, "token": "USDC"})).await.unwrap();
+    create(json!({"code": "VIP", "type": "percentage", "percent_bps": 5000, "merchant_id": "shop"})).await.unwrap_err();
+
+    // Код вводится в любом регистре; скидка округляется вниз до базовой единицы
+    let paid = service.create_payment_with_fee(request(1.234567, "USDC", "Spring-10"), None).await.expect("payment");
+    let discount = paid.discount.clone().expect("discount applied");
+    assert_eq!((discount.code.as_str(), discount.original_base_units, discount.discount_base_units), ("SPRING-10", 1_234_567, 123_456));
+    assert_eq!((paid.amount_base_units, paid.amount), (1_111_111, 1.111111));
+
+    let fixed = service.create_payment_with_fee(request(20.0, "USDC", "five"), None).await.expect("payment");
+    assert_eq!(fixed.amount, 15.0);
+    assert_eq!(rejection(service.create_payment_with_fee(request(1.0, "SOL", "FIVE"), None).await), "discount_code:wrong_token");
+    assert_eq!(rejection(service.create_payment_with_fee(request(5.0, "USDC", "FIVE"), None).await), "discount_code:exceeds_amount");
+    assert_eq!(rejection(service.create_payment_with_fee(request(1.0, "USDC", "NOPE"), None).await), "discount_code:not_found");
+
+    service.create_payment_with_fee(request(2.0, "SOL", "SPRING-10"), None).await.expect("second use");
+    assert_eq!(rejection(service.create_payment_with_fee(request(2.0, "SOL", "SPRING-10"), None).await), "discount_code:exhausted");
+
+    let details = discounts.details("spring-10").await.unwrap();
+    assert_eq!(details.discount.uses, 2);
+    assert_eq!(details.redemptions.len(), 2);
+    assert_eq!(details.redemptions[0].payment_id, paid.id);
+    assert_eq!(details.redemptions[0].discount_amount, 0.123456);
+
+    discounts.deactivate("FIVE").await.unwrap();
+    assert_eq!(rejection(service.create_payment_with_fee(request(20.0, "USDC", "FIVE"), None).await), "discount_code:inactive");
+
+    let duplicate = create(json!({"code": "five", "type": "percentage", "percent_bps": 100})).await.expect_err("duplicate");
+    assert!(matches!(duplicate.downcast_ref::<DiscountError>(), Some(DiscountError::AlreadyExists(_))));
+    let invalid = create(json!({"code": "x", "type": "percentage", "percent_bps": 10_000, "max_uses": 0})).await
+        .expect_err("invalid");
+    let fields: Vec<&str> = invalid.downcast_ref::<ValidationErrors>().unwrap().errors().iter().map(|e| e.field.as_str()).collect();
+    assert_eq!(fields, vec!["code", "percent_bps", "max_uses"]);
+}
+
+/// Ценовой API для комиссии в USD: создание платежа ждет его ответа уже после выдачи ID
+fn usd_price_api() -> String {
+    use std::io::{Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/price", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut buffer = [0u8; 4096];
+            let _ = stream.read(&mut buffer);
+            let body = r#"{"data":{"So11111111111111111111111111111111111111112":{"price":"150.5"}}}"#;
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(), body,
+            );
+        }
+    });
+    url
+}
+
+/// Генератор с постоянным ID: два одновременных платежа получают один ID, и второй
+/// не сохраняется (конфликт версии)
+struct SameId;
+
+impl IdGenerator for SameId {
+    fn generate(&self) -> String {
+        "pay_SAMEID".to_string()
+    }
+}
+
+#[tokio::test]
+async fn discount_use_is_released_when_payment_is_not_saved() {
+    let config = config_with("reject", &format!("fee_usd = 0.5\nprice_api_url = \"{}\"\n", usd_price_api()), "");
+    let service = PaymentService::with_rpc(config, Arc::new(MockSolanaRpc::new())).await.expect("service");
+    service.discounts().create(serde_json::from_value(
+        json!({"code": "TWICE", "type": "percentage", "percent_bps": 1000, "max_uses": 2}),
+    ).unwrap()).await.unwrap();
+    let request = || -> CreatePaymentRequest {
+        serde_json::from_value(json!({
+            "recipient": Pubkey::new_unique().to_string(), "amount": 1.0, "token": "SOL", "discount_code": "TWICE",
+        })).unwrap()
+    };
+
+    // Оба платежа засчитывают код, второй не сохраняется — его использование возвращается
+    let racing = service.clone().with_id_generator(Arc::new(SameId));
+    let (first, second) = tokio::join!(
+        racing.create_payment_with_fee(request(), None),
+        racing.create_payment_with_fee(request(), None),
+    );
+    assert_eq!([first.is_ok(), second.is_ok()].iter().filter(|&&saved| saved).count(), 1);
+    let error = first.err().or(second.err()).unwrap();
+    assert!(error.downcast_ref::<VersionConflict>().is_some(), "{}", error);
+    let details = service.discounts().details("TWICE").await.unwrap();
+    assert_eq!((details.discount.uses, details.redemptions.len()), (1, 1));
+
+    // Код не исчерпан неудачной попыткой
+    service.create_payment_with_fee(request(), None).await.expect("second use");
+    assert_eq!(service.discounts().details("TWICE").await.unwrap().discount.uses, 2);
+}
+
+#[tokio::test]
+async fn loyalty_points_and_discount_are_returned_when_payment_is_not_created() {
+    let config = config_with("reject", &format!("fee_usd = 0.5\nprice_api_url = \"{}\"\n", usd_price_api()), r#"
+[[merchants]]
+id = "shop"
+name = "Shop"
+api_key = "live-key-0123456789"
+
+[merchants.loyalty]
+earn_rate = { SOL = 10.0 }
+redeem_value = { SOL = 0.01 }
+max_redeem_bps = 5000
+"#);
+    let service = PaymentService::with_rpc(config, Arc::new(MockSolanaRpc::new())).await.expect("service");
+    let merchant = service.config().merchants[0].clone();
+    service.discounts().create(serde_json::from_value(
+        json!({"code": "SHOP10", "type": "percentage", "percent_bps": 1000}),
+    ).unwrap()).await.unwrap();
+    let (alice, bob) = (Pubkey::new_unique().to_string(), Pubkey::new_unique().to_string());
+    for wallet in [&alice, &bob] {
+        let entry = LoyaltyEntry { payment_id: "pay_seed".to_string(), kind: LoyaltyEntryKind::Earned, points: 25, at: chrono::Utc::now() };
+        service.storage().apply_loyalty_entry("shop", wallet, entry).await.unwrap().unwrap();
+    }
+    let request = |wallet: &str| -> CreatePaymentRequest {
+        serde_json::from_value(json!({
+            "recipient": Pubkey::new_unique().to_string(), "amount": 1.0, "token": "SOL", "discount_code": "SHOP10",
+            "redeem_points": {"wallet": wallet, "points": 20},
+        })).unwrap()
+    };
+    let balance = |wallet: String| {
+        let service = service.clone();
+        async move { service.loyalty().balances(&wallet, Some("shop")).await.unwrap()[0].balance }
+    };
+    let uses = || async { service.discounts().details("SHOP10").await.unwrap().discount.uses };
+
+    // Платеж с тем же ID не сохраняется — его баллы и использование кода возвращаются
+    let racing = service.clone().with_id_generator(Arc::new(SameId));
+    let (first, second) = tokio::join!(
+        racing.create_payment_with_fee(request(&alice), Some(&merchant)),
+        racing.create_payment_with_fee(request(&bob), Some(&merchant)),
+    );
+    let (saved, loser) = match (first, second) {
+        (Ok(_), Err(e)) => (&alice, (e, &bob)),
+        (Err(e), Ok(_)) => (&bob, (e, &alice)),
+        _ => panic!("exactly one payment is saved"),
+    };
+    assert!(loser.0.downcast_ref::<VersionConflict>().is_some(), "{}", loser.0);
+    assert_eq!((balance(saved.clone()).await, balance(loser.1.clone()).await), (5, 25));
+    assert_eq!(uses().await, 1);
+
+    // Баллы закончились у второго платежа после проверки — код, засчитанный ему, возвращается
+    let (first, second) = tokio::join!(
+        service.create_payment_with_fee(request(loser.1), Some(&merchant)),
+        service.create_payment_with_fee(request(loser.1), Some(&merchant)),
+    );
+    let error = first.err().or(second.err()).expect("one payment rejected");
+    let errors = error.downcast_ref::<ValidationErrors>().expect("validation errors");
+    assert_eq!(errors.errors()[0].code, "insufficient");
+    assert_eq!(balance(loser.1.clone()).await, 5);
+    assert_eq!(uses().await, 2);
+}
+
+#[tokio::test]
+async fn held_payments_are_captured_released_or_expire_back_to_payer() {
+    let hot_wallet = Keypair::new();
+    let keypair_path = std::env::temp_dir().join(format!("cryptonow-rpc-mock-{}-escrow.json", std::process::id()));
+    write_keypair_file(&hot_wallet, &keypair_path).expect("write keypair");
+    let config = config_with(
+        "reject",
+        &format!("server_keypair_path = {:?}", keypair_path.to_str().unwrap()),
+        "[escrow]\nenabled = true\ndefault_hold_secs = 3600",
+    );
+    let signer = ServerSigner::from_file(keypair_path.to_str().unwrap()).expect("signer");
+    let _ = std::fs::remove_file(&keypair_path);
+
+    let rpc = Arc::new(MockSolanaRpc::new());
+    rpc.set_blockhash(Hash::new_unique());
+    let service = PaymentService::with_rpc(config, rpc.clone()).await.expect("service")
+        .with_escrow_account(hot_wallet.pubkey());
+    let escrow = EscrowService::new(service.clone(), Some(signer));
+    let request = |hold: bool, hold_secs: Option<u64>| CreatePaymentRequest {
+        recipient: Pubkey::new_unique().to_string(),
+        amount: 1.0,
+        amount_base_units: None,
+        token: "SOL".to_string(),
+        label: None,
+        message: None,
+        memo: None,
+        tip_presets_bps: None,
+        locale: None,
+        metadata: None,
+        fee_amount: None,
+        fee_token: None,
+        success_url: None,
+        cancel_url: None,
+        discount_code: None,
+        redeem_points: None,
+        allowed_payers: None,
+        hold,
+        hold_secs,
+        amount_range: None,
+    };
+    // Оплата плательщиком: на счет удержания пришла вся сумма
+    let pay = |payer: &Pubkey| {
+        let signature = Signature::new_unique();
+        rpc.set_transaction(signature, TransactionStatus {
+            slot: 42,
+            balance_changes: vec![
+                BalanceChange { owner: payer.to_string(), mint: None, delta: -1_001_005_000 },
+                BalanceChange { owner: hot_wallet.pubkey().to_string(), mint: None, delta: 1_000_000_000 },
+            ],
+            ..TransactionStatus::default()
+        });
+        signature.to_string()
+    };
+    // Последний перевод hot wallet: получатель и сумма
+    let last_transfer = || {
+        let sent = rpc.sent_transactions().pop().expect("hold transfer sent");
+        assert!(sent.verify_with_results().iter().all(|&ok| ok), "signed by hot wallet");
+        let keys = sent.message.static_account_keys();
+        assert_eq!(keys[0], hot_wallet.pubkey());
+        let instruction = &sent.message.instructions()[0];
+        let lamports = u64::from_le_bytes(instruction.data[4..12].try_into().unwrap());
+        (keys[instruction.accounts[1] as usize], lamports)
+    };
+
+    let hold_secs_alone = service.create_payment_with_fee(request(false, Some(60)), None).await.expect_err("no hold");
+    let errors = hold_secs_alone.downcast_ref::<ValidationErrors>().expect("validation errors");
+    assert_eq!(errors.errors()[0].code, "conflict");
+
+    // Транзакция платежа переводит сумму на счет удержания, а не получателю
+    let captured = service.create_payment_with_fee(request(true, None), None).await.expect("payment");
+    assert_eq!(captured.destination(), hot_wallet.pubkey().to_string());
+    let transaction = build(&service, &captured).await.expect("transaction");
+    assert!(transaction.message.account_keys.contains(&hot_wallet.pubkey()));
+    assert!(!transaction.message.account_keys.contains(&captured.recipient.parse().unwrap()));
+
+    let payer = Pubkey::new_unique();
+    let result = service.verify_payment(&captured.id, &pay(&payer)).await.expect("verified");
+    assert_eq!(result.status, PaymentStatus::Held);
+    let held = service.get_payment(&captured.id).await.unwrap().unwrap();
+    let hold = held.hold.clone().expect("hold");
+    assert_eq!(held.payer, Some(payer.to_string()));
+    assert!(hold.expires_at.is_some_and(|at| at > chrono::Utc::now() + chrono::Duration::minutes(59)));
+
+    let done = escrow.capture(&captured.id, None).await.expect("captured").expect("payment");
+    assert_eq!(done.status, PaymentStatus::Completed);
+    assert_eq!(last_transfer(), (captured.recipient.parse().unwrap(), 1_000_000_000));
+    assert!(done.hold.as_ref().is_some_and(|hold| hold.signature.is_some() && hold.pending_action.is_none()));
+    let again = escrow.release(&captured.id, None, None).await.expect_err("already captured");
+    assert!(matches!(again.downcast_ref::<TransitionError>(), Some(TransitionError::Illegal { .. })));
+
+    // Возврат плательщику; чужой мерчант платеж не видит
+    let released = service.create_payment_with_fee(request(true, None), None).await.expect("payment");
+    service.verify_payment(&released.id, &pay(&payer)).await.expect("verified");
+    assert!(escrow.release(&released.id, Some("other"), None).await.unwrap().is_none());
+    let done = escrow.release(&released.id, None, None).await.expect("released").expect("payment");
+    assert_eq!(done.status, PaymentStatus::Released);
+    assert_eq!(last_transfer(), (payer, 1_000_000_000));
+
+    // Сбой отправки снимает заявку — перевод можно повторить
+    let expiring = service.create_payment_with_fee(request(true, Some(1)), None).await.expect("payment");
+    service.verify_payment(&expiring.id, &pay(&payer)).await.expect("verified");
+    rpc.fail("send_transaction", MockFailure::Error("unavailable".to_string()));
+    escrow.capture(&expiring.id, None).await.expect_err("send failed");
+    let pending = service.get_payment(&expiring.id).await.unwrap().unwrap();
+    assert_eq!(pending.status, PaymentStatus::Held);
+    assert_eq!(pending.hold.unwrap().pending_action, None::<HoldAction>);
+    rpc.clear_failures();
+
+    // Срок удержания вышел — задача возвращает средства сама
+    assert_eq!(escrow.release_expired().await.unwrap(), 0);
+    tokio::time::sleep(Duration::from_millis(1_100)).await;
+    assert_eq!(escrow.release_expired().await.unwrap(), 1);
+    let expired = service.get_payment(&expiring.id).await.unwrap().unwrap();
+    assert_eq!(expired.status, PaymentStatus::Released);
+    assert_eq!(expired.status_history.last().unwrap().reason.as_deref().map(|r| r.starts_with("Hold period elapsed")), Some(true));
+}
+
+#[tokio::test]
+async fn disputes_are_resolved_by_admin_with_refund_or_denial() {
+    let hot_wallet = Keypair::new();
+    let keypair_path = std::env::temp_dir().join(format!("cryptonow-rpc-mock-{}-disputes.json", std::process::id()));
+    write_keypair_file(&hot_wallet, &keypair_path).expect("write keypair");
+    let config = config_with(
+        "reject",
+        &format!("server_keypair_path = {:?}", keypair_path.to_str().unwrap()),
+        r#"
+[escrow]
+enabled = true
+
+[[merchants]]
+id = "shop"
+name = "Shop"
+api_key = "live-key-0123456789"
+"#,
+    );
+    let signer = ServerSigner::from_file(keypair_path.to_str().unwrap()).expect("signer");
+    let _ = std::fs::remove_file(&keypair_path);
+
+    let rpc = Arc::new(MockSolanaRpc::new());
+    rpc.set_blockhash(Hash::new_unique());
+    let service = PaymentService::with_rpc(config, rpc.clone()).await.expect("service")
+        .with_escrow_account(hot_wallet.pubkey());
+    let escrow = EscrowService::new(service.clone(), Some(signer));
+    let disputes = DisputeService::new(service.clone(), escrow.clone());
+    let merchant = service.config().merchants[0].clone();
+    let create = |hold: bool| service.create_payment_with_fee(CreatePaymentRequest {
+        recipient: Pubkey::new_unique().to_string(),
+        amount: 1.0,
+        amount_base_units: None,
+        token: "SOL".to_string(),
+        label: None,
+        message: None,
+        memo: None,
+        tip_presets_bps: None,
+        locale: None,
+        metadata: Some([("order".to_string(), "42".to_string())].into()),
+        fee_amount: None,
+        fee_token: None,
+        success_url: None,
+        cancel_url: None,
+        discount_code: None,
+        redeem_points: None,
+        allowed_payers: None,
+        hold,
+        hold_secs: None,
+        amount_range: None,
+    }, Some(&merchant));
+    let payer = Pubkey::new_unique();
+    let pay = |payment: &Payment| {
+        let signature = Signature::new_unique();
+        let balance_changes = match payment.hold {
+            Some(_) => vec![
+                BalanceChange { owner: payer.to_string(), mint: None, delta: -1_001_005_000 },
+                BalanceChange { owner: hot_wallet.pubkey().to_string(), mint: None, delta: 1_000_000_000 },
+            ],
+            None => paid_transaction(&service, payment).balance_changes,
+        };
+        rpc.set_transaction(signature, TransactionStatus { slot: 42, balance_changes, ..TransactionStatus::default() });
+        signature.to_string()
+    };
+    let open = |reason: DisputeReason, evidence: Vec<DisputeEvidence>| OpenDisputeRequest {
+        reason,
+        description: "Parcel never arrived".to_string(),
+        evidence,
+        contact: Some("buyer@example.com".to_string()),
+    };
+    let resolve = |resolution: DisputeResolution| ResolveDisputeRequest { resolution, note: Some("Checked".to_string()), refund_signature: None };
+
+    // Неоплаченный платеж оспорить нельзя, чужой мерчант платеж не видит
+    let completed = create(false).await.expect("payment");
+    let not_paid = disputes.open(&completed.id, None, open(DisputeReason::NotReceived, Vec::new())).await.expect_err("pending");
+    assert!(matches!(not_paid.downcast_ref::<DisputeError>(), Some(DisputeError::NotDisputable(PaymentStatus::Pending))));
+    service.verify_payment(&completed.id, &pay(&completed)).await.expect("verified");
+    assert!(disputes.open(&completed.id, Some("other"), open(DisputeReason::Other, Vec::new())).await.unwrap().is_none());
+
+    let bad_evidence = vec![DisputeEvidence { description: "Chat".to_string(), url: Some("ftp://example.com/log".to_string()) }];
+    let invalid = disputes.open(&completed.id, None, open(DisputeReason::NotReceived, bad_evidence)).await.expect_err("invalid url");
+    assert_eq!(invalid.downcast_ref::<ValidationErrors>().expect("validation").errors()[0].field, "evidence[0].url");
+
+    let evidence = vec![DisputeEvidence { description: "Tracking".to_string(), url: Some("https://example.com/track".to_string()) }];
+    let dispute = disputes.open(&completed.id, None, open(DisputeReason::NotReceived, evidence)).await.unwrap().expect("dispute");
+    assert_eq!(dispute.status, DisputeStatus::Open);
+    assert_eq!(dispute.merchant_id.as_deref(), Some("shop"));
+    let twice = disputes.open(&completed.id, Some("shop"), open(DisputeReason::Duplicate, Vec::new())).await.expect_err("already open");
+    assert!(matches!(twice.downcast_ref::<DisputeError>(), Some(DisputeError::AlreadyOpen(id)) if *id == dispute.id));
+
+    // Событие dispute.opened несет поля платежа для фильтров webhooks и описано схемой
+    let outbox = service.storage().pending_outbox_events().await.unwrap();
+    let event = outbox.iter().find(|e| e.event_type == "dispute.opened").expect("dispute event");
+    assert_eq!(event.payload["payment_id"], completed.id.as_str());
+    assert_eq!(event.payload["metadata"]["order"], "42");
+    let schema = events::schema(1).unwrap();
+    let declared = schema["properties"]["data"]["oneOf"][1]["properties"].as_object().unwrap();
+    let mut declared: Vec<&str> = declared.keys().map(String::as_str).collect();
+    let mut sent: Vec<&str> = event.payload.as_object().unwrap().keys().map(String::as_str).collect();
+    declared.sort_unstable();
+    sent.sort_unstable();
+    assert_eq!(sent, declared);
+
+    // Возврат по завершенному платежу помечает его refunded; повторное решение — конфликт
+    let refunded = disputes.resolve(&dispute.id, resolve(DisputeResolution::Refund)).await.expect("resolved");
+    assert_eq!(refunded.status, DisputeStatus::Refunded);
+    assert_eq!(refunded.history.len(), 2);
+    assert_eq!(service.get_payment(&completed.id).await.unwrap().unwrap().status, PaymentStatus::Refunded);
+    let again = disputes.resolve(&dispute.id, resolve(DisputeResolution::Deny)).await.expect_err("resolved");
+    assert!(matches!(again.downcast_ref::<DisputeError>(), Some(DisputeError::AlreadyResolved(DisputeStatus::Refunded))));
+
+    // Удержание под спором нельзя списать и оно не возвращается по сроку; отказ снимает блокировку
+    let held = create(true).await.expect("payment");
+    service.verify_payment(&held.id, &pay(&held)).await.expect("verified");
+    let denied = disputes.open(&held.id, Some("shop"), open(DisputeReason::NotAsDescribed, Vec::new())).await.unwrap().unwrap();
+    let blocked = escrow.capture(&held.id, Some("shop")).await.expect_err("disputed");
+    assert!(matches!(blocked.downcast_ref::<EscrowError>(), Some(EscrowError::Disputed(id)) if *id == denied.id));
+    disputes.resolve(&denied.id, resolve(DisputeResolution::Deny)).await.expect("denied");
+    assert_eq!(service.get_payment(&held.id).await.unwrap().unwrap().status, PaymentStatus::Held);
+
+    // Второй спор по тому же удержанию — возврат переводит средства плательщику
+    let reopened = disputes.open(&held.id, None, open(DisputeReason::Fraudulent, Vec::new())).await.unwrap().unwrap();
+    let resolved = disputes.resolve(&reopened.id, resolve(DisputeResolution::Refund)).await.expect("refunded");
+    assert!(resolved.refund_signature.is_some());
+    assert_eq!(service.get_payment(&held.id).await.unwrap().unwrap().status, PaymentStatus::Released);
+    let sent = rpc.sent_transactions().pop().expect("refund sent");
+    let instruction = &sent.message.instructions()[0];
+    assert_eq!(sent.message.static_account_keys()[instruction.accounts[1] as usize], payer);
+
+    let open_only = DisputeFilter { status: Some(DisputeStatus::Open), ..Default::default() };
+    assert!(disputes.list(&open_only).await.unwrap().is_empty());
+    let for_held = DisputeFilter { payment_id: Some(held.id.clone()), ..Default::default() };
+    let history: Vec<DisputeStatus> = disputes.list(&for_held).await.unwrap().iter().map(|d| d.status).collect();
+    assert_eq!(history, vec![DisputeStatus::Refunded, DisputeStatus::Denied]);
+    let types: Vec<String> = service.storage().pending_outbox_events().await.unwrap().into_iter()
+        .map(|e| e.event_type)
+        .filter(|t| t.starts_with("dispute."))
+        .collect();
+    assert_eq!(types, ["dispute.opened", "dispute.refunded", "dispute.opened", "dispute.denied", "dispute.opened", "dispute.refunded"]);
+}
+
+#[tokio::test]
+async fn receipt_nfts_are_minted_to_payers_of_opted_in_merchants() {
+    let hot_wallet = Keypair::new();
+    let keypair_path = std::env::temp_dir().join(format!("cryptonow-rpc-mock-{}-receipt-nft.json", std::process::id()));
+    write_keypair_file(&hot_wallet, &keypair_path).expect("write keypair");
+    let config = config_with(
+        "reject",
+        &format!("server_keypair_path = {:?}", keypair_path.to_str().unwrap()),
+        r#"
+[receipt_nft]
+enabled = true
+max_attempts = 2
+
+[[merchants]]
+id = "shop"
+name = "Shop"
+api_key = "live-key-0123456789"
+receipt_nft = true
+
+[[merchants]]
+id = "plain"
+name = "Plain"
+api_key = "live-key-9876543210"
+"#,
+    );
+    assert!(config.jobs["receipt_nft"].enabled);
+    let signer = ServerSigner::from_file(keypair_path.to_str().unwrap()).expect("signer");
+    let _ = std::fs::remove_file(&keypair_path);
+
+    let rpc = Arc::new(MockSolanaRpc::new());
+    rpc.set_blockhash(Hash::new_unique());
+    let service = PaymentService::with_rpc(config, rpc.clone()).await.expect("service");
+    let receipts = ReceiptNftService::new(service.clone(), Some(signer));
+    let payer = Pubkey::new_unique();
+    let paid = |merchant: usize| {
+        let service = service.clone();
+        let rpc = rpc.clone();
+        async move {
+            let merchant = service.config().merchants[merchant].clone();
+            let payment = service.create_payment_with_fee(CreatePaymentRequest {
+                recipient: Pubkey::new_unique().to_string(),
+                amount: 1.0,
+                amount_base_units: None,
+                token: "SOL".to_string(),
+                label: Some("Order 7".to_string()),
+                message: None,
+                memo: None,
+                tip_presets_bps: None,
+                locale: None,
+                metadata: None,
+                fee_amount: None,
+                fee_token: None,
+                success_url: None,
+                cancel_url: None,
+                discount_code: None,
+                redeem_points: None,
+                allowed_payers: None,
+                hold: false,
+                hold_secs: None,
+                amount_range: None,
+            }, Some(&merchant)).await.expect("payment");
+            let signature = Signature::new_unique();
+            rpc.set_transaction(signature, TransactionStatus {
+                slot: 42,
+                balance_changes: vec![
+                    BalanceChange { owner: payer.to_string(), mint: None, delta: -1_001_005_000 },
+                    BalanceChange { owner: payment.recipient.clone(), mint: None, delta: 1_000_000_000 },
+                ],
+                ..TransactionStatus::default()
+            });
+            service.verify_payment(&payment.id, &signature.to_string()).await.expect("verified");
+            payment
+        }
+    };
+
+    let opted_in = paid(0).await;
+    let opted_out = paid(1).await;
+    assert_eq!(service.get_payment(&opted_in.id).await.unwrap().unwrap().payer, Some(payer.to_string()));
+
+    // Сбой отправки засчитывается как попытка; после max_attempts платеж больше не берется
+    rpc.fail("send_transaction", MockFailure::Error("unavailable".to_string()));
+    assert_eq!(receipts.mint_pending().await.unwrap(), 0);
+    let failed = service.get_payment(&opted_in.id).await.unwrap().unwrap().receipt_nft.expect("attempt recorded");
+    assert_eq!((failed.attempts, failed.mint), (1, None));
+    assert!(failed.last_error.is_some());
+    rpc.clear_failures();
+
+    assert_eq!(receipts.mint_pending().await.unwrap(), 1);
+    let minted = service.get_payment(&opted_in.id).await.unwrap().unwrap().receipt_nft.expect("receipt nft");
+    assert_eq!(minted.attempts, 2);
+    assert!(minted.minted_at.is_some() && minted.signature.is_some());
+    assert!(service.get_payment(&opted_out.id).await.unwrap().unwrap().receipt_nft.is_none());
+    assert_eq!(receipts.mint_pending().await.unwrap(), 0, "already minted");
+
+    // Транзакция: новый mint подписан вместе с hot wallet, один токен в ATA плательщика
+    let sent = rpc.sent_transactions().pop().expect("mint transaction");
+    assert!(sent.verify_with_results().iter().all(|&ok| ok));
+    let keys = sent.message.static_account_keys();
+    let mint: Pubkey = minted.mint.as_deref().unwrap().parse().unwrap();
+    assert_eq!(keys[0], hot_wallet.pubkey());
+    assert!(keys[..sent.message.header().num_required_signatures as usize].contains(&mint));
+    let payer_account = spl_associated_token_account::get_associated_token_address(&payer, &mint);
+    assert!(keys.contains(&payer_account));
+    let metadata_program: Pubkey = TOKEN_METADATA_PROGRAM_ID.parse().unwrap();
+    assert!(sent.message.instructions().iter().any(|ix| keys[ix.program_id_index as usize] == metadata_program));
+
+    let metadata = ReceiptNftMetadata::for_payment(&service.get_payment(&opted_in.id).await.unwrap().unwrap(), service.config())
+        .expect("metadata");
+    assert!(metadata.name.len() <= 32 && metadata.name.starts_with("Receipt "));
+    assert_eq!(metadata.symbol, "RCPT");
+    assert!(metadata.attributes.iter().any(|a| a.trait_type == "Payment ID" && a.value == opted_in.id));
+}
+
+#[tokio::test]
+async fn loyalty_points_accrue_on_completion_and_pay_for_later_payments() {
+    let config = config_with("reject", "", r#"
+[[merchants]]
+id = "shop"
+name = "Shop"
+api_key = "live-key-0123456789"
+
+[merchants.loyalty]
+earn_rate = { SOL = 10.0 }
+redeem_value = { SOL = 0.01 }
+max_redeem_bps = 5000
+"#);
+    let rpc = Arc::new(MockSolanaRpc::new());
+    let service = PaymentService::with_rpc(config, rpc.clone()).await.expect("service");
+    let merchant = service.config().merchants[0].clone();
+    let payer = Pubkey::new_unique();
+    let request = |amount: f64, points: Option<u64>| CreatePaymentRequest {
+        recipient: Pubkey::new_unique().to_string(),
+        amount,
+        amount_base_units: None,
+        token: "SOL".to_string(),
+        label: None,
+        message: None,
+        memo: None,
+        tip_presets_bps: None,
+        locale: None,
+        metadata: None,
+        fee_amount: None,
+        fee_token: None,
+        success_url: None,
+        cancel_url: None,
+        discount_code: None,
+        redeem_points: points.map(|points| RedeemPointsRequest { wallet: payer.to_string(), points }),
+        allowed_payers: None,
+        hold: false,
+        hold_secs: None,
+        amount_range: None,
+    };
+    let pay = |payment: Payment| {
+        let service = service.clone();
+        let rpc = rpc.clone();
+        async move {
+            let signature = Signature::new_unique();
+            rpc.set_transaction(signature, TransactionStatus {
+                slot: 42,
+                balance_changes: vec![
+                    BalanceChange { owner: payer.to_string(), mint: None, delta: -(payment.amount_base_units as i128) - 1_005_000 },
+                    BalanceChange { owner: payment.recipient.clone(), mint: None, delta: payment.amount_base_units as i128 },
+                ],
+                ..TransactionStatus::default()
+            });
+            service.verify_payment(&payment.id, &signature.to_string()).await.expect("verified");
+        }
+    };
+    let balance = || {
+        let service = service.clone();
+        async move { service.loyalty().balances(&payer.to_string(), Some("shop")).await.unwrap()[0].balance }
+    };
+    let rejection = |result: anyhow::Result<Payment>| {
+        let error = result.expect_err("redemption rejected");
+        let errors = error.downcast_ref::<ValidationErrors>().expect("validation errors");
+        errors.errors().iter().map(|e| format!("{}:{}", e.field, e.code)).collect::<Vec<_>>().join(",")
+    };
+
+    // 2.5 SOL * 10 баллов
+    let first = service.create_payment_with_fee(request(2.5, None), Some(&merchant)).await.expect("payment");
+    pay(first.clone()).await;
+    assert_eq!(balance().await, 25);
+
+    assert_eq!(rejection(service.create_payment_with_fee(request(1.0, Some(30)), Some(&merchant)).await), "redeem_points:insufficient");
+    assert_eq!(rejection(service.create_payment_with_fee(request(0.2, Some(20)), Some(&merchant)).await), "redeem_points:exceeds_limit");
+    assert_eq!(rejection(service.create_payment_with_fee(request(1.0, Some(5)), None).await), "redeem_points:not_enabled");
+
+    // Баллы списываются при создании и возвращаются, если платеж не оплачен
+    let cancelled = service.create_payment_with_fee(request(1.0, Some(20)), Some(&merchant)).await.expect("payment");
+    let redemption = cancelled.loyalty.clone().expect("redemption");
+    assert_eq!((redemption.amount_base_units, cancelled.amount_base_units), (200_000_000, 800_000_000));
+    assert_eq!(balance().await, 5);
+    service.change_status(&cancelled.id, Some("shop"), PaymentStatus::Cancelled, None).await.unwrap();
+    assert_eq!(balance().await, 25);
+
+    // Платеж, частично оплаченный баллами, приносит баллы с суммы к оплате; возврат снимает их
+    // и возвращает списанные
+    let redeemed = service.create_payment_with_fee(request(1.0, Some(20)), Some(&merchant)).await.expect("payment");
+    pay(redeemed.clone()).await;
+    assert_eq!(balance().await, 5 + 8);
+    service.change_status(&redeemed.id, Some("shop"), PaymentStatus::Refunded, Some("returned")).await.unwrap();
+    assert_eq!(balance().await, 25);
+
+    let accounts = service.loyalty().balances(&payer.to_string(), None).await.unwrap();
+    assert_eq!(accounts.len(), 1);
+    let kinds: Vec<LoyaltyEntryKind> = accounts[0].entries.iter().map(|entry| entry.kind).collect();
+    assert_eq!(kinds, vec![
+        LoyaltyEntryKind::Earned,
+        LoyaltyEntryKind::Redeemed,
+        LoyaltyEntryKind::Restored,
+        LoyaltyEntryKind::Redeemed,
+        LoyaltyEntryKind::Earned,
+        LoyaltyEntryKind::Reversed,
+        LoyaltyEntryKind::Restored,
+    ]);
+    assert_eq!((accounts[0].earned, accounts[0].redeemed), (25, 0));
+}
+
+#[tokio::test]
+async fn payments_are_exposed_as_solana_actions() {
+    let (service, _rpc) = service("reject").await;
+    let mut payment = create_payment(&service, &Pubkey::new_unique(), 1.5).await;
+    let token = payment.request_token.clone().expect("request token").token;
+    let href = format!("{}/api/actions/pay/{}?t={}", service.config().base_url(), payment.id, token);
+    let encoded: String = url::form_urlencoded::byte_serialize(href.as_bytes()).collect();
+    assert_eq!(payment.action_url, format!("solana-action:{}", encoded));
+    assert_eq!(actions::actions_json()["rules"][0]["apiPath"], "/api/actions/pay/*");
+    assert!(actions::is_action_path("/api/actions/pay/abc") && !actions::is_action_path("/api/payment/abc"));
+    assert_eq!(actions::blockchain_id(service.config().solana.cluster), "solana:EtWTRABZaYq6iMfeYKouRu166VU2xqa1");
+
+    // Чаевые — отдельные кнопки с tip_bps в href
+    payment.tip_presets_bps = vec![1000, 1500];
+    let action = serde_json::to_value(ActionGetResponse::for_payment(&payment, service.config())).unwrap();
+    assert_eq!((action["type"].as_str(), action["disabled"].as_bool()), (Some("action"), Some(false)));
+    assert_eq!(action["icon"], actions::DEFAULT_ICON);
+    assert!(action.get("error").is_none());
+    let links = action["links"]["actions"].as_array().expect("linked actions");
+    let hrefs: Vec<&str> = links.iter().map(|link| link["href"].as_str().unwrap()).collect();
+    assert_eq!(hrefs, vec![href.clone(), format!("{}&tip_bps=1000", href), format!("{}&tip_bps=1500", href)]);
+    assert_eq!(links[2]["label"], "Pay with 15% tip");
+
+    // Оплаченный или истекший платеж показывается без кнопок
+    payment.status = PaymentStatus::Completed;
+    let completed = ActionGetResponse::for_payment(&payment, service.config());
+    assert_eq!((completed.kind, completed.disabled), ("completed", true));
+    assert!(completed.links.is_none());
+    payment.status = PaymentStatus::Pending;
+    payment.expires_at = chrono::Utc::now() - chrono::Duration::minutes(1);
+    let expired = ActionGetResponse::for_payment(&payment, service.config());
+    assert_eq!(expired.error.map(|e| e.message).as_deref(), Some("Payment has expired"));
+}
+
+#[tokio::test]
+async fn allowed_payers_restrict_who_can_pay_an_invoice() {
+    let (service, rpc) = service("reject").await;
+    let treasury = Pubkey::new_unique();
+    let request = |allowed_payers: Vec<String>| CreatePaymentRequest {
+        recipient: Pubkey::new_unique().to_string(),
+        amount: 1.0,
+        amount_base_units: None,
+        token: "SOL".to_string(),
+        label: None,
+        message: None,
+        memo: None,
+        tip_presets_bps: None,
+        locale: None,
+        metadata: None,
+        fee_amount: None,
+        fee_token: None,
+        success_url: None,
+        cancel_url: None,
+        discount_code: None,
+        redeem_points: None,
+        allowed_payers: Some(allowed_payers),
+        hold: false,
+        hold_secs: None,
+        amount_range: None,
+    };
+
+    let error = service.create_payment_with_fee(request(vec![]), None).await.expect_err("empty list");
+    let errors = error.downcast_ref::<ValidationErrors>().expect("validation errors");
+    assert_eq!((errors.errors()[0].field.as_str(), errors.errors()[0].code), ("allowed_payers", "out_of_range"));
+    let error = service.create_payment_with_fee(request(vec![treasury.to_string(), "nope".to_string()]), None).await
+        .expect_err("invalid address");
+    let errors = error.downcast_ref::<ValidationErrors>().expect("validation errors");
+    assert_eq!((errors.errors()[0].field.as_str(), errors.errors()[0].code), ("allowed_payers[1]", "invalid_pubkey"));
+
+    let payment = service.create_payment_with_fee(request(vec![treasury.to_string()]), None).await.expect("payment");
+    assert!(payment.allows_payer(&treasury.to_string()));
+    assert!(!payment.allows_payer(&Pubkey::new_unique().to_string()));
+    assert!(create_payment(&service, &Pubkey::new_unique(), 1.0).await.allows_payer(&Pubkey::new_unique().to_string()));
+
+    let pay = |source: Pubkey, fee_payer: Pubkey| {
+        let signature = Signature::new_unique();
+        rpc.set_transaction(signature, TransactionStatus {
+            slot: 42,
+            balance_changes: vec![
+                BalanceChange { owner: source.to_string(), mint: None, delta: -1_001_005_000 },
+                BalanceChange { owner: payment.recipient.clone(), mint: None, delta: 1_000_000_000 },
+            ],
+            fee_payer: Some(fee_payer.to_string()),
+            ..TransactionStatus::default()
+        });
+        signature.to_string()
+    };
+
+    // Перевод с чужого кошелька или комиссия сети чужим fee payer — платеж не засчитывается
+    let stranger = Pubkey::new_unique();
+    let result = service.verify_payment(&payment.id, &pay(stranger, stranger)).await.unwrap();
+    assert!(!result.verified);
+    assert_eq!(result.details, format!("Fee payer {} is not an allowed payer", stranger));
+    let result = service.verify_payment(&payment.id, &pay(stranger, treasury)).await.unwrap();
+    assert_eq!(result.details, format!("Source account {} is not an allowed payer", stranger));
+    assert_eq!(service.get_payment(&payment.id).await.unwrap().unwrap().status, PaymentStatus::Pending);
+
+    let result = service.verify_payment(&payment.id, &pay(treasury, treasury)).await.unwrap();
+    assert!(result.verified);
+    let paid = service.get_payment(&payment.id).await.unwrap().unwrap();
+    assert_eq!((paid.status, paid.payer), (PaymentStatus::Completed, Some(treasury.to_string())));
+}
+
+#[tokio::test]
+async fn payment_statuses_are_read_in_bulk() {
+    let (service, rpc) = service("reject").await;
+    let open = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    let paid = create_payment(&service, &Pubkey::new_unique(), 2.0).await;
+    let signature = Signature::new_unique();
+    rpc.set_transaction(signature, paid_transaction(&service, &paid));
+    service.verify_payment(&paid.id, &signature.to_string()).await.expect("verified");
+
+    let requested = vec![paid.id.clone(), "pay_unknown".to_string(), open.id.clone()];
+    let found = service.get_payments(&requested).await.unwrap();
+    let ids: Vec<&str> = found.iter().map(|(id, _)| id.as_str()).collect();
+    assert_eq!(ids, vec![paid.id.as_str(), open.id.as_str()]);
+
+    let summary = serde_json::to_value(PaymentStatusSummary::from(&found[0].1)).unwrap();
+    assert_eq!(summary["status"], "completed");
+    assert_eq!(summary["signature"], signature.to_string());
+    assert!(summary["updated_at"].is_string());
+    let summary = PaymentStatusSummary::from(&found[1].1);
+    assert_eq!((summary.status, summary.signature), (PaymentStatus::Pending, None));
+}
+
+#[tokio::test]
+async fn storage_snapshot_survives_restart() {
+    let config = config_with("reject", "", r#"
+[[merchants]]
+id = "shop"
+name = "Shop"
+api_key = "live-key-0123456789"
+"#);
+    let service = PaymentService::with_rpc(config.clone(), Arc::new(MockSolanaRpc::new())).await.expect("service");
+    let webhooks = WebhookService::new(service.storage().clone());
+    let merchant = &service.config().merchants[0];
+    let request = RegisterWebhookRequest {
+        url: "http://127.0.0.1:1/hook".to_string(),
+        description: None,
+        filter: WebhookFilter::default(),
+    };
+    let registered = webhooks.register(request, None, merchant).await.expect("registered");
+    let payment = create_payment(&service, &Pubkey::new_unique(), 1.5).await;
+
+    let path = std::env::temp_dir().join(format!("cryptonow-snapshot-{}.json", std::process::id()));
+    let snapshot = SnapshotService::new(service.storage().clone(), path.to_str());
+    assert_eq!(snapshot.write().await.unwrap(), 1);
+    let outbox = service.storage().snapshot().await.outbox_seq;
+
+    // Новый процесс: пустое хранилище заполняется из файла
+    let restarted = PaymentService::with_rpc(config, Arc::new(MockSolanaRpc::new())).await.expect("service");
+    assert!(SnapshotService::new(restarted.storage().clone(), path.to_str()).load().await.unwrap());
+    std::fs::remove_file(&path).ok();
+
+    let restored = restarted.get_payment(&payment.id).await.unwrap().expect("payment restored");
+    assert_eq!(restored.amount_base_units, payment.amount_base_units);
+    // Токен transaction request в JSON платежа не пишется, но переживает снимок
+    assert_eq!(
+        restored.request_token.as_ref().map(|t| t.token.clone()),
+        payment.request_token.as_ref().map(|t| t.token.clone()),
+    );
+    let by_reference = restarted.storage().find_payment_by_reference(&payment.reference).await.unwrap();
+    assert_eq!(by_reference.map(|p| p.id), Some(payment.id.clone()));
+    let endpoint = restarted.storage().get_webhook(&registered.endpoint.id).await.unwrap().expect("webhook restored");
+    assert_eq!(endpoint.secret, registered.secret);
+    assert_eq!(restarted.storage().snapshot().await.outbox_seq, outbox);
+
+    // Восстановленный платеж меняется как обычно
+    let mut restored = restored;
+    restarted.transition(&mut restored, PaymentStatus::Cancelled, None).await.expect("cancelled");
+
+    // Снимок другой версии формата не применяется
+    let mut foreign = restarted.storage().snapshot().await;
+    foreign.format_version += 1;
+    assert!(restarted.storage().restore(foreign).await.is_err());
+
+    // Без файла загружать нечего
+    assert!(!SnapshotService::new(restarted.storage().clone(), path.to_str()).load().await.unwrap());
+}
+
+#[tokio::test]
+async fn payment_reads_are_cached_until_the_payment_changes() {
+    let (service, _rpc) = service("reject").await;
+    let payment = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    let before = service.storage().payment_cache_stats();
+    assert!(before.enabled);
+
+    // Созданный платеж уже в кэше
+    service.get_payment(&payment.id).await.unwrap().expect("payment");
+    let stats = service.storage().payment_cache_stats();
+    assert_eq!(stats.hits, before.hits + 1);
+    assert!(stats.hit_rate > 0.0);
+
+    // Сохранение обновляет запись: кэш не отдает старый статус
+    let mut stored = service.get_payment(&payment.id).await.unwrap().unwrap();
+    service.transition(&mut stored, PaymentStatus::Cancelled, None).await.expect("cancelled");
+    let cached = service.get_payment(&payment.id).await.unwrap().unwrap();
+    assert_eq!((cached.status, cached.version), (PaymentStatus::Cancelled, stored.version));
+
+    // Удаление сбрасывает запись
+    service.storage().delete_payment(&payment.id).await.unwrap();
+    assert!(service.get_payment(&payment.id).await.unwrap().is_none());
+
+    // Ожидающий платеж с вышедшим сроком не кэшируется, вытесняется давно не читанный
+    let cache = PaymentCache::new(2, std::time::Duration::from_secs(60));
+    let mut overdue = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    overdue.expires_at = chrono::Utc::now() - chrono::Duration::seconds(1);
+    cache.put(&overdue);
+    assert!(cache.get(&overdue.id).is_none());
+    let first = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    let second = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    let third = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    for payment in [&first, &second, &third] {
+        cache.put(payment);
+    }
+    assert!(cache.get(&first.id).is_none());
+    assert!(cache.get(&third.id).is_some());
+    let stats = cache.stats();
+    assert_eq!((stats.entries, stats.hits, stats.misses), (2, 1, 2));
+
+    assert!(!PaymentCache::new(0, std::time::Duration::from_secs(60)).stats().enabled);
+}
+
+#[tokio::test]
+async fn verification_can_require_finalized_transactions() {
+    let (service, rpc) = service("reject").await;
+    let payment = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    let signature = Signature::new_unique();
+    rpc.set_unfinalized(signature, paid_transaction(&service, &payment));
+
+    // Подтвержденная, но не finalized транзакция не проходит проверку с finalized
+    let result = service
+        .verify_payment_with_commitment(&payment.id, &signature.to_string(), Some(CommitmentConfig::finalized()))
+        .await
+        .unwrap();
+    assert!(!result.verified);
+    assert_eq!(result.details, "Transaction not found");
+    assert_eq!(service.get_payment(&payment.id).await.unwrap().unwrap().status, PaymentStatus::Pending);
+
+    // С commitment по умолчанию (confirmed) та же транзакция завершает платеж
+    let result = service.verify_payment(&payment.id, &signature.to_string()).await.unwrap();
+    assert!(result.verified);
+
+    assert_eq!(transaction_commitment("processed"), CommitmentConfig::confirmed());
+    assert_eq!(transaction_commitment("finalized"), CommitmentConfig::finalized());
+    let config = config_with("reject", "verification_commitment = \"finalized\"\n", "");
+    assert_eq!(config.solana.verification_commitment, "finalized");
+    assert_eq!(config.solana.blockhash_commitment, config.solana.commitment);
+}
+
+#[tokio::test]
+async fn payment_payloads_are_signed_with_the_server_key() {
+    let keypair = Keypair::new();
+    let path = std::env::temp_dir().join(format!("cryptonow-signing-{}.json", std::process::id()));
+    write_keypair_file(&keypair, &path).expect("write keypair");
+    let config = config_with("reject", "", &format!("[server]\nsigning_key_path = \"{}\"\n", path.display()));
+    let signed = PaymentService::with_rpc(config, Arc::new(MockSolanaRpc::new())).await.expect("service");
+    std::fs::remove_file(&path).ok();
+
+    let signer = signed.payload_signer().expect("signing key loaded");
+    assert_eq!(signer.public_key(), keypair.pubkey().to_string());
+    let payment = create_payment(&signed, &Pubkey::new_unique(), 1.0).await;
+    let signature = signer.sign(&payment).unwrap();
+
+    // Клиент проверяет полученный `data` независимо от порядка ключей
+    let data = serde_json::to_value(&payment).unwrap();
+    assert!(payload_signer::verify(&signer.public_key(), &data, &signature));
+    let reordered: serde_json::Value = serde_json::from_str(&payload_signer::canonical_json(&data)).unwrap();
+    assert!(payload_signer::verify(&signer.public_key(), &reordered, &signature));
+    assert_eq!(payload_signer::canonical_json(&json!({"b": [1, {"d": null, "c": "x"}], "a": true})),
+        r#"{"a":true,"b":[1,{"c":"x","d":null}]}"#);
+
+    // Подмененный получатель или чужой ключ — подпись не сходится
+    let mut tampered = data.clone();
+    tampered["recipient"] = json!(Pubkey::new_unique().to_string());
+    assert!(!payload_signer::verify(&signer.public_key(), &tampered, &signature));
+    assert!(!payload_signer::verify(&Pubkey::new_unique().to_string(), &data, &signature));
+    assert!(!payload_signer::verify(&signer.public_key(), &data, "not-a-signature"));
+
+    // Без ключа в конфигурации ответы не подписываются
+    let (unsigned, _rpc) = service("reject").await;
+    assert!(unsigned.payload_signer().is_none());
+    assert!(PayloadSigner::load(Some("/nonexistent/signing.json")).is_err());
+}
+
+#[tokio::test]
+async fn tenants_are_managed_at_runtime_with_their_own_settings() {
+    let shop = r#"
+[[merchants]]
+id = "shop"
+name = "Shop"
+api_key = "live-key-0123456789"
+"#;
+    let service = PaymentService::with_rpc(config_with("reject", "", shop), Arc::new(MockSolanaRpc::new())).await.expect("service");
+    let fee_wallet = Pubkey::new_unique().to_string();
+    let merchant: MerchantConfig = serde_json::from_value(json!({
+        "id": "cafe",
+        "name": "Cafe",
+        "api_key": "cafe-key-0123456789",
+        "fee_wallet": fee_wallet,
+        "tokens": ["SOL"],
+        "branding": {"display_name": "Cafe on Main", "icon_url": "https://cafe.example/icon.png"},
+    })).unwrap();
+    let tenant = service.tenants().upsert(merchant.clone()).await.expect("tenant saved");
+    assert_eq!((tenant.source, tenant.merchant.api_key.as_str()), (TenantSource::Api, "[redacted]"));
+
+    // Новый ключ действует сразу, настройки мерчанта применяются к его платежам
+    let cafe = service.config().find_merchant_by_api_key("cafe-key-0123456789").expect("api key resolves");
+    let request = |token: &str| CreatePaymentRequest {
+        recipient: Pubkey::new_unique().to_string(),
+        amount: 1.0,
+        amount_base_units: None,
+        token: token.to_string(),
+        label: None,
+        message: None,
+        memo: None,
+        tip_presets_bps: None,
+        locale: None,
+        metadata: None,
+        fee_amount: None,
+        fee_token: None,
+        success_url: None,
+        cancel_url: None,
+        discount_code: None,
+        redeem_points: None,
+        allowed_payers: None,
+        hold: false,
+        hold_secs: None,
+        amount_range: None,
+    };
+    let payment = service.create_payment_with_fee(request("SOL"), Some(&cafe)).await.expect("payment");
+    assert_eq!(payment.fee_recipient, fee_wallet);
+    assert_eq!(payment.label, "Cafe on Main");
+    assert_eq!(service.config().merchant_icon(payment.merchant_id.as_deref()).as_deref(), Some("https://cafe.example/icon.png"));
+    let err = service.create_payment_with_fee(request("USDC"), Some(&cafe)).await.unwrap_err();
+    assert_eq!(err.downcast_ref::<ValidationErrors>().expect("validation").0[0].code, "not_accepted");
+    let global = service.create_payment_with_fee(request("USDC"), None).await.expect("payment");
+    assert_eq!(global.fee_recipient, FEE_WALLET);
+
+    // Ошибки настроек и чужой ключ отклоняются
+    let mut invalid = merchant.clone();
+    invalid.id = "bad".to_string();
+    invalid.api_key = "short".to_string();
+    invalid.tokens = vec!["DOGE".to_string()];
+    let err = service.tenants().upsert(invalid).await.unwrap_err();
+    assert_eq!(err.downcast_ref::<ValidationErrors>().expect("validation").0.len(), 2);
+    let mut stolen = merchant.clone();
+    stolen.id = "thief".to_string();
+    stolen.api_key = "live-key-0123456789".to_string();
+    assert!(matches!(service.tenants().upsert(stolen).await.unwrap_err().downcast_ref(), Some(TenantError::ApiKeyTaken)));
+    assert!(matches!(service.tenants().delete("shop").await.unwrap_err().downcast_ref(), Some(TenantError::ConfigManaged(_))));
+
+    // Мерчант переживает перезапуск через снимок хранилища
+    let snapshot = service.storage().snapshot().await;
+    assert_eq!(snapshot.tenants.len(), 1);
+    // Клоны конфигурации делят реестр, новый процесс загружает ее заново
+    let restarted = PaymentService::with_rpc(config_with("reject", "", shop), Arc::new(MockSolanaRpc::new())).await.expect("service");
+    assert!(restarted.config().find_merchant("cafe").is_none());
+    restarted.storage().restore(snapshot).await.unwrap();
+    assert_eq!(restarted.config().find_merchant("cafe").map(|m| m.fee_wallet.clone()), Some(Some(fee_wallet)));
+    let ids: Vec<String> = restarted.tenants().list().await.unwrap().into_iter().map(|t| t.merchant.id).collect();
+    assert_eq!(ids, vec!["shop", "cafe"]);
+
+    // Удаленный мерчант больше не авторизуется
+    restarted.tenants().delete("cafe").await.expect("deleted");
+    assert!(restarted.config().find_merchant_by_api_key("cafe-key-0123456789").is_none());
+}
+
+#[tokio::test]
+async fn payment_lists_read_the_replica_unless_strong_consistency_is_requested() {
+    let config = config_with("reject", "", "[storage]\nreplica_max_lag_ms = 60000\n");
+    assert_eq!(config.storage.replica_max_lag_ms, 60_000);
+    let service = PaymentService::with_rpc(config, Arc::new(MockSolanaRpc::new())).await.expect("service");
+    let first = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+
+    // Первое чтение копирует основную таблицу, следующие читают копию
+    let listed = service.storage().list_payments(ReadConsistency::Eventual).await.unwrap();
+    assert_eq!(listed.len(), 1);
+    let second = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    let stale = service.storage().list_payments(ReadConsistency::Eventual).await.unwrap();
+    assert!(Arc::ptr_eq(&listed, &stale));
+    assert!(!stale.iter().any(|p| p.id == second.id));
+
+    // Strong видит новый платеж сразу и не трогает реплику
+    let strong = service.storage().list_payments(ReadConsistency::Strong).await.unwrap();
+    assert!(strong.iter().any(|p| p.id == first.id) && strong.iter().any(|p| p.id == second.id));
+    let stats = service.storage().payment_replica_stats();
+    assert!(stats.enabled);
+    assert_eq!((stats.payments, stats.reads, stats.refreshes), (1, 1, 1));
+
+    // Восстановление снимка сбрасывает копию
+    let snapshot = service.storage().snapshot().await;
+    service.storage().restore(snapshot).await.unwrap();
+    assert_eq!(service.storage().list_payments(ReadConsistency::Eventual).await.unwrap().len(), 2);
+
+    let filter = PaymentListFilter::parse([("consistency", "strong")]).unwrap();
+    assert_eq!(filter.consistency, ReadConsistency::Strong);
+    assert_eq!(PaymentListFilter::parse([]).unwrap().consistency, ReadConsistency::Eventual);
+    assert!(PaymentListFilter::parse([("consistency", "stale")]).is_err());
+
+    // Копия старше допустимого отставания не отдается
+    let replica = PaymentReplica::new(Duration::from_millis(20));
+    replica.put(listed);
+    assert!(replica.get().is_some());
+    tokio::time::sleep(Duration::from_millis(40)).await;
+    assert!(replica.get().is_none());
+
+    // Без реплики каждое чтение идет в основную таблицу
+    let disabled = PaymentReplica::new(Duration::ZERO);
+    assert!(disabled.lock_refresh().await.is_none());
+    assert!(!disabled.stats().enabled);
+}
+
+#[tokio::test]
+async fn wallet_transactions_are_decoded_and_compared_with_the_issued_one() {
+    let (service, rpc) = service("reject").await;
+    rpc.set_blockhash(Hash::new_unique());
+    let recipient = Pubkey::new_unique();
+    let payment = create_payment(&service, &recipient, 1.0).await;
+    let payer = Keypair::new();
+    let account = payer.pubkey().to_string();
+    let encode = |transaction: &VersionedTransaction| {
+        base64::engine::general_purpose::STANDARD.encode(bincode::serialize(transaction).unwrap())
+    };
+
+    service.remember_transaction(&payment, &account, TransactionPart::Full, payment.locale, None, None).await.expect("remembered");
+    let built = create_payment_transaction(
+        &payment, &account, service.config(), service.rpc().as_ref(), TransactionPart::Full, None,
+    ).await.expect("transaction built");
+
+    // Неподписанная выданная транзакция: переводы с суммами, подписи еще нет
+    let decoded = service.decode_transaction(&built.transaction, None).await.expect("decoded");
+    assert_eq!((decoded.version.as_str(), decoded.fee_payer.as_str()), ("legacy", account.as_str()));
+    assert!(!decoded.signers[0].signed);
+    assert!(decoded.issued.is_none());
+    let transfers: Vec<_> = decoded.instructions.iter()
+        .filter_map(|i| match &i.parsed {
+            PreviewInstruction::Transfer { token, amount, to, .. } => Some((token.as_str(), *amount, to.clone())),
+            _ => None,
+        })
+        .collect();
+    assert!(transfers.contains(&("SOL", 1.0, recipient.to_string())));
+    assert!(transfers.contains(&("SOL", 0.001, FEE_WALLET.to_string())));
+
+    // Подписанная, но измененная транзакция: видно, какая инструкция лишняя
+    let unsigned: Transaction = bincode::deserialize(
+        &base64::engine::general_purpose::STANDARD.decode(&built.transaction).unwrap(),
+    ).unwrap();
+    let mut tampered = unsigned.message.clone();
+    tampered.instructions.push(tampered.instructions[0].clone());
+    let tampered = VersionedTransaction::try_new(VersionedMessage::Legacy(tampered), &[&payer]).unwrap();
+    let decoded = service.decode_transaction(&encode(&tampered), Some(&payment)).await.expect("decoded");
+    assert!(decoded.signers[0].signed);
+    let issued = decoded.issued.expect("compared with the issued transaction");
+    assert!(!issued.matches);
+    assert!(issued.differences.contains(&"instructions".to_string()));
+
+    let signed = VersionedTransaction::try_new(VersionedMessage::Legacy(unsigned.message), &[&payer]).unwrap();
+    let issued = service.decode_transaction(&encode(&signed), Some(&payment)).await.unwrap().issued.unwrap();
+    assert!(issued.matches && issued.differences.is_empty());
+
+    // Перевод неизвестного токена: decimals из аккаунта mint
+    let mint = Pubkey::new_unique();
+    let mut data = vec![0; spl_token::state::Mint::LEN];
+    spl_token::state::Mint::pack(spl_token::state::Mint {
+        decimals: 6,
+        is_initialized: true,
+        ..Default::default()
+    }, &mut data).unwrap();
+    rpc.set_account(mint, Account { lamports: 1_461_600, owner: spl_token::ID, data, ..Account::default() });
+    let (source, destination) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let transfer = spl_token::instruction::transfer_checked(
+        &spl_token::ID, &source, &mint, &destination, &payer.pubkey(), &[], 2_500_000, 6,
+    ).unwrap();
+    let other = Pubkey::new_unique();
+    let transfer_and_unknown = [transfer, solana_sdk::instruction::Instruction::new_with_bytes(other, &[1], vec![])];
+    let message = solana_sdk::message::Message::new(&transfer_and_unknown, Some(&payer.pubkey()));
+    let unsigned = VersionedTransaction::from(Transaction::new_unsigned(message));
+    let decoded = service.decode_transaction(&encode(&unsigned), None).await.unwrap();
+    match &decoded.instructions[0].parsed {
+        PreviewInstruction::Transfer { token, amount, from, .. } => {
+            assert_eq!((token.as_str(), *amount, from.as_str()), (mint.to_string().as_str(), 2.5, source.to_string().as_str()));
+        }
+        other => panic!("unexpected instruction {:?}", other),
+    }
+    assert!(matches!(&decoded.instructions[1].parsed, PreviewInstruction::Program { program_id } if *program_id == other.to_string()));
+
+    // Не base64 транзакции — ошибка поля, как при отправке
+    let error = service.decode_transaction("not a transaction", None).await.expect_err("rejected");
+    assert_eq!(error.downcast_ref::<ValidationErrors>().unwrap().errors()[0].code, "invalid");
+}
+
+#[tokio::test]
+async fn transaction_requests_need_the_single_use_url_token() {
+    use futures::StreamExt;
+
+    let (service, _rpc) = service("reject").await;
+    let payment = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    let token = payment.request_token.clone().expect("request token").token;
+    assert_eq!(token.len(), 26);
+
+    // Ссылка с query параметром в solana: URL передается URL-encoded
+    let link = format!("{}/api/payment/{}/transaction?t={}", service.config().base_url(), payment.id, token);
+    let encoded: String = url::form_urlencoded::byte_serialize(link.as_bytes()).collect();
+    assert_eq!(payment.url, format!("solana:{}", encoded));
+
+    // Токен есть только в ссылках ответа создателю: платеж по ID (JSON, QR, SSE, long-poll)
+    // отдается без него
+    let json = serde_json::to_value(&payment).unwrap();
+    assert!(json.get("request_token").is_none());
+    let public = service.without_request_token(payment.clone());
+    assert_eq!(public.url, format!("solana:{}/api/payment/{}/transaction", service.config().base_url(), payment.id));
+    assert!(payment.action_url.contains(&token) && !public.action_url.contains(&token), "{}", public.action_url);
+    let polled = sse::wait_for_status_change(&service, &payment.id, None, Duration::from_millis(10)).await
+        .unwrap().expect("payment");
+    assert!(!serde_json::to_string(&polled).unwrap().contains(&token));
+    let events = sse::payment_events(service.clone(), &payment.id).await.unwrap().expect("stream");
+    let first = Box::pin(events).next().await.unwrap().unwrap();
+    assert!(!String::from_utf8_lossy(&first).contains(&token));
+
+    // По одному ID платежа метаданные и транзакцию не получить
+    assert!(matches!(payment.check_request_token(None), Err(RequestTokenError::Invalid)));
+    assert!(matches!(payment.check_request_token(Some("WRONG")), Err(RequestTokenError::Invalid)));
+    assert!(payment.check_request_token(Some(&token)).is_ok());
+    let wallet = Pubkey::new_unique().to_string();
+    let error = service.claim_request_token(payment.clone(), None, &wallet).await.expect_err("no token");
+    assert!(matches!(error.downcast_ref::<RequestTokenError>(), Some(RequestTokenError::Invalid)));
+
+    // Первый кошелек закрепляет токен; его повторный запрос проходит, чужой — нет
+    let claimed = service.claim_request_token(payment.clone(), Some(&token), &wallet).await.expect("claimed");
+    assert_eq!(claimed.request_token.as_ref().unwrap().claimed_by.as_deref(), Some(wallet.as_str()));
+    let stored = service.get_payment(&payment.id).await.unwrap().unwrap();
+    service.claim_request_token(stored.clone(), Some(&token), &wallet).await.expect("same wallet retries");
+    let error = service.claim_request_token(stored, Some(&token), &Pubkey::new_unique().to_string()).await
+        .expect_err("token already claimed");
+    assert!(matches!(error.downcast_ref::<RequestTokenError>(), Some(RequestTokenError::Claimed)));
+    // Устаревшая копия без отметки не обходит закрепление
+    let error = service.claim_request_token(payment, Some(&token), &Pubkey::new_unique().to_string()).await
+        .expect_err("claimed concurrently");
+    assert!(matches!(error.downcast_ref::<RequestTokenError>(), Some(RequestTokenError::Claimed)));
+
+    // Без transaction_request_tokens — прежние URL и платежи без токена открыты
+    let config = config_with("reject", "", "[server]\ntransaction_request_tokens = false\n");
+    let open = PaymentService::with_rpc(config, Arc::new(MockSolanaRpc::new())).await.expect("service");
+    let payment = create_payment(&open, &Pubkey::new_unique(), 1.0).await;
+    assert!(payment.request_token.is_none());
+    assert!(payment.url.ends_with(&format!("/api/payment/{}/transaction", payment.id)), "{}", payment.url);
+    assert!(payment.check_request_token(None).is_ok());
+    open.claim_request_token(payment, None, &wallet).await.expect("no token required");
+}
+
+#[tokio::test]
+async fn requests_are_traced_with_correlation_ids_and_latency_histograms() {
+    let (service, _rpc) = service("reject").await;
+    let payment = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+
+    // Операции хранилища внутри запроса попадают в его трассировку с correlation id
+    let (correlation_id, steps, dropped) = Observability::trace("req-1", async {
+        service.get_payment(&payment.id).await.unwrap().expect("payment");
+        observability::correlation_id()
+    }).await;
+    assert_eq!(correlation_id.as_deref(), Some("req-1"));
+    assert_eq!(dropped, 0);
+    assert!(steps.iter().any(|step| step.kind == "storage" && step.name == "get_payment"), "{:?}", steps);
+    // Вне запроса ничего не пишется
+    assert!(observability::correlation_id().is_none());
+
+    let histograms = LatencyHistograms::default();
+    for latency_ms in [3, 7, 40, 40, 2_000] {
+        histograms.observe("GET /api/payment/{id}", Duration::from_millis(latency_ms));
+    }
+    histograms.observe("POST /api/payment/create", Duration::from_millis(60_000));
+    let snapshot = histograms.snapshot();
+    assert_eq!(snapshot.len(), 2);
+    let route = &snapshot[0];
+    assert_eq!(route.route, "GET /api/payment/{id}");
+    assert_eq!((route.count, route.sum_ms, route.max_ms), (5, 2_090, 2_000));
+    assert_eq!((route.p50_ms, route.p99_ms), (50, 2_000));
+    // Корзины накопительные, последняя — все запросы
+    assert_eq!((route.buckets[0].le_ms, route.buckets[0].count), (Some(5), 1));
+    assert_eq!((route.buckets[3].le_ms, route.buckets[3].count), (Some(50), 4));
+    assert_eq!(route.buckets.last().map(|b| (b.le_ms, b.count)), Some((None, 5)));
+    // Квантиль корзины сверх границ — максимум маршрута
+    assert_eq!(snapshot[1].p50_ms, 60_000);
+
+    let config = config_with("reject", "", "[server]\nslow_request_ms = 0\n");
+    assert_eq!(config.server.slow_request_ms, 0);
+    assert!(Observability::from_config(&config.server).histograms().snapshot().is_empty());
+}
+
+#[tokio::test]
+async fn pooled_payments_are_pregenerated_and_claimed_once() {
+    let config = config_with("reject", "", r#"
+[[merchants]]
+id = "shop"
+name = "Shop"
+api_key = "shop-api-key-0123456789"
+[merchants.limits]
+max_daily_payments = 3
+
+[[merchants]]
+id = "other"
+name = "Other"
+api_key = "other-api-key-0123456789"
+"#);
+    let service = PaymentService::with_rpc(config, Arc::new(MockSolanaRpc::new())).await.expect("service");
+    let pools = PoolService::new(service.clone());
+    let shop = service.config().find_merchant("shop").unwrap();
+    let other = service.config().find_merchant("other").unwrap();
+    let request = |count: usize, extra: serde_json::Value| -> CreatePoolRequest {
+        let mut payment = serde_json::json!({"recipient": Pubkey::new_unique().to_string(), "amount": 2.0, "token": "SOL"});
+        payment.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(serde_json::json!({"count": count, "payment": payment})).unwrap()
+    };
+
+    // Скидки и баллы — на конкретного покупателя, не на партию
+    let error = pools.create(request(3, serde_json::json!({"discount_code": "SALE"})), &shop).await.expect_err("discount");
+    assert!(error.downcast_ref::<ValidationErrors>().is_some());
+    let error = pools.create(request(0, serde_json::json!({})), &shop).await.expect_err("empty pool");
+    assert!(error.downcast_ref::<ValidationErrors>().is_some());
+
+    // Лимиты проверяются на весь пул, а не на одну заготовку
+    let error = pools.create(request(4, serde_json::json!({})), &shop).await.expect_err("pool over daily limit");
+    assert!(matches!(error.downcast_ref::<LimitError>(), Some(LimitError::TooManyPayments { .. })));
+
+    let pool = pools.create(request(3, serde_json::json!({})), &shop).await.expect("pool");
+    assert_eq!((pool.available, pool.generated, pool.claimed), (3, 3, 0));
+    assert_eq!(pool.amount, 2.0);
+    // Заготовки не видны как платежи, пока их не выдали
+    assert!(service.storage().list_payments(ReadConsistency::Strong).await.unwrap().is_empty());
+    assert!(matches!(
+        pools.get(&pool.id, &other).await.unwrap_err().downcast_ref::<PoolError>(),
+        Some(PoolError::NotFound)
+    ));
+
+    let first = pools.claim(&pool.id, &shop).await.expect("claimed");
+    let second = pools.claim(&pool.id, &shop).await.expect("claimed");
+    assert_ne!(first.id, second.id);
+    assert_ne!(first.reference, second.reference);
+    assert_ne!(first.url, second.url);
+    // QR коды готовы заранее, сроки — от момента выдачи
+    assert!(first.qr_code.is_some() && first.transfer_qr_code.is_some());
+    assert!(first.expires_in_seconds > 29 * 60);
+    assert_eq!(first.merchant_id.as_deref(), Some("shop"));
+    let stored = service.get_payment(&first.id).await.unwrap().expect("stored as a payment");
+    assert_eq!(stored.status, PaymentStatus::Pending);
+    assert!(stored.qr_code.is_none());
+    assert_eq!(service.storage().find_payment_by_reference(&second.reference).await.unwrap().unwrap().id, second.id);
+
+    // Выданные платежи учитываются в лимитах мерчанта вместе с обычными
+    let direct: CreatePaymentRequest = serde_json::from_value(
+        serde_json::json!({"recipient": Pubkey::new_unique().to_string(), "amount": 2.0, "token": "SOL"}),
+    ).unwrap();
+    service.create_payment_with_fee(direct, Some(&shop)).await.expect("direct payment");
+    let error = pools.claim(&pool.id, &shop).await.expect_err("daily limit");
+    assert!(matches!(error.downcast_ref::<LimitError>(), Some(LimitError::TooManyPayments { .. })));
+    let status = pools.get(&pool.id, &shop).await.unwrap();
+    assert_eq!((status.available, status.claimed), (1, 2));
+
+    // Пул переживает снимок хранилища
+    let snapshot = service.storage().snapshot().await;
+    assert_eq!(snapshot.payment_pools[&pool.id].payments.len(), 1);
+
+    assert_eq!(pools.delete(&pool.id, &shop).await.expect("deleted"), 1);
+    assert!(pools.get(&pool.id, &shop).await.is_err());
+    service.storage().restore(snapshot).await.unwrap();
+    assert_eq!(pools.get(&pool.id, &shop).await.unwrap().available, 1);
+
+    // Последняя заготовка выдана — пул исчерпан
+    let roomy = pools.create(request(1, serde_json::json!({})), &other).await.expect("pool");
+    pools.claim(&roomy.id, &other).await.expect("claimed");
+    let error = pools.claim(&roomy.id, &other).await.expect_err("exhausted");
+    assert!(matches!(error.downcast_ref::<PoolError>(), Some(PoolError::Exhausted)));
+}
+
+/// Плагин с инструкцией заданного размера (проверка лимита размера транзакции)
+struct Padding {
+    bytes: usize,
+    required: bool,
+}
+
+impl InstructionDecorator for Padding {
+    fn name(&self) -> &str {
+        "padding"
+    }
+
+    fn position(&self) -> PluginPosition {
+        PluginPosition::After
+    }
+
+    fn required(&self) -> bool {
+        self.required
+    }
+
+    fn instructions(&self, _context: &DecoratorContext<'_>) -> anyhow::Result<Vec<Instruction>> {
+        Ok(vec![Instruction { program_id: Pubkey::new_unique(), accounts: Vec::new(), data: vec![0; self.bytes] }])
+    }
+}
+
+#[tokio::test]
+async fn instruction_plugins_are_added_to_every_payment_transaction() {
+    let loyalty = Pubkey::new_unique();
+    let config = config_with("reject", "", &format!(r#"
+[compute_budget]
+enabled = false
+
+[[instruction_plugins]]
+name = "brand"
+kind = "memo"
+text = "Order {{payment_id}}"
+
+[[instruction_plugins]]
+name = "loyalty"
+kind = "program"
+program_id = "{loyalty}"
+data = "AQ=="
+position = "before"
+required = true
+accounts = [{{ pubkey = "payer", signer = true, writable = true }}, {{ pubkey = "recipient" }}]
+
+[[instruction_plugins]]
+name = "budget"
+kind = "compute_budget"
+unit_limit = 50000
+"#));
+    let rpc = Arc::new(MockSolanaRpc::new());
+    rpc.set_blockhash(Hash::new_unique());
+    let service = PaymentService::with_rpc(config, rpc.clone()).await.expect("service");
+    let payment = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    let payer = Pubkey::new_unique();
+    let built = |config: Config| {
+        let payment = payment.clone();
+        let rpc = rpc.clone();
+        async move {
+            create_payment_transaction(&payment, &payer.to_string(), &config, rpc.as_ref(), TransactionPart::Full, None).await
+        }
+    };
+
+    // Бюджет в начале, loyalty перед переводами, memo плагина после них
+    let transaction: Transaction = bincode::deserialize(&base64::engine::general_purpose::STANDARD.decode(
+        built(service.config().clone()).await.expect("transaction built").transaction,
+    ).unwrap()).unwrap();
+    let message = &transaction.message;
+    let programs: Vec<Pubkey> = (0..message.instructions.len()).map(|i| *message.program_id(i).unwrap()).collect();
+    assert_eq!(programs, vec![
+        solana_sdk::compute_budget::id(),
+        loyalty,
+        solana_sdk::system_program::id(),
+        solana_sdk::system_program::id(),
+        crypto_server::transaction::MEMO_PROGRAM_ID,
+    ]);
+    let call = &message.instructions[1];
+    assert_eq!(call.data, vec![1]);
+    assert_eq!(message.account_keys[call.accounts[0] as usize], payer);
+    assert!(message.is_signer(call.accounts[0] as usize) && message.is_writable(call.accounts[0] as usize));
+    assert_eq!(message.instructions[4].data, format!("Order {}", payment.id).into_bytes());
+
+    // Необязательный плагин выбрасывается первым, если транзакция не помещается
+    let mut padded = service.config().clone();
+    padded.decorators = padded.decorators.with(Arc::new(Padding { bytes: 1_000, required: false }));
+    let trimmed = built(padded).await.expect("transaction built");
+    assert_eq!(trimmed.notes, vec!["padding omitted to fit the transaction size limit".to_string()]);
+    let transaction: Transaction = bincode::deserialize(
+        &base64::engine::general_purpose::STANDARD.decode(trimmed.transaction).unwrap(),
+    ).unwrap();
+    assert_eq!(transaction.message.instructions.len(), 5);
+    // Обязательный — никогда
+    let mut padded = service.config().clone();
+    padded.decorators = padded.decorators.with(Arc::new(Padding { bytes: 1_000, required: true }));
+    let error = built(padded).await.expect_err("too large");
+    assert!(error.to_string().contains("exceeding the 1232 byte limit"), "{}", error);
+
+    // Фиксированный бюджет плагина не совмещается с оценкой симуляцией
+    let mut estimated = service.config().clone();
+    estimated.compute_budget.enabled = true;
+    assert!(estimated.validate().unwrap_err().to_string().contains("compute_budget.enabled = false"));
+}
+
+#[tokio::test]
+async fn payment_events_are_dispatched_once_with_idempotency_keys() {
+    use std::io::{Read, Write};
+
+    // Endpoint мерчанта: принимает доставки и пересылает их в тест целиком
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let (received, requests) = std::sync::mpsc::channel::<String>();
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 4096];
+            loop {
+                let read = stream.read(&mut buffer).unwrap_or(0);
+                request.extend_from_slice(&buffer[..read]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                let complete = text.split_once("\r\n\r\n").is_some_and(|(head, body)| {
+                    let length = head.lines()
+                        .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().to_string()))
+                        .and_then(|v| v.parse::<usize>().ok())
+                        .unwrap_or(0);
+                    body.len() >= length
+                });
+                if read == 0 || complete {
+                    break;
+                }
+            }
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+            let _ = received.send(String::from_utf8_lossy(&request).to_string());
+        }
+    });
+
+    let config = config_with("reject", "", r#"
+[[merchants]]
+id = "shop"
+name = "Shop"
+api_key = "live-key-0123456789"
+"#);
+    let service = PaymentService::with_rpc(config.clone(), Arc::new(MockSolanaRpc::new())).await.expect("service");
+    let webhooks = WebhookService::new(service.storage().clone());
+    let merchant = &service.config().merchants[0];
+    let request = RegisterWebhookRequest { url, description: None, filter: WebhookFilter::default() };
+    let endpoint = webhooks.register(request, None, merchant).await.expect("registered").endpoint;
+
+    let mut payment = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    payment.merchant_id = Some("shop".to_string());
+    service.transition(&mut payment, PaymentStatus::Completed, None).await.unwrap();
+    // Второй воркер записывает тот же переход: событие не дублируется, платеж сохраняется
+    let mut raced = service.get_payment(&payment.id).await.unwrap().unwrap();
+    service.storage().save_payment_with_event(&mut raced, "payment.completed").await.unwrap();
+    let key = events::idempotency_key("payment.completed", &payment.id);
+    let pending = service.storage().pending_outbox_events().await.unwrap();
+    let completed: Vec<_> = pending.iter().filter(|event| event.event_type == "payment.completed").collect();
+    assert_eq!(completed.len(), 1);
+    assert_eq!(completed[0].idempotency_key, key);
+    assert_ne!(key, events::idempotency_key("payment.completed", "pay_other"));
+
+    let dispatcher = OutboxDispatcher::new(
+        service.storage().clone(),
+        webhooks.clone(),
+        NotificationService::new(config.clone()).unwrap(),
+        LeaderElection::from_config(&config.coordination).await.unwrap(),
+    );
+    assert_eq!(dispatcher.drain().await.unwrap(), 1);
+    let delivery = loop {
+        if let Ok(delivery) = requests.try_recv() {
+            break delivery;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    };
+    assert!(delivery.to_ascii_lowercase().contains(&format!("x-cryptonow-idempotency-key: {}", key)), "{}", delivery);
+
+    // Повторная публикация (сбой до отметки в outbox) новой доставки не создает
+    webhooks.publish_event("shop", "payment.completed", completed[0].payload.clone(), &key).await.unwrap();
+    assert_eq!(webhooks.deliveries(&endpoint.id, merchant).await.unwrap().len(), 1);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(requests.try_recv().is_err());
+    assert_eq!(dispatcher.drain().await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn merchants_register_with_invite_codes_and_rotate_their_keys() {
+    let open = config_with("reject", "", "[registration]\nenabled = true\ninvite_codes = [\"partner-2026\"]\n");
+    let service = PaymentService::with_rpc(open, Arc::new(MockSolanaRpc::new())).await.expect("service");
+    let registration = RegistrationService::new(service.clone());
+    let request = |id: &str, invite_code: Option<&str>, webhook_url: &str| RegisterMerchantRequest {
+        id: id.to_string(),
+        name: "Corner Cafe".to_string(),
+        invite_code: invite_code.map(str::to_string),
+        fee_wallet: None,
+        tokens: vec!["USDC".to_string()],
+        branding: None,
+        webhook: Some(RegisterWebhookRequest {
+            url: webhook_url.to_string(),
+            description: None,
+            filter: WebhookFilter::default(),
+        }),
+    };
+    let hook = "https://cafe.example/hook";
+
+    for code in [None, Some("guess")] {
+        let error = registration.register(request("cafe", code, hook)).await.unwrap_err();
+        assert!(matches!(error.downcast_ref(), Some(RegistrationError::InvalidInviteCode)));
+    }
+    // Неверный webhook: мерчант не создается
+    let error = registration.register(request("cafe", Some("partner-2026"), "http://cafe.example/hook")).await.unwrap_err();
+    assert_eq!(error.downcast_ref::<ValidationErrors>().unwrap().errors()[0].field, "webhook.url");
+    assert!(service.config().find_merchant("cafe").is_none());
+
+    let registered = registration.register(request("cafe", Some("partner-2026"), hook)).await.expect("registered");
+    assert!(registered.api_key.starts_with("cn_live_"), "{}", registered.api_key);
+    assert_eq!(registered.merchant.merchant.api_key, "[redacted]");
+    assert_eq!(registered.merchant.source, TenantSource::Api);
+    let secret = &registered.webhook.as_ref().expect("webhook").secret;
+    assert!(secret.starts_with("whsec_"));
+    let merchant = service.config().find_merchant_by_api_key(&registered.api_key).expect("key works");
+    assert_eq!((merchant.id.as_str(), merchant.tokens.as_slice()), ("cafe", ["USDC".to_string()].as_slice()));
+    assert_eq!(service.webhooks().list(&merchant).await.unwrap().len(), 1);
+    let taken = registration.register(request("cafe", Some("partner-2026"), hook)).await.unwrap_err();
+    assert!(matches!(taken.downcast_ref(), Some(TenantError::IdTaken(_))));
+
+    // Ротация: старый ключ перестает действовать сразу, настройки сохраняются
+    let rotated = service.tenants().rotate_api_key("cafe").await.expect("rotated");
+    assert_ne!(rotated.api_key, registered.api_key);
+    assert!(service.config().find_merchant_by_api_key(&registered.api_key).is_none());
+    let merchant = service.config().find_merchant_by_api_key(&rotated.api_key).expect("new key works");
+    assert_eq!(merchant.tokens, ["USDC".to_string()]);
+
+    // Отзыв: не действует ни один ключ, пока администратор не выпустит новый
+    service.tenants().revoke_api_key("cafe").await.expect("revoked");
+    assert!(service.config().find_merchant_by_api_key(&rotated.api_key).is_none());
+    let reissued = service.tenants().rotate_api_key("cafe").await.expect("reissued");
+    assert!(service.config().find_merchant_by_api_key(&reissued.api_key).is_some());
+    assert!(matches!(
+        service.tenants().rotate_api_key("missing").await.unwrap_err().downcast_ref(),
+        Some(TenantError::NotFound)
+    ));
+
+    // По умолчанию регистрация выключена
+    let closed = PaymentService::with_rpc(config("reject"), Arc::new(MockSolanaRpc::new())).await.expect("service");
+    let error = RegistrationService::new(closed).register(request("cafe", None, hook)).await.unwrap_err();
+    assert!(matches!(error.downcast_ref(), Some(RegistrationError::Disabled)));
+}
+
+#[tokio::test]
+async fn scoped_api_keys_are_limited_by_scope_and_expiry() {
+    let config = config_with("reject", "", r#"
+[[merchants]]
+id = "shop"
+name = "Shop"
+api_key = "shop-api-key-0123456789"
+[[merchants.api_keys]]
+id = "reports"
+api_key = "shop-reports-key-0123456789"
+scope = "read_only"
+"#);
+    let service = PaymentService::with_rpc(config, Arc::new(MockSolanaRpc::new())).await.expect("service");
+    let tenants = &service.config().tenants;
+    let create = (Method::POST, "/api/payment/create");
+    let list = (Method::GET, "/api/payments");
+    let allowed = |key: &str, (method, path): &(Method, &str)| authorize_key(tenants, key, method, path);
+
+    // Ключ из конфигурации: только чтение
+    let grant = allowed("shop-reports-key-0123456789", &list).expect("read allowed");
+    assert_eq!((grant.merchant.id.as_str(), grant.key_id.as_str()), ("shop", "reports"));
+    assert!(matches!(
+        allowed("shop-reports-key-0123456789", &create),
+        Err(AuthError::InsufficientScope(ApiKeyScope::ReadOnly))
+    ));
+    assert_eq!(allowed("shop-api-key-0123456789", &create).expect("primary").scope, ApiKeyScope::Admin);
+
+    let terminal = service.tenants().create_api_key("shop", CreateApiKeyRequest {
+        scope: ApiKeyScope::CreateOnly,
+        description: Some("POS terminal".to_string()),
+        expires_at: None,
+    }).await.expect("created");
+    assert!(terminal.api_key.starts_with("cn_live_"));
+    assert!(allowed(&terminal.api_key, &create).is_ok());
+    assert!(allowed(&terminal.api_key, &(Method::GET, "/api/payment/pay_123/qr")).is_ok());
+    assert!(matches!(allowed(&terminal.api_key, &list), Err(AuthError::InsufficientScope(ApiKeyScope::CreateOnly))));
+    assert!(allowed(&terminal.api_key, &(Method::POST, "/api/webhooks")).is_err());
+
+    // Истекший ключ отклоняется независимо от прав
+    let expiring = service.tenants().create_api_key("shop", CreateApiKeyRequest {
+        scope: ApiKeyScope::Admin,
+        description: None,
+        expires_at: Some(chrono::Utc::now() + chrono::Duration::milliseconds(200)),
+    }).await.expect("created");
+    assert!(allowed(&expiring.api_key, &list).is_ok());
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    assert!(matches!(allowed(&expiring.api_key, &list), Err(AuthError::ApiKeyExpired)));
+    let past = service.tenants().create_api_key("shop", CreateApiKeyRequest {
+        scope: ApiKeyScope::ReadOnly,
+        description: None,
+        expires_at: Some(chrono::Utc::now() - chrono::Duration::hours(1)),
+    }).await.unwrap_err();
+    assert_eq!(past.downcast_ref::<ValidationErrors>().unwrap().errors()[0].field, "expires_at");
+
+    // Список без секретов, с последним использованием
+    let keys = service.tenants().api_keys("shop").await.expect("keys");
+    let ids: Vec<_> = keys.iter().map(|key| key.id.as_str()).collect();
+    assert_eq!(ids, ["primary", "reports", terminal.key.id.as_str(), expiring.key.id.as_str()]);
+    assert!(keys.iter().all(|key| key.last_used_at.is_some()));
+    assert!(keys[3].expired && !keys[2].expired);
+    let listed = serde_json::to_value(&keys).unwrap();
+    assert!(!listed.to_string().contains(&terminal.api_key));
+
+    service.tenants().delete_api_key("shop", &terminal.key.id).await.expect("deleted");
+    assert!(matches!(allowed(&terminal.api_key, &create), Err(AuthError::InvalidApiKey)));
+    assert!(matches!(
+        service.tenants().delete_api_key("shop", &terminal.key.id).await.unwrap_err().downcast_ref(),
+        Some(TenantError::ApiKeyNotFound)
+    ));
+
+    // Повторяющийся ID ключа отклоняется как ошибка настроек
+    let mut merchant = (*service.config().find_merchant("shop").unwrap()).clone();
+    let mut duplicate = merchant.api_keys[0].clone();
+    duplicate.api_key = "another-reports-key-0123456789".to_string();
+    merchant.api_keys.push(duplicate);
+    let error = service.tenants().upsert(merchant).await.unwrap_err();
+    let errors = error.downcast_ref::<ValidationErrors>().expect("validation");
+    assert!(errors.errors()[0].message.contains("duplicates 'reports'"), "{:?}", errors.errors());
+}
+
+#[tokio::test]
+async fn amount_range_intents_accept_any_amount_in_range() {
+    let rpc = Arc::new(MockSolanaRpc::new());
+    let service = PaymentService::with_rpc(config("reject"), rpc.clone()).await.expect("service");
+    let payer = Pubkey::new_unique();
+    let request = |amount: f64, range: Option<(f64, f64)>, tips: Option<Vec<u16>>| CreatePaymentRequest {
+        recipient: Pubkey::new_unique().to_string(),
+        amount,
+        amount_base_units: None,
+        token: "SOL".to_string(),
+        label: Some("Donation".to_string()),
+        message: None,
+        memo: None,
+        tip_presets_bps: tips,
+        locale: None,
+        metadata: None,
+        fee_amount: None,
+        fee_token: None,
+        success_url: None,
+        cancel_url: None,
+        discount_code: None,
+        redeem_points: None,
+        allowed_payers: None,
+        hold: false,
+        hold_secs: None,
+        amount_range: range.map(|(min, max)| AmountRange { min, max }),
+    };
+    let rejection = |error: anyhow::Error| {
+        let errors = error.downcast_ref::<ValidationErrors>().expect("validation errors").clone();
+        errors.errors().iter().map(|e| format!("{}:{}", e.field, e.code)).collect::<Vec<_>>().join(",")
+    };
+
+    for (request, expected) in [
+        (request(5.0, Some((1.0, 100.0)), None), "amount_range:conflict"),
+        (request(0.0, Some((10.0, 1.0)), None), "amount_range.max:too_small"),
+        (request(0.0, Some((0.0, 1.0)), None), "amount_range.min:too_small"),
+        (request(0.0, Some((1.0, 100.0)), Some(vec![1000])), "tip_presets_bps:not_allowed"),
+    ] {
+        let error = service.create_payment_with_fee(request, None).await.expect_err("rejected");
+        assert_eq!(rejection(error), expected);
+    }
+
+    // До выбора суммы — минимум; transfer request без суммы: ее вводит плательщик в кошельке
+    let payment = service.create_payment_with_fee(request(0.0, Some((1.0, 100.0)), None), None).await.expect("intent");
+    assert_eq!((payment.amount, payment.amount_base_units), (1.0, 1_000_000_000));
+    assert_eq!(payment.amount_range, Some(AmountRange { min: 1.0, max: 100.0 }));
+    assert!(!payment.transfer_url.contains("amount="), "{}", payment.transfer_url);
+    let action = serde_json::to_value(ActionGetResponse::for_payment(&payment, service.config())).unwrap();
+    let link = &action["links"]["actions"][0];
+    assert!(link["href"].as_str().unwrap().ends_with("amount={amount}"), "{}", link);
+    assert_eq!((link["parameters"][0]["min"].as_f64(), link["parameters"][0]["max"].as_f64()), (Some(1.0), Some(100.0)));
+
+    // Сумма из transaction request — в пределах
+    let error = service.apply_amount(payment.clone(), None).await.expect_err("required");
+    assert_eq!(rejection(error), "amount:required");
+    let error = service.apply_amount(payment.clone(), Some(250.0)).await.expect_err("too much");
+    assert_eq!(rejection(error), "amount:out_of_range");
+    let chosen = service.apply_amount(payment.clone(), Some(25.0)).await.expect("chosen");
+    assert_eq!((chosen.amount, chosen.amount_base_units), (25.0, 25_000_000_000));
+    let fixed = create_payment(&service, &Pubkey::new_unique(), 1.0).await;
+    let error = service.apply_amount(fixed, Some(2.0)).await.expect_err("fixed");
+    assert_eq!(rejection(error), "amount:not_allowed");
+
+    // Верификация: принимается любая сумма в пределах, записывается полученная
+    let pay = |received: i128| {
+        let rpc = rpc.clone();
+        let recipient = payment.recipient.clone();
+        move || {
+            let signature = Signature::new_unique();
+            rpc.set_transaction(signature, TransactionStatus {
+                slot: 42,
+                balance_changes: vec![
+                    BalanceChange { owner: payer.to_string(), mint: None, delta: -received - 1_005_000 },
+                    BalanceChange { owner: recipient.clone(), mint: None, delta: received },
+                ],
+                ..TransactionStatus::default()
+            });
+            signature.to_string()
+        }
+    };
+    let over = service.verify_payment(&payment.id, &pay(150_000_000_000)()).await.unwrap();
+    assert!(!over.success && over.details.contains("more than the maximum"), "{}", over.details);
+    let under = service.verify_payment(&payment.id, &pay(500_000_000)()).await.unwrap();
+    assert!(!under.success, "{}", under.details);
+    let paid = service.verify_payment(&payment.id, &pay(40_000_000_000)()).await.unwrap();
+    assert!(paid.success, "{}", paid.details);
+
+    let completed = service.get_payment(&payment.id).await.unwrap().unwrap();
+    assert_eq!(completed.status, PaymentStatus::Completed);
+    assert_eq!((completed.amount, completed.amount_base_units, completed.paid_amount), (40.0, 40_000_000_000, Some(40.0)));
+    assert!(completed.amount_display.starts_with("40"), "{}", completed.amount_display);
+}